@@ -0,0 +1,11 @@
+-- Create audit_comments table for human reviewer notes
+CREATE TABLE IF NOT EXISTS audit_comments (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    audit_id UUID NOT NULL REFERENCES ai_audits(id) ON DELETE CASCADE,
+    author TEXT NOT NULL,
+    body TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Create index on audit_id for listing the comments of an audit
+CREATE INDEX idx_audit_comments_audit_id ON audit_comments(audit_id, created_at);
@@ -0,0 +1,6 @@
+-- Store the rustc error code of the first compilation error for analytics
+ALTER TABLE ai_audits ADD COLUMN primary_error_code TEXT;
+
+-- Create index on primary_error_code for error frequency queries
+CREATE INDEX idx_ai_audits_primary_error_code ON ai_audits(primary_error_code)
+    WHERE primary_error_code IS NOT NULL;
@@ -0,0 +1,7 @@
+-- Store the client-supplied idempotency key and a fingerprint of the payload it was used with
+ALTER TABLE ai_audits ADD COLUMN idempotency_key TEXT;
+ALTER TABLE ai_audits ADD COLUMN request_fingerprint TEXT;
+
+-- Create unique index so that a key can only ever identify a single audit
+CREATE UNIQUE INDEX idx_ai_audits_idempotency_key ON ai_audits(idempotency_key)
+    WHERE idempotency_key IS NOT NULL;
@@ -0,0 +1,6 @@
+-- Create persisted_queries table backing Automatic Persisted Queries across restarts
+CREATE TABLE IF NOT EXISTS persisted_queries (
+    sha256_hash TEXT PRIMARY KEY,
+    query TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
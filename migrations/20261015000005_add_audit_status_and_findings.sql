@@ -0,0 +1,9 @@
+-- Record the verdict of each audit, the heuristic findings and why it was rejected, if it was
+ALTER TABLE ai_audits ADD COLUMN status TEXT;
+UPDATE ai_audits SET status = CASE WHEN is_valid THEN 'valid' ELSE 'compile_error' END;
+ALTER TABLE ai_audits ALTER COLUMN status SET NOT NULL;
+ALTER TABLE ai_audits ADD CONSTRAINT ai_audits_status_check
+    CHECK (status IN ('valid', 'compile_error', 'rejected'));
+
+ALTER TABLE ai_audits ADD COLUMN findings JSONB NOT NULL DEFAULT '[]';
+ALTER TABLE ai_audits ADD COLUMN rejection_reason TEXT;
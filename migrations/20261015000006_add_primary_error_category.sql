@@ -0,0 +1,6 @@
+-- Store the category of the primary compilation error for analytics
+ALTER TABLE ai_audits ADD COLUMN primary_error_category TEXT;
+
+-- Create index on primary_error_category for category frequency queries
+CREATE INDEX idx_ai_audits_primary_error_category ON ai_audits(primary_error_category)
+    WHERE primary_error_category IS NOT NULL;
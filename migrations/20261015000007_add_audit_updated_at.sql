@@ -0,0 +1,5 @@
+-- Track when the verdict of an audit last changed
+ALTER TABLE ai_audits ADD COLUMN updated_at TIMESTAMPTZ;
+UPDATE ai_audits SET updated_at = created_at;
+ALTER TABLE ai_audits ALTER COLUMN updated_at SET NOT NULL;
+ALTER TABLE ai_audits ALTER COLUMN updated_at SET DEFAULT NOW();
@@ -0,0 +1,15 @@
+-- Store the size of the generated code for analytics
+ALTER TABLE ai_audits ADD COLUMN code_line_count INTEGER;
+ALTER TABLE ai_audits ADD COLUMN code_char_count INTEGER;
+
+-- Backfill existing audits; a trailing newline does not start a new line
+UPDATE ai_audits SET
+    code_line_count = cardinality(string_to_array(generated_code, E'\n'))
+        - CASE WHEN generated_code LIKE E'%\n' THEN 1 ELSE 0 END,
+    code_char_count = char_length(generated_code);
+
+ALTER TABLE ai_audits ALTER COLUMN code_line_count SET NOT NULL;
+ALTER TABLE ai_audits ALTER COLUMN code_char_count SET NOT NULL;
+
+-- Create index on code_line_count for size filters
+CREATE INDEX idx_ai_audits_code_line_count ON ai_audits(code_line_count);
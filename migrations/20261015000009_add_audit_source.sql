@@ -0,0 +1,8 @@
+-- Record where audits created by integrations came from
+ALTER TABLE ai_audits ADD COLUMN source_repository TEXT;
+ALTER TABLE ai_audits ADD COLUMN source_pull_request INTEGER;
+ALTER TABLE ai_audits ADD COLUMN source_path TEXT;
+
+-- Create index on the source pull request for per-PR lookups
+CREATE INDEX idx_ai_audits_source ON ai_audits(source_repository, source_pull_request)
+    WHERE source_repository IS NOT NULL;
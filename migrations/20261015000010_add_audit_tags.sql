@@ -0,0 +1,5 @@
+-- Attach free-form labels (project, dataset, experiment) to audits
+ALTER TABLE ai_audits ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}';
+
+-- Create GIN index on tags for containment (@>) filters
+CREATE INDEX idx_ai_audits_tags ON ai_audits USING GIN (tags);
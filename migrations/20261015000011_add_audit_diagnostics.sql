@@ -0,0 +1,2 @@
+-- Store the structured compiler diagnostics of each audit
+ALTER TABLE ai_audits ADD COLUMN diagnostics JSONB NOT NULL DEFAULT '[]';
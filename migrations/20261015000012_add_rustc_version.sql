@@ -0,0 +1,6 @@
+-- Record the rustc version that produced the verdict of each audit
+ALTER TABLE ai_audits ADD COLUMN rustc_version TEXT;
+
+-- Create index on rustc_version for per-toolchain lookups
+CREATE INDEX idx_ai_audits_rustc_version ON ai_audits(rustc_version)
+    WHERE rustc_version IS NOT NULL;
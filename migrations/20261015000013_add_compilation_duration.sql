@@ -0,0 +1,2 @@
+-- Record how long rustc took to compile the code of each audit
+ALTER TABLE ai_audits ADD COLUMN compilation_duration_ms INTEGER;
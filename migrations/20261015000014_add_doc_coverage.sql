@@ -0,0 +1,2 @@
+-- Record the share of documented public items in the code of each audit
+ALTER TABLE ai_audits ADD COLUMN doc_coverage_percent DOUBLE PRECISION;
@@ -0,0 +1,2 @@
+-- Record the release channel of the toolchain compiling each audit
+ALTER TABLE ai_audits ADD COLUMN channel TEXT NOT NULL DEFAULT 'stable';
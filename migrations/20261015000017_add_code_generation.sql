@@ -0,0 +1,7 @@
+-- Record how the code of audits generated server-side was produced, for cost tracking
+ALTER TABLE ai_audits ADD COLUMN generation_provider TEXT;
+ALTER TABLE ai_audits ADD COLUMN generation_model TEXT;
+ALTER TABLE ai_audits ADD COLUMN raw_model_output TEXT;
+ALTER TABLE ai_audits ADD COLUMN prompt_tokens INTEGER;
+ALTER TABLE ai_audits ADD COLUMN completion_tokens INTEGER;
+ALTER TABLE ai_audits ADD COLUMN generation_latency_ms INTEGER;
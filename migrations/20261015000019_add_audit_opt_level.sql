@@ -0,0 +1,2 @@
+-- Record the optimization level each audit is compiled with
+ALTER TABLE ai_audits ADD COLUMN opt_level TEXT NOT NULL DEFAULT '0';
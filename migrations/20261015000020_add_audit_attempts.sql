@@ -0,0 +1,4 @@
+-- Link the audits produced by the automatic fix loop to the audit they try to fix
+ALTER TABLE ai_audits ADD COLUMN parent_audit_id UUID REFERENCES ai_audits(id);
+ALTER TABLE ai_audits ADD COLUMN attempt_number INTEGER NOT NULL DEFAULT 1;
+CREATE INDEX idx_ai_audits_parent_audit_id ON ai_audits(parent_audit_id);
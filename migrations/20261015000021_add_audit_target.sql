@@ -0,0 +1,4 @@
+-- Record the target triple each audit is compiled for (NULL for the host)
+ALTER TABLE ai_audits ADD COLUMN target TEXT;
+
+CREATE INDEX idx_ai_audits_target ON ai_audits(target);
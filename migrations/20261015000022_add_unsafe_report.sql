@@ -0,0 +1,2 @@
+-- Store the analysis of the unsafe code of each audit
+ALTER TABLE ai_audits ADD COLUMN unsafe_report JSONB;
@@ -0,0 +1,2 @@
+-- Store the debug output, TODO comments and placeholders left in the code of each audit
+ALTER TABLE ai_audits ADD COLUMN hygiene_report JSONB;
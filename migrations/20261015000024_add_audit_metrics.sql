@@ -0,0 +1,2 @@
+-- Store the numbers derived from the analyses of each audit
+ALTER TABLE ai_audits ADD COLUMN metrics JSONB;
@@ -0,0 +1,3 @@
+-- Record the code as formatted by rustfmt and whether it differs from the submitted code
+ALTER TABLE ai_audits ADD COLUMN formatted_code TEXT;
+ALTER TABLE ai_audits ADD COLUMN needs_formatting BOOLEAN;
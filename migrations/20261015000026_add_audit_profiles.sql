@@ -0,0 +1,37 @@
+-- Named sets of enabled validation rules and quality score weights, selectable per audit
+CREATE TABLE audit_profiles (
+    name TEXT PRIMARY KEY,
+    enabled_rules TEXT[] NOT NULL,
+    weights JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO audit_profiles (name, enabled_rules, weights) VALUES
+    (
+        'default',
+        ARRAY['RAA0001', 'RAA0002', 'RAA0003', 'RAA0004', 'RAA0005', 'RAA0006', 'RAA0007',
+              'RAA0008', 'RAA0009', 'RAA0010', 'RAA0011', 'RAA0101', 'RAA0102', 'RAA0103',
+              'RAA0104', 'RAA0105', 'RAA0201', 'RAA0202'],
+        '{"compilation": 40, "documentation": 20, "hygiene": 20, "cleanliness": 20,
+          "warning_penalty": 5, "error_penalty": 10, "security_penalty": 25}'
+    ),
+    (
+        'strict',
+        ARRAY['RAA0001', 'RAA0002', 'RAA0003', 'RAA0004', 'RAA0005', 'RAA0006', 'RAA0007',
+              'RAA0008', 'RAA0009', 'RAA0010', 'RAA0011', 'RAA0101', 'RAA0102', 'RAA0103',
+              'RAA0104', 'RAA0105', 'RAA0201', 'RAA0202'],
+        '{"compilation": 40, "documentation": 20, "hygiene": 20, "cleanliness": 20,
+          "warning_penalty": 10, "error_penalty": 20, "security_penalty": 50}'
+    ),
+    (
+        'compile-only',
+        ARRAY[]::TEXT[],
+        '{"compilation": 100, "documentation": 0, "hygiene": 0, "cleanliness": 0,
+          "warning_penalty": 0, "error_penalty": 0, "security_penalty": 0}'
+    );
+
+-- The profile applied to each audit, and its weights at the time, so that editing the
+-- profile later does not change how the audit is scored
+ALTER TABLE ai_audits ADD COLUMN profile TEXT;
+ALTER TABLE ai_audits ADD COLUMN profile_weights JSONB;
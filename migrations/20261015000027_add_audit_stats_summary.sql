@@ -0,0 +1,34 @@
+-- Keep running totals of the audits, so that statistics do not aggregate the whole table
+CREATE TABLE audit_stats_summary (
+    -- A single row, enforced by the primary key and the check
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    total_audits BIGINT NOT NULL,
+    valid_audits BIGINT NOT NULL,
+    total_code_lines BIGINT NOT NULL,
+    total_code_chars BIGINT NOT NULL,
+    max_code_line_count BIGINT NOT NULL,
+    scored_audits BIGINT NOT NULL,
+    total_quality_score DOUBLE PRECISION NOT NULL,
+    timed_audits BIGINT NOT NULL,
+    total_compilation_ms BIGINT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO audit_stats_summary (
+    total_audits, valid_audits, total_code_lines, total_code_chars, max_code_line_count,
+    scored_audits, total_quality_score, timed_audits, total_compilation_ms
+)
+SELECT
+    COUNT(*),
+    COUNT(*) FILTER (WHERE is_valid = true),
+    COALESCE(SUM(code_line_count), 0),
+    COALESCE(SUM(code_char_count), 0),
+    COALESCE(MAX(code_line_count), 0),
+    COUNT(metrics->'quality_score'),
+    COALESCE(SUM((metrics->>'quality_score')::float8), 0),
+    COUNT(compilation_duration_ms),
+    COALESCE(SUM(compilation_duration_ms), 0)
+FROM ai_audits;
+
+-- Find the last write to the audits quickly, to detect a summary that drifted
+CREATE INDEX idx_ai_audits_updated_at ON ai_audits(updated_at);
@@ -0,0 +1,4 @@
+-- Find audits with near-identical code by trigram similarity
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX idx_ai_audits_generated_code_trgm ON ai_audits USING GIN (generated_code gin_trgm_ops);
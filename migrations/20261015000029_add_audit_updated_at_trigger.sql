@@ -0,0 +1,18 @@
+-- Keep updated_at current when an audit is changed by a statement that does not set it,
+-- such as a manual fix. Clearing an expired idempotency key is bookkeeping, not a change.
+CREATE FUNCTION touch_ai_audit_updated_at() RETURNS trigger AS $$
+BEGIN
+    IF to_jsonb(NEW) - 'idempotency_key' - 'request_fingerprint'
+        IS DISTINCT FROM to_jsonb(OLD) - 'idempotency_key' - 'request_fingerprint' THEN
+        NEW.updated_at := NOW();
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Statements setting updated_at themselves also update the statistics summary
+CREATE TRIGGER ai_audits_touch_updated_at
+    BEFORE UPDATE ON ai_audits
+    FOR EACH ROW
+    WHEN (OLD.updated_at IS NOT DISTINCT FROM NEW.updated_at)
+    EXECUTE FUNCTION touch_ai_audit_updated_at();
@@ -0,0 +1,22 @@
+-- Audits submitted in the background wait in the 'pending' status until a job compiles them
+ALTER TABLE ai_audits DROP CONSTRAINT ai_audits_status_check;
+ALTER TABLE ai_audits ADD CONSTRAINT ai_audits_status_check
+    CHECK (status IN ('valid', 'compile_error', 'rejected', 'pending'));
+
+-- The queue of audits to compile, shared by every replica of the server
+CREATE TABLE audit_jobs (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    audit_id UUID NOT NULL REFERENCES ai_audits (id) ON DELETE CASCADE,
+    -- 'dead' jobs failed too many times and are only retried by an administrator
+    state TEXT NOT NULL DEFAULT 'pending' CHECK (state IN ('pending', 'running', 'done', 'dead')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    run_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    locked_at TIMESTAMPTZ,
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Workers only look for jobs that are waiting or whose worker may have died
+CREATE INDEX audit_jobs_claimable_idx ON audit_jobs (run_at) WHERE state IN ('pending', 'running');
+CREATE INDEX audit_jobs_audit_id_idx ON audit_jobs (audit_id);
@@ -0,0 +1,9 @@
+-- Quality ratings of audits by external reviewers, one per reviewer and audit
+CREATE TABLE audit_ratings (
+    audit_id UUID NOT NULL REFERENCES ai_audits(id) ON DELETE CASCADE,
+    rater TEXT NOT NULL,
+    score SMALLINT NOT NULL CHECK (score BETWEEN 1 AND 5),
+    comment TEXT,
+    rated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (audit_id, rater)
+);
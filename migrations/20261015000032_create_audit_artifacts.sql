@@ -0,0 +1,10 @@
+-- The files kept from the compilation of an audit, uploaded to the artifact store after
+-- the audit is stored
+CREATE TABLE audit_artifacts (
+    audit_id UUID PRIMARY KEY REFERENCES ai_audits (id) ON DELETE CASCADE,
+    -- The keys of the uploaded files in the store, e.g. 'audits/<id>/diagnostics.json'
+    keys TEXT[] NOT NULL DEFAULT '{}',
+    -- Why some files could not be produced or uploaded; the audit itself is unaffected
+    error TEXT,
+    uploaded_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
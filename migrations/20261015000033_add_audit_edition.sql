@@ -0,0 +1,4 @@
+-- The Rust edition audits are compiled with. Audits created before were compiled with the
+-- default of rustc (2015) without recording it, and keep a NULL edition
+ALTER TABLE ai_audits ADD COLUMN edition TEXT
+    CHECK (edition IN ('2015', '2018', '2021', '2024'));
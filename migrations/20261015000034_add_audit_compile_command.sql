@@ -0,0 +1,3 @@
+-- The rustc invocation that produced the verdict of compiled audits, without the paths of
+-- the temporary crate
+ALTER TABLE ai_audits ADD COLUMN compile_command TEXT[];
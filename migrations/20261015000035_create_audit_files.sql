@@ -0,0 +1,8 @@
+-- The files of audits submitted as several files. The code of the crate root is also
+-- kept in ai_audits.generated_code; audits of a single file have no rows here
+CREATE TABLE audit_files (
+    audit_id UUID NOT NULL REFERENCES ai_audits(id) ON DELETE CASCADE,
+    path TEXT NOT NULL,
+    content TEXT NOT NULL,
+    PRIMARY KEY (audit_id, path)
+);
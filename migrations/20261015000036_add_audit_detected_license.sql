@@ -0,0 +1,24 @@
+-- The license expression of the SPDX-License-Identifier comment in the first ten lines of
+-- the code of audits. NULL when the code declares no license
+ALTER TABLE ai_audits ADD COLUMN detected_license TEXT;
+
+UPDATE ai_audits a SET detected_license = license.expression
+FROM (
+    SELECT DISTINCT ON (id) id, expression
+    FROM (
+        SELECT id, n, btrim(
+            regexp_replace(split_part(line, 'SPDX-License-Identifier:', 2), '\*/\s*$', ''),
+            E' \t\r'
+        ) AS expression
+        FROM ai_audits,
+            unnest((string_to_array(generated_code, E'\n'))[1:10]) WITH ORDINALITY AS l (line, n)
+        WHERE line ~ '^\s*(//|/\*|\*)' AND line LIKE '%SPDX-License-Identifier:%'
+    ) candidates
+    WHERE expression <> ''
+    ORDER BY id, n
+) license
+WHERE a.id = license.id;
+
+-- Code without a license header is noted by the profiles checking every rule
+UPDATE audit_profiles SET enabled_rules = array_append(enabled_rules, 'RAA0013')
+WHERE name IN ('default', 'strict');
@@ -0,0 +1,4 @@
+-- Imports of standard library paths that do not exist are reported by the profiles
+-- checking every rule
+UPDATE audit_profiles SET enabled_rules = array_append(enabled_rules, 'RAA0014')
+WHERE name IN ('default', 'strict');
@@ -0,0 +1,22 @@
+-- Estimates of the number of tokens of the prompts and code of audits: each run of letters,
+-- digits and underscores counts as one token, and so does every other non-blank character
+ALTER TABLE ai_audits
+    ADD COLUMN prompt_token_count INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN code_token_count INTEGER NOT NULL DEFAULT 0;
+
+UPDATE ai_audits SET
+    prompt_token_count = regexp_count(prompt, '[[:alnum:]_]+|[^[:alnum:]_[:space:]]'),
+    code_token_count = regexp_count(generated_code, '[[:alnum:]_]+|[^[:alnum:]_[:space:]]');
+
+ALTER TABLE ai_audits
+    ALTER COLUMN prompt_token_count DROP DEFAULT,
+    ALTER COLUMN code_token_count DROP DEFAULT;
+
+-- The running totals of the token counts
+ALTER TABLE audit_stats_summary
+    ADD COLUMN total_prompt_tokens BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN total_code_tokens BIGINT NOT NULL DEFAULT 0;
+
+UPDATE audit_stats_summary SET
+    total_prompt_tokens = (SELECT COALESCE(SUM(prompt_token_count), 0) FROM ai_audits),
+    total_code_tokens = (SELECT COALESCE(SUM(code_token_count), 0) FROM ai_audits);
@@ -0,0 +1,4 @@
+-- The edition guessed from the code of audits whose request did not choose one, which is
+-- also their edition. NULL when the request chose the edition
+ALTER TABLE ai_audits ADD COLUMN edition_detected TEXT
+    CHECK (edition_detected IN ('2015', '2018', '2021', '2024'));
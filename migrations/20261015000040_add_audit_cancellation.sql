@@ -0,0 +1,8 @@
+-- Background audits can be cancelled before they get their verdict
+ALTER TABLE ai_audits DROP CONSTRAINT ai_audits_status_check;
+ALTER TABLE ai_audits ADD CONSTRAINT ai_audits_status_check
+    CHECK (status IN ('valid', 'compile_error', 'rejected', 'pending', 'cancelled'));
+
+ALTER TABLE audit_jobs DROP CONSTRAINT audit_jobs_state_check;
+ALTER TABLE audit_jobs ADD CONSTRAINT audit_jobs_state_check
+    CHECK (state IN ('pending', 'running', 'done', 'dead', 'cancelled'));
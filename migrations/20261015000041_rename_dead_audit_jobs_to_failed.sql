@@ -0,0 +1,5 @@
+-- Jobs that failed too many times are reported as 'failed' rather than 'dead'
+ALTER TABLE audit_jobs DROP CONSTRAINT audit_jobs_state_check;
+UPDATE audit_jobs SET state = 'failed' WHERE state = 'dead';
+ALTER TABLE audit_jobs ADD CONSTRAINT audit_jobs_state_check
+    CHECK (state IN ('pending', 'running', 'done', 'failed', 'cancelled'));
@@ -0,0 +1,12 @@
+-- The number of RustSec advisories affecting the dependencies of Cargo packages, NULL when
+-- they were not checked, and why they were not checked
+ALTER TABLE ai_audits
+    ADD COLUMN vulnerability_count INTEGER,
+    ADD COLUMN advisory_note TEXT;
+
+-- The running total of the advisories
+ALTER TABLE audit_stats_summary ADD COLUMN total_vulnerabilities BIGINT NOT NULL DEFAULT 0;
+
+-- Vulnerable dependencies are reported by the profiles checking every rule
+UPDATE audit_profiles SET enabled_rules = array_append(enabled_rules, 'RAA0203')
+WHERE name IN ('default', 'strict');
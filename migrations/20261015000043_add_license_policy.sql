@@ -0,0 +1,18 @@
+-- The licenses the dependencies of audited Cargo packages may use, in a single row
+CREATE TABLE license_policy (
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    allowed TEXT[] NOT NULL,
+    denied TEXT[] NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO license_policy (allowed, denied)
+VALUES (ARRAY['MIT', 'Apache-2.0', 'BSD-*'], ARRAY['AGPL-*', 'GPL-*']);
+
+-- The number of dependencies whose license the policy does not allow, NULL when they were
+-- not checked
+ALTER TABLE ai_audits ADD COLUMN license_violation_count INTEGER;
+
+-- License violations are reported by the profiles checking every rule
+UPDATE audit_profiles SET enabled_rules = array_append(enabled_rules, 'RAA0301')
+WHERE name IN ('default', 'strict');
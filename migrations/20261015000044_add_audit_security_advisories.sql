@@ -0,0 +1,4 @@
+-- The advisories `cargo audit` reported for the dependencies of audited Cargo packages,
+-- NULL when it did not run
+ALTER TABLE ai_audits ADD COLUMN security_advisories JSONB;
+ALTER TABLE ai_audits ADD COLUMN has_security_issues BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,566 @@
+//! Checks the dependencies of Cargo packages against the RustSec advisory database.
+//!
+//! The database is a git repository of advisories, cloned into a local directory and
+//! fetched again periodically by `run_refresh`; between refreshes, and whenever the
+//! repository cannot be reached, the local copy is used as is. After cargo resolved the
+//! dependencies of an audited package, its `Cargo.lock` is checked against the database
+//! and every advisory affecting a dependency becomes a `RAA0203` finding. When no copy of
+//! the database could be loaded, audits note it instead of failing.
+//!
+//! Servers with `cargo-audit` installed also run `cargo audit` on the `Cargo.lock`, whose
+//! report is stored as the `security_advisories` of the audit.
+
+use crate::{
+    auditor::{MANIFEST, VULNERABLE_DEPENDENCY_CODE},
+    models::{Finding, FindingCategory, SecurityAdvisory, Severity},
+};
+use anyhow::Context;
+use cvss::Cvss;
+use rustsec::{Database, Lockfile, Vulnerability, advisory};
+use serde::Deserialize;
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tokio::process::Command;
+
+/// The repository the database is fetched from when `ADVISORY_DB_URL` is not set.
+pub const DEFAULT_URL: &str = "https://github.com/rustsec/advisory-db";
+
+/// How often the database is fetched when `ADVISORY_DB_REFRESH_SECS` is not set.
+const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long fetching the database may take before `git` is killed.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How long `cargo audit` may take before it is killed.
+const CARGO_AUDIT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The note of the audits whose dependencies could not be checked because no copy of the
+/// database could be loaded.
+pub const UNAVAILABLE: &str = "advisory DB unavailable";
+
+/// The advisory database: where it is kept and fetched from, and its loaded copy.
+#[derive(Clone)]
+pub struct AdvisoryDatabase {
+    inner: Arc<AdvisoryState>,
+}
+
+/// The state behind an `AdvisoryDatabase`.
+struct AdvisoryState {
+    /// The directory holding the local copy of the database.
+    path: PathBuf,
+    /// The git repository the database is fetched from, or `None` to only use the local
+    /// copy.
+    url: Option<String>,
+    /// How often the database is fetched.
+    refresh_interval: Duration,
+    /// The advisories read from the local copy, once it could be loaded.
+    database: RwLock<Option<Arc<Database>>>,
+}
+
+impl Default for AdvisoryDatabase {
+    /// A database that is never fetched nor loaded, so that the dependencies of packages
+    /// are noted as not checked.
+    fn default() -> Self {
+        AdvisoryDatabase::new(default_path(), None, DEFAULT_REFRESH_INTERVAL)
+    }
+}
+
+impl AdvisoryDatabase {
+    /// Creates a database kept in `path` and fetched from `url`. Nothing is read until
+    /// `refresh` or `run_refresh` runs.
+    pub fn new(path: impl Into<PathBuf>, url: Option<String>, refresh_interval: Duration) -> Self {
+        AdvisoryDatabase {
+            inner: Arc::new(AdvisoryState {
+                path: path.into(),
+                url,
+                refresh_interval,
+                database: RwLock::new(None),
+            }),
+        }
+    }
+
+    /// Configures the database from the environment.
+    ///
+    /// * `ADVISORY_DB_PATH` - The directory holding the local copy of the database
+    ///   (defaults to `$CARGO_HOME/advisory-db`, the copy `cargo audit` uses). A
+    ///   directory that is not a git repository is used as is, without being fetched.
+    /// * `ADVISORY_DB_URL` - The git repository the database is fetched from (defaults to
+    ///   the RustSec one). When empty, only the local copy is used.
+    /// * `ADVISORY_DB_REFRESH_SECS` - How often the database is fetched, in seconds
+    ///   (defaults to 86400).
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(AdvisoryDatabase)` - The configured database, not loaded yet.
+    /// * `Err(anyhow::Error)` - If `ADVISORY_DB_REFRESH_SECS` is not a positive integer.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let path = std::env::var("ADVISORY_DB_PATH")
+            .ok()
+            .filter(|path| !path.is_empty())
+            .map_or_else(default_path, PathBuf::from);
+        let url = match std::env::var("ADVISORY_DB_URL") {
+            Ok(url) if url.is_empty() => None,
+            Ok(url) => Some(url),
+            Err(_) => Some(DEFAULT_URL.to_string()),
+        };
+        let refresh_interval = match std::env::var("ADVISORY_DB_REFRESH_SECS") {
+            Ok(value) => value
+                .parse::<u64>()
+                .ok()
+                .filter(|&secs| secs > 0)
+                .map(Duration::from_secs)
+                .context("ADVISORY_DB_REFRESH_SECS must be a positive integer")?,
+            Err(_) => DEFAULT_REFRESH_INTERVAL,
+        };
+        Ok(AdvisoryDatabase::new(path, url, refresh_interval))
+    }
+
+    /// Loads the local copy of the database, then fetches and loads it again every
+    /// refresh interval, for as long as the server runs.
+    pub async fn run_refresh(self) {
+        self.load().await;
+        let mut interval = tokio::time::interval(self.inner.refresh_interval);
+        loop {
+            interval.tick().await;
+            self.refresh().await;
+        }
+    }
+
+    /// Fetches the database, then loads its local copy.
+    ///
+    /// The local copy is loaded even if it could not be fetched, e.g. when the server is
+    /// offline, so that the advisories known so far are still checked.
+    pub async fn refresh(&self) {
+        if let Some(url) = &self.inner.url
+            && let Err(e) = self.fetch(url).await
+        {
+            tracing::warn!(error = %e, url, "Failed to fetch the advisory database");
+        }
+        self.load().await;
+    }
+
+    /// Clones the database into its directory, or fetches its latest commit if it was
+    /// cloned already. A directory that is not a git repository is left alone.
+    async fn fetch(&self, url: &str) -> anyhow::Result<()> {
+        let path = &self.inner.path;
+        if path.join(".git").is_dir() {
+            run_git(
+                Command::new("git")
+                    .arg("-C")
+                    .arg(path)
+                    .args(["fetch", "--quiet", "--depth", "1", url, "HEAD"]),
+            )
+            .await?;
+            run_git(Command::new("git").arg("-C").arg(path).args([
+                "reset",
+                "--quiet",
+                "--hard",
+                "FETCH_HEAD",
+            ]))
+            .await?;
+        } else if !path.exists() {
+            // Cloned aside, so that an interrupted clone never looks like a local copy.
+            let mut partial = path.clone().into_os_string();
+            partial.push(".partial");
+            let partial = PathBuf::from(partial);
+            let _ = tokio::fs::remove_dir_all(&partial).await;
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent)
+                    .await
+                    .with_context(|| format!("Failed to create {}", parent.display()))?;
+            }
+            run_git(
+                Command::new("git")
+                    .args(["clone", "--quiet", "--depth", "1", url])
+                    .arg(&partial),
+            )
+            .await?;
+            tokio::fs::rename(&partial, path)
+                .await
+                .with_context(|| format!("Failed to move the clone to {}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Reads the local copy of the database, replacing the advisories loaded before. They
+    /// are kept if it cannot be read.
+    async fn load(&self) {
+        let path = self.inner.path.clone();
+        match tokio::task::spawn_blocking(move || Database::open(&path)).await {
+            Ok(Ok(database)) => {
+                tracing::info!(
+                    path = %self.inner.path.display(),
+                    advisories = database.iter().count(),
+                    "Loaded the advisory database"
+                );
+                *self
+                    .inner
+                    .database
+                    .write()
+                    .unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(database));
+            }
+            Ok(Err(e)) => tracing::warn!(
+                error = %e,
+                path = %self.inner.path.display(),
+                "Failed to load the advisory database"
+            ),
+            Err(e) => tracing::warn!(error = %e, "Failed to load the advisory database"),
+        }
+    }
+
+    /// Checks the dependencies locked by a `Cargo.lock` against the database.
+    ///
+    /// # Arguments
+    ///
+    /// * `lockfile` - The content of the `Cargo.lock` cargo resolved.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<Finding>)` - A `RAA0203` finding for each advisory affecting a locked
+    ///   version, whose severity follows the CVSS score of the advisory.
+    /// * `Err(String)` - Why the dependencies could not be checked: [`UNAVAILABLE`] if no
+    ///   copy of the database could be loaded, or the reason the lockfile is invalid.
+    pub fn check(&self, lockfile: &str) -> Result<Vec<Finding>, String> {
+        let database = self
+            .inner
+            .database
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+            .ok_or_else(|| UNAVAILABLE.to_string())?;
+        let lockfile: Lockfile = lockfile
+            .parse()
+            .map_err(|e| format!("Cargo.lock could not be read: {}", e))?;
+        Ok(database
+            .vulnerabilities(&lockfile)
+            .iter()
+            .map(vulnerability_finding)
+            .collect())
+    }
+}
+
+/// Runs `cargo audit` on the `Cargo.lock` of the Cargo package in `dir`.
+///
+/// `cargo audit` reads its own copy of the database (`~/.cargo/advisory-db`) without
+/// fetching it.
+///
+/// # Returns
+///
+/// * `Some(Vec<SecurityAdvisory>)` - The advisories affecting the locked packages.
+/// * `None` - If `cargo-audit` is not installed, or failed without a report.
+pub async fn cargo_audit(dir: &Path) -> Option<Vec<SecurityAdvisory>> {
+    let output = Command::new("cargo-audit")
+        .args(["audit", "--json", "--no-fetch", "--file", "Cargo.lock"])
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let output = match tokio::time::timeout(CARGO_AUDIT_TIMEOUT, output).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            tracing::debug!("cargo-audit is not installed");
+            return None;
+        }
+        Ok(Err(e)) => {
+            tracing::warn!(error = %e, "Failed to execute cargo-audit");
+            return None;
+        }
+        Err(_) => {
+            tracing::warn!("cargo-audit timed out");
+            return None;
+        }
+    };
+    // `cargo audit` exits with an error when it finds vulnerabilities, so its report is
+    // read whatever its status.
+    let advisories = parse_cargo_audit(&String::from_utf8_lossy(&output.stdout));
+    if advisories.is_none() {
+        tracing::warn!(
+            stderr = %String::from_utf8_lossy(&output.stderr).trim(),
+            "cargo-audit failed"
+        );
+    }
+    advisories
+}
+
+/// Parses the vulnerabilities of the JSON report of `cargo audit`.
+fn parse_cargo_audit(report: &str) -> Option<Vec<SecurityAdvisory>> {
+    #[derive(Deserialize)]
+    struct Report {
+        vulnerabilities: Vulnerabilities,
+    }
+    #[derive(Deserialize)]
+    struct Vulnerabilities {
+        list: Vec<ReportedVulnerability>,
+    }
+    #[derive(Deserialize)]
+    struct ReportedVulnerability {
+        advisory: Advisory,
+        package: Package,
+    }
+    #[derive(Deserialize)]
+    struct Advisory {
+        id: String,
+        title: String,
+        cvss: Option<Cvss>,
+    }
+    #[derive(Deserialize)]
+    struct Package {
+        name: String,
+        version: String,
+    }
+
+    let report: Report = serde_json::from_str(report).ok()?;
+    Some(
+        report
+            .vulnerabilities
+            .list
+            .into_iter()
+            .map(|vulnerability| SecurityAdvisory {
+                package: format!(
+                    "{} {}",
+                    vulnerability.package.name, vulnerability.package.version
+                ),
+                vulnerability_id: vulnerability.advisory.id,
+                title: vulnerability.advisory.title,
+                severity: vulnerability
+                    .advisory
+                    .cvss
+                    .map_or("unknown", |cvss| cvss.severity().as_str())
+                    .to_string(),
+            })
+            .collect(),
+    )
+}
+
+/// Returns the directory of the local copy of the database when `ADVISORY_DB_PATH` is not
+/// set: `advisory-db` in the home directory of cargo.
+fn default_path() -> PathBuf {
+    std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cargo")))
+        .unwrap_or_default()
+        .join("advisory-db")
+}
+
+/// Runs a `git` command, failing if it fails or takes longer than [`FETCH_TIMEOUT`].
+async fn run_git(command: &mut Command) -> anyhow::Result<()> {
+    let output = command
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(FETCH_TIMEOUT, output)
+        .await
+        .context("git timed out")?
+        .context("Failed to execute git")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Returns the severity of the finding reporting an advisory with a given CVSS severity:
+/// an error for high and critical ones, a warning for medium ones and those without a
+/// score, and information for the others.
+fn finding_severity(severity: Option<advisory::Severity>) -> Severity {
+    match severity {
+        Some(advisory::Severity::High | advisory::Severity::Critical) => Severity::Error,
+        Some(advisory::Severity::Medium) | None => Severity::Warning,
+        Some(advisory::Severity::Low | advisory::Severity::None) => Severity::Info,
+    }
+}
+
+/// Builds the finding reporting a dependency affected by an advisory.
+fn vulnerability_finding(vulnerability: &Vulnerability) -> Finding {
+    let advisory = &vulnerability.advisory;
+    let severity = advisory.cvss.as_ref().map(|cvss| cvss.severity());
+    let score = match severity {
+        Some(severity) => format!("{} severity", severity.as_str()),
+        None => "no CVSS score".to_string(),
+    };
+    Finding {
+        code: VULNERABLE_DEPENDENCY_CODE.to_string(),
+        severity: finding_severity(severity),
+        message: format!(
+            "Depends on {} {}, affected by {}: {} ({})",
+            vulnerability.package.name,
+            vulnerability.package.version,
+            advisory.id,
+            advisory.title,
+            score
+        ),
+        line: None,
+        category: Some(FindingCategory::Security),
+        file: Some(MANIFEST.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The vendored snapshot of the database, in which `regex` is affected by
+    /// RUSTSEC-2022-0013 (high) before 1.5.5 and `rsa` by RUSTSEC-2023-0071 (medium).
+    const SNAPSHOT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/advisory-db");
+
+    /// Returns a `Cargo.lock` locking crates.io packages at the given versions.
+    fn lockfile(packages: &[(&str, &str)]) -> String {
+        let mut lockfile = "version = 4\n".to_string();
+        for (name, version) in packages {
+            lockfile.push_str(&format!(
+                "\n[[package]]\nname = \"{}\"\nversion = \"{}\"\n\
+                 source = \"registry+https://github.com/rust-lang/crates.io-index\"\n",
+                name, version
+            ));
+        }
+        lockfile
+    }
+
+    async fn snapshot() -> AdvisoryDatabase {
+        let database = AdvisoryDatabase::new(SNAPSHOT, None, DEFAULT_REFRESH_INTERVAL);
+        database.refresh().await;
+        database
+    }
+
+    #[tokio::test]
+    async fn affected_versions_are_reported_with_the_severity_of_their_score() {
+        let database = snapshot().await;
+        let mut findings = database
+            .check(&lockfile(&[
+                ("regex", "1.5.4"),
+                ("rsa", "0.9.10"),
+                ("serde", "1.0.228"),
+            ]))
+            .unwrap();
+        findings.sort_by(|a, b| a.message.cmp(&b.message));
+
+        assert_eq!(findings.len(), 2, "{:?}", findings);
+        assert!(findings.iter().all(|finding| {
+            finding.code == VULNERABLE_DEPENDENCY_CODE
+                && finding.category == Some(FindingCategory::Security)
+                && finding.file.as_deref() == Some(MANIFEST)
+        }));
+        assert_eq!(findings[0].severity, Severity::Error);
+        assert!(
+            findings[0]
+                .message
+                .starts_with("Depends on regex 1.5.4, affected by RUSTSEC-2022-0013: "),
+            "{}",
+            findings[0].message
+        );
+        assert!(findings[0].message.ends_with("(high severity)"));
+        assert_eq!(findings[1].severity, Severity::Warning);
+        assert!(
+            findings[1]
+                .message
+                .starts_with("Depends on rsa 0.9.10, affected by RUSTSEC-2023-0071: "),
+            "{}",
+            findings[1].message
+        );
+        assert!(findings[1].message.ends_with("(medium severity)"));
+    }
+
+    #[tokio::test]
+    async fn patched_versions_are_not_reported() {
+        let database = snapshot().await;
+        assert!(
+            database
+                .check(&lockfile(&[("regex", "1.5.5")]))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_lockfiles_are_not_checked() {
+        let database = snapshot().await;
+        let error = database.check("[[package]]\nname = 1\n").unwrap_err();
+        assert!(
+            error.starts_with("Cargo.lock could not be read: "),
+            "{}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn an_unreachable_database_is_unavailable() {
+        let path = std::env::temp_dir().join(format!("advisory-db-{}", uuid::Uuid::new_v4()));
+        let database = AdvisoryDatabase::new(
+            &path,
+            Some("http://127.0.0.1:1/advisory-db".to_string()),
+            DEFAULT_REFRESH_INTERVAL,
+        );
+        database.refresh().await;
+
+        assert_eq!(database.check(&lockfile(&[])).unwrap_err(), UNAVAILABLE);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn severities_follow_the_cvss_score() {
+        assert_eq!(
+            finding_severity(Some(advisory::Severity::Critical)),
+            Severity::Error
+        );
+        assert_eq!(
+            finding_severity(Some(advisory::Severity::High)),
+            Severity::Error
+        );
+        assert_eq!(
+            finding_severity(Some(advisory::Severity::Medium)),
+            Severity::Warning
+        );
+        assert_eq!(finding_severity(None), Severity::Warning);
+        assert_eq!(
+            finding_severity(Some(advisory::Severity::Low)),
+            Severity::Info
+        );
+        assert_eq!(
+            finding_severity(Some(advisory::Severity::None)),
+            Severity::Info
+        );
+    }
+
+    #[test]
+    fn cargo_audit_reports_name_the_package_and_the_severity() {
+        let report = include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/cargo-audit/report.json"
+        ));
+        assert_eq!(
+            parse_cargo_audit(report).unwrap(),
+            [
+                SecurityAdvisory {
+                    package: "rsa 0.9.10".to_string(),
+                    vulnerability_id: "RUSTSEC-2023-0071".to_string(),
+                    title: "Marvin Attack: potential key recovery through timing sidechannels"
+                        .to_string(),
+                    severity: "medium".to_string(),
+                },
+                SecurityAdvisory {
+                    package: "demo-parser 1.0.0".to_string(),
+                    vulnerability_id: "RUSTSEC-2025-0001".to_string(),
+                    title: "Unchecked input length".to_string(),
+                    severity: "unknown".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_cargo_audit_reports_are_ignored() {
+        assert_eq!(
+            parse_cargo_audit(r#"{"vulnerabilities":{"found":false,"count":0,"list":[]}}"#),
+            Some(Vec::new())
+        );
+        assert_eq!(parse_cargo_audit(""), None);
+        assert_eq!(parse_cargo_audit(r#"{"error":"no Cargo.lock"}"#), None);
+    }
+}
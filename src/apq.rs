@@ -0,0 +1,249 @@
+//! Implements Apollo's Automatic Persisted Queries (APQ) protocol for the GraphQL endpoint.
+//!
+//! Clients first send only the SHA-256 hash of a query in `extensions.persistedQuery`.
+//! If the server does not know the hash, it answers with a `PERSISTED_QUERY_NOT_FOUND`
+//! error and the client retries with both the query and its hash, which registers it.
+//!
+//! [Reference](https://www.apollographql.com/docs/apollo-server/performance/apq/)
+
+use crate::models::ApqStats;
+use anyhow::Context;
+use async_graphql::{
+    ErrorExtensionValues, Request, ServerError, ServerResult,
+    extensions::{Extension, ExtensionContext, ExtensionFactory, NextPrepareRequest},
+};
+use lru::LruCache;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+/// The number of queries kept in memory when `APQ_CACHE_CAPACITY` is not set.
+const DEFAULT_CACHE_CAPACITY: usize = 1000;
+
+/// The `extensions.persistedQuery` object sent by APQ clients.
+#[derive(Deserialize)]
+struct PersistedQuery {
+    version: i32,
+    #[serde(rename = "sha256Hash")]
+    sha256_hash: String,
+}
+
+/// Stores persisted queries by hash and counts how often lookups succeed.
+///
+/// Queries are kept in an in-memory LRU cache, optionally backed by the
+/// `persisted_queries` table so that they survive restarts.
+pub struct PersistedQueryStore {
+    /// The most recently used queries, keyed by their SHA-256 hash.
+    cache: Mutex<LruCache<String, String>>,
+    /// The database pool backing the cache, if persistence is enabled.
+    pool: Option<PgPool>,
+    /// The number of hash-only requests whose query was found.
+    hits: AtomicU64,
+    /// The number of hash-only requests whose query was unknown.
+    misses: AtomicU64,
+    /// The number of queries registered by clients.
+    registrations: AtomicU64,
+}
+
+impl PersistedQueryStore {
+    /// Creates a store holding at most `capacity` queries in memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The capacity of the in-memory LRU cache.
+    /// * `pool` - The database pool to persist queries to, if any.
+    pub fn new(capacity: NonZeroUsize, pool: Option<PgPool>) -> Self {
+        Self {
+            cache: Mutex::new(LruCache::new(capacity)),
+            pool,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            registrations: AtomicU64::new(0),
+        }
+    }
+
+    /// Creates a store configured from the environment.
+    ///
+    /// * `APQ_CACHE_CAPACITY` - The number of queries kept in memory (defaults to 1000).
+    /// * `APQ_PERSIST` - When set to `true`, queries are also stored in Postgres.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(PersistedQueryStore)` - The configured store.
+    /// * `Err(anyhow::Error)` - If `APQ_CACHE_CAPACITY` is not a positive integer.
+    pub fn from_env(pool: &PgPool) -> anyhow::Result<Self> {
+        let capacity = match std::env::var("APQ_CACHE_CAPACITY") {
+            Ok(value) => value
+                .parse()
+                .context("APQ_CACHE_CAPACITY must be a positive integer")?,
+            Err(_) => NonZeroUsize::new(DEFAULT_CACHE_CAPACITY).expect("capacity is non-zero"),
+        };
+        let persist = std::env::var("APQ_PERSIST")
+            .map(|v| v.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+
+        tracing::info!(capacity, persist, "Automatic persisted queries enabled");
+        Ok(Self::new(capacity, persist.then(|| pool.clone())))
+    }
+
+    /// Looks up a query by hash, falling back to the database on a cache miss.
+    async fn get(&self, hash: &str) -> Option<String> {
+        let cached = self.lock_cache().get(hash).cloned();
+        let found = match (cached, &self.pool) {
+            (Some(query), _) => Some(query),
+            (None, Some(pool)) => {
+                let stored = sqlx::query_scalar!(
+                    "SELECT query FROM persisted_queries WHERE sha256_hash = $1",
+                    hash
+                )
+                .fetch_optional(pool)
+                .await;
+                match stored {
+                    Ok(stored) => stored.inspect(|query| {
+                        self.lock_cache().put(hash.to_string(), query.clone());
+                    }),
+                    Err(e) => {
+                        tracing::warn!(error = %e, "Failed to load persisted query");
+                        None
+                    }
+                }
+            }
+            (None, None) => None,
+        };
+
+        let counter = if found.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        found
+    }
+
+    /// Registers a query under its hash.
+    async fn register(&self, hash: &str, query: &str) {
+        self.lock_cache().put(hash.to_string(), query.to_string());
+        self.registrations.fetch_add(1, Ordering::Relaxed);
+
+        if let Some(pool) = &self.pool {
+            let stored = sqlx::query!(
+                "INSERT INTO persisted_queries (sha256_hash, query) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+                hash,
+                query
+            )
+            .execute(pool)
+            .await;
+            if let Err(e) = stored {
+                tracing::warn!(error = %e, "Failed to store persisted query");
+            }
+        }
+    }
+
+    /// Applies the APQ protocol to a request.
+    ///
+    /// A request carrying only a hash gets its query filled in from the store; a request
+    /// carrying both a query and its hash registers the query. The `persistedQuery`
+    /// extension is removed, so resolving an already resolved request does nothing.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The GraphQL request to resolve.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the request is ready to be executed.
+    /// * `Err(ServerError)` - If the extension is malformed, the hash does not match the
+    ///   query, or the hash is unknown (`PERSISTED_QUERY_NOT_FOUND`).
+    pub async fn resolve(&self, request: &mut Request) -> ServerResult<()> {
+        let Some(value) = request.extensions.remove("persistedQuery") else {
+            return Ok(());
+        };
+
+        let persisted_query: PersistedQuery = async_graphql::from_value(value)
+            .map_err(|_| ServerError::new("Invalid \"persistedQuery\" extension.", None))?;
+        if persisted_query.version != 1 {
+            return Err(ServerError::new(
+                format!(
+                    "Unsupported \"persistedQuery\" version {}; only version 1 is supported.",
+                    persisted_query.version
+                ),
+                None,
+            ));
+        }
+
+        if request.query.is_empty() {
+            match self.get(&persisted_query.sha256_hash).await {
+                Some(query) => request.query = query,
+                None => {
+                    let mut error = ServerError::new("PersistedQueryNotFound", None);
+                    let mut extensions = ErrorExtensionValues::default();
+                    extensions.set("code", "PERSISTED_QUERY_NOT_FOUND");
+                    error.extensions = Some(extensions);
+                    return Err(error);
+                }
+            }
+        } else {
+            let hash = hex::encode(Sha256::digest(request.query.as_bytes()));
+            if !hash.eq_ignore_ascii_case(&persisted_query.sha256_hash) {
+                return Err(ServerError::new("provided sha does not match query", None));
+            }
+            self.register(&hash, &request.query).await;
+        }
+        Ok(())
+    }
+
+    /// Returns the lookup counters of the store.
+    pub fn stats(&self) -> ApqStats {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let lookups = hits + misses;
+        ApqStats {
+            hits,
+            misses,
+            registrations: self.registrations.load(Ordering::Relaxed),
+            hit_rate: if lookups > 0 {
+                hits as f64 / lookups as f64
+            } else {
+                0.0
+            },
+        }
+    }
+
+    /// Locks the in-memory cache.
+    fn lock_cache(&self) -> std::sync::MutexGuard<'_, LruCache<String, String>> {
+        // The cache holds no invariants that a panic could break, so recover from poisoning.
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The GraphQL extension resolving persisted queries from a shared store.
+pub struct PersistedQueries(pub Arc<PersistedQueryStore>);
+
+impl ExtensionFactory for PersistedQueries {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(PersistedQueriesExtension(self.0.clone()))
+    }
+}
+
+/// The per-request instance of the `PersistedQueries` extension.
+struct PersistedQueriesExtension(Arc<PersistedQueryStore>);
+
+#[async_trait::async_trait]
+impl Extension for PersistedQueriesExtension {
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        mut request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        self.0.resolve(&mut request).await?;
+        next.run(ctx, request).await
+    }
+}
@@ -0,0 +1,416 @@
+//! Keeps the files produced by the compilation of audits in an artifact store.
+//!
+//! After an audit is stored, the JSON diagnostics of `rustc`, those of Clippy and the
+//! compiled library are uploaded under `audits/{id}/` on a spawned task, so the upload
+//! never delays the response. The keys of the uploaded files are recorded along with
+//! the audit, as is the reason of a failed upload, which leaves the verdict untouched.
+//! Files are stored on the local filesystem or in an S3-compatible bucket, and are
+//! downloaded through presigned URLs when the store can sign them, or through the server.
+
+use crate::{
+    auditor::{self, CompilationOutcome, CompileOptions},
+    error::AppError,
+    models::{ArtifactFile, AuditArtifacts},
+    services,
+};
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use object_store::{
+    ObjectStoreExt,
+    aws::{AmazonS3, AmazonS3Builder},
+    path::Path as StorePath,
+    signer::{Method, Signer},
+};
+use sqlx::PgPool;
+use std::{path::PathBuf, sync::Arc, time::Duration};
+use uuid::Uuid;
+
+/// How long download URLs are valid when `ARTIFACT_URL_TTL_SECS` is not set.
+const DEFAULT_URL_TTL: Duration = Duration::from_secs(900);
+
+/// The name of the JSON diagnostics written by `rustc`.
+pub const DIAGNOSTICS: &str = "diagnostics.json";
+
+/// The name of the JSON diagnostics written by Clippy.
+pub const CLIPPY: &str = "clippy.json";
+
+/// The name of the compiled library.
+pub const LIBRARY: &str = "lib.rlib";
+
+/// The names of the files an audit may have, in the order they are listed.
+pub const ARTIFACT_NAMES: [&str; 3] = [DIAGNOSTICS, CLIPPY, LIBRARY];
+
+/// A store of artifact files.
+#[async_trait]
+pub trait ArtifactStore: Send + Sync {
+    /// Stores `data` under `key`, replacing the file stored there if any.
+    async fn put(&self, key: &str, data: Vec<u8>) -> anyhow::Result<()>;
+
+    /// Returns the file stored under `key`, or `None` if there is none.
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>>;
+
+    /// Returns a URL granting access to the file stored under `key` for `expires_in`, or
+    /// `None` if the store cannot sign URLs, in which case the file is downloaded
+    /// through the server.
+    async fn signed_url(&self, key: &str, expires_in: Duration) -> anyhow::Result<Option<String>>;
+}
+
+/// A store keeping files in a directory of the local filesystem, one file per key.
+pub struct FsArtifactStore {
+    root: PathBuf,
+}
+
+impl FsArtifactStore {
+    /// Creates a store keeping files under `root`, which is created on the first upload.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        FsArtifactStore { root: root.into() }
+    }
+}
+
+#[async_trait]
+impl ArtifactStore for FsArtifactStore {
+    async fn put(&self, key: &str, data: Vec<u8>) -> anyhow::Result<()> {
+        let path = self.root.join(key);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        tokio::fs::write(&path, data)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let path = self.root.join(key);
+        match tokio::fs::read(&path).await {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    async fn signed_url(
+        &self,
+        _key: &str,
+        _expires_in: Duration,
+    ) -> anyhow::Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+/// A store keeping files in an S3-compatible bucket, downloaded through presigned URLs.
+pub struct S3ArtifactStore {
+    s3: AmazonS3,
+}
+
+impl S3ArtifactStore {
+    /// Creates a store keeping files in `bucket`.
+    ///
+    /// The credentials, region and endpoint are read from the usual `AWS_*` variables
+    /// (e.g. `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`, `AWS_REGION`, and
+    /// `AWS_ENDPOINT` with `AWS_ALLOW_HTTP` for a MinIO server).
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(S3ArtifactStore)` - The store. The bucket is only reached on the first upload.
+    /// * `Err(anyhow::Error)` - If the `AWS_*` variables are invalid.
+    pub fn new(bucket: &str) -> anyhow::Result<Self> {
+        let s3 = AmazonS3Builder::from_env()
+            .with_bucket_name(bucket)
+            .build()
+            .context("Invalid S3 configuration")?;
+        Ok(S3ArtifactStore { s3 })
+    }
+}
+
+#[async_trait]
+impl ArtifactStore for S3ArtifactStore {
+    async fn put(&self, key: &str, data: Vec<u8>) -> anyhow::Result<()> {
+        self.s3
+            .put(&StorePath::from(key), data.into())
+            .await
+            .with_context(|| format!("Failed to upload {}", key))?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let path = StorePath::from(key);
+        match self.s3.get(&path).await {
+            Ok(result) => {
+                let data = result
+                    .bytes()
+                    .await
+                    .with_context(|| format!("Failed to download {}", key))?;
+                Ok(Some(data.to_vec()))
+            }
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to download {}", key)),
+        }
+    }
+
+    async fn signed_url(&self, key: &str, expires_in: Duration) -> anyhow::Result<Option<String>> {
+        let url = self
+            .s3
+            .signed_url(Method::GET, &StorePath::from(key), expires_in)
+            .await
+            .with_context(|| format!("Failed to sign the URL of {}", key))?;
+        Ok(Some(url.to_string()))
+    }
+}
+
+/// The outputs of a compilation kept as the artifacts of an audit.
+#[derive(Debug, Clone)]
+pub struct CompiledArtifacts {
+    /// The settings the code was compiled with, which Clippy lints it with as well.
+    options: CompileOptions,
+    /// Whether the code compiled, in which case it is linted with Clippy.
+    compiled: bool,
+    /// The JSON lines written by `rustc`.
+    json_output: String,
+    /// The compiled library, unless the compilation result came from the cache.
+    library: Option<Vec<u8>>,
+}
+
+impl CompiledArtifacts {
+    /// Takes the JSON diagnostics and the library out of the outcome of a compilation.
+    pub fn take(outcome: &mut CompilationOutcome, options: CompileOptions) -> Self {
+        CompiledArtifacts {
+            options,
+            compiled: outcome.success,
+            json_output: std::mem::take(&mut outcome.json_output),
+            library: outcome.library.take(),
+        }
+    }
+
+    /// Returns a copy of the artifacts without the library, like those of a compilation
+    /// result taken from the cache.
+    pub fn without_library(&self) -> Self {
+        CompiledArtifacts {
+            options: self.options.clone(),
+            compiled: self.compiled,
+            json_output: self.json_output.clone(),
+            library: None,
+        }
+    }
+}
+
+/// The artifact store, if any, and the lifetime of the URLs it signs.
+#[derive(Clone)]
+pub struct ArtifactSettings {
+    /// The store of the files, or `None` if artifacts are not kept.
+    pub store: Option<Arc<dyn ArtifactStore>>,
+    /// How long download URLs are valid.
+    pub url_ttl: Duration,
+}
+
+impl Default for ArtifactSettings {
+    fn default() -> Self {
+        ArtifactSettings {
+            store: None,
+            url_ttl: DEFAULT_URL_TTL,
+        }
+    }
+}
+
+impl ArtifactSettings {
+    /// Configures the artifact store from the environment.
+    ///
+    /// * `ARTIFACT_S3_BUCKET` - The S3 bucket storing the files, accessed with the
+    ///   `AWS_*` variables (see `S3ArtifactStore::new`).
+    /// * `ARTIFACT_DIR` - The local directory storing the files, if no bucket is set.
+    /// * `ARTIFACT_URL_TTL_SECS` - How long presigned URLs are valid (defaults to 900).
+    ///
+    /// Artifacts are not kept when neither `ARTIFACT_S3_BUCKET` nor `ARTIFACT_DIR` is set.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ArtifactSettings)` - The configured store.
+    /// * `Err(anyhow::Error)` - If both stores are set, `ARTIFACT_URL_TTL_SECS` is not a
+    ///   positive integer, or the S3 configuration is invalid.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let url_ttl = match std::env::var("ARTIFACT_URL_TTL_SECS") {
+            Ok(value) => value
+                .parse::<u64>()
+                .ok()
+                .filter(|&secs| secs > 0)
+                .map(Duration::from_secs)
+                .context("ARTIFACT_URL_TTL_SECS must be a positive integer")?,
+            Err(_) => DEFAULT_URL_TTL,
+        };
+        let bucket = std::env::var("ARTIFACT_S3_BUCKET").unwrap_or_default();
+        let dir = std::env::var("ARTIFACT_DIR").unwrap_or_default();
+        let store: Option<Arc<dyn ArtifactStore>> = match (bucket.is_empty(), dir.is_empty()) {
+            (false, false) => {
+                anyhow::bail!("ARTIFACT_S3_BUCKET and ARTIFACT_DIR are mutually exclusive")
+            }
+            (false, true) => {
+                tracing::info!(bucket, "Keeping audit artifacts in S3");
+                Some(Arc::new(S3ArtifactStore::new(&bucket)?))
+            }
+            (true, false) => {
+                tracing::info!(dir, "Keeping audit artifacts on the local filesystem");
+                Some(Arc::new(FsArtifactStore::new(dir)))
+            }
+            (true, true) => None,
+        };
+        Ok(ArtifactSettings { store, url_ttl })
+    }
+
+    /// Uploads the artifacts of a stored audit on a spawned task, then records their
+    /// keys, or why some could not be uploaded, along with the audit.
+    ///
+    /// Does nothing if artifacts are not kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - A reference to the database connection pool.
+    /// * `audit_id` - The UUID of the audit.
+    /// * `code` - The audited code, linted with Clippy if it compiled.
+    /// * `artifacts` - The outputs of its compilation.
+    pub fn upload(&self, pool: &PgPool, audit_id: Uuid, code: &str, artifacts: CompiledArtifacts) {
+        let Some(store) = self.store.clone() else {
+            return;
+        };
+        let pool = pool.clone();
+        let code = code.to_string();
+        tokio::spawn(async move {
+            let (keys, errors) = upload_artifacts(store.as_ref(), audit_id, code, artifacts).await;
+            let error = (!errors.is_empty()).then(|| errors.join("; "));
+            if let Some(error) = &error {
+                tracing::warn!(audit = %audit_id, %error, "Could not upload every artifact of the audit.");
+            }
+            match services::record_audit_artifacts(&pool, audit_id, &keys, error.as_deref()).await {
+                Ok(()) => {
+                    tracing::debug!(audit = %audit_id, files = keys.len(), "Audit artifacts uploaded.")
+                }
+                Err(e) => {
+                    tracing::warn!(audit = %audit_id, error = %e, "Could not record the artifacts of the audit.");
+                }
+            }
+        });
+    }
+
+    /// Lists the artifacts recorded for an audit, with the URLs they can be downloaded
+    /// from.
+    ///
+    /// # Arguments
+    ///
+    /// * `audit_id` - The UUID of the audit.
+    /// * `keys` - The keys recorded for the audit.
+    /// * `error` - The error recorded for the audit, if any.
+    /// * `uploaded_at` - When the artifacts were recorded, or `None` if they were not.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(AuditArtifacts)` - The artifacts. None are listed if artifacts are no
+    ///   longer kept.
+    /// * `Err(AppError::Upstream)` - If a URL cannot be signed.
+    pub async fn links(
+        &self,
+        audit_id: Uuid,
+        keys: &[String],
+        error: Option<String>,
+        uploaded_at: Option<DateTime<Utc>>,
+    ) -> Result<AuditArtifacts, AppError> {
+        let mut files = Vec::new();
+        if let Some(store) = &self.store {
+            for key in keys {
+                let name = key.rsplit('/').next().unwrap_or(key).to_string();
+                let signed = store
+                    .signed_url(key, self.url_ttl)
+                    .await
+                    .map_err(|e| AppError::Upstream(format!("{:#}", e)))?;
+                let (url, expires_at) = match signed {
+                    Some(url) => (url, Some(Utc::now() + self.url_ttl)),
+                    None => (format!("/audit/{}/artifacts/{}", audit_id, name), None),
+                };
+                files.push(ArtifactFile {
+                    name,
+                    key: key.clone(),
+                    url,
+                    expires_at,
+                });
+            }
+        }
+        Ok(AuditArtifacts {
+            audit_id,
+            files,
+            error,
+            uploaded_at,
+        })
+    }
+
+    /// Downloads an artifact of an audit.
+    ///
+    /// # Arguments
+    ///
+    /// * `audit_id` - The UUID of the audit.
+    /// * `name` - The name of the file, one of [`ARTIFACT_NAMES`].
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<u8>)` - The content of the file.
+    /// * `Err(AppError::NotFound)` - If artifacts are not kept, the name is unknown, or
+    ///   the audit has no such file.
+    /// * `Err(AppError::Upstream)` - If the store cannot be read.
+    pub async fn download(&self, audit_id: Uuid, name: &str) -> Result<Vec<u8>, AppError> {
+        let store = self
+            .store
+            .as_ref()
+            .ok_or_else(|| AppError::NotFound("Audit artifacts are not kept".to_string()))?;
+        if !ARTIFACT_NAMES.contains(&name) {
+            return Err(AppError::NotFound(format!("Unknown artifact '{}'", name)));
+        }
+        store
+            .get(&artifact_key(audit_id, name))
+            .await
+            .map_err(|e| AppError::Upstream(format!("{:#}", e)))?
+            .ok_or_else(|| {
+                AppError::NotFound(format!("Audit {} has no artifact '{}'", audit_id, name))
+            })
+    }
+}
+
+/// Returns the key an artifact of an audit is stored under.
+pub fn artifact_key(audit_id: Uuid, name: &str) -> String {
+    format!("audits/{}/{}", audit_id, name)
+}
+
+/// Lints the code with Clippy if it compiled, and uploads every file produced.
+///
+/// # Returns
+///
+/// * `(Vec<String>, Vec<String>)` - The keys of the uploaded files, and why the others
+///   could not be produced or uploaded.
+async fn upload_artifacts(
+    store: &dyn ArtifactStore,
+    audit_id: Uuid,
+    code: String,
+    artifacts: CompiledArtifacts,
+) -> (Vec<String>, Vec<String>) {
+    let mut errors = Vec::new();
+    let mut files = vec![(DIAGNOSTICS, artifacts.json_output.into_bytes())];
+    if artifacts.compiled {
+        let options = artifacts.options;
+        match tokio::task::spawn_blocking(move || auditor::run_clippy(&code, &options)).await {
+            Ok(Ok(output)) => files.push((CLIPPY, output.into_bytes())),
+            Ok(Err(e)) => errors.push(format!("{}: {}", CLIPPY, e)),
+            Err(e) => errors.push(format!("{}: {}", CLIPPY, e)),
+        }
+    }
+    if let Some(library) = artifacts.library {
+        files.push((LIBRARY, library));
+    }
+
+    let mut keys = Vec::new();
+    for (name, data) in files {
+        let key = artifact_key(audit_id, name);
+        match store.put(&key, data).await {
+            Ok(()) => keys.push(key),
+            Err(e) => errors.push(format!("{}: {:#}", name, e)),
+        }
+    }
+    (keys, errors)
+}
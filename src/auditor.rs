@@ -1,64 +1,3738 @@
 //! Handles the business logic of compiling and auditing Rust code.
 
-use crate::error::AppError;
+use crate::{
+    advisories,
+    error::AppError,
+    models::{
+        AuditFile, AuditProfile, AuditProgressStage, Channel, Diagnostic, Edition, Finding,
+        FindingCategory, HygieneReport, LicensePolicy, OptLevel, SecurityAdvisory, Severity,
+        UnsafeReport,
+    },
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::process::Command;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{LazyLock, Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
+use syn::visit::{self, Visit};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, BufReader, Lines},
+    sync::mpsc,
+};
+use uuid::Uuid;
+
+/// The doc coverage threshold used when `AUDIT_DOC_COVERAGE_THRESHOLD` is not set.
+const DEFAULT_DOC_COVERAGE_THRESHOLD: f64 = 50.0;
+
+/// How long the automatic fix loop may run when `AUDIT_AUTO_FIX_BUDGET_SECS` is not set.
+const DEFAULT_AUTO_FIX_BUDGET: Duration = Duration::from_secs(300);
+
+/// The audit profile applied when `AUDIT_DEFAULT_PROFILE` is not set.
+const DEFAULT_PROFILE: &str = "default";
+
+/// The maximum number of lines of audited code when `AUDIT_MAX_LINES` is not set.
+const DEFAULT_MAX_LINES: usize = 2000;
+
+/// The identifier of the findings reporting a banned crate.
+///
+/// Such findings reject the code without compiling it, even when validation is not strict.
+pub const BANNED_CRATE_CODE: &str = "RAA0008";
+
+/// The size of audited code, in bytes, above which requests are refused when
+/// `MAX_CODE_SIZE_BYTES` is not set.
+pub const DEFAULT_MAX_CODE_BYTES: usize = 256 * 1024;
+
+/// The identifier of the findings reporting code longer than `AuditPolicy::max_lines`.
+///
+/// Such findings reject the code without compiling it, even when validation is not strict,
+/// and cannot be disabled.
+pub const TOO_MANY_LINES_CODE: &str = "RAA0012";
+
+/// The identifier of the findings reporting code without a license header.
+pub const MISSING_LICENSE_CODE: &str = "RAA0013";
+
+/// The identifier of the findings reporting imports of standard library paths that do
+/// not exist.
+pub const UNKNOWN_STD_PATH_CODE: &str = "RAA0014";
+
+/// The identifier of the findings reporting dependencies of a Cargo package affected by
+/// an advisory of the RustSec database (see `advisories`).
+pub const VULNERABLE_DEPENDENCY_CODE: &str = "RAA0203";
+
+/// The identifier of the findings reporting dependencies of a Cargo package whose license
+/// the license policy does not allow (see `licenses`).
+pub const LICENSE_VIOLATION_CODE: &str = "RAA0301";
+
+/// The number of lines at the top of the code searched for a license header.
+const LICENSE_HEADER_LINES: usize = 10;
+
+/// The tag of the comments declaring the license of the code.
+const SPDX_LICENSE_TAG: &str = "SPDX-License-Identifier:";
+
+/// Returns whether a finding rejects the code without compiling it, even when validation
+/// is not strict: banned crates and code that is too long.
+pub fn always_rejects(finding: &Finding) -> bool {
+    finding.code == BANNED_CRATE_CODE || finding.code == TOO_MANY_LINES_CODE
+}
+
+/// Server-wide settings controlling how audits are performed.
+#[derive(Debug, Clone)]
+pub struct AuditPolicy {
+    /// Whether blocking findings reject the code without compiling it, unless the
+    /// request says otherwise.
+    pub strict: bool,
+    /// The share of public items, in percent, that must be documented to avoid a
+    /// `RAA0006` warning.
+    pub doc_coverage_threshold: f64,
+    /// How long creating an audit and its automatic fix attempts may take in total.
+    pub auto_fix_budget: Duration,
+    /// The crates that audited code may not use. A pattern ending with `*` matches every
+    /// crate whose name starts with the rest of the pattern.
+    pub banned_crates: Vec<String>,
+    /// The severity of the findings reporting debug output, `TODO`/`FIXME` comments and
+    /// placeholder identifiers.
+    pub hygiene_severity: Severity,
+    /// The codes of the rules whose findings are not reported (e.g. `RAA0103`).
+    pub disabled_rules: HashSet<String>,
+    /// The audit profile applied when a request does not choose one.
+    pub default_profile: String,
+    /// Whether code compiling with `rustc` warnings is considered invalid.
+    pub warnings_as_errors: bool,
+    /// The number of lines above which code is rejected without being analyzed or
+    /// compiled.
+    pub max_lines: usize,
+    /// The size of the code of a request, in bytes, above which the request is refused.
+    /// Servers take it from `AppConfig::max_code_size_bytes`.
+    pub max_code_bytes: usize,
+    /// The paths of the standard library that imports may name in addition to the
+    /// bundled ones, such as `std::sync::LazyLock`.
+    pub known_std_paths: Vec<String>,
+    /// The licenses the dependencies of Cargo packages may use. Servers read it from the
+    /// database, where the `licensePolicy` mutation sets it.
+    pub licenses: LicensePolicy,
+}
+
+impl Default for AuditPolicy {
+    /// Returns the policy of a server whose settings all keep their default value.
+    fn default() -> Self {
+        AuditPolicy {
+            strict: false,
+            doc_coverage_threshold: DEFAULT_DOC_COVERAGE_THRESHOLD,
+            auto_fix_budget: DEFAULT_AUTO_FIX_BUDGET,
+            banned_crates: Vec::new(),
+            hygiene_severity: Severity::Warning,
+            disabled_rules: HashSet::new(),
+            default_profile: DEFAULT_PROFILE.to_string(),
+            warnings_as_errors: false,
+            max_lines: DEFAULT_MAX_LINES,
+            max_code_bytes: DEFAULT_MAX_CODE_BYTES,
+            known_std_paths: Vec::new(),
+            licenses: LicensePolicy::default(),
+        }
+    }
+}
+
+impl AuditPolicy {
+    /// Reads the policy from settings named like their environment variable. Servers
+    /// read them with `AppConfig`, from the environment and the configuration file.
+    ///
+    /// * `AUDIT_STRICT` - When set to `true`, strict validation is the default.
+    /// * `AUDIT_DOC_COVERAGE_THRESHOLD` - The minimum doc coverage, in percent, below
+    ///   which a warning is reported (defaults to 50).
+    /// * `AUDIT_AUTO_FIX_BUDGET_SECS` - How long the automatic fix loop of an audit may
+    ///   run, in seconds (defaults to 300).
+    /// * `AUDIT_BANNED_CRATES` - A comma-separated list of crates that audited code may
+    ///   not use, such as `reqwest,tokio*` (defaults to none).
+    /// * `AUDIT_HYGIENE_SEVERITY` - The severity of the code hygiene findings: `info`,
+    ///   `warning` or `error` (defaults to `warning`).
+    /// * `AUDIT_DISABLED_RULES` - A comma-separated list of rule codes whose findings are
+    ///   not reported, such as `RAA0003,RAA0104` (defaults to none).
+    /// * `AUDIT_DEFAULT_PROFILE` - The audit profile applied when a request does not
+    ///   choose one (defaults to `default`).
+    /// * `AUDIT_WARNINGS_AS_ERRORS` - When set to `true`, code compiling with warnings is
+    ///   invalid.
+    /// * `AUDIT_MAX_LINES` - The number of lines above which code is rejected without
+    ///   being compiled (defaults to 2000).
+    /// * `AUDIT_KNOWN_STD_PATHS` - A comma-separated list of paths of the standard library
+    ///   that imports may name in addition to the bundled ones, such as
+    ///   `std::simd::Simd,std::sync::LazyLock` (defaults to none).
+    ///
+    /// # Arguments
+    ///
+    /// * `setting` - Returns the value of a setting, or `None` if it is not set.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(AuditPolicy)` - The policy.
+    /// * `Err(anyhow::Error)` - If `AUDIT_DOC_COVERAGE_THRESHOLD` is not a number between
+    ///   0 and 100, `AUDIT_AUTO_FIX_BUDGET_SECS` or `AUDIT_MAX_LINES` is not a positive
+    ///   integer, `AUDIT_HYGIENE_SEVERITY` is not a severity, or `AUDIT_KNOWN_STD_PATHS`
+    ///   lists a path outside of `std`.
+    pub fn from_settings(setting: impl Fn(&str) -> Option<String>) -> anyhow::Result<Self> {
+        let defaults = AuditPolicy::default();
+        let strict = setting("AUDIT_STRICT")
+            .map(|v| v.eq_ignore_ascii_case("true"))
+            .unwrap_or(defaults.strict);
+        let doc_coverage_threshold = match setting("AUDIT_DOC_COVERAGE_THRESHOLD") {
+            Some(value) => value
+                .parse::<f64>()
+                .ok()
+                .filter(|t| (0.0..=100.0).contains(t))
+                .context("AUDIT_DOC_COVERAGE_THRESHOLD must be a number between 0 and 100")?,
+            None => defaults.doc_coverage_threshold,
+        };
+        let auto_fix_budget = match setting("AUDIT_AUTO_FIX_BUDGET_SECS") {
+            Some(value) => value
+                .parse::<u64>()
+                .ok()
+                .filter(|&secs| secs > 0)
+                .map(Duration::from_secs)
+                .context("AUDIT_AUTO_FIX_BUDGET_SECS must be a positive integer")?,
+            None => defaults.auto_fix_budget,
+        };
+        let banned_crates = setting("AUDIT_BANNED_CRATES")
+            .unwrap_or_default()
+            .split(',')
+            .map(|pattern| pattern.trim().replace('-', "_"))
+            .filter(|pattern| !pattern.is_empty())
+            .collect();
+        let hygiene_severity = match setting("AUDIT_HYGIENE_SEVERITY") {
+            Some(value) => match value.to_ascii_lowercase().as_str() {
+                "info" => Severity::Info,
+                "warning" => Severity::Warning,
+                "error" => Severity::Error,
+                _ => anyhow::bail!("AUDIT_HYGIENE_SEVERITY must be info, warning or error"),
+            },
+            None => defaults.hygiene_severity,
+        };
+        let disabled_rules = setting("AUDIT_DISABLED_RULES")
+            .unwrap_or_default()
+            .split(',')
+            .map(|code| code.trim().to_ascii_uppercase())
+            .filter(|code| !code.is_empty())
+            .collect();
+        let default_profile = setting("AUDIT_DEFAULT_PROFILE")
+            .filter(|name| !name.is_empty())
+            .unwrap_or(defaults.default_profile);
+        let warnings_as_errors = setting("AUDIT_WARNINGS_AS_ERRORS")
+            .map(|v| v.eq_ignore_ascii_case("true"))
+            .unwrap_or(defaults.warnings_as_errors);
+        let max_lines = match setting("AUDIT_MAX_LINES") {
+            Some(value) => value
+                .parse::<usize>()
+                .ok()
+                .filter(|&lines| lines > 0)
+                .context("AUDIT_MAX_LINES must be a positive integer")?,
+            None => defaults.max_lines,
+        };
+        let known_std_paths: Vec<String> = setting("AUDIT_KNOWN_STD_PATHS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|path| path.trim().to_string())
+            .filter(|path| !path.is_empty())
+            .collect();
+        if known_std_paths
+            .iter()
+            .any(|path| !path.starts_with("std::"))
+        {
+            anyhow::bail!("AUDIT_KNOWN_STD_PATHS must list paths starting with `std::`");
+        }
+        Ok(AuditPolicy {
+            strict,
+            doc_coverage_threshold,
+            auto_fix_budget,
+            banned_crates,
+            hygiene_severity,
+            disabled_rules,
+            default_profile,
+            warnings_as_errors,
+            max_lines,
+            known_std_paths,
+            ..defaults
+        })
+    }
+
+    /// Returns whether compiled code is valid under this policy.
+    ///
+    /// Code is valid if it compiled, and, when warnings count as errors, if `rustc`
+    /// emitted no warning.
+    pub fn accepts(&self, outcome: &CompilationOutcome) -> bool {
+        outcome.success
+            && !(self.warnings_as_errors
+                && outcome.diagnostics.iter().any(|d| d.level == "warning"))
+    }
+
+    /// Returns a description of the rules code is validated and judged with, which
+    /// changes whenever one of them is edited (e.g. by updating an audit profile).
+    pub fn rules_version(&self) -> String {
+        let mut disabled_rules: Vec<&str> =
+            self.disabled_rules.iter().map(String::as_str).collect();
+        disabled_rules.sort_unstable();
+        format!(
+            "{}|{:?}|{}|{:?}|{:?}|{}|{}|{:?}|{:?}",
+            env!("CARGO_PKG_VERSION"),
+            disabled_rules,
+            self.doc_coverage_threshold,
+            self.banned_crates,
+            self.hygiene_severity,
+            self.warnings_as_errors,
+            self.max_lines,
+            self.known_std_paths,
+            self.licenses
+        )
+    }
+
+    /// Returns this policy with the rules that `profile` does not enable disabled too.
+    pub fn with_profile(&self, profile: &AuditProfile) -> AuditPolicy {
+        let mut policy = self.clone();
+        policy.disabled_rules.extend(
+            RULE_CODES
+                .iter()
+                .filter(|code| !profile.enabled_rules.iter().any(|r| r == *code))
+                .map(|code| code.to_string()),
+        );
+        policy
+    }
+}
+
+/// The codes of every validation rule, which audit profiles enable.
+pub const RULE_CODES: &[&str] = &[
+    "RAA0001", "RAA0002", "RAA0003", "RAA0004", "RAA0005", "RAA0006", "RAA0007", "RAA0008",
+    "RAA0009", "RAA0010", "RAA0011", "RAA0013", "RAA0014", "RAA0101", "RAA0102", "RAA0103",
+    "RAA0104", "RAA0105", "RAA0201", "RAA0202", "RAA0203", "RAA0301",
+];
+
+/// A check run by `validate_code` over the whole source code.
+trait ValidationRule {
+    /// Returns the findings of the rule for `code`.
+    fn check(&self, code: &str) -> Vec<Finding>;
+}
+
+/// A heuristic validation rule matching a token in the source code.
+struct TokenRule {
+    /// The stable identifier reported in findings.
+    code: &'static str,
+    /// The token whose presence triggers the rule.
+    token: &'static str,
+    /// The severity of the findings produced by the rule.
+    severity: Severity,
+    /// The message reported in findings.
+    message: &'static str,
+}
+
+/// The token rules applied by `validate_code`.
+const TOKEN_RULES: &[TokenRule] = &[
+    TokenRule {
+        code: "RAA0001",
+        token: "unsafe",
+        severity: Severity::Error,
+        message: "Uses `unsafe` code",
+    },
+    TokenRule {
+        code: "RAA0002",
+        token: "process::Command",
+        severity: Severity::Error,
+        message: "Spawns external processes with `std::process::Command`",
+    },
+    TokenRule {
+        code: "RAA0003",
+        token: ".unwrap()",
+        severity: Severity::Warning,
+        message: "Calls `unwrap()`, which panics on `None`/`Err`",
+    },
+    TokenRule {
+        code: "RAA0004",
+        token: "panic!",
+        severity: Severity::Warning,
+        message: "Explicitly panics with `panic!`",
+    },
+    TokenRule {
+        code: "RAA0005",
+        token: "todo!",
+        severity: Severity::Warning,
+        message: "Leaves unfinished code behind `todo!`",
+    },
+    TokenRule {
+        code: "RAA0005",
+        token: "unimplemented!",
+        severity: Severity::Warning,
+        message: "Leaves unfinished code behind `unimplemented!`",
+    },
+];
+
+/// Notes code without an `SPDX-License-Identifier` header, which matters for compliance.
+struct LicenseHeaderRule;
+
+impl ValidationRule for LicenseHeaderRule {
+    fn check(&self, code: &str) -> Vec<Finding> {
+        if detect_license_header(code).is_some() {
+            return Vec::new();
+        }
+        vec![Finding {
+            code: MISSING_LICENSE_CODE.to_string(),
+            severity: Severity::Info,
+            message: format!(
+                "Has no `{}` comment in its first {} lines",
+                SPDX_LICENSE_TAG.trim_end_matches(':'),
+                LICENSE_HEADER_LINES
+            ),
+            line: None,
+            category: None,
+            file: None,
+        }]
+    }
+}
+
+/// Finds the license the code declares with an SPDX comment.
+///
+/// The first 10 lines are searched for a line or block comment holding
+/// `SPDX-License-Identifier: <expression>`, as in `// SPDX-License-Identifier: MIT`.
+///
+/// # Arguments
+///
+/// * `code` - A string slice containing the Rust code to be analyzed.
+///
+/// # Returns
+///
+/// * `Some(String)` - The license expression (e.g. `MIT OR Apache-2.0`).
+/// * `None` - If no comment of the first lines declares a license.
+pub fn detect_license_header(code: &str) -> Option<String> {
+    code.lines().take(LICENSE_HEADER_LINES).find_map(|line| {
+        let line = line.trim_start();
+        if !["//", "/*", "*"]
+            .iter()
+            .any(|marker| line.starts_with(marker))
+        {
+            return None;
+        }
+        let (_, expression) = line.split_once(SPDX_LICENSE_TAG)?;
+        let expression = expression.trim();
+        let expression = expression.strip_suffix("*/").unwrap_or(expression).trim();
+        (!expression.is_empty()).then(|| expression.to_string())
+    })
+}
+
+/// Applies every token rule to the code, line by line.
+///
+/// This is a line-based heuristic: text following `//` on a line is ignored, but
+/// tokens inside string literals or block comments are still reported.
+struct TokenRules;
+
+impl ValidationRule for TokenRules {
+    fn check(&self, code: &str) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        for (index, line) in code.lines().enumerate() {
+            let line = line.split("//").next().unwrap_or_default();
+            for rule in TOKEN_RULES {
+                if contains_token(line, rule.token) {
+                    findings.push(Finding {
+                        code: rule.code.to_string(),
+                        severity: rule.severity,
+                        message: rule.message.to_string(),
+                        line: u32::try_from(index + 1).ok(),
+                        category: None,
+                        file: None,
+                    });
+                }
+            }
+        }
+        findings
+    }
+}
+
+/// Warns when too few public items are documented.
+///
+/// Code that does not parse is left to the compiler and produces no finding.
+struct DocCoverageRule {
+    /// The minimum doc coverage, in percent.
+    threshold: f64,
+}
+
+impl ValidationRule for DocCoverageRule {
+    fn check(&self, code: &str) -> Vec<Finding> {
+        match compute_doc_coverage(code) {
+            Ok(coverage) if coverage < self.threshold => vec![Finding {
+                code: "RAA0006".to_string(),
+                severity: Severity::Warning,
+                message: format!(
+                    "Documents {:.1}% of public items, below the {:.1}% threshold",
+                    coverage, self.threshold
+                ),
+                line: None,
+                category: None,
+                file: None,
+            }],
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// A blocking API that should not be called from asynchronous code.
+struct BlockingApi {
+    /// The trailing segments of the paths calling the API, e.g. `["thread", "sleep"]`
+    /// for `std::thread::sleep`. A single segment matches any path containing it.
+    path: &'static [&'static str],
+    /// The non-blocking alternative suggested in findings.
+    alternative: &'static str,
+}
+
+/// The blocking APIs reported by `BlockingCallRule`.
+const BLOCKING_APIS: &[BlockingApi] = &[
+    BlockingApi {
+        path: &["thread", "sleep"],
+        alternative: "`tokio::time::sleep`",
+    },
+    BlockingApi {
+        path: &["fs", "read_to_string"],
+        alternative: "`tokio::fs::read_to_string`",
+    },
+    BlockingApi {
+        path: &["fs", "read"],
+        alternative: "`tokio::fs::read`",
+    },
+    BlockingApi {
+        path: &["fs", "write"],
+        alternative: "`tokio::fs::write`",
+    },
+    BlockingApi {
+        path: &["File", "open"],
+        alternative: "`tokio::fs::File::open`",
+    },
+    BlockingApi {
+        path: &["File", "create"],
+        alternative: "`tokio::fs::File::create`",
+    },
+    BlockingApi {
+        path: &["reqwest", "blocking"],
+        alternative: "the async `reqwest::Client`",
+    },
+];
+
+/// The crates providing async versions of the blocking APIs, under the same names.
+const ASYNC_RUNTIMES: &[&str] = &["tokio", "async_std", "smol"];
+
+/// The functions whose closure arguments run on a thread where blocking is allowed.
+const BLOCKING_CONTEXTS: &[&str] = &["spawn_blocking", "block_in_place"];
+
+/// Warns about blocking calls made from `async` functions, blocks and closures.
+///
+/// Calls are matched by path, so APIs that are imported and called by their bare name
+/// (e.g. `sleep(..)` after `use std::thread::sleep`) are not reported, and awaited calls
+/// are assumed to be async versions. Calls inside closures passed to `spawn_blocking`
+/// or `block_in_place` are allowed.
+/// Code that does not parse is left to the compiler and produces no finding.
+struct BlockingCallRule;
+
+impl ValidationRule for BlockingCallRule {
+    fn check(&self, code: &str) -> Vec<Finding> {
+        let Ok(file) = syn::parse_file(code) else {
+            return Vec::new();
+        };
+        let mut finder = BlockingCallFinder {
+            context: None,
+            findings: Vec::new(),
+        };
+        finder.visit_file(&file);
+        finder.findings
+    }
+}
+
+/// Collects the blocking calls made in asynchronous contexts.
+struct BlockingCallFinder {
+    /// The description of the asynchronous context being visited (e.g. "async fn
+    /// `fetch`"), or `None` outside of one.
+    context: Option<String>,
+    findings: Vec<Finding>,
+}
+
+impl BlockingCallFinder {
+    /// Visits a node with `context` as the current asynchronous context.
+    fn within(&mut self, context: Option<String>, visit: impl FnOnce(&mut Self)) {
+        let outer = std::mem::replace(&mut self.context, context);
+        visit(self);
+        self.context = outer;
+    }
+
+    /// Visits a function body, which is asynchronous only if the function is `async`.
+    fn visit_fn(&mut self, sig: &syn::Signature, block: &syn::Block) {
+        let context = sig
+            .asyncness
+            .is_some()
+            .then(|| format!("async fn `{}`", sig.ident));
+        self.within(context, |finder| finder.visit_block(block));
+    }
+
+    /// Records a finding if `path` names a blocking API called from an async context.
+    fn check_path(&mut self, path: &syn::Path) {
+        let Some(context) = &self.context else {
+            return;
+        };
+        let segments: Vec<String> = path.segments.iter().map(|s| s.ident.to_string()).collect();
+        if segments
+            .first()
+            .is_some_and(|s| ASYNC_RUNTIMES.contains(&s.as_str()))
+        {
+            return;
+        }
+        let Some(api) = BLOCKING_APIS.iter().find(|api| {
+            segments
+                .windows(api.path.len())
+                .any(|window| window.iter().zip(api.path).all(|(a, b)| a == b))
+        }) else {
+            return;
+        };
+        let line = path.segments.first().map(|s| span_line(s.ident.span()));
+        self.findings.push(Finding {
+            code: "RAA0007".to_string(),
+            severity: Severity::Warning,
+            message: format!(
+                "Calls blocking `{}` in {}; use {} or `spawn_blocking`",
+                segments.join("::"),
+                context,
+                api.alternative
+            ),
+            line,
+            category: None,
+            file: None,
+        });
+    }
+}
+
+impl<'ast> Visit<'ast> for BlockingCallFinder {
+    fn visit_item_fn(&mut self, item: &'ast syn::ItemFn) {
+        self.visit_fn(&item.sig, &item.block);
+    }
+
+    fn visit_impl_item_fn(&mut self, item: &'ast syn::ImplItemFn) {
+        self.visit_fn(&item.sig, &item.block);
+    }
+
+    fn visit_trait_item_fn(&mut self, item: &'ast syn::TraitItemFn) {
+        if let Some(block) = &item.default {
+            self.visit_fn(&item.sig, block);
+        }
+    }
+
+    fn visit_expr_async(&mut self, expr: &'ast syn::ExprAsync) {
+        // An async block inside an async fn keeps the name of the function.
+        let context = self
+            .context
+            .clone()
+            .unwrap_or_else(|| "an async block".to_string());
+        self.within(Some(context), |finder| {
+            visit::visit_expr_async(finder, expr)
+        });
+    }
+
+    fn visit_expr_closure(&mut self, expr: &'ast syn::ExprClosure) {
+        if expr.asyncness.is_some() {
+            self.within(Some("an async closure".to_string()), |finder| {
+                visit::visit_expr_closure(finder, expr)
+            });
+        } else {
+            // A plain closure usually runs where it is defined (e.g. in `map`).
+            visit::visit_expr_closure(self, expr);
+        }
+    }
+
+    fn visit_expr_call(&mut self, expr: &'ast syn::ExprCall) {
+        if let syn::Expr::Path(func) = &*expr.func {
+            self.check_path(&func.path);
+            if is_blocking_context(&expr.func) {
+                self.within(None, |finder| {
+                    expr.args.iter().for_each(|arg| finder.visit_expr(arg))
+                });
+                return;
+            }
+        }
+        visit::visit_expr_call(self, expr);
+    }
+
+    fn visit_expr_await(&mut self, expr: &'ast syn::ExprAwait) {
+        // An awaited call returns a future, so it is the async version of the API (e.g.
+        // `fs::read(..).await` after `use tokio::fs`).
+        // Awaiting `spawn_blocking(..)` still runs its closure on a blocking thread.
+        if let syn::Expr::Call(call) = &*expr.base
+            && !is_blocking_context(&call.func)
+        {
+            call.args.iter().for_each(|arg| self.visit_expr(arg));
+        } else {
+            visit::visit_expr_await(self, expr);
+        }
+    }
+}
+
+/// Returns whether `func` is one of the [`BLOCKING_CONTEXTS`].
+fn is_blocking_context(func: &syn::Expr) -> bool {
+    let syn::Expr::Path(func) = func else {
+        return false;
+    };
+    func.path
+        .segments
+        .last()
+        .is_some_and(|s| BLOCKING_CONTEXTS.contains(&s.ident.to_string().as_str()))
+}
+
+/// Reports the crates used by the code that match a banned pattern.
+///
+/// A crate is used when a `use` declaration, an `extern crate` item or a qualified path
+/// (e.g. `reqwest::get`) starts with its name. Code that does not parse is left to the
+/// compiler and produces no finding.
+struct BannedCrateRule<'a> {
+    /// The banned crate patterns, with `-` replaced by `_`.
+    patterns: &'a [String],
+}
+
+impl BannedCrateRule<'_> {
+    /// Returns the pattern banning the crate `name`, if any.
+    fn banning_pattern(&self, name: &str) -> Option<&str> {
+        self.patterns
+            .iter()
+            .find(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == pattern.as_str(),
+            })
+            .map(String::as_str)
+    }
+}
+
+impl ValidationRule for BannedCrateRule<'_> {
+    fn check(&self, code: &str) -> Vec<Finding> {
+        if self.patterns.is_empty() {
+            return Vec::new();
+        }
+        let Ok(file) = syn::parse_file(code) else {
+            return Vec::new();
+        };
+        let mut finder = CrateUseFinder::default();
+        finder.visit_file(&file);
+
+        // Report each crate once, at its first use.
+        finder.uses.sort_by_key(|(_, line)| *line);
+        let mut reported = HashSet::new();
+        let mut findings = Vec::new();
+        for (name, line) in finder.uses {
+            if let Some(pattern) = self.banning_pattern(&name)
+                && reported.insert(name.clone())
+            {
+                findings.push(Finding {
+                    code: BANNED_CRATE_CODE.to_string(),
+                    severity: Severity::Error,
+                    message: format!(
+                        "Uses the crate `{}`, which is banned by the pattern `{}`",
+                        name, pattern
+                    ),
+                    line: Some(line),
+                    category: None,
+                    file: None,
+                });
+            }
+        }
+        findings
+    }
+}
+
+/// Collects the first segment and line of the paths that may name a crate.
+#[derive(Default)]
+struct CrateUseFinder {
+    uses: Vec<(String, u32)>,
+}
+
+impl CrateUseFinder {
+    /// Records `ident` unless it refers to the current crate or module.
+    fn record(&mut self, ident: &syn::Ident) {
+        let name = ident.to_string();
+        if !matches!(name.as_str(), "crate" | "self" | "super" | "Self") {
+            self.uses.push((name, span_line(ident.span())));
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for CrateUseFinder {
+    fn visit_item_use(&mut self, item: &'ast syn::ItemUse) {
+        let mut roots = vec![&item.tree];
+        while let Some(tree) = roots.pop() {
+            match tree {
+                syn::UseTree::Path(path) => self.record(&path.ident),
+                syn::UseTree::Name(name) => self.record(&name.ident),
+                syn::UseTree::Rename(rename) => self.record(&rename.ident),
+                syn::UseTree::Group(group) => roots.extend(&group.items),
+                syn::UseTree::Glob(_) => {}
+            }
+        }
+    }
+
+    fn visit_item_extern_crate(&mut self, item: &'ast syn::ItemExternCrate) {
+        self.record(&item.ident);
+    }
+
+    fn visit_path(&mut self, path: &'ast syn::Path) {
+        if path.segments.len() > 1
+            && let Some(first) = path.segments.first()
+        {
+            self.record(&first.ident);
+        }
+        visit::visit_path(self, path);
+    }
+}
+
+/// Warns about `use` declarations importing paths of the standard library that do not
+/// exist, such as `std::collections::SortedMap`, which AI models tend to make up.
+struct StdPathRule<'a> {
+    /// The paths known in addition to the bundled ones, such as `std::sync::LazyLock`.
+    extra_paths: &'a [String],
+}
+
+impl StdPathRule<'_> {
+    /// Returns whether `path` is known: bundled, configured or the parent of a
+    /// configured path.
+    fn is_known(&self, path: &str) -> bool {
+        KNOWN_STD_PATHS.contains(path)
+            || self.extra_paths.iter().any(|extra| {
+                extra
+                    .strip_prefix(path)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+    }
+
+    /// Returns the shortest prefix of an imported path of the standard library that
+    /// names nothing, if any.
+    ///
+    /// Only the items of the modules with bundled items are checked: anything under
+    /// another module, a type or an enum is accepted.
+    fn unknown_prefix(&self, segments: &[String]) -> Option<String> {
+        for end in 2..=segments.len() {
+            let prefix = segments[..end].join("::");
+            if self.is_known(&prefix) {
+                continue;
+            }
+            let parent = segments[..end - 1].join("::");
+            return CHECKED_STD_MODULES
+                .contains(parent.as_str())
+                .then_some(prefix);
+        }
+        None
+    }
+}
+
+impl ValidationRule for StdPathRule<'_> {
+    fn check(&self, code: &str) -> Vec<Finding> {
+        let Ok(file) = syn::parse_file(code) else {
+            return Vec::new();
+        };
+        let mut finder = StdImportFinder::default();
+        finder.visit_file(&file);
+
+        // Report each unknown path once, at its first import.
+        finder.imports.sort_by_key(|(_, line)| *line);
+        let mut reported = HashSet::new();
+        let mut findings = Vec::new();
+        for (segments, line) in finder.imports {
+            if let Some(path) = self.unknown_prefix(&segments)
+                && reported.insert(path.clone())
+            {
+                findings.push(Finding {
+                    code: UNKNOWN_STD_PATH_CODE.to_string(),
+                    severity: Severity::Warning,
+                    message: format!(
+                        "Imports `{}`, which does not exist in the standard library",
+                        path
+                    ),
+                    line: Some(line),
+                    category: None,
+                    file: None,
+                });
+            }
+        }
+        findings
+    }
+}
+
+/// Collects the paths starting with `std` imported by `use` declarations, split into
+/// segments, with the line they are imported on.
+#[derive(Default)]
+struct StdImportFinder {
+    imports: Vec<(Vec<String>, u32)>,
+}
+
+impl StdImportFinder {
+    /// Records the paths imported by `tree`, below the segments of `prefix`.
+    fn collect(&mut self, tree: &syn::UseTree, prefix: &mut Vec<String>) {
+        match tree {
+            syn::UseTree::Path(path) => {
+                prefix.push(path.ident.to_string());
+                self.collect(&path.tree, prefix);
+                prefix.pop();
+            }
+            syn::UseTree::Name(name) => self.record(prefix, Some(&name.ident), name.ident.span()),
+            syn::UseTree::Rename(rename) => {
+                self.record(prefix, Some(&rename.ident), rename.ident.span());
+            }
+            syn::UseTree::Glob(glob) => self.record(prefix, None, glob.star_token.span),
+            syn::UseTree::Group(group) => {
+                for tree in &group.items {
+                    self.collect(tree, prefix);
+                }
+            }
+        }
+    }
+
+    /// Records the path `prefix::ident`, or `prefix` for globs and `self`, if it is a
+    /// path of the standard library.
+    fn record(&mut self, prefix: &[String], ident: Option<&syn::Ident>, span: proc_macro2::Span) {
+        let mut path = prefix.to_vec();
+        if let Some(ident) = ident.filter(|ident| *ident != "self") {
+            path.push(ident.to_string());
+        }
+        if path.len() > 1 && path[0] == "std" {
+            self.imports.push((path, span_line(span)));
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for StdImportFinder {
+    fn visit_item_use(&mut self, item: &'ast syn::ItemUse) {
+        self.collect(&item.tree, &mut Vec::new());
+    }
+}
+
+/// The paths of the standard library bundled with the auditor (see `std_paths.txt`).
+static KNOWN_STD_PATHS: LazyLock<HashSet<&str>> = LazyLock::new(|| {
+    include_str!("std_paths.txt")
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+});
+
+/// The modules of the standard library whose items are checked: `std` and the modules
+/// with bundled items.
+static CHECKED_STD_MODULES: LazyLock<HashSet<&str>> = LazyLock::new(|| {
+    KNOWN_STD_PATHS
+        .iter()
+        .filter_map(|path| path.rsplit_once("::").map(|(module, _)| module))
+        .collect()
+});
+
+/// The kinds of leftovers reported by the code hygiene checks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HygieneCategory {
+    /// A `dbg!`, `eprintln!` or `eprint!` call, or a `println!`/`print!` of a message
+    /// starting with "debug".
+    DebugOutput,
+    /// A `TODO`, `FIXME` or `XXX` marker in a comment.
+    TodoMarker,
+    /// An identifier left to be filled in, such as `your_function_here`.
+    Placeholder,
+}
+
+impl HygieneCategory {
+    /// Returns the identifier of the findings of the category.
+    fn code(&self) -> &'static str {
+        match self {
+            HygieneCategory::DebugOutput => "RAA0009",
+            HygieneCategory::TodoMarker => "RAA0010",
+            HygieneCategory::Placeholder => "RAA0011",
+        }
+    }
+
+    /// Returns how many points each occurrence takes off the hygiene score.
+    fn penalty(&self) -> f64 {
+        match self {
+            HygieneCategory::DebugOutput => 10.0,
+            HygieneCategory::TodoMarker => 5.0,
+            HygieneCategory::Placeholder => 20.0,
+        }
+    }
+}
+
+/// A leftover found by the code hygiene checks.
+struct HygieneIssue {
+    category: HygieneCategory,
+    line: u32,
+    message: String,
+}
+
+/// The markers reported in comments.
+const TODO_MARKERS: &[&str] = &["TODO", "FIXME", "XXX"];
+
+/// Finds the debug output, comment markers and placeholder identifiers of the code.
+///
+/// Comments are found by a lexer aware of string and character literals, so a string
+/// containing "TODO" is not reported. Macros and identifiers are only looked for if
+/// the code parses.
+fn scan_hygiene(code: &str) -> Vec<HygieneIssue> {
+    let mut issues = Vec::new();
+    for (line, text) in comment_lines(code) {
+        for marker in TODO_MARKERS {
+            if contains_token(&text, marker) {
+                issues.push(HygieneIssue {
+                    category: HygieneCategory::TodoMarker,
+                    line,
+                    message: format!("Leaves a `{}` comment behind", marker),
+                });
+            }
+        }
+    }
+    if let Ok(file) = syn::parse_file(code) {
+        let mut finder = HygieneFinder::default();
+        finder.visit_file(&file);
+        issues.extend(finder.issues);
+    }
+    issues.sort_by_key(|issue| issue.line);
+    issues
+}
+
+/// Returns the text of every comment line of the code with its 1-based line number.
+///
+/// Block comments, which may be nested, yield one entry per line. String, byte string,
+/// raw string and character literals are skipped.
+fn comment_lines(code: &str) -> Vec<(u32, String)> {
+    let chars: Vec<char> = code.chars().collect();
+    let is_ident = |c: Option<&char>| c.is_some_and(|c| c.is_alphanumeric() || *c == '_');
+    let mut comments = Vec::new();
+    let (mut i, mut line) = (0, 1);
+    while i < chars.len() {
+        match (chars[i], chars.get(i + 1)) {
+            ('\n', _) => {
+                line += 1;
+                i += 1;
+            }
+            ('/', Some('/')) => {
+                let start = i + 2;
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                comments.push((line, chars[start..i].iter().collect()));
+            }
+            ('/', Some('*')) => {
+                let (mut depth, mut text) = (1, String::new());
+                i += 2;
+                while i < chars.len() && depth > 0 {
+                    match (chars[i], chars.get(i + 1)) {
+                        ('/', Some('*')) => {
+                            depth += 1;
+                            i += 2;
+                        }
+                        ('*', Some('/')) => {
+                            depth -= 1;
+                            i += 2;
+                        }
+                        ('\n', _) => {
+                            comments.push((line, std::mem::take(&mut text)));
+                            line += 1;
+                            i += 1;
+                        }
+                        (c, _) => {
+                            text.push(c);
+                            i += 1;
+                        }
+                    }
+                }
+                comments.push((line, text));
+            }
+            ('"', _) => {
+                i += 1;
+                while i < chars.len() && chars[i] != '"' {
+                    if chars[i] == '\\' {
+                        i += 1;
+                    }
+                    if chars.get(i) == Some(&'\n') {
+                        line += 1;
+                    }
+                    i += 1;
+                }
+                i += 1;
+            }
+            ('r', Some('"' | '#'))
+                if !is_ident(i.checked_sub(1).and_then(|j| chars.get(j)))
+                    || (i >= 1
+                        && chars[i - 1] == 'b'
+                        && !is_ident(i.checked_sub(2).and_then(|j| chars.get(j)))) =>
+            {
+                let hashes = chars[i + 1..].iter().take_while(|&&c| c == '#').count();
+                if chars.get(i + 1 + hashes) != Some(&'"') {
+                    // A raw identifier, such as `r#type`.
+                    i += 1 + hashes;
+                    continue;
+                }
+                i += hashes + 2;
+                while i < chars.len()
+                    && !(chars[i] == '"'
+                        && chars[i + 1..].iter().take_while(|&&c| c == '#').count() >= hashes)
+                {
+                    if chars[i] == '\n' {
+                        line += 1;
+                    }
+                    i += 1;
+                }
+                i += hashes + 1;
+            }
+            ('\'', Some('\\')) => {
+                // An escaped character literal, such as '\n' or '\u{1F600}'.
+                i += 2;
+                while i < chars.len() && chars[i] != '\'' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            ('\'', Some(_)) if chars.get(i + 2) == Some(&'\'') => i += 3,
+            _ => i += 1,
+        }
+    }
+    comments
+}
+
+/// Collects the debug output and placeholder identifiers of a parsed file.
+#[derive(Default)]
+struct HygieneFinder {
+    issues: Vec<HygieneIssue>,
+    /// The placeholder identifiers already reported, each being reported once.
+    placeholders: HashSet<String>,
+}
+
+impl<'ast> Visit<'ast> for HygieneFinder {
+    fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+        if let Some(name) = mac.path.segments.last().map(|s| s.ident.to_string()) {
+            let reported = match name.as_str() {
+                "dbg" | "eprintln" | "eprint" => true,
+                "println" | "print" => mac
+                    .parse_body_with(
+                        syn::punctuated::Punctuated::<syn::Expr, syn::Token![,]>::parse_terminated,
+                    )
+                    .ok()
+                    .and_then(|args| match args.first() {
+                        Some(syn::Expr::Lit(syn::ExprLit {
+                            lit: syn::Lit::Str(format),
+                            ..
+                        })) => Some(format.value()),
+                        _ => None,
+                    })
+                    .is_some_and(|format| {
+                        format
+                            .trim_start_matches(['[', '(', ' '])
+                            .to_ascii_lowercase()
+                            .starts_with("debug")
+                    }),
+                _ => false,
+            };
+            if reported {
+                self.issues.push(HygieneIssue {
+                    category: HygieneCategory::DebugOutput,
+                    line: mac
+                        .path
+                        .segments
+                        .first()
+                        .map_or(0, |s| span_line(s.ident.span())),
+                    message: format!("Leaves debug output behind with `{}!`", name),
+                });
+            }
+        }
+        visit::visit_macro(self, mac);
+    }
+
+    fn visit_ident(&mut self, ident: &'ast proc_macro2::Ident) {
+        let name = ident.to_string().to_ascii_lowercase();
+        if (name.starts_with("your_") || name.ends_with("_here") || name.contains("placeholder"))
+            && self.placeholders.insert(name)
+        {
+            self.issues.push(HygieneIssue {
+                category: HygieneCategory::Placeholder,
+                line: span_line(ident.span()),
+                message: format!("Uses the placeholder identifier `{}`", ident),
+            });
+        }
+    }
+}
+
+/// Reports debug output, `TODO`/`FIXME` comments and placeholder identifiers.
+struct HygieneRule {
+    /// The severity of the findings.
+    severity: Severity,
+}
+
+impl ValidationRule for HygieneRule {
+    fn check(&self, code: &str) -> Vec<Finding> {
+        scan_hygiene(code)
+            .into_iter()
+            .map(|issue| Finding {
+                code: issue.category.code().to_string(),
+                severity: self.severity,
+                message: issue.message,
+                line: Some(issue.line),
+                category: None,
+                file: None,
+            })
+            .collect()
+    }
+}
+
+/// Counts the leftovers of the code and scores its hygiene.
+///
+/// The score starts at 100 and loses 10 points per debug output, 5 per `TODO`, `FIXME`
+/// or `XXX` comment and 20 per placeholder identifier, down to 0.
+///
+/// # Arguments
+///
+/// * `code` - A string slice containing the Rust code to be analyzed.
+///
+/// # Returns
+///
+/// * `HygieneReport` - The number of leftovers per category and the score. Macros and
+///   identifiers are not counted if the code does not parse.
+pub fn check_hygiene(code: &str) -> HygieneReport {
+    let mut report = HygieneReport::default();
+    let mut penalty = 0.0;
+    for issue in scan_hygiene(code) {
+        match issue.category {
+            HygieneCategory::DebugOutput => report.debug_output_count += 1,
+            HygieneCategory::TodoMarker => report.todo_marker_count += 1,
+            HygieneCategory::Placeholder => report.placeholder_count += 1,
+        }
+        penalty += issue.category.penalty();
+    }
+    report.hygiene_score = (100.0 - penalty).max(0.0);
+    report
+}
+
+/// A check run by `SyntaxRules` over the syntax tree of the code.
+trait SyntaxRule {
+    /// Returns the findings of the rule for `file`.
+    fn check(&self, file: &syn::File) -> Vec<Finding>;
+}
+
+/// The syntax rules applied by `validate_code`, reporting non-idiomatic error handling
+/// and injection vulnerabilities.
+const SYNTAX_RULES: &[&dyn SyntaxRule] = &[
+    &StringlyErrorRule,
+    &OkUnwrapRule,
+    &SwallowedErrorRule,
+    &PanicInResultFnRule,
+    &InjectionRule,
+];
+
+/// Parses the code once and applies every syntax rule to it.
+///
+/// Code that does not parse is left to the compiler and produces no finding.
+struct SyntaxRules;
+
+impl ValidationRule for SyntaxRules {
+    fn check(&self, code: &str) -> Vec<Finding> {
+        let Ok(file) = syn::parse_file(code) else {
+            return Vec::new();
+        };
+        let mut findings: Vec<Finding> = SYNTAX_RULES
+            .iter()
+            .flat_map(|rule| rule.check(&file))
+            .collect();
+        findings.sort_by_key(|f| f.line);
+        findings
+    }
+}
+
+/// Returns whether a function returns a `Result`, and its error type if it is written
+/// (it is not in aliases such as `io::Result<T>`).
+fn result_error_type(sig: &syn::Signature) -> (bool, Option<&syn::Type>) {
+    let syn::ReturnType::Type(_, ty) = &sig.output else {
+        return (false, None);
+    };
+    let syn::Type::Path(path) = &**ty else {
+        return (false, None);
+    };
+    let Some(last) = path.path.segments.last().filter(|s| s.ident == "Result") else {
+        return (false, None);
+    };
+    let error = match &last.arguments {
+        syn::PathArguments::AngleBracketed(args) => {
+            args.args.iter().nth(1).and_then(|arg| match arg {
+                syn::GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            })
+        }
+        _ => None,
+    };
+    (true, error)
+}
+
+/// Returns whether the last segment of the type's path is `name`.
+fn is_path_type(ty: &syn::Type, name: &str) -> bool {
+    matches!(ty, syn::Type::Path(path) if path.path.segments.last().is_some_and(|s| s.ident == name))
+}
+
+/// Describes an error type that carries no structure, if `ty` is one: `String`, `&str`
+/// or `Box<dyn Error>`.
+fn unstructured_error(ty: &syn::Type) -> Option<(&'static str, &'static str)> {
+    if is_path_type(ty, "String") {
+        return Some(("RAA0101", "`String`"));
+    }
+    if let syn::Type::Reference(reference) = ty
+        && is_path_type(&reference.elem, "str")
+    {
+        return Some(("RAA0101", "`&str`"));
+    }
+    if let syn::Type::Path(path) = ty
+        && let Some(last) = path.path.segments.last()
+        && last.ident == "Box"
+        && let syn::PathArguments::AngleBracketed(args) = &last.arguments
+        && let Some(syn::GenericArgument::Type(syn::Type::TraitObject(object))) = args.args.first()
+        && object.bounds.iter().any(|bound| {
+            matches!(bound, syn::TypeParamBound::Trait(t)
+                if t.path.segments.last().is_some_and(|s| s.ident == "Error"))
+        })
+    {
+        return Some(("RAA0102", "`Box<dyn Error>`"));
+    }
+    None
+}
+
+/// Reports public functions whose `Result` error type is a string or a boxed
+/// `dyn Error`, which callers cannot match on (`RAA0101` and `RAA0102`).
+///
+/// `main` is not reported, as applications commonly return such errors from it.
+struct StringlyErrorRule;
+
+impl SyntaxRule for StringlyErrorRule {
+    fn check(&self, file: &syn::File) -> Vec<Finding> {
+        let mut finder = StringlyErrorFinder::default();
+        finder.visit_file(file);
+        finder.findings
+    }
+}
+
+/// Collects the public functions returning unstructured errors.
+#[derive(Default)]
+struct StringlyErrorFinder {
+    findings: Vec<Finding>,
+}
+
+impl StringlyErrorFinder {
+    /// Records a finding if `sig` is a public function returning an unstructured error.
+    fn check_fn(&mut self, vis: &syn::Visibility, sig: &syn::Signature) {
+        if !matches!(vis, syn::Visibility::Public(_)) || sig.ident == "main" {
+            return;
+        }
+        if let (_, Some(error)) = result_error_type(sig)
+            && let Some((code, description)) = unstructured_error(error)
+        {
+            self.findings.push(Finding {
+                code: code.to_string(),
+                severity: Severity::Warning,
+                message: format!(
+                    "`{}` returns {} errors; define an error type callers can match on",
+                    sig.ident, description
+                ),
+                line: Some(span_line(sig.ident.span())),
+                category: None,
+                file: None,
+            });
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for StringlyErrorFinder {
+    fn visit_item_fn(&mut self, item: &'ast syn::ItemFn) {
+        self.check_fn(&item.vis, &item.sig);
+        visit::visit_item_fn(self, item);
+    }
+
+    fn visit_impl_item_fn(&mut self, item: &'ast syn::ImplItemFn) {
+        self.check_fn(&item.vis, &item.sig);
+        visit::visit_impl_item_fn(self, item);
+    }
+}
+
+/// Reports `.ok().unwrap()` and `.ok().expect(..)` chains, which discard the error
+/// before panicking on it (`RAA0103`).
+struct OkUnwrapRule;
+
+impl SyntaxRule for OkUnwrapRule {
+    fn check(&self, file: &syn::File) -> Vec<Finding> {
+        let mut finder = OkUnwrapFinder::default();
+        finder.visit_file(file);
+        finder.findings
+    }
+}
+
+/// Collects the `.ok().unwrap()` chains.
+#[derive(Default)]
+struct OkUnwrapFinder {
+    findings: Vec<Finding>,
+}
+
+impl<'ast> Visit<'ast> for OkUnwrapFinder {
+    fn visit_expr_method_call(&mut self, expr: &'ast syn::ExprMethodCall) {
+        if (expr.method == "unwrap" || expr.method == "expect")
+            && let syn::Expr::MethodCall(receiver) = &*expr.receiver
+            && receiver.method == "ok"
+            && receiver.args.is_empty()
+        {
+            self.findings.push(Finding {
+                code: "RAA0103".to_string(),
+                severity: Severity::Warning,
+                message: format!(
+                    "Calls `.ok().{}()`, which hides the error; call `{}` on the `Result`",
+                    expr.method, expr.method
+                ),
+                line: Some(span_line(receiver.method.span())),
+                category: None,
+                file: None,
+            });
+        }
+        visit::visit_expr_method_call(self, expr);
+    }
+}
+
+/// Reports the `_ => {}` and `Err(_) => {}` arms of matches on a `Result`, which
+/// silently ignore errors (`RAA0104`).
+///
+/// A match is assumed to be on a `Result` when one of its arms matches `Ok(..)`.
+struct SwallowedErrorRule;
+
+impl SyntaxRule for SwallowedErrorRule {
+    fn check(&self, file: &syn::File) -> Vec<Finding> {
+        let mut finder = SwallowedErrorFinder::default();
+        finder.visit_file(file);
+        finder.findings
+    }
+}
+
+/// Collects the match arms ignoring errors.
+#[derive(Default)]
+struct SwallowedErrorFinder {
+    findings: Vec<Finding>,
+}
+
+/// Returns whether the pattern is a tuple variant named `name`, such as `Ok(value)`.
+fn is_variant_pattern(pat: &syn::Pat, name: &str) -> bool {
+    matches!(pat, syn::Pat::TupleStruct(p) if p.path.segments.last().is_some_and(|s| s.ident == name))
+}
+
+impl<'ast> Visit<'ast> for SwallowedErrorFinder {
+    fn visit_expr_match(&mut self, expr: &'ast syn::ExprMatch) {
+        if expr
+            .arms
+            .iter()
+            .any(|arm| is_variant_pattern(&arm.pat, "Ok"))
+        {
+            for arm in &expr.arms {
+                let ignores_error = match &arm.pat {
+                    syn::Pat::Wild(_) => true,
+                    syn::Pat::TupleStruct(p) => {
+                        is_variant_pattern(&arm.pat, "Err")
+                            && p.elems
+                                .iter()
+                                .all(|e| matches!(e, syn::Pat::Wild(_) | syn::Pat::Rest(_)))
+                    }
+                    _ => false,
+                };
+                let does_nothing = match &*arm.body {
+                    syn::Expr::Block(block) => block.block.stmts.is_empty(),
+                    syn::Expr::Tuple(tuple) => tuple.elems.is_empty(),
+                    _ => false,
+                };
+                if ignores_error && does_nothing {
+                    self.findings.push(Finding {
+                        code: "RAA0104".to_string(),
+                        severity: Severity::Warning,
+                        message: "Silently ignores the error of a `Result` in an empty match arm"
+                            .to_string(),
+                        line: Some(span_line(arm.fat_arrow_token.spans[0])),
+                        category: None,
+                        file: None,
+                    });
+                }
+            }
+        }
+        visit::visit_expr_match(self, expr);
+    }
+}
+
+/// Reports `panic!` calls in functions that return a `Result`, which should return an
+/// error instead (`RAA0105`).
+///
+/// Closures and nested functions are checked against their own return type; closures
+/// never are, since their return type is usually not written.
+struct PanicInResultFnRule;
+
+impl SyntaxRule for PanicInResultFnRule {
+    fn check(&self, file: &syn::File) -> Vec<Finding> {
+        let mut finder = PanicFinder::default();
+        finder.visit_file(file);
+        finder.findings
+    }
+}
+
+/// Collects the `panic!` calls of functions returning a `Result`.
+#[derive(Default)]
+struct PanicFinder {
+    /// The name of the enclosing function, if it returns a `Result`.
+    result_fn: Option<String>,
+    findings: Vec<Finding>,
+}
+
+impl PanicFinder {
+    /// Visits a function body with the function as the enclosing one.
+    fn visit_fn(&mut self, sig: &syn::Signature, block: &syn::Block) {
+        let result_fn = result_error_type(sig).0.then(|| sig.ident.to_string());
+        let outer = std::mem::replace(&mut self.result_fn, result_fn);
+        self.visit_block(block);
+        self.result_fn = outer;
+    }
+}
+
+impl<'ast> Visit<'ast> for PanicFinder {
+    fn visit_item_fn(&mut self, item: &'ast syn::ItemFn) {
+        self.visit_fn(&item.sig, &item.block);
+    }
+
+    fn visit_impl_item_fn(&mut self, item: &'ast syn::ImplItemFn) {
+        self.visit_fn(&item.sig, &item.block);
+    }
+
+    fn visit_expr_closure(&mut self, expr: &'ast syn::ExprClosure) {
+        let outer = self.result_fn.take();
+        visit::visit_expr_closure(self, expr);
+        self.result_fn = outer;
+    }
+
+    fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+        if let Some(function) = &self.result_fn
+            && let Some(name) = mac.path.segments.last().filter(|s| s.ident == "panic")
+        {
+            self.findings.push(Finding {
+                code: "RAA0105".to_string(),
+                severity: Severity::Warning,
+                message: format!(
+                    "Panics in `{}`, which returns a `Result`; return an error instead",
+                    function
+                ),
+                line: Some(span_line(name.ident.span())),
+                category: None,
+                file: None,
+            });
+        }
+        visit::visit_macro(self, mac);
+    }
+}
+
+/// The functions whose first argument is run as SQL, e.g. `sqlx::query(sql)`.
+const SQL_FUNCTIONS: &[&str] = &["query", "query_as", "query_scalar", "query_with"];
+
+/// The methods whose first argument is run as SQL, e.g. `client.execute(sql, &[])`.
+const SQL_METHODS: &[&str] = &[
+    "execute",
+    "query",
+    "query_one",
+    "query_opt",
+    "prepare",
+    "batch_execute",
+    "simple_query",
+];
+
+/// What the limits of `InjectionRule` are, appended to its findings.
+const INJECTION_RULE_LIMITS: &str = "only values built directly in the call are checked, \
+    not strings built beforehand";
+
+/// Reports SQL queries (`RAA0201`) and process arguments (`RAA0202`) built with
+/// `format!` or `+` from non-literal values, which are open to injection.
+///
+/// This is a conservative heuristic: only a `format!` or a concatenation passed directly
+/// to a known sink is reported, so strings built in a variable first are missed.
+struct InjectionRule;
+
+impl SyntaxRule for InjectionRule {
+    fn check(&self, file: &syn::File) -> Vec<Finding> {
+        let mut finder = InjectionFinder::default();
+        finder.visit_file(file);
+        finder.findings
+    }
+}
+
+/// Collects the interpolated strings passed to SQL and process sinks.
+#[derive(Default)]
+struct InjectionFinder {
+    findings: Vec<Finding>,
+}
+
+impl InjectionFinder {
+    /// Records a finding if `arg`, passed to `sink`, is an interpolated string.
+    fn check_arg(&mut self, arg: Option<&syn::Expr>, sink: &str, sql: bool, line: u32) {
+        if !arg.is_some_and(is_interpolated) {
+            return;
+        }
+        let (code, message) = if sql {
+            (
+                "RAA0201",
+                format!(
+                    "Builds the SQL passed to `{}` from interpolated values, which allows SQL \
+                     injection; bind parameters instead ({})",
+                    sink, INJECTION_RULE_LIMITS
+                ),
+            )
+        } else {
+            (
+                "RAA0202",
+                format!(
+                    "Passes an interpolated string to `{}`, which allows command injection; \
+                     pass each value as a separate argument without a shell ({})",
+                    sink, INJECTION_RULE_LIMITS
+                ),
+            )
+        };
+        self.findings.push(Finding {
+            code: code.to_string(),
+            severity: Severity::Error,
+            message,
+            line: Some(line),
+            category: Some(FindingCategory::Security),
+            file: None,
+        });
+    }
+}
+
+impl<'ast> Visit<'ast> for InjectionFinder {
+    fn visit_expr_call(&mut self, expr: &'ast syn::ExprCall) {
+        if let syn::Expr::Path(path) = &*expr.func
+            && let Some(last) = path.path.segments.last()
+        {
+            let segments = &path.path.segments;
+            let is_command_new = last.ident == "new"
+                && segments.len() >= 2
+                && segments[segments.len() - 2].ident == "Command";
+            if is_command_new {
+                self.check_arg(
+                    expr.args.first(),
+                    "Command::new",
+                    false,
+                    span_line(last.ident.span()),
+                );
+            } else if SQL_FUNCTIONS.iter().any(|f| last.ident == f) {
+                self.check_arg(
+                    expr.args.first(),
+                    &last.ident.to_string(),
+                    true,
+                    span_line(last.ident.span()),
+                );
+            }
+        }
+        visit::visit_expr_call(self, expr);
+    }
+
+    fn visit_expr_method_call(&mut self, expr: &'ast syn::ExprMethodCall) {
+        let line = span_line(expr.method.span());
+        if expr.method == "arg" {
+            self.check_arg(expr.args.first(), "arg", false, line);
+        } else if SQL_METHODS.iter().any(|m| expr.method == m) {
+            self.check_arg(expr.args.first(), &expr.method.to_string(), true, line);
+        }
+        visit::visit_expr_method_call(self, expr);
+    }
+}
+
+/// Returns whether `expr` is a string built from non-literal values: a `format!` with
+/// arguments or placeholders, or a `+` concatenation of something else than literals.
+fn is_interpolated(expr: &syn::Expr) -> bool {
+    match expr {
+        syn::Expr::Reference(reference) => is_interpolated(&reference.expr),
+        syn::Expr::Paren(paren) => is_interpolated(&paren.expr),
+        syn::Expr::MethodCall(call) if call.args.is_empty() => is_interpolated(&call.receiver),
+        syn::Expr::Macro(mac) if mac.mac.path.is_ident("format") => {
+            let Ok(args) = mac.mac.parse_body_with(
+                syn::punctuated::Punctuated::<syn::Expr, syn::Token![,]>::parse_terminated,
+            ) else {
+                return false;
+            };
+            match args.first() {
+                Some(syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(template),
+                    ..
+                })) => args.len() > 1 || template.value().replace("{{", "").contains('{'),
+                _ => false,
+            }
+        }
+        syn::Expr::Binary(binary) if matches!(binary.op, syn::BinOp::Add(_)) => {
+            !is_literal(&binary.left) || !is_literal(&binary.right)
+        }
+        _ => false,
+    }
+}
+
+/// Returns whether `expr` is a string literal, possibly converted to a `String` or
+/// concatenated with other literals.
+fn is_literal(expr: &syn::Expr) -> bool {
+    match expr {
+        syn::Expr::Lit(_) => true,
+        syn::Expr::Reference(reference) => is_literal(&reference.expr),
+        syn::Expr::Paren(paren) => is_literal(&paren.expr),
+        syn::Expr::MethodCall(call) if call.args.is_empty() => is_literal(&call.receiver),
+        syn::Expr::Binary(binary) if matches!(binary.op, syn::BinOp::Add(_)) => {
+            is_literal(&binary.left) && is_literal(&binary.right)
+        }
+        _ => false,
+    }
+}
+
+/// The number of items of a snippet and how complex its functions are.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CodeStructure {
+    /// The number of functions, including methods and default trait methods.
+    pub function_count: usize,
+    /// The number of structs.
+    pub struct_count: usize,
+    /// The average cyclomatic complexity of the functions, or `None` without functions.
+    pub complexity: Option<f64>,
+}
+
+/// Counts the functions and structs of the code and measures their complexity.
+///
+/// The cyclomatic complexity of a function is 1 plus the number of its decision points:
+/// `if`, `while`, `for` and `loop` expressions, match arms beyond the first, `&&`, `||`
+/// and `?`. Closures count towards the enclosing function, nested functions do not.
+///
+/// # Arguments
+///
+/// * `code` - A string slice containing the Rust code to be analyzed.
+///
+/// # Returns
+///
+/// * `Ok(CodeStructure)` - The number of items and the average complexity.
+/// * `Err(AppError::Audit)` - If the code cannot be parsed.
+pub fn analyze_structure(code: &str) -> Result<CodeStructure, AppError> {
+    let file = syn::parse_file(code)
+        .map_err(|e| AppError::Audit(format!("Failed to parse code: {}", e)))?;
+    let mut counter = StructureCounter::default();
+    counter.visit_file(&file);
+
+    let function_count = counter.complexities.len();
+    Ok(CodeStructure {
+        function_count,
+        struct_count: counter.struct_count,
+        complexity: (function_count > 0)
+            .then(|| counter.complexities.iter().sum::<usize>() as f64 / function_count as f64),
+    })
+}
+
+/// Collects the complexity of every function and counts the structs of a parsed file.
+#[derive(Default)]
+struct StructureCounter {
+    /// The number of decision points of the function being visited, if any.
+    current: Option<usize>,
+    complexities: Vec<usize>,
+    struct_count: usize,
+}
+
+impl StructureCounter {
+    /// Measures the complexity of a function body.
+    fn visit_fn(&mut self, block: &syn::Block) {
+        let outer = self.current.replace(0);
+        self.visit_block(block);
+        let decisions = std::mem::replace(&mut self.current, outer).unwrap_or_default();
+        self.complexities.push(1 + decisions);
+    }
+
+    /// Counts `count` decision points in the function being visited.
+    fn decide(&mut self, count: usize) {
+        if let Some(decisions) = &mut self.current {
+            *decisions += count;
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for StructureCounter {
+    fn visit_item_fn(&mut self, item: &'ast syn::ItemFn) {
+        self.visit_fn(&item.block);
+    }
+
+    fn visit_impl_item_fn(&mut self, item: &'ast syn::ImplItemFn) {
+        self.visit_fn(&item.block);
+    }
+
+    fn visit_trait_item_fn(&mut self, item: &'ast syn::TraitItemFn) {
+        if let Some(block) = &item.default {
+            self.visit_fn(block);
+        }
+    }
+
+    fn visit_item_struct(&mut self, item: &'ast syn::ItemStruct) {
+        self.struct_count += 1;
+        visit::visit_item_struct(self, item);
+    }
+
+    fn visit_expr_if(&mut self, expr: &'ast syn::ExprIf) {
+        self.decide(1);
+        visit::visit_expr_if(self, expr);
+    }
+
+    fn visit_expr_while(&mut self, expr: &'ast syn::ExprWhile) {
+        self.decide(1);
+        visit::visit_expr_while(self, expr);
+    }
+
+    fn visit_expr_for_loop(&mut self, expr: &'ast syn::ExprForLoop) {
+        self.decide(1);
+        visit::visit_expr_for_loop(self, expr);
+    }
+
+    fn visit_expr_loop(&mut self, expr: &'ast syn::ExprLoop) {
+        self.decide(1);
+        visit::visit_expr_loop(self, expr);
+    }
+
+    fn visit_expr_match(&mut self, expr: &'ast syn::ExprMatch) {
+        self.decide(expr.arms.len().saturating_sub(1));
+        visit::visit_expr_match(self, expr);
+    }
+
+    fn visit_expr_binary(&mut self, expr: &'ast syn::ExprBinary) {
+        if matches!(expr.op, syn::BinOp::And(_) | syn::BinOp::Or(_)) {
+            self.decide(1);
+        }
+        visit::visit_expr_binary(self, expr);
+    }
+
+    fn visit_expr_try(&mut self, expr: &'ast syn::ExprTry) {
+        self.decide(1);
+        visit::visit_expr_try(self, expr);
+    }
+}
+
+/// Scans code for dangerous or suspicious patterns without compiling it.
+///
+/// # Arguments
+///
+/// * `code` - A string slice containing the Rust code to be validated.
+/// * `policy` - The policy providing the doc coverage threshold, the banned crates, the
+///   known standard library paths, the severity of hygiene findings and the disabled
+///   rules.
+///
+/// # Returns
+///
+/// * `Vec<Finding>` - The token rule matches in source order, followed by the error
+///   handling findings, the blocking calls made in async code, the banned crates used,
+///   the unknown standard library paths imported, the hygiene findings, the doc coverage
+///   warning and the missing license header, if any, except those of the rules disabled
+///   by the policy. For code longer than `policy.max_lines`, a single `RAA0012` error
+///   instead, since it is not analyzed.
+pub fn validate_code(code: &str, policy: &AuditPolicy) -> Vec<Finding> {
+    // `lines` splits on both `\n` and `\r\n`, and ignores a final line break.
+    let line_count = code.lines().count();
+    if line_count > policy.max_lines {
+        return vec![Finding {
+            code: TOO_MANY_LINES_CODE.to_string(),
+            severity: Severity::Error,
+            message: format!(
+                "Has {} lines, more than the limit of {}",
+                line_count, policy.max_lines
+            ),
+            line: None,
+            category: None,
+            file: None,
+        }];
+    }
+    let rules: [&dyn ValidationRule; 8] = [
+        &TokenRules,
+        &SyntaxRules,
+        &BlockingCallRule,
+        &BannedCrateRule {
+            patterns: &policy.banned_crates,
+        },
+        &StdPathRule {
+            extra_paths: &policy.known_std_paths,
+        },
+        &HygieneRule {
+            severity: policy.hygiene_severity,
+        },
+        &DocCoverageRule {
+            threshold: policy.doc_coverage_threshold,
+        },
+        &LicenseHeaderRule,
+    ];
+    rules
+        .iter()
+        .flat_map(|rule| rule.check(code))
+        .filter(|finding| !policy.disabled_rules.contains(&finding.code))
+        .collect()
+}
+
+/// Computes the share of public items carrying a `///` doc comment.
+///
+/// Public functions, structs, enums and traits are counted, including those nested in
+/// inline modules and the public methods of `impl` blocks.
+///
+/// # Arguments
+///
+/// * `code` - A string slice containing the Rust code to be analyzed.
+///
+/// # Returns
+///
+/// * `Ok(f64)` - The percentage of documented public items, between 0.0 and 100.0. Code
+///   without public items is fully covered.
+/// * `Err(AppError::Audit)` - If the code cannot be parsed.
+pub fn compute_doc_coverage(code: &str) -> Result<f64, AppError> {
+    let file = syn::parse_file(code)
+        .map_err(|e| AppError::Audit(format!("Failed to parse code: {}", e)))?;
+
+    let (mut public, mut documented) = (0u32, 0u32);
+    count_documented_items(&file.items, &mut public, &mut documented);
+
+    if public == 0 {
+        return Ok(100.0);
+    }
+    Ok(f64::from(documented) * 100.0 / f64::from(public))
+}
+
+/// Counts the public items of `items` and how many of them are documented.
+fn count_documented_items(items: &[syn::Item], public: &mut u32, documented: &mut u32) {
+    let mut count = |vis: &syn::Visibility, attrs: &[syn::Attribute]| {
+        if matches!(vis, syn::Visibility::Public(_)) {
+            *public += 1;
+            if has_doc_comment(attrs) {
+                *documented += 1;
+            }
+        }
+    };
+
+    let mut nested = Vec::new();
+    for item in items {
+        match item {
+            syn::Item::Fn(item) => count(&item.vis, &item.attrs),
+            syn::Item::Struct(item) => count(&item.vis, &item.attrs),
+            syn::Item::Enum(item) => count(&item.vis, &item.attrs),
+            syn::Item::Trait(item) => count(&item.vis, &item.attrs),
+            syn::Item::Impl(item) => {
+                for impl_item in &item.items {
+                    if let syn::ImplItem::Fn(method) = impl_item {
+                        count(&method.vis, &method.attrs);
+                    }
+                }
+            }
+            syn::Item::Mod(item) => {
+                if let Some((_, content)) = &item.content {
+                    nested.push(content);
+                }
+            }
+            _ => {}
+        }
+    }
+    for content in nested {
+        count_documented_items(content, public, documented);
+    }
+}
+
+/// Returns whether the attributes include an outer doc comment (`///` or `#[doc = ...]`).
+fn has_doc_comment(attrs: &[syn::Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        matches!(attr.style, syn::AttrStyle::Outer)
+            && matches!(&attr.meta, syn::Meta::NameValue(meta) if meta.path.is_ident("doc"))
+    })
+}
+
+/// The operation that makes an `unsafe` block, function or implementation necessary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnsafeCategory {
+    /// Dereferences a raw pointer.
+    RawPointerDeref,
+    /// Calls a function declared in an `extern` block.
+    ForeignFunction,
+    /// Uses inline assembly (`asm!`, `global_asm!` or `naked_asm!`).
+    InlineAssembly,
+    /// Reads or writes a `static mut`.
+    MutableStaticAccess,
+    /// Implements an `unsafe` trait (e.g. `unsafe impl Send`).
+    UnsafeTraitImpl,
+    /// Any other operation, such as calling an `unsafe fn`.
+    Other,
+}
+
+impl UnsafeCategory {
+    /// Returns the identifier under which the category is stored and reported.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UnsafeCategory::RawPointerDeref => "raw_pointer_deref",
+            UnsafeCategory::ForeignFunction => "foreign_function",
+            UnsafeCategory::InlineAssembly => "inline_assembly",
+            UnsafeCategory::MutableStaticAccess => "mutable_static_access",
+            UnsafeCategory::UnsafeTraitImpl => "unsafe_trait_impl",
+            UnsafeCategory::Other => "other",
+        }
+    }
+}
+
+/// Locates and classifies the `unsafe` code of a snippet.
+///
+/// Every `unsafe` block, `unsafe fn` with a body and `unsafe impl` is counted. Blocks
+/// and functions are classified by the operations they contain; when several kinds are
+/// present, inline assembly wins over foreign calls, which win over `static mut`
+/// accesses, which win over dereferences. Dereferences are assumed to be of raw
+/// pointers, since types are not known before compilation.
+///
+/// # Arguments
+///
+/// * `code` - A string slice containing the Rust code to be analyzed.
+///
+/// # Returns
+///
+/// * `Ok(UnsafeReport)` - The number of occurrences, per category and in total, and the
+///   line of the first one.
+/// * `Err(AppError::Audit)` - If the code cannot be parsed.
+pub fn check_unsafe_usage(code: &str) -> Result<UnsafeReport, AppError> {
+    let file = syn::parse_file(code)
+        .map_err(|e| AppError::Audit(format!("Failed to parse code: {}", e)))?;
+
+    let mut declarations = UnsafeDeclarations::default();
+    declarations.visit_file(&file);
+    let mut finder = UnsafeFinder {
+        declarations: &declarations,
+        occurrences: Vec::new(),
+    };
+    finder.visit_file(&file);
+
+    let mut by_category = HashMap::new();
+    for (_, category) in &finder.occurrences {
+        *by_category
+            .entry(category.as_str().to_string())
+            .or_insert(0) += 1;
+    }
+    Ok(UnsafeReport {
+        total_count: finder.occurrences.len(),
+        by_category,
+        first_occurrence_line: finder.occurrences.iter().map(|(line, _)| *line).min(),
+    })
+}
+
+/// The items of a snippet whose use requires `unsafe`.
+#[derive(Default)]
+struct UnsafeDeclarations {
+    /// The functions declared in `extern` blocks.
+    foreign_functions: HashSet<String>,
+    /// The `static mut` items.
+    mutable_statics: HashSet<String>,
+}
+
+impl<'ast> Visit<'ast> for UnsafeDeclarations {
+    fn visit_foreign_item_fn(&mut self, item: &'ast syn::ForeignItemFn) {
+        self.foreign_functions.insert(item.sig.ident.to_string());
+    }
+
+    fn visit_foreign_item_static(&mut self, item: &'ast syn::ForeignItemStatic) {
+        self.mutable_statics.insert(item.ident.to_string());
+    }
+
+    fn visit_item_static(&mut self, item: &'ast syn::ItemStatic) {
+        if matches!(item.mutability, syn::StaticMutability::Mut(_)) {
+            self.mutable_statics.insert(item.ident.to_string());
+        }
+        visit::visit_item_static(self, item);
+    }
+}
+
+/// Collects the line and category of every `unsafe` block, function and implementation.
+struct UnsafeFinder<'a> {
+    declarations: &'a UnsafeDeclarations,
+    occurrences: Vec<(u32, UnsafeCategory)>,
+}
+
+impl UnsafeFinder<'_> {
+    /// Records an occurrence starting at `span`, classified by the operations in `block`.
+    fn record(&mut self, span: proc_macro2::Span, block: &syn::Block) {
+        let category = UnsafeOperations::of(block, self.declarations).category();
+        self.occurrences.push((span_line(span), category));
+    }
+
+    /// Records an occurrence if `sig` is an `unsafe fn` with a body.
+    fn record_fn(&mut self, sig: &syn::Signature, block: &syn::Block) {
+        if let syn::Safety::Unsafe(token) = &sig.safety {
+            self.record(token.span, block);
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for UnsafeFinder<'_> {
+    fn visit_expr_unsafe(&mut self, expr: &'ast syn::ExprUnsafe) {
+        self.record(expr.unsafe_token.span, &expr.block);
+        visit::visit_expr_unsafe(self, expr);
+    }
+
+    fn visit_item_fn(&mut self, item: &'ast syn::ItemFn) {
+        self.record_fn(&item.sig, &item.block);
+        visit::visit_item_fn(self, item);
+    }
+
+    fn visit_impl_item_fn(&mut self, item: &'ast syn::ImplItemFn) {
+        self.record_fn(&item.sig, &item.block);
+        visit::visit_impl_item_fn(self, item);
+    }
+
+    fn visit_trait_item_fn(&mut self, item: &'ast syn::TraitItemFn) {
+        if let Some(block) = &item.default {
+            self.record_fn(&item.sig, block);
+        }
+        visit::visit_trait_item_fn(self, item);
+    }
+
+    fn visit_item_impl(&mut self, item: &'ast syn::ItemImpl) {
+        if let Some(token) = &item.unsafety {
+            self.occurrences
+                .push((span_line(token.span), UnsafeCategory::UnsafeTraitImpl));
+        }
+        visit::visit_item_impl(self, item);
+    }
+}
+
+/// The kinds of operations found in an `unsafe` block or function.
+struct UnsafeOperations<'a> {
+    declarations: &'a UnsafeDeclarations,
+    inline_assembly: bool,
+    foreign_call: bool,
+    mutable_static: bool,
+    dereference: bool,
+}
+
+impl<'a> UnsafeOperations<'a> {
+    /// Finds the operations of `block`.
+    fn of(block: &syn::Block, declarations: &'a UnsafeDeclarations) -> Self {
+        let mut operations = UnsafeOperations {
+            declarations,
+            inline_assembly: false,
+            foreign_call: false,
+            mutable_static: false,
+            dereference: false,
+        };
+        operations.visit_block(block);
+        operations
+    }
+
+    /// Returns the category of the most significant operation found.
+    fn category(&self) -> UnsafeCategory {
+        if self.inline_assembly {
+            UnsafeCategory::InlineAssembly
+        } else if self.foreign_call {
+            UnsafeCategory::ForeignFunction
+        } else if self.mutable_static {
+            UnsafeCategory::MutableStaticAccess
+        } else if self.dereference {
+            UnsafeCategory::RawPointerDeref
+        } else {
+            UnsafeCategory::Other
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for UnsafeOperations<'_> {
+    fn visit_expr_unary(&mut self, expr: &'ast syn::ExprUnary) {
+        if matches!(expr.op, syn::UnOp::Deref(_)) {
+            self.dereference = true;
+        }
+        visit::visit_expr_unary(self, expr);
+    }
+
+    fn visit_expr_call(&mut self, expr: &'ast syn::ExprCall) {
+        if let syn::Expr::Path(path) = &*expr.func
+            && let Some(name) = path.path.get_ident()
+            && self
+                .declarations
+                .foreign_functions
+                .contains(&name.to_string())
+        {
+            self.foreign_call = true;
+        }
+        visit::visit_expr_call(self, expr);
+    }
+
+    fn visit_expr_path(&mut self, expr: &'ast syn::ExprPath) {
+        if let Some(name) = expr.path.get_ident()
+            && self
+                .declarations
+                .mutable_statics
+                .contains(&name.to_string())
+        {
+            self.mutable_static = true;
+        }
+        visit::visit_expr_path(self, expr);
+    }
+
+    fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+        if mac.path.segments.last().is_some_and(|s| {
+            matches!(
+                s.ident.to_string().as_str(),
+                "asm" | "global_asm" | "naked_asm"
+            )
+        }) {
+            self.inline_assembly = true;
+        }
+        // Macro arguments are not parsed: look for `static mut` names among their tokens,
+        // as in `addr_of_mut!(COUNTER)`.
+        if mac.tokens.clone().into_iter().any(|token| {
+            matches!(token, proc_macro2::TokenTree::Ident(ident)
+                if self.declarations.mutable_statics.contains(&ident.to_string()))
+        }) {
+            self.mutable_static = true;
+        }
+        visit::visit_macro(self, mac);
+    }
+}
+
+/// Returns the 1-based line a span starts on.
+fn span_line(span: proc_macro2::Span) -> u32 {
+    u32::try_from(span.start().line).unwrap_or(u32::MAX)
+}
+
+/// Returns whether `token` occurs in `line` without being part of a longer identifier.
+fn contains_token(line: &str, token: &str) -> bool {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    line.match_indices(token).any(|(start, _)| {
+        let before = line[..start].chars().next_back();
+        let after = line[start + token.len()..].chars().next();
+        let joined_before = token.starts_with(is_ident) && before.is_some_and(is_ident);
+        let joined_after = token.ends_with(is_ident) && after.is_some_and(is_ident);
+        !joined_before && !joined_after
+    })
+}
+
+/// A token of code, as seen by `detect_edition_from_code`. Delimiters are punctuation.
+#[derive(Debug, PartialEq)]
+enum EditionToken {
+    Ident(String),
+    Punct(char),
+    Literal,
+}
+
+/// Flattens a token stream into `tokens`, with the delimiters of groups around their
+/// content.
+fn flatten_tokens(stream: proc_macro2::TokenStream, tokens: &mut Vec<EditionToken>) {
+    use proc_macro2::{Delimiter, TokenTree};
+
+    for tree in stream {
+        match tree {
+            TokenTree::Ident(ident) => tokens.push(EditionToken::Ident(ident.to_string())),
+            TokenTree::Punct(punct) => tokens.push(EditionToken::Punct(punct.as_char())),
+            TokenTree::Literal(_) => tokens.push(EditionToken::Literal),
+            TokenTree::Group(group) => {
+                let (open, close) = match group.delimiter() {
+                    Delimiter::Parenthesis => (Some('('), Some(')')),
+                    Delimiter::Brace => (Some('{'), Some('}')),
+                    Delimiter::Bracket => (Some('['), Some(']')),
+                    Delimiter::None => (None, None),
+                };
+                tokens.extend(open.map(EditionToken::Punct));
+                flatten_tokens(group.stream(), tokens);
+                tokens.extend(close.map(EditionToken::Punct));
+            }
+        }
+    }
+}
+
+/// The traits of the 2021 prelude that earlier editions must import, with the module
+/// they are imported from and the methods that need them in scope.
+const EDITION_2021_PRELUDE: [(&str, &str, &str); 3] = [
+    ("TryFrom", "convert", "try_from"),
+    ("TryInto", "convert", "try_into"),
+    ("FromIterator", "iter", "from_iter"),
+];
+
+/// Guesses the Rust edition code is written for.
+///
+/// The guess relies on syntax that only some editions accept:
+///
+/// * `let` chains (`if let Some(x) = a && let Some(y) = b`) require 2024.
+/// * Using `TryFrom`, `TryInto` or `FromIterator` (or their methods) without importing
+///   them requires the prelude of 2021.
+/// * `async` blocks and functions, `.await` and `dyn Trait` require 2018.
+/// * `try!`, `async`, `await`, `dyn` or `try` used as names, and `extern crate` of
+///   crates other than the standard ones, are only accepted or needed by 2015.
+///
+/// The latest edition some syntax requires wins; code that looks like 2015 code is
+/// guessed to be 2015 code, and code without hints, or that cannot be tokenized, 2021
+/// code, the default of the audits.
+///
+/// # Arguments
+///
+/// * `code` - The code to guess the edition of.
+///
+/// # Returns
+///
+/// * `Edition` - The guessed edition.
+pub fn detect_edition_from_code(code: &str) -> Edition {
+    use EditionToken::{Ident, Punct};
+
+    let Ok(stream) = code.parse::<proc_macro2::TokenStream>() else {
+        return Edition::default();
+    };
+    let mut tokens = Vec::new();
+    flatten_tokens(stream, &mut tokens);
+
+    let is_ident = |token: Option<&EditionToken>, names: &[&str]| matches!(token, Some(Ident(name)) if names.contains(&name.as_str()));
+    let is_punct = |token: Option<&EditionToken>, chars: &[char]| matches!(token, Some(Punct(c)) if chars.contains(c));
+
+    let mut imported = HashSet::new();
+    let mut in_use = false;
+    let mut used_prelude = HashSet::new();
+    let mut required = None;
+    let mut looks_like_2015 = false;
+    for (i, token) in tokens.iter().enumerate() {
+        let (previous, next) = (
+            i.checked_sub(1).and_then(|i| tokens.get(i)),
+            tokens.get(i + 1),
+        );
+        match token {
+            Ident(name) if name == "use" => in_use = true,
+            Punct(';') => in_use = false,
+            Ident(name) if in_use => {
+                imported.insert(name.clone());
+            }
+            Ident(name) if name == "let" && is_punct(previous, &['&']) => {
+                required = required.max(Some(Edition::E2024));
+            }
+            Ident(name) => {
+                used_prelude.extend(
+                    EDITION_2021_PRELUDE
+                        .iter()
+                        .filter(|(trait_name, _, method)| name == trait_name || name == method)
+                        .map(|(trait_name, module, _)| (*trait_name, *module)),
+                );
+                let async_syntax = name == "async"
+                    && (is_ident(next, &["fn", "move", "unsafe"]) || is_punct(next, &['{', '|']));
+                let await_syntax =
+                    name == "await" && is_punct(previous, &['.']) && !is_punct(next, &['(']);
+                let dyn_syntax = name == "dyn" && matches!(next, Some(Ident(_)));
+                if async_syntax || await_syntax || dyn_syntax {
+                    required = required.max(Some(Edition::E2018));
+                }
+                let keyword_as_name = ["async", "await", "dyn", "try"].contains(&name.as_str())
+                    && is_ident(
+                        previous,
+                        &[
+                            "let", "fn", "mut", "struct", "enum", "mod", "trait", "type", "const",
+                            "static",
+                        ],
+                    );
+                let try_macro = name == "try" && is_punct(next, &['!']);
+                let extern_crate = is_ident(previous, &["crate"])
+                    && is_ident(i.checked_sub(2).and_then(|i| tokens.get(i)), &["extern"])
+                    && !["std", "core", "alloc", "proc_macro", "test"].contains(&name.as_str());
+                if keyword_as_name || try_macro || extern_crate {
+                    looks_like_2015 = true;
+                }
+            }
+            _ => {}
+        }
+    }
+    let needs_2021_prelude = used_prelude
+        .iter()
+        .any(|(name, module)| !imported.contains(*name) && !imported.contains(*module));
+    if needs_2021_prelude {
+        required = required.max(Some(Edition::E2021));
+    }
+
+    match required {
+        Some(edition) => edition,
+        None if looks_like_2015 => Edition::E2015,
+        None => Edition::default(),
+    }
+}
+
+/// The settings of a `rustc` invocation, or of `cargo check` for a Cargo package.
+#[derive(Debug, Clone, Default)]
+pub struct CompileOptions {
+    /// The release channel of the toolchain to compile with.
+    pub channel: Channel,
+    /// The optimization level, passed as `-C opt-level=`.
+    pub opt_level: OptLevel,
+    /// The Rust edition, passed as `--edition`. A Cargo package has the edition of its
+    /// manifest instead.
+    pub edition: Edition,
+    /// The target triple, passed as `--target`, or `None` for the host.
+    pub target: Option<String>,
+    /// The files of a crate submitted as several files, compiled from its root (see
+    /// `crate_root`) instead of the code, or checked by cargo if they include a
+    /// [`MANIFEST`]. Empty for a single file.
+    pub files: Vec<AuditFile>,
+}
+
+/// The paths a crate of several files may be compiled from, by order of preference.
+pub const CRATE_ROOTS: [&str; 4] = ["src/lib.rs", "lib.rs", "src/main.rs", "main.rs"];
+
+/// The deepest a file of a crate of several files may be, in directories from its root.
+pub const MAX_FILE_DEPTH: usize = 4;
+
+/// The manifest making a crate of several files a Cargo package, checked by `cargo check`.
+pub const MANIFEST: &str = "Cargo.toml";
+
+/// The tables of a manifest declaring dependencies, also found under `[target.<cfg>]`.
+const DEPENDENCY_TABLES: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// The keys of a dependency taking it from elsewhere than crates.io.
+const NON_REGISTRY_KEYS: [&str; 4] = ["path", "git", "registry", "registry-index"];
+
+/// Returns whether a crate submitted as several files is a Cargo package, that is whether
+/// one of its files is the [`MANIFEST`].
+pub fn is_cargo_package(files: &[AuditFile]) -> bool {
+    files.iter().any(|file| file.path == MANIFEST)
+}
+
+/// Checks the manifest of a Cargo package before cargo reads it.
+///
+/// Cargo runs build scripts and reads path dependencies from anywhere on the server, so
+/// neither is accepted: dependencies must come from crates.io, and are resolved from the
+/// local cache of the server since packages are checked offline. The package is checked
+/// on its own, so workspaces, `[patch]` and `[replace]` are refused too.
+///
+/// # Arguments
+///
+/// * `files` - The files of the crate.
+///
+/// # Returns
+///
+/// * `Ok(())` - If the crate has no manifest, or one that can be checked.
+/// * `Err(String)` - Why the manifest is refused, e.g. it declares a path dependency.
+pub fn check_manifest(files: &[AuditFile]) -> Result<(), String> {
+    let Some(manifest) = files.iter().find(|file| file.path == MANIFEST) else {
+        return Ok(());
+    };
+    let manifest: toml::Table = manifest
+        .content
+        .parse()
+        .map_err(|e| format!("{}: invalid manifest: {}", MANIFEST, e))?;
+    let Some(package) = manifest.get("package").and_then(toml::Value::as_table) else {
+        return Err(format!("{}: a [package] table is required", MANIFEST));
+    };
+    if let Some(table) = ["workspace", "patch", "replace"]
+        .into_iter()
+        .find(|table| manifest.contains_key(*table))
+    {
+        return Err(format!("{}: [{}] is not supported", MANIFEST, table));
+    }
+    let build_script = match package.get("build") {
+        Some(toml::Value::Boolean(false)) => false,
+        Some(_) => true,
+        None => files.iter().any(|file| file.path == "build.rs"),
+    };
+    if build_script {
+        return Err(format!(
+            "{}: build scripts are not run; remove build.rs or set `build = false`",
+            MANIFEST
+        ));
+    }
+
+    let targets = manifest
+        .get("target")
+        .and_then(toml::Value::as_table)
+        .into_iter()
+        .flat_map(|targets| targets.values().filter_map(toml::Value::as_table));
+    let dependencies = std::iter::once(&manifest)
+        .chain(targets)
+        .flat_map(|table| DEPENDENCY_TABLES.iter().filter_map(|key| table.get(*key)))
+        .filter_map(toml::Value::as_table)
+        .flatten();
+    for (name, dependency) in dependencies {
+        let from_registry = dependency.as_table().is_none_or(|dependency| {
+            !NON_REGISTRY_KEYS
+                .iter()
+                .any(|key| dependency.contains_key(*key))
+        });
+        if !from_registry {
+            return Err(format!(
+                "{}: the dependency `{}` must come from crates.io",
+                MANIFEST, name
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the root of a crate submitted as several files, the first of [`CRATE_ROOTS`]
+/// among its files.
+pub fn crate_root(files: &[AuditFile]) -> Option<&AuditFile> {
+    CRATE_ROOTS
+        .iter()
+        .find_map(|root| files.iter().find(|file| file.path == *root))
+}
+
+/// Checks the path of a file of a crate of several files.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file, relative to the root of the crate.
+///
+/// # Returns
+///
+/// * `Ok(())` - If the path is a `.rs` file, or `Cargo.toml` at the root, at most
+///   [`MAX_FILE_DEPTH`] directories deep.
+/// * `Err(String)` - Why the path is refused, e.g. it is absolute or contains `..`.
+pub fn check_file_path(path: &str) -> Result<(), String> {
+    if path.is_empty() {
+        return Err("paths must not be empty".to_string());
+    }
+    if path.contains(['\\', '\0']) {
+        return Err(format!(
+            "{}: paths must use / as the separator, without NUL characters",
+            path
+        ));
+    }
+    // Split by hand: `Path::components` skips the `.` and empty components.
+    let components: Vec<&str> = path.split('/').collect();
+    if components
+        .iter()
+        .any(|component| matches!(*component, "" | "." | ".."))
+    {
+        return Err(format!(
+            "{}: paths must be relative, without . or .. components",
+            path
+        ));
+    }
+    if components.len() > MAX_FILE_DEPTH + 1 {
+        return Err(format!(
+            "{}: files must be at most {} directories deep",
+            path, MAX_FILE_DEPTH
+        ));
+    }
+    if !path.ends_with(".rs") && path != "Cargo.toml" {
+        return Err(format!(
+            "{}: only .rs files and a Cargo.toml at the root are accepted",
+            path
+        ));
+    }
+    Ok(())
+}
+
+/// What `stream_compilation` sends while code compiles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompilerOutput {
+    /// The compilation reached a stage: `compiling` once the compiler runs on the code,
+    /// preceded by `dependencies_resolved` for a Cargo package.
+    Stage(AuditProgressStage),
+    /// A line of compiler output.
+    Line(String),
+}
+
+/// The outcome of a completed `rustc` invocation.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompilationOutcome {
+    /// Whether the code compiled successfully.
+    pub success: bool,
+    /// The human-readable compiler output, as `rustc` would print it to a terminal.
+    pub output: String,
+    /// The diagnostics parsed from the compiler's JSON output.
+    pub diagnostics: Vec<Diagnostic>,
+    /// How long `rustc` ran.
+    pub duration: Duration,
+    /// The JSON lines `rustc` wrote, from which the diagnostics are parsed.
+    #[serde(default)]
+    pub json_output: String,
+    /// The compiled library, if the code compiled. It is not cached, so a result read
+    /// from the compilation cache has none.
+    #[serde(skip)]
+    pub library: Option<Vec<u8>>,
+    /// The `Cargo.lock` cargo resolved the dependencies of a Cargo package into, if it got
+    /// that far.
+    #[serde(default)]
+    pub lockfile: Option<String>,
+    /// The packages a Cargo package depends on, directly or not, with their license, if
+    /// cargo resolved them.
+    #[serde(default)]
+    pub dependencies: Option<Vec<LockedDependency>>,
+    /// The advisories `cargo audit` reported for the dependencies of a Cargo package, if
+    /// it ran.
+    #[serde(default)]
+    pub security_advisories: Option<Vec<SecurityAdvisory>>,
+}
+
+/// A package locked in the `Cargo.lock` of a Cargo package.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockedDependency {
+    /// The name of the package.
+    pub name: String,
+    /// The locked version of the package.
+    pub version: String,
+    /// The SPDX license expression of its manifest (e.g. `MIT OR Apache-2.0`), if any.
+    pub license: Option<String>,
+}
+
+/// A diagnostic as emitted by `rustc --error-format=json`.
+#[derive(Debug, Deserialize)]
+struct RustcDiagnostic {
+    message: String,
+    code: Option<RustcDiagnosticCode>,
+    level: String,
+    spans: Vec<RustcSpan>,
+    rendered: Option<String>,
+}
+
+/// The error code attached to a `rustc` JSON diagnostic.
+#[derive(Debug, Deserialize)]
+struct RustcDiagnosticCode {
+    code: String,
+}
+
+/// A source location attached to a `rustc` JSON diagnostic.
+#[derive(Debug, Deserialize)]
+struct RustcSpan {
+    file_name: String,
+    line_start: u32,
+    column_start: u32,
+    is_primary: bool,
+}
+
+/// The directory holding the temporary files of compilations.
+const TEMP_DIR: &str = "/tmp";
+
+/// The names of the temporary crates that exist, whose files must not be swept.
+static ACTIVE_TEMP_CRATES: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(Mutex::default);
+
+/// Locks the names of the temporary crates that exist.
+fn active_temp_crates() -> MutexGuard<'static, HashSet<String>> {
+    // The set holds no invariants that a panic could break, so recover from poisoning.
+    ACTIVE_TEMP_CRATES.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A uniquely named temporary crate, removed along with its output when dropped.
+///
+/// Dropping also cleans up after compilations that are cancelled halfway.
+///
+/// A crate of a single file is written to `audit_<uuid>.rs`, and a crate of several
+/// files to the `audit_<uuid>` directory, along with the build directory of cargo for a
+/// Cargo package.
+struct TempCrate {
+    name: String,
+    source_path: String,
+    /// The directory holding the files of a crate of several files.
+    dir: Option<String>,
+    /// Whether the crate is a Cargo package, checked by cargo instead of `rustc`.
+    package: bool,
+}
+
+impl TempCrate {
+    /// Writes `code` to a new temporary source file, or `files` to a new temporary
+    /// directory if there are any, in which case `code` is the one of their root.
+    fn write(code: &str, files: &[AuditFile]) -> Result<Self, AppError> {
+        let name = format!("audit_{}", Uuid::new_v4().simple());
+        let write_error = |e: std::io::Error| {
+            AppError::Audit(format!("Failed to write temporary audit file: {}", e))
+        };
+        // Registered first, so that the sweeper never sees its files unregistered.
+        active_temp_crates().insert(name.clone());
+        if files.is_empty() {
+            let source_path = format!("{}/{}.rs", TEMP_DIR, name);
+            let temp_crate = TempCrate {
+                name,
+                source_path,
+                dir: None,
+                package: false,
+            };
+            fs::write(&temp_crate.source_path, code).map_err(write_error)?;
+            return Ok(temp_crate);
+        }
+
+        let dir = format!("{}/{}", TEMP_DIR, name);
+        let root = crate_root(files).map_or(CRATE_ROOTS[0], |root| root.path.as_str());
+        let temp_crate = TempCrate {
+            source_path: format!("{}/{}", dir, root),
+            name,
+            dir: Some(dir.clone()),
+            package: is_cargo_package(files),
+        };
+        // Paths and manifests are checked when audits are created, but they end up on
+        // the disk and in cargo.
+        check_manifest(files).map_err(AppError::Audit)?;
+        for file in files {
+            check_file_path(&file.path).map_err(AppError::Audit)?;
+            let path = Path::new(&dir).join(&file.path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(write_error)?;
+            }
+            let mut content = file.content.clone();
+            if file.path == MANIFEST {
+                // Keeps cargo from looking for a workspace in the parent directories.
+                content.push_str("\n[workspace]\n");
+            }
+            fs::write(path, content).map_err(write_error)?;
+        }
+        Ok(temp_crate)
+    }
+
+    /// Returns the name of the program compiling the crate.
+    fn compiler(&self) -> &'static str {
+        if self.package { "cargo" } else { "rustc" }
+    }
+
+    /// Builds the command compiling the crate, with diagnostics in JSON format: `cargo
+    /// check` for a Cargo package, else `rustc` compiling the crate as a library (so
+    /// `fn main()` is not required).
+    fn compile_command(&self, options: &CompileOptions) -> Command {
+        match &self.dir {
+            Some(dir) if self.package => {
+                package_command(cargo_command(options.channel, "check"), dir, options)
+            }
+            _ => self.compiler_command(rustc_command(options.channel), options),
+        }
+    }
+
+    /// Builds the command linting the crate with Clippy: `cargo clippy` for a Cargo
+    /// package, else `clippy-driver`.
+    fn clippy_command(&self, options: &CompileOptions) -> Command {
+        match &self.dir {
+            Some(dir) if self.package => {
+                package_command(cargo_command(options.channel, "clippy"), dir, options)
+            }
+            _ => self.compiler_command(clippy_command(options.channel), options),
+        }
+    }
+
+    /// Adds the arguments compiling the crate to `command`, which runs `rustc` or a
+    /// wrapper taking the same arguments such as `clippy-driver`.
+    fn compiler_command(&self, mut command: Command, options: &CompileOptions) -> Command {
+        command
+            .args(compiler_flags(options))
+            .arg("--crate-name")
+            .arg(&self.name)
+            .arg("--out-dir")
+            .arg(TEMP_DIR)
+            .arg(&self.source_path);
+        command
+    }
+
+    /// Reads the library compiled from the crate, if any.
+    fn library(&self) -> Option<Vec<u8>> {
+        fs::read(format!("{}/lib{}.rlib", TEMP_DIR, self.name)).ok()
+    }
+
+    /// Builds the command resolving the dependencies of a Cargo package into its
+    /// `Cargo.lock` from the local registry cache, if the crate is one.
+    fn resolve_command(&self, options: &CompileOptions) -> Option<Command> {
+        let dir = self.dir.as_ref().filter(|_| self.package)?;
+        let mut command = cargo_command(options.channel, "generate-lockfile");
+        command
+            .args(["--offline", "--quiet", "--manifest-path"])
+            .arg(format!("{}/{}", dir, MANIFEST))
+            .current_dir(dir);
+        Some(command)
+    }
+
+    /// Resolves the dependencies of a Cargo package offline, so that it is then checked
+    /// with `--frozen`. Other crates have nothing to resolve. Cargo runs like the
+    /// compiler (see [`run_cargo`]), within the timeout of the compilation.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the crate is not a Cargo package, or cargo resolved its
+    ///   dependencies.
+    /// * `Err(AppError::Audit)` - If a dependency is not in the local registry cache, or
+    ///   cargo failed to resolve them for another reason.
+    async fn resolve(&self, options: &CompileOptions) -> Result<(), AppError> {
+        let Some(command) = self.resolve_command(options) else {
+            return Ok(());
+        };
+        let output = run_cargo(command)
+            .await
+            .map_err(|e| AppError::Audit(format!("Failed to execute cargo command: {}", e)))?;
+        check_resolution(&output)
+    }
+
+    /// Reads the `Cargo.lock` cargo wrote for a Cargo package, if it resolved its
+    /// dependencies.
+    fn lockfile(&self) -> Option<String> {
+        let dir = self.dir.as_ref().filter(|_| self.package)?;
+        fs::read_to_string(format!("{}/Cargo.lock", dir)).ok()
+    }
+
+    /// Runs `cargo audit` on the `Cargo.lock` of a Cargo package, if cargo resolved its
+    /// dependencies and `cargo-audit` is installed.
+    async fn security_advisories(&self) -> Option<Vec<SecurityAdvisory>> {
+        let dir = self.dir.as_ref().filter(|_| self.package)?;
+        if !Path::new(dir).join("Cargo.lock").exists() {
+            return None;
+        }
+        advisories::cargo_audit(Path::new(dir)).await
+    }
+
+    /// Lists the packages locked for a Cargo package with `cargo metadata`, if cargo
+    /// resolved its dependencies. Cargo runs like the compiler (see [`run_cargo`]),
+    /// within the timeout of the compilation.
+    async fn dependencies(&self, options: &CompileOptions) -> Option<Vec<LockedDependency>> {
+        let dir = self.dir.as_ref().filter(|_| self.package)?;
+        if !Path::new(dir).join("Cargo.lock").exists() {
+            return None;
+        }
+        let mut command = cargo_command(options.channel, "metadata");
+        command
+            .args(["--format-version", "1", "--offline", "--locked", "--quiet"])
+            .arg("--manifest-path")
+            .arg(format!("{}/{}", dir, MANIFEST))
+            .current_dir(dir);
+        match run_cargo(command).await {
+            Ok(output) if output.status.success() => {
+                parse_cargo_metadata(&String::from_utf8_lossy(&output.stdout))
+            }
+            Ok(output) => {
+                tracing::warn!(
+                    stderr = %String::from_utf8_lossy(&output.stderr).trim(),
+                    "cargo metadata failed"
+                );
+                None
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to execute cargo metadata");
+                None
+            }
+        }
+    }
+}
+
+/// Checks the output of `cargo generate-lockfile` resolving the dependencies of a Cargo
+/// package offline (see `TempCrate::resolve`).
+///
+/// # Returns
+///
+/// * `Ok(())` - If cargo resolved the dependencies.
+/// * `Err(AppError::Audit)` - `dependency not available offline` naming the missing
+///   dependency if cargo did not find it, or a version matching its requirement, in the
+///   local registry cache; the error of cargo otherwise.
+fn check_resolution(output: &std::process::Output) -> Result<(), AppError> {
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let missing = stderr.lines().find_map(|line| {
+        let dependency = line
+            .strip_prefix("error: no matching package named `")
+            .or_else(|| {
+                line.strip_prefix("error: failed to select a version for the requirement `")
+            })?;
+        dependency.split_once('`').map(|(dependency, _)| dependency)
+    });
+    Err(AppError::Audit(match missing {
+        Some(dependency) => format!(
+            "dependency not available offline: {} is not in the local registry cache",
+            dependency
+        ),
+        None => format!(
+            "cargo could not resolve the dependencies: {}",
+            stderr.trim()
+        ),
+    }))
+}
+
+/// Parses the packages of the output of `cargo metadata`, other than the audited one.
+fn parse_cargo_metadata(output: &str) -> Option<Vec<LockedDependency>> {
+    #[derive(Deserialize)]
+    struct Metadata {
+        packages: Vec<Package>,
+        workspace_members: Vec<String>,
+    }
+    #[derive(Deserialize)]
+    struct Package {
+        id: String,
+        name: String,
+        version: String,
+        license: Option<String>,
+    }
+
+    let metadata: Metadata = serde_json::from_str(output).ok()?;
+    Some(
+        metadata
+            .packages
+            .into_iter()
+            .filter(|package| !metadata.workspace_members.contains(&package.id))
+            .map(|package| LockedDependency {
+                name: package.name,
+                version: package.version,
+                license: package.license,
+            })
+            .collect(),
+    )
+}
+
+/// Returns the flags of the compiler compiling code with `options`, without the paths of
+/// the temporary crate.
+fn compiler_flags(options: &CompileOptions) -> Vec<String> {
+    let mut flags = vec![
+        "--crate-type".to_string(),
+        "lib".to_string(),
+        "--error-format=json".to_string(),
+        "--edition".to_string(),
+        options.edition.as_str().to_string(),
+        "-C".to_string(),
+        format!("opt-level={}", options.opt_level.as_str()),
+    ];
+    if let Some(target) = &options.target {
+        flags.extend(["--target".to_string(), target.clone()]);
+    }
+    flags
+}
+
+/// Returns the flags of cargo checking a Cargo package with `options`, without the path
+/// of the temporary package. The package is checked offline with the `Cargo.lock`
+/// resolved beforehand from the local cache of the server, which cargo may not update.
+fn cargo_flags(options: &CompileOptions) -> Vec<String> {
+    let opt_level = options.opt_level.as_str();
+    // The numeric levels are integers in the configuration of cargo, the others strings.
+    let opt_level = match opt_level.parse::<u8>() {
+        Ok(level) => level.to_string(),
+        Err(_) => format!("\"{}\"", opt_level),
+    };
+    let mut flags = vec![
+        "--message-format=json".to_string(),
+        "--offline".to_string(),
+        "--frozen".to_string(),
+        "--quiet".to_string(),
+        "--config".to_string(),
+        format!("profile.dev.opt-level={}", opt_level),
+    ];
+    if let Some(target) = &options.target {
+        flags.extend(["--target".to_string(), target.clone()]);
+    }
+    flags
+}
+
+/// Adds the arguments checking the Cargo package in `dir` to `command`, which runs a
+/// cargo subcommand such as `check`. Its build directory is kept in `dir` as well.
+fn package_command(mut command: Command, dir: &str, options: &CompileOptions) -> Command {
+    command
+        .args(cargo_flags(options))
+        .arg("--manifest-path")
+        .arg(format!("{}/{}", dir, MANIFEST))
+        .env("CARGO_TARGET_DIR", format!("{}/target", dir))
+        .current_dir(dir);
+    command
+}
+
+/// Returns the `rustc` invocation compiling code with `options`, or the `cargo check`
+/// invocation for a Cargo package, without the paths of the temporary crate, so that a
+/// verdict can be reproduced.
+///
+/// # Arguments
+///
+/// * `options` - The toolchain and flags the code is compiled with.
+///
+/// # Returns
+///
+/// * `Vec<String>` - The program followed by its arguments, e.g. `["rustc",
+///   "--crate-type", "lib", ...]`.
+pub fn compile_command(options: &CompileOptions) -> Vec<String> {
+    let (command, flags) = if is_cargo_package(&options.files) {
+        (
+            cargo_command(options.channel, "check"),
+            cargo_flags(options),
+        )
+    } else {
+        (rustc_command(options.channel), compiler_flags(options))
+    };
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .chain(flags)
+        .collect()
+}
+
+/// Removes the files left in the temporary directory by compilations that were
+/// interrupted, e.g. by a crash.
+///
+/// Only the files named like those of `TempCrate` (`audit_<uuid>.rs`,
+/// `libaudit_<uuid>.rlib` and the `audit_<uuid>` directory) are removed, so this must not
+/// run while code is compiled.
+///
+/// # Returns
+///
+/// * `usize` - The number of files removed.
+pub fn remove_temp_files() -> usize {
+    let Ok(entries) = fs::read_dir(TEMP_DIR) else {
+        return 0;
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter(|entry| temp_crate_name(&entry.file_name().to_string_lossy()).is_some())
+        .filter(|entry| remove_temp_path(&entry.path()))
+        .count()
+}
+
+/// Removes the files left in the temporary directory by compilations that were
+/// interrupted, once they are older than `max_age`.
+///
+/// Unlike `remove_temp_files`, this may run while code is compiled: the files of the
+/// temporary crates that still exist are kept, however old.
+///
+/// # Arguments
+///
+/// * `max_age` - How long after their last modification files are considered stale.
+///
+/// # Returns
+///
+/// * `usize` - The number of files removed.
+pub fn sweep_temp_files(max_age: Duration) -> usize {
+    let Ok(entries) = fs::read_dir(TEMP_DIR) else {
+        return 0;
+    };
+    let is_stale = |entry: &fs::DirEntry| {
+        entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|modified| modified.elapsed().is_ok_and(|age| age > max_age))
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let Some(name) = temp_crate_name(&file_name) else {
+                return false;
+            };
+            // Removed under the lock, so that the crate is not created meanwhile.
+            let active = active_temp_crates();
+            !active.contains(name) && is_stale(entry) && remove_temp_path(&entry.path())
+        })
+        .count()
+}
+
+/// Returns the name of the temporary crate a file belongs to (`audit_<uuid>`), if it is
+/// one of the files of `TempCrate` or its directory.
+fn temp_crate_name(file_name: &str) -> Option<&str> {
+    let is_uuid = |s: &str| s.len() == 32 && s.bytes().all(|b| b.is_ascii_hexdigit());
+    file_name
+        .strip_suffix(".rs")
+        .or_else(|| {
+            file_name
+                .strip_prefix("lib")
+                .and_then(|rest| rest.strip_suffix(".rlib"))
+        })
+        .or(Some(file_name))
+        .filter(|name| name.strip_prefix("audit_").is_some_and(is_uuid))
+}
+
+/// Removes a file or the directory of a temporary crate, telling whether it was removed.
+fn remove_temp_path(path: &Path) -> bool {
+    if path.is_dir() {
+        fs::remove_dir_all(path).is_ok()
+    } else {
+        fs::remove_file(path).is_ok()
+    }
+}
+
+/// Returns the path of a file of a temporary crate of several files relative to the
+/// root of the crate, or `None` for the file of a crate of a single file or of a
+/// dependency. Cargo already reports the files of the package relative to its root.
+fn crate_file_path(file_name: &str) -> Option<String> {
+    if Path::new(file_name).is_relative() {
+        return Some(file_name.to_string());
+    }
+    let (name, path) = file_name
+        .strip_prefix(TEMP_DIR)?
+        .strip_prefix('/')?
+        .split_once('/')?;
+    temp_crate_name(name).map(|_| path.to_string())
+}
+
+impl Drop for TempCrate {
+    fn drop(&mut self) {
+        match &self.dir {
+            Some(dir) => {
+                let _ = fs::remove_dir_all(dir);
+            }
+            None => {
+                let _ = fs::remove_file(&self.source_path);
+            }
+        }
+        let _ = fs::remove_file(format!("{}/lib{}.rlib", TEMP_DIR, self.name));
+        active_temp_crates().remove(&self.name);
+    }
+}
+
+/// Checks that the toolchain and target of a compilation are installed.
+fn check_toolchain(options: &CompileOptions) -> Result<(), AppError> {
+    check_rustc_available(options.channel)
+        .map_err(|e| AppError::Audit(format!("The requested toolchain is not available: {}", e)))?;
+    if let Some(target) = &options.target {
+        check_target_installed(options.channel, target).map_err(AppError::Audit)?;
+    }
+    Ok(())
+}
 
 /// Compiles a given string of Rust code and returns the result.
 ///
-/// This function writes the code to a temporary file, invokes `rustc`
+/// This function writes the code to a uniquely named temporary file, invokes `rustc`
 /// to compile it as a library (so `fn main()` is not required), and captures
-/// any compilation errors.
+/// the diagnostics it emits in JSON format. Concurrent calls do not interfere
+/// with each other. If this future is dropped before it completes, `rustc` is killed
+/// and the temporary files are removed.
+///
+/// # Arguments
+///
+/// * `code` - A string slice containing the Rust code to be compiled.
+/// * `options` - The toolchain and flags to compile with.
+///
+/// # Returns
+///
+/// * `Ok(CompilationOutcome)` - The result of the compilation, whether it succeeded or not.
+/// * `Err(AppError::Audit)` - If the toolchain is not available, a dependency of a Cargo
+///   package is not available offline, or writing the temporary file or executing
+///   `rustc` fails.
+pub async fn check_compilation(
+    code: &str,
+    options: &CompileOptions,
+) -> Result<CompilationOutcome, AppError> {
+    // rustup reports a missing toolchain on stderr without JSON, so detect it up front
+    // rather than recording it as a compilation error.
+    check_toolchain(options)?;
+
+    let temp_crate = TempCrate::write(code, &options.files)?;
+    temp_crate.resolve(options).await?;
+    let execute_error = |e: std::io::Error| {
+        AppError::Audit(format!(
+            "Failed to execute {} command: {}",
+            temp_crate.compiler(),
+            e
+        ))
+    };
+    let started = Instant::now();
+    let child = spawn_compiler(temp_crate.compile_command(options)).map_err(execute_error)?;
+    let group = ProcessGroup(child.id());
+    let output = child.wait_with_output().await.map_err(execute_error)?;
+    group.completed();
+    let duration = started.elapsed();
+    let success = output.status.success();
+    let library = success.then(|| temp_crate.library()).flatten();
+    let lockfile = temp_crate.lockfile();
+    let dependencies = temp_crate.dependencies(options).await;
+    let security_advisories = temp_crate.security_advisories().await;
+    let package = temp_crate.package;
+    drop(temp_crate);
+
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    let (json_output, (rendered, diagnostics)) = if package {
+        let parsed = parse_cargo_output(&stdout, &stderr);
+        (stdout, parsed)
+    } else {
+        let parsed = parse_diagnostics(&stderr);
+        (stderr, parsed)
+    };
+    Ok(CompilationOutcome {
+        lockfile,
+        dependencies,
+        security_advisories,
+        ..compilation_outcome(
+            success,
+            rendered,
+            diagnostics,
+            duration,
+            json_output,
+            library,
+        )
+    })
+}
+
+/// Compiles a given string of Rust code like `check_compilation`, sending the compiler
+/// output line by line as it is produced.
+///
+/// Diagnostics are sent in their human-readable rendering. The `compiling` stage is sent
+/// once `rustc` starts; cargo first resolves and builds the dependencies of a package,
+/// so `dependencies_resolved` then `compiling` are sent with its first message, such as
+/// the artifact of a dependency. If this future is dropped before it completes, the
+/// compiler is killed and the temporary files are removed.
 ///
 /// # Arguments
 ///
 /// * `code` - A string slice containing the Rust code to be compiled.
+/// * `options` - The toolchain and flags to compile with.
+/// * `output` - The channel receiving the stages and output lines, which are dropped once
+///   it closes.
 ///
 /// # Returns
 ///
-/// * `Ok(())` - If the code compiles successfully.
-/// * `Err(AppError::Audit)` - If writing the temporary file, executing `rustc`,
-///   or the compilation itself fails. The error contains the compiler's output.
-pub fn check_compilation(code: &str) -> Result<(), AppError> {
-    let temp_file = "/tmp/audit_test.rs";
-    let out_dir = "/tmp";
+/// * `Ok(CompilationOutcome)` - The result of the compilation, whether it succeeded or not.
+/// * `Err(AppError::Audit)` - If the toolchain is not available, a dependency of a Cargo
+///   package is not available offline, or writing the temporary file or executing
+///   `rustc` fails.
+pub async fn stream_compilation(
+    code: &str,
+    options: &CompileOptions,
+    output: &mpsc::Sender<CompilerOutput>,
+) -> Result<CompilationOutcome, AppError> {
+    check_toolchain(options)?;
+
+    let temp_crate = TempCrate::write(code, &options.files)?;
+    temp_crate.resolve(options).await?;
+    let compiler = temp_crate.compiler();
+    let execute_error = |e: std::io::Error| {
+        AppError::Audit(format!("Failed to execute {} command: {}", compiler, e))
+    };
+    let started = Instant::now();
+    let mut child = spawn_compiler(temp_crate.compile_command(options)).map_err(execute_error)?;
+    let group = ProcessGroup(child.id());
+    let mut resolved = !temp_crate.package;
+    if resolved {
+        send_stage(output, AuditProgressStage::Compiling).await;
+    }
+
+    let read_error =
+        |e: std::io::Error| AppError::Audit(format!("Failed to read {} output: {}", compiler, e));
+    let mut stdout = child.stdout.take().map(|out| BufReader::new(out).lines());
+    let mut stderr = child.stderr.take().map(|err| BufReader::new(err).lines());
+    let mut rendered = String::new();
+    let mut diagnostics = Vec::new();
+    let mut json_output = String::new();
+
+    // `rustc` writes its diagnostics to its standard error, cargo to its standard output
+    // along with its other messages, and its own errors to its standard error.
+    while stdout.is_some() || stderr.is_some() {
+        tokio::select! {
+            line = next_line(&mut stdout) => match line.map_err(read_error)? {
+                Some(line) if temp_crate.package => {
+                    json_output.push_str(&line);
+                    json_output.push('\n');
+                    // Cargo only builds anything once the dependencies are resolved.
+                    if !resolved && line.starts_with('{') {
+                        resolved = true;
+                        send_stage(output, AuditProgressStage::DependenciesResolved).await;
+                        send_stage(output, AuditProgressStage::Compiling).await;
+                    }
+                    if let Some((text, diagnostic)) = parse_cargo_line(&line) {
+                        diagnostics.push(diagnostic);
+                        send_lines(output, &text).await;
+                        rendered.push_str(&text);
+                    }
+                }
+                Some(line) => {
+                    let _ = output.send(CompilerOutput::Line(line)).await;
+                }
+                None => stdout = None,
+            },
+            line = next_line(&mut stderr) => match line.map_err(read_error)? {
+                Some(line) if temp_crate.package => {
+                    rendered.push_str(&line);
+                    rendered.push('\n');
+                    let _ = output.send(CompilerOutput::Line(line)).await;
+                }
+                Some(line) => {
+                    json_output.push_str(&line);
+                    json_output.push('\n');
+                    let text = match parse_diagnostic_line(&line) {
+                        Some((text, diagnostic)) => {
+                            diagnostics.push(diagnostic);
+                            text
+                        }
+                        None => format!("{}\n", line),
+                    };
+                    send_lines(output, &text).await;
+                    rendered.push_str(&text);
+                }
+                None => stderr = None,
+            },
+        }
+    }
 
-    // Write code to a temporary file.
-    fs::write(temp_file, code)
-        .map_err(|e| AppError::Audit(format!("Failed to write temporary audit file: {}", e)))?;
+    let status = child.wait().await.map_err(execute_error)?;
+    group.completed();
+    let duration = started.elapsed();
+    let library = status.success().then(|| temp_crate.library()).flatten();
+    let lockfile = temp_crate.lockfile();
+    let dependencies = temp_crate.dependencies(options).await;
+    let security_advisories = temp_crate.security_advisories().await;
+    drop(temp_crate);
+
+    Ok(CompilationOutcome {
+        lockfile,
+        dependencies,
+        security_advisories,
+        ..compilation_outcome(
+            status.success(),
+            rendered,
+            diagnostics,
+            duration,
+            json_output,
+            library,
+        )
+    })
+}
+
+/// Lints a given string of Rust code with Clippy.
+///
+/// The code is compiled by `clippy-driver` like `check_compilation` compiles it, with
+/// the default Clippy lints on top of the `rustc` ones.
+///
+/// # Arguments
+///
+/// * `code` - A string slice containing the Rust code to be linted.
+/// * `options` - The toolchain and flags to compile with.
+///
+/// # Returns
+///
+/// * `Ok(String)` - The diagnostics of `rustc` and Clippy, in JSON format.
+/// * `Err(AppError::Audit)` - If the toolchain is not available, a dependency of a Cargo
+///   package is not available offline, or writing the temporary file or executing
+///   `clippy-driver` fails.
+pub fn run_clippy(code: &str, options: &CompileOptions) -> Result<String, AppError> {
+    check_toolchain(options)?;
 
-    // Execute rustc with --crate-type lib to avoid requiring a main function.
-    let output = Command::new("rustc")
-        .arg("--crate-type")
-        .arg("lib")
-        .arg("--out-dir")
-        .arg(out_dir)
-        .arg(temp_file)
+    let temp_crate = TempCrate::write(code, &options.files)?;
+    if let Some(mut command) = temp_crate.resolve_command(options) {
+        let output = command
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| AppError::Audit(format!("Failed to execute cargo command: {}", e)))?;
+        check_resolution(&output)?;
+    }
+    let output = temp_crate
+        .clippy_command(options)
         .output()
-        .map_err(|e| AppError::Audit(format!("Failed to execute rustc command: {}", e)))?;
+        .map_err(|e| AppError::Audit(format!("Failed to execute Clippy: {}", e)))?;
+    drop(temp_crate);
+    // Cargo writes the diagnostics to its standard output.
+    let diagnostics = if is_cargo_package(&options.files) {
+        output.stdout
+    } else {
+        output.stderr
+    };
+    Ok(String::from_utf8_lossy(&diagnostics).into_owned())
+}
 
-    // Clean up temporary files.
-    let _ = fs::remove_file(temp_file);
-    let _ = fs::remove_file("/tmp/libaudit_test.rlib");
+/// Spawns a compiler in its own process group, capturing its output. It is killed if
+/// the returned child is dropped; see [`ProcessGroup`] for the processes it spawns.
+fn spawn_compiler(command: Command) -> std::io::Result<tokio::process::Child> {
+    tokio::process::Command::from(command)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .kill_on_drop(true)
+        .spawn()
+}
 
-    if output.status.success() {
+/// Runs a cargo command in its own process group like a compiler (see
+/// [`spawn_compiler`]), so that the processes it spawns are killed along with it if the
+/// returned future is dropped, e.g. when the compilation times out.
+async fn run_cargo(mut command: Command) -> std::io::Result<std::process::Output> {
+    command.stdin(Stdio::null());
+    let child = spawn_compiler(command)?;
+    let group = ProcessGroup(child.id());
+    let output = child.wait_with_output().await?;
+    group.completed();
+    Ok(output)
+}
+
+/// Kills the process group of a compiler when dropped before the compiler completed,
+/// e.g. when its compilation times out.
+///
+/// Cargo runs `rustc` in processes of its own, which killing cargo would leave running.
+struct ProcessGroup(Option<u32>);
+
+impl ProcessGroup {
+    /// Leaves the group alone once the compiler completed.
+    fn completed(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        if let Some(group) = self.0.and_then(|id| libc::pid_t::try_from(id).ok()) {
+            // SAFETY: `killpg` only sends a signal, and the group is the one of the
+            // compiler, which has not been waited for.
+            unsafe {
+                libc::killpg(group, libc::SIGKILL);
+            }
+        }
+    }
+}
+
+/// Sends each line of a text to a channel of compiler output, dropping them once it
+/// closes.
+async fn send_lines(output: &mpsc::Sender<CompilerOutput>, text: &str) {
+    for line in text.lines() {
+        let _ = output.send(CompilerOutput::Line(line.to_string())).await;
+    }
+}
+
+/// Sends a stage reached by a compilation to a channel of compiler output, dropping it
+/// once the channel closes.
+async fn send_stage(output: &mpsc::Sender<CompilerOutput>, stage: AuditProgressStage) {
+    let _ = output.send(CompilerOutput::Stage(stage)).await;
+}
+
+/// Reads the next line of an output stream, or waits forever once the stream is closed.
+async fn next_line<R: AsyncBufRead + Unpin>(
+    reader: &mut Option<Lines<R>>,
+) -> std::io::Result<Option<String>> {
+    match reader {
+        Some(reader) => reader.next_line().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Logs the result of a compilation and builds its outcome.
+fn compilation_outcome(
+    success: bool,
+    rendered: String,
+    diagnostics: Vec<Diagnostic>,
+    duration: Duration,
+    json_output: String,
+    library: Option<Vec<u8>>,
+) -> CompilationOutcome {
+    if success {
         tracing::info!("Code compiled successfully.");
-        Ok(())
     } else {
-        let error = String::from_utf8_lossy(&output.stderr).to_string();
-        tracing::warn!(error = %error, "Compilation error detected.");
-        Err(AppError::Audit(error))
+        tracing::warn!(error = %rendered, "Compilation error detected.");
+    }
+
+    CompilationOutcome {
+        success,
+        output: rendered,
+        diagnostics,
+        duration,
+        json_output,
+        library,
+        lockfile: None,
+        dependencies: None,
+        security_advisories: None,
+    }
+}
+
+/// Parses the JSON lines written by `rustc --error-format=json`.
+///
+/// Lines that are not JSON diagnostics (e.g. an internal compiler error dump) are kept
+/// verbatim in the rendered output so that no information is lost.
+///
+/// # Arguments
+///
+/// * `stderr` - The standard error output of `rustc`.
+///
+/// # Returns
+///
+/// * `(String, Vec<Diagnostic>)` - The human-readable rendering of all diagnostics and
+///   the parsed diagnostics themselves.
+fn parse_diagnostics(stderr: &str) -> (String, Vec<Diagnostic>) {
+    let mut rendered = String::new();
+    let mut diagnostics = Vec::new();
+
+    for line in stderr.lines() {
+        match parse_diagnostic_line(line) {
+            Some((text, diagnostic)) => {
+                rendered.push_str(&text);
+                diagnostics.push(diagnostic);
+            }
+            None => {
+                rendered.push_str(line);
+                rendered.push('\n');
+            }
+        }
+    }
+
+    (rendered, diagnostics)
+}
+
+/// Parses one JSON line written by `rustc --error-format=json`.
+///
+/// # Returns
+///
+/// * `Some((String, Diagnostic))` - The human-readable rendering of the diagnostic and
+///   the diagnostic itself.
+/// * `None` - If the line is not a JSON diagnostic.
+fn parse_diagnostic_line(line: &str) -> Option<(String, Diagnostic)> {
+    serde_json::from_str::<RustcDiagnostic>(line)
+        .ok()
+        .map(rendered_diagnostic)
+}
+
+/// Parses the output of `cargo --message-format=json`.
+///
+/// The diagnostics are read from the JSON messages of its standard output. Its own
+/// errors, such as a dependency missing from the local cache, are kept verbatim from its
+/// standard error.
+///
+/// # Arguments
+///
+/// * `stdout` - The standard output of cargo.
+/// * `stderr` - The standard error output of cargo.
+///
+/// # Returns
+///
+/// * `(String, Vec<Diagnostic>)` - The human-readable rendering of the diagnostics
+///   followed by the errors of cargo, and the parsed diagnostics.
+fn parse_cargo_output(stdout: &str, stderr: &str) -> (String, Vec<Diagnostic>) {
+    let (mut rendered, diagnostics): (String, Vec<Diagnostic>) =
+        stdout.lines().filter_map(parse_cargo_line).unzip();
+    rendered.push_str(stderr);
+    (rendered, diagnostics)
+}
+
+/// A message written by `cargo --message-format=json`.
+#[derive(Debug, Deserialize)]
+struct CargoMessage {
+    reason: String,
+    /// The diagnostic of a `compiler-message`.
+    message: Option<RustcDiagnostic>,
+}
+
+/// Parses one JSON line written by `cargo --message-format=json`.
+///
+/// # Returns
+///
+/// * `Some((String, Diagnostic))` - The human-readable rendering of the diagnostic of a
+///   `compiler-message`, and the diagnostic itself.
+/// * `None` - If the line is another message, such as an artifact built.
+fn parse_cargo_line(line: &str) -> Option<(String, Diagnostic)> {
+    let message = serde_json::from_str::<CargoMessage>(line).ok()?;
+    if message.reason != "compiler-message" {
+        return None;
+    }
+    message.message.map(rendered_diagnostic)
+}
+
+/// Returns the human-readable rendering of a diagnostic of the compiler, and the
+/// diagnostic itself.
+fn rendered_diagnostic(diagnostic: RustcDiagnostic) -> (String, Diagnostic) {
+    let text = diagnostic
+        .rendered
+        .clone()
+        .unwrap_or_else(|| diagnostic.message.clone());
+    let primary_span = diagnostic.spans.iter().find(|span| span.is_primary);
+    (
+        text,
+        Diagnostic {
+            code: diagnostic.code.map(|c| c.code),
+            level: diagnostic.level,
+            message: diagnostic.message,
+            line: primary_span.map(|span| span.line_start),
+            column: primary_span.map(|span| span.column_start),
+            file: primary_span.and_then(|span| crate_file_path(&span.file_name)),
+        },
+    )
+}
+
+/// A broad category of rustc compilation errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// A value does not have the expected type or does not implement a required trait.
+    TypeMismatch,
+    /// The borrow checker rejected the code (e.g. use after move).
+    BorrowCheck,
+    /// The code could not be parsed.
+    Syntax,
+    /// An item, field, method or import could not be found.
+    Missing,
+    /// A declared parameter is never used.
+    Unused,
+    /// An error code without a dedicated category.
+    Other(String),
+}
+
+impl ErrorCategory {
+    /// Returns the identifier under which the category is stored and reported.
+    pub fn as_str(&self) -> &str {
+        match self {
+            ErrorCategory::TypeMismatch => "type_mismatch",
+            ErrorCategory::BorrowCheck => "borrow_check",
+            ErrorCategory::Syntax => "syntax",
+            ErrorCategory::Missing => "missing",
+            ErrorCategory::Unused => "unused",
+            ErrorCategory::Other(_) => "other",
+        }
+    }
+}
+
+/// Maps a rustc error code to its category.
+///
+/// # Arguments
+///
+/// * `code` - A rustc error code (e.g. `E0308`).
+///
+/// # Returns
+///
+/// * `ErrorCategory` - The category of the code, or `ErrorCategory::Other` if the code
+///   has no dedicated category.
+pub fn categorize_error_code(code: &str) -> ErrorCategory {
+    match code {
+        "E0061" | "E0277" | "E0282" | "E0308" | "E0605" | "E0606" => ErrorCategory::TypeMismatch,
+        "E0373" | "E0381" | "E0382" | "E0384" | "E0499" | "E0502" | "E0505" | "E0506" | "E0507"
+        | "E0596" | "E0597" | "E0716" => ErrorCategory::BorrowCheck,
+        "E0178" | "E0586" => ErrorCategory::Syntax,
+        "E0046" | "E0063" | "E0412" | "E0425" | "E0432" | "E0433" | "E0560" | "E0599" | "E0609" => {
+            ErrorCategory::Missing
+        }
+        "E0091" | "E0392" => ErrorCategory::Unused,
+        other => ErrorCategory::Other(other.to_string()),
+    }
+}
+
+/// Returns the rustc error code of a diagnostic.
+///
+/// Lint diagnostics also carry a code (e.g. `unused_variables`), which is not a
+/// rustc error code and is ignored.
+///
+/// # Arguments
+///
+/// * `diagnostic` - A diagnostic emitted by the compiler.
+///
+/// # Returns
+///
+/// * `Some(&str)` - The error code (e.g. `E0308`).
+/// * `None` - If the diagnostic carries no error code.
+pub fn extract_error_code(diagnostic: &Diagnostic) -> Option<&str> {
+    diagnostic.code.as_deref().filter(|code| {
+        code.len() == 5 && code.starts_with('E') && code[1..].chars().all(|c| c.is_ascii_digit())
+    })
+}
+
+/// Returns the error code of the first error-level diagnostic that carries one.
+///
+/// # Arguments
+///
+/// * `diagnostics` - The diagnostics emitted by the compiler, in emission order.
+///
+/// # Returns
+///
+/// * `Some(String)` - The primary error code (e.g. `E0308`).
+/// * `None` - If no error diagnostic carries a code.
+pub fn primary_error_code(diagnostics: &[Diagnostic]) -> Option<String> {
+    diagnostics
+        .iter()
+        .filter(|d| d.level == "error")
+        .find_map(extract_error_code)
+        .map(str::to_string)
+}
+
+/// Returns the category of the dominant compilation error.
+///
+/// The category of the primary error code is used when there is one. Parse errors are
+/// reported by rustc without a code, so errors without any code are categorized as
+/// `ErrorCategory::Syntax`.
+///
+/// # Arguments
+///
+/// * `diagnostics` - The diagnostics emitted by the compiler, in emission order.
+///
+/// # Returns
+///
+/// * `Some(ErrorCategory)` - The category of the dominant error.
+/// * `None` - If there is no error diagnostic.
+pub fn primary_error_category(diagnostics: &[Diagnostic]) -> Option<ErrorCategory> {
+    match primary_error_code(diagnostics) {
+        Some(code) => Some(categorize_error_code(&code)),
+        None => diagnostics
+            .iter()
+            .any(|d| d.level == "error")
+            .then_some(ErrorCategory::Syntax),
+    }
+}
+
+/// The paths of the toolchain used to compile packages and nightly code.
+#[derive(Debug, Clone, Default)]
+pub struct Toolchain {
+    /// The path of the nightly `rustc`. When not set, nightly code is compiled with
+    /// `rustc +nightly`, which requires the toolchain to be installed with rustup.
+    pub rustc_nightly: Option<String>,
+    /// The cargo home whose registry (`registry/index` and `registry/cache`) the
+    /// dependencies of packages are resolved from. When not set, that of the server
+    /// (`CARGO_HOME`, or `~/.cargo`).
+    pub offline_cargo_home: Option<String>,
+}
+
+/// The toolchain of the process, set once at startup.
+static TOOLCHAIN: OnceLock<Toolchain> = OnceLock::new();
+
+/// Sets the toolchain compiling packages and nightly code.
+///
+/// Only the first call has an effect; without one, the toolchain of the server is used.
+///
+/// # Arguments
+///
+/// * `toolchain` - The paths of the toolchain.
+pub fn set_toolchain(toolchain: Toolchain) {
+    let _ = TOOLCHAIN.set(toolchain);
+}
+
+/// Returns the toolchain of the process.
+fn toolchain() -> &'static Toolchain {
+    static DEFAULT: Toolchain = Toolchain {
+        rustc_nightly: None,
+        offline_cargo_home: None,
+    };
+    TOOLCHAIN.get().unwrap_or(&DEFAULT)
+}
+
+/// Builds the command invoking `rustc` for a release channel. Nightly code is compiled
+/// with the nightly `rustc` of the toolchain, or with `rustc +nightly`.
+fn rustc_command(channel: Channel) -> Command {
+    match channel {
+        Channel::Stable => Command::new("rustc"),
+        Channel::Nightly => match &toolchain().rustc_nightly {
+            Some(path) => Command::new(path),
+            None => {
+                let mut command = Command::new("rustc");
+                command.arg("+nightly");
+                command
+            }
+        },
+    }
+}
+
+/// Builds the command running a cargo subcommand for a release channel, resolving
+/// dependencies from the cargo home of the toolchain. Nightly packages are checked with
+/// `cargo +nightly`, or with the nightly `rustc` of the toolchain if it has one.
+fn cargo_command(channel: Channel, subcommand: &str) -> Command {
+    let mut command = Command::new("cargo");
+    if let Some(path) = &toolchain().offline_cargo_home {
+        command.env("CARGO_HOME", path);
+    }
+    if channel == Channel::Nightly {
+        match &toolchain().rustc_nightly {
+            Some(path) => {
+                command.env("RUSTC", path);
+            }
+            None => {
+                command.arg("+nightly");
+            }
+        }
+    }
+    command.arg(subcommand);
+    command
+}
+
+/// Builds the command invoking `clippy-driver` for a release channel. It comes with the
+/// Clippy component of the toolchain; nightly code is linted with
+/// `clippy-driver +nightly`.
+fn clippy_command(channel: Channel) -> Command {
+    let mut command = Command::new("clippy-driver");
+    if channel == Channel::Nightly {
+        command.arg("+nightly");
     }
+    command
 }
 
-/// Checks if the `rustc` compiler is available on the system PATH.
+/// The result of the first `rustc --version` invocation of the stable toolchain.
+static STABLE_RUSTC_VERSION: OnceLock<Result<String, String>> = OnceLock::new();
+
+/// The result of the first `rustc --version` invocation of the nightly toolchain.
+static NIGHTLY_RUSTC_VERSION: OnceLock<Result<String, String>> = OnceLock::new();
+
+/// Checks if the `rustc` compiler of a release channel is available.
+///
+/// `rustc` is only invoked the first time; later calls return the cached result, so the
+/// toolchain is assumed not to change while the server runs.
+///
+/// # Arguments
+///
+/// * `channel` - The release channel of the toolchain.
 ///
 /// # Returns
 ///
-/// * `Ok(String)` - If `rustc` is available, returns the version string.
-/// * `Err(String)` - If `rustc` could not be executed.
-pub fn check_rustc_available() -> Result<String, String> {
-    let output = Command::new("rustc")
+/// * `Ok(&str)` - If `rustc` is available, returns the version string.
+/// * `Err(&str)` - If `rustc` could not be executed.
+pub fn check_rustc_available(channel: Channel) -> Result<&'static str, &'static str> {
+    let version = match channel {
+        Channel::Stable => &STABLE_RUSTC_VERSION,
+        Channel::Nightly => &NIGHTLY_RUSTC_VERSION,
+    };
+    version
+        .get_or_init(|| query_rustc_version(channel))
+        .as_deref()
+        .map_err(String::as_str)
+}
+
+/// Runs `rustc --version` for a release channel.
+fn query_rustc_version(channel: Channel) -> Result<String, String> {
+    let output = rustc_command(channel)
         .arg("--version")
         .output()
         .map_err(|e| format!("Failed to execute rustc: {}", e))?;
@@ -72,3 +3746,532 @@ pub fn check_rustc_available() -> Result<String, String> {
         Err(format!("rustc execution failed: {}", error))
     }
 }
+
+/// Formats code with `rustfmt`, using its default settings.
+///
+/// This runs a process: call it from a blocking task.
+///
+/// # Arguments
+///
+/// * `code` - A string slice containing the Rust code to be formatted.
+///
+/// # Returns
+///
+/// * `Ok(String)` - The formatted code, identical to `code` if it was already formatted.
+/// * `Err(AppError::Audit)` - If `rustfmt` is not installed or cannot parse the code.
+pub fn format_code(code: &str) -> Result<String, AppError> {
+    let mut child = Command::new("rustfmt")
+        .arg("--emit")
+        .arg("stdout")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| AppError::Audit(format!("Failed to execute rustfmt: {}", e)))?;
+    // rustfmt reads all of its input before writing anything, so this cannot deadlock.
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(code.as_bytes())
+            .map_err(|e| AppError::Audit(format!("Failed to write to rustfmt: {}", e)))?;
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|e| AppError::Audit(format!("Failed to wait for rustfmt: {}", e)))?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(AppError::Audit(format!(
+            "rustfmt failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
+
+/// The targets a toolchain supports, and the sysroot their standard libraries are
+/// installed in.
+#[derive(Debug)]
+struct ToolchainTargets {
+    supported: HashSet<String>,
+    sysroot: PathBuf,
+}
+
+/// The targets of the stable toolchain, queried on first use.
+static STABLE_TARGETS: OnceLock<Result<ToolchainTargets, String>> = OnceLock::new();
+
+/// The targets of the nightly toolchain, queried on first use.
+static NIGHTLY_TARGETS: OnceLock<Result<ToolchainTargets, String>> = OnceLock::new();
+
+/// Checks that code can be compiled for a target with the toolchain of a release channel.
+///
+/// The target must be listed by `rustc --print target-list`, and its standard library
+/// must be installed in the sysroot of the toolchain (e.g. with `rustup target add`).
+/// The target list is only queried the first time; the sysroot is checked on every
+/// call, so targets installed while the server runs are picked up.
+///
+/// # Arguments
+///
+/// * `channel` - The release channel of the toolchain.
+/// * `target` - The target triple (e.g. `wasm32-unknown-unknown`).
+///
+/// # Returns
+///
+/// * `Ok(())` - If the target is installed.
+/// * `Err(String)` - A message explaining why the target cannot be used.
+pub fn check_target_installed(channel: Channel, target: &str) -> Result<(), String> {
+    let targets = match channel {
+        Channel::Stable => &STABLE_TARGETS,
+        Channel::Nightly => &NIGHTLY_TARGETS,
+    };
+    let targets = targets
+        .get_or_init(|| query_targets(channel))
+        .as_ref()
+        .map_err(Clone::clone)?;
+
+    if !targets.supported.contains(target) {
+        return Err(format!(
+            "Unknown target '{}'; `rustc --print target-list` lists the supported targets",
+            target
+        ));
+    }
+    let std_dir = targets.sysroot.join("lib/rustlib").join(target).join("lib");
+    if !std_dir.is_dir() {
+        return Err(format!(
+            "The target '{}' is not installed on this server (run `rustup target add {}`)",
+            target, target
+        ));
+    }
+    Ok(())
+}
+
+/// Runs `rustc --print target-list` and `rustc --print sysroot` for a release channel.
+fn query_targets(channel: Channel) -> Result<ToolchainTargets, String> {
+    let print = |what: &str| {
+        let output = rustc_command(channel)
+            .arg("--print")
+            .arg(what)
+            .output()
+            .map_err(|e| format!("Failed to execute rustc: {}", e))?;
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        } else {
+            Err(format!(
+                "rustc execution failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ))
+        }
+    };
+
+    let supported = print("target-list")?.lines().map(str::to_string).collect();
+    let sysroot = PathBuf::from(print("sysroot")?.trim());
+    Ok(ToolchainTargets { supported, sysroot })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the policy of a server configured with the defaults.
+    fn policy() -> AuditPolicy {
+        AuditPolicy {
+            strict: false,
+            doc_coverage_threshold: 50.0,
+            auto_fix_budget: Duration::from_secs(300),
+            banned_crates: Vec::new(),
+            hygiene_severity: Severity::Warning,
+            disabled_rules: HashSet::new(),
+            default_profile: "default".to_string(),
+            warnings_as_errors: false,
+            max_lines: 2000,
+            max_code_bytes: DEFAULT_MAX_CODE_BYTES,
+            known_std_paths: Vec::new(),
+            licenses: LicensePolicy::default(),
+        }
+    }
+
+    /// Returns the code and line of the error handling findings among `findings`.
+    fn error_handling_findings(findings: Vec<Finding>) -> Vec<(String, Option<u32>)> {
+        findings
+            .into_iter()
+            .filter(|finding| finding.code.starts_with("RAA01"))
+            .map(|finding| (finding.code, finding.line))
+            .collect()
+    }
+
+    /// Returns the line and message of the findings of `rule` on `code`.
+    fn findings(rule: &dyn ValidationRule, code: &str) -> Vec<(Option<u32>, String)> {
+        rule.check(code)
+            .into_iter()
+            .map(|finding| (finding.line, finding.message))
+            .collect()
+    }
+
+    #[test]
+    fn blocking_calls_in_nested_async_blocks_are_reported() {
+        let code = include_str!("../tests/fixtures/blocking/nested_async.rs");
+        assert_eq!(
+            findings(&BlockingCallRule, code),
+            [
+                (
+                    Some(5),
+                    "Calls blocking `std::thread::sleep` in async fn `poll`; use \
+                     `tokio::time::sleep` or `spawn_blocking`"
+                        .to_string()
+                ),
+                (
+                    Some(11),
+                    "Calls blocking `std::fs::read_to_string` in an async block; use \
+                     `tokio::fs::read_to_string` or `spawn_blocking`"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn blocking_calls_in_async_closures_are_reported() {
+        let code = include_str!("../tests/fixtures/blocking/async_closure.rs");
+        assert_eq!(
+            findings(&BlockingCallRule, code),
+            [(
+                Some(2),
+                "Calls blocking `reqwest::blocking::get` in an async closure; use the async \
+                 `reqwest::Client` or `spawn_blocking`"
+                    .to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn blocking_calls_in_spawn_blocking_and_async_apis_are_not_reported() {
+        let code = include_str!("../tests/fixtures/blocking/spawn_blocking.rs");
+        assert!(BlockingCallRule.check(code).is_empty());
+    }
+
+    #[test]
+    fn blocking_calls_in_synchronous_code_are_not_reported() {
+        let code = include_str!("../tests/fixtures/blocking/sync.rs");
+        assert!(BlockingCallRule.check(code).is_empty());
+    }
+
+    #[test]
+    fn non_idiomatic_error_handling_is_reported() {
+        let code = include_str!("../tests/fixtures/error_handling/flagged.rs");
+        let expected = [
+            ("RAA0101", 3),
+            ("RAA0102", 7),
+            ("RAA0103", 12),
+            ("RAA0104", 18),
+            ("RAA0105", 24),
+        ]
+        .map(|(code, line)| (code.to_string(), Some(line)));
+        assert_eq!(error_handling_findings(SyntaxRules.check(code)), expected);
+    }
+
+    #[test]
+    fn idiomatic_error_handling_is_not_reported() {
+        let code = include_str!("../tests/fixtures/error_handling/near_misses.rs");
+        assert_eq!(error_handling_findings(SyntaxRules.check(code)), []);
+    }
+
+    #[test]
+    fn error_handling_rules_can_be_disabled() {
+        let code = include_str!("../tests/fixtures/error_handling/flagged.rs");
+        let mut policy = policy();
+        policy.disabled_rules = ["RAA0101", "RAA0104"].map(String::from).into();
+
+        let codes: Vec<String> = error_handling_findings(validate_code(code, &policy))
+            .into_iter()
+            .map(|(code, _)| code)
+            .collect();
+        assert_eq!(codes, ["RAA0102", "RAA0103", "RAA0105"]);
+    }
+
+    #[test]
+    fn made_up_std_paths_are_reported() {
+        let rule = StdPathRule { extra_paths: &[] };
+        let code = "use std::collections::HashMap;\n\
+                    use std::collections::SortedMap;\n\
+                    use std::{io::{self, Read}, sync::{Arc, AtomicMap}};\n\
+                    use std::hashmap::*;\n\
+                    \n\
+                    fn f() {\n    use std::collections::SortedMap as Map;\n}\n";
+        assert_eq!(
+            findings(&rule, code),
+            [
+                (
+                    Some(2),
+                    "Imports `std::collections::SortedMap`, which does not exist in the \
+                     standard library"
+                        .to_string()
+                ),
+                (
+                    Some(3),
+                    "Imports `std::sync::AtomicMap`, which does not exist in the standard \
+                     library"
+                        .to_string()
+                ),
+                (
+                    Some(4),
+                    "Imports `std::hashmap`, which does not exist in the standard library"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn real_std_paths_are_not_reported() {
+        let rule = StdPathRule { extra_paths: &[] };
+        let code = "use std::collections::{HashMap, BTreeMap, hash_map::Entry};\n\
+                    use std::io::{self, prelude::*};\n\
+                    use std::sync::atomic::{AtomicUsize, Ordering};\n\
+                    use core::cell::SortedCell;\n";
+        assert_eq!(findings(&rule, code), []);
+    }
+
+    #[test]
+    fn configured_std_paths_are_known() {
+        let code = "use std::collections::SortedMap;\nuse std::sync::nightly::Gate;\n";
+        let mut policy = policy();
+        let lines = |policy: &AuditPolicy| -> Vec<Option<u32>> {
+            validate_code(code, policy)
+                .into_iter()
+                .filter(|finding| finding.code == UNKNOWN_STD_PATH_CODE)
+                .map(|finding| finding.line)
+                .collect()
+        };
+        assert_eq!(lines(&policy), [Some(1), Some(2)]);
+
+        policy.known_std_paths = ["std::collections::SortedMap", "std::sync::nightly::Gate"]
+            .map(String::from)
+            .into();
+        assert_eq!(lines(&policy), []);
+    }
+
+    /// Sets the modification time of `path` to two hours ago.
+    fn back_date(path: &str) {
+        let two_hours_ago = std::time::SystemTime::now() - Duration::from_secs(2 * 3600);
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .and_then(|file| file.set_modified(two_hours_ago))
+            .unwrap_or_else(|e| panic!("{}: {}", path, e));
+    }
+
+    #[test]
+    fn stale_temp_files_are_swept_but_active_ones_are_kept() {
+        let stale = format!("{}/audit_{}.rs", TEMP_DIR, Uuid::new_v4().simple());
+        fs::write(&stale, "pub fn f() {}").unwrap();
+        back_date(&stale);
+        let stale_dir = format!("{}/audit_{}", TEMP_DIR, Uuid::new_v4().simple());
+        fs::create_dir_all(format!("{}/src", stale_dir)).unwrap();
+        fs::write(format!("{}/src/lib.rs", stale_dir), "").unwrap();
+        // Back-dated last, as writing its files updates it.
+        let dir = fs::File::open(&stale_dir).unwrap();
+        dir.set_modified(std::time::SystemTime::now() - Duration::from_secs(2 * 3600))
+            .unwrap();
+        let fresh = format!("{}/audit_{}.rs", TEMP_DIR, Uuid::new_v4().simple());
+        fs::write(&fresh, "pub fn f() {}").unwrap();
+        let unrelated = format!("{}/audit_notes_{}.rs", TEMP_DIR, Uuid::new_v4().simple());
+        fs::write(&unrelated, "").unwrap();
+        back_date(&unrelated);
+        let active = TempCrate::write("pub fn f() {}", &[]).unwrap();
+        back_date(&active.source_path);
+
+        assert!(sweep_temp_files(Duration::from_secs(3600)) >= 2);
+        assert!(!Path::new(&stale).exists());
+        assert!(!Path::new(&stale_dir).exists());
+        assert!(Path::new(&fresh).exists());
+        assert!(Path::new(&unrelated).exists());
+        assert!(Path::new(&active.source_path).exists());
+
+        let source_path = active.source_path.clone();
+        drop(active);
+        assert!(!Path::new(&source_path).exists());
+        fs::remove_file(fresh).unwrap();
+        fs::remove_file(unrelated).unwrap();
+    }
+
+    /// Returns the files of a Cargo package with the given manifest and a library root.
+    fn package(manifest: &str) -> Vec<AuditFile> {
+        vec![
+            AuditFile {
+                path: MANIFEST.to_string(),
+                content: manifest.to_string(),
+            },
+            AuditFile {
+                path: "src/lib.rs".to_string(),
+                content: "pub fn f() {}".to_string(),
+            },
+        ]
+    }
+
+    #[test]
+    fn paths_escaping_the_crate_are_refused() {
+        for path in [
+            "../lib.rs",
+            "src/../../lib.rs",
+            "/etc/lib.rs",
+            "./src/lib.rs",
+            "src//lib.rs",
+            "src\\..\\lib.rs",
+            "src/lib.rs\0",
+            "",
+        ] {
+            assert!(check_file_path(path).is_err(), "{:?}", path);
+        }
+        assert_eq!(
+            check_file_path("a/b/c/d/e/lib.rs"),
+            Err("a/b/c/d/e/lib.rs: files must be at most 4 directories deep".to_string())
+        );
+        assert!(check_file_path("src/a/b/c/mod.rs").is_ok());
+        assert!(check_file_path("Cargo.toml").is_ok());
+        assert!(check_file_path("src/Cargo.toml").is_err());
+    }
+
+    #[test]
+    fn manifests_running_code_or_reading_the_server_are_refused() {
+        let refused = [
+            (
+                "[package]\nname = \"a\"\n\n[dependencies]\nb = { path = \"/srv/b\" }\n",
+                "Cargo.toml: the dependency `b` must come from crates.io",
+            ),
+            (
+                "[package]\nname = \"a\"\n\n[target.'cfg(unix)'.dev-dependencies]\nb = { git = \"https://example.com/b\" }\n",
+                "Cargo.toml: the dependency `b` must come from crates.io",
+            ),
+            (
+                "[package]\nname = \"a\"\nbuild = \"src/gen.rs\"\n",
+                "Cargo.toml: build scripts are not run; remove build.rs or set `build = false`",
+            ),
+            (
+                "[package]\nname = \"a\"\n\n[workspace]\n",
+                "Cargo.toml: [workspace] is not supported",
+            ),
+            (
+                "[package]\nname = \"a\"\n\n[patch.crates-io]\nb = { path = \"b\" }\n",
+                "Cargo.toml: [patch] is not supported",
+            ),
+            (
+                "[dependencies]\n",
+                "Cargo.toml: a [package] table is required",
+            ),
+        ];
+        for (manifest, error) in refused {
+            assert_eq!(check_manifest(&package(manifest)), Err(error.to_string()));
+        }
+        assert!(
+            check_manifest(&package("[package\n"))
+                .unwrap_err()
+                .starts_with("Cargo.toml: invalid manifest: ")
+        );
+
+        // A build.rs is only run if the manifest does not disable build scripts.
+        let mut files = package("[package]\nname = \"a\"\n");
+        files.push(AuditFile {
+            path: "build.rs".to_string(),
+            content: "fn main() {}".to_string(),
+        });
+        assert!(check_manifest(&files).is_err());
+        files[0].content.push_str("build = false\n");
+        assert_eq!(check_manifest(&files), Ok(()));
+
+        let manifest = "[package]\nname = \"a\"\nedition = \"2021\"\n\n[dependencies]\nitoa = \"1\"\nserde = { version = \"1\", features = [\"derive\"] }\n";
+        assert_eq!(check_manifest(&package(manifest)), Ok(()));
+    }
+
+    #[test]
+    fn cargo_diagnostics_are_read_from_compiler_messages() {
+        let stdout = concat!(
+            r#"{"reason":"compiler-artifact","package_id":"itoa","target":{"name":"itoa"}}"#,
+            "\n",
+            r#"{"reason":"compiler-message","package_id":"a","message":{"message":"mismatched types","code":{"code":"E0308"},"level":"error","spans":[{"file_name":"src/token.rs","line_start":3,"column_start":5,"is_primary":true}],"rendered":"error[E0308]: mismatched types\n"}}"#,
+            "\n",
+            r#"{"reason":"build-finished","success":false}"#,
+            "\n",
+        );
+        let (rendered, diagnostics) =
+            parse_cargo_output(stdout, "error: could not compile `a` (lib)\n");
+        assert_eq!(
+            rendered,
+            "error[E0308]: mismatched types\nerror: could not compile `a` (lib)\n"
+        );
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code.as_deref(), Some("E0308"));
+        assert_eq!(diagnostics[0].file.as_deref(), Some("src/token.rs"));
+        assert_eq!(
+            (diagnostics[0].line, diagnostics[0].column),
+            (Some(3), Some(5))
+        );
+    }
+
+    #[test]
+    fn packages_are_checked_by_cargo_offline() {
+        let options = CompileOptions {
+            opt_level: OptLevel::Os,
+            files: package("[package]\nname = \"a\"\n"),
+            ..CompileOptions::default()
+        };
+        assert_eq!(
+            compile_command(&options),
+            [
+                "cargo",
+                "check",
+                "--message-format=json",
+                "--offline",
+                "--frozen",
+                "--quiet",
+                "--config",
+                "profile.dev.opt-level=\"s\"",
+            ]
+        );
+        let options = CompileOptions {
+            opt_level: OptLevel::O2,
+            ..options
+        };
+        assert_eq!(compile_command(&options)[7], "profile.dev.opt-level=2");
+    }
+
+    fn cargo_failure(stderr: &str) -> std::process::Output {
+        use std::os::unix::process::ExitStatusExt;
+        std::process::Output {
+            status: std::process::ExitStatus::from_raw(101 << 8),
+            stdout: Vec::new(),
+            stderr: stderr.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn dependencies_missing_from_the_registry_cache_are_named() {
+        let error = check_resolution(&cargo_failure(
+            "error: no matching package named `left-pad` found\n\
+             location searched: crates.io index\n",
+        ))
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Audit error: dependency not available offline: left-pad is not in the local \
+             registry cache"
+        );
+
+        let error = check_resolution(&cargo_failure(
+            "error: failed to select a version for the requirement `untrusted = \"=0.9.99\"`\n\
+             candidate versions found which didn't match: 0.9.0\n",
+        ))
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Audit error: dependency not available offline: untrusted = \"=0.9.99\" is not \
+             in the local registry cache"
+        );
+
+        let error =
+            check_resolution(&cargo_failure("error: failed to parse manifest\n")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Audit error: cargo could not resolve the dependencies: error: failed to parse \
+             manifest"
+        );
+    }
+}
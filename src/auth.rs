@@ -0,0 +1,257 @@
+//! Authentication of requests to the administrative endpoints, and issuance of the
+//! access tokens of users.
+
+use crate::{
+    error::AppError,
+    models::{Role, TokenResponse, User},
+};
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::{header, request::Parts},
+};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
+use uuid::Uuid;
+
+/// The bearer token that grants access to the administrative endpoints.
+///
+/// When no token is configured, administrative endpoints reject every request.
+#[derive(Clone, Default)]
+pub struct AdminToken(Option<SecretString>);
+
+impl AdminToken {
+    /// Wraps the configured admin token (see `AppConfig::admin_api_token`).
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The token, or `None` to reject every request.
+    pub fn new(token: Option<SecretString>) -> Self {
+        if token.is_none() {
+            tracing::warn!("ADMIN_API_TOKEN is not set: administrative endpoints are disabled");
+        }
+        AdminToken(token)
+    }
+}
+
+/// An extractor that only succeeds for requests authenticated as an administrator.
+///
+/// Clients authenticate with an `Authorization: Bearer <ADMIN_API_TOKEN>` header.
+pub struct AdminAuth;
+
+impl<S> FromRequestParts<S> for AdminAuth
+where
+    S: Send + Sync,
+    AdminToken: FromRef<S>,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let AdminToken(expected) = AdminToken::from_ref(state);
+        let expected = expected.ok_or_else(|| {
+            AppError::Unauthorized("Administrative endpoints are disabled".to_string())
+        })?;
+
+        let provided = bearer_token(parts)?;
+        if constant_time_eq(provided.as_bytes(), expected.expose_secret().as_bytes()) {
+            Ok(AdminAuth)
+        } else {
+            Err(AppError::Unauthorized("Invalid bearer token".to_string()))
+        }
+    }
+}
+
+/// The authenticated caller of a request: an administrator using the admin token, or a
+/// user with an access token.
+///
+/// As an extractor, it rejects requests carrying neither.
+#[derive(Debug, Clone)]
+pub enum Caller {
+    /// A client authenticated with `ADMIN_API_TOKEN`.
+    Admin,
+    /// A user, with the claims of their access token.
+    User(Claims),
+}
+
+impl Caller {
+    /// Returns the user whose own resources (e.g. comments) are the only ones the caller
+    /// may modify, or `None` for administrators: the admin token, and users with the
+    /// `admin` role.
+    pub fn restricted_to(&self) -> Option<&str> {
+        match self {
+            Caller::User(claims) if claims.role != Role::Admin => Some(&claims.username),
+            _ => None,
+        }
+    }
+}
+
+impl<S> FromRequestParts<S> for Caller
+where
+    S: Send + Sync,
+    AdminToken: FromRef<S>,
+    Option<Arc<TokenIssuer>>: FromRef<S>,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if AdminAuth::from_request_parts(parts, state).await.is_ok() {
+            return Ok(Caller::Admin);
+        }
+        Claims::from_request_parts(parts, state)
+            .await
+            .map(Caller::User)
+            .map_err(|e| match e {
+                AppError::Unauthorized(message) if message == "Missing bearer token" => {
+                    AppError::Unauthorized(
+                        "Administrator bearer token or access token required".to_string(),
+                    )
+                }
+                e => e,
+            })
+    }
+}
+
+/// Reads the token of the `Authorization: Bearer <token>` header of a request.
+fn bearer_token(parts: &Parts) -> Result<&str, AppError> {
+    parts
+        .headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(|| AppError::Unauthorized("Missing bearer token".to_string()))
+}
+
+/// Compares two byte strings in time independent of where they first differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Reads whether `POST /auth/register` is enabled from the `AUTH_REGISTRATION_ENABLED`
+/// environment variable.
+///
+/// Registration lets anyone create an account, so it is meant for development only and
+/// disabled unless the variable is set to `true`.
+pub fn registration_enabled_from_env() -> bool {
+    std::env::var("AUTH_REGISTRATION_ENABLED")
+        .map(|v| v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+/// The claims of the access tokens.
+///
+/// As an extractor, it only succeeds for requests carrying an unexpired access token
+/// signed by this server, in an `Authorization: Bearer <token>` header.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    /// The ID of the user.
+    pub sub: Uuid,
+    /// The name of the user.
+    pub username: String,
+    /// What the user is allowed to do.
+    pub role: Role,
+    /// When the token was issued, in seconds since the Unix epoch.
+    pub iat: u64,
+    /// When the token expires, in seconds since the Unix epoch.
+    pub exp: u64,
+}
+
+impl<S> FromRequestParts<S> for Claims
+where
+    S: Send + Sync,
+    Option<Arc<TokenIssuer>>: FromRef<S>,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let issuer = Option::<Arc<TokenIssuer>>::from_ref(state).ok_or_else(|| {
+            AppError::Unauthorized("Access tokens are not enabled on this server".to_string())
+        })?;
+        issuer.verify(bearer_token(parts)?)
+    }
+}
+
+/// Issues the short-lived JWT access tokens of users, signed with HMAC-SHA256, and
+/// verifies the tokens presented back.
+pub struct TokenIssuer {
+    key: EncodingKey,
+    decoding_key: DecodingKey,
+    expires_in: u64,
+}
+
+impl TokenIssuer {
+    /// Builds the issuer of the configured secret (see `AppConfig::jwt_secret`).
+    ///
+    /// # Arguments
+    ///
+    /// * `secret` - The secret the tokens are signed with, or `None` to disable token
+    ///   issuance.
+    /// * `expiry` - How long the tokens are valid for.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(TokenIssuer)` - If a secret is configured.
+    /// * `None` - If no secret is configured.
+    pub fn new(secret: Option<&SecretString>, expiry: Duration) -> Option<Self> {
+        let Some(secret) = secret else {
+            tracing::warn!("JWT_SECRET is not set: access tokens cannot be issued");
+            return None;
+        };
+        let secret = secret.expose_secret().as_bytes();
+        Some(TokenIssuer {
+            key: EncodingKey::from_secret(secret),
+            decoding_key: DecodingKey::from_secret(secret),
+            expires_in: expiry.as_secs(),
+        })
+    }
+
+    /// Issues an access token for a user.
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - The authenticated user.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(TokenResponse)` - The signed token and its lifetime.
+    /// * `Err(AppError::Audit)` - If the token cannot be signed.
+    pub fn issue(&self, user: &User) -> Result<TokenResponse, AppError> {
+        let now = chrono::Utc::now().timestamp().max(0) as u64;
+        let claims = Claims {
+            sub: user.id,
+            username: user.username.clone(),
+            role: user.role,
+            iat: now,
+            exp: now + self.expires_in,
+        };
+        let access_token = jsonwebtoken::encode(&Header::default(), &claims, &self.key)
+            .map_err(|e| AppError::Audit(format!("Failed to sign the access token: {}", e)))?;
+        Ok(TokenResponse {
+            access_token,
+            expires_in: self.expires_in,
+        })
+    }
+    /// Verifies an access token issued by `issue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The token presented by the client.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Claims)` - The claims of the token, if its signature is valid and it has not
+    ///   expired.
+    /// * `Err(AppError::Unauthorized)` - If the token is malformed, forged or expired.
+    pub fn verify(&self, token: &str) -> Result<Claims, AppError> {
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.leeway = 0;
+        jsonwebtoken::decode::<Claims>(token, &self.decoding_key, &validation)
+            .map(|data| data.claims)
+            .map_err(|e| match e.kind() {
+                jsonwebtoken::errors::ErrorKind::ExpiredSignature => {
+                    AppError::Unauthorized("The access token has expired".to_string())
+                }
+                _ => AppError::Unauthorized("Invalid access token".to_string()),
+            })
+    }
+}
@@ -0,0 +1,204 @@
+//! Renders shields.io-style badges showing the share of valid audits.
+//!
+//! Badges are embedded in READMEs and fetched on every page view, so the counts behind
+//! them are cached in memory for a few minutes instead of being recomputed each time.
+
+use crate::{error::AppError, models::ValidityCounts, services};
+use lru::LruCache;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+use std::{
+    num::NonZeroUsize,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// How long the counts behind a badge are reused before being recomputed.
+const CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// The number of projects whose counts are kept in memory.
+const CACHE_CAPACITY: NonZeroUsize = NonZeroUsize::new(1000).unwrap();
+
+/// The `Cache-Control` header sent with badges, matching the in-memory cache.
+pub const CACHE_CONTROL: &str = "public, max-age=300";
+
+/// The label shown on the left side of every badge.
+const LABEL: &str = "AI audit";
+
+/// The color of a badge, chosen from the validity rate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BadgeColor {
+    Green,
+    Yellow,
+    Red,
+    Gray,
+}
+
+impl BadgeColor {
+    /// Returns the shields.io name of the color.
+    fn name(self) -> &'static str {
+        match self {
+            BadgeColor::Green => "brightgreen",
+            BadgeColor::Yellow => "yellow",
+            BadgeColor::Red => "red",
+            BadgeColor::Gray => "lightgrey",
+        }
+    }
+
+    /// Returns the hexadecimal value of the color, as rendered by shields.io.
+    fn hex(self) -> &'static str {
+        match self {
+            BadgeColor::Green => "#4c1",
+            BadgeColor::Yellow => "#dfb317",
+            BadgeColor::Red => "#e05d44",
+            BadgeColor::Gray => "#9f9f9f",
+        }
+    }
+}
+
+/// A badge showing the validity rate of a set of audits.
+#[derive(Debug, Clone)]
+pub struct Badge {
+    message: String,
+    color: BadgeColor,
+}
+
+impl Badge {
+    /// Builds the badge for the given counts.
+    ///
+    /// The rate is rounded down, so that a badge never shows a better rate than the
+    /// actual one: green from 90%, yellow from 70%, red below. A set without audits
+    /// gets a gray "unknown" badge.
+    pub fn validity(counts: ValidityCounts) -> Self {
+        if counts.total <= 0 {
+            return Badge {
+                message: "unknown".to_string(),
+                color: BadgeColor::Gray,
+            };
+        }
+        let percent = counts.valid * 100 / counts.total;
+        let color = match percent {
+            90.. => BadgeColor::Green,
+            70.. => BadgeColor::Yellow,
+            _ => BadgeColor::Red,
+        };
+        Badge {
+            message: format!("{}% valid", percent),
+            color,
+        }
+    }
+
+    /// Returns the badge in the shields.io endpoint format.
+    ///
+    /// [Reference](https://shields.io/badges/endpoint-badge)
+    pub fn to_shields_json(&self) -> Value {
+        json!({
+            "schemaVersion": 1,
+            "label": LABEL,
+            "message": self.message,
+            "color": self.color.name(),
+        })
+    }
+
+    /// Renders the badge as an SVG image in the shields.io "flat" style.
+    pub fn to_svg(&self) -> String {
+        let label_width = text_width(LABEL);
+        let message_width = text_width(&self.message);
+        let width = label_width + message_width;
+        let label = escape(LABEL);
+        let message = escape(&self.message);
+        format!(
+            concat!(
+                r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="20" role="img" aria-label="{label}: {message}">"##,
+                r##"<title>{label}: {message}</title>"##,
+                r##"<linearGradient id="s" x2="0" y2="100%"><stop offset="0" stop-color="#bbb" stop-opacity=".1"/><stop offset="1" stop-opacity=".1"/></linearGradient>"##,
+                r##"<clipPath id="r"><rect width="{width}" height="20" rx="3" fill="#fff"/></clipPath>"##,
+                r##"<g clip-path="url(#r)"><rect width="{label_width}" height="20" fill="#555"/><rect x="{label_width}" width="{message_width}" height="20" fill="{color}"/><rect width="{width}" height="20" fill="url(#s)"/></g>"##,
+                r##"<g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11">"##,
+                r##"<text x="{label_x}" y="15" fill="#010101" fill-opacity=".3">{label}</text><text x="{label_x}" y="14">{label}</text>"##,
+                r##"<text x="{message_x}" y="15" fill="#010101" fill-opacity=".3">{message}</text><text x="{message_x}" y="14">{message}</text>"##,
+                "</g></svg>"
+            ),
+            width = width,
+            label_width = label_width,
+            message_width = message_width,
+            label_x = label_width / 2,
+            message_x = label_width + message_width / 2,
+            color = self.color.hex(),
+            label = label,
+            message = message,
+        )
+    }
+}
+
+/// Estimates the width, in pixels, of a badge section holding `text` in 11px Verdana.
+fn text_width(text: &str) -> usize {
+    text.chars().count() * 7 + 10
+}
+
+/// Escapes text for use in SVG content and attributes.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Caches the validity counts behind badges, keyed by project tag.
+pub struct BadgeCache {
+    /// The counts and the time they were computed, `None` standing for every audit.
+    entries: Mutex<LruCache<Option<String>, (Instant, ValidityCounts)>>,
+}
+
+impl Default for BadgeCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BadgeCache {
+    /// Creates an empty cache.
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(LruCache::new(CACHE_CAPACITY)),
+        }
+    }
+
+    /// Returns the validity counts of a project, computing them if the cached ones are
+    /// missing or stale.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - A reference to the database connection pool.
+    /// * `project` - The tag identifying the project, or `None` for every audit.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ValidityCounts)` - The counts, both zero for unknown projects.
+    /// * `Err(AppError::Sqlx)` - If the counts had to be computed and the query failed.
+    pub async fn validity_counts(
+        &self,
+        pool: &PgPool,
+        project: Option<&str>,
+    ) -> Result<ValidityCounts, AppError> {
+        // Tags are stored lowercase.
+        let key = project.map(|p| p.trim().to_lowercase());
+        if let Some((computed_at, counts)) = self.lock_entries().get(&key)
+            && computed_at.elapsed() < CACHE_TTL
+        {
+            return Ok(*counts);
+        }
+
+        let counts = services::get_validity_counts(pool, key.as_deref()).await?;
+        self.lock_entries().put(key, (Instant::now(), counts));
+        Ok(counts)
+    }
+
+    /// Locks the cached counts.
+    fn lock_entries(
+        &self,
+    ) -> std::sync::MutexGuard<'_, LruCache<Option<String>, (Instant, ValidityCounts)>> {
+        // The cache holds no invariants that a panic could break, so recover from poisoning.
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,452 @@
+//! Caches compilation results, so that code submitted again is not recompiled.
+//!
+//! Results are keyed by a hash of the code, the compilation settings and the version of
+//! the toolchain, so upgrading `rustc` never serves a stale verdict. The cache is an
+//! optimization only: when it is unavailable, code is simply compiled.
+//!
+//! On top of it, each replica keeps the whole result of its recent audits in memory
+//! (see [`AuditResultCache`]), so that code submitted again and again, such as from an
+//! interactive playground, is not validated nor compiled again.
+
+use crate::{
+    auditor::{self, AuditPolicy, CompilationOutcome, CompileOptions},
+    models::AuditCacheStats,
+};
+use anyhow::Context;
+use async_trait::async_trait;
+use lru::LruCache;
+use redis::{
+    AsyncCommands,
+    aio::{ConnectionManager, ConnectionManagerConfig},
+};
+use sha2::{Digest, Sha256};
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// How long results are cached when `CACHE_TTL_SECS` is not set.
+const DEFAULT_TTL: Duration = Duration::from_secs(3600);
+
+/// The number of audit results kept in memory when `AUDIT_CACHE_CAPACITY` is not set.
+const DEFAULT_AUDIT_CACHE_CAPACITY: usize = 1000;
+
+/// How long audit results are kept in memory when `AUDIT_CACHE_TTL_SECS` is not set.
+const DEFAULT_AUDIT_CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// How long a Redis connection attempt or command may take before the cache is skipped.
+const REDIS_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The prefix of the keys of cached compilation results.
+const KEY_PREFIX: &str = "rust-ai-auditor:compilation:";
+
+/// A store of compilation results.
+///
+/// Implementations swallow their own errors: a failing cache behaves as an empty one.
+#[async_trait]
+pub trait CompilationCache: Send + Sync {
+    /// Returns the result cached under `hash`, if any.
+    async fn get(&self, hash: &str) -> Option<CompilationOutcome>;
+
+    /// Caches a result under `hash` for `ttl`.
+    async fn set(&self, hash: &str, result: &CompilationOutcome, ttl: Duration);
+}
+
+/// A cache that stores nothing, used when no cache is configured.
+pub struct NoopCache;
+
+#[async_trait]
+impl CompilationCache for NoopCache {
+    async fn get(&self, _hash: &str) -> Option<CompilationOutcome> {
+        None
+    }
+
+    async fn set(&self, _hash: &str, _result: &CompilationOutcome, _ttl: Duration) {}
+}
+
+/// A cache storing results as JSON in Redis, expiring them with the TTL of each entry.
+pub struct RedisCache {
+    connection: ConnectionManager,
+}
+
+impl RedisCache {
+    /// Connects to the Redis server at `url` (e.g. `redis://localhost:6379`).
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(RedisCache)` - The connected cache. It reconnects on its own if the
+    ///   connection is lost later.
+    /// * `Err(anyhow::Error)` - If the URL is invalid or the server cannot be reached.
+    pub async fn connect(url: &str) -> anyhow::Result<Self> {
+        let client = redis::Client::open(url).context("REDIS_URL is not a valid Redis URL")?;
+        // Fail fast while Redis is down, so that audits are compiled without delay.
+        let config = ConnectionManagerConfig::new()
+            .set_connection_timeout(Some(REDIS_TIMEOUT))
+            .set_response_timeout(Some(REDIS_TIMEOUT))
+            .set_number_of_retries(1);
+        let connection = ConnectionManager::new_with_config(client, config)
+            .await
+            .context("Failed to connect to Redis")?;
+        Ok(RedisCache { connection })
+    }
+}
+
+#[async_trait]
+impl CompilationCache for RedisCache {
+    async fn get(&self, hash: &str) -> Option<CompilationOutcome> {
+        let mut connection = self.connection.clone();
+        let cached: Option<String> = connection
+            .get(format!("{}{}", KEY_PREFIX, hash))
+            .await
+            .inspect_err(|e| tracing::warn!(error = %e, "Could not read the compilation cache."))
+            .ok()?;
+        serde_json::from_str(&cached?).ok()
+    }
+
+    async fn set(&self, hash: &str, result: &CompilationOutcome, ttl: Duration) {
+        let Ok(json) = serde_json::to_string(result) else {
+            return;
+        };
+        let mut connection = self.connection.clone();
+        let stored: redis::RedisResult<()> = connection
+            .set_ex(
+                format!("{}{}", KEY_PREFIX, hash),
+                json,
+                ttl.as_secs().max(1),
+            )
+            .await;
+        if let Err(e) = stored {
+            tracing::warn!(error = %e, "Could not write the compilation cache.");
+        }
+    }
+}
+
+/// The compilation cache and the lifetime of its entries.
+#[derive(Clone)]
+pub struct CacheSettings {
+    /// The store of the results.
+    pub cache: Arc<dyn CompilationCache>,
+    /// How long results are kept.
+    pub ttl: Duration,
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        CacheSettings {
+            cache: Arc::new(NoopCache),
+            ttl: DEFAULT_TTL,
+        }
+    }
+}
+
+impl CacheSettings {
+    /// Configures the cache from the environment.
+    ///
+    /// * `REDIS_URL` - The Redis server caching results. Results are not cached when it
+    ///   is not set.
+    /// * `CACHE_TTL_SECS` - How long results are cached (defaults to 3600).
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(CacheSettings)` - The configured cache.
+    /// * `Err(anyhow::Error)` - If `CACHE_TTL_SECS` is not a positive integer, or Redis
+    ///   cannot be reached.
+    pub async fn from_env() -> anyhow::Result<Self> {
+        let ttl = match std::env::var("CACHE_TTL_SECS") {
+            Ok(value) => value
+                .parse::<u64>()
+                .ok()
+                .filter(|&secs| secs > 0)
+                .map(Duration::from_secs)
+                .context("CACHE_TTL_SECS must be a positive integer")?,
+            Err(_) => DEFAULT_TTL,
+        };
+        let cache: Arc<dyn CompilationCache> = match std::env::var("REDIS_URL") {
+            Ok(url) if !url.is_empty() => {
+                tracing::info!(
+                    ttl_secs = ttl.as_secs(),
+                    "Caching compilation results in Redis"
+                );
+                Arc::new(RedisCache::connect(&url).await?)
+            }
+            _ => Arc::new(NoopCache),
+        };
+        Ok(CacheSettings { cache, ttl })
+    }
+}
+
+/// Returns the key a compilation is cached under.
+///
+/// # Arguments
+///
+/// * `code` - The compiled code.
+/// * `options` - The settings of the compilation.
+///
+/// # Returns
+///
+/// * `String` - The hex-encoded SHA-256 of the code, its other files, the settings and
+///   the version of the toolchain.
+pub fn cache_key(code: &str, options: &CompileOptions) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(
+        auditor::check_rustc_available(options.channel)
+            .unwrap_or_default()
+            .as_bytes(),
+    );
+    hasher.update([0]);
+    hasher.update(format!("{:?}", options.channel).as_bytes());
+    hasher.update([0]);
+    hasher.update(options.opt_level.as_str().as_bytes());
+    hasher.update([0]);
+    hasher.update(options.edition.as_str().as_bytes());
+    hasher.update([0]);
+    hasher.update(options.target.as_deref().unwrap_or_default().as_bytes());
+    hasher.update([0]);
+    hasher.update(code.as_bytes());
+    for file in &options.files {
+        hasher.update([0]);
+        hasher.update(file.path.as_bytes());
+        hasher.update([0]);
+        hasher.update(file.content.as_bytes());
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Returns the key the result of an audit is cached under.
+///
+/// # Arguments
+///
+/// * `code` - The audited code.
+/// * `options` - The settings the code is compiled with.
+/// * `policy` - The rules the code is validated and judged with.
+/// * `strict` - Whether blocking findings reject the code without compiling it.
+///
+/// # Returns
+///
+/// * `String` - The hex-encoded SHA-256 of the compilation key (see [`cache_key`]), the
+///   version of the rules and the strictness.
+pub fn audit_result_key(
+    code: &str,
+    options: &CompileOptions,
+    policy: &AuditPolicy,
+    strict: bool,
+) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(cache_key(code, options).as_bytes());
+    hasher.update([0]);
+    hasher.update(policy.rules_version().as_bytes());
+    hasher.update([0]);
+    hasher.update([u8::from(strict)]);
+    hex::encode(hasher.finalize())
+}
+
+/// An in-memory cache of the results of recent audits, evicting the least recently
+/// used ones once full.
+///
+/// Results expire after a TTL, and are keyed by [`audit_result_key`], so that a new
+/// toolchain or edited rules never serve a stale result.
+pub struct AuditResultCache<V> {
+    /// The cached results and when they were cached, or `None` if caching is disabled.
+    entries: Option<Mutex<LruCache<String, (Instant, V)>>>,
+    /// How long results are kept.
+    ttl: Duration,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    bypasses: AtomicU64,
+}
+
+impl<V: Clone> Default for AuditResultCache<V> {
+    /// Returns a disabled cache, which never holds a result.
+    fn default() -> Self {
+        AuditResultCache::new(0, DEFAULT_AUDIT_CACHE_TTL)
+    }
+}
+
+impl<V: Clone> AuditResultCache<V> {
+    /// Creates an empty cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The number of results kept at most; 0 disables the cache.
+    /// * `ttl` - How long results are kept.
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        AuditResultCache {
+            entries: NonZeroUsize::new(capacity).map(|c| Mutex::new(LruCache::new(c))),
+            ttl,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            bypasses: AtomicU64::new(0),
+        }
+    }
+
+    /// Configures the cache from the environment.
+    ///
+    /// * `AUDIT_CACHE_CAPACITY` - The number of audit results kept in memory, 0 to
+    ///   disable the cache (defaults to 1000).
+    /// * `AUDIT_CACHE_TTL_SECS` - How long audit results are kept (defaults to 300).
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(AuditResultCache)` - The configured cache.
+    /// * `Err(anyhow::Error)` - If a variable is not a valid number.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let capacity = match std::env::var("AUDIT_CACHE_CAPACITY") {
+            Ok(value) => value
+                .parse()
+                .context("AUDIT_CACHE_CAPACITY must be a non-negative integer")?,
+            Err(_) => DEFAULT_AUDIT_CACHE_CAPACITY,
+        };
+        let ttl = match std::env::var("AUDIT_CACHE_TTL_SECS") {
+            Ok(value) => value
+                .parse::<u64>()
+                .ok()
+                .filter(|&secs| secs > 0)
+                .map(Duration::from_secs)
+                .context("AUDIT_CACHE_TTL_SECS must be a positive integer")?,
+            Err(_) => DEFAULT_AUDIT_CACHE_TTL,
+        };
+        if capacity > 0 {
+            tracing::info!(
+                capacity,
+                ttl_secs = ttl.as_secs(),
+                "Caching audit results in memory"
+            );
+        }
+        Ok(AuditResultCache::new(capacity, ttl))
+    }
+
+    /// Returns whether results are cached at all.
+    pub fn is_enabled(&self) -> bool {
+        self.entries.is_some()
+    }
+
+    /// Returns the result cached under `key`, unless it expired, and counts the lookup.
+    pub fn get(&self, key: &str) -> Option<V> {
+        let entries = self.entries.as_ref()?;
+        let mut entries = lock(entries);
+        let found = match entries.get(key) {
+            Some((cached_at, value)) if cached_at.elapsed() < self.ttl => Some(value.clone()),
+            Some(_) => {
+                entries.pop(key);
+                None
+            }
+            None => None,
+        };
+        let counter = if found.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        found
+    }
+
+    /// Caches `value` under `key`, replacing the result cached under it if any.
+    pub fn put(&self, key: String, value: V) {
+        if let Some(entries) = &self.entries {
+            lock(entries).put(key, (Instant::now(), value));
+        }
+    }
+
+    /// Counts an audit that skipped the cache.
+    pub fn record_bypass(&self) {
+        self.bypasses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the size and the counters of the cache.
+    pub fn stats(&self) -> AuditCacheStats {
+        let (capacity, entries) = self.entries.as_ref().map_or((0, 0), |entries| {
+            let entries = lock(entries);
+            (entries.cap().get() as u64, entries.len() as u64)
+        });
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let lookups = hits + misses;
+        AuditCacheStats {
+            capacity,
+            entries,
+            hits,
+            misses,
+            bypasses: self.bypasses.load(Ordering::Relaxed),
+            hit_rate: if lookups > 0 {
+                hits as f64 / lookups as f64
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+/// Locks the entries of an audit result cache.
+fn lock<V>(
+    entries: &Mutex<LruCache<String, (Instant, V)>>,
+) -> std::sync::MutexGuard<'_, LruCache<String, (Instant, V)>> {
+    // The cache holds no invariants that a panic could break, so recover from poisoning.
+    entries.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{AuditFile, Edition, OptLevel};
+
+    fn outcome() -> CompilationOutcome {
+        CompilationOutcome {
+            success: true,
+            output: String::new(),
+            diagnostics: Vec::new(),
+            duration: Duration::from_millis(10),
+            json_output: String::new(),
+            library: None,
+            lockfile: None,
+            dependencies: None,
+            security_advisories: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn noop_cache_never_holds_a_result() {
+        let cache = NoopCache;
+        cache.set("hash", &outcome(), DEFAULT_TTL).await;
+        assert!(cache.get("hash").await.is_none());
+    }
+
+    #[test]
+    fn cache_key_depends_on_the_code_and_the_settings() {
+        let code = "pub fn f() {}";
+        let options = CompileOptions::default();
+        let key = cache_key(code, &options);
+        assert_eq!(key, cache_key(code, &options));
+        assert_eq!(key.len(), 64);
+
+        let variants = [
+            CompileOptions {
+                opt_level: OptLevel::O3,
+                ..CompileOptions::default()
+            },
+            CompileOptions {
+                edition: Edition::E2018,
+                ..CompileOptions::default()
+            },
+            CompileOptions {
+                target: Some("wasm32-unknown-unknown".into()),
+                ..CompileOptions::default()
+            },
+            CompileOptions {
+                files: vec![AuditFile {
+                    path: "src/lib.rs".into(),
+                    content: code.into(),
+                }],
+                ..CompileOptions::default()
+            },
+        ];
+        for options in &variants {
+            assert_ne!(cache_key(code, options), key, "{:?}", options);
+        }
+        assert_ne!(cache_key("pub fn g() {}", &options), key);
+    }
+}
@@ -0,0 +1,326 @@
+//! Runs the audit pipeline on local files, without the web server or a database.
+//!
+//! This is meant for pre-commit hooks and CI jobs: every file is validated and compiled
+//! exactly as the server would, the results are printed, and the exit code tells whether
+//! every file is valid. The results can also be recorded on a remote server.
+
+use crate::{
+    auditor::{self, AuditPolicy, CompileOptions},
+    config,
+    models::{AuditStatus, Channel, Diagnostic, Edition, Finding, OptLevel, Severity},
+};
+use anyhow::{Context, bail};
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::json;
+use std::{path::PathBuf, process::ExitCode, time::Duration};
+use uuid::Uuid;
+
+/// The output formats of the `audit` subcommand.
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// One human-readable block per file.
+    #[default]
+    Text,
+    /// A JSON array with one report per file.
+    Json,
+}
+
+/// The arguments of the `audit` subcommand.
+#[derive(Debug, clap::Args)]
+pub struct AuditArgs {
+    /// The Rust files to audit.
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+    /// The output format.
+    #[arg(long, value_enum, default_value_t)]
+    pub format: OutputFormat,
+    /// Reject files with blocking findings without compiling them (defaults to
+    /// `AUDIT_STRICT`).
+    #[arg(long)]
+    pub strict: bool,
+    /// Compile with the nightly toolchain.
+    #[arg(long)]
+    pub nightly: bool,
+    /// The optimization level passed to `rustc -C opt-level=` (0-3, s or z).
+    #[arg(long, default_value = "0")]
+    pub opt_level: OptLevel,
+    /// The Rust edition passed to `rustc --edition` (2015, 2018, 2021 or 2024). Defaults
+    /// to the edition guessed from each file.
+    #[arg(long)]
+    pub edition: Option<Edition>,
+    /// The target triple passed to `rustc --target` (defaults to the host).
+    #[arg(long)]
+    pub target: Option<String>,
+    /// The URL of a rust-ai-auditor server to record the audits on.
+    #[arg(long, requires = "api_key")]
+    pub server: Option<String>,
+    /// The bearer token sent to the server.
+    #[arg(long, requires = "server")]
+    pub api_key: Option<String>,
+}
+
+/// The result of auditing one file.
+#[derive(Debug, Serialize)]
+struct FileReport {
+    /// The path of the file, as given on the command line.
+    path: String,
+    /// The verdict of the audit.
+    status: AuditStatus,
+    /// Whether the file is valid.
+    is_valid: bool,
+    /// The potential problems detected by the heuristic validation.
+    findings: Vec<Finding>,
+    /// The diagnostics emitted by `rustc`, if the file was compiled.
+    diagnostics: Vec<Diagnostic>,
+    /// The human-readable compiler output, if the file failed to compile.
+    compilation_error: Option<String>,
+    /// Why the file was rejected without being compiled, if it was.
+    rejection_reason: Option<String>,
+    /// The ID of the audit recorded on the remote server, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remote_audit_id: Option<Uuid>,
+}
+
+/// Audits local files and prints the results.
+///
+/// # Arguments
+///
+/// * `args` - The arguments of the `audit` subcommand.
+///
+/// # Returns
+///
+/// * `Ok(ExitCode::SUCCESS)` - If every file is valid.
+/// * `Ok(ExitCode::from(1))` - If at least one file is invalid.
+/// * `Err(anyhow::Error)` - If a file cannot be read, `rustc` cannot be run, or the
+///   results cannot be recorded on the server.
+pub async fn run(args: AuditArgs) -> anyhow::Result<ExitCode> {
+    let (policy, toolchain) =
+        config::load_audit_settings().context("Invalid audit configuration")?;
+    auditor::set_toolchain(toolchain);
+    let strict = args.strict || policy.strict;
+    let options = CompileOptions {
+        channel: if args.nightly {
+            Channel::Nightly
+        } else {
+            Channel::Stable
+        },
+        opt_level: args.opt_level,
+        edition: Edition::default(),
+        target: args.target,
+        files: Vec::new(),
+    };
+    if let Some(target) = &options.target {
+        auditor::check_target_installed(options.channel, target).map_err(anyhow::Error::msg)?;
+    }
+    let remote = match (&args.server, &args.api_key) {
+        (Some(server), Some(api_key)) => Some(RemoteServer::new(server, api_key)?),
+        _ => None,
+    };
+
+    let mut reports = Vec::with_capacity(args.files.len());
+    for path in &args.files {
+        let code = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let options = CompileOptions {
+            edition: args
+                .edition
+                .unwrap_or_else(|| auditor::detect_edition_from_code(&code)),
+            ..options.clone()
+        };
+        let mut report = audit_file(path.display().to_string(), &code, &policy, strict, &options)
+            .await
+            .with_context(|| format!("Failed to audit {}", path.display()))?;
+        if let Some(remote) = &remote {
+            report.remote_audit_id = Some(
+                remote
+                    .record(&report.path, &code, strict, &options, args.edition)
+                    .await?,
+            );
+        }
+        reports.push(report);
+    }
+
+    match args.format {
+        OutputFormat::Text => reports.iter().for_each(print_text),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&reports)?),
+    }
+
+    if reports.iter().all(|r| r.is_valid) {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        Ok(ExitCode::from(1))
+    }
+}
+
+/// Validates and compiles the code of one file.
+async fn audit_file(
+    path: String,
+    code: &str,
+    policy: &AuditPolicy,
+    strict: bool,
+    options: &CompileOptions,
+) -> anyhow::Result<FileReport> {
+    let findings = auditor::validate_code(code, policy);
+    let blocking: Vec<&Finding> = findings
+        .iter()
+        .filter(|f| f.severity == Severity::Error)
+        .collect();
+
+    let always_rejected = findings.iter().any(auditor::always_rejects);
+    if (strict || always_rejected) && !blocking.is_empty() {
+        let reason = blocking
+            .iter()
+            .map(|f| format!("{}: {}", f.code, f.message))
+            .collect::<Vec<_>>()
+            .join("; ");
+        return Ok(FileReport {
+            path,
+            status: AuditStatus::Rejected,
+            is_valid: false,
+            findings,
+            diagnostics: Vec::new(),
+            compilation_error: None,
+            rejection_reason: Some(reason),
+            remote_audit_id: None,
+        });
+    }
+
+    let outcome = auditor::check_compilation(code, options).await?;
+    let is_valid = policy.accepts(&outcome);
+    Ok(FileReport {
+        path,
+        status: if is_valid {
+            AuditStatus::Valid
+        } else {
+            AuditStatus::CompileError
+        },
+        is_valid,
+        findings,
+        diagnostics: outcome.diagnostics,
+        compilation_error: (!is_valid).then_some(outcome.output),
+        rejection_reason: None,
+        remote_audit_id: None,
+    })
+}
+
+/// Prints the report of one file in the text format.
+fn print_text(report: &FileReport) {
+    let verdict = match report.status {
+        AuditStatus::Valid => "valid",
+        AuditStatus::CompileError => "compile error",
+        AuditStatus::Rejected => "rejected",
+        AuditStatus::Pending => "pending",
+        AuditStatus::Cancelled => "cancelled",
+    };
+    println!("{}: {}", report.path, verdict);
+    for finding in &report.findings {
+        let severity = match finding.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+        };
+        match finding.line {
+            Some(line) => println!(
+                "  {}[{}] line {}: {}",
+                severity, finding.code, line, finding.message
+            ),
+            None => println!("  {}[{}]: {}", severity, finding.code, finding.message),
+        }
+    }
+    for diagnostic in report.diagnostics.iter().filter(|d| d.line.is_some()) {
+        let code = diagnostic
+            .code
+            .as_deref()
+            .map(|c| format!("[{}]", c))
+            .unwrap_or_default();
+        println!(
+            "  {}{} line {}:{}: {}",
+            diagnostic.level,
+            code,
+            diagnostic.line.unwrap_or_default(),
+            diagnostic.column.unwrap_or_default(),
+            diagnostic.message
+        );
+    }
+    if let Some(reason) = &report.rejection_reason {
+        println!("  {}", reason);
+    }
+    if let Some(id) = report.remote_audit_id {
+        println!("  recorded as audit {}", id);
+    }
+}
+
+/// A rust-ai-auditor server the audits are recorded on.
+struct RemoteServer {
+    http: reqwest::Client,
+    url: String,
+    api_key: String,
+}
+
+impl RemoteServer {
+    /// Creates a client for the server at `url`.
+    fn new(url: &str, api_key: &str) -> anyhow::Result<Self> {
+        let http = reqwest::Client::builder()
+            .user_agent(concat!("rust-ai-auditor/", env!("CARGO_PKG_VERSION")))
+            .timeout(Duration::from_secs(120))
+            .build()?;
+        Ok(RemoteServer {
+            http,
+            url: url.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+        })
+    }
+
+    /// Submits the code of a file to the server, which audits it again and stores it.
+    ///
+    /// The edition is only sent if it was chosen on the command line, so that the server
+    /// records it as guessed otherwise.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Uuid)` - The ID of the audit created on the server.
+    /// * `Err(anyhow::Error)` - If the server cannot be reached or rejects the request.
+    async fn record(
+        &self,
+        path: &str,
+        code: &str,
+        strict: bool,
+        options: &CompileOptions,
+        edition: Option<Edition>,
+    ) -> anyhow::Result<Uuid> {
+        #[derive(serde::Deserialize)]
+        struct CreatedAudit {
+            id: Uuid,
+        }
+
+        let response = self
+            .http
+            .post(format!("{}/audit", self.url))
+            .bearer_auth(&self.api_key)
+            .json(&json!({
+                "prompt": path,
+                "generated_code": code,
+                "strict": strict,
+                "channel": options.channel,
+                "opt_level": options.opt_level,
+                "edition": edition,
+                "target": options.target,
+            }))
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach {}", self.url))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            bail!(
+                "The server rejected the audit of {} ({}): {}",
+                path,
+                status,
+                body
+            );
+        }
+        Ok(response.json::<CreatedAudit>().await?.id)
+    }
+}
@@ -0,0 +1,67 @@
+//! Builds the CORS policy applied to every route of the web server.
+
+use anyhow::{Context, bail};
+use axum::http::{HeaderName, HeaderValue, Method, header};
+use std::time::Duration;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+/// How long browsers may cache the result of a preflight request.
+const PREFLIGHT_MAX_AGE: Duration = Duration::from_secs(600);
+
+/// Builds the CORS layer from the settings of the server.
+///
+/// When any origin is allowed (`CORS_ALLOW_ANY`), credentials are not: browsers refuse
+/// credentialed requests against a wildcard origin. Otherwise the listed origins
+/// (`CORS_ALLOWED_ORIGINS`, e.g. `https://dashboard.example.com`) may call the API with
+/// credentials. When neither is set, no cross-origin requests are allowed.
+///
+/// # Arguments
+///
+/// * `allow_any` - Whether requests from any origin are allowed.
+/// * `allowed_origins` - The origins allowed to call the API with credentials.
+///
+/// # Returns
+///
+/// * `Ok(CorsLayer)` - The configured CORS layer.
+/// * `Err(anyhow::Error)` - If an origin is not a valid header value, or if a
+///   wildcard origin is listed without `CORS_ALLOW_ANY=true`.
+pub fn cors_layer(allow_any: bool, allowed_origins: &[String]) -> anyhow::Result<CorsLayer> {
+    if allow_any {
+        tracing::warn!("CORS_ALLOW_ANY is enabled: requests from any origin are allowed");
+        return Ok(CorsLayer::new()
+            .allow_origin(Any)
+            .allow_methods(Any)
+            .allow_headers(Any)
+            .max_age(PREFLIGHT_MAX_AGE));
+    }
+
+    let origins = allowed_origins
+        .iter()
+        .map(|origin| origin.trim())
+        .filter(|origin| !origin.is_empty())
+        .map(|origin| {
+            if origin == "*" {
+                bail!("Wildcard CORS origin requires CORS_ALLOW_ANY=true");
+            }
+            HeaderValue::from_str(origin)
+                .with_context(|| format!("Invalid origin in CORS_ALLOWED_ORIGINS: {}", origin))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    tracing::info!(origins = ?origins, "CORS allowed origins configured");
+
+    Ok(CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_credentials(true)
+        .allow_methods([Method::GET, Method::POST, Method::DELETE, Method::OPTIONS])
+        .allow_headers([
+            header::ACCEPT,
+            header::AUTHORIZATION,
+            header::CONTENT_TYPE,
+            HeaderName::from_static("idempotency-key"),
+            // Headers sent by Apollo and other GraphQL clients.
+            HeaderName::from_static("apollo-require-preflight"),
+            HeaderName::from_static("x-apollo-operation-name"),
+        ])
+        .max_age(PREFLIGHT_MAX_AGE))
+}
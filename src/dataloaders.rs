@@ -0,0 +1,99 @@
+//! Batch loaders used by the GraphQL resolvers to avoid N+1 database queries.
+
+use crate::{
+    db::Db,
+    error::AppError,
+    models::{AuditComment, Diagnostic},
+};
+use async_graphql::dataloader::Loader;
+use sqlx::types::Json;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Loads the comments of many audits in a single query, keyed by audit ID.
+pub struct CommentLoader {
+    /// The database pools, read from the replica if one is reachable.
+    pub db: Db,
+}
+
+impl Loader<Uuid> for CommentLoader {
+    type Value = Vec<AuditComment>;
+    type Error = AppError;
+
+    /// Loads the comments of every requested audit, oldest first.
+    ///
+    /// Audits without comments are absent from the returned map.
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        let comments = sqlx::query_as!(
+            AuditComment,
+            "SELECT id, audit_id, author, body, created_at FROM audit_comments WHERE audit_id = ANY($1) ORDER BY created_at",
+            keys
+        )
+        .fetch_all(self.db.read())
+        .await?;
+
+        let mut by_audit: HashMap<Uuid, Vec<AuditComment>> = HashMap::new();
+        for comment in comments {
+            by_audit.entry(comment.audit_id).or_default().push(comment);
+        }
+        Ok(by_audit)
+    }
+}
+
+/// Loads the average score and number of ratings of many audits in a single query, keyed
+/// by audit ID.
+pub struct RatingLoader {
+    /// The database pools, read from the replica if one is reachable.
+    pub db: Db,
+}
+
+impl Loader<Uuid> for RatingLoader {
+    type Value = (f64, i64);
+    type Error = AppError;
+
+    /// Loads the average score and number of ratings of every requested audit.
+    ///
+    /// Audits without ratings are absent from the returned map.
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT audit_id, AVG(score)::float8 AS "average!", COUNT(*) AS "count!"
+            FROM audit_ratings WHERE audit_id = ANY($1) GROUP BY audit_id
+            "#,
+            keys
+        )
+        .fetch_all(self.db.read())
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.audit_id, (row.average, row.count)))
+            .collect())
+    }
+}
+
+/// Loads the stored compiler diagnostics of many audits in a single query, keyed by audit ID.
+pub struct DiagnosticsLoader {
+    /// The database pools, read from the replica if one is reachable.
+    pub db: Db,
+}
+
+impl Loader<Uuid> for DiagnosticsLoader {
+    type Value = Vec<Diagnostic>;
+    type Error = AppError;
+
+    /// Loads the diagnostics of every requested audit, in the order `rustc` emitted them.
+    ///
+    /// Audits that no longer exist are absent from the returned map.
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        let rows = sqlx::query!(
+            r#"SELECT id, diagnostics AS "diagnostics: Json<Vec<Diagnostic>>" FROM ai_audits WHERE id = ANY($1)"#,
+            keys
+        )
+        .fetch_all(self.db.read())
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.id, row.diagnostics.0))
+            .collect())
+    }
+}
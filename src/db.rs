@@ -0,0 +1,143 @@
+//! Chooses the database pool queries run on.
+//!
+//! Writes, and reads that must see them immediately, run on the primary database. Pure
+//! reads (listings, searches, statistics, exports) run on the read replica if one is
+//! configured and reachable, and fall back to the primary otherwise.
+
+use crate::models::DbStats;
+use sqlx::PgPool;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+/// How often the read replica is checked.
+const REPLICA_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long the read replica may take to answer a check before it is deemed unreachable.
+const REPLICA_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The database pools, shared by the handlers and the GraphQL resolvers.
+#[derive(Clone)]
+pub struct Db {
+    /// The pools and the state of the replica.
+    inner: Arc<Pools>,
+}
+
+/// The pools behind a [`Db`].
+struct Pools {
+    /// The pool of the primary database.
+    primary: PgPool,
+    /// The pool of the read replica, if one is configured.
+    replica: Option<PgPool>,
+    /// Whether the read replica answered its last check.
+    replica_healthy: AtomicBool,
+    /// The number of reads sent to the primary because the replica was unreachable.
+    fallback_reads: AtomicU64,
+}
+
+impl Db {
+    /// Creates the pools.
+    ///
+    /// The read replica is deemed reachable until it is first checked.
+    ///
+    /// # Arguments
+    ///
+    /// * `primary` - The pool of the primary database.
+    /// * `replica` - The pool of the read replica, if one is configured.
+    pub fn new(primary: PgPool, replica: Option<PgPool>) -> Self {
+        Self {
+            inner: Arc::new(Pools {
+                primary,
+                replica,
+                replica_healthy: AtomicBool::new(true),
+                fallback_reads: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Returns the pool of the primary database, for writes and for reads that must see
+    /// them immediately.
+    pub fn primary(&self) -> &PgPool {
+        &self.inner.primary
+    }
+
+    /// Returns the pool pure reads run on: the read replica if one is configured and
+    /// reachable, else the primary database.
+    pub fn read(&self) -> &PgPool {
+        match &self.inner.replica {
+            Some(replica) if self.inner.replica_healthy.load(Ordering::Relaxed) => {
+                tracing::trace!(pool = "replica", "Reading from the read replica");
+                replica
+            }
+            Some(_) => {
+                self.inner.fallback_reads.fetch_add(1, Ordering::Relaxed);
+                tracing::trace!(
+                    pool = "primary",
+                    "Read replica unreachable, reading from the primary database"
+                );
+                &self.inner.primary
+            }
+            None => {
+                tracing::trace!(pool = "primary", "Reading from the primary database");
+                &self.inner.primary
+            }
+        }
+    }
+
+    /// Checks whether the read replica answers a query, and sends reads to the primary
+    /// database until it does again.
+    ///
+    /// A warning is logged when the replica becomes unreachable, and a notice when it
+    /// recovers.
+    pub async fn check_replica(&self) {
+        let Some(replica) = &self.inner.replica else {
+            return;
+        };
+        let check = sqlx::query_scalar!(r#"SELECT 1 AS "one!""#).fetch_one(replica);
+        let error = match tokio::time::timeout(REPLICA_CHECK_TIMEOUT, check).await {
+            Ok(Ok(_)) => None,
+            Ok(Err(e)) => Some(e.to_string()),
+            Err(_) => Some(format!("no answer within {:?}", REPLICA_CHECK_TIMEOUT)),
+        };
+        let healthy = error.is_none();
+        let was_healthy = self.inner.replica_healthy.swap(healthy, Ordering::Relaxed);
+        match error {
+            Some(error) if was_healthy => tracing::warn!(
+                %error,
+                "Read replica unreachable, reading from the primary database."
+            ),
+            None if !was_healthy => tracing::info!("Read replica reachable again."),
+            _ => {}
+        }
+    }
+
+    /// Checks the read replica every [`REPLICA_CHECK_INTERVAL`], starting after one
+    /// interval, for as long as the server runs. Does nothing if no replica is configured.
+    pub async fn watch_replica(self) {
+        if self.inner.replica.is_none() {
+            return;
+        }
+        let start = tokio::time::Instant::now() + REPLICA_CHECK_INTERVAL;
+        let mut interval = tokio::time::interval_at(start, REPLICA_CHECK_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            self.check_replica().await;
+        }
+    }
+
+    /// Returns a snapshot of the state of the read replica.
+    pub fn stats(&self) -> DbStats {
+        let replica_configured = self.inner.replica.is_some();
+        DbStats {
+            replica_configured,
+            replica_healthy: replica_configured
+                && self.inner.replica_healthy.load(Ordering::Relaxed),
+            fallback_reads: self.inner.fallback_reads.load(Ordering::Relaxed),
+        }
+    }
+}
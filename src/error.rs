@@ -5,15 +5,23 @@ use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
 };
+use serde::Serialize;
 use serde_json::json;
+use std::{collections::BTreeMap, sync::Arc};
 use thiserror::Error;
+use utoipa::{
+    IntoResponses, ToSchema,
+    openapi::{self, ContentBuilder, Ref, RefOr, ResponseBuilder},
+};
 
 /// The primary error type for this application, designed to be easily convertible into an HTTP response.
-#[derive(Debug, Error)]
+///
+/// It is cheaply cloneable so that it can be shared by the GraphQL data loaders.
+#[derive(Debug, Clone, Error)]
 pub enum AppError {
     /// Represents a failure from the database.
     #[error("Database error: {0}")]
-    Sqlx(#[from] sqlx::Error),
+    Sqlx(Arc<sqlx::Error>),
 
     /// Represents an error during the code compilation/auditing process.
     #[error("Audit error: {0}")]
@@ -22,6 +30,62 @@ pub enum AppError {
     /// Represents a failure to find a required resource.
     #[error("Resource not found: {0}")]
     NotFound(String),
+
+    /// Represents a request that is malformed or violates an input constraint.
+    #[error("Validation error: {0}")]
+    Validation(String),
+
+    /// Represents a request that conflicts with the current state of a resource.
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    /// Represents a request lacking valid credentials for the requested operation.
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
+    /// Represents an authenticated request for an operation the caller is not allowed to
+    /// perform, e.g. deleting the comment of another user.
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
+    /// Represents a failure of an external service the application depends on.
+    #[error("Upstream error: {0}")]
+    Upstream(String),
+
+    /// Represents a failure of the LLM provider asked to generate code.
+    #[error("Provider error: {0}")]
+    Provider(String),
+
+    /// Represents a request refused because of rate limits: those of the LLM provider, or
+    /// the limit of audits per client of this server.
+    #[error("Rate limited: {0}")]
+    RateLimited(String),
+
+    /// Represents a model answer without a Rust code block to audit.
+    #[error("Missing code block: {0}")]
+    MissingCodeBlock(String),
+
+    /// Represents a request body that is well-formed but cannot be read, e.g. an uploaded
+    /// file that is not UTF-8.
+    #[error("Unprocessable: {0}")]
+    Unprocessable(String),
+
+    /// Represents a request refused because the server is shutting down.
+    #[error("Service unavailable: {0}")]
+    Unavailable(String),
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        AppError::Sqlx(Arc::new(e))
+    }
+}
+
+/// The JSON body returned to REST clients when a request fails.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorResponse {
+    /// A human-readable description of the error.
+    pub error: String,
 }
 
 impl IntoResponse for AppError {
@@ -37,9 +101,119 @@ impl IntoResponse for AppError {
             }
             AppError::Audit(e) => (StatusCode::BAD_REQUEST, e),
             AppError::NotFound(e) => (StatusCode::NOT_FOUND, e),
+            AppError::Validation(e) => (StatusCode::BAD_REQUEST, e),
+            AppError::Conflict(e) => (StatusCode::CONFLICT, e),
+            AppError::Unauthorized(e) => (StatusCode::UNAUTHORIZED, e),
+            AppError::Forbidden(e) => (StatusCode::FORBIDDEN, e),
+            AppError::Upstream(e) => (StatusCode::BAD_GATEWAY, e),
+            AppError::Provider(e) => (StatusCode::BAD_GATEWAY, e),
+            AppError::RateLimited(e) => (StatusCode::TOO_MANY_REQUESTS, e),
+            AppError::MissingCodeBlock(e) => (StatusCode::UNPROCESSABLE_ENTITY, e),
+            AppError::Unprocessable(e) => (StatusCode::UNPROCESSABLE_ENTITY, e),
+            AppError::Unavailable(e) => (StatusCode::SERVICE_UNAVAILABLE, e),
         };
 
-        let body = Json(json!({ "error": error_message }));
+        let body = Json(ErrorResponse {
+            error: error_message,
+        });
         (status, body).into_response()
     }
 }
+
+/// Documents the HTTP responses produced by each `AppError` variant in the OpenAPI document.
+///
+/// The status codes and messages mirror the `IntoResponse` implementation above.
+impl IntoResponses for AppError {
+    fn responses() -> BTreeMap<String, RefOr<openapi::Response>> {
+        let error_response = |description: &str, example: &str| {
+            let content = ContentBuilder::new()
+                .schema(Some(Ref::from_schema_name("ErrorResponse")))
+                .example(Some(json!({ "error": example })))
+                .build();
+            ResponseBuilder::new()
+                .description(description)
+                .content("application/json", content)
+                .build()
+                .into()
+        };
+
+        BTreeMap::from([
+            (
+                StatusCode::BAD_REQUEST.as_str().to_string(),
+                error_response(
+                    "The audit could not be performed (`AppError::Audit`) or the request \
+                     is invalid (`AppError::Validation`)",
+                    "Failed to execute rustc command: No such file or directory",
+                ),
+            ),
+            (
+                StatusCode::UNAUTHORIZED.as_str().to_string(),
+                error_response(
+                    "The request lacks valid credentials (`AppError::Unauthorized`)",
+                    "Invalid bearer token",
+                ),
+            ),
+            (
+                StatusCode::FORBIDDEN.as_str().to_string(),
+                error_response(
+                    "The caller is not allowed to perform the operation (`AppError::Forbidden`)",
+                    "Only the author of a comment or an administrator can delete it",
+                ),
+            ),
+            (
+                StatusCode::NOT_FOUND.as_str().to_string(),
+                error_response(
+                    "A required resource does not exist (`AppError::NotFound`)",
+                    "Audit 00000000-0000-0000-0000-000000000000 not found",
+                ),
+            ),
+            (
+                StatusCode::CONFLICT.as_str().to_string(),
+                error_response(
+                    "The request conflicts with an existing resource (`AppError::Conflict`)",
+                    "Idempotency key 'retry-42' was already used with a different payload",
+                ),
+            ),
+            (
+                StatusCode::UNPROCESSABLE_ENTITY.as_str().to_string(),
+                error_response(
+                    "The generated answer contains no Rust code block \
+                     (`AppError::MissingCodeBlock`) or the request body cannot be read \
+                     (`AppError::Unprocessable`)",
+                    "The answer of gpt-4o contains no Rust code block",
+                ),
+            ),
+            (
+                StatusCode::TOO_MANY_REQUESTS.as_str().to_string(),
+                error_response(
+                    "The client or the LLM provider exceeded its rate limit \
+                     (`AppError::RateLimited`)",
+                    "OpenAI rate limit exceeded, retry after 20 seconds",
+                ),
+            ),
+            (
+                StatusCode::INTERNAL_SERVER_ERROR.as_str().to_string(),
+                error_response(
+                    "A database operation failed (`AppError::Sqlx`)",
+                    "An internal database error occurred",
+                ),
+            ),
+            (
+                StatusCode::BAD_GATEWAY.as_str().to_string(),
+                error_response(
+                    "An external service failed (`AppError::Upstream`) or the LLM provider \
+                     failed to generate code (`AppError::Provider`)",
+                    "GitHub API returned 503 Service Unavailable",
+                ),
+            ),
+            (
+                StatusCode::SERVICE_UNAVAILABLE.as_str().to_string(),
+                error_response(
+                    "The server is shutting down and accepts no new audits \
+                     (`AppError::Unavailable`)",
+                    "The server is shutting down, retry later",
+                ),
+            ),
+        ])
+    }
+}
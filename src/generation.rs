@@ -0,0 +1,572 @@
+//! Generates the code of audits from their prompt with an LLM provider.
+//!
+//! Each provider is reached through its chat API. The model is asked to answer with a
+//! single Rust code block, which is extracted from the answer and audited like code
+//! submitted by a client.
+
+use crate::{
+    error::AppError,
+    models::{GenerateOptions, Provider},
+};
+use reqwest::{StatusCode, header};
+use serde::Deserialize;
+use serde_json::json;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// The instructions sent to the model along with the prompt.
+const SYSTEM_PROMPT: &str = "You are an expert Rust programmer. Answer with the complete \
+     implementation in a single ```rust code block. The code is compiled as a library crate \
+     without dependencies, so do not use external crates.";
+
+/// The maximum number of characters of compiler output sent back to the model.
+const MAX_FIX_ERROR_CHARS: usize = 8000;
+
+/// The maximum number of tokens Anthropic models may generate, which its API requires.
+const ANTHROPIC_MAX_TOKENS: u32 = 4096;
+
+/// The version of the Anthropic messages API.
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// The answer of a model to a prompt.
+#[derive(Debug, Clone)]
+pub struct ModelResponse {
+    /// The complete text of the answer.
+    pub text: String,
+    /// The number of prompt tokens billed by the provider, if it reported them.
+    pub prompt_tokens: Option<i32>,
+    /// The number of completion tokens billed by the provider, if it reported them.
+    pub completion_tokens: Option<i32>,
+}
+
+/// Code generated from a prompt, along with the answer it was extracted from.
+#[derive(Debug, Clone)]
+pub struct GeneratedCode {
+    /// The code extracted from the answer.
+    pub code: String,
+    /// The answer of the model.
+    pub response: ModelResponse,
+    /// How long the provider took to answer.
+    pub latency: Duration,
+}
+
+/// The chat API of an LLM provider.
+///
+/// This is a trait so that the providers can be replaced in tests.
+#[async_trait::async_trait]
+pub trait CodeGenerator: Send + Sync {
+    /// Asks a model to write the code described by a prompt.
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - The name of the model.
+    /// * `prompt` - The description of the code to write.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ModelResponse)` - The answer of the model.
+    /// * `Err(AppError::RateLimited)` - If the provider refused the request because of
+    ///   its rate limits.
+    /// * `Err(AppError::Provider)` - If the provider cannot be reached or fails.
+    async fn generate(&self, model: &str, prompt: &str) -> Result<ModelResponse, AppError>;
+}
+
+/// The HTTP client and endpoint shared by the provider clients.
+struct ChatClient {
+    /// The name of the provider, used in error messages.
+    name: &'static str,
+    http: reqwest::Client,
+    base_url: String,
+}
+
+impl ChatClient {
+    fn new(name: &'static str, base_url: String) -> anyhow::Result<Self> {
+        let http = reqwest::Client::builder()
+            .user_agent(concat!("rust-ai-auditor/", env!("CARGO_PKG_VERSION")))
+            .timeout(Duration::from_secs(300))
+            .build()?;
+        Ok(ChatClient {
+            name,
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// Sends a request and decodes the JSON answer.
+    async fn send<T: for<'de> Deserialize<'de>>(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<T, AppError> {
+        let response = request
+            .send()
+            .await
+            .map_err(|e| AppError::Provider(format!("{} request failed: {}", self.name, e)))?;
+
+        let status = response.status();
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = response
+                .headers()
+                .get(header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| format!(", retry after {} seconds", v))
+                .unwrap_or_default();
+            return Err(AppError::RateLimited(format!(
+                "{} rate limit exceeded{}",
+                self.name, retry_after
+            )));
+        }
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(AppError::Provider(format!(
+                "{} returned {}: {}",
+                self.name,
+                status,
+                body.trim()
+            )));
+        }
+        response
+            .json()
+            .await
+            .map_err(|e| AppError::Provider(format!("Invalid {} response: {}", self.name, e)))
+    }
+}
+
+/// The OpenAI chat completions API.
+pub struct OpenAiClient {
+    chat: ChatClient,
+    api_key: String,
+}
+
+impl OpenAiClient {
+    /// Creates a client authenticating with the given key.
+    ///
+    /// # Arguments
+    ///
+    /// * `base_url` - The base URL of the API (e.g. `https://api.openai.com/v1`).
+    /// * `api_key` - The API key.
+    pub fn new(base_url: String, api_key: String) -> anyhow::Result<Self> {
+        Ok(OpenAiClient {
+            chat: ChatClient::new("OpenAI", base_url)?,
+            api_key,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl CodeGenerator for OpenAiClient {
+    async fn generate(&self, model: &str, prompt: &str) -> Result<ModelResponse, AppError> {
+        #[derive(Deserialize)]
+        struct Completion {
+            choices: Vec<Choice>,
+            usage: Option<Usage>,
+        }
+        #[derive(Deserialize)]
+        struct Choice {
+            message: Message,
+        }
+        #[derive(Deserialize)]
+        struct Message {
+            content: Option<String>,
+        }
+        #[derive(Deserialize)]
+        struct Usage {
+            prompt_tokens: i32,
+            completion_tokens: i32,
+        }
+
+        let url = format!("{}/chat/completions", self.chat.base_url);
+        let completion: Completion = self
+            .chat
+            .send(
+                self.chat
+                    .http
+                    .post(url)
+                    .bearer_auth(&self.api_key)
+                    .json(&json!({
+                        "model": model,
+                        "messages": [
+                            { "role": "system", "content": SYSTEM_PROMPT },
+                            { "role": "user", "content": prompt },
+                        ],
+                    })),
+            )
+            .await?;
+
+        Ok(ModelResponse {
+            text: completion
+                .choices
+                .into_iter()
+                .next()
+                .and_then(|choice| choice.message.content)
+                .unwrap_or_default(),
+            prompt_tokens: completion.usage.as_ref().map(|u| u.prompt_tokens),
+            completion_tokens: completion.usage.as_ref().map(|u| u.completion_tokens),
+        })
+    }
+}
+
+/// The Anthropic messages API.
+pub struct AnthropicClient {
+    chat: ChatClient,
+    api_key: String,
+}
+
+impl AnthropicClient {
+    /// Creates a client authenticating with the given key.
+    ///
+    /// # Arguments
+    ///
+    /// * `base_url` - The base URL of the API (e.g. `https://api.anthropic.com`).
+    /// * `api_key` - The API key.
+    pub fn new(base_url: String, api_key: String) -> anyhow::Result<Self> {
+        Ok(AnthropicClient {
+            chat: ChatClient::new("Anthropic", base_url)?,
+            api_key,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl CodeGenerator for AnthropicClient {
+    async fn generate(&self, model: &str, prompt: &str) -> Result<ModelResponse, AppError> {
+        #[derive(Deserialize)]
+        struct Message {
+            content: Vec<ContentBlock>,
+            usage: Option<Usage>,
+        }
+        #[derive(Deserialize)]
+        struct ContentBlock {
+            #[serde(rename = "type")]
+            kind: String,
+            #[serde(default)]
+            text: String,
+        }
+        #[derive(Deserialize)]
+        struct Usage {
+            input_tokens: i32,
+            output_tokens: i32,
+        }
+
+        let url = format!("{}/v1/messages", self.chat.base_url);
+        let message: Message = self
+            .chat
+            .send(
+                self.chat
+                    .http
+                    .post(url)
+                    .header("x-api-key", &self.api_key)
+                    .header("anthropic-version", ANTHROPIC_VERSION)
+                    .json(&json!({
+                        "model": model,
+                        "max_tokens": ANTHROPIC_MAX_TOKENS,
+                        "system": SYSTEM_PROMPT,
+                        "messages": [{ "role": "user", "content": prompt }],
+                    })),
+            )
+            .await?;
+
+        Ok(ModelResponse {
+            text: message
+                .content
+                .into_iter()
+                .filter(|block| block.kind == "text")
+                .map(|block| block.text)
+                .collect(),
+            prompt_tokens: message.usage.as_ref().map(|u| u.input_tokens),
+            completion_tokens: message.usage.as_ref().map(|u| u.output_tokens),
+        })
+    }
+}
+
+/// The chat API of an Ollama server.
+pub struct OllamaClient {
+    chat: ChatClient,
+}
+
+impl OllamaClient {
+    /// Creates a client for the server at `base_url` (e.g. `http://localhost:11434`).
+    pub fn new(base_url: String) -> anyhow::Result<Self> {
+        Ok(OllamaClient {
+            chat: ChatClient::new("Ollama", base_url)?,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl CodeGenerator for OllamaClient {
+    async fn generate(&self, model: &str, prompt: &str) -> Result<ModelResponse, AppError> {
+        #[derive(Deserialize)]
+        struct Chat {
+            message: Message,
+            prompt_eval_count: Option<i32>,
+            eval_count: Option<i32>,
+        }
+        #[derive(Deserialize)]
+        struct Message {
+            content: String,
+        }
+
+        let url = format!("{}/api/chat", self.chat.base_url);
+        let chat: Chat = self
+            .chat
+            .send(self.chat.http.post(url).json(&json!({
+                "model": model,
+                "stream": false,
+                "messages": [
+                    { "role": "system", "content": SYSTEM_PROMPT },
+                    { "role": "user", "content": prompt },
+                ],
+            })))
+            .await?;
+
+        Ok(ModelResponse {
+            text: chat.message.content,
+            prompt_tokens: chat.prompt_eval_count,
+            completion_tokens: chat.eval_count,
+        })
+    }
+}
+
+/// The configured LLM providers.
+#[derive(Clone, Default)]
+pub struct CodeGenerators {
+    /// The OpenAI client, if configured.
+    pub openai: Option<Arc<dyn CodeGenerator>>,
+    /// The Anthropic client, if configured.
+    pub anthropic: Option<Arc<dyn CodeGenerator>>,
+    /// The Ollama client, if configured.
+    pub ollama: Option<Arc<dyn CodeGenerator>>,
+}
+
+impl CodeGenerators {
+    /// Builds the provider clients configured in the environment.
+    ///
+    /// * `OPENAI_API_KEY` - Enables OpenAI.
+    /// * `OPENAI_API_URL` - The base URL of the OpenAI API (defaults to
+    ///   `https://api.openai.com/v1`, override for compatible APIs).
+    /// * `ANTHROPIC_API_KEY` - Enables Anthropic.
+    /// * `ANTHROPIC_API_URL` - The base URL of the Anthropic API (defaults to
+    ///   `https://api.anthropic.com`).
+    /// * `OLLAMA_URL` - Enables Ollama, served at this URL (e.g. `http://localhost:11434`).
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(CodeGenerators)` - The clients, without any provider if none is configured.
+    /// * `Err(anyhow::Error)` - If a client cannot be built.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let mut generators = CodeGenerators::default();
+
+        if let Some(api_key) = var("OPENAI_API_KEY") {
+            let url =
+                var("OPENAI_API_URL").unwrap_or_else(|| "https://api.openai.com/v1".to_string());
+            generators.openai = Some(Arc::new(OpenAiClient::new(url, api_key)?));
+        }
+        if let Some(api_key) = var("ANTHROPIC_API_KEY") {
+            let url =
+                var("ANTHROPIC_API_URL").unwrap_or_else(|| "https://api.anthropic.com".to_string());
+            generators.anthropic = Some(Arc::new(AnthropicClient::new(url, api_key)?));
+        }
+        if let Some(url) = var("OLLAMA_URL") {
+            generators.ollama = Some(Arc::new(OllamaClient::new(url)?));
+        }
+
+        tracing::info!(
+            openai = generators.openai.is_some(),
+            anthropic = generators.anthropic.is_some(),
+            ollama = generators.ollama.is_some(),
+            "Code generation providers configured"
+        );
+        Ok(generators)
+    }
+
+    /// Returns the client of a provider.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(&dyn CodeGenerator)` - The client.
+    /// * `Err(AppError::Validation)` - If the provider is not configured on this server.
+    pub fn get(&self, provider: Provider) -> Result<&dyn CodeGenerator, AppError> {
+        let generator = match provider {
+            Provider::OpenAi => &self.openai,
+            Provider::Anthropic => &self.anthropic,
+            Provider::Ollama => &self.ollama,
+        };
+        generator.as_deref().ok_or_else(|| {
+            AppError::Validation(format!(
+                "The {} provider is not configured on this server",
+                provider.as_str()
+            ))
+        })
+    }
+
+    /// Asks a model to write the code described by a prompt and extracts it from the answer.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The provider and model generating the code.
+    /// * `prompt` - The description of the code to write.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(GeneratedCode)` - The generated code.
+    /// * `Err(AppError::Validation)` - If the provider is not configured or the model is empty.
+    /// * `Err(AppError::RateLimited)` - If the provider refused the request because of
+    ///   its rate limits.
+    /// * `Err(AppError::Provider)` - If the provider cannot be reached or fails.
+    /// * `Err(AppError::MissingCodeBlock)` - If the answer contains no code block.
+    pub async fn generate_code(
+        &self,
+        options: &GenerateOptions,
+        prompt: &str,
+    ) -> Result<GeneratedCode, AppError> {
+        let generator = self.get(options.provider)?;
+        if options.model.trim().is_empty() {
+            return Err(AppError::Validation(
+                "generate.model must not be empty".to_string(),
+            ));
+        }
+
+        let started = Instant::now();
+        let response = generator.generate(&options.model, prompt).await?;
+        let latency = started.elapsed();
+        tracing::info!(
+            provider = options.provider.as_str(),
+            model = %options.model,
+            ?latency,
+            prompt_tokens = response.prompt_tokens,
+            completion_tokens = response.completion_tokens,
+            "Code generated."
+        );
+
+        let code = extract_rust_code(&response.text).ok_or_else(|| {
+            AppError::MissingCodeBlock(format!(
+                "The answer of {} contains no Rust code block",
+                options.model
+            ))
+        })?;
+        Ok(GeneratedCode {
+            code,
+            response,
+            latency,
+        })
+    }
+}
+
+/// Builds the prompt asking a model to correct code that does not compile.
+///
+/// # Arguments
+///
+/// * `prompt` - The prompt the code was originally generated from.
+/// * `code` - The code that does not compile.
+/// * `compiler_output` - The errors reported by `rustc`, truncated if very long.
+///
+/// # Returns
+///
+/// * `String` - The prompt.
+pub fn fix_prompt(prompt: &str, code: &str, compiler_output: &str) -> String {
+    let errors: String = compiler_output.chars().take(MAX_FIX_ERROR_CHARS).collect();
+    format!(
+        "The following Rust code was written for this request:\n\n{}\n\n```rust\n{}\n```\n\n\
+         It does not compile. rustc reported:\n\n```text\n{}\n```\n\n\
+         Answer with the complete corrected code.",
+        prompt.trim(),
+        code.trim_end(),
+        errors.trim_end()
+    )
+}
+
+/// Extracts the Rust code from the answer of a model.
+///
+/// The first fenced code block tagged `rust` (or `rs`) is used. If there is none, the
+/// first untagged code block is used instead, since models sometimes omit the language.
+///
+/// # Arguments
+///
+/// * `answer` - The complete text of the answer.
+///
+/// # Returns
+///
+/// * `Some(String)` - The contents of the code block.
+/// * `None` - If the answer contains no Rust or untagged code block.
+pub fn extract_rust_code(answer: &str) -> Option<String> {
+    let mut untagged = None;
+    let mut lines = answer.lines();
+
+    while let Some(line) = lines.next() {
+        let Some(info) = line.trim_start().strip_prefix("```") else {
+            continue;
+        };
+        let language = info.trim().split([' ', ',']).next().unwrap_or_default();
+        let body: Vec<&str> = lines
+            .by_ref()
+            .take_while(|line| !line.trim_start().starts_with("```"))
+            .collect();
+        let code = body.join("\n");
+
+        match language {
+            "rust" | "rs" => return Some(code),
+            "" if untagged.is_none() => untagged = Some(code),
+            _ => {}
+        }
+    }
+    untagged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rust_code_blocks_are_extracted() {
+        let answer = "Here it is:\n\n```rust\npub fn one() -> u32 {\n    1\n}\n```\n\nEnjoy.";
+        assert_eq!(
+            extract_rust_code(answer).as_deref(),
+            Some("pub fn one() -> u32 {\n    1\n}")
+        );
+        assert_eq!(
+            extract_rust_code("```rs\npub fn two() {}\n```").as_deref(),
+            Some("pub fn two() {}")
+        );
+        assert_eq!(
+            extract_rust_code("```rust,ignore\npub fn three() {}\n```").as_deref(),
+            Some("pub fn three() {}")
+        );
+    }
+
+    #[test]
+    fn tagged_blocks_win_over_untagged_ones() {
+        let answer =
+            "```\ncargo add serde\n```\n\n```toml\n[package]\n```\n\n```rust\npub fn f() {}\n```";
+        assert_eq!(extract_rust_code(answer).as_deref(), Some("pub fn f() {}"));
+    }
+
+    #[test]
+    fn untagged_blocks_are_used_without_a_rust_block() {
+        let answer =
+            "```toml\n[package]\n```\n\n```\npub fn f() {}\n```\n\n```\npub fn g() {}\n```";
+        assert_eq!(extract_rust_code(answer).as_deref(), Some("pub fn f() {}"));
+    }
+
+    #[test]
+    fn answers_without_code_blocks_have_no_code() {
+        assert_eq!(extract_rust_code("pub fn f() {}"), None);
+        assert_eq!(extract_rust_code("```python\nprint(1)\n```"), None);
+        assert_eq!(extract_rust_code(""), None);
+    }
+
+    #[test]
+    fn fix_prompts_truncate_the_compiler_output() {
+        let output = "e".repeat(MAX_FIX_ERROR_CHARS + 10);
+        let prompt = fix_prompt(" Add numbers ", "pub fn f() {}\n", &output);
+        assert!(
+            prompt.starts_with(
+                "The following Rust code was written for this request:\n\nAdd numbers\n"
+            )
+        );
+        assert!(prompt.contains(&"e".repeat(MAX_FIX_ERROR_CHARS)));
+        assert!(!prompt.contains(&"e".repeat(MAX_FIX_ERROR_CHARS + 1)));
+    }
+}
@@ -0,0 +1,628 @@
+//! Integrates the auditor with GitHub pull requests.
+//!
+//! GitHub delivers `pull_request` webhooks to the server. The changed Rust files of the
+//! pull request are audited in the background, and a commit status summarizing the
+//! results is posted on the head commit.
+
+use crate::{
+    auditor::AuditPolicy,
+    error::AppError,
+    generation::CodeGenerators,
+    models::{AuditSource, CreateAuditRequest},
+    notifications::AuditNotifiers,
+    services,
+    workers::CompilationQueue,
+};
+use hmac::{Hmac, KeyInit, Mac};
+use reqwest::{StatusCode, header};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::PgPool;
+use std::{sync::Arc, time::Duration};
+
+/// The context under which commit statuses are reported on GitHub.
+const STATUS_CONTEXT: &str = "rust-ai-auditor";
+
+/// The pull request actions that trigger an audit.
+const AUDITED_ACTIONS: &[&str] = &["opened", "synchronize", "reopened"];
+
+/// The number of changed files requested per page from the GitHub API.
+const FILES_PER_PAGE: usize = 100;
+
+/// How many times a rate-limited GitHub API call is retried.
+const MAX_RATE_LIMIT_RETRIES: u32 = 3;
+
+/// The longest time to wait for a rate limit to reset before giving up.
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+
+/// A file changed by a pull request.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PullRequestFile {
+    /// The path of the file within the repository.
+    pub filename: String,
+    /// How the file was changed (`added`, `modified`, `removed`, ...).
+    pub status: String,
+}
+
+/// The state of a commit status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CommitState {
+    Pending,
+    Success,
+    Failure,
+    Error,
+}
+
+/// A commit status reported on GitHub.
+#[derive(Debug, Clone, Serialize)]
+pub struct CommitStatus {
+    /// The state of the status.
+    pub state: CommitState,
+    /// A short summary shown next to the status.
+    pub description: String,
+    /// The label distinguishing this status from those of other tools.
+    pub context: &'static str,
+}
+
+impl CommitStatus {
+    fn new(state: CommitState, description: impl Into<String>) -> Self {
+        CommitStatus {
+            state,
+            description: description.into(),
+            context: STATUS_CONTEXT,
+        }
+    }
+}
+
+/// The GitHub API calls made by the integration.
+///
+/// This is a trait so that the HTTP client can be replaced in tests.
+#[async_trait::async_trait]
+pub trait GitHubApi: Send + Sync {
+    /// Lists the files changed by a pull request.
+    async fn list_pull_request_files(
+        &self,
+        repository: &str,
+        number: i32,
+    ) -> Result<Vec<PullRequestFile>, AppError>;
+
+    /// Fetches the contents of a file at a given commit.
+    async fn get_file_contents(
+        &self,
+        repository: &str,
+        path: &str,
+        git_ref: &str,
+    ) -> Result<String, AppError>;
+
+    /// Creates a commit status on a commit.
+    async fn create_commit_status(
+        &self,
+        repository: &str,
+        sha: &str,
+        status: &CommitStatus,
+    ) -> Result<(), AppError>;
+}
+
+/// The GitHub REST API client.
+pub struct GitHubClient {
+    http: reqwest::Client,
+    api_url: String,
+    token: String,
+}
+
+impl GitHubClient {
+    /// Creates a client authenticating with the given token.
+    ///
+    /// # Arguments
+    ///
+    /// * `api_url` - The base URL of the API (e.g. `https://api.github.com`).
+    /// * `token` - A token allowed to read contents and write commit statuses.
+    pub fn new(api_url: String, token: String) -> anyhow::Result<Self> {
+        let http = reqwest::Client::builder()
+            .user_agent(concat!("rust-ai-auditor/", env!("CARGO_PKG_VERSION")))
+            .timeout(Duration::from_secs(30))
+            .build()?;
+        Ok(GitHubClient {
+            http,
+            api_url: api_url.trim_end_matches('/').to_string(),
+            token,
+        })
+    }
+
+    /// Sends a request built by `build`, waiting and retrying while the API reports
+    /// that the rate limit is exceeded.
+    async fn send(
+        &self,
+        build: impl Fn() -> reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, AppError> {
+        let mut attempt = 0;
+        loop {
+            let response = build()
+                .bearer_auth(&self.token)
+                .header("X-GitHub-Api-Version", "2022-11-28")
+                .send()
+                .await
+                .map_err(|e| AppError::Upstream(format!("GitHub API request failed: {}", e)))?;
+
+            match rate_limit_wait(&response) {
+                Some(wait) if attempt < MAX_RATE_LIMIT_RETRIES && wait <= MAX_RATE_LIMIT_WAIT => {
+                    attempt += 1;
+                    tracing::warn!(?wait, attempt, "GitHub API rate limit exceeded, retrying.");
+                    tokio::time::sleep(wait).await;
+                }
+                Some(_) => {
+                    return Err(AppError::Upstream(
+                        "GitHub API rate limit exceeded".to_string(),
+                    ));
+                }
+                None if response.status().is_success() => return Ok(response),
+                None => {
+                    return Err(AppError::Upstream(format!(
+                        "GitHub API returned {}",
+                        response.status()
+                    )));
+                }
+            }
+        }
+    }
+}
+
+/// Returns how long to wait before retrying a rate-limited response.
+///
+/// GitHub signals primary rate limits with `x-ratelimit-remaining: 0` and the reset time
+/// in `x-ratelimit-reset`, and secondary rate limits with `retry-after`.
+///
+/// # Returns
+///
+/// * `Some(Duration)` - If the response is rate-limited.
+/// * `None` - If the response is not rate-limited.
+fn rate_limit_wait(response: &reqwest::Response) -> Option<Duration> {
+    if !matches!(
+        response.status(),
+        StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS
+    ) {
+        return None;
+    }
+    let header_u64 = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok())
+    };
+
+    if let Some(seconds) = header_u64(header::RETRY_AFTER.as_str()) {
+        return Some(Duration::from_secs(seconds));
+    }
+    if header_u64("x-ratelimit-remaining") == Some(0) {
+        let reset = header_u64("x-ratelimit-reset").unwrap_or(0);
+        let now = chrono::Utc::now().timestamp().max(0) as u64;
+        return Some(Duration::from_secs(reset.saturating_sub(now).max(1)));
+    }
+    // A 429 without any hint is still a rate limit.
+    (response.status() == StatusCode::TOO_MANY_REQUESTS).then_some(Duration::from_secs(1))
+}
+
+#[async_trait::async_trait]
+impl GitHubApi for GitHubClient {
+    async fn list_pull_request_files(
+        &self,
+        repository: &str,
+        number: i32,
+    ) -> Result<Vec<PullRequestFile>, AppError> {
+        let url = format!(
+            "{}/repos/{}/pulls/{}/files",
+            self.api_url, repository, number
+        );
+        let mut files = Vec::new();
+        for page in 1.. {
+            let batch: Vec<PullRequestFile> = self
+                .send(|| {
+                    self.http
+                        .get(&url)
+                        .query(&[("per_page", FILES_PER_PAGE), ("page", page)])
+                })
+                .await?
+                .json()
+                .await
+                .map_err(|e| {
+                    AppError::Upstream(format!("Invalid pull request files response: {}", e))
+                })?;
+            let last_page = batch.len() < FILES_PER_PAGE;
+            files.extend(batch);
+            if last_page {
+                break;
+            }
+        }
+        Ok(files)
+    }
+
+    async fn get_file_contents(
+        &self,
+        repository: &str,
+        path: &str,
+        git_ref: &str,
+    ) -> Result<String, AppError> {
+        let url = format!("{}/repos/{}/contents/{}", self.api_url, repository, path);
+        self.send(|| {
+            self.http
+                .get(&url)
+                .query(&[("ref", git_ref)])
+                .header(header::ACCEPT, "application/vnd.github.raw+json")
+        })
+        .await?
+        .text()
+        .await
+        .map_err(|e| AppError::Upstream(format!("Failed to read {}: {}", path, e)))
+    }
+
+    async fn create_commit_status(
+        &self,
+        repository: &str,
+        sha: &str,
+        status: &CommitStatus,
+    ) -> Result<(), AppError> {
+        let url = format!("{}/repos/{}/statuses/{}", self.api_url, repository, sha);
+        self.send(|| self.http.post(&url).json(status)).await?;
+        Ok(())
+    }
+}
+
+/// The configured GitHub integration.
+pub struct GitHubIntegration {
+    /// The secret GitHub signs webhook deliveries with.
+    webhook_secret: String,
+    /// The GitHub API client.
+    pub api: Arc<dyn GitHubApi>,
+}
+
+impl GitHubIntegration {
+    /// Builds the integration from the environment.
+    ///
+    /// * `GITHUB_WEBHOOK_SECRET` - The secret configured on the GitHub webhook.
+    /// * `GITHUB_TOKEN` - A token allowed to read contents and write commit statuses.
+    /// * `GITHUB_API_URL` - The base URL of the API (defaults to `https://api.github.com`,
+    ///   override for GitHub Enterprise Server).
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(GitHubIntegration))` - If the secret and token are set.
+    /// * `Ok(None)` - If neither is set, disabling the integration.
+    /// * `Err(anyhow::Error)` - If only one of them is set or the client cannot be built.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let secret = std::env::var("GITHUB_WEBHOOK_SECRET")
+            .ok()
+            .filter(|v| !v.is_empty());
+        let token = std::env::var("GITHUB_TOKEN").ok().filter(|v| !v.is_empty());
+
+        let (webhook_secret, token) = match (secret, token) {
+            (Some(secret), Some(token)) => (secret, token),
+            (None, None) => return Ok(None),
+            (Some(_), None) => {
+                anyhow::bail!("GITHUB_WEBHOOK_SECRET is set but GITHUB_TOKEN is not")
+            }
+            (None, Some(_)) => {
+                anyhow::bail!("GITHUB_TOKEN is set but GITHUB_WEBHOOK_SECRET is not")
+            }
+        };
+        let api_url = std::env::var("GITHUB_API_URL")
+            .unwrap_or_else(|_| "https://api.github.com".to_string());
+
+        tracing::info!(api_url = %api_url, "GitHub integration enabled");
+        Ok(Some(GitHubIntegration {
+            webhook_secret,
+            api: Arc::new(GitHubClient::new(api_url, token)?),
+        }))
+    }
+
+    /// Verifies the `X-Hub-Signature-256` header of a webhook delivery.
+    ///
+    /// # Arguments
+    ///
+    /// * `body` - The raw request body.
+    /// * `signature` - The header value, formatted as `sha256=<hex digest>`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the body was signed with the webhook secret.
+    /// * `Err(AppError::Unauthorized)` - If the signature is missing or does not match.
+    pub fn verify_signature(&self, body: &[u8], signature: Option<&str>) -> Result<(), AppError> {
+        let digest = signature
+            .and_then(|s| s.strip_prefix("sha256="))
+            .and_then(|s| hex::decode(s).ok())
+            .ok_or_else(|| AppError::Unauthorized("Missing webhook signature".to_string()))?;
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.webhook_secret.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(body);
+        mac.verify_slice(&digest)
+            .map_err(|_| AppError::Unauthorized("Invalid webhook signature".to_string()))
+    }
+}
+
+/// The parts of a `pull_request` webhook payload used by the integration.
+#[derive(Debug, Deserialize)]
+pub struct PullRequestEvent {
+    pub action: String,
+    pub number: i32,
+    pub pull_request: PullRequest,
+    pub repository: Repository,
+}
+
+/// The pull request of a `pull_request` webhook payload.
+#[derive(Debug, Deserialize)]
+pub struct PullRequest {
+    pub head: CommitRef,
+}
+
+/// A commit reference of a pull request.
+#[derive(Debug, Deserialize)]
+pub struct CommitRef {
+    pub sha: String,
+}
+
+/// The repository of a webhook payload.
+#[derive(Debug, Deserialize)]
+pub struct Repository {
+    pub full_name: String,
+}
+
+impl PullRequestEvent {
+    /// Whether this event changes the code of the pull request and should be audited.
+    pub fn should_audit(&self) -> bool {
+        AUDITED_ACTIONS.contains(&self.action.as_str())
+    }
+}
+
+/// Audits the Rust files changed by a pull request and reports the result on its head
+/// commit.
+///
+/// Failures are logged and, where possible, reported as an `error` commit status; this
+/// function is meant to run in a background task.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `policy` - The server-wide audit settings.
+/// * `compiler` - The queue of the compilation workers.
+/// * `notifiers` - The notifiers told about the new audits.
+/// * `api` - The GitHub API client.
+/// * `event` - The webhook payload.
+#[tracing::instrument(skip_all, fields(repository = %event.repository.full_name, pull_request = event.number))]
+pub async fn audit_pull_request(
+    pool: &PgPool,
+    policy: &AuditPolicy,
+    compiler: &CompilationQueue,
+    notifiers: &AuditNotifiers,
+    api: &dyn GitHubApi,
+    event: &PullRequestEvent,
+) {
+    let repository = &event.repository.full_name;
+    let sha = &event.pull_request.head.sha;
+
+    let pending = CommitStatus::new(CommitState::Pending, "Auditing changed Rust files");
+    if let Err(e) = api.create_commit_status(repository, sha, &pending).await {
+        tracing::warn!(error = %e, "Could not report pending status.");
+    }
+
+    let status = match audit_changed_files(pool, policy, compiler, notifiers, api, event).await {
+        Ok((0, 0)) => CommitStatus::new(CommitState::Success, "No Rust files changed"),
+        Ok((passed, 0)) => CommitStatus::new(
+            CommitState::Success,
+            format!("All {} Rust files passed the audit", passed),
+        ),
+        Ok((passed, failed)) => CommitStatus::new(
+            CommitState::Failure,
+            format!(
+                "{} of {} Rust files failed the audit",
+                failed,
+                passed + failed
+            ),
+        ),
+        Err(e) => {
+            tracing::error!(error = %e, "Pull request audit failed.");
+            CommitStatus::new(CommitState::Error, "The audit could not be completed")
+        }
+    };
+
+    tracing::info!(state = ?status.state, description = %status.description, "Pull request audited.");
+    if let Err(e) = api.create_commit_status(repository, sha, &status).await {
+        tracing::error!(error = %e, "Could not report audit status.");
+    }
+}
+
+/// Creates one audit per Rust file changed by a pull request.
+///
+/// # Returns
+///
+/// * `Ok((usize, usize))` - The number of files that passed and failed the audit.
+/// * `Err(AppError)` - If a GitHub API call or a database query fails.
+async fn audit_changed_files(
+    pool: &PgPool,
+    policy: &AuditPolicy,
+    compiler: &CompilationQueue,
+    notifiers: &AuditNotifiers,
+    api: &dyn GitHubApi,
+    event: &PullRequestEvent,
+) -> Result<(usize, usize), AppError> {
+    let repository = &event.repository.full_name;
+    let files = api
+        .list_pull_request_files(repository, event.number)
+        .await?;
+
+    let (mut passed, mut failed) = (0, 0);
+    for file in files
+        .iter()
+        .filter(|f| f.filename.ends_with(".rs") && f.status != "removed")
+    {
+        let code = api
+            .get_file_contents(repository, &file.filename, &event.pull_request.head.sha)
+            .await?;
+        let input = CreateAuditRequest {
+            prompt: format!("{}#{}: {}", repository, event.number, file.filename),
+            generated_code: code,
+            files: Vec::new(),
+            generate: None,
+            auto_fix: None,
+            idempotency_key: None,
+            strict: None,
+            tags: Vec::new(),
+            channel: None,
+            opt_level: None,
+            edition: None,
+            target: None,
+            profile: None,
+            background: false,
+            bypass_cache: false,
+        };
+        let source = AuditSource {
+            repository: repository.clone(),
+            pull_request: event.number,
+            path: file.filename.clone(),
+        };
+        // The code is taken from the pull request, never generated.
+        let audit = services::create_audit(
+            pool,
+            policy,
+            compiler,
+            &CodeGenerators::default(),
+            &input,
+            Some(&source),
+            notifiers,
+        )
+        .await?;
+        if audit.is_valid {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+    Ok((passed, failed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, extract::State, http::HeaderMap, response::IntoResponse, routing::get};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A `pull_request` delivery recorded from GitHub, and the signature it came with.
+    const DELIVERY: &str = include_str!("../tests/fixtures/github/pull_request_opened.json");
+    const DELIVERY_SIGNATURE: &str =
+        "sha256=5a126ab5ac8701176b2b6a4122d15c9551bd133ca23d39378688dafcc39c1d63";
+    const WEBHOOK_SECRET: &str = "It's a Secret to Everybody";
+
+    /// The files of the pull request of the delivery, as listed by the API.
+    const FILES: &str = include_str!("../tests/fixtures/github/pull_request_files.json");
+
+    fn integration() -> GitHubIntegration {
+        GitHubIntegration {
+            webhook_secret: WEBHOOK_SECRET.to_string(),
+            api: Arc::new(GitHubClient::new("http://localhost".into(), "token".into()).unwrap()),
+        }
+    }
+
+    /// Serves the pull request files after answering the first `rate_limited` requests
+    /// with `rate_limit_headers`, and returns the client of this fake API with the number
+    /// of requests it received.
+    async fn fake_api(
+        rate_limited: usize,
+        rate_limit_headers: HeaderMap,
+    ) -> (GitHubClient, Arc<AtomicUsize>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let app = Router::new()
+            .route(
+                "/repos/octocat/hello-world/pulls/42/files",
+                get(move |State(requests): State<Arc<AtomicUsize>>| async move {
+                    if requests.fetch_add(1, Ordering::SeqCst) < rate_limited {
+                        (StatusCode::FORBIDDEN, rate_limit_headers, "rate limited").into_response()
+                    } else {
+                        ([(header::CONTENT_TYPE, "application/json")], FILES).into_response()
+                    }
+                }),
+            )
+            .with_state(requests.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (GitHubClient::new(url, "token".into()).unwrap(), requests)
+    }
+
+    #[test]
+    fn recorded_delivery_signature_is_accepted() {
+        assert!(
+            integration()
+                .verify_signature(DELIVERY.as_bytes(), Some(DELIVERY_SIGNATURE))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn signature_mismatch_is_rejected() {
+        let tampered = DELIVERY.replace("\"opened\"", "\"closed\"");
+        let error = integration()
+            .verify_signature(tampered.as_bytes(), Some(DELIVERY_SIGNATURE))
+            .unwrap_err();
+        assert!(
+            matches!(error, AppError::Unauthorized(message) if message == "Invalid webhook signature")
+        );
+
+        let error = integration()
+            .verify_signature(DELIVERY.as_bytes(), None)
+            .unwrap_err();
+        assert!(
+            matches!(error, AppError::Unauthorized(message) if message == "Missing webhook signature")
+        );
+    }
+
+    #[test]
+    fn recorded_delivery_is_audited() {
+        let event: PullRequestEvent = serde_json::from_str(DELIVERY).unwrap();
+        assert!(event.should_audit());
+        assert_eq!(event.number, 42);
+        assert_eq!(event.repository.full_name, "octocat/hello-world");
+        assert_eq!(
+            event.pull_request.head.sha,
+            "6dcb09b5b57875f334f61aebed695e2e4193db5e"
+        );
+    }
+
+    #[tokio::test]
+    async fn rate_limited_requests_are_retried_after_the_reset() {
+        let reset = chrono::Utc::now().timestamp() + 1;
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-remaining", "0".parse().unwrap());
+        headers.insert("x-ratelimit-reset", reset.to_string().parse().unwrap());
+        let (client, requests) = fake_api(1, headers).await;
+
+        let files = client
+            .list_pull_request_files("octocat/hello-world", 42)
+            .await
+            .unwrap();
+
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        let names: Vec<&str> = files.iter().map(|f| f.filename.as_str()).collect();
+        assert_eq!(
+            names,
+            ["src/config.rs", "src/broken.rs", "README.md", "src/old.rs"]
+        );
+    }
+
+    #[tokio::test]
+    async fn rate_limits_resetting_too_late_fail_without_waiting() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RETRY_AFTER, "3600".parse().unwrap());
+        let (client, requests) = fake_api(usize::MAX, headers).await;
+
+        let error = client
+            .list_pull_request_files("octocat/hello-world", 42)
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(error, AppError::Upstream(message) if message == "GitHub API rate limit exceeded")
+        );
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+}
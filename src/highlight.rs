@@ -0,0 +1,297 @@
+//! Renders the code of an audit as syntax-highlighted HTML.
+//!
+//! Lines with findings or compiler diagnostics are annotated with a marker whose tooltip
+//! lists their messages. Highlighting is expensive for large files, so the rendered HTML
+//! is cached in memory, keyed by the audit and the time it was last updated.
+
+use crate::{
+    error::AppError,
+    models::{AiAudit, Diagnostic, Severity},
+};
+use chrono::{DateTime, Utc};
+use lru::LruCache;
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    num::NonZeroUsize,
+    sync::{Arc, LazyLock, Mutex},
+};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{Theme, ThemeSet},
+    html::{IncludeBackground, styled_line_to_highlighted_html},
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
+use uuid::Uuid;
+
+/// The number of rendered audits kept in memory.
+const CACHE_CAPACITY: NonZeroUsize = NonZeroUsize::new(200).unwrap();
+
+/// The number of lines rendered before the rest of the code is left out.
+const MAX_LINES: usize = 5_000;
+
+/// The number of bytes of code rendered before the rest of the code is left out.
+const MAX_BYTES: usize = 256 * 1024;
+
+/// The syntax definitions, loaded once on first use.
+static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+
+/// The color theme of the highlighted code, loaded once on first use.
+static THEME: LazyLock<Theme> = LazyLock::new(|| {
+    ThemeSet::load_defaults()
+        .themes
+        .remove("InspiredGitHub")
+        .unwrap_or_default()
+});
+
+/// The stylesheet of the standalone page, styling the line numbers and markers.
+const STYLE: &str = "body{margin:0;background:#fff}\
+pre.code{margin:0;padding:1em 0;font:13px/1.5 monospace;counter-reset:line}\
+.line{display:block;padding-right:1em}\
+.line::before{counter-increment:line;content:counter(line);display:inline-block;width:4em;padding-right:1em;text-align:right;color:#999}\
+.line.annotated{background:#fff5f5}\
+.marker{cursor:help;margin-right:.5em}\
+.marker.error{color:#c00}.marker.warning{color:#b80}.marker.info{color:#06c}\
+.truncated{display:block;padding:.5em 1em;color:#666;font-style:italic}";
+
+/// Renders code as a highlighted `<pre>` fragment.
+///
+/// Each line is wrapped in a `<span class="line" id="L<n>">`. Lines with annotations get a
+/// marker whose `title` holds their messages, one per line. All code and messages are
+/// HTML-escaped. Only the first [`MAX_LINES`] lines and [`MAX_BYTES`] bytes are rendered,
+/// followed by a notice if the rest was left out.
+///
+/// # Arguments
+///
+/// * `code` - The Rust code to render.
+/// * `annotations` - The messages to attach to each 1-based line, with the most severe
+///   level among them (`error`, `warning` or `info`).
+///
+/// # Returns
+///
+/// The HTML fragment.
+pub fn render_fragment(code: &str, annotations: &BTreeMap<u32, (&str, Vec<String>)>) -> String {
+    let syntax = SYNTAXES
+        .find_syntax_by_extension("rs")
+        .unwrap_or_else(|| SYNTAXES.find_syntax_plain_text());
+    let mut highlighter = HighlightLines::new(syntax, &THEME);
+
+    let mut html = String::from("<pre class=\"code\">");
+    let mut rendered_lines = 0;
+    let mut rendered_bytes = 0;
+    let mut truncated = false;
+    for (index, line) in LinesWithEndings::from(code).enumerate() {
+        if index == MAX_LINES || rendered_bytes + line.len() > MAX_BYTES {
+            truncated = true;
+            break;
+        }
+        rendered_lines += 1;
+        rendered_bytes += line.len();
+        let number = index + 1;
+        let content = line.trim_end_matches(['\n', '\r']);
+        // Fall back to the plain escaped line if the highlighter gives up on it.
+        let highlighted = highlighter
+            .highlight_line(line, &SYNTAXES)
+            .and_then(|regions| styled_line_to_highlighted_html(&regions, IncludeBackground::No))
+            // The line break is inside the last span; the `.line` spans already break lines.
+            .map(|html| html.replace(['\n', '\r'], ""))
+            .unwrap_or_else(|_| escape(content));
+
+        match u32::try_from(number)
+            .ok()
+            .and_then(|number| annotations.get(&number))
+        {
+            Some((level, messages)) => {
+                let _ = write!(
+                    html,
+                    "<span class=\"line annotated\" id=\"L{number}\"><span class=\"marker {level}\" title=\"{title}\">&#9679;</span>{highlighted}</span>",
+                    level = escape(level),
+                    title = escape(&messages.join("\n")),
+                );
+            }
+            None => {
+                let _ = write!(
+                    html,
+                    "<span class=\"line\" id=\"L{number}\">{highlighted}</span>"
+                );
+            }
+        }
+    }
+    if truncated {
+        let _ = write!(
+            html,
+            "<span class=\"truncated\">The code was truncated after {} of {} lines.</span>",
+            rendered_lines,
+            code.lines().count()
+        );
+    }
+    html.push_str("</pre>");
+    html
+}
+
+/// Groups the findings and diagnostics of an audit by line.
+///
+/// Findings and diagnostics without a line are left out, as they cannot be shown next to
+/// the code.
+///
+/// # Arguments
+///
+/// * `audit` - The audit whose findings are grouped.
+/// * `diagnostics` - The compiler diagnostics of the audit.
+///
+/// # Returns
+///
+/// The messages of each line, with the most severe level among them.
+pub fn annotations<'a>(
+    audit: &'a AiAudit,
+    diagnostics: &'a [Diagnostic],
+) -> BTreeMap<u32, (&'a str, Vec<String>)> {
+    let mut annotations: BTreeMap<u32, (&str, Vec<String>)> = BTreeMap::new();
+    let mut annotate = |line: u32, level: &'a str, message: String| {
+        let entry = annotations.entry(line).or_insert((level, Vec::new()));
+        if rank(level) > rank(entry.0) {
+            entry.0 = level;
+        }
+        entry.1.push(message);
+    };
+    for finding in &audit.findings {
+        if let Some(line) = finding.line {
+            let level = match finding.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+                Severity::Info => "info",
+            };
+            annotate(
+                line,
+                level,
+                format!("{}: {}", finding.code, finding.message),
+            );
+        }
+    }
+    for diagnostic in diagnostics {
+        if let Some(line) = diagnostic.line {
+            let level = match diagnostic.level.as_str() {
+                "error" | "error: internal compiler error" => "error",
+                "warning" => "warning",
+                _ => "info",
+            };
+            let message = match &diagnostic.code {
+                Some(code) => format!("{}[{}]: {}", diagnostic.level, code, diagnostic.message),
+                None => format!("{}: {}", diagnostic.level, diagnostic.message),
+            };
+            annotate(line, level, message);
+        }
+    }
+    annotations
+}
+
+/// Wraps a fragment rendered by [`render_fragment`] in a standalone HTML page.
+///
+/// # Arguments
+///
+/// * `id` - The UUID of the audit, used as the page title.
+/// * `fragment` - The highlighted code.
+///
+/// # Returns
+///
+/// The HTML page.
+pub fn render_page(id: Uuid, fragment: &str) -> String {
+    format!(
+        "<!DOCTYPE html><html lang=\"en\"><head><meta charset=\"utf-8\"><title>audit-{id}.rs</title><style>{STYLE}</style></head><body>{fragment}</body></html>"
+    )
+}
+
+/// Orders annotation levels by severity.
+fn rank(level: &str) -> u8 {
+    match level {
+        "error" => 2,
+        "warning" => 1,
+        _ => 0,
+    }
+}
+
+/// Escapes text for use in HTML content and attributes.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Identifies a rendering by the audit and the time it was last updated.
+type RenderKey = (Uuid, DateTime<Utc>);
+
+/// Caches the highlighted code of audits, keyed by audit and last update.
+///
+/// An audit that is updated (e.g. recompiled) gets a new key, so stale renderings are
+/// never served; they are evicted as the cache fills up.
+pub struct HighlightCache {
+    /// The rendered fragments, by audit and last update.
+    entries: Mutex<LruCache<RenderKey, Arc<str>>>,
+}
+
+impl Default for HighlightCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HighlightCache {
+    /// Creates an empty cache.
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(LruCache::new(CACHE_CAPACITY)),
+        }
+    }
+
+    /// Returns the cached fragment of an audit, if it was rendered since its last update.
+    ///
+    /// # Arguments
+    ///
+    /// * `audit` - The audit whose fragment is looked up.
+    ///
+    /// # Returns
+    ///
+    /// The cached fragment, or `None` if it has to be rendered.
+    pub fn get(&self, audit: &AiAudit) -> Option<Arc<str>> {
+        self.lock_entries()
+            .get(&(audit.id, audit.updated_at))
+            .cloned()
+    }
+
+    /// Renders the fragment of an audit on the blocking thread pool and caches it.
+    ///
+    /// # Arguments
+    ///
+    /// * `audit` - The audit to render.
+    /// * `diagnostics` - The compiler diagnostics of the audit.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Arc<str>)` - On success, returns the HTML fragment.
+    /// * `Err(AppError)` - If the rendering task panicked.
+    pub async fn render(
+        &self,
+        audit: &AiAudit,
+        diagnostics: Vec<Diagnostic>,
+    ) -> Result<Arc<str>, AppError> {
+        let key = (audit.id, audit.updated_at);
+        let audit = audit.clone();
+        let fragment: Arc<str> = tokio::task::spawn_blocking(move || {
+            render_fragment(&audit.generated_code, &annotations(&audit, &diagnostics))
+        })
+        .await
+        .map_err(|e| AppError::Audit(format!("Highlighting failed: {}", e)))?
+        .into();
+        self.lock_entries().put(key, fragment.clone());
+        Ok(fragment)
+    }
+
+    /// Locks the cached fragments.
+    fn lock_entries(&self) -> std::sync::MutexGuard<'_, LruCache<RenderKey, Arc<str>>> {
+        // The cache holds no invariants that a panic could break, so recover from poisoning.
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
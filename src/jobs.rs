@@ -0,0 +1,312 @@
+//! Workers running the audits submitted in the background.
+//!
+//! Audits created with `background` are stored with the `pending` status along with a
+//! row of the `audit_jobs` table. The workers of every replica of the server poll that
+//! table, claim jobs with `FOR UPDATE SKIP LOCKED` so that no job is run twice at the
+//! same time, compile the audits through the compilation queue and store their verdict.
+//! Failed attempts are retried with an exponential backoff, until the job is
+//! dead-lettered.
+//!
+//! While a job runs, the compiler output is published in batches to the clients following
+//! the progress of its audit (see `services::audit_progress`).
+//!
+//! A job can be cancelled until its audit gets its verdict (see `services::cancel_audit`).
+//! The worker running it on the same replica stops waiting for the compilation right
+//! away; a worker of another replica drops the result once the compilation ends.
+
+use crate::{
+    auditor::{AuditPolicy, CompilerOutput},
+    models::{AuditJob, AuditProgress, JobState},
+    notifications::AuditNotifiers,
+    services,
+    workers::{CompilationQueue, ShutdownFlag},
+};
+use sqlx::PgPool;
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+use tokio::sync::{broadcast, mpsc};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+/// The number of job workers used when `AUDIT_JOB_WORKERS` is not set.
+pub const DEFAULT_WORKERS: usize = 2;
+
+/// The number of attempts of a job when `AUDIT_JOB_MAX_ATTEMPTS` is not set.
+pub const DEFAULT_MAX_ATTEMPTS: i32 = 5;
+
+/// How long an idle worker waits before looking for a due job again.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a worker may run a job before the workers of other replicas may claim it.
+const LEASE: Duration = Duration::from_secs(600);
+
+/// How often the compiler output of a running job is published to the clients following
+/// its progress.
+const PROGRESS_BATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The number of progress events kept for a slow client before it misses some.
+const PROGRESS_CAPACITY: usize = 64;
+
+/// The delay before the second attempt of a job, doubled before each of the next ones.
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(10);
+
+/// The longest delay between two attempts of a job.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(3600);
+
+/// What the job workers need to run audits.
+#[derive(Clone)]
+pub struct JobContext {
+    /// The pool of the primary database, holding the queue.
+    pub pool: PgPool,
+    /// The server-wide audit settings the verdicts are given under.
+    pub policy: AuditPolicy,
+    /// The queue of the compilation workers.
+    pub compiler: CompilationQueue,
+    /// The notifiers told about the audits once they have their verdict.
+    pub notifiers: AuditNotifiers,
+    /// The number of attempts after which a job is dead-lettered.
+    pub max_attempts: i32,
+    /// The tokens cancelling the jobs running on this replica.
+    pub cancellation_tokens: CancellationTokens,
+    /// The channels publishing the progress of the jobs running on this replica.
+    pub progress: ProgressChannels,
+}
+
+/// The channels publishing the progress of the jobs run by the workers of this replica,
+/// keyed by the ID of their audit.
+#[derive(Debug, Clone, Default)]
+pub struct ProgressChannels(Arc<Mutex<HashMap<Uuid, broadcast::Sender<AuditProgress>>>>);
+
+impl ProgressChannels {
+    /// Follows the progress of the job of an audit, if a worker of this replica is
+    /// running it.
+    pub fn subscribe(&self, audit_id: Uuid) -> Option<broadcast::Receiver<AuditProgress>> {
+        self.lock().get(&audit_id).map(broadcast::Sender::subscribe)
+    }
+
+    /// Returns the channel publishing the progress of the job of an audit, registered
+    /// until `close`.
+    fn open(&self, audit_id: Uuid) -> broadcast::Sender<AuditProgress> {
+        self.lock()
+            .entry(audit_id)
+            .or_insert_with(|| broadcast::channel(PROGRESS_CAPACITY).0)
+            .clone()
+    }
+
+    /// Forgets the channel of the job of an audit once the job stopped running, which
+    /// closes the subscriptions.
+    fn close(&self, audit_id: Uuid) {
+        self.lock().remove(&audit_id);
+    }
+
+    /// Locks the channels.
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, broadcast::Sender<AuditProgress>>> {
+        // The map holds no invariants that a panic could break, so recover from poisoning.
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The tokens cancelling the jobs run by the workers of this replica, keyed by the ID of
+/// their audit.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationTokens(Arc<Mutex<HashMap<Uuid, CancellationToken>>>);
+
+impl CancellationTokens {
+    /// Cancels the job of an audit, if a worker of this replica is running it.
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - Whether a running job was cancelled.
+    pub fn cancel(&self, audit_id: Uuid) -> bool {
+        match self.lock().remove(&audit_id) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the token cancelling the job of an audit, registered until `remove`.
+    fn register(&self, audit_id: Uuid) -> CancellationToken {
+        let token = CancellationToken::new();
+        self.lock().insert(audit_id, token.clone());
+        token
+    }
+
+    /// Forgets the token of the job of an audit once the job stopped running.
+    fn remove(&self, audit_id: Uuid) {
+        self.lock().remove(&audit_id);
+    }
+
+    /// Locks the tokens.
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, CancellationToken>> {
+        // The map holds no invariants that a panic could break, so recover from poisoning.
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Spawns `workers` long-running job workers.
+///
+/// The workers claim no job until `ready` is set, so that the migrations creating the
+/// queue have run, and stop claiming jobs once the server shuts down. A job interrupted
+/// by the shutdown is claimed again once its lease expires.
+///
+/// # Arguments
+///
+/// * `workers` - The number of workers.
+/// * `context` - What the workers need to run audits.
+/// * `ready` - The flag set once the service is ready.
+/// * `shutdown` - The flag stopping the workers.
+pub fn start(workers: usize, context: JobContext, ready: Arc<AtomicBool>, shutdown: ShutdownFlag) {
+    tracing::info!(
+        workers,
+        max_attempts = context.max_attempts,
+        "Starting job workers"
+    );
+    for worker in 0..workers {
+        tokio::spawn(run_worker(
+            worker,
+            context.clone(),
+            ready.clone(),
+            shutdown.clone(),
+        ));
+    }
+}
+
+/// Claims and runs due jobs until the server shuts down, waiting [`POLL_INTERVAL`]
+/// whenever none is due.
+async fn run_worker(
+    worker: usize,
+    context: JobContext,
+    ready: Arc<AtomicBool>,
+    shutdown: ShutdownFlag,
+) {
+    while !shutdown.is_triggered() {
+        let claimed = if ready.load(Ordering::SeqCst) {
+            services::claim_audit_job(&context.pool, LEASE, context.max_attempts).await
+        } else {
+            Ok(None)
+        };
+        match claimed {
+            Ok(Some(job)) if job.state == JobState::Failed => {
+                tracing::warn!(worker, job = %job.id, audit = %job.audit_id, "Dead-lettered audit job whose worker stopped.");
+            }
+            Ok(Some(job)) => run_job(worker, &context, &job).await,
+            Ok(None) => {
+                tokio::select! {
+                    () = tokio::time::sleep(POLL_INTERVAL) => {}
+                    () = shutdown.triggered() => {}
+                }
+            }
+            Err(e) => {
+                tracing::warn!(worker, error = %e, "Could not claim an audit job.");
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        }
+    }
+    tracing::debug!(worker, "Job worker stopped");
+}
+
+/// Runs a claimed job, and schedules its retry if it fails.
+///
+/// The job stops as soon as it is cancelled, leaving its audit to the cancellation.
+/// Until then, its progress is published to the clients following it.
+async fn run_job(worker: usize, context: &JobContext, job: &AuditJob) {
+    let cancelled = context.cancellation_tokens.register(job.audit_id);
+    let progress = context.progress.open(job.audit_id);
+    let (lines, output) = mpsc::channel(PROGRESS_CAPACITY);
+    let ran = tokio::select! {
+        (ran, ()) = async {
+            tokio::join!(
+                services::run_audit_job(
+                    &context.pool,
+                    &context.policy,
+                    &context.compiler,
+                    &context.notifiers,
+                    job,
+                    Some(lines),
+                ),
+                publish_output(output, &progress),
+            )
+        } => Some(ran),
+        () = cancelled.cancelled() => None,
+    };
+    context.cancellation_tokens.remove(job.audit_id);
+    if let Some(Ok(Some(audit))) = &ran {
+        let _ = progress.send(AuditProgress::completed(audit.clone()));
+    }
+    context.progress.close(job.audit_id);
+    let Some(ran) = ran else {
+        tracing::info!(worker, job = %job.id, audit = %job.audit_id, "Audit job cancelled.");
+        return;
+    };
+    let error = match ran {
+        Ok(Some(audit)) => {
+            tracing::info!(worker, job = %job.id, audit = %audit.id, attempt = job.attempts, is_valid = audit.is_valid, "Audit job done.");
+            return;
+        }
+        Ok(None) => return,
+        Err(e) => e.to_string(),
+    };
+
+    let retry_in = retry_delay(job.attempts);
+    match services::fail_audit_job(&context.pool, job, &error, retry_in, context.max_attempts).await
+    {
+        Ok(Some(JobState::Failed)) => {
+            tracing::warn!(worker, job = %job.id, audit = %job.audit_id, attempts = job.attempts, %error, "Audit job failed too many times, dead-lettered.");
+        }
+        Ok(Some(_)) => {
+            tracing::warn!(worker, job = %job.id, attempt = job.attempts, retry_in_secs = retry_in.as_secs(), %error, "Audit job failed, retrying later.");
+        }
+        Ok(None) => {}
+        Err(e) => {
+            tracing::warn!(worker, job = %job.id, error = %e, "Could not record the failure of an audit job.");
+        }
+    }
+}
+
+/// Publishes the stages a job's compilation reaches, and its compiler output in batches
+/// sent every [`PROGRESS_BATCH_INTERVAL`], until the compilation ends.
+async fn publish_output(
+    mut output: mpsc::Receiver<CompilerOutput>,
+    progress: &broadcast::Sender<AuditProgress>,
+) {
+    let mut interval = tokio::time::interval(PROGRESS_BATCH_INTERVAL);
+    let mut batch = Vec::new();
+    // Sending fails when nobody follows the job, which is not a problem.
+    loop {
+        tokio::select! {
+            output = output.recv() => match output {
+                Some(CompilerOutput::Line(line)) => batch.push(line),
+                Some(CompilerOutput::Stage(stage)) => {
+                    if !batch.is_empty() {
+                        let _ = progress.send(AuditProgress::compiling(std::mem::take(&mut batch)));
+                    }
+                    let _ = progress.send(AuditProgress::new(stage));
+                }
+                None => break,
+            },
+            _ = interval.tick(), if !batch.is_empty() => {
+                let _ = progress.send(AuditProgress::compiling(std::mem::take(&mut batch)));
+            }
+        }
+    }
+    if !batch.is_empty() {
+        let _ = progress.send(AuditProgress::compiling(batch));
+    }
+}
+
+/// Returns how long to wait after the failed `attempt` (1-based) of a job.
+fn retry_delay(attempt: i32) -> Duration {
+    let exponent = u32::try_from(attempt.saturating_sub(1)).unwrap_or_default();
+    RETRY_BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(exponent))
+        .min(MAX_RETRY_DELAY)
+}
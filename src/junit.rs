@@ -0,0 +1,162 @@
+//! Renders audits as JUnit XML reports for CI servers.
+
+use crate::models::{AiAudit, AuditStatus};
+use std::fmt::Write;
+
+/// The maximum number of characters of the prompt used as a test case name.
+const MAX_NAME_LEN: usize = 80;
+
+/// Renders audits as a JUnit XML report with one test suite.
+///
+/// Each audit becomes a `<testcase>`: valid audits pass, audits whose code does not
+/// compile carry the compiler output in a `<failure>`, and audits rejected by strict
+/// validation carry the rejection reason in a `<failure>`. Audits still waiting for a
+/// background job, or whose job was cancelled, are `<skipped>`. Test case durations are
+/// the stored compilation durations.
+///
+/// # Arguments
+///
+/// * `audits` - The audits to report, in the order they should appear.
+///
+/// # Returns
+///
+/// * `String` - The XML document.
+pub fn audits_to_junit(audits: &[AiAudit]) -> String {
+    let skipped = audits
+        .iter()
+        .filter(|a| matches!(a.status, AuditStatus::Pending | AuditStatus::Cancelled))
+        .count();
+    let failures = audits.iter().filter(|a| !a.is_valid).count() - skipped;
+    let total_ms: i64 = audits
+        .iter()
+        .filter_map(|a| a.compilation_duration_ms)
+        .map(i64::from)
+        .sum();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<testsuites name=\"rust-ai-auditor\" tests=\"{}\" failures=\"{}\" errors=\"0\" skipped=\"{}\" time=\"{}\">",
+        audits.len(),
+        failures,
+        skipped,
+        seconds(total_ms)
+    );
+    let _ = writeln!(
+        xml,
+        "  <testsuite name=\"rust-ai-auditor\" tests=\"{}\" failures=\"{}\" errors=\"0\" skipped=\"{}\" time=\"{}\">",
+        audits.len(),
+        failures,
+        skipped,
+        seconds(total_ms)
+    );
+
+    for audit in audits {
+        let _ = write!(
+            xml,
+            "    <testcase name=\"{}\" classname=\"audit.{}\" time=\"{}\"",
+            escape_attribute(&test_case_name(&audit.prompt)),
+            audit.id,
+            seconds(audit.compilation_duration_ms.map_or(0, i64::from))
+        );
+
+        let skip_reason = match audit.status {
+            AuditStatus::Pending => Some("Waiting to be compiled by a background job"),
+            AuditStatus::Cancelled => Some("Cancelled before being compiled"),
+            _ => None,
+        };
+        if let Some(reason) = skip_reason {
+            let _ = writeln!(
+                xml,
+                ">\n      <skipped message=\"{}\"/>\n    </testcase>",
+                reason
+            );
+            continue;
+        }
+        let failure = match audit.status {
+            AuditStatus::Valid | AuditStatus::Pending | AuditStatus::Cancelled => None,
+            AuditStatus::CompileError => {
+                let output = audit.compilation_error.clone().unwrap_or_default();
+                let message = output
+                    .lines()
+                    .next()
+                    .filter(|line| !line.is_empty())
+                    .unwrap_or("Compilation failed")
+                    .to_string();
+                Some(("compile_error", message, output))
+            }
+            AuditStatus::Rejected => Some((
+                "rejected",
+                "Rejected by strict validation".to_string(),
+                audit.rejection_reason.clone().unwrap_or_default(),
+            )),
+        };
+
+        match failure {
+            None => xml.push_str("/>\n"),
+            Some((kind, message, details)) => {
+                let _ = writeln!(
+                    xml,
+                    ">\n      <failure type=\"{}\" message=\"{}\">{}</failure>\n    </testcase>",
+                    kind,
+                    escape_attribute(&message),
+                    cdata(&details)
+                );
+            }
+        }
+    }
+
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+/// Builds a test case name from the first line of a prompt.
+fn test_case_name(prompt: &str) -> String {
+    let first_line = prompt.lines().next().unwrap_or_default().trim();
+    if first_line.chars().count() > MAX_NAME_LEN {
+        let truncated: String = first_line.chars().take(MAX_NAME_LEN - 1).collect();
+        format!("{}…", truncated)
+    } else {
+        first_line.to_string()
+    }
+}
+
+/// Formats a duration in milliseconds as seconds.
+fn seconds(ms: i64) -> String {
+    format!("{:.3}", ms as f64 / 1000.0)
+}
+
+/// Replaces the characters XML 1.0 does not allow, even escaped (e.g. the ANSI escape
+/// character found in colored compiler output), with U+FFFD.
+fn xml_chars(text: &str) -> impl Iterator<Item = char> + '_ {
+    text.chars().map(|c| match c {
+        '\t' | '\n' | '\r' => c,
+        c if c < ' ' || c == '\u{FFFE}' || c == '\u{FFFF}' => '\u{FFFD}',
+        c => c,
+    })
+}
+
+/// Escapes text for use in a double-quoted XML attribute.
+fn escape_attribute(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in xml_chars(text) {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            // Line breaks would otherwise be normalized to spaces by XML parsers.
+            '\n' => escaped.push_str("&#10;"),
+            '\r' => escaped.push_str("&#13;"),
+            '\t' => escaped.push_str("&#9;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Wraps text in a CDATA section, splitting it wherever the text contains `]]>`.
+fn cdata(text: &str) -> String {
+    let text: String = xml_chars(text).collect();
+    format!("<![CDATA[{}]]>", text.replace("]]>", "]]]]><![CDATA[>"))
+}
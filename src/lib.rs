@@ -0,0 +1,39 @@
+//! The library behind the rust-ai-auditor web service and command-line tool.
+//!
+//! The web service is built on these modules in `main.rs`. The validation and
+//! compilation pipeline (`auditor`) and the data structures (`models`) can also be used
+//! on their own, without a database, as the `audit` subcommand (`cli`) does; `pipeline`
+//! audits whole requests the way the web service does.
+
+pub mod advisories;
+pub mod apq;
+pub mod artifacts;
+pub mod auditor;
+pub mod auth;
+pub mod badges;
+pub mod cache;
+pub mod cli;
+pub mod config;
+pub mod cors;
+pub mod dataloaders;
+pub mod db;
+pub mod error;
+pub mod generation;
+pub mod github;
+pub mod highlight;
+pub mod jobs;
+pub mod junit;
+pub mod licenses;
+pub mod models;
+pub mod notifications;
+pub mod pipeline;
+pub mod rate_limit;
+pub mod sarif;
+pub mod schema;
+pub mod services;
+pub mod stats;
+pub mod telemetry;
+pub mod tls;
+pub mod upload;
+pub mod webhooks;
+pub mod workers;
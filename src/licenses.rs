@@ -0,0 +1,379 @@
+//! Checks the licenses of the dependencies of Cargo packages against the license policy.
+//!
+//! After cargo resolved the dependencies of an audited package, the license of each
+//! locked package (its SPDX expression, read with `cargo metadata`) is evaluated against
+//! the allowed and denied licenses of the policy. A package whose license is denied, or
+//! not allowed, becomes a `RAA0301` finding.
+
+use crate::{
+    auditor::{LICENSE_VIOLATION_CODE, LockedDependency, MANIFEST},
+    models::{Finding, LicensePolicy, LicensePolicyInput, Severity},
+};
+use std::collections::HashSet;
+
+/// The maximum number of licenses of each list of the policy.
+pub const MAX_LICENSES: usize = 100;
+
+/// The maximum length of a license of the policy.
+const MAX_LICENSE_LEN: usize = 64;
+
+/// How a license expression stands with the policy. Alternatives (`OR`) take the best
+/// standing of their licenses, and conjunctions (`AND`) the worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Standing {
+    /// A license the policy denies.
+    Denied,
+    /// A license on neither list.
+    Unlisted,
+    /// A license the policy allows.
+    Allowed,
+}
+
+/// Checks the licenses of the dependencies of a Cargo package.
+///
+/// # Arguments
+///
+/// * `dependencies` - The packages cargo locked, with their license.
+/// * `policy` - The allowed and denied licenses.
+///
+/// # Returns
+///
+/// * `Vec<Finding>` - A `RAA0301` finding for each package whose license is denied (an
+///   error), or not allowed, missing or invalid (a warning).
+pub fn check(dependencies: &[LockedDependency], policy: &LicensePolicy) -> Vec<Finding> {
+    dependencies
+        .iter()
+        .filter_map(|dependency| {
+            let package = format!("{} {}", dependency.name, dependency.version);
+            let (severity, message) = match &dependency.license {
+                None => (
+                    Severity::Warning,
+                    format!("Depends on {}, which declares no license", package),
+                ),
+                Some(license) => match evaluate(license, policy) {
+                    Some(Standing::Allowed) => return None,
+                    Some(Standing::Denied) => (
+                        Severity::Error,
+                        format!(
+                            "Depends on {}, licensed under {}, which the license policy denies",
+                            package, license
+                        ),
+                    ),
+                    Some(Standing::Unlisted) => (
+                        Severity::Warning,
+                        format!(
+                            "Depends on {}, licensed under {}, which the license policy does not allow",
+                            package, license
+                        ),
+                    ),
+                    None => (
+                        Severity::Warning,
+                        format!(
+                            "Depends on {}, whose license {} is not a valid SPDX expression",
+                            package, license
+                        ),
+                    ),
+                },
+            };
+            Some(Finding {
+                code: LICENSE_VIOLATION_CODE.to_string(),
+                severity,
+                message,
+                line: None,
+                category: None,
+                file: Some(MANIFEST.to_string()),
+            })
+        })
+        .collect()
+}
+
+/// Checks the licenses of a new license policy.
+///
+/// # Returns
+///
+/// * `Ok(LicensePolicy)` - The policy, with its licenses trimmed and deduplicated.
+/// * `Err(String)` - If a list has more than [`MAX_LICENSES`] licenses, or a license is
+///   not an SPDX license identifier, optionally ending with `*`.
+pub fn check_policy(input: &LicensePolicyInput) -> Result<LicensePolicy, String> {
+    let check_list = |name: &str, licenses: &[String]| {
+        if licenses.len() > MAX_LICENSES {
+            return Err(format!(
+                "{} may list at most {} licenses",
+                name, MAX_LICENSES
+            ));
+        }
+        let mut seen = HashSet::new();
+        let mut checked = Vec::new();
+        for license in licenses {
+            let license = license.trim();
+            let identifier = license.strip_suffix('*').unwrap_or(license);
+            let valid = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '+' | ':');
+            if license.is_empty()
+                || license.len() > MAX_LICENSE_LEN
+                || !identifier.chars().all(valid)
+                || ["AND", "OR", "WITH"]
+                    .iter()
+                    .any(|operator| identifier.eq_ignore_ascii_case(operator))
+            {
+                return Err(format!(
+                    "{}: '{}' is not an SPDX license identifier",
+                    name, license
+                ));
+            }
+            if seen.insert(license.to_ascii_lowercase()) {
+                checked.push(license.to_string());
+            }
+        }
+        Ok(checked)
+    };
+    Ok(LicensePolicy {
+        allowed: check_list("allowed", &input.allowed)?,
+        denied: check_list("denied", &input.denied)?,
+    })
+}
+
+/// Evaluates an SPDX license expression, such as `MIT OR Apache-2.0`, against the policy.
+///
+/// `OR` binds looser than `AND`, which binds looser than `WITH`; the exception named by
+/// `WITH` does not change the standing of the license. The deprecated `/` separator of
+/// cargo manifests is read as `OR`.
+///
+/// # Returns
+///
+/// * `Some(Standing)` - How the expression stands with the policy.
+/// * `None` - If the expression is not valid.
+fn evaluate(expression: &str, policy: &LicensePolicy) -> Option<Standing> {
+    let expression = expression
+        .replace('/', " OR ")
+        .replace('(', " ( ")
+        .replace(')', " ) ");
+    let mut parser = Parser {
+        tokens: expression.split_whitespace().collect(),
+        position: 0,
+        policy,
+    };
+    let standing = parser.alternatives()?;
+    (parser.position == parser.tokens.len()).then_some(standing)
+}
+
+/// A recursive descent parser of SPDX license expressions, evaluating them as it goes.
+struct Parser<'a> {
+    tokens: Vec<&'a str>,
+    position: usize,
+    policy: &'a LicensePolicy,
+}
+
+impl<'a> Parser<'a> {
+    /// Parses licenses separated by `OR`.
+    fn alternatives(&mut self) -> Option<Standing> {
+        let mut standing = self.conjunction()?;
+        while self.eat("OR") {
+            standing = standing.max(self.conjunction()?);
+        }
+        Some(standing)
+    }
+
+    /// Parses licenses separated by `AND`.
+    fn conjunction(&mut self) -> Option<Standing> {
+        let mut standing = self.license()?;
+        while self.eat("AND") {
+            standing = standing.min(self.license()?);
+        }
+        Some(standing)
+    }
+
+    /// Parses a parenthesized expression, or a license with an optional `WITH`
+    /// exception.
+    fn license(&mut self) -> Option<Standing> {
+        let token = self.next()?;
+        if token == "(" {
+            let standing = self.alternatives()?;
+            return (self.next()? == ")").then_some(standing);
+        }
+        if !is_identifier(token) {
+            return None;
+        }
+        if self.eat("WITH") && !is_identifier(self.next()?) {
+            return None;
+        }
+        Some(license_standing(token, self.policy))
+    }
+
+    /// Returns the next token, if any.
+    fn next(&mut self) -> Option<&'a str> {
+        let token = self.tokens.get(self.position).copied()?;
+        self.position += 1;
+        Some(token)
+    }
+
+    /// Skips the next token if it is the operator `operator`.
+    fn eat(&mut self, operator: &str) -> bool {
+        let found = self
+            .tokens
+            .get(self.position)
+            .is_some_and(|token| token.eq_ignore_ascii_case(operator));
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+}
+
+/// Returns whether a token is a license or exception identifier rather than an operator
+/// or a parenthesis.
+fn is_identifier(token: &str) -> bool {
+    !matches!(token, "(" | ")")
+        && !["AND", "OR", "WITH"]
+            .iter()
+            .any(|operator| token.eq_ignore_ascii_case(operator))
+}
+
+/// Returns how a license identifier stands with the policy: denied if a denied license
+/// matches it, else allowed if an allowed one does. `GPL-2.0+` is read as `GPL-2.0`.
+fn license_standing(license: &str, policy: &LicensePolicy) -> Standing {
+    let license = license.strip_suffix('+').unwrap_or(license);
+    let matches = |pattern: &String| match pattern.strip_suffix('*') {
+        Some(prefix) => license
+            .get(..prefix.len())
+            .is_some_and(|start| start.eq_ignore_ascii_case(prefix)),
+        None => license.eq_ignore_ascii_case(pattern),
+    };
+    if policy.denied.iter().any(matches) {
+        Standing::Denied
+    } else if policy.allowed.iter().any(matches) {
+        Standing::Allowed
+    } else {
+        Standing::Unlisted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dependency(name: &str, license: Option<&str>) -> LockedDependency {
+        LockedDependency {
+            name: name.to_string(),
+            version: "1.0.0".to_string(),
+            license: license.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn any_allowed_alternative_passes() {
+        let policy = LicensePolicy::default();
+        assert_eq!(
+            evaluate("MIT OR Apache-2.0", &policy),
+            Some(Standing::Allowed)
+        );
+        assert_eq!(
+            evaluate("GPL-3.0-only OR MIT", &policy),
+            Some(Standing::Allowed)
+        );
+        assert_eq!(evaluate("MIT/Apache-2.0", &policy), Some(Standing::Allowed));
+        assert_eq!(
+            evaluate("(MIT OR Apache-2.0) AND BSD-3-Clause", &policy),
+            Some(Standing::Allowed)
+        );
+        assert_eq!(
+            evaluate("Apache-2.0 WITH LLVM-exception", &policy),
+            Some(Standing::Allowed)
+        );
+    }
+
+    #[test]
+    fn every_license_of_a_conjunction_must_pass() {
+        let policy = LicensePolicy::default();
+        assert_eq!(
+            evaluate("MIT AND GPL-2.0+", &policy),
+            Some(Standing::Denied)
+        );
+        assert_eq!(
+            evaluate("MIT AND (AGPL-3.0-or-later OR MPL-2.0)", &policy),
+            Some(Standing::Unlisted)
+        );
+        assert_eq!(
+            evaluate("MIT OR Apache-2.0 AND GPL-3.0", &policy),
+            Some(Standing::Allowed)
+        );
+    }
+
+    #[test]
+    fn denied_licenses_win_over_allowed_ones() {
+        let policy = LicensePolicy {
+            allowed: vec!["*".to_string()],
+            denied: vec!["gpl-3.0".to_string()],
+        };
+        assert_eq!(evaluate("GPL-3.0", &policy), Some(Standing::Denied));
+        assert_eq!(evaluate("LGPL-3.0", &policy), Some(Standing::Allowed));
+    }
+
+    #[test]
+    fn invalid_expressions_are_rejected() {
+        let policy = LicensePolicy::default();
+        for expression in [
+            "",
+            "MIT OR",
+            "(MIT",
+            "MIT Apache-2.0",
+            "AND MIT",
+            "MIT WITH",
+        ] {
+            assert_eq!(evaluate(expression, &policy), None, "{}", expression);
+        }
+    }
+
+    #[test]
+    fn violations_name_the_crate_its_version_and_its_license() {
+        let findings = check(
+            &[
+                dependency("serde", Some("MIT OR Apache-2.0")),
+                dependency("readline", Some("GPL-3.0-or-later")),
+                dependency("webpki-roots", Some("MPL-2.0")),
+                dependency("internal", None),
+            ],
+            &LicensePolicy::default(),
+        );
+        let findings: Vec<(Severity, &str)> = findings
+            .iter()
+            .map(|finding| (finding.severity, finding.message.as_str()))
+            .collect();
+        assert_eq!(
+            findings,
+            [
+                (
+                    Severity::Error,
+                    "Depends on readline 1.0.0, licensed under GPL-3.0-or-later, which the \
+                     license policy denies"
+                ),
+                (
+                    Severity::Warning,
+                    "Depends on webpki-roots 1.0.0, licensed under MPL-2.0, which the license \
+                     policy does not allow"
+                ),
+                (
+                    Severity::Warning,
+                    "Depends on internal 1.0.0, which declares no license"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn policies_list_license_identifiers() {
+        let policy = check_policy(&LicensePolicyInput {
+            allowed: vec![" MIT ".to_string(), "mit".to_string(), "BSD-*".to_string()],
+            denied: vec![],
+        })
+        .unwrap();
+        assert_eq!(policy.allowed, ["MIT", "BSD-*"]);
+
+        for license in ["", "MIT OR Apache-2.0", "GPL*3.0", "OR"] {
+            let input = LicensePolicyInput {
+                allowed: vec![],
+                denied: vec![license.to_string()],
+            };
+            assert!(check_policy(&input).is_err(), "{}", license);
+        }
+    }
+}
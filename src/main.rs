@@ -1,92 +1,2597 @@
 //! The main entry point for the rust-ai-auditor web service.
 //!
 //! This module sets up the database connection, initializes the web server (Axum),
-//! configures logging (tracing), and defines the application's routes.
+//! configures logging (tracing), and defines the application's routes. The `audit`
+//! subcommand runs the audit pipeline on local files instead.
 
 // Import necessary crates and modules.
 use anyhow::Context;
-use async_graphql::http::GraphiQLSource;
-use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
+use async_graphql::{
+    dataloader::DataLoader,
+    http::GraphiQLSource,
+    parser::{
+        parse_query,
+        types::{DocumentOperations, OperationType},
+    },
+};
+use async_graphql_axum::{
+    GraphQLRequest, GraphQLResponse, GraphQLSubscription, rejection::GraphQLRejection,
+};
 use axum::{
     Json, Router,
-    extract::State,
-    http::StatusCode,
-    response::{Html, IntoResponse},
-    routing::{get, post},
+    body::{Body, Bytes},
+    extract::{
+        ConnectInfo, FromRef, Path, Query, Request, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::{HeaderMap, StatusCode, header},
+    middleware::{self, Next},
+    response::{
+        Html, IntoResponse, Redirect, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::{delete, get, post},
 };
+use clap::{Parser, Subcommand};
+use futures_util::StreamExt;
 use sqlx::{PgPool, postgres::PgPoolOptions};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use std::{
+    future::Future,
+    io::Write,
+    net::SocketAddr,
+    pin::Pin,
+    process::ExitCode,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tracing_subscriber::{
+    fmt::writer::BoxMakeWriter, layer::SubscriberExt, util::SubscriberInitExt,
+};
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
+
+// Import the application modules from the library.
+use rust_ai_auditor::{
+    advisories, apq, artifacts, auditor, auth, badges, cache, cli, config, cors, dataloaders, db,
+    error, generation, github, highlight, jobs, junit, models,
+    notifications::{AuditNotifiers, slack::SlackNotifier},
+    rate_limit, schema, services, stats, telemetry, tls, upload, webhooks, workers,
+};
+
+// Import items from our modules.
+use advisories::AdvisoryDatabase;
+use apq::{PersistedQueries, PersistedQueryStore};
+use artifacts::ArtifactSettings;
+use auditor::{AuditPolicy, CompileOptions, CompilerOutput};
+use auth::{AdminAuth, AdminToken, Caller, Claims, TokenIssuer};
+use badges::{Badge, BadgeCache};
+use cache::{AuditResultCache, CacheSettings};
+use config::AppConfig;
+use dataloaders::{CommentLoader, DiagnosticsLoader, RatingLoader};
+use db::Db;
+use error::{AppError, ErrorResponse};
+use generation::CodeGenerators;
+use github::{GitHubIntegration, PullRequestEvent};
+use highlight::HighlightCache;
+use jobs::{CancellationTokens, JobContext, ProgressChannels};
+use models::{
+    AiAudit, ApqStats, ArtifactFile, AuditArtifacts, AuditCacheStats, AuditChain, AuditComment,
+    AuditComparison, AuditFile, AuditFilter, AuditJob, AuditJobStatus, AuditMetrics, AuditOutcome,
+    AuditProgress, AuditProgressStage, AuditRating, AuditStats, AuditStatus, AutoFixOptions,
+    CategoryFrequency, Channel, CommonError, CompilationEvent, CreateAuditRequest,
+    CreateCommentRequest, CreateWebhookRequest, DbStats, Diagnostic, Edition, EditionStats,
+    Finding, FindingCategory, FixAuditRequest, GenerateOptions, HygieneReport, HygieneStats,
+    ImportAuditRecord, ImportReport, ImportRowError, JobState, LicenseStat, LoginRequest, Provider,
+    RateAuditRequest, ReauditReport, RerunReport, Role, SecurityAdvisory, Severity, StatsBucket,
+    StatsGroupBy, StatsResponse, StreamCompilationRequest, SystemInfo, TargetStats, TokenResponse,
+    UnsafeReport, User, Webhook, WorkerStats,
+};
+use rate_limit::{ClientAddr, RateLimiter};
+use schema::{AppSchema, MutationRoot, QueryRoot, SubscriptionRoot};
+use serde::Deserialize;
+use stats::StatsCache;
+use uuid::Uuid;
+use webhooks::WebhookNotifier;
+use workers::{CompilationQueue, ShutdownFlag};
+
+/// Represents the shared state that is accessible from all route handlers.
+#[derive(Clone)]
+struct AppState {
+    /// The database pools: the primary, and the read replica if one is configured.
+    db: Db,
+    /// The GraphQL schema.
+    schema: AppSchema,
+    /// The token granting access to the administrative endpoints.
+    admin_token: AdminToken,
+    /// The store of Automatic Persisted Queries, shared with the GraphQL schema.
+    persisted_queries: Arc<PersistedQueryStore>,
+    /// The server-wide audit settings.
+    policy: AuditPolicy,
+    /// The queue of the compilation workers.
+    compiler: CompilationQueue,
+    /// The limit of audits each client may create per minute, shared with the GraphQL
+    /// schema.
+    rate_limiter: Arc<RateLimiter>,
+    /// The GitHub pull request integration, if configured.
+    github: Option<Arc<GitHubIntegration>>,
+    /// The cached counts behind the validity badges.
+    badges: Arc<BadgeCache>,
+    /// The cached syntax-highlighted code of audits, shared with the GraphQL schema.
+    highlights: Arc<HighlightCache>,
+    /// The memoized statistics served by `/stats`.
+    stats: Arc<StatsCache>,
+    /// The clients of the LLM providers generating code.
+    generators: CodeGenerators,
+    /// The notifiers told about every newly created audit.
+    notifiers: AuditNotifiers,
+    /// The tokens cancelling the background jobs running on this replica.
+    cancellation_tokens: CancellationTokens,
+    /// The channels publishing the progress of the background jobs running on this
+    /// replica.
+    progress: ProgressChannels,
+    /// The issuer of user access tokens, if `JWT_SECRET` is configured.
+    token_issuer: Option<Arc<TokenIssuer>>,
+    /// Whether `POST /auth/register` accepts new accounts.
+    registration_enabled: bool,
+    /// The settings of the server.
+    config: Arc<AppConfig>,
+    /// Set once the server starts shutting down, to refuse new audits.
+    shutdown: ShutdownFlag,
+    /// Set once the migrations have run and the compiler has been probed.
+    ready: Arc<AtomicBool>,
+    /// When the server started, to report its uptime.
+    startup_time: Instant,
+    /// The version of the primary database, as reported at startup.
+    database_version: String,
+}
+
+impl FromRef<AppState> for AdminToken {
+    fn from_ref(state: &AppState) -> Self {
+        state.admin_token.clone()
+    }
+}
+
+impl FromRef<AppState> for Option<Arc<TokenIssuer>> {
+    fn from_ref(state: &AppState) -> Self {
+        state.token_issuer.clone()
+    }
+}
+
+/// The OpenAPI 3 description of the REST endpoints.
+///
+/// The GraphQL API is self-describing through introspection and is not part of this document.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "rust-ai-auditor",
+        description = "REST API for auditing AI-generated Rust code.",
+        license(name = "MIT")
+    ),
+    paths(
+        create_audit_handler,
+        create_async_audit_handler,
+        dry_run_audit_handler,
+        audit_job_handler,
+        cancel_audit_handler,
+        audit_progress_handler,
+        get_audit_handler,
+        list_audits_handler,
+        stream_audits_handler,
+        compare_audits_handler,
+        junit_report_handler,
+        stats_handler,
+        category_stats_handler,
+        add_comment_handler,
+        list_comments_handler,
+        delete_comment_handler,
+        rate_audit_handler,
+        list_ratings_handler,
+        list_audit_files_handler,
+        sarif_handler,
+        fix_audit_handler,
+        audit_lineage_handler,
+        download_audit_handler,
+        list_artifacts_handler,
+        download_artifact_handler,
+        audit_code_handler,
+        audit_code_html_handler,
+        global_svg_badge_handler,
+        global_json_badge_handler,
+        project_badge_handler,
+        rerun_failed_handler,
+        reaudit_invalid_handler,
+        import_audits_handler,
+        create_webhook_handler,
+        list_webhooks_handler,
+        system_info_handler,
+        config_template_handler,
+        delete_webhook_handler,
+        issue_token_handler,
+        register_handler,
+        github_webhook_handler,
+        apq_metrics_handler,
+        audit_cache_metrics_handler,
+        worker_metrics_handler,
+        db_metrics_handler,
+        health_handler,
+        ready_handler
+    ),
+    components(schemas(
+        AiAudit,
+        CreateAuditRequest,
+        AutoFixOptions,
+        AuditChain,
+        AuditJob,
+        AuditJobStatus,
+        AuditOutcome,
+        Diagnostic,
+        JobState,
+        AuditComparison,
+        AuditStats,
+        StatsResponse,
+        StatsBucket,
+        StatsGroupBy,
+        TargetStats,
+        EditionStats,
+        LicenseStat,
+        HygieneStats,
+        CommonError,
+        CategoryFrequency,
+        AuditStatus,
+        Channel,
+        GenerateOptions,
+        Provider,
+        Finding,
+        FindingCategory,
+        FixAuditRequest,
+        SecurityAdvisory,
+        UnsafeReport,
+        HygieneReport,
+        AuditMetrics,
+        Severity,
+        AuditComment,
+        CreateCommentRequest,
+        AuditRating,
+        RateAuditRequest,
+        AuditArtifacts,
+        ArtifactFile,
+        RerunReport,
+        ReauditReport,
+        ImportAuditRecord,
+        ImportReport,
+        ImportRowError,
+        ApqStats,
+        AuditCacheStats,
+        AuditFile,
+        AuditProgress,
+        AuditProgressStage,
+        WorkerStats,
+        DbStats,
+        SystemInfo,
+        Webhook,
+        CreateWebhookRequest,
+        LoginRequest,
+        TokenResponse,
+        User,
+        Role,
+        ErrorResponse
+    )),
+    tags(
+        (name = "audits", description = "Creation and analytics of AI code audits"),
+        (name = "comments", description = "Reviewer notes attached to audits"),
+        (name = "ratings", description = "Quality ratings of audits by reviewers"),
+        (name = "admin", description = "Maintenance operations, requiring the admin bearer token"),
+        (name = "auth", description = "User accounts and access tokens"),
+        (name = "integrations", description = "Webhooks from code hosting services"),
+        (name = "metrics", description = "Operational counters"),
+        (name = "health", description = "Liveness and readiness probes"),
+        (name = "badges", description = "README badges showing the share of valid audits")
+    ),
+    modifiers(&AdminSecurity)
+)]
+struct ApiDoc;
+
+/// Registers the bearer-token security scheme used by the administrative endpoints.
+struct AdminSecurity;
+
+impl utoipa::Modify for AdminSecurity {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "admin_token",
+            SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+        );
+        components.add_security_scheme(
+            "access_token",
+            SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+        );
+    }
+}
+
+/// Handles REST requests to create a new AI code audit.
+///
+/// If the request sets `auto_fix`, the response is the chain of audits created while
+/// asking the model to fix its code, instead of a single audit.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `headers` - The request headers, which may carry an `Idempotency-Key`.
+/// * `payload` - The audit request data, read from a JSON payload, a multipart form or
+///   raw code (see [`upload`]).
+///
+/// # Returns
+///
+/// * `Ok(Response)` - On success, returns a `201 CREATED` status and the newly created
+///   audit record or audit chain, a `202 ACCEPTED` status and the pending audit if
+///   `background` is set and the code is left to a job, or a `200 OK` status and the
+///   original audit if the `Idempotency-Key` was already used.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    post,
+    path = "/audit",
+    tag = "audits",
+    request_body(
+        description = "The audit request as JSON; or a multipart form with a `prompt` field, \
+            one or more `code` file parts (or a zip archive of `.rs` files) and the other fields \
+            as text; or the code itself as `text/x-rust`, with the prompt in the `X-Audit-Prompt` \
+            header or the `prompt` query parameter and the other fields as query parameters",
+        content(
+            (CreateAuditRequest = "application/json"),
+            (String = "multipart/form-data"),
+            (String = "text/x-rust")
+        )
+    ),
+    params(
+        ("Idempotency-Key" = Option<String>, Header,
+            description = "Client-chosen key making retries of this request return the original audit"),
+        ("X-Audit-Prompt" = Option<String>, Header,
+            description = "The prompt of raw `text/x-rust` uploads")
+    ),
+    responses(
+        (status = 201, description = "Audit created, or the audit chain if `auto_fix` is set",
+            content((AiAudit), (AuditChain))),
+        (status = 202, description = "Pending audit, compiled by a background job since `background` is set",
+            body = AiAudit),
+        (status = 200, description = "The audit created earlier with the same Idempotency-Key",
+            body = AiAudit),
+        (status = 422, description = "Malformed request body, or uploaded code that is not UTF-8"),
+        AppError
+    )
+)]
+async fn create_audit_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    upload::AuditPayload(mut payload): upload::AuditPayload,
+) -> Result<Response, AppError> {
+    if let Some(key) = headers.get("idempotency-key") {
+        let key = key.to_str().map_err(|_| {
+            AppError::Validation("Idempotency-Key header must be valid ASCII".to_string())
+        })?;
+        payload.idempotency_key = Some(key.to_string());
+    }
+    state.shutdown.check()?;
+    if payload.auto_fix.is_some() {
+        let chain = services::create_audit_chain(
+            state.db.primary(),
+            &state.policy,
+            &state.compiler,
+            &state.generators,
+            &payload,
+            &state.notifiers,
+        )
+        .await?;
+        return Ok((StatusCode::CREATED, Json(chain)).into_response());
+    }
+    let (audit, created) = services::create_or_replay_audit(
+        state.db.primary(),
+        &state.policy,
+        &state.compiler,
+        &state.generators,
+        &payload,
+        None,
+        &state.notifiers,
+    )
+    .await?;
+    let status = match (created, audit.status) {
+        (false, _) => StatusCode::OK,
+        (true, AuditStatus::Pending) => StatusCode::ACCEPTED,
+        (true, _) => StatusCode::CREATED,
+    };
+    Ok((status, Json(audit)).into_response())
+}
+
+/// Handles REST requests to audit code without storing anything.
+///
+/// The request is the same as for `POST /audit`, and goes through the same checks and
+/// the same pipeline, but nothing is written to the database.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `payload` - The audit request, as JSON, multipart form data or raw `text/x-rust`.
+///
+/// # Returns
+///
+/// * `Ok(Json<AuditOutcome>)` - On success, returns a `200 OK` status and the verdict,
+///   the findings, the compiler diagnostics and the metrics of the code.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    post,
+    path = "/audit/dry-run",
+    tag = "audits",
+    request_body(
+        description = "The same request as for `POST /audit`; `generate`, `auto_fix` and \
+            `background` are rejected",
+        content(
+            (CreateAuditRequest = "application/json"),
+            (String = "multipart/form-data"),
+            (String = "text/x-rust")
+        )
+    ),
+    params(
+        ("X-Audit-Prompt" = Option<String>, Header,
+            description = "The prompt of raw `text/x-rust` uploads")
+    ),
+    responses(
+        (status = 200, description = "The outcome of the audit, which is not stored", body = AuditOutcome),
+        (status = 422, description = "Malformed request body, or uploaded code that is not UTF-8"),
+        AppError
+    )
+)]
+async fn dry_run_audit_handler(
+    State(state): State<AppState>,
+    upload::AuditPayload(payload): upload::AuditPayload,
+) -> Result<Json<AuditOutcome>, AppError> {
+    state.shutdown.check()?;
+    let outcome =
+        services::analyze_code(state.db.read(), &state.policy, &state.compiler, payload).await?;
+    Ok(Json(outcome))
+}
+
+/// Handles REST requests to create an audit compiled by a background job.
+///
+/// The request is the same as for `POST /audit`, with `background` forced. The audit is
+/// validated and stored right away, and the response carries its job, whose progress is
+/// reported by `GET /audit/job/{id}`.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `headers` - The request headers, which may carry an `Idempotency-Key`.
+/// * `payload` - The JSON payload containing the audit request data.
+///
+/// # Returns
+///
+/// * `Ok((StatusCode, Json<AuditJobStatus>))` - On success, returns a `202 ACCEPTED` status
+///   and the job, with its audit if it is already done (e.g. the code was rejected
+///   without compiling).
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    post,
+    path = "/audit/async",
+    tag = "audits",
+    request_body = CreateAuditRequest,
+    params(
+        ("Idempotency-Key" = Option<String>, Header,
+            description = "Client-chosen key making retries of this request return the original job")
+    ),
+    responses(
+        (status = 202, description = "Audit stored, compiled by a background job", body = AuditJobStatus),
+        (status = 422, description = "Malformed request body"),
+        AppError
+    )
+)]
+async fn create_async_audit_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(mut payload): Json<CreateAuditRequest>,
+) -> Result<(StatusCode, Json<AuditJobStatus>), AppError> {
+    if let Some(key) = headers.get("idempotency-key") {
+        let key = key.to_str().map_err(|_| {
+            AppError::Validation("Idempotency-Key header must be valid ASCII".to_string())
+        })?;
+        payload.idempotency_key = Some(key.to_string());
+    }
+    payload.background = true;
+    state.shutdown.check()?;
+    let (audit, _) = services::create_or_replay_audit(
+        state.db.primary(),
+        &state.policy,
+        &state.compiler,
+        &state.generators,
+        &payload,
+        None,
+        &state.notifiers,
+    )
+    .await?;
+    // A key first used with `POST /audit` replays an audit that has no job.
+    let job = services::find_audit_job(state.db.primary(), audit.id)
+        .await?
+        .ok_or_else(|| {
+            AppError::Conflict(format!(
+                "Audit {} was not submitted in the background",
+                audit.id
+            ))
+        })?;
+    let status = services::audit_job_status(state.db.primary(), job).await?;
+    Ok((StatusCode::ACCEPTED, Json(status)))
+}
+
+/// Handles REST requests for the progress of a background audit job.
+///
+/// The job is read from the primary database, so that clients polling it see it
+/// complete as soon as it does.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `id` - The UUID of the job.
+///
+/// # Returns
+///
+/// * `Ok(Json<AuditJobStatus>)` - On success, returns the job, with its audit once it is
+///   done.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    get,
+    path = "/audit/job/{id}",
+    tag = "audits",
+    params(("id" = Uuid, Path, description = "The job identifier")),
+    responses(
+        (status = 200, description = "The job: `pending`, `running`, `done` with its audit, `failed` after failing too many times, or `cancelled`", body = AuditJobStatus),
+        AppError
+    )
+)]
+async fn audit_job_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<AuditJobStatus>, AppError> {
+    let job = services::get_audit_job(state.db.primary(), id).await?;
+    Ok(Json(
+        services::audit_job_status(state.db.primary(), job).await?,
+    ))
+}
+
+/// Handles REST requests to cancel the background compilation of an audit.
+///
+/// # Arguments
+///
+/// * `_caller` - Proof that the request is authenticated, with the admin token or an
+///   access token.
+/// * `state` - The shared application state.
+/// * `id` - The UUID of the audit.
+///
+/// # Returns
+///
+/// * `Ok(StatusCode)` - On success, returns a `204 NO CONTENT` status.
+/// * `Err(AppError)` - On failure, returns an application-specific error, such as
+///   `409 CONFLICT` if the audit has no background compilation left to cancel.
+#[utoipa::path(
+    delete,
+    path = "/audit/{id}/compilation",
+    tag = "audits",
+    params(("id" = Uuid, Path, description = "The audit identifier")),
+    security(("admin_token" = []), ("access_token" = [])),
+    responses(
+        (status = 204, description = "Compilation cancelled, the audit has the `cancelled` status"),
+        AppError
+    )
+)]
+async fn cancel_audit_handler(
+    _caller: Caller,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    if !services::cancel_audit(state.db.primary(), &state.cancellation_tokens, id).await? {
+        return Err(AppError::Conflict(format!(
+            "Audit {} has no background compilation to cancel",
+            id
+        )));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Handles REST requests to follow the progress of an audit as Server-Sent Events.
+///
+/// Each event is named after its stage and carries an `AuditProgress` as JSON data:
+/// `validated` while the audit waits for a background job, `dependencies_resolved` once
+/// cargo resolved the dependencies of a Cargo package, `compiling` with batches of
+/// compiler output while this replica compiles it, and a final `completed` with the
+/// audit, right away if it already has its verdict. An `error` event ends the stream if
+/// the audit cannot be read.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `id` - The UUID of the audit.
+///
+/// # Returns
+///
+/// * `Ok(Response)` - On success, returns the `text/event-stream` of the progress events.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    get,
+    path = "/audit/{id}/progress",
+    tag = "audits",
+    params(("id" = Uuid, Path, description = "The audit identifier")),
+    responses(
+        (status = 200, description = "The progress events of the audit, ending with `completed`",
+            content_type = "text/event-stream", body = AuditProgress),
+        AppError
+    )
+)]
+async fn audit_progress_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, AppError> {
+    services::get_audit_by_id(state.db.primary(), id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Audit {} not found", id)))?;
+    let events =
+        services::audit_progress(state.db.primary(), &state.progress, id).map(|progress| {
+            let event = match progress {
+                Ok(progress) => Event::default()
+                    .event(progress.stage.as_str())
+                    .json_data(&progress)?,
+                Err(e) => Event::default().event("error").data(e.to_string()),
+            };
+            Ok::<_, axum::Error>(event)
+        });
+    Ok(Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response())
+}
+
+/// Upgrades a request to a WebSocket streaming the compilation of a snippet.
+///
+/// The client sends one JSON text message (`{"generated_code": "...", "channel": "stable",
+/// "opt_level": 0, "edition": "2021", "target": "wasm32-unknown-unknown"}`; only
+/// `generated_code` is required).
+/// The server answers with one `log` message per line of compiler output as it is
+/// produced, then a final `verdict` (or `error`) message, and closes the socket. The
+/// snippet is not validated nor stored. If the client disconnects, `rustc` is killed.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `ws` - The WebSocket upgrade request.
+///
+/// # Returns
+///
+/// * `Response` - The `101 Switching Protocols` response, or `503 SERVICE UNAVAILABLE` while
+///   the server is shutting down.
+async fn audit_stream_handler(State(state): State<AppState>, ws: WebSocketUpgrade) -> Response {
+    if let Err(e) = state.shutdown.check() {
+        return e.into_response();
+    }
+    ws.on_upgrade(move |socket| stream_compilation(socket, state.compiler))
+}
+
+/// Runs the compilation requested on a WebSocket, streaming its output.
+async fn stream_compilation(mut socket: WebSocket, compiler: CompilationQueue) {
+    let request = loop {
+        match socket.recv().await {
+            Some(Ok(Message::Text(text))) => break serde_json::from_str(&text),
+            Some(Ok(Message::Ping(_) | Message::Pong(_))) => continue,
+            _ => return,
+        }
+    };
+    let request: StreamCompilationRequest = match request {
+        Ok(request) => request,
+        Err(e) => {
+            let message = format!("Invalid compilation request: {}", e);
+            let _ = send_event(&mut socket, &CompilationEvent::Error { message }).await;
+            let _ = socket.send(Message::Close(None)).await;
+            return;
+        }
+    };
+    let options = CompileOptions {
+        channel: request.channel.unwrap_or_default(),
+        opt_level: request.opt_level.unwrap_or_default(),
+        edition: request
+            .edition
+            .unwrap_or_else(|| auditor::detect_edition_from_code(&request.generated_code)),
+        target: request.target,
+        files: Vec::new(),
+    };
+
+    let (lines, mut output) = tokio::sync::mpsc::channel(64);
+    let compilation = compiler.compile_streaming(request.generated_code, options, lines);
+    tokio::pin!(compilation);
+
+    let outcome = loop {
+        tokio::select! {
+            outcome = &mut compilation => break outcome,
+            Some(output) = output.recv() => {
+                let CompilerOutput::Line(line) = output else {
+                    continue;
+                };
+                if send_event(&mut socket, &CompilationEvent::Log { line }).await.is_err() {
+                    // Dropping the output receiver cancels the compilation.
+                    return;
+                }
+            }
+            message = socket.recv() => {
+                if matches!(message, None | Some(Err(_)) | Some(Ok(Message::Close(_)))) {
+                    tracing::debug!("Client disconnected, cancelling the compilation.");
+                    return;
+                }
+            }
+        }
+    };
+
+    // Forward the lines still buffered when the compilation completed.
+    while let Ok(output) = output.try_recv() {
+        let CompilerOutput::Line(line) = output else {
+            continue;
+        };
+        if send_event(&mut socket, &CompilationEvent::Log { line })
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+
+    let event = match outcome {
+        Ok(outcome) => CompilationEvent::Verdict {
+            success: outcome.success,
+            primary_error_code: auditor::primary_error_code(&outcome.diagnostics),
+            primary_error_category: auditor::primary_error_category(&outcome.diagnostics)
+                .map(|category| category.as_str().to_string()),
+            duration_ms: outcome.duration.as_millis(),
+            diagnostics: outcome.diagnostics,
+        },
+        Err(e) => CompilationEvent::Error {
+            message: e.to_string(),
+        },
+    };
+    let _ = send_event(&mut socket, &event).await;
+    let _ = socket.send(Message::Close(None)).await;
+}
+
+/// Sends a compilation event as a JSON text message.
+async fn send_event(socket: &mut WebSocket, event: &CompilationEvent) -> Result<(), axum::Error> {
+    let json = serde_json::to_string(event).unwrap_or_default();
+    socket.send(Message::Text(json.into())).await
+}
+
+/// Handles REST requests for a single audit.
+///
+/// The response carries an `ETag` derived from the audit's identifier and last update,
+/// so that clients polling an audit can send it back in `If-None-Match` and receive an
+/// empty `304 Not Modified` until the audit is re-audited.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `id` - The UUID of the audit.
+/// * `headers` - The request headers, which may carry `If-None-Match`.
+///
+/// # Returns
+///
+/// * `Ok(Response)` - On success, returns the audit, or `304 Not Modified` if the client
+///   already has its current version.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    get,
+    path = "/audit/{id}",
+    tag = "audits",
+    params(
+        ("id" = Uuid, Path, description = "The audit identifier"),
+        ("If-None-Match" = Option<String>, Header,
+            description = "The ETag of a previously fetched version of the audit")
+    ),
+    responses(
+        (status = 200, description = "The audit", body = AiAudit,
+            headers(
+                ("ETag" = String, description = "The version of the audit"),
+                ("Cache-Control" = String, description = "`no-cache`: revalidate with `If-None-Match`")
+            )),
+        (status = 304, description = "The audit has not changed since the given ETag"),
+        AppError
+    )
+)]
+async fn get_audit_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let audit = services::get_audit_by_id(state.db.read(), id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Audit {} not found", id)))?;
+    let etag = format!(
+        "\"{}-{}\"",
+        audit.id.simple(),
+        audit.updated_at.timestamp_micros()
+    );
+
+    // Audits change when recompiled, so clients must revalidate before reusing them.
+    let headers_out = [
+        (header::ETAG, etag.clone()),
+        (header::CACHE_CONTROL, "no-cache".to_string()),
+    ];
+    if if_none_match(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, headers_out).into_response());
+    }
+    Ok((headers_out, Json(audit)).into_response())
+}
+
+/// Returns whether the `If-None-Match` header of a request matches an entity tag.
+///
+/// The header may list several tags, or be `*`; weak tags (`W/"..."`) are compared as
+/// if they were strong, as GET requests allow, on both sides.
+fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag.trim_start_matches("W/"))
+}
+
+/// Handles REST requests to list audits, most recent first.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `filter` - The conditions the listed audits must meet.
+///
+/// # Returns
+///
+/// * `Ok(Json<Vec<AiAudit>>)` - On success, returns the matching audits.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    get,
+    path = "/audits",
+    tag = "audits",
+    params(AuditFilter),
+    responses(
+        (status = 200, description = "The matching audits, most recent first", body = [AiAudit]),
+        AppError
+    )
+)]
+async fn list_audits_handler(
+    State(state): State<AppState>,
+    Query(filter): Query<AuditFilter>,
+) -> Result<Json<Vec<AiAudit>>, AppError> {
+    let audits = services::list_audits(state.db.read(), &filter).await?;
+    Ok(Json(audits))
+}
+
+/// Handles REST requests to stream audits as newline-delimited JSON, most recent first.
+///
+/// Unlike `/audits`, the audits are sent as they are read from the database, one JSON
+/// object per line, so that the whole table can be exported. Reading follows the pace of
+/// the client, and stops when it disconnects. An error while streaming ends the response
+/// early, since its status has already been sent.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `filter` - The conditions the streamed audits must meet.
+///
+/// # Returns
+///
+/// * `Ok(Response)` - On success, returns the matching audits as `application/x-ndjson`.
+/// * `Err(AppError)` - If the filter is invalid.
+#[utoipa::path(
+    get,
+    path = "/audits/stream",
+    tag = "audits",
+    params(AuditFilter),
+    responses(
+        (status = 200, description = "The matching audits, most recent first, one JSON object per line",
+            content_type = "application/x-ndjson", body = AiAudit),
+        AppError
+    )
+)]
+async fn stream_audits_handler(
+    State(state): State<AppState>,
+    Query(filter): Query<AuditFilter>,
+) -> Result<Response, AppError> {
+    // The audits already read are sent together, rather than in a chunk each.
+    let lines = services::stream_audits(state.db.read(), &filter)?
+        .ready_chunks(STREAM_CHUNK_AUDITS)
+        .map(|audits| {
+            let mut lines = Vec::new();
+            for audit in audits {
+                let audit =
+                    audit.inspect_err(|e| tracing::error!(error = %e, "Audit stream failed"))?;
+                serde_json::to_writer(&mut lines, &audit)?;
+                lines.push(b'\n');
+            }
+            Ok::<_, axum::BoxError>(Bytes::from(lines))
+        });
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(lines),
+    )
+        .into_response())
+}
+
+/// The query parameters accepted by the comparison endpoint.
+#[derive(Debug, Deserialize, utoipa::IntoParams)]
+struct CompareParams {
+    /// The ID of the audit to compare from.
+    a: Uuid,
+    /// The ID of the audit to compare to.
+    b: Uuid,
+}
+
+/// Handles REST requests to compare two audits.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `params` - The IDs of the audits to compare.
+///
+/// # Returns
+///
+/// * `Ok(Json<AuditComparison>)` - On success, returns both audits and their differences.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    get,
+    path = "/audits/compare",
+    tag = "audits",
+    params(CompareParams),
+    responses(
+        (status = 200, description = "Both audits and a unified diff of their code", body = AuditComparison),
+        AppError
+    )
+)]
+async fn compare_audits_handler(
+    State(state): State<AppState>,
+    Query(params): Query<CompareParams>,
+) -> Result<Json<AuditComparison>, AppError> {
+    let comparison = services::compare_audits(state.db.read(), params.a, params.b).await?;
+    Ok(Json(comparison))
+}
+
+/// The maximum number of audits in a JUnit report.
+const MAX_REPORT_AUDITS: usize = 1000;
+
+/// The query parameters accepted by the JUnit report endpoint.
+#[derive(Debug, Deserialize, utoipa::IntoParams)]
+struct JunitReportParams {
+    /// The comma-separated IDs of the audits to report, in order (at most 1000).
+    ids: String,
+}
+
+/// Handles REST requests for a JUnit XML report of several audits.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `params` - The IDs of the audits to report.
+///
+/// # Returns
+///
+/// * `Ok(Response)` - On success, returns the report as `application/xml`.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    get,
+    path = "/audits/report/junit",
+    tag = "audits",
+    params(JunitReportParams),
+    responses(
+        (status = 200, description = "One test case per audit", content_type = "application/xml", body = String),
+        AppError
+    )
+)]
+async fn junit_report_handler(
+    State(state): State<AppState>,
+    Query(params): Query<JunitReportParams>,
+) -> Result<Response, AppError> {
+    let ids = params
+        .ids
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| {
+            Uuid::parse_str(id)
+                .map_err(|_| AppError::Validation(format!("Invalid audit ID '{}'", id)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if ids.is_empty() || ids.len() > MAX_REPORT_AUDITS {
+        return Err(AppError::Validation(format!(
+            "Between 1 and {} audit IDs are required",
+            MAX_REPORT_AUDITS
+        )));
+    }
+
+    let audits = services::get_audits_by_ids(state.db.read(), &ids).await?;
+    let xml = junit::audits_to_junit(&audits);
+    Ok((
+        [(header::CONTENT_TYPE, "application/xml; charset=utf-8")],
+        xml,
+    )
+        .into_response())
+}
+
+/// The query parameters accepted by the statistics endpoint.
+#[derive(Debug, Deserialize, utoipa::IntoParams)]
+struct StatsParams {
+    /// Groups the audits by `day`, `model`, `tag` or `error_code` instead of aggregating
+    /// them all.
+    group_by: Option<String>,
+    /// The maximum number of buckets when grouping (1 to 1000, defaults to 100).
+    limit: Option<i64>,
+    /// The number of buckets to skip when grouping (defaults to 0).
+    offset: Option<i64>,
+    /// The number of days covered by the daily counts when not grouping (1 to 366,
+    /// defaults to 30).
+    days: Option<i32>,
+    /// Only counts the audits compiled with this edition (`2015`, `2018`, `2021` or
+    /// `2024`).
+    #[param(value_type = Option<String>)]
+    edition: Option<Edition>,
+}
+
+/// Handles REST requests to get audit statistics.
+///
+/// Statistics are memoized for a few seconds and carry a weak `ETag`, changing whenever
+/// an audit is created, updated or deleted.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `params` - The grouping, pagination and edition filter of the statistics.
+/// * `headers` - The request headers, which may carry `If-None-Match`.
+///
+/// # Returns
+///
+/// * `Ok(Response)` - On success, returns the statistics of all audits, or one bucket per
+///   group if `group_by` is set, or `304 Not Modified` if the client already has them.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    get,
+    path = "/stats",
+    tag = "audits",
+    params(
+        StatsParams,
+        ("If-None-Match" = Option<String>, Header,
+            description = "The ETag of previously fetched statistics")
+    ),
+    responses(
+        (status = 200, description = "Aggregated audit statistics, or grouped buckets with `group_by`", body = StatsResponse,
+            headers(
+                ("ETag" = String, description = "The version of the statistics"),
+                ("Cache-Control" = String, description = "`public, max-age=5`")
+            )),
+        (status = 304, description = "The statistics have not changed since the given ETag"),
+        AppError
+    )
+)]
+async fn stats_handler(
+    State(state): State<AppState>,
+    Query(params): Query<StatsParams>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let group_by = params
+        .group_by
+        .map(|group_by| group_by.parse())
+        .transpose()
+        .map_err(AppError::Validation)?;
+    let stats = state
+        .stats
+        .stats(
+            state.db.read(),
+            group_by,
+            params.limit.unwrap_or(100),
+            params.offset.unwrap_or(0),
+            params.days.unwrap_or(services::DEFAULT_DAILY_COUNT_DAYS),
+            params.edition,
+        )
+        .await?;
+
+    let headers_out = [
+        (header::ETAG, stats.etag.clone()),
+        (header::CACHE_CONTROL, stats::CACHE_CONTROL.to_string()),
+    ];
+    if if_none_match(&headers, &stats.etag) {
+        return Ok((StatusCode::NOT_MODIFIED, headers_out).into_response());
+    }
+    Ok((headers_out, Json(&stats.response)).into_response())
+}
+
+/// Handles REST requests to get how often each error category caused compilation to fail.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// * `Ok(Json<Vec<CategoryFrequency>>)` - On success, returns the error categories,
+///   most frequent first.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    get,
+    path = "/stats/categories",
+    tag = "audits",
+    responses(
+        (status = 200, description = "Frequency of each primary error category", body = [CategoryFrequency]),
+        AppError
+    )
+)]
+async fn category_stats_handler(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<CategoryFrequency>>, AppError> {
+    let categories = services::get_category_frequencies(state.db.read()).await?;
+    Ok(Json(categories))
+}
+
+/// Handles REST requests to add a reviewer comment to an audit.
+///
+/// The comment is written in the name of the user the access token was issued to.
+///
+/// # Arguments
+///
+/// * `claims` - The access token of the user writing the comment.
+/// * `state` - The shared application state.
+/// * `id` - The UUID of the audit being commented on.
+/// * `payload` - The JSON payload containing the body of the comment.
+///
+/// # Returns
+///
+/// * `Ok((StatusCode, Json<AuditComment>))` - On success, returns a `201 CREATED` status
+///   and the newly created comment.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    post,
+    path = "/audit/{id}/comments",
+    tag = "comments",
+    params(("id" = Uuid, Path, description = "The audit identifier")),
+    request_body = CreateCommentRequest,
+    security(("access_token" = [])),
+    responses(
+        (status = 201, description = "Comment created", body = AuditComment),
+        (status = 422, description = "Malformed request body"),
+        AppError
+    )
+)]
+async fn add_comment_handler(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<CreateCommentRequest>,
+) -> Result<(StatusCode, Json<AuditComment>), AppError> {
+    let comment =
+        services::add_comment(state.db.primary(), id, &claims.username, &payload.body).await?;
+    Ok((StatusCode::CREATED, Json(comment)))
+}
+
+/// Handles REST requests to list the reviewer comments of an audit.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `id` - The UUID of the audit whose comments to list.
+///
+/// # Returns
+///
+/// * `Ok(Json<Vec<AuditComment>>)` - On success, returns the comments, oldest first.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    get,
+    path = "/audit/{id}/comments",
+    tag = "comments",
+    params(("id" = Uuid, Path, description = "The audit identifier")),
+    responses(
+        (status = 200, description = "Comments of the audit", body = Vec<AuditComment>),
+        AppError
+    )
+)]
+async fn list_comments_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<AuditComment>>, AppError> {
+    let comments = services::list_comments(state.db.read(), id).await?;
+    Ok(Json(comments))
+}
+
+/// Handles REST requests to rate the quality of an audit.
+///
+/// The audit is rated in the name of the user the access token was issued to, and a
+/// user rating the same audit again replaces their earlier rating.
+///
+/// # Arguments
+///
+/// * `claims` - The access token of the user rating the audit.
+/// * `state` - The shared application state.
+/// * `id` - The UUID of the audit being rated.
+/// * `payload` - The JSON payload containing the score and comment.
+///
+/// # Returns
+///
+/// * `Ok(Json<AuditRating>)` - On success, returns the rating.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    post,
+    path = "/audit/{id}/rate",
+    tag = "ratings",
+    params(("id" = Uuid, Path, description = "The audit identifier")),
+    request_body = RateAuditRequest,
+    security(("access_token" = [])),
+    responses(
+        (status = 200, description = "Audit rated", body = AuditRating),
+        (status = 422, description = "Malformed request body"),
+        AppError
+    )
+)]
+async fn rate_audit_handler(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<RateAuditRequest>,
+) -> Result<Json<AuditRating>, AppError> {
+    let rating = services::rate_audit(
+        state.db.primary(),
+        id,
+        &claims.username,
+        payload.score,
+        payload.comment.as_deref(),
+    )
+    .await?;
+    Ok(Json(rating))
+}
+
+/// Handles REST requests to list the ratings of an audit.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `id` - The UUID of the audit whose ratings to list.
+///
+/// # Returns
+///
+/// * `Ok(Json<Vec<AuditRating>>)` - On success, returns the ratings, most recent first.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    get,
+    path = "/audit/{id}/ratings",
+    tag = "ratings",
+    params(("id" = Uuid, Path, description = "The audit identifier")),
+    responses(
+        (status = 200, description = "Ratings of the audit", body = Vec<AuditRating>),
+        AppError
+    )
+)]
+async fn list_ratings_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<AuditRating>>, AppError> {
+    let ratings = services::get_audit_ratings(state.db.read(), id).await?;
+    Ok(Json(ratings))
+}
+
+/// Handles REST requests to list the files of an audit submitted as several files.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `id` - The UUID of the audit whose files to list.
+///
+/// # Returns
+///
+/// * `Ok(Json<Vec<AuditFile>>)` - On success, returns the files ordered by path, none
+///   for an audit of a single file.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    get,
+    path = "/audit/{id}/files",
+    tag = "audits",
+    params(("id" = Uuid, Path, description = "The audit identifier")),
+    responses(
+        (status = 200, description = "Files of the audit", body = Vec<AuditFile>),
+        AppError
+    )
+)]
+async fn list_audit_files_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<AuditFile>>, AppError> {
+    services::get_audit_by_id(state.db.read(), id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Audit {} not found", id)))?;
+    let files = services::get_audit_files(state.db.read(), id).await?;
+    Ok(Json(files))
+}
+
+/// Handles REST requests for the SARIF 2.1.0 log of an audit.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `id` - The UUID of the audit.
+///
+/// # Returns
+///
+/// * `Ok(Response)` - On success, returns the SARIF log as `application/sarif+json`.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    get,
+    path = "/audit/{id}/sarif",
+    tag = "audits",
+    params(("id" = Uuid, Path, description = "The audit identifier")),
+    responses(
+        (status = 200, description = "The findings and compiler diagnostics as a SARIF 2.1.0 log", content_type = "application/sarif+json", body = Object),
+        AppError
+    )
+)]
+async fn sarif_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, AppError> {
+    let sarif = services::get_audit_sarif(state.db.read(), id).await?;
+    Ok((
+        [(header::CONTENT_TYPE, "application/sarif+json")],
+        Json(sarif),
+    )
+        .into_response())
+}
+
+/// Handles REST requests to retrieve the audits an audit derives from.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `id` - The UUID of the audit.
+///
+/// # Returns
+///
+/// * `Ok(Json<Vec<AiAudit>>)` - On success, returns the audits from the original one to
+///   the given one, following `parent_audit_id`.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    get,
+    path = "/audit/{id}/lineage",
+    tag = "audits",
+    params(("id" = Uuid, Path, description = "The audit identifier")),
+    responses(
+        (status = 200, description = "The ancestry of the audit, oldest first", body = Vec<AiAudit>),
+        AppError
+    )
+)]
+async fn audit_lineage_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<AiAudit>>, AppError> {
+    services::get_audit_lineage(state.db.read(), id)
+        .await
+        .map(Json)
+}
+
+/// Handles REST requests to ask a model for a corrected version of an audit that does
+/// not compile.
+///
+/// # Arguments
+///
+/// * `_caller` - Proof that the request is authenticated, with the admin token or an
+///   access token.
+/// * `state` - The shared application state.
+/// * `id` - The UUID of the audit to fix.
+/// * `payload` - The optional JSON payload choosing the model.
+///
+/// # Returns
+///
+/// * `Ok(Response)` - On success, returns a `201 CREATED` status and the audit of the
+///   corrected code, which may still not compile.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    post,
+    path = "/audit/{id}/fix",
+    tag = "audits",
+    params(("id" = Uuid, Path, description = "The identifier of the audit to fix")),
+    request_body(content = Option<FixAuditRequest>,
+        description = "The model to ask, required if the code was not generated by this server"),
+    security(("admin_token" = []), ("access_token" = [])),
+    responses(
+        (status = 201, description = "The audit of the corrected code", body = AiAudit),
+        AppError
+    )
+)]
+async fn fix_audit_handler(
+    _caller: Caller,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    payload: Option<Json<FixAuditRequest>>,
+) -> Result<Response, AppError> {
+    state.shutdown.check()?;
+    let Json(payload) = payload.unwrap_or_default();
+    let audit = services::attempt_fix(
+        state.db.primary(),
+        &state.policy,
+        &state.compiler,
+        &state.generators,
+        id,
+        payload.generate,
+        &state.notifiers,
+    )
+    .await?;
+    Ok((StatusCode::CREATED, Json(audit)).into_response())
+}
+
+/// Handles REST requests to download the code of an audit as a Rust source file.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `id` - The UUID of the audit.
+///
+/// # Returns
+///
+/// * `Ok(Response)` - On success, returns the code as a `text/x-rust` attachment named
+///   `audit_<id>.rs`.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    get,
+    path = "/audit/{id}/download",
+    tag = "audits",
+    params(("id" = Uuid, Path, description = "The audit identifier")),
+    responses(
+        (status = 200, description = "The generated code", content_type = "text/x-rust", body = String,
+            headers(("Content-Disposition" = String, description = "`attachment; filename=\"audit_<id>.rs\"`"))),
+        AppError
+    )
+)]
+async fn download_audit_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, AppError> {
+    let audit = services::get_audit_by_id(state.db.read(), id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Audit {} not found", id)))?;
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                "text/x-rust; charset=utf-8".to_string(),
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"audit_{}.rs\"", audit.id),
+            ),
+        ],
+        audit.generated_code,
+    )
+        .into_response())
+}
+
+/// Handles REST requests to list the files kept from the compilation of an audit.
+///
+/// The files are uploaded after the audit is stored, so none are listed until the
+/// upload completes (`uploaded_at`).
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `id` - The UUID of the audit.
+///
+/// # Returns
+///
+/// * `Ok(Json<AuditArtifacts>)` - On success, returns the files with their download URLs.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    get,
+    path = "/audit/{id}/artifacts",
+    tag = "audits",
+    params(("id" = Uuid, Path, description = "The audit identifier")),
+    responses(
+        (status = 200, description = "Artifacts of the audit", body = AuditArtifacts),
+        AppError
+    )
+)]
+async fn list_artifacts_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<AuditArtifacts>, AppError> {
+    let artifacts =
+        services::get_audit_artifacts(state.db.read(), state.compiler.artifacts(), id).await?;
+    Ok(Json(artifacts))
+}
+
+/// Handles REST requests to download a file kept from the compilation of an audit.
+///
+/// This is the download URL of the files of a store that cannot presign URLs, such as
+/// the local filesystem; files kept in S3 can be downloaded through here as well.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `id` - The UUID of the audit.
+/// * `name` - The name of the file: `diagnostics.json`, `clippy.json` or `lib.rlib`.
+///
+/// # Returns
+///
+/// * `Ok(Response)` - On success, returns the file as an attachment.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    get,
+    path = "/audit/{id}/artifacts/{name}",
+    tag = "audits",
+    params(
+        ("id" = Uuid, Path, description = "The audit identifier"),
+        ("name" = String, Path, description = "The name of the file: `diagnostics.json`, `clippy.json` or `lib.rlib`")
+    ),
+    responses(
+        (status = 200, description = "The file", content_type = "application/octet-stream", body = Vec<u8>,
+            headers(("Content-Disposition" = String, description = "`attachment; filename=\"<name>\"`"))),
+        AppError
+    )
+)]
+async fn download_artifact_handler(
+    State(state): State<AppState>,
+    Path((id, name)): Path<(Uuid, String)>,
+) -> Result<Response, AppError> {
+    let data = state.compiler.artifacts().download(id, &name).await?;
+    let content_type = if name.ends_with(".json") {
+        "application/json"
+    } else {
+        "application/octet-stream"
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", name),
+            ),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+/// Handles REST requests to view the code of an audit as plain text.
+///
+/// Unlike `/audit/{id}/download`, the code is served inline, so that it can be opened in
+/// a browser or piped from `curl` into an editor.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `id` - The UUID of the audit.
+///
+/// # Returns
+///
+/// * `Ok(Response)` - On success, returns the code as `text/plain`, named `audit-<id>.rs`.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    get,
+    path = "/audit/{id}/code",
+    tag = "audits",
+    params(("id" = Uuid, Path, description = "The audit identifier")),
+    responses(
+        (status = 200, description = "The generated code", content_type = "text/plain", body = String,
+            headers(("Content-Disposition" = String, description = "`inline; filename=\"audit-<id>.rs\"`"))),
+        AppError
+    )
+)]
+async fn audit_code_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, AppError> {
+    let audit = services::get_audit_by_id(state.db.read(), id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Audit {} not found", id)))?;
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                "text/plain; charset=utf-8".to_string(),
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("inline; filename=\"audit-{}.rs\"", audit.id),
+            ),
+        ],
+        audit.generated_code,
+    )
+        .into_response())
+}
+
+/// Handles REST requests for the syntax-highlighted code of an audit.
+///
+/// Lines with findings or compiler diagnostics carry a marker whose tooltip lists their
+/// messages. Very large files are truncated, with a notice at the end.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `id` - The UUID of the audit.
+///
+/// # Returns
+///
+/// * `Ok(Response)` - On success, returns the code as a standalone HTML page.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    get,
+    path = "/audit/{id}/code.html",
+    tag = "audits",
+    params(("id" = Uuid, Path, description = "The audit identifier")),
+    responses(
+        (status = 200, description = "The generated code, syntax-highlighted and annotated with its findings", content_type = "text/html", body = String),
+        AppError
+    )
+)]
+async fn audit_code_html_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, AppError> {
+    let audit = services::get_audit_by_id(state.db.read(), id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Audit {} not found", id)))?;
+    let fragment = match state.highlights.get(&audit) {
+        Some(fragment) => fragment,
+        None => {
+            let diagnostics = services::get_audit_diagnostics(state.db.read(), id).await?;
+            state.highlights.render(&audit, diagnostics).await?
+        }
+    };
+    Ok((
+        [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
+        highlight::render_page(audit.id, &fragment),
+    )
+        .into_response())
+}
+
+/// The formats a badge can be served in, chosen by the file extension.
+#[derive(Debug, Clone, Copy)]
+enum BadgeFormat {
+    /// A rendered SVG image (`.svg`).
+    Svg,
+    /// The shields.io endpoint JSON format (`.json`).
+    Json,
+}
+
+impl BadgeFormat {
+    /// Returns the format matching a file extension, if supported.
+    fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "svg" => Some(BadgeFormat::Svg),
+            "json" => Some(BadgeFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Builds the response holding a validity badge.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `project` - The tag identifying the project, or `None` for every audit.
+/// * `format` - The format to serve the badge in.
+///
+/// # Returns
+///
+/// * `Ok(Response)` - The badge, with a `Cache-Control` header.
+/// * `Err(AppError)` - If the validity counts cannot be computed.
+async fn badge_response(
+    state: &AppState,
+    project: Option<&str>,
+    format: BadgeFormat,
+) -> Result<Response, AppError> {
+    let counts = state
+        .badges
+        .validity_counts(state.db.read(), project)
+        .await?;
+    let badge = Badge::validity(counts);
+    let cache_control = (header::CACHE_CONTROL, badges::CACHE_CONTROL);
+    Ok(match format {
+        BadgeFormat::Svg => (
+            [(header::CONTENT_TYPE, "image/svg+xml"), cache_control],
+            badge.to_svg(),
+        )
+            .into_response(),
+        BadgeFormat::Json => ([cache_control], Json(badge.to_shields_json())).into_response(),
+    })
+}
+
+/// Handles REST requests for the validity badge of all audits, as an SVG image.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// * `Ok(Response)` - On success, returns the badge.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    get,
+    path = "/badge.svg",
+    tag = "badges",
+    responses(
+        (status = 200, description = "The share of valid audits", content_type = "image/svg+xml", body = String),
+        AppError
+    )
+)]
+async fn global_svg_badge_handler(State(state): State<AppState>) -> Result<Response, AppError> {
+    badge_response(&state, None, BadgeFormat::Svg).await
+}
+
+/// Handles REST requests for the validity badge of all audits, in the shields.io
+/// endpoint format.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// * `Ok(Response)` - On success, returns the badge.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    get,
+    path = "/badge.json",
+    tag = "badges",
+    responses(
+        (status = 200, description = "The share of valid audits, as `{schemaVersion, label, message, color}`", body = Object),
+        AppError
+    )
+)]
+async fn global_json_badge_handler(State(state): State<AppState>) -> Result<Response, AppError> {
+    badge_response(&state, None, BadgeFormat::Json).await
+}
+
+/// Handles REST requests for the validity badge of a project.
+///
+/// Projects are identified by a tag. Unknown projects get a gray "unknown" badge rather
+/// than an error, so that READMEs embedding the badge do not show a broken image.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `file` - The project tag followed by `.svg` or `.json`.
+///
+/// # Returns
+///
+/// * `Ok(Response)` - On success, returns the badge.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    get,
+    path = "/badge/project/{file}",
+    tag = "badges",
+    params(("file" = String, Path, description = "The project tag followed by `.svg` or `.json`, e.g. `my-project.svg`")),
+    responses(
+        (status = 200, description = "The share of valid audits of the project, as an SVG image or shields.io endpoint JSON", content_type = "image/svg+xml", body = String),
+        AppError
+    )
+)]
+async fn project_badge_handler(
+    State(state): State<AppState>,
+    Path(file): Path<String>,
+) -> Result<Response, AppError> {
+    let (project, format) = file
+        .rsplit_once('.')
+        .and_then(|(project, extension)| Some((project, BadgeFormat::from_extension(extension)?)))
+        .ok_or_else(|| {
+            AppError::NotFound(format!(
+                "Badges are served as .svg or .json, not '{}'",
+                file
+            ))
+        })?;
+    badge_response(&state, Some(project), format).await
+}
+
+/// Handles REST requests to delete a reviewer comment from an audit.
+///
+/// Users may only delete their own comments; administrators (the admin token, or users
+/// with the `admin` role) may delete any comment.
+///
+/// # Arguments
+///
+/// * `caller` - The authenticated caller deleting the comment.
+/// * `state` - The shared application state.
+/// * `id` - The UUID of the audit the comment belongs to.
+/// * `comment_id` - The UUID of the comment to delete.
+///
+/// # Returns
+///
+/// * `Ok(StatusCode)` - On success, returns a `204 NO CONTENT` status.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    delete,
+    path = "/audit/{id}/comments/{comment_id}",
+    tag = "comments",
+    params(
+        ("id" = Uuid, Path, description = "The audit identifier"),
+        ("comment_id" = Uuid, Path, description = "The comment identifier")
+    ),
+    security(("admin_token" = []), ("access_token" = [])),
+    responses(
+        (status = 204, description = "Comment deleted"),
+        AppError
+    )
+)]
+async fn delete_comment_handler(
+    caller: Caller,
+    State(state): State<AppState>,
+    Path((id, comment_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, AppError> {
+    services::delete_comment(state.db.primary(), id, comment_id, caller.restricted_to()).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// The query parameters accepted by the re-run endpoint.
+#[derive(Debug, Deserialize, utoipa::IntoParams)]
+struct RerunParams {
+    /// The maximum number of failed audits to recompile (1 to 1000, defaults to 100).
+    limit: Option<i64>,
+}
+
+/// Handles REST requests to recompile previously failing audits.
+///
+/// # Arguments
+///
+/// * `_admin` - Proof that the request is authenticated as an administrator.
+/// * `state` - The shared application state.
+/// * `params` - The query parameters limiting how many audits are recompiled.
+///
+/// # Returns
+///
+/// * `Ok(Json<RerunReport>)` - On success, returns a summary of the re-run.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    post,
+    path = "/admin/rerun-failed",
+    tag = "admin",
+    params(RerunParams),
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "Summary of the re-run", body = RerunReport),
+        AppError
+    )
+)]
+async fn rerun_failed_handler(
+    _admin: AdminAuth,
+    State(state): State<AppState>,
+    Query(params): Query<RerunParams>,
+) -> Result<Json<RerunReport>, AppError> {
+    let limit = params.limit.unwrap_or(100);
+    if !(1..=1000).contains(&limit) {
+        return Err(AppError::Validation(
+            "limit must be between 1 and 1000".to_string(),
+        ));
+    }
+    let report =
+        services::rerun_failed_audits(state.db.primary(), &state.policy, &state.compiler, limit)
+            .await?;
+    Ok(Json(report))
+}
+
+/// Handles REST requests to re-audit every audit that failed to compile.
+///
+/// Requires the admin bearer token. The whole table is processed, in batches, before
+/// the response is sent.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// * `Ok(Json<ReauditReport>)` - On success, returns a summary of the re-audit.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    post,
+    path = "/audits/reaudit-invalid",
+    tag = "admin",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "Summary of the re-audit", body = ReauditReport),
+        AppError
+    )
+)]
+async fn reaudit_invalid_handler(
+    _admin: AdminAuth,
+    State(state): State<AppState>,
+) -> Result<Json<ReauditReport>, AppError> {
+    let report =
+        services::reaudit_invalid_audits(state.db.primary(), &state.policy, &state.compiler)
+            .await?;
+    Ok(Json(report))
+}
+
+/// Handles REST requests to import audit records without compiling their code.
+///
+/// This is meant for seeding a database, e.g. with audits exported from another
+/// instance. Invalid records are reported, records whose id is already used are skipped,
+/// and the others are stored as given. It is served at `/admin/import`, and at
+/// `/audits/import` for existing clients.
+///
+/// # Arguments
+///
+/// * `_admin` - Proof that the request is authenticated as an administrator.
+/// * `state` - The shared application state.
+/// * `records` - The JSON array of audit records to import.
+///
+/// # Returns
+///
+/// * `Ok(Json<ImportReport>)` - On success, returns how many records were imported and
+///   why the others were rejected.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    post,
+    path = "/admin/import",
+    tag = "admin",
+    request_body = [ImportAuditRecord],
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "Import summary", body = ImportReport),
+        (status = 422, description = "The body is not a JSON array"),
+        AppError
+    )
+)]
+async fn import_audits_handler(
+    _admin: AdminAuth,
+    State(state): State<AppState>,
+    Json(records): Json<Vec<serde_json::Value>>,
+) -> Result<Json<ImportReport>, AppError> {
+    let report = services::import_audits(state.db.primary(), records).await?;
+    Ok(Json(report))
+}
+
+/// Handles REST requests to register a webhook notified when audits complete.
+///
+/// # Arguments
+///
+/// * `_admin` - Proof that the request is authenticated as an administrator.
+/// * `state` - The shared application state.
+/// * `payload` - The JSON payload containing the URL, secret and events of the webhook.
+///
+/// # Returns
+///
+/// * `Ok((StatusCode, Json<Webhook>))` - On success, returns a `201 CREATED` status and
+///   the registered webhook, without its secret.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    post,
+    path = "/admin/webhooks",
+    tag = "admin",
+    request_body = CreateWebhookRequest,
+    security(("admin_token" = [])),
+    responses(
+        (status = 201, description = "Webhook registered", body = Webhook),
+        (status = 422, description = "Malformed request body"),
+        AppError
+    )
+)]
+async fn create_webhook_handler(
+    _admin: AdminAuth,
+    State(state): State<AppState>,
+    Json(payload): Json<CreateWebhookRequest>,
+) -> Result<(StatusCode, Json<Webhook>), AppError> {
+    let webhook = services::create_webhook(state.db.primary(), &payload).await?;
+    Ok((StatusCode::CREATED, Json(webhook)))
+}
+
+/// Handles REST requests to list the registered webhooks.
+///
+/// # Arguments
+///
+/// * `_admin` - Proof that the request is authenticated as an administrator.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// * `Ok(Json<Vec<Webhook>>)` - On success, returns the webhooks, oldest first.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    get,
+    path = "/admin/webhooks",
+    tag = "admin",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "The registered webhooks", body = [Webhook]),
+        AppError
+    )
+)]
+async fn list_webhooks_handler(
+    _admin: AdminAuth,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Webhook>>, AppError> {
+    let webhooks = services::list_webhooks(state.db.read()).await?;
+    Ok(Json(webhooks))
+}
+
+/// Handles REST requests to remove a webhook.
+///
+/// # Arguments
+///
+/// * `_admin` - Proof that the request is authenticated as an administrator.
+/// * `state` - The shared application state.
+/// * `id` - The UUID of the webhook to remove.
+///
+/// # Returns
+///
+/// * `Ok(StatusCode)` - On success, returns a `204 NO CONTENT` status.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    delete,
+    path = "/admin/webhooks/{id}",
+    tag = "admin",
+    params(("id" = Uuid, Path, description = "The webhook identifier")),
+    security(("admin_token" = [])),
+    responses(
+        (status = 204, description = "Webhook removed"),
+        AppError
+    )
+)]
+async fn delete_webhook_handler(
+    _admin: AdminAuth,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    services::delete_webhook(state.db.primary(), id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
 
-// Declare application modules.
-mod auditor;
-mod error;
-mod models;
-mod schema;
-mod services;
+/// Handles REST requests for a sample configuration file.
+///
+/// # Arguments
+///
+/// * `_admin` - Proof that the request is authenticated as an administrator.
+///
+/// # Returns
+///
+/// * `impl IntoResponse` - The TOML file listing every server setting with its default,
+///   to be loaded with `CONFIG_FILE`.
+#[utoipa::path(
+    get,
+    path = "/admin/config-template",
+    tag = "admin",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "A sample configuration file", content_type = "application/toml", body = String),
+        AppError
+    )
+)]
+async fn config_template_handler(_admin: AdminAuth) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "application/toml; charset=utf-8")],
+        config::CONFIG_TEMPLATE,
+    )
+}
 
-// Import items from our modules.
-use crate::error::AppError;
-use models::{AiAudit, AuditStats, CreateAuditRequest};
-use schema::{AppSchema, MutationRoot, QueryRoot};
+/// Handles REST requests describing the server, its toolchain and its database.
+///
+/// # Arguments
+///
+/// * `_admin` - Proof that the request is authenticated as an administrator.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// * `Ok(Json<SystemInfo>)` - On success, returns the versions and settings of the server.
+/// * `Err(AppError)` - On failure, returns an application-specific error.
+#[utoipa::path(
+    get,
+    path = "/admin/system-info",
+    tag = "admin",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "The versions and settings of the server", body = SystemInfo),
+        AppError
+    )
+)]
+async fn system_info_handler(
+    _admin: AdminAuth,
+    State(state): State<AppState>,
+) -> Result<Json<SystemInfo>, AppError> {
+    // The version is cached after the first call, which runs `rustc`.
+    let rustc_version =
+        tokio::task::spawn_blocking(|| auditor::check_rustc_available(Channel::Stable))
+            .await
+            .map_err(|e| AppError::Audit(format!("Failed to query rustc: {}", e)))?
+            .unwrap_or_else(|e| e)
+            .to_string();
+    Ok(Json(SystemInfo {
+        rustc_version,
+        server_version: env!("CARGO_PKG_VERSION").to_string(),
+        database_version: state.database_version.clone(),
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        worker_concurrency: u32::try_from(state.compiler.concurrency()).unwrap_or(u32::MAX),
+        uptime_secs: state.startup_time.elapsed().as_secs(),
+    }))
+}
 
-/// Represents the shared state that is accessible from all route handlers.
-#[derive(Clone)]
-struct AppState {
-    /// The database connection pool.
-    db: PgPool,
-    /// The GraphQL schema.
-    schema: AppSchema,
+/// Handles REST requests to exchange credentials for a short-lived access token.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `payload` - The JSON payload containing the username and password.
+///
+/// # Returns
+///
+/// * `Ok(Json<TokenResponse>)` - On success, returns the signed JWT and its lifetime.
+/// * `Err(AppError)` - On failure, returns an application-specific error, `401
+///   UNAUTHORIZED` for bad credentials or when token issuance is not configured.
+#[utoipa::path(
+    post,
+    path = "/auth/token",
+    tag = "auth",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Access token issued", body = TokenResponse),
+        (status = 422, description = "Malformed request body"),
+        AppError
+    )
+)]
+async fn issue_token_handler(
+    State(state): State<AppState>,
+    Json(payload): Json<LoginRequest>,
+) -> Result<Json<TokenResponse>, AppError> {
+    let issuer = state.token_issuer.as_ref().ok_or_else(|| {
+        AppError::Unauthorized("Access tokens are not enabled on this server".to_string())
+    })?;
+    let user =
+        services::authenticate_user(state.db.primary(), &payload.username, &payload.password)
+            .await?;
+    Ok(Json(issuer.issue(&user)?))
 }
 
-/// Handles REST requests to create a new AI code audit.
+/// Handles REST requests to create a user account, for development use.
+///
+/// The endpoint answers `404 NOT FOUND` unless `AUTH_REGISTRATION_ENABLED` is `true`.
 ///
 /// # Arguments
 ///
 /// * `state` - The shared application state.
-/// * `payload` - The JSON payload containing the audit request data.
+/// * `payload` - The JSON payload containing the username and password.
 ///
 /// # Returns
 ///
-/// * `Ok((StatusCode, Json<AiAudit>))` - On success, returns a `201 CREATED` status
-///   and the newly created audit record.
+/// * `Ok((StatusCode, Json<User>))` - On success, returns a `201 CREATED` status and the
+///   new user, without its password hash.
 /// * `Err(AppError)` - On failure, returns an application-specific error.
-async fn create_audit_handler(
+#[utoipa::path(
+    post,
+    path = "/auth/register",
+    tag = "auth",
+    request_body = LoginRequest,
+    responses(
+        (status = 201, description = "User registered", body = User),
+        (status = 422, description = "Malformed request body"),
+        AppError
+    )
+)]
+async fn register_handler(
     State(state): State<AppState>,
-    Json(payload): Json<CreateAuditRequest>,
-) -> Result<(StatusCode, Json<AiAudit>), AppError> {
-    let audit = services::create_audit(&state.db, &payload).await?;
-    Ok((StatusCode::CREATED, Json(audit)))
+    Json(payload): Json<LoginRequest>,
+) -> Result<(StatusCode, Json<User>), AppError> {
+    if !state.registration_enabled {
+        return Err(AppError::NotFound("Registration is disabled".to_string()));
+    }
+    let user =
+        services::register_user(state.db.primary(), &payload.username, &payload.password).await?;
+    Ok((StatusCode::CREATED, Json(user)))
 }
 
-/// Handles REST requests to get audit statistics.
+/// Handles webhook deliveries from GitHub.
+///
+/// Deliveries must be signed with the webhook secret. `pull_request` events that change
+/// the code of a pull request are acknowledged immediately and audited in the background;
+/// other events are ignored.
 ///
 /// # Arguments
 ///
 /// * `state` - The shared application state.
+/// * `headers` - The request headers, carrying the event name and signature.
+/// * `body` - The raw request body, as signed by GitHub.
 ///
 /// # Returns
 ///
-/// * `Ok(Json<AuditStats>)` - On success, returns the audit statistics.
-/// * `Err(AppError)` - On failure, returns an application-specific error.
-async fn stats_handler(State(state): State<AppState>) -> Result<Json<AuditStats>, AppError> {
-    let stats = services::get_audit_stats(&state.db).await?;
-    Ok(Json(stats))
+/// * `Ok(StatusCode::ACCEPTED)` - If an audit of the pull request was started.
+/// * `Ok(StatusCode::NO_CONTENT)` - If the event was ignored.
+/// * `Err(AppError)` - If the integration is disabled, the signature is invalid or the
+///   payload is malformed.
+#[utoipa::path(
+    post,
+    path = "/integrations/github/webhook",
+    tag = "integrations",
+    request_body(content = String, description = "A GitHub webhook payload", content_type = "application/json"),
+    params(
+        ("X-GitHub-Event" = String, Header, description = "The name of the event"),
+        ("X-Hub-Signature-256" = String, Header, description = "The HMAC-SHA256 signature of the body")
+    ),
+    responses(
+        (status = 202, description = "The pull request is being audited"),
+        (status = 204, description = "The event was ignored"),
+        AppError
+    )
+)]
+async fn github_webhook_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<StatusCode, AppError> {
+    let github = state
+        .github
+        .clone()
+        .ok_or_else(|| AppError::Unauthorized("GitHub integration is disabled".to_string()))?;
+
+    let header_str = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    github.verify_signature(&body, header_str("x-hub-signature-256"))?;
+
+    if header_str("x-github-event") != Some("pull_request") {
+        return Ok(StatusCode::NO_CONTENT);
+    }
+    let event: PullRequestEvent = serde_json::from_slice(&body)
+        .map_err(|e| AppError::Validation(format!("Invalid pull_request payload: {}", e)))?;
+    if !event.should_audit() {
+        return Ok(StatusCode::NO_CONTENT);
+    }
+    state.shutdown.check()?;
+
+    // GitHub expects a response within 10 seconds, so the audit runs in the background.
+    tokio::spawn(async move {
+        github::audit_pull_request(
+            state.db.primary(),
+            &state.policy,
+            &state.compiler,
+            &state.notifiers,
+            github.api.as_ref(),
+            &event,
+        )
+        .await;
+    });
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Handles liveness probes.
+///
+/// # Returns
+///
+/// * `StatusCode` - `200 OK` as long as the server answers requests.
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "health",
+    responses((status = 200, description = "The server is running"))
+)]
+async fn health_handler() -> StatusCode {
+    StatusCode::OK
+}
+
+/// Handles readiness probes.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// * `StatusCode` - `200 OK` once the database migrations have run and the compiler has
+///   been probed, `503 SERVICE UNAVAILABLE` before, or while the server shuts down.
+#[utoipa::path(
+    get,
+    path = "/ready",
+    tag = "health",
+    responses(
+        (status = 200, description = "The server is ready to handle requests"),
+        (status = 503, description = "The server is starting or shutting down")
+    )
+)]
+async fn ready_handler(State(state): State<AppState>) -> StatusCode {
+    if state.ready.load(Ordering::SeqCst) && !state.shutdown.is_triggered() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+/// Handles REST requests for the compilation worker counters.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// * `Json<WorkerStats>` - The activity counters of the compilation workers.
+#[utoipa::path(
+    get,
+    path = "/metrics/workers",
+    tag = "metrics",
+    responses((status = 200, description = "Compilation worker counters", body = WorkerStats))
+)]
+async fn worker_metrics_handler(State(state): State<AppState>) -> Json<WorkerStats> {
+    Json(state.compiler.metrics.stats())
+}
+
+/// Handles REST requests for the state of the read replica.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// * `Json<DbStats>` - Whether the read replica is configured and reachable, and how many
+///   reads fell back to the primary database.
+#[utoipa::path(
+    get,
+    path = "/metrics/db",
+    tag = "metrics",
+    responses((status = 200, description = "Read replica state and fallback counter", body = DbStats))
+)]
+async fn db_metrics_handler(State(state): State<AppState>) -> Json<DbStats> {
+    Json(state.db.stats())
+}
+
+/// Handles REST requests for the Automatic Persisted Queries counters.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// * `Json<ApqStats>` - The hit, miss and registration counters.
+#[utoipa::path(
+    get,
+    path = "/metrics/apq",
+    tag = "metrics",
+    responses((status = 200, description = "Persisted query counters", body = ApqStats))
+)]
+async fn apq_metrics_handler(State(state): State<AppState>) -> Json<ApqStats> {
+    Json(state.persisted_queries.stats())
+}
+
+/// Handles REST requests for the counters of the in-memory cache of audit results.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// * `Json<AuditCacheStats>` - The size, hit, miss and bypass counters of the cache.
+#[utoipa::path(
+    get,
+    path = "/metrics/audit-cache",
+    tag = "metrics",
+    responses((status = 200, description = "Audit result cache counters", body = AuditCacheStats))
+)]
+async fn audit_cache_metrics_handler(State(state): State<AppState>) -> Json<AuditCacheStats> {
+    Json(state.compiler.audit_results().stats())
 }
 
 /// The main handler for all GraphQL requests.
 ///
-/// It executes the incoming GraphQL query against the schema.
+/// It executes the incoming GraphQL query against the schema. Requests authenticated
+/// with the admin bearer token may also run administrative mutations, and requests
+/// carrying a user access token the mutations acting in the name of a user.
 ///
 /// # Arguments
 ///
 /// * `state` - The shared application state.
+/// * `admin` - Whether the request is authenticated as an administrator.
+/// * `claims` - The access token of the user sending the request, if valid.
 /// * `req` - The incoming GraphQL request.
 ///
 /// # Returns
 ///
 /// * `GraphQLResponse` - The result of the query execution.
-async fn graphql_handler(State(state): State<AppState>, req: GraphQLRequest) -> GraphQLResponse {
-    state.schema.execute(req.into_inner()).await.into()
+async fn graphql_handler(
+    State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    admin: Result<AdminAuth, AppError>,
+    claims: Result<Claims, AppError>,
+    req: GraphQLRequest,
+) -> GraphQLResponse {
+    let mut request = req.into_inner().data(ClientAddr(client.ip()));
+    if let Ok(admin) = admin {
+        request = request.data(admin);
+    }
+    if let Ok(claims) = claims {
+        request = request.data(claims);
+    }
+    state.schema.execute(request).await.into()
+}
+
+/// Refuses the requests creating audits of clients over their rate limit (see
+/// `RateLimiter`).
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `client` - The address of the client.
+/// * `request` - The request, passed on to the route if it is allowed.
+/// * `next` - The route handler.
+///
+/// # Returns
+///
+/// * `Response` - The response of the route, or `429 Too Many Requests`.
+async fn limit_audit_creation(
+    State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match state.rate_limiter.check(client.ip()) {
+        Ok(()) => next.run(request).await,
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Handles GraphQL requests sent over `GET`, as described by the GraphQL-over-HTTP spec.
+///
+/// The query, variables and operation name are read from the query string. Mutations are
+/// rejected with `405 Method Not Allowed` because `GET` requests must be safe. Browsers
+/// (clients preferring HTML over JSON) are served the GraphiQL IDE instead, if it is
+/// enabled.
+///
+/// # Arguments
+///
+/// * `state` - The shared application state.
+/// * `headers` - The request headers, used for content negotiation.
+/// * `req` - The GraphQL request parsed from the query string, if any.
+///
+/// # Returns
+///
+/// * `Response` - The query result, the GraphiQL page, or an error response.
+async fn graphql_get_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    req: Result<GraphQLRequest, GraphQLRejection>,
+) -> Response {
+    if state.config.graphiql_enabled && prefers_html(&headers) {
+        return graphiql().await.into_response();
+    }
+
+    let mut request = match req {
+        Ok(req) => req.into_inner(),
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    // Resolve persisted queries first, so that a stored mutation cannot be run over GET.
+    if let Err(error) = state.persisted_queries.resolve(&mut request).await {
+        return GraphQLResponse::from(async_graphql::Response::from_errors(vec![error]))
+            .into_response();
+    }
+
+    if is_mutation(&request) {
+        let body = Json(ErrorResponse {
+            error: "Mutations are not allowed over GET; use POST instead".to_string(),
+        });
+        return (
+            StatusCode::METHOD_NOT_ALLOWED,
+            [(header::ALLOW, "POST")],
+            body,
+        )
+            .into_response();
+    }
+
+    GraphQLResponse::from(state.schema.execute(request).await).into_response()
+}
+
+/// Returns whether the `Accept` header ranks HTML above JSON.
+///
+/// A wildcard (`*/*`) only counts towards JSON, so that API clients which accept
+/// anything (e.g. `curl`) keep receiving JSON.
+fn prefers_html(headers: &HeaderMap) -> bool {
+    let Some(accept) = headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+
+    let (mut html_quality, mut json_quality) = (0.0_f32, 0.0_f32);
+    for media_range in accept.split(',') {
+        let mut parts = media_range.split(';').map(str::trim);
+        let media_type = parts.next().unwrap_or_default();
+        let quality = parts
+            .find_map(|param| param.strip_prefix("q="))
+            .and_then(|q| q.parse().ok())
+            .unwrap_or(1.0);
+        match media_type {
+            "text/html" => html_quality = html_quality.max(quality),
+            "application/json" | "application/graphql-response+json" | "*/*" => {
+                json_quality = json_quality.max(quality)
+            }
+            _ => {}
+        }
+    }
+    html_quality > 0.0 && html_quality >= json_quality
+}
+
+/// Returns whether the operation selected by a GraphQL request is a mutation.
+///
+/// Documents that fail to parse are not considered mutations; executing them reports
+/// the parse error to the client.
+fn is_mutation(request: &async_graphql::Request) -> bool {
+    let Ok(document) = parse_query(&request.query) else {
+        return false;
+    };
+    match &document.operations {
+        DocumentOperations::Single(operation) => operation.node.ty == OperationType::Mutation,
+        DocumentOperations::Multiple(operations) => operations
+            .iter()
+            .filter(|(name, _)| {
+                request
+                    .operation_name
+                    .as_deref()
+                    .is_none_or(|selected| name.as_str() == selected)
+            })
+            .any(|(_, operation)| operation.node.ty == OperationType::Mutation),
+    }
+}
+
+/// Handles requests using a method that the matched route does not support.
+///
+/// Axum adds the `Allow` header listing the supported methods to this response.
+///
+/// # Returns
+///
+/// * `impl IntoResponse` - A `405 METHOD NOT ALLOWED` status with a JSON error body.
+async fn method_not_allowed() -> impl IntoResponse {
+    let body = Json(ErrorResponse {
+        error: "Method not allowed".to_string(),
+    });
+    (StatusCode::METHOD_NOT_ALLOWED, body)
+}
+
+/// Forgets expired idempotency keys and deletes expired audits every
+/// [`RETENTION_INTERVAL`], for as long as the server runs.
+///
+/// # Arguments
+///
+/// * `db` - The database connection pool.
+/// * `ttl` - How long a key is remembered after its audit was created.
+/// * `audit_retention` - How long audits are kept, or `None` to keep them forever.
+async fn run_retention_job(db: PgPool, ttl: Duration, audit_retention: Option<Duration>) {
+    // The first run waits for the interval, leaving the startup time to run the migrations.
+    let start = tokio::time::Instant::now() + RETENTION_INTERVAL;
+    let mut interval = tokio::time::interval_at(start, RETENTION_INTERVAL);
+    loop {
+        interval.tick().await;
+        match services::expire_idempotency_keys(&db, ttl).await {
+            Ok(0) => {}
+            Ok(expired) => tracing::info!(expired, "Forgot expired idempotency keys"),
+            Err(e) => tracing::warn!(error = %e, "Failed to forget expired idempotency keys"),
+        }
+        let Some(retention) = audit_retention else {
+            continue;
+        };
+        match services::delete_expired_audits(&db, retention).await {
+            Ok(0) => {}
+            Ok(deleted) => tracing::info!(deleted, "Deleted expired audits"),
+            Err(e) => tracing::warn!(error = %e, "Failed to delete expired audits"),
+        }
+    }
+}
+
+/// Removes the stale files of interrupted compilations every [`TEMP_SWEEP_INTERVAL`],
+/// for as long as the server runs.
+///
+/// # Arguments
+///
+/// * `max_age` - How old the files get before they are removed.
+async fn run_temp_file_sweeper(max_age: Duration) {
+    let mut interval = tokio::time::interval(TEMP_SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        match tokio::task::spawn_blocking(move || auditor::sweep_temp_files(max_age)).await {
+            Ok(0) => {}
+            Ok(removed) => tracing::info!(removed, "Removed stale temporary compilation files"),
+            Err(e) => tracing::warn!(error = %e, "Failed to remove stale temporary files"),
+        }
+    }
 }
 
 /// Serves the GraphiQL user interface.
@@ -97,49 +2602,483 @@ async fn graphql_handler(State(state): State<AppState>, req: GraphQLRequest) ->
 ///
 /// * `impl IntoResponse` - An HTML response containing the GraphiQL page.
 async fn graphiql() -> impl IntoResponse {
-    Html(GraphiQLSource::build().endpoint("/graphql").finish())
+    Html(
+        GraphiQLSource::build()
+            .endpoint("/graphql")
+            .subscription_endpoint("/graphql/ws")
+            .finish(),
+    )
+}
+
+/// Redirects the former location of the Swagger UI to `/swagger-ui/`.
+///
+/// # Returns
+///
+/// * `Redirect` - A permanent redirect to the Swagger UI.
+async fn docs_redirect() -> Redirect {
+    Redirect::permanent("/swagger-ui/")
+}
+
+/// The command-line arguments of the application.
+#[derive(Debug, Parser)]
+#[command(version, about = "Audits AI-generated Rust code")]
+struct Args {
+    /// The command to run, `serve` by default.
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// The commands of the application.
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run the web server.
+    Serve,
+    /// Audit local files without the web server or a database.
+    Audit(cli::AuditArgs),
 }
 
 /// The main entry point of the application.
 ///
-/// It initializes the logger, loads environment variables, connects to the database,
-/// runs migrations, builds the application state and router, and starts the web server.
+/// It parses the command line, initializes the logger and loads environment variables,
+/// then either runs the web server or audits local files.
 ///
 /// # Returns
 ///
-/// * `anyhow::Result<()>` - Returns `Ok(())` on successful server shutdown,
-///   or an error if any part of the setup or server execution fails.
+/// * `Ok(ExitCode)` - The exit code: failure if the `audit` subcommand found invalid
+///   files (1) or could not complete (2).
+/// * `Err(anyhow::Error)` - If any part of the server setup or execution fails.
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
+async fn main() -> anyhow::Result<ExitCode> {
+    let args = Args::parse();
+    let command = args.command.unwrap_or(Command::Serve);
+
+    // Load environment variables from a .env file if it exists.
+    dotenvy::dotenv().ok();
+
+    // Read the settings of the server, which say where its spans are exported.
+    let config = match command {
+        Command::Serve => Some(Arc::new(
+            AppConfig::load().context("Invalid server configuration")?,
+        )),
+        Command::Audit(_) => None,
+    };
+    let (otlp_layer, tracer_provider) =
+        match config.as_ref().and_then(|c| c.otlp_endpoint.as_deref()) {
+            Some(endpoint) => {
+                let (layer, provider) = telemetry::otlp_layer(endpoint)?;
+                (Some(layer), Some(provider))
+            }
+            None => (None, None),
+        };
+
     // Initialize tracing subscriber for logging.
     // It reads the log level from the `RUST_LOG` environment variable,
-    // defaulting to "rust_ai_auditor=info".
+    // defaulting to "rust_ai_auditor=info" for the server. The `audit` subcommand logs
+    // errors only, to stderr, so that its output can be parsed.
+    let default_filter = match command {
+        Command::Serve => "rust_ai_auditor=info",
+        Command::Audit(_) => "rust_ai_auditor=error",
+    };
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "rust_ai_auditor=info".into()),
+                .unwrap_or_else(|_| default_filter.into()),
         )
-        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_subscriber::fmt::layer().with_writer(match command {
+            Command::Serve => BoxMakeWriter::new(std::io::stdout),
+            Command::Audit(_) => BoxMakeWriter::new(std::io::stderr),
+        }))
+        .with(otlp_layer)
         .init();
 
-    // Load environment variables from a .env file if it exists.
-    dotenvy::dotenv().ok();
+    match (command, config) {
+        (Command::Serve, Some(config)) => {
+            let result = serve(config).await;
+            // Send the spans still waiting for their batch.
+            if let Some(provider) = tracer_provider
+                && let Err(e) = provider.shutdown()
+            {
+                tracing::warn!(error = %e, "Failed to export the last spans");
+            }
+            result.map(|()| ExitCode::SUCCESS)
+        }
+        (Command::Serve, None) => unreachable!("the settings of the server are read above"),
+        (Command::Audit(args), _) => match cli::run(args).await {
+            Ok(code) => Ok(code),
+            Err(e) => {
+                eprintln!("error: {:#}", e);
+                Ok(ExitCode::from(2))
+            }
+        },
+    }
+}
+
+/// Connects to the database, runs migrations, builds the application state and router,
+/// and runs the web server.
+///
+/// # Arguments
+///
+/// * `config` - The settings of the server.
+///
+/// # Returns
+///
+/// * `anyhow::Result<()>` - Returns `Ok(())` on successful server shutdown,
+///   or an error if any part of the setup or server execution fails.
+async fn serve(config: Arc<AppConfig>) -> anyhow::Result<()> {
+    let startup_time = Instant::now();
 
-    // Get the database URL from the environment.
-    let database_url = std::env::var("DATABASE_URL")
-        .context("DATABASE_URL must be set in the environment or .env file")?;
+    // Retry the queries failing while the database fails over.
+    services::set_db_retry_policy(config.db_retry);
+
+    // Compile packages and nightly code with the configured toolchain.
+    auditor::set_toolchain(config.toolchain.clone());
 
     // Create a database connection pool.
     let db = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&database_url)
+        .max_connections(config.max_db_connections)
+        .connect(&config.database_url)
         .await
         .context("Failed to connect to Postgres")?;
 
     // Verify the database connection with a test query.
-    let version: (String,) = sqlx::query_as("SELECT version()").fetch_one(&db).await?;
-    tracing::info!(db_version = %version.0, "Successfully connected to Postgres");
+    let version = sqlx::query_scalar!(r#"SELECT version() AS "version!""#)
+        .fetch_one(&db)
+        .await?;
+    tracing::info!(db_version = %version, "Successfully connected to Postgres");
+
+    // Send pure reads to the read replica, if one is configured. The server starts even
+    // if the replica is unreachable: reads go to the primary until it answers.
+    let read_db = match &config.read_replica_url {
+        Some(url) => Some(
+            PgPoolOptions::new()
+                .max_connections(config.max_db_connections)
+                .connect_lazy(url)
+                .context("Invalid read replica URL")?,
+        ),
+        None => None,
+    };
+    let db = Db::new(db, read_db);
+    db.check_replica().await;
+    tokio::spawn(db.clone().watch_replica());
+
+    // Run the database migrations and probe the compiler in the background, so that the
+    // server answers liveness probes meanwhile. `/ready` reports when this is done.
+    let ready = Arc::new(AtomicBool::new(false));
+    let startup = tokio::spawn(prepare(db.primary().clone(), ready.clone()));
+
+    // Create the store of Automatic Persisted Queries.
+    let persisted_queries = Arc::new(
+        PersistedQueryStore::from_env(db.primary())
+            .context("Invalid persisted query configuration")?,
+    );
+
+    // Forget the idempotency keys of old audits, so that their requests can be sent again,
+    // and delete the audits past their retention period.
+    tokio::spawn(run_retention_job(
+        db.primary().clone(),
+        config.idempotency_key_ttl,
+        config.audit_retention,
+    ));
+
+    // Remove the files left behind by compilations interrupted by a crash.
+    tokio::spawn(run_temp_file_sweeper(config.temp_file_max_age));
+
+    // Read the server-wide audit settings.
+    let policy = config.audit_policy.clone();
+
+    // Start the workers compiling audited code.
+    let cache = CacheSettings::from_env()
+        .await
+        .context("Invalid compilation cache configuration")?;
+    let artifacts = ArtifactSettings::from_env().context("Invalid artifact store configuration")?;
+    let audit_results =
+        AuditResultCache::from_env().context("Invalid audit result cache configuration")?;
+    let advisories =
+        AdvisoryDatabase::from_env().context("Invalid advisory database configuration")?;
+    tokio::spawn(advisories.clone().run_refresh());
+    let compiler = CompilationQueue::start(config.worker_concurrency, config.compilation_timeout)
+        .with_cache(cache)
+        .with_artifacts(artifacts)
+        .with_audit_results(audit_results)
+        .with_advisories(advisories);
+
+    // Create the client notifying the registered webhooks.
+    let webhooks = WebhookNotifier::new().context("Failed to create the webhook client")?;
+
+    // Create the Slack notifier, if Slack alerts are configured.
+    let slack = SlackNotifier::from_env()
+        .context("Invalid Slack configuration")?
+        .map(Arc::new);
+    let notifiers = AuditNotifiers { webhooks, slack };
+
+    // Create the clients of the LLM providers generating code, if any is configured.
+    let generators = CodeGenerators::from_env().context("Invalid code generation configuration")?;
+
+    // Create the flag refusing new audits once the server shuts down.
+    let shutdown = ShutdownFlag::default();
+
+    // Start the workers running the audits submitted in the background.
+    let cancellation_tokens = CancellationTokens::default();
+    let progress = ProgressChannels::default();
+    jobs::start(
+        config.job_workers,
+        JobContext {
+            pool: db.primary().clone(),
+            policy: policy.clone(),
+            compiler: compiler.clone(),
+            notifiers: notifiers.clone(),
+            max_attempts: config.job_max_attempts,
+            cancellation_tokens: cancellation_tokens.clone(),
+            progress: progress.clone(),
+        },
+        ready.clone(),
+        shutdown.clone(),
+    );
+
+    // Create the GraphQL schema.
+    let highlights = Arc::new(HighlightCache::new());
+    let rate_limiter = Arc::new(RateLimiter::new(config.rate_limit_rpm));
+    let schema = async_graphql::Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
+        .extension(PersistedQueries(persisted_queries.clone()))
+        .data(db.primary().clone())
+        .data(db.clone())
+        .data(policy.clone())
+        .data(compiler.clone())
+        .data(generators.clone())
+        .data(notifiers.clone())
+        .data(shutdown.clone())
+        .data(cancellation_tokens.clone())
+        .data(progress.clone())
+        .data(highlights.clone())
+        .data(rate_limiter.clone())
+        .data(DataLoader::new(
+            CommentLoader { db: db.clone() },
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            DiagnosticsLoader { db: db.clone() },
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            RatingLoader { db: db.clone() },
+            tokio::spawn,
+        ))
+        .finish();
+
+    // Create the application state.
+    let state = AppState {
+        db,
+        schema,
+        admin_token: AdminToken::new(config.admin_api_token.clone()),
+        persisted_queries,
+        policy,
+        compiler: compiler.clone(),
+        rate_limiter,
+        github: GitHubIntegration::from_env()
+            .context("Invalid GitHub integration configuration")?
+            .map(Arc::new),
+        badges: Arc::new(BadgeCache::new()),
+        highlights,
+        stats: Arc::new(StatsCache::new()),
+        generators,
+        notifiers,
+        cancellation_tokens,
+        progress,
+        token_issuer: TokenIssuer::new(config.jwt_secret.as_ref(), config.jwt_expiry).map(Arc::new),
+        registration_enabled: auth::registration_enabled_from_env(),
+        config: config.clone(),
+        shutdown: shutdown.clone(),
+        ready,
+        startup_time,
+        database_version: version,
+    };
+
+    // Build the CORS policy for browser-based clients.
+    let cors = cors::cors_layer(config.cors_allow_any, &config.cors_allowed_origins)
+        .context("Invalid CORS configuration")?;
+
+    // Load the TLS certificate, if the server should serve HTTPS itself.
+    let tls_config = tls::tls_config_from_env()
+        .await
+        .context("Invalid TLS configuration")?;
+
+    // Build the Axum router. The routes creating audits, including the fixes of existing
+    // ones, count against the rate limit of the client.
+    let audit_creation = Router::new()
+        .route("/audit", post(create_audit_handler))
+        .route("/audit/async", post(create_async_audit_handler))
+        .route("/audit/dry-run", post(dry_run_audit_handler))
+        .route("/audit/stream", get(audit_stream_handler))
+        .route("/audit/{id}/fix", post(fix_audit_handler))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            limit_audit_creation,
+        ));
+    let app = Router::new()
+        .route("/graphql", get(graphql_get_handler).post(graphql_handler))
+        .route_service(
+            "/graphql/ws",
+            GraphQLSubscription::new(state.schema.clone()),
+        )
+        .merge(audit_creation)
+        .route("/audit/job/{id}", get(audit_job_handler))
+        .route("/audit/{id}", get(get_audit_handler))
+        .route("/audits", get(list_audits_handler))
+        .route("/audits/stream", get(stream_audits_handler))
+        .route("/audits/compare", get(compare_audits_handler))
+        .route("/audits/report/junit", get(junit_report_handler))
+        .route("/stats", get(stats_handler))
+        .route("/stats/categories", get(category_stats_handler))
+        .route(
+            "/audit/{id}/comments",
+            post(add_comment_handler).get(list_comments_handler),
+        )
+        .route(
+            "/audit/{id}/comments/{comment_id}",
+            delete(delete_comment_handler),
+        )
+        .route("/audit/{id}/rate", post(rate_audit_handler))
+        .route("/audit/{id}/ratings", get(list_ratings_handler))
+        .route("/audit/{id}/sarif", get(sarif_handler))
+        .route("/audit/{id}/files", get(list_audit_files_handler))
+        .route("/audit/{id}/compilation", delete(cancel_audit_handler))
+        .route("/audit/{id}/progress", get(audit_progress_handler))
+        .route("/audit/{id}/lineage", get(audit_lineage_handler))
+        .route("/audit/{id}/download", get(download_audit_handler))
+        .route("/audit/{id}/artifacts", get(list_artifacts_handler))
+        .route(
+            "/audit/{id}/artifacts/{name}",
+            get(download_artifact_handler),
+        )
+        .route("/audit/{id}/code", get(audit_code_handler))
+        .route("/audit/{id}/code.html", get(audit_code_html_handler))
+        .route("/badge.svg", get(global_svg_badge_handler))
+        .route("/badge.json", get(global_json_badge_handler))
+        .route("/badge/project/{file}", get(project_badge_handler))
+        .route("/admin/rerun-failed", post(rerun_failed_handler))
+        .route("/audits/reaudit-invalid", post(reaudit_invalid_handler))
+        .route("/admin/import", post(import_audits_handler))
+        .route("/audits/import", post(import_audits_handler))
+        .route(
+            "/admin/webhooks",
+            get(list_webhooks_handler).post(create_webhook_handler),
+        )
+        .route("/admin/webhooks/{id}", delete(delete_webhook_handler))
+        .route("/admin/system-info", get(system_info_handler))
+        .route("/admin/config-template", get(config_template_handler))
+        .route("/auth/token", post(issue_token_handler))
+        .route("/auth/register", post(register_handler))
+        .route("/integrations/github/webhook", post(github_webhook_handler))
+        .route("/metrics/apq", get(apq_metrics_handler))
+        .route("/metrics/audit-cache", get(audit_cache_metrics_handler))
+        .route("/metrics/workers", get(worker_metrics_handler))
+        .route("/metrics/db", get(db_metrics_handler))
+        .route("/health", get(health_handler))
+        .route("/ready", get(ready_handler))
+        .route("/docs", get(docs_redirect))
+        .merge(SwaggerUi::new("/swagger-ui").url("/openapi.json", ApiDoc::openapi()));
+    // Without the IDE, `/` is not routed and answers `404 Not Found`.
+    let app = if config.graphiql_enabled {
+        app.route("/", get(graphiql))
+    } else {
+        app
+    };
+    let app = app
+        .method_not_allowed_fallback(method_not_allowed)
+        .layer(cors)
+        .with_state(state);
+
+    // Start the web server.
+    let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
+    let scheme = if tls_config.is_some() {
+        "https"
+    } else {
+        "http"
+    };
+    tracing::info!("Server listening on {}://{}", scheme, addr);
+    if config.graphiql_enabled {
+        tracing::info!(
+            "GraphiQL IDE available at {}://localhost:{}",
+            scheme,
+            config.port
+        );
+    }
+    tracing::info!(
+        "Swagger UI available at {}://localhost:{}/swagger-ui/",
+        scheme,
+        config.port
+    );
+    let drained = drain_on_shutdown_signal(shutdown.clone(), compiler);
+    let server: Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>> = match tls_config {
+        Some(config) => {
+            let handle = axum_server::Handle::new();
+            let server_handle = handle.clone();
+            tokio::spawn(async move {
+                drained.await;
+                server_handle.graceful_shutdown(Some(SHUTDOWN_GRACE_PERIOD));
+            });
+            Box::pin(async move {
+                axum_server::bind_rustls(addr, config)
+                    .handle(handle)
+                    .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                    .await?;
+                Ok(())
+            })
+        }
+        None => {
+            let listener = tokio::net::TcpListener::bind(addr).await?;
+            Box::pin(async move {
+                let server = axum::serve(
+                    listener,
+                    app.into_make_service_with_connect_info::<SocketAddr>(),
+                )
+                .with_graceful_shutdown(drained);
+                // Requests still waiting for a compilation after the drain are not waited
+                // for indefinitely.
+                let deadline = async {
+                    shutdown.triggered().await;
+                    tokio::time::sleep(SHUTDOWN_DRAIN_TIMEOUT + SHUTDOWN_GRACE_PERIOD).await;
+                };
+                tokio::select! {
+                    result = server => result?,
+                    () = deadline => tracing::warn!("Dropped the connections still open after the grace period."),
+                }
+                Ok(())
+            })
+        }
+    };
+    // A failed startup stops the server, as it did before it started listening.
+    let startup_failed = async {
+        match startup.await {
+            Ok(Ok(())) => std::future::pending().await,
+            Ok(Err(e)) => e,
+            Err(e) => anyhow::Error::new(e).context("The startup task failed"),
+        }
+    };
+    tokio::select! {
+        result = server => result?,
+        e = startup_failed => return Err(e),
+    }
+
+    // Compilations killed before the drain completed may have left their files behind.
+    let removed = auditor::remove_temp_files();
+    tracing::info!(removed_temp_files = removed, "Server stopped");
+    std::io::stdout().flush()?;
+    Ok(())
+}
 
+/// Runs the steps of the startup that may take a while, then marks the service as ready.
+///
+/// # Arguments
+///
+/// * `db` - The database connection pool.
+/// * `ready` - The flag reported by `/ready`, set once the steps are done.
+///
+/// # Returns
+///
+/// * `Ok(())` - If the service is ready.
+/// * `Err(anyhow::Error)` - If the database migrations failed.
+async fn prepare(db: PgPool, ready: Arc<AtomicBool>) -> anyhow::Result<()> {
     // Run database migrations.
     sqlx::migrate!()
         .run(&db)
@@ -148,7 +3087,7 @@ async fn main() -> anyhow::Result<()> {
     tracing::info!("Database migrations ran successfully");
 
     // Check if the Rust compiler is available.
-    match auditor::check_rustc_available() {
+    match tokio::task::spawn_blocking(|| auditor::check_rustc_available(Channel::Stable)).await? {
         Ok(version) => tracing::info!(version = %version, "Rust compiler is available"),
         Err(e) => tracing::warn!(
             "Could not execute rustc: {}. Audit functionality will be impaired.",
@@ -156,28 +3095,72 @@ async fn main() -> anyhow::Result<()> {
         ),
     }
 
-    // Create the GraphQL schema.
-    let schema =
-        async_graphql::Schema::build(QueryRoot, MutationRoot, async_graphql::EmptySubscription)
-            .data(db.clone())
-            .finish();
+    ready.store(true, Ordering::SeqCst);
+    tracing::info!("Service is ready");
+    Ok(())
+}
 
-    // Create the application state.
-    let state = AppState { db, schema };
+/// The maximum number of audits sent in one chunk of `GET /audits/stream`.
+const STREAM_CHUNK_AUDITS: usize = 64;
 
-    // Build the Axum router.
-    let app = Router::new()
-        .route("/", get(graphiql))
-        .route("/graphql", post(graphql_handler))
-        .route("/audit", post(create_audit_handler))
-        .route("/stats", get(stats_handler))
-        .with_state(state);
+/// How often expired idempotency keys are forgotten.
+const RETENTION_INTERVAL: Duration = Duration::from_secs(3600);
 
-    // Start the web server.
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
-    tracing::info!("Server listening on http://0.0.0.0:3000");
-    tracing::info!("GraphiQL IDE available at http://localhost:3000");
-    axum::serve(listener, app).await?;
+/// How often the stale files of interrupted compilations are looked for.
+const TEMP_SWEEP_INTERVAL: Duration = Duration::from_secs(600);
 
-    Ok(())
+/// How long in-flight compilations may run once a shutdown is requested.
+const SHUTDOWN_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long the open connections may take to complete after the drain.
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// Waits for `Ctrl+C` or `SIGTERM`, then refuses new audits and waits for the queued and
+/// running compilations to complete, for at most `SHUTDOWN_DRAIN_TIMEOUT`.
+///
+/// The server keeps answering during the drain, with `503 SERVICE UNAVAILABLE` for new
+/// audits; it stops accepting connections once this future completes.
+///
+/// # Arguments
+///
+/// * `shutdown` - The flag refusing new audits.
+/// * `compiler` - The queue of the compilation workers to drain.
+async fn drain_on_shutdown_signal(shutdown: ShutdownFlag, compiler: CompilationQueue) {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!(error = %e, "Could not listen for Ctrl+C.");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "Could not listen for SIGTERM.");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+    }
+
+    tracing::info!("Shutdown requested, draining in-flight compilations");
+    shutdown.trigger();
+    if tokio::time::timeout(SHUTDOWN_DRAIN_TIMEOUT, compiler.drain())
+        .await
+        .is_err()
+    {
+        tracing::warn!(
+            timeout_secs = SHUTDOWN_DRAIN_TIMEOUT.as_secs(),
+            "Compilations were still running when the drain timed out."
+        );
+    }
 }
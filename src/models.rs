@@ -1,14 +1,16 @@
 //! Contains the core data structures and models for the application.
 
-use async_graphql::{InputObject, SimpleObject};
-use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use async_graphql::{Enum, InputObject, SimpleObject};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
 use sqlx::FromRow;
+use std::collections::HashMap;
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
 /// Represents a single AI code audit record in the database.
-#[derive(Debug, Serialize, Deserialize, FromRow, SimpleObject)]
-#[graphql(name = "AiAudit")]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, SimpleObject, ToSchema)]
+#[graphql(name = "AiAudit", complex)]
 pub struct AiAudit {
     /// The unique identifier for the audit.
     pub id: Uuid,
@@ -20,25 +22,930 @@ pub struct AiAudit {
     /// A boolean indicating whether the generated code compiled successfully.
     #[graphql(name = "isValid")]
     pub is_valid: bool,
+    /// The verdict of the audit.
+    pub status: AuditStatus,
+    /// Labels organizing the audit, sorted alphabetically.
+    pub tags: Vec<String>,
+    /// The number of lines of the generated code.
+    #[graphql(name = "codeLineCount")]
+    pub code_line_count: i32,
+    /// The number of characters of the generated code.
+    #[graphql(name = "codeCharCount")]
+    pub code_char_count: i32,
     /// The compilation error message, if any.
     #[graphql(name = "compilationError")]
     pub compilation_error: Option<String>,
+    /// The rustc error code of the first compilation error (e.g. `E0308`), if any.
+    #[graphql(name = "primaryErrorCode")]
+    pub primary_error_code: Option<String>,
+    /// The category of the dominant compilation error (e.g. `borrow_check`), if any.
+    #[graphql(name = "primaryErrorCategory")]
+    pub primary_error_category: Option<String>,
+    /// The release channel of the toolchain the code is compiled with.
+    pub channel: Channel,
+    /// The optimization level the code is compiled with (`0`-`3`, `s` or `z`).
+    #[graphql(name = "optLevel")]
+    #[schema(value_type = String, example = "0")]
+    pub opt_level: OptLevel,
+    /// The Rust edition the code is compiled with, or `None` for audits created before
+    /// editions were recorded, which were compiled with the 2015 edition.
+    #[schema(value_type = Option<String>, example = "2021")]
+    pub edition: Option<Edition>,
+    /// The edition guessed from the code when the request did not choose one, which is
+    /// then the edition the code is compiled with, or `None` if the request chose it.
+    #[graphql(name = "editionDetected")]
+    #[schema(value_type = Option<String>, example = "2018")]
+    pub edition_detected: Option<Edition>,
+    /// The target triple the code is compiled for, or `None` for the host of the server.
+    pub target: Option<String>,
+    /// The `rustc --version` of the toolchain that compiled the code, if it was compiled.
+    #[graphql(name = "rustcVersion")]
+    pub rustc_version: Option<String>,
+    /// How long `rustc` took to compile the code, in milliseconds, if it was compiled.
+    #[graphql(name = "compilationDurationMs")]
+    pub compilation_duration_ms: Option<i32>,
+    /// The `rustc` invocation that gave the verdict, without the paths of the temporary
+    /// crate, if the code was compiled (e.g. `["rustc", "--crate-type", "lib", ...]`).
+    #[graphql(name = "compileCommand")]
+    pub compile_command: Option<Vec<String>>,
+    /// The number of advisories of the RustSec database affecting the dependencies of a
+    /// Cargo package, each reported by a `RAA0203` finding, or `None` if they were not
+    /// checked (e.g. the code is a single file, or the advisory database is unavailable).
+    #[graphql(name = "vulnerabilityCount")]
+    pub vulnerability_count: Option<i32>,
+    /// Why the dependencies of a Cargo package were not checked against the advisory
+    /// database, e.g. `advisory DB unavailable` when the server has no copy of it.
+    #[graphql(name = "advisoryNote")]
+    pub advisory_note: Option<String>,
+    /// The number of dependencies of a Cargo package whose license the license policy
+    /// does not allow, each reported by a `RAA0301` finding, or `None` if they were not
+    /// checked (e.g. the code is a single file).
+    #[graphql(name = "licenseViolationCount")]
+    pub license_violation_count: Option<i32>,
+    /// The advisories `cargo audit` reported for the dependencies of a Cargo package, or
+    /// `None` if it did not run (e.g. the code is a single file, or `cargo-audit` is not
+    /// installed on the server).
+    #[graphql(name = "securityAdvisories")]
+    #[sqlx(json(nullable))]
+    pub security_advisories: Option<Vec<SecurityAdvisory>>,
+    /// Whether `cargo audit` reported any advisory.
+    #[graphql(name = "hasSecurityIssues")]
+    pub has_security_issues: bool,
+    /// The estimated number of tokens of the prompt.
+    #[graphql(name = "promptTokenCount")]
+    pub prompt_token_count: i32,
+    /// The estimated number of tokens of the generated code.
+    #[graphql(name = "codeTokenCount")]
+    pub code_token_count: i32,
+    /// The license expression of the `SPDX-License-Identifier` comment in the first lines
+    /// of the code (e.g. `MIT OR Apache-2.0`), if it has one.
+    #[graphql(name = "detectedLicense")]
+    pub detected_license: Option<String>,
+    /// The percentage of public items documented with `///` comments, if the code parses.
+    #[graphql(name = "docCoveragePercent")]
+    pub doc_coverage_percent: Option<f64>,
+    /// The potential problems detected by the heuristic validation of the code.
+    #[sqlx(json)]
+    pub findings: Vec<Finding>,
+    /// The `unsafe` blocks, functions and trait implementations of the code, if it parses.
+    #[sqlx(json(nullable))]
+    #[graphql(name = "unsafeReport")]
+    pub unsafe_report: Option<UnsafeReport>,
+    /// The debug output, `TODO` comments and placeholders left in the code.
+    #[sqlx(json(nullable))]
+    #[graphql(name = "hygieneReport")]
+    pub hygiene_report: Option<HygieneReport>,
+    /// The numbers derived from the analyses of the audit, including its quality score.
+    #[sqlx(json(nullable))]
+    pub metrics: Option<AuditMetrics>,
+    /// The name of the audit profile applied to the code, if any.
+    pub profile: Option<String>,
+    /// The code as formatted by `rustfmt`, if it is installed and the code parses.
+    #[graphql(name = "formattedCode")]
+    pub formatted_code: Option<String>,
+    /// Whether `rustfmt` changes the code, if it could format it.
+    #[graphql(name = "needsFormatting")]
+    pub needs_formatting: Option<bool>,
+    /// Why the code was rejected without being compiled, if it was.
+    #[graphql(name = "rejectionReason")]
+    pub rejection_reason: Option<String>,
+    /// The repository (`owner/name`) the code was taken from, for integration audits.
+    #[graphql(name = "sourceRepository")]
+    pub source_repository: Option<String>,
+    /// The pull request the code was taken from, for integration audits.
+    #[graphql(name = "sourcePullRequest")]
+    pub source_pull_request: Option<i32>,
+    /// The path of the file the code was taken from, for integration audits.
+    #[graphql(name = "sourcePath")]
+    pub source_path: Option<String>,
+    /// The provider that generated the code, for audits generated server-side.
+    #[graphql(name = "generationProvider")]
+    pub generation_provider: Option<Provider>,
+    /// The model that generated the code, for audits generated server-side.
+    #[graphql(name = "generationModel")]
+    pub generation_model: Option<String>,
+    /// The complete answer of the model the code was extracted from, for audits
+    /// generated server-side.
+    #[graphql(name = "rawModelOutput")]
+    pub raw_model_output: Option<String>,
+    /// The number of prompt tokens billed by the provider, if it reported them.
+    #[graphql(name = "promptTokens")]
+    pub prompt_tokens: Option<i32>,
+    /// The number of completion tokens billed by the provider, if it reported them.
+    #[graphql(name = "completionTokens")]
+    pub completion_tokens: Option<i32>,
+    /// How long the provider took to answer, in milliseconds, for audits generated
+    /// server-side.
+    #[graphql(name = "generationLatencyMs")]
+    pub generation_latency_ms: Option<i32>,
+    /// The audit whose code this audit is an automatic fix of, if any.
+    #[graphql(name = "parentAuditId")]
+    pub parent_audit_id: Option<Uuid>,
+    /// The position of the audit in its chain of fix attempts, starting at 1.
+    #[graphql(name = "attemptNumber")]
+    pub attempt_number: i32,
     /// The timestamp when the audit was created.
     #[graphql(name = "createdAt")]
     pub created_at: DateTime<Utc>,
+    /// The timestamp when the audit last changed, e.g. its verdict or its tags.
+    #[graphql(name = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
 }
 
 /// Represents the incoming request payload for creating a new audit.
-#[derive(Debug, Deserialize, InputObject)]
+#[derive(Debug, Deserialize, InputObject, ToSchema)]
 pub struct CreateAuditRequest {
     /// The prompt that was given to the AI.
     pub prompt: String,
     /// The code that was generated by the AI.
+    ///
+    /// Leave it empty and set `generate` to have the server generate the code, or
+    /// `files` to audit several files.
+    #[serde(default)]
+    #[graphql(default)]
     pub generated_code: String,
+    /// The files of a crate generated by the AI, instead of `generated_code`.
+    ///
+    /// The crate is compiled from its root, the first of `src/lib.rs`, `lib.rs`,
+    /// `src/main.rs` and `main.rs`, whose code becomes the `generated_code` of the audit.
+    /// Paths must be relative, without `.` or `..`; only `.rs` files and a `Cargo.toml`
+    /// at the root are accepted.
+    #[serde(default)]
+    #[graphql(default)]
+    pub files: Vec<AuditFile>,
+    /// The model generating the code from the prompt on the server, instead of
+    /// auditing `generated_code`.
+    #[serde(default)]
+    pub generate: Option<GenerateOptions>,
+    /// Feeds compilation errors back to the model generating the code, asking for a
+    /// corrected version, until the code compiles. Requires `generate`.
+    #[serde(default)]
+    pub auto_fix: Option<AutoFixOptions>,
+    /// A client-chosen key identifying this submission, so that retries return the
+    /// original audit instead of creating a duplicate.
+    ///
+    /// REST clients may send it in the `Idempotency-Key` header instead.
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+    /// Whether blocking findings reject the code without compiling it.
+    ///
+    /// Defaults to the server-wide `AUDIT_STRICT` setting.
+    #[serde(default)]
+    pub strict: Option<bool>,
+    /// Labels organizing the audit (e.g. by project, dataset or experiment).
+    ///
+    /// Tags are trimmed, lowercased and deduplicated.
+    #[serde(default)]
+    #[graphql(default)]
+    pub tags: Vec<String>,
+    /// The release channel of the toolchain compiling the code.
+    ///
+    /// Defaults to `stable`; use `nightly` for code relying on `#![feature(...)]`.
+    #[serde(default)]
+    pub channel: Option<Channel>,
+    /// The optimization level passed to `rustc -C opt-level=`: `0` to `3`, `s` or `z`.
+    ///
+    /// Defaults to `0`.
+    #[serde(default)]
+    #[schema(value_type = Option<String>, example = "3")]
+    pub opt_level: Option<OptLevel>,
+    /// The Rust edition passed to `rustc --edition`: `2015`, `2018`, `2021` or `2024`.
+    ///
+    /// Defaults to the edition guessed from the code (see
+    /// `auditor::detect_edition_from_code`), `2021` if nothing hints at another one.
+    #[serde(default)]
+    #[schema(value_type = Option<String>, example = "2021")]
+    pub edition: Option<Edition>,
+    /// The target triple passed to `rustc --target` (e.g. `wasm32-unknown-unknown`).
+    ///
+    /// Defaults to the host of the server. The target must be installed on the server.
+    #[serde(default)]
+    pub target: Option<String>,
+    /// The audit profile choosing the reported rules and the score weights.
+    ///
+    /// Defaults to `AUDIT_DEFAULT_PROFILE`.
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Whether the code is compiled by a background job instead of during the request.
+    ///
+    /// The audit is returned right away with the `pending` status, and gets its verdict
+    /// once a job worker compiled it. Cannot be combined with `auto_fix`.
+    #[serde(default)]
+    #[graphql(default)]
+    pub background: bool,
+    /// Whether the code is validated and compiled again even if the same code was
+    /// audited recently with the same settings. The fresh result replaces the cached one.
+    #[serde(default)]
+    #[graphql(default)]
+    pub bypass_cache: bool,
+}
+
+/// The result of auditing code without storing it (see `pipeline::Auditor`).
+#[derive(Debug, Clone, Serialize, SimpleObject, ToSchema)]
+#[graphql(name = "AuditOutcome")]
+pub struct AuditOutcome {
+    /// A boolean indicating whether the code compiled successfully.
+    #[graphql(name = "isValid")]
+    pub is_valid: bool,
+    /// The verdict of the audit.
+    pub status: AuditStatus,
+    /// The Rust edition the code was compiled with.
+    #[schema(value_type = String, example = "2021")]
+    pub edition: Edition,
+    /// The potential problems detected by the heuristic validation of the code.
+    pub findings: Vec<Finding>,
+    /// The diagnostics emitted by `rustc`, if the code was compiled.
+    pub diagnostics: Vec<Diagnostic>,
+    /// The compilation error message, if any.
+    #[graphql(name = "compilationError")]
+    pub compilation_error: Option<String>,
+    /// The rustc error code of the first compilation error (e.g. `E0308`), if any.
+    #[graphql(name = "primaryErrorCode")]
+    pub primary_error_code: Option<String>,
+    /// The category of the dominant compilation error (e.g. `borrow_check`), if any.
+    #[graphql(name = "primaryErrorCategory")]
+    pub primary_error_category: Option<String>,
+    /// Why the code was rejected without being compiled, if it was.
+    #[graphql(name = "rejectionReason")]
+    pub rejection_reason: Option<String>,
+    /// The `rustc --version` of the toolchain that compiled the code, if it was compiled.
+    #[graphql(name = "rustcVersion")]
+    pub rustc_version: Option<String>,
+    /// How long `rustc` took to compile the code, in milliseconds, if it was compiled.
+    #[graphql(name = "compilationDurationMs")]
+    pub compilation_duration_ms: Option<i32>,
+    /// The `rustc` invocation that gave the verdict, without the paths of the temporary
+    /// crate, if the code was compiled.
+    #[graphql(name = "compileCommand")]
+    pub compile_command: Option<Vec<String>>,
+    /// The number of advisories of the RustSec database affecting the dependencies of a
+    /// Cargo package, each reported by a `RAA0203` finding, or `None` if they were not
+    /// checked.
+    #[graphql(name = "vulnerabilityCount")]
+    pub vulnerability_count: Option<i32>,
+    /// Why the dependencies of a Cargo package were not checked against the advisory
+    /// database, e.g. `advisory DB unavailable`.
+    #[graphql(name = "advisoryNote")]
+    pub advisory_note: Option<String>,
+    /// The number of dependencies of a Cargo package whose license the license policy
+    /// does not allow, or `None` if they were not checked.
+    #[graphql(name = "licenseViolationCount")]
+    pub license_violation_count: Option<i32>,
+    /// The advisories `cargo audit` reported for the dependencies of a Cargo package, or
+    /// `None` if it did not run.
+    #[graphql(name = "securityAdvisories")]
+    pub security_advisories: Option<Vec<SecurityAdvisory>>,
+    /// Whether `cargo audit` reported any advisory.
+    #[graphql(name = "hasSecurityIssues")]
+    pub has_security_issues: bool,
+    /// The percentage of public items documented with `///` comments, if the code parses.
+    #[graphql(name = "docCoveragePercent")]
+    pub doc_coverage_percent: Option<f64>,
+    /// The summary of the `unsafe` code, if the code parses.
+    #[graphql(name = "unsafeReport")]
+    pub unsafe_report: Option<UnsafeReport>,
+    /// The leftovers of the code and its hygiene score.
+    #[graphql(name = "hygieneReport")]
+    pub hygiene_report: HygieneReport,
+    /// The numbers derived from the analyses, including the quality score.
+    pub metrics: AuditMetrics,
+}
+
+/// A file of an audit submitted as several files.
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    FromRow,
+    SimpleObject,
+    InputObject,
+    ToSchema,
+)]
+#[graphql(name = "AuditFile", input_name = "AuditFileInput")]
+pub struct AuditFile {
+    /// The path of the file, relative to the root of the crate (e.g. `src/parser.rs`).
+    pub path: String,
+    /// The content of the file.
+    pub content: String,
+}
+
+/// The model generating the code of an audit on the server.
+#[derive(Debug, Clone, Deserialize, InputObject, ToSchema)]
+pub struct GenerateOptions {
+    /// The provider serving the model.
+    pub provider: Provider,
+    /// The name of the model (e.g. `gpt-4o`, `claude-sonnet-4-5`, `qwen2.5-coder`).
+    pub model: String,
+}
+
+/// Settings of the automatic fix loop of an audit.
+#[derive(Debug, Clone, Copy, Deserialize, InputObject, ToSchema)]
+pub struct AutoFixOptions {
+    /// The maximum number of corrected versions to ask for (1 to 5).
+    pub max_attempts: u8,
+}
+
+/// The request payload asking for a corrected version of an audit that does not compile.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct FixAuditRequest {
+    /// The model asked to fix the code. Defaults to the model that generated it, for
+    /// audits generated server-side.
+    #[serde(default)]
+    pub generate: Option<GenerateOptions>,
+}
+
+/// An audit and its automatic fix attempts.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuditChain {
+    /// Whether a fix attempt produced valid code.
+    pub fixed: bool,
+    /// The audits of the chain, ordered by attempt number.
+    pub attempts: Vec<AiAudit>,
+}
+
+/// An LLM provider able to generate code from a prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum, sqlx::Type, ToSchema)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+#[graphql(rename_items = "UPPERCASE")]
+pub enum Provider {
+    /// The OpenAI chat completions API.
+    OpenAi,
+    /// The Anthropic messages API.
+    Anthropic,
+    /// A local Ollama server.
+    Ollama,
+}
+
+impl Provider {
+    /// Returns the name of the provider, as accepted in requests.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Provider::OpenAi => "openai",
+            Provider::Anthropic => "anthropic",
+            Provider::Ollama => "ollama",
+        }
+    }
+}
+
+/// The release channel of the Rust toolchain compiling an audit.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Enum, sqlx::Type, ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum Channel {
+    /// The stable toolchain.
+    #[default]
+    Stable,
+    /// The nightly toolchain, accepting unstable feature gates.
+    Nightly,
+}
+
+impl Channel {
+    /// Returns the name of the channel, as accepted in requests.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Channel::Stable => "stable",
+            Channel::Nightly => "nightly",
+        }
+    }
+}
+
+/// The optimization level code is compiled with, as passed to `rustc -C opt-level=`.
+///
+/// JSON accepts the level as a number (`3`) or a string (`"3"`, `"s"`, `"z"`) and
+/// returns it as a string.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Enum, sqlx::Type)]
+#[sqlx(type_name = "text")]
+pub enum OptLevel {
+    /// No optimizations, the default.
+    #[default]
+    #[sqlx(rename = "0")]
+    O0,
+    /// Basic optimizations.
+    #[sqlx(rename = "1")]
+    O1,
+    /// Some optimizations.
+    #[sqlx(rename = "2")]
+    O2,
+    /// All optimizations.
+    #[sqlx(rename = "3")]
+    O3,
+    /// Optimizations for binary size.
+    #[sqlx(rename = "s")]
+    Os,
+    /// Optimizations for binary size, also turning off loop vectorization.
+    #[sqlx(rename = "z")]
+    Oz,
+}
+
+impl OptLevel {
+    /// Returns the level as passed to `rustc -C opt-level=`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OptLevel::O0 => "0",
+            OptLevel::O1 => "1",
+            OptLevel::O2 => "2",
+            OptLevel::O3 => "3",
+            OptLevel::Os => "s",
+            OptLevel::Oz => "z",
+        }
+    }
+}
+
+impl std::str::FromStr for OptLevel {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "0" => Ok(OptLevel::O0),
+            "1" => Ok(OptLevel::O1),
+            "2" => Ok(OptLevel::O2),
+            "3" => Ok(OptLevel::O3),
+            "s" => Ok(OptLevel::Os),
+            "z" => Ok(OptLevel::Oz),
+            _ => Err(format!(
+                "invalid optimization level '{}', expected 0, 1, 2, 3, s or z",
+                value
+            )),
+        }
+    }
+}
+
+impl Serialize for OptLevel {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for OptLevel {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Level {
+            Number(u64),
+            Text(String),
+        }
+
+        let value = match Level::deserialize(deserializer)? {
+            Level::Number(n) => n.to_string(),
+            Level::Text(text) => text,
+        };
+        value.parse().map_err(de::Error::custom)
+    }
+}
+
+/// The Rust edition code is compiled with, as passed to `rustc --edition`.
+///
+/// JSON accepts the edition as a number (`2021`) or a string (`"2021"`) and returns it as
+/// a string.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Enum, sqlx::Type)]
+#[sqlx(type_name = "text")]
+pub enum Edition {
+    /// The 2015 edition, the default of `rustc` itself.
+    #[sqlx(rename = "2015")]
+    E2015,
+    /// The 2018 edition.
+    #[sqlx(rename = "2018")]
+    E2018,
+    /// The 2021 edition, the default.
+    #[default]
+    #[sqlx(rename = "2021")]
+    E2021,
+    /// The 2024 edition.
+    #[sqlx(rename = "2024")]
+    E2024,
+}
+
+impl Edition {
+    /// The edition of the audits created before editions were recorded, which were
+    /// compiled with the default of `rustc`.
+    pub const UNRECORDED: Edition = Edition::E2015;
+
+    /// Returns the edition as passed to `rustc --edition`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Edition::E2015 => "2015",
+            Edition::E2018 => "2018",
+            Edition::E2021 => "2021",
+            Edition::E2024 => "2024",
+        }
+    }
+}
+
+impl std::str::FromStr for Edition {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "2015" => Ok(Edition::E2015),
+            "2018" => Ok(Edition::E2018),
+            "2021" => Ok(Edition::E2021),
+            "2024" => Ok(Edition::E2024),
+            _ => Err(format!(
+                "invalid edition '{}', expected 2015, 2018, 2021 or 2024",
+                value
+            )),
+        }
+    }
+}
+
+impl Serialize for Edition {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Edition {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Year {
+            Number(u64),
+            Text(String),
+        }
+
+        let value = match Year::deserialize(deserializer)? {
+            Year::Number(n) => n.to_string(),
+            Year::Text(text) => text,
+        };
+        value.parse().map_err(de::Error::custom)
+    }
+}
+
+/// The verdict of an audit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum, sqlx::Type, ToSchema)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum AuditStatus {
+    /// The code compiled successfully.
+    Valid,
+    /// The code failed to compile.
+    CompileError,
+    /// The code was rejected by strict validation and never compiled.
+    Rejected,
+    /// The code waits to be compiled by a background job.
+    Pending,
+    /// The background job was cancelled before the code got its verdict.
+    Cancelled,
+}
+
+/// The severity of a validation finding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// A remark that does not indicate a problem by itself.
+    Info,
+    /// A likely problem that does not prevent compilation.
+    Warning,
+    /// A dangerous pattern; blocks compilation in strict mode.
+    Error,
+}
+
+/// The kind of problem a finding reports, for the rules that classify their findings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingCategory {
+    /// A vulnerability, such as SQL or command injection.
+    Security,
+}
+
+/// Represents a potential problem detected by the heuristic validation of generated code.
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject, ToSchema)]
+#[graphql(name = "Finding")]
+pub struct Finding {
+    /// A stable identifier of the rule that produced the finding (e.g. `RAA0001`).
+    pub code: String,
+    /// How serious the finding is.
+    pub severity: Severity,
+    /// A description of the problem.
+    pub message: String,
+    /// The 1-based line the problem was found on, if known.
+    pub line: Option<u32>,
+    /// The kind of problem, if the rule classifies its findings.
+    #[serde(default)]
+    pub category: Option<FindingCategory>,
+    /// The path of the file the problem was found in, for audits of several files.
+    #[serde(default)]
+    pub file: Option<String>,
+}
+
+/// A security advisory `cargo audit` reported for a dependency of a Cargo package.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject, ToSchema)]
+#[graphql(name = "SecurityAdvisory")]
+pub struct SecurityAdvisory {
+    /// The affected package and its locked version (e.g. `rsa 0.9.10`).
+    pub package: String,
+    /// The identifier of the advisory (e.g. `RUSTSEC-2023-0071`).
+    #[graphql(name = "vulnerabilityId")]
+    pub vulnerability_id: String,
+    /// The title of the advisory.
+    pub title: String,
+    /// The severity of its CVSS score: `none`, `low`, `medium`, `high` or `critical`, or
+    /// `unknown` if it has no score.
+    pub severity: String,
+}
+
+/// Summarizes the `unsafe` code of an audit.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, SimpleObject, ToSchema)]
+#[graphql(name = "UnsafeReport")]
+pub struct UnsafeReport {
+    /// The number of `unsafe` blocks, functions and trait implementations.
+    #[graphql(name = "totalCount")]
+    pub total_count: usize,
+    /// The number of occurrences per category (e.g. `raw_pointer_deref`,
+    /// `foreign_function`, `inline_assembly`, `mutable_static_access`,
+    /// `unsafe_trait_impl` or `other`).
+    #[graphql(name = "byCategory")]
+    pub by_category: HashMap<String, usize>,
+    /// The 1-based line of the first occurrence, if any.
+    #[graphql(name = "firstOccurrenceLine")]
+    pub first_occurrence_line: Option<u32>,
+}
+
+/// Counts the leftovers of generated code and scores its hygiene.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, SimpleObject, ToSchema)]
+#[graphql(name = "HygieneReport")]
+pub struct HygieneReport {
+    /// The number of `dbg!`, `eprintln!`/`eprint!` and debug `println!`/`print!` calls.
+    #[graphql(name = "debugOutputCount")]
+    pub debug_output_count: usize,
+    /// The number of `TODO`, `FIXME` and `XXX` markers in comments.
+    #[graphql(name = "todoMarkerCount")]
+    pub todo_marker_count: usize,
+    /// The number of placeholder identifiers, such as `your_function_here`.
+    #[graphql(name = "placeholderCount")]
+    pub placeholder_count: usize,
+    /// The hygiene of the code, from 0 to 100 (no leftovers).
+    #[graphql(name = "hygieneScore")]
+    pub hygiene_score: f64,
+}
+
+/// The numbers derived from the analyses of an audit.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, SimpleObject, ToSchema)]
+#[graphql(name = "AuditMetrics")]
+pub struct AuditMetrics {
+    /// The overall quality of the code, from 0 to 100.
+    #[graphql(name = "qualityScore")]
+    pub quality_score: f64,
+    /// The average cyclomatic complexity of the functions, if the code parses and has any.
+    #[graphql(name = "complexityScore")]
+    pub complexity_score: Option<f64>,
+    /// The percentage of public items documented with `///` comments, if the code parses.
+    #[graphql(name = "docCoveragePct")]
+    pub doc_coverage_pct: Option<f64>,
+    /// The number of `unsafe` blocks, functions and trait implementations.
+    #[graphql(name = "unsafeCount")]
+    pub unsafe_count: i32,
+    /// The number of `TODO`, `FIXME` and `XXX` comments.
+    #[graphql(name = "todoCount")]
+    pub todo_count: i32,
+    /// The number of warnings, from the validation and from `rustc`.
+    #[graphql(name = "warningCount")]
+    pub warning_count: i32,
+    /// The number of functions, including methods.
+    #[graphql(name = "functionCount")]
+    pub function_count: i32,
+    /// The number of structs.
+    #[graphql(name = "structCount")]
+    pub struct_count: i32,
+    /// How long `rustc` took to compile the code, in milliseconds, if it was compiled.
+    #[graphql(name = "compilationDurationMs")]
+    pub compilation_duration_ms: Option<i32>,
+}
+
+/// The weights of the components of the quality score of an audit.
+///
+/// With the default weights, the score ranges from 0 to 100.
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject, InputObject, ToSchema)]
+#[graphql(name = "ScoreWeights", input_name = "ScoreWeightsInput")]
+pub struct ScoreWeights {
+    /// The points given to code that compiles.
+    pub compilation: f64,
+    /// The points given to fully documented code, scaled by the doc coverage.
+    pub documentation: f64,
+    /// The points given to code without leftovers, scaled by the hygiene score.
+    pub hygiene: f64,
+    /// The points given to code without findings or `rustc` warnings.
+    pub cleanliness: f64,
+    /// The points of `cleanliness` lost per warning.
+    #[graphql(name = "warningPenalty")]
+    pub warning_penalty: f64,
+    /// The points of `cleanliness` lost per error finding.
+    #[graphql(name = "errorPenalty")]
+    pub error_penalty: f64,
+    /// The points subtracted from the score per security finding.
+    #[graphql(name = "securityPenalty")]
+    pub security_penalty: f64,
+}
+
+impl Default for ScoreWeights {
+    fn default() -> Self {
+        ScoreWeights {
+            compilation: 40.0,
+            documentation: 20.0,
+            hygiene: 20.0,
+            cleanliness: 20.0,
+            warning_penalty: 5.0,
+            error_penalty: 10.0,
+            security_penalty: 25.0,
+        }
+    }
+}
+
+/// A named set of validation rules and score weights, selectable per audit.
+#[derive(Debug, Clone, Serialize, FromRow, SimpleObject, ToSchema)]
+#[graphql(name = "AuditProfile")]
+pub struct AuditProfile {
+    /// The unique name of the profile (e.g. `default`, `strict`, `compile-only`).
+    pub name: String,
+    /// The codes of the validation rules reported under this profile, sorted.
+    #[graphql(name = "enabledRules")]
+    pub enabled_rules: Vec<String>,
+    /// The weights of the quality score under this profile.
+    #[sqlx(json)]
+    pub weights: ScoreWeights,
+    /// The timestamp when the profile was created.
+    #[graphql(name = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    /// The timestamp when the profile was last changed.
+    #[graphql(name = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Represents the incoming request payload for creating or replacing an audit profile.
+#[derive(Debug, Deserialize, InputObject, ToSchema)]
+pub struct AuditProfileInput {
+    /// The name of the profile: 1 to 64 lowercase letters, digits, `-` or `_`.
+    pub name: String,
+    /// The codes of the validation rules to report (e.g. `RAA0001`).
+    #[serde(default)]
+    #[graphql(default)]
+    pub enabled_rules: Vec<String>,
+    /// The weights of the quality score. Defaults to the standard weights.
+    #[serde(default)]
+    pub weights: Option<ScoreWeights>,
+}
+
+/// The licenses the dependencies of audited Cargo packages may use, as SPDX license
+/// identifiers (e.g. `MIT`). An identifier ending with `*` matches every license starting
+/// with the rest of it, such as `GPL-*`.
+#[derive(
+    Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, FromRow, SimpleObject, ToSchema,
+)]
+#[graphql(name = "LicensePolicy")]
+pub struct LicensePolicy {
+    /// The licenses dependencies may use.
+    pub allowed: Vec<String>,
+    /// The licenses dependencies may not use, even if they are allowed too.
+    pub denied: Vec<String>,
+}
+
+impl Default for LicensePolicy {
+    /// Allows the MIT, Apache 2.0 and BSD licenses, and denies the GPL and AGPL ones.
+    fn default() -> Self {
+        LicensePolicy {
+            allowed: vec![
+                "MIT".to_string(),
+                "Apache-2.0".to_string(),
+                "BSD-*".to_string(),
+            ],
+            denied: vec!["AGPL-*".to_string(), "GPL-*".to_string()],
+        }
+    }
+}
+
+/// Represents the incoming request payload for replacing the license policy.
+#[derive(Debug, Deserialize, InputObject, ToSchema)]
+pub struct LicensePolicyInput {
+    /// The licenses dependencies may use: SPDX license identifiers, optionally ending
+    /// with `*`.
+    #[serde(default)]
+    #[graphql(default)]
+    pub allowed: Vec<String>,
+    /// The licenses dependencies may not use, like `allowed`.
+    #[serde(default)]
+    #[graphql(default)]
+    pub denied: Vec<String>,
+}
+
+/// An audit whose code is similar to the code of another audit.
+#[derive(Debug, Serialize, Deserialize, SimpleObject, FromRow, ToSchema)]
+#[graphql(name = "SimilarAudit")]
+pub struct SimilarAudit {
+    /// The similar audit.
+    #[sqlx(flatten)]
+    pub audit: AiAudit,
+    /// The trigram similarity of the two codes, from 0 (nothing in common) to 1
+    /// (identical).
+    pub similarity: f64,
+}
+
+/// Represents the differences between two audits.
+#[derive(Debug, Serialize, Deserialize, SimpleObject, ToSchema)]
+#[graphql(name = "AuditComparison")]
+pub struct AuditComparison {
+    /// The audit compared from.
+    #[graphql(name = "auditA")]
+    pub audit_a: AiAudit,
+    /// The audit compared to.
+    #[graphql(name = "auditB")]
+    pub audit_b: AiAudit,
+    /// A unified diff from the code of `audit_a` to the code of `audit_b`.
+    #[graphql(name = "codeDiff")]
+    pub code_diff: String,
+    /// Whether one of the audits is valid and the other is not.
+    #[graphql(name = "validityChanged")]
+    pub validity_changed: bool,
+    /// The quality score of `audit_b` minus the one of `audit_a`, or `None` if either
+    /// audit has no metrics.
+    #[graphql(name = "qualityScoreDelta")]
+    pub quality_score_delta: Option<f64>,
+}
+
+/// Where the code of an audit created by an integration came from.
+#[derive(Debug, Clone)]
+pub struct AuditSource {
+    /// The repository, as `owner/name`.
+    pub repository: String,
+    /// The pull request number.
+    pub pull_request: i32,
+    /// The path of the file within the repository.
+    pub path: String,
+}
+
+/// How many audits of a set are valid.
+#[derive(Debug, Clone, Copy, FromRow)]
+pub struct ValidityCounts {
+    /// The number of audits in the set.
+    pub total: i64,
+    /// The number of valid audits in the set.
+    pub valid: i64,
+}
+
+/// Filters applied when listing audits.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditFilter {
+    /// Only include audits whose code has at least this many lines.
+    pub min_lines: Option<i32>,
+    /// Only include audits whose code has at most this many lines.
+    pub max_lines: Option<i32>,
+    /// Only include audits carrying all of these tags (comma-separated).
+    #[serde(default, deserialize_with = "deserialize_comma_separated")]
+    #[param(value_type = Option<String>)]
+    pub tags_contains: Option<Vec<String>>,
+    /// Only include audits compiled by this toolchain (e.g. `rustc 1.95.0 (...)`).
+    pub rustc_version: Option<String>,
+    /// Only include audits whose prompt and code have at most this many estimated tokens
+    /// in total.
+    pub max_tokens: Option<i32>,
+    /// Only include audits whose code has (`true`) or lacks (`false`) an
+    /// `SPDX-License-Identifier` header.
+    pub has_license: Option<bool>,
+    /// Only include audits with (`true`) or without (`false`) dependencies whose license
+    /// the license policy does not allow.
+    pub license_violations: Option<bool>,
+    /// Only include audits whose dependencies `cargo audit` reported (`true`) or did not
+    /// report (`false`) advisories for.
+    pub has_security_issues: Option<bool>,
+}
+
+/// Deserializes a comma-separated query parameter into a list.
+fn deserialize_comma_separated<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = Option::<String>::deserialize(deserializer)?;
+    Ok(value.map(|v| v.split(',').map(str::to_string).collect()))
 }
 
 /// Represents the statistics of all AI code audits.
-#[derive(Debug, Serialize, Deserialize, SimpleObject)]
+#[derive(Debug, Serialize, Deserialize, SimpleObject, ToSchema)]
 #[graphql(name = "AuditStats")]
 pub struct AuditStats {
     /// The total number of audits performed.
@@ -53,13 +960,188 @@ pub struct AuditStats {
     /// The ratio of valid audits to total audits (0.0 to 1.0).
     #[graphql(name = "validationRate")]
     pub validation_rate: f64,
+    /// The average number of lines of the generated code.
+    #[graphql(name = "averageCodeLineCount")]
+    pub average_code_line_count: f64,
+    /// The average number of characters of the generated code.
+    #[graphql(name = "averageCodeCharCount")]
+    pub average_code_char_count: f64,
+    /// The number of lines of the longest generated code.
+    #[graphql(name = "maxCodeLineCount")]
+    pub max_code_line_count: i64,
+    /// The average quality score of the audits (0 to 100).
+    #[graphql(name = "averageQualityScore")]
+    pub average_quality_score: f64,
+    /// The average time `rustc` took to compile the code, in milliseconds.
+    #[graphql(name = "averageCompilationDurationMs")]
+    pub average_compilation_duration_ms: f64,
+    /// The estimated number of tokens of the prompts and code of all audits.
+    #[graphql(name = "totalTokensProcessed")]
+    pub total_tokens_processed: i64,
+    /// The average estimated number of tokens of the prompts.
+    #[graphql(name = "averagePromptTokens")]
+    pub average_prompt_tokens: f64,
+    /// The number of advisories of the RustSec database affecting the dependencies of
+    /// the audits.
+    #[graphql(name = "vulnerabilityCount")]
+    pub vulnerability_count: i64,
     /// A list of the most common compilation errors.
     #[graphql(name = "commonErrors")]
     pub common_errors: Vec<CommonError>,
+    /// The audits grouped by the target they are compiled for.
+    #[graphql(name = "byTarget")]
+    pub by_target: Vec<TargetStats>,
+    /// The audits grouped by the Rust edition they are compiled with.
+    #[graphql(name = "byEdition")]
+    pub by_edition: Vec<EditionStats>,
+    /// The number of audits per license expression of their `SPDX-License-Identifier`
+    /// header.
+    #[graphql(name = "licenseDistribution")]
+    pub license_distribution: Vec<LicenseStat>,
+    /// How often audited code contains leftovers.
+    pub hygiene: HygieneStats,
+    /// The number of audits created on each of the last days, oldest first.
+    #[graphql(name = "dailyCounts")]
+    pub daily_counts: Vec<DailyCount>,
+}
+
+/// The number of audits created on a UTC day, and how many of them are valid.
+#[derive(Debug, Serialize, Deserialize, SimpleObject, FromRow, ToSchema)]
+#[graphql(name = "DailyCount")]
+pub struct DailyCount {
+    /// The UTC day.
+    pub date: NaiveDate,
+    /// The number of audits created that day.
+    pub count: i64,
+    /// The number of valid audits created that day.
+    #[graphql(name = "validCount")]
+    pub valid_count: i64,
+}
+
+/// How often audited code contains debug output, `TODO` comments or placeholders.
+///
+/// Rates are computed over the audits with a hygiene report, from 0.0 to 1.0.
+#[derive(Debug, Serialize, Deserialize, SimpleObject, FromRow, ToSchema)]
+#[graphql(name = "HygieneStats")]
+pub struct HygieneStats {
+    /// The number of audits with a hygiene report.
+    #[graphql(name = "analyzedAudits")]
+    pub analyzed_audits: i64,
+    /// The share of audits containing debug output.
+    #[graphql(name = "debugOutputRate")]
+    pub debug_output_rate: f64,
+    /// The share of audits containing `TODO`, `FIXME` or `XXX` comments.
+    #[graphql(name = "todoMarkerRate")]
+    pub todo_marker_rate: f64,
+    /// The share of audits containing placeholder identifiers.
+    #[graphql(name = "placeholderRate")]
+    pub placeholder_rate: f64,
+    /// The average hygiene score, or 0 without analyzed audits.
+    #[graphql(name = "averageHygieneScore")]
+    pub average_hygiene_score: f64,
+}
+
+/// The number of audits compiled for a target, and how many of them are valid.
+#[derive(Debug, Serialize, Deserialize, SimpleObject, FromRow, ToSchema)]
+#[graphql(name = "TargetStats")]
+pub struct TargetStats {
+    /// The target triple, or `None` for the host of the server.
+    pub target: Option<String>,
+    /// The number of audits compiled for this target.
+    #[graphql(name = "totalAudits")]
+    pub total_audits: i64,
+    /// The number of valid audits compiled for this target.
+    #[graphql(name = "validAudits")]
+    pub valid_audits: i64,
+}
+
+/// The number of audits compiled with an edition, and how many of them are valid.
+#[derive(Debug, Serialize, Deserialize, SimpleObject, FromRow, ToSchema)]
+#[graphql(name = "EditionStats")]
+pub struct EditionStats {
+    /// The edition (e.g. `2021`), or `unknown` for audits created before editions were
+    /// recorded.
+    pub edition: String,
+    /// The number of audits compiled with this edition.
+    pub count: i64,
+    /// The number of valid audits compiled with this edition.
+    #[graphql(name = "validCount")]
+    pub valid_count: i64,
+    /// The share of valid audits, between 0 and 1.
+    #[graphql(name = "passRate")]
+    pub pass_rate: f64,
+}
+
+/// The number of audits whose code declares a license expression.
+#[derive(Debug, Serialize, Deserialize, SimpleObject, FromRow, ToSchema)]
+#[graphql(name = "LicenseStat")]
+pub struct LicenseStat {
+    /// The license expression (e.g. `MIT`), or `none` for audits without a license header.
+    pub license: String,
+    /// The number of audits declaring this license expression.
+    pub count: i64,
+}
+
+/// The property audits are grouped by in grouped statistics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Enum, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsGroupBy {
+    /// The UTC day the audit was created, as `YYYY-MM-DD`.
+    Day,
+    /// The model that generated the code; `None` for code submitted directly.
+    Model,
+    /// Each tag of the audit; audits without tags are left out.
+    Tag,
+    /// The primary `rustc` error code; `None` for audits without one.
+    ErrorCode,
+}
+
+impl std::str::FromStr for StatsGroupBy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "day" => Ok(StatsGroupBy::Day),
+            "model" => Ok(StatsGroupBy::Model),
+            "tag" => Ok(StatsGroupBy::Tag),
+            "error_code" => Ok(StatsGroupBy::ErrorCode),
+            _ => Err(format!(
+                "invalid group_by '{}', expected day, model, tag or error_code",
+                value
+            )),
+        }
+    }
+}
+
+/// The number of audits sharing a value of the grouped property, and how many are valid.
+#[derive(Debug, Serialize, Deserialize, SimpleObject, FromRow, ToSchema)]
+#[graphql(name = "StatsBucket")]
+pub struct StatsBucket {
+    /// The value shared by the audits of the bucket, or `None` if they have none.
+    pub key: Option<String>,
+    /// The number of audits in the bucket.
+    #[graphql(name = "totalAudits")]
+    pub total_audits: i64,
+    /// The number of valid audits in the bucket.
+    #[graphql(name = "validAudits")]
+    pub valid_audits: i64,
+    /// The ratio of valid audits to audits in the bucket (0.0 to 1.0).
+    #[graphql(name = "successRate")]
+    pub success_rate: f64,
+}
+
+/// The body of `GET /stats`: the aggregate statistics, or the buckets of `group_by`.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum StatsResponse {
+    /// The statistics of all audits, returned without `group_by`.
+    Aggregate(Box<AuditStats>),
+    /// The audits grouped by `group_by`.
+    Grouped(Vec<StatsBucket>),
 }
 
 /// Represents a common compilation error and its frequency.
-#[derive(Debug, Serialize, Deserialize, SimpleObject, FromRow)]
+#[derive(Debug, Serialize, Deserialize, SimpleObject, FromRow, ToSchema)]
 #[graphql(name = "CommonError")]
 pub struct CommonError {
     /// The compilation error message.
@@ -68,3 +1150,579 @@ pub struct CommonError {
     /// The number of times this error has occurred.
     pub frequency: i64,
 }
+
+/// Represents a note attached to an audit by a human reviewer.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, SimpleObject, ToSchema)]
+#[graphql(name = "AuditComment")]
+pub struct AuditComment {
+    /// The unique identifier for the comment.
+    pub id: Uuid,
+    /// The audit this comment belongs to.
+    #[graphql(name = "auditId")]
+    pub audit_id: Uuid,
+    /// The name of the user who wrote the comment.
+    pub author: String,
+    /// The content of the comment.
+    pub body: String,
+    /// The timestamp when the comment was created.
+    #[graphql(name = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+/// Represents the incoming request payload for commenting on an audit.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateCommentRequest {
+    /// The content of the comment.
+    pub body: String,
+}
+
+/// Represents the quality rating of an audit by a reviewer.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, SimpleObject, ToSchema)]
+#[graphql(name = "AuditRating")]
+pub struct AuditRating {
+    /// The audit this rating belongs to.
+    #[graphql(name = "auditId")]
+    pub audit_id: Uuid,
+    /// The name of the user who rated the audit.
+    pub rater: String,
+    /// The score given, from 1 (poor) to 5 (excellent).
+    pub score: i16,
+    /// The reason for the score, if the reviewer gave one.
+    pub comment: Option<String>,
+    /// The timestamp when the reviewer last rated the audit.
+    #[graphql(name = "ratedAt")]
+    pub rated_at: DateTime<Utc>,
+}
+
+/// Represents the incoming request payload for rating an audit.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RateAuditRequest {
+    /// The score, from 1 (poor) to 5 (excellent).
+    pub score: i16,
+    /// The reason for the score.
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+/// Represents the files kept from the compilation of an audit.
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject, ToSchema)]
+#[graphql(name = "AuditArtifacts")]
+pub struct AuditArtifacts {
+    /// The audit the files belong to.
+    #[graphql(name = "auditId")]
+    pub audit_id: Uuid,
+    /// The uploaded files, with the URLs they can be downloaded from.
+    pub files: Vec<ArtifactFile>,
+    /// Why some files could not be produced or uploaded, if any.
+    pub error: Option<String>,
+    /// The timestamp when the files were uploaded, or `None` while the upload is under
+    /// way or if the audit has no artifacts.
+    #[graphql(name = "uploadedAt")]
+    pub uploaded_at: Option<DateTime<Utc>>,
+}
+
+/// Represents a file kept from the compilation of an audit.
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject, ToSchema)]
+#[graphql(name = "ArtifactFile")]
+pub struct ArtifactFile {
+    /// The name of the file: `diagnostics.json`, `clippy.json` or `lib.rlib`.
+    pub name: String,
+    /// The key of the file in the artifact store, e.g. `audits/<id>/lib.rlib`.
+    pub key: String,
+    /// The URL the file can be downloaded from.
+    pub url: String,
+    /// The timestamp after which the URL no longer works, if it expires.
+    #[graphql(name = "expiresAt")]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// An endpoint notified when audits complete.
+///
+/// The signing secret is never returned.
+#[derive(Debug, Serialize, FromRow, ToSchema)]
+pub struct Webhook {
+    /// The unique identifier for the webhook.
+    pub id: Uuid,
+    /// The URL the notifications are POSTed to.
+    pub url: String,
+    /// The events the webhook subscribes to (e.g. `audit.completed`).
+    pub events: Vec<String>,
+    /// Whether notifications are sent to the webhook.
+    pub active: bool,
+    /// The timestamp when the webhook was registered.
+    pub created_at: DateTime<Utc>,
+}
+
+/// Represents the incoming request payload for registering a webhook.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateWebhookRequest {
+    /// The `http` or `https` URL the notifications are POSTed to.
+    pub url: String,
+    /// The key signing each notification in the `X-Signature-256` header.
+    pub secret: String,
+    /// The events to subscribe to. Defaults to every event (`audit.completed`).
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Whether notifications are sent to the webhook. Defaults to `true`.
+    #[serde(default)]
+    pub active: Option<bool>,
+}
+
+/// A user account allowed to request access tokens.
+///
+/// The password hash is never returned.
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
+pub struct User {
+    /// The unique identifier for the user.
+    pub id: Uuid,
+    /// The unique name the user logs in with.
+    pub username: String,
+    /// The bcrypt hash of the password.
+    #[serde(skip)]
+    pub password_hash: String,
+    /// What the user is allowed to do.
+    pub role: Role,
+}
+
+/// What a user is allowed to do.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum Role {
+    /// A regular user.
+    #[default]
+    User,
+    /// An administrator.
+    Admin,
+}
+
+/// Represents the incoming request payload for logging in or registering.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct LoginRequest {
+    /// The name of the user.
+    pub username: String,
+    /// The password of the user.
+    pub password: String,
+}
+
+/// An access token issued to a user.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TokenResponse {
+    /// The JWT to send in the `Authorization: Bearer` header.
+    pub access_token: String,
+    /// The number of seconds the token is valid for.
+    pub expires_in: u64,
+}
+
+/// Represents a single diagnostic (error, warning, note...) emitted by the compiler.
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject, ToSchema)]
+#[graphql(name = "Diagnostic")]
+pub struct Diagnostic {
+    /// The rustc error code (e.g. `E0308`), if any.
+    pub code: Option<String>,
+    /// The severity level (`error`, `warning`, `note`...).
+    pub level: String,
+    /// The main diagnostic message.
+    pub message: String,
+    /// The line of the primary span, if any.
+    pub line: Option<u32>,
+    /// The column of the primary span, if any.
+    pub column: Option<u32>,
+    /// The path of the file of the primary span, for audits of several files.
+    #[serde(default)]
+    pub file: Option<String>,
+}
+
+/// The first message sent on the `/audit/stream` WebSocket, describing the code to compile.
+#[derive(Debug, Deserialize)]
+pub struct StreamCompilationRequest {
+    /// The code to compile.
+    pub generated_code: String,
+    /// The release channel of the toolchain compiling the code. Defaults to `stable`.
+    #[serde(default)]
+    pub channel: Option<Channel>,
+    /// The optimization level passed to `rustc -C opt-level=`. Defaults to `0`.
+    #[serde(default)]
+    pub opt_level: Option<OptLevel>,
+    /// The Rust edition passed to `rustc --edition`. Defaults to the edition guessed from
+    /// the code.
+    #[serde(default)]
+    pub edition: Option<Edition>,
+    /// The target triple passed to `rustc --target`. Defaults to the host of the server.
+    #[serde(default)]
+    pub target: Option<String>,
+}
+
+/// A message sent on the `/audit/stream` WebSocket while code is compiled.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CompilationEvent {
+    /// A line of compiler output, sent as soon as `rustc` produces it.
+    Log {
+        /// The line, without its line terminator.
+        line: String,
+    },
+    /// The result of the compilation, sent last.
+    Verdict {
+        /// Whether the code compiled successfully.
+        success: bool,
+        /// The rustc error code of the dominant error, if the code did not compile.
+        primary_error_code: Option<String>,
+        /// The category of the dominant error, if the code did not compile.
+        primary_error_category: Option<String>,
+        /// The diagnostics emitted by `rustc`.
+        diagnostics: Vec<Diagnostic>,
+        /// How long `rustc` ran, in milliseconds.
+        duration_ms: u128,
+    },
+    /// The request could not be processed, sent last.
+    Error {
+        /// What went wrong.
+        message: String,
+    },
+}
+
+/// A step reached by an audit compiled in the background.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditProgressStage {
+    /// The code passed validation and waits to be compiled.
+    Validated,
+    /// Cargo resolved the dependencies of a Cargo package, which it builds before the
+    /// package itself.
+    DependenciesResolved,
+    /// The code is being compiled; sent with the compiler output produced since the
+    /// previous event.
+    Compiling,
+    /// The audit has its verdict, or was cancelled. Sent last.
+    Completed,
+}
+
+impl AuditProgressStage {
+    /// Returns the name of the stage, as used for the SSE event names.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditProgressStage::Validated => "validated",
+            AuditProgressStage::DependenciesResolved => "dependencies_resolved",
+            AuditProgressStage::Compiling => "compiling",
+            AuditProgressStage::Completed => "completed",
+        }
+    }
+}
+
+/// An event streamed by `/audit/{id}/progress` and the `auditProgress` subscription.
+#[derive(Debug, Clone, Serialize, SimpleObject, ToSchema)]
+#[graphql(name = "AuditProgress")]
+pub struct AuditProgress {
+    /// The step the audit reached.
+    pub stage: AuditProgressStage,
+    /// The lines of compiler output produced since the previous event, for `compiling`
+    /// events.
+    pub lines: Vec<String>,
+    /// The audit with its verdict, for the `completed` event.
+    pub audit: Option<AiAudit>,
+}
+
+impl AuditProgress {
+    /// Returns an event carrying no lines nor audit.
+    pub fn new(stage: AuditProgressStage) -> Self {
+        AuditProgress {
+            stage,
+            lines: Vec::new(),
+            audit: None,
+        }
+    }
+
+    /// Returns a `compiling` event carrying lines of compiler output.
+    pub fn compiling(lines: Vec<String>) -> Self {
+        AuditProgress {
+            lines,
+            ..AuditProgress::new(AuditProgressStage::Compiling)
+        }
+    }
+
+    /// Returns the `completed` event of an audit.
+    pub fn completed(audit: AiAudit) -> Self {
+        AuditProgress {
+            audit: Some(audit),
+            ..AuditProgress::new(AuditProgressStage::Completed)
+        }
+    }
+}
+
+/// Represents how often a given error category was the primary error category of an audit.
+#[derive(Debug, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct CategoryFrequency {
+    /// The error category (e.g. `type_mismatch`).
+    pub category: String,
+    /// The number of audits whose dominant error had this category.
+    pub count: i64,
+}
+
+/// Represents how often a given rustc error code was the primary error of an audit.
+#[derive(Debug, Serialize, Deserialize, SimpleObject, FromRow)]
+#[graphql(name = "ErrorCodeFrequency")]
+pub struct ErrorCodeFrequency {
+    /// The rustc error code (e.g. `E0308`).
+    pub code: String,
+    /// The number of audits whose primary error had this code.
+    pub count: i64,
+}
+
+/// An audit record loaded by `POST /audits/import`, stored as-is without compiling it.
+///
+/// The columns of the first version of the schema (`codigo_generado`, `es_valido`,
+/// `error_compilacion`) are accepted as aliases, so old backups can be restored.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ImportAuditRecord {
+    /// The identifier of the audit. A new one is generated when omitted.
+    #[serde(default)]
+    pub id: Option<Uuid>,
+    /// The prompt that was given to the AI.
+    pub prompt: String,
+    /// The code that was generated by the AI.
+    #[serde(alias = "codigo_generado")]
+    pub generated_code: String,
+    /// Whether the code compiled successfully.
+    #[serde(alias = "es_valido")]
+    pub is_valid: bool,
+    /// The verdict of the audit. Defaults to `valid` or `compile_error` after `is_valid`.
+    #[serde(default)]
+    pub status: Option<AuditStatus>,
+    /// The compilation error message, if any.
+    #[serde(default, alias = "error_compilacion")]
+    pub compilation_error: Option<String>,
+    /// The rustc error code of the first compilation error (e.g. `E0308`), if any.
+    #[serde(default)]
+    pub primary_error_code: Option<String>,
+    /// The potential problems detected by the heuristic validation of the code.
+    #[serde(default)]
+    pub findings: Vec<Finding>,
+    /// Labels organizing the audit.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// The release channel the code was compiled with. Defaults to `stable`.
+    #[serde(default)]
+    pub channel: Option<Channel>,
+    /// The optimization level the code was compiled with. Defaults to `0`.
+    #[serde(default)]
+    #[schema(value_type = Option<String>, example = "0")]
+    pub opt_level: Option<OptLevel>,
+    /// The Rust edition the code was compiled with, if known.
+    #[serde(default)]
+    #[schema(value_type = Option<String>, example = "2021")]
+    pub edition: Option<Edition>,
+    /// The target triple the code was compiled for, if not the host.
+    #[serde(default)]
+    pub target: Option<String>,
+    /// The `rustc --version` of the toolchain that compiled the code, if known.
+    #[serde(default)]
+    pub rustc_version: Option<String>,
+    /// The `rustc` invocation that compiled the code, if known.
+    #[serde(default)]
+    pub compile_command: Option<Vec<String>>,
+    /// When the audit was created. Defaults to the time of the import.
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Summarizes a bulk import of audit records.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct ImportReport {
+    /// The number of records in the imported array.
+    pub attempted: usize,
+    /// The number of records stored.
+    pub imported: usize,
+    /// The number of records not stored because an audit with the same id exists.
+    pub skipped: usize,
+    /// The records that were not stored, and why.
+    pub errors: Vec<ImportRowError>,
+}
+
+/// Why a record of a bulk import was not stored.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImportRowError {
+    /// The 0-based position of the record in the imported array.
+    pub index: usize,
+    /// What is wrong with the record.
+    pub message: String,
+}
+
+/// Summarizes a bulk re-run of previously failing audits.
+#[derive(Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct RerunReport {
+    /// The number of audits that were recompiled.
+    pub attempted: i64,
+    /// The number of audits that compile successfully now.
+    pub now_passing: i64,
+    /// The number of audits that still fail to compile.
+    pub still_failing: i64,
+}
+
+/// Summarizes a re-audit of every audit that failed to compile, and of the valid audits
+/// with warnings when warnings count as errors.
+#[derive(Debug, Serialize, Deserialize, SimpleObject, ToSchema)]
+#[graphql(name = "ReauditReport")]
+pub struct ReauditReport {
+    /// The number of audits that were recompiled.
+    pub processed: i64,
+    /// The number of audits that compile successfully now.
+    #[graphql(name = "newlyValid")]
+    pub newly_valid: i64,
+    /// The number of audits that still fail to compile.
+    #[graphql(name = "stillInvalid")]
+    pub still_invalid: i64,
+    /// The number of valid audits that compiled with warnings and are invalid now that
+    /// warnings count as errors.
+    #[graphql(name = "newlyInvalid")]
+    pub newly_invalid: i64,
+}
+
+/// Represents the activity counters of the compilation workers.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WorkerStats {
+    /// The number of workers currently compiling.
+    pub active_workers: u64,
+    /// The number of compilations waiting for a worker.
+    pub queue_depth: u64,
+    /// The number of compilations completed since startup.
+    pub total_processed: u64,
+    /// The number of compilations for which `rustc` could not be run.
+    pub total_failed: u64,
+}
+
+/// Describes the server, its toolchain and its database, for operators and auditors.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SystemInfo {
+    /// The output of `rustc --version` for the stable toolchain, or why it could not be run.
+    pub rustc_version: String,
+    /// The version of rust-ai-auditor.
+    pub server_version: String,
+    /// The output of `SELECT version()` on the primary database.
+    pub database_version: String,
+    /// The operating system the server runs on (e.g. `linux`).
+    pub os: String,
+    /// The CPU architecture the server runs on (e.g. `x86_64`).
+    pub arch: String,
+    /// The number of compilations run at the same time.
+    pub worker_concurrency: u32,
+    /// The number of seconds since the server started.
+    pub uptime_secs: u64,
+}
+
+/// The state of a background audit job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum, sqlx::Type, ToSchema)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum JobState {
+    /// The job waits for a worker, possibly until a retry is due.
+    Pending,
+    /// A worker is compiling the audit.
+    Running,
+    /// The audit got its verdict.
+    Done,
+    /// The job failed too many times and is only retried on request.
+    Failed,
+    /// The job was cancelled before the audit got its verdict.
+    Cancelled,
+}
+
+/// A job compiling an audit submitted in the background.
+#[derive(Debug, Clone, Serialize, FromRow, SimpleObject, ToSchema)]
+#[graphql(name = "AuditJob")]
+pub struct AuditJob {
+    /// The unique identifier of the job.
+    pub id: Uuid,
+    /// The audit compiled by the job.
+    #[graphql(name = "auditId")]
+    pub audit_id: Uuid,
+    /// The state of the job.
+    pub state: JobState,
+    /// The number of times a worker claimed the job.
+    pub attempts: i32,
+    /// When the job is due to run, or ran last.
+    #[graphql(name = "runAt")]
+    pub run_at: DateTime<Utc>,
+    /// Why the last attempt failed, if it did.
+    #[graphql(name = "lastError")]
+    pub last_error: Option<String>,
+    /// The timestamp when the job was enqueued.
+    #[graphql(name = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    /// The timestamp when the job last changed state.
+    #[graphql(name = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Represents a background audit job and, once it is done, its audit.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuditJobStatus {
+    /// The job.
+    #[serde(flatten)]
+    pub job: AuditJob,
+    /// The audit with its verdict, once the job is done.
+    pub audit: Option<AiAudit>,
+}
+
+/// Describes the queue of background audit jobs.
+#[derive(Debug, Serialize, SimpleObject)]
+#[graphql(name = "JobQueueStats")]
+pub struct JobQueueStats {
+    /// The number of jobs waiting for a worker or being run.
+    pub depth: i64,
+    /// How long the oldest job waiting for a worker or being run has been enqueued, in
+    /// seconds, or null if the queue is empty.
+    #[graphql(name = "oldestPendingAgeSecs")]
+    pub oldest_pending_age_secs: Option<f64>,
+    /// The number of jobs that failed too many times.
+    #[graphql(name = "deadCount")]
+    pub dead_count: i64,
+    /// The most recently failed jobs that failed too many times, newest first.
+    #[graphql(name = "deadLetters")]
+    pub dead_letters: Vec<AuditJob>,
+}
+
+/// Describes the read replica and how often reads fell back to the primary database.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DbStats {
+    /// Whether a read replica is configured.
+    pub replica_configured: bool,
+    /// Whether the read replica answered its last check.
+    pub replica_healthy: bool,
+    /// The number of reads sent to the primary database since startup because the read
+    /// replica was unreachable.
+    pub fallback_reads: u64,
+}
+
+/// Represents the counters of the in-memory cache of audit results.
+#[derive(Debug, Serialize, Deserialize, SimpleObject, ToSchema)]
+#[graphql(name = "AuditCacheStats")]
+pub struct AuditCacheStats {
+    /// The number of results the cache holds at most; 0 if it is disabled.
+    pub capacity: u64,
+    /// The number of results currently cached, including expired ones not evicted yet.
+    pub entries: u64,
+    /// The number of audits whose result was found in the cache.
+    pub hits: u64,
+    /// The number of audits whose result was not cached, or had expired.
+    pub misses: u64,
+    /// The number of audits that skipped the cache with `bypass_cache`.
+    pub bypasses: u64,
+    /// The ratio of hits to lookups (0.0 to 1.0).
+    pub hit_rate: f64,
+}
+
+/// Represents the hit/miss counters of the Automatic Persisted Queries store.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ApqStats {
+    /// The number of hash-only requests whose query was known.
+    pub hits: u64,
+    /// The number of hash-only requests whose query was unknown.
+    pub misses: u64,
+    /// The number of queries registered by clients.
+    pub registrations: u64,
+    /// The ratio of hits to hash-only requests (0.0 to 1.0).
+    pub hit_rate: f64,
+}
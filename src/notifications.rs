@@ -0,0 +1,38 @@
+//! Sends alerts about audits to chat services.
+
+pub mod slack;
+
+use crate::{models::AiAudit, webhooks::WebhookNotifier};
+use slack::SlackNotifier;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+/// The notifiers told about every newly created audit.
+#[derive(Clone)]
+pub struct AuditNotifiers {
+    /// The notifier of the registered webhooks.
+    pub webhooks: WebhookNotifier,
+    /// The Slack notifier alerting about failed audits, if configured.
+    pub slack: Option<Arc<SlackNotifier>>,
+}
+
+impl AuditNotifiers {
+    /// Notifies the webhooks subscribed to `audit.completed`, and Slack if the code is
+    /// not valid, in the background.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - A reference to the database connection pool.
+    /// * `audit` - The newly created audit.
+    pub fn audit_created(&self, pool: &PgPool, audit: &AiAudit) {
+        self.webhooks.audit_completed(pool, audit);
+        if let Some(slack) = self.slack.as_ref().filter(|_| !audit.is_valid) {
+            let (slack, failed) = (slack.clone(), audit.clone());
+            tokio::spawn(async move {
+                if let Err(e) = slack.notify_failure(&failed).await {
+                    tracing::warn!(audit = %failed.id, error = %e, "Could not notify Slack.");
+                }
+            });
+        }
+    }
+}
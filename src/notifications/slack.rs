@@ -0,0 +1,126 @@
+//! Posts a Slack message when an audit fails, through an incoming webhook.
+//!
+//! [Reference](https://api.slack.com/messaging/webhooks)
+
+use crate::{
+    error::AppError,
+    models::{AiAudit, AuditStatus},
+};
+use serde_json::json;
+use std::time::Duration;
+
+/// The maximum number of characters of the prompt included in a message.
+const PROMPT_EXCERPT_LEN: usize = 100;
+
+/// Notifies a Slack channel about failed audits.
+pub struct SlackNotifier {
+    /// The incoming webhook URL of the channel.
+    webhook_url: String,
+    /// The HTTP client posting the messages.
+    client: reqwest::Client,
+}
+
+impl SlackNotifier {
+    /// Creates a notifier configured from the environment.
+    ///
+    /// * `SLACK_WEBHOOK_URL` - The incoming webhook URL of the channel to alert.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(SlackNotifier))` - If `SLACK_WEBHOOK_URL` is set.
+    /// * `Ok(None)` - If `SLACK_WEBHOOK_URL` is not set.
+    /// * `Err(anyhow::Error)` - If the HTTP client cannot be created.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Some(webhook_url) = std::env::var("SLACK_WEBHOOK_URL")
+            .ok()
+            .filter(|v| !v.is_empty())
+        else {
+            return Ok(None);
+        };
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("rust-ai-auditor/", env!("CARGO_PKG_VERSION")))
+            .timeout(Duration::from_secs(10))
+            .build()?;
+
+        tracing::info!("Slack notifications enabled");
+        Ok(Some(SlackNotifier {
+            webhook_url,
+            client,
+        }))
+    }
+
+    /// Posts a message describing a failed audit.
+    ///
+    /// The message holds the audit ID, the beginning of the prompt and the first
+    /// compilation error (or the rejection reason).
+    ///
+    /// # Arguments
+    ///
+    /// * `audit` - The audit whose code is not valid.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If Slack accepted the message.
+    /// * `Err(AppError::Upstream)` - If Slack cannot be reached or rejects the message.
+    pub async fn notify_failure(&self, audit: &AiAudit) -> Result<(), AppError> {
+        let response = self
+            .client
+            .post(&self.webhook_url)
+            .json(&json!({ "text": failure_message(audit) }))
+            .send()
+            .await
+            .map_err(|e| AppError::Upstream(format!("Failed to reach Slack: {}", e)))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(AppError::Upstream(format!(
+                "Slack rejected the notification ({}): {}",
+                status, body
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Builds the text of the message describing a failed audit.
+fn failure_message(audit: &AiAudit) -> String {
+    let mut prompt: String = audit.prompt.chars().take(PROMPT_EXCERPT_LEN).collect();
+    if audit.prompt.chars().count() > PROMPT_EXCERPT_LEN {
+        prompt.push('…');
+    }
+
+    let (headline, error) = match audit.status {
+        AuditStatus::Rejected => (
+            "was rejected by strict validation",
+            audit.rejection_reason.clone(),
+        ),
+        _ => (
+            "failed to compile",
+            audit.compilation_error.as_deref().and_then(first_error),
+        ),
+    };
+
+    let mut text = format!(
+        ":x: Audit `{}` {}\n>{}",
+        audit.id,
+        headline,
+        prompt.replace('\n', "\n>")
+    );
+    if let Some(error) = error {
+        text.push_str(&format!("\n```{}```", error));
+    }
+    text
+}
+
+/// Returns the first error of the compiler output, from its headline to the next blank
+/// line.
+fn first_error(output: &str) -> Option<String> {
+    let start = output.find("error")?;
+    let error = output[start..]
+        .split("\n\n")
+        .next()
+        .unwrap_or_default()
+        .trim_end();
+    Some(error.to_string())
+}
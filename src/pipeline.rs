@@ -0,0 +1,899 @@
+//! Audits code in-process: validation and compilation, without the web server or a
+//! database.
+//!
+//! [`Auditor`] is the library entry point for embedding the auditor in another tool, and
+//! what the web service judges requests with before storing them. It only needs an
+//! `AuditPolicy` and the compilation workers (`CompilationQueue::start`), so audits can
+//! be run without Postgres.
+
+use crate::{
+    artifacts::CompiledArtifacts,
+    auditor::{
+        self, AuditPolicy, CompilationOutcome, CompileOptions, CompilerOutput, LockedDependency,
+    },
+    cache,
+    error::AppError,
+    licenses,
+    models::{
+        AuditFile, AuditMetrics, AuditOutcome, AuditProfile, AuditStatus, Channel,
+        CreateAuditRequest, Diagnostic, Edition, Finding, FindingCategory, HygieneReport,
+        ScoreWeights, SecurityAdvisory, Severity, UnsafeReport,
+    },
+    workers::CompilationQueue,
+};
+use std::collections::HashSet;
+use tokio::sync::mpsc;
+
+/// Audits code like the web service, without storing anything.
+///
+/// The code is validated with the rules of the policy and, unless the findings reject it,
+/// compiled by the compilation workers, reusing their caches. It is then analyzed and
+/// scored.
+#[derive(Clone)]
+pub struct Auditor {
+    /// The settings the code is validated and judged with.
+    policy: AuditPolicy,
+    /// The queue of the compilation workers.
+    compiler: CompilationQueue,
+    /// The weights of the quality score.
+    weights: ScoreWeights,
+}
+
+impl Auditor {
+    /// Creates an auditor judging code under `policy` and compiling it on `compiler`.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The settings the code is validated and judged with.
+    /// * `compiler` - The queue of the compilation workers (see `CompilationQueue::start`).
+    pub fn new(policy: AuditPolicy, compiler: CompilationQueue) -> Self {
+        Auditor {
+            policy,
+            compiler,
+            weights: ScoreWeights::default(),
+        }
+    }
+
+    /// Makes the auditor only report the rules `profile` enables and score code with its
+    /// weights.
+    pub fn with_profile(mut self, profile: &AuditProfile) -> Self {
+        self.policy = self.policy.with_profile(profile);
+        self.weights = profile.weights.clone();
+        self
+    }
+
+    /// Returns the settings the code is validated and judged with.
+    pub fn policy(&self) -> &AuditPolicy {
+        &self.policy
+    }
+
+    /// Validates, compiles and analyzes the code of an audit request.
+    ///
+    /// The rules of the auditor's policy apply: `profile` is ignored (see `with_profile`),
+    /// and so are `idempotency_key` and `tags`, which only matter to stored audits.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The audit request, with its code in `generated_code` or `files`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(AuditOutcome)` - The verdict, the findings, the compiler diagnostics and the
+    ///   metrics of the code.
+    /// * `Err(AppError::Validation)` - If the request is invalid (see `check_request`), or
+    ///   sets `generate`, `auto_fix` or `background`, which need a stored audit.
+    /// * `Err(AppError)` - If the compilation workers fail for another reason than
+    ///   `rustc` itself.
+    pub async fn audit(&self, request: CreateAuditRequest) -> Result<AuditOutcome, AppError> {
+        if request.generate.is_some() || request.auto_fix.is_some() || request.background {
+            return Err(AppError::Validation(
+                "generate, auto_fix and background need a stored audit".to_string(),
+            ));
+        }
+        let code = match check_request(&request, &self.policy)? {
+            Some(root) => root.content.as_str(),
+            None => request.generated_code.as_str(),
+        }
+        .trim();
+        let (outcome, _) = self.run_audit_pipeline(&request, code).await?;
+        Ok(outcome)
+    }
+
+    /// Judges code (see `judge`), then analyzes and scores it. Stored audits and dry runs
+    /// both go through here, so that their results never differ.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The audit request, already checked (see `check_request`).
+    /// * `code` - The audited code: `generated_code`, the generated code or the crate root.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok((AuditOutcome, Option<CompiledArtifacts>))` - The result of the audit, and
+    ///   the outputs of the compilation to upload, if the code was compiled.
+    /// * `Err(AppError)` - If the compilation workers fail for another reason than `rustc`
+    ///   itself.
+    pub(crate) async fn run_audit_pipeline(
+        &self,
+        input: &CreateAuditRequest,
+        code: &str,
+    ) -> Result<(AuditOutcome, Option<CompiledArtifacts>), AppError> {
+        let (mut findings, mut verdict) = self.judge(input, code).await?;
+        let artifacts = verdict.artifacts.take();
+        let vulnerability_count = verdict.vulnerability_count();
+        let license_violation_count = verdict.license_violation_count();
+        findings.extend(verdict.vulnerabilities.take().unwrap_or_default());
+        findings.extend(verdict.license_violations.take().unwrap_or_default());
+        let has_security_issues = verdict
+            .security_advisories
+            .as_ref()
+            .is_some_and(|advisories| !advisories.is_empty());
+        let doc_coverage_percent = auditor::compute_doc_coverage(code).ok();
+        let unsafe_report = auditor::check_unsafe_usage(code).ok();
+        let hygiene_report = auditor::check_hygiene(code);
+        let metrics = compute_audit_metrics(&PartialAudit {
+            code,
+            is_valid: verdict.is_valid(),
+            findings: &findings,
+            diagnostics: &verdict.diagnostics,
+            doc_coverage_percent,
+            unsafe_report: unsafe_report.as_ref(),
+            hygiene_report: &hygiene_report,
+            compilation_duration_ms: verdict.compilation_duration_ms,
+            weights: &self.weights,
+        });
+        let outcome = AuditOutcome {
+            is_valid: verdict.is_valid(),
+            status: verdict.status,
+            edition: audit_edition(input, code),
+            findings,
+            diagnostics: verdict.diagnostics,
+            compilation_error: verdict.compilation_error,
+            primary_error_code: verdict.primary_error_code,
+            primary_error_category: verdict.primary_error_category,
+            rejection_reason: verdict.rejection_reason,
+            rustc_version: verdict.rustc_version,
+            compilation_duration_ms: verdict.compilation_duration_ms,
+            compile_command: verdict.compile_command,
+            vulnerability_count,
+            advisory_note: verdict.advisory_note,
+            license_violation_count,
+            security_advisories: verdict.security_advisories,
+            has_security_issues,
+            doc_coverage_percent,
+            unsafe_report,
+            hygiene_report,
+            metrics,
+        };
+        Ok((outcome, artifacts))
+    }
+
+    /// Validates code and, unless strict validation or a banned crate rejects it, compiles it.
+    ///
+    /// Code submitted in the background is not compiled: it gets a pending verdict, and a
+    /// job compiles it later (see `run_audit_job`).
+    ///
+    /// # Returns
+    ///
+    /// * `Ok((Vec<Finding>, Verdict))` - The findings of the validation and the verdict.
+    /// * `Err(AppError)` - If the compilation workers fail for another reason than `rustc`
+    ///   itself.
+    async fn judge(
+        &self,
+        input: &CreateAuditRequest,
+        code: &str,
+    ) -> Result<(Vec<Finding>, Verdict), AppError> {
+        let (policy, compiler) = (&self.policy, &self.compiler);
+        let strict = input.strict.unwrap_or(policy.strict);
+        let options = CompileOptions {
+            channel: input.channel.unwrap_or_default(),
+            opt_level: input.opt_level.unwrap_or_default(),
+            edition: audit_edition(input, code),
+            target: input.target.clone(),
+            files: input.files.clone(),
+        };
+        // Background audits are compiled by a job, which is not worth skipping.
+        let results = compiler.audit_results();
+        let key = (results.is_enabled() && !input.background)
+            .then(|| cache::audit_result_key(code, &options, policy, strict));
+        if let Some(key) = &key {
+            if input.bypass_cache {
+                results.record_bypass();
+            } else if let Some(judged) = results.get(key) {
+                tracing::debug!("Audit result found in the cache.");
+                return Ok((judged.findings, judged.verdict));
+            }
+        }
+
+        let findings = if input.files.is_empty() {
+            auditor::validate_code(code, policy)
+        } else {
+            validate_files(&input.files, policy)
+        };
+        let blocking: Vec<&Finding> = findings
+            .iter()
+            .filter(|f| f.severity == Severity::Error)
+            .collect();
+
+        let always_rejected = findings.iter().any(auditor::always_rejects);
+        let mut verdict = if always_rejected || strict && !blocking.is_empty() {
+            tracing::info!(
+                findings = blocking.len(),
+                always_rejected,
+                "Code rejected by validation."
+            );
+            Verdict::rejected(&blocking)
+        } else if input.background {
+            Verdict::pending()
+        } else {
+            compile_verdict(policy, compiler, code, options, None).await?
+        };
+
+        // Code that could not be compiled at all is compiled again next time, and so are
+        // dependencies that could not be checked against the advisory database.
+        if let Some(key) = key
+            && (verdict.status == AuditStatus::Rejected || verdict.compile_command.is_some())
+            && verdict.advisory_note.is_none()
+        {
+            let artifacts = verdict.artifacts.take();
+            let cached = Verdict {
+                artifacts: artifacts.as_ref().map(CompiledArtifacts::without_library),
+                ..verdict.clone()
+            };
+            verdict.artifacts = artifacts;
+            results.put(
+                key,
+                JudgedCode {
+                    findings: findings.clone(),
+                    verdict: cached,
+                },
+            );
+        }
+        Ok((findings, verdict))
+    }
+}
+
+/// Checks the parts of an audit request that do not depend on stored data: the prompt,
+/// the code, the files and the toolchain.
+///
+/// # Arguments
+///
+/// * `input` - The audit request.
+/// * `policy` - The settings limiting the size of the code.
+///
+/// # Returns
+///
+/// * `Ok(Option<&AuditFile>)` - The root of the crate for requests submitting several
+///   files, whose code is audited.
+/// * `Err(AppError::Validation)` - If the prompt is blank, `generated_code` is blank
+///   without `generate` or `files`, more than one of them is set, the code is larger than
+///   `policy.max_code_bytes`, the files are invalid (see `check_files`), or the nightly
+///   toolchain or the target is not installed.
+pub fn check_request<'a>(
+    input: &'a CreateAuditRequest,
+    policy: &AuditPolicy,
+) -> Result<Option<&'a AuditFile>, AppError> {
+    if input.prompt.trim().is_empty() {
+        return Err(AppError::Validation("prompt must not be empty".to_string()));
+    }
+    let code_bytes = input.generated_code.len()
+        + input
+            .files
+            .iter()
+            .map(|file| file.content.len())
+            .sum::<usize>();
+    if code_bytes > policy.max_code_bytes {
+        return Err(AppError::Validation(format!(
+            "The code must be at most {} bytes long, got {}",
+            policy.max_code_bytes, code_bytes
+        )));
+    }
+    if input.generate.is_none() && input.files.is_empty() && input.generated_code.trim().is_empty()
+    {
+        return Err(AppError::Validation(
+            "generated_code must not be empty unless generate or files is set".to_string(),
+        ));
+    }
+    let root = if input.files.is_empty() {
+        None
+    } else if input.generate.is_some() || !input.generated_code.trim().is_empty() {
+        return Err(AppError::Validation(
+            "files, generated_code and generate are mutually exclusive".to_string(),
+        ));
+    } else {
+        Some(check_files(&input.files)?)
+    };
+    if input.generate.is_some() && !input.generated_code.trim().is_empty() {
+        return Err(AppError::Validation(
+            "generated_code and generate are mutually exclusive".to_string(),
+        ));
+    }
+
+    let channel = input.channel.unwrap_or_default();
+    if channel == Channel::Nightly
+        && let Err(e) = auditor::check_rustc_available(channel)
+    {
+        return Err(AppError::Validation(format!(
+            "The nightly toolchain is not installed on this server: {}",
+            e
+        )));
+    }
+    if let Some(target) = &input.target
+        && let Err(e) = auditor::check_target_installed(channel, target)
+    {
+        return Err(AppError::Validation(e));
+    }
+    Ok(root)
+}
+
+/// The columns of an audit describing its verdict.
+#[derive(Clone)]
+pub(crate) struct Verdict {
+    pub(crate) status: AuditStatus,
+    pub(crate) compilation_error: Option<String>,
+    pub(crate) primary_error_code: Option<String>,
+    pub(crate) primary_error_category: Option<String>,
+    pub(crate) rejection_reason: Option<String>,
+    pub(crate) diagnostics: Vec<Diagnostic>,
+    pub(crate) rustc_version: Option<String>,
+    pub(crate) compilation_duration_ms: Option<i32>,
+    /// The `rustc` invocation that gave the verdict, if the code was compiled.
+    pub(crate) compile_command: Option<Vec<String>>,
+    /// The outputs of the compilation uploaded to the artifact store, if it was compiled.
+    pub(crate) artifacts: Option<CompiledArtifacts>,
+    /// The `RAA0203` findings of the dependencies of a Cargo package, if they were
+    /// checked against the advisory database.
+    pub(crate) vulnerabilities: Option<Vec<Finding>>,
+    /// Why the dependencies of a Cargo package were not checked, if they were resolved.
+    pub(crate) advisory_note: Option<String>,
+    /// The `RAA0301` findings of the dependencies of a Cargo package, if their licenses
+    /// were checked against the license policy.
+    pub(crate) license_violations: Option<Vec<Finding>>,
+    /// The advisories `cargo audit` reported for the dependencies of a Cargo package, if
+    /// it ran.
+    pub(crate) security_advisories: Option<Vec<SecurityAdvisory>>,
+}
+
+impl Verdict {
+    /// Builds the verdict of code that was compiled with the toolchain of `channel`.
+    ///
+    /// Code compiling with warnings is a compile error if `policy` treats warnings as
+    /// errors.
+    pub(crate) fn compiled(
+        outcome: CompilationOutcome,
+        channel: Channel,
+        policy: &AuditPolicy,
+    ) -> Self {
+        let compilation_duration_ms =
+            Some(i32::try_from(outcome.duration.as_millis()).unwrap_or(i32::MAX));
+        if policy.accepts(&outcome) {
+            Verdict {
+                status: AuditStatus::Valid,
+                compilation_error: None,
+                primary_error_code: None,
+                primary_error_category: None,
+                rejection_reason: None,
+                diagnostics: outcome.diagnostics,
+                rustc_version: compiling_rustc_version(channel),
+                compilation_duration_ms,
+                compile_command: None,
+                artifacts: None,
+                vulnerabilities: None,
+                advisory_note: None,
+                license_violations: None,
+                security_advisories: None,
+            }
+        } else {
+            Verdict {
+                status: AuditStatus::CompileError,
+                primary_error_code: auditor::primary_error_code(&outcome.diagnostics),
+                primary_error_category: auditor::primary_error_category(&outcome.diagnostics)
+                    .map(|category| category.as_str().to_string()),
+                compilation_error: Some(outcome.output),
+                rejection_reason: None,
+                diagnostics: outcome.diagnostics,
+                rustc_version: compiling_rustc_version(channel),
+                compilation_duration_ms,
+                compile_command: None,
+                artifacts: None,
+                vulnerabilities: None,
+                advisory_note: None,
+                license_violations: None,
+                security_advisories: None,
+            }
+        }
+    }
+
+    /// Builds the verdict of code rejected by blocking findings before compilation.
+    pub(crate) fn rejected(blocking: &[&Finding]) -> Self {
+        let reason = blocking
+            .iter()
+            .map(|f| format!("{}: {}", f.code, f.message))
+            .collect::<Vec<_>>()
+            .join("; ");
+        Verdict {
+            status: AuditStatus::Rejected,
+            compilation_error: None,
+            primary_error_code: None,
+            primary_error_category: None,
+            rejection_reason: Some(reason),
+            diagnostics: Vec::new(),
+            rustc_version: None,
+            compilation_duration_ms: None,
+            compile_command: None,
+            artifacts: None,
+            vulnerabilities: None,
+            advisory_note: None,
+            license_violations: None,
+            security_advisories: None,
+        }
+    }
+
+    /// Builds the verdict of code left to a background job, which compiles it later.
+    pub(crate) fn pending() -> Self {
+        Verdict {
+            status: AuditStatus::Pending,
+            compilation_error: None,
+            primary_error_code: None,
+            primary_error_category: None,
+            rejection_reason: None,
+            diagnostics: Vec::new(),
+            rustc_version: None,
+            compilation_duration_ms: None,
+            compile_command: None,
+            artifacts: None,
+            vulnerabilities: None,
+            advisory_note: None,
+            license_violations: None,
+            security_advisories: None,
+        }
+    }
+
+    /// Builds the verdict of code that `rustc` could not be run on.
+    pub(crate) fn not_compiled(error: String) -> Self {
+        Verdict {
+            status: AuditStatus::CompileError,
+            compilation_error: Some(error),
+            primary_error_code: None,
+            primary_error_category: None,
+            rejection_reason: None,
+            diagnostics: Vec::new(),
+            rustc_version: None,
+            compilation_duration_ms: None,
+            compile_command: None,
+            artifacts: None,
+            vulnerabilities: None,
+            advisory_note: None,
+            license_violations: None,
+            security_advisories: None,
+        }
+    }
+
+    /// Checks the dependencies cargo locked in `lockfile` against the advisory database
+    /// of `compiler`, unless `policy` disables `RAA0203`, and the licenses of
+    /// `dependencies` against the license policy, unless it disables `RAA0301`.
+    pub(crate) fn with_dependencies_checked(
+        self,
+        lockfile: Option<&str>,
+        dependencies: Option<&[LockedDependency]>,
+        policy: &AuditPolicy,
+        compiler: &CompilationQueue,
+    ) -> Self {
+        let enabled = |code| !policy.disabled_rules.contains(code);
+        let verdict = match dependencies.filter(|_| enabled(auditor::LICENSE_VIOLATION_CODE)) {
+            Some(dependencies) => Verdict {
+                license_violations: Some(licenses::check(dependencies, &policy.licenses)),
+                ..self
+            },
+            None => self,
+        };
+        let Some(lockfile) = lockfile.filter(|_| enabled(auditor::VULNERABLE_DEPENDENCY_CODE))
+        else {
+            return verdict;
+        };
+        match compiler.advisories().check(lockfile) {
+            Ok(vulnerabilities) => Verdict {
+                vulnerabilities: Some(vulnerabilities),
+                ..verdict
+            },
+            Err(note) => Verdict {
+                advisory_note: Some(note),
+                ..verdict
+            },
+        }
+    }
+
+    /// Whether the audited code is considered valid.
+    pub(crate) fn is_valid(&self) -> bool {
+        self.status == AuditStatus::Valid
+    }
+
+    /// The number of advisories affecting the dependencies, if they were checked.
+    pub(crate) fn vulnerability_count(&self) -> Option<i32> {
+        self.vulnerabilities
+            .as_ref()
+            .map(|v| i32::try_from(v.len()).unwrap_or(i32::MAX))
+    }
+
+    /// The number of dependencies whose license the policy does not allow, if their
+    /// licenses were checked.
+    pub(crate) fn license_violation_count(&self) -> Option<i32> {
+        self.license_violations
+            .as_ref()
+            .map(|v| i32::try_from(v.len()).unwrap_or(i32::MAX))
+    }
+}
+
+/// Returns the version of the toolchain compiling audits on a release channel.
+fn compiling_rustc_version(channel: Channel) -> Option<String> {
+    auditor::check_rustc_available(channel)
+        .ok()
+        .map(str::to_string)
+}
+
+/// The maximum number of files of an audit submitted as several files.
+pub const MAX_FILES: usize = 32;
+
+/// Checks the files of an audit submitted as several files.
+///
+/// # Arguments
+///
+/// * `files` - The files as supplied by the client.
+///
+/// # Returns
+///
+/// * `Ok(&AuditFile)` - The root of the crate, whose code is audited as `generated_code`.
+/// * `Err(AppError::Validation)` - If there are too many files, a path is invalid (see
+///   `auditor::check_file_path`) or used twice, the manifest is refused (see
+///   `auditor::check_manifest`), or no file is a crate root.
+fn check_files(files: &[AuditFile]) -> Result<&AuditFile, AppError> {
+    if files.len() > MAX_FILES {
+        return Err(AppError::Validation(format!(
+            "At most {} files are allowed",
+            MAX_FILES
+        )));
+    }
+    let mut paths = HashSet::new();
+    for file in files {
+        auditor::check_file_path(&file.path).map_err(AppError::Validation)?;
+        if !paths.insert(file.path.as_str()) {
+            return Err(AppError::Validation(format!(
+                "{}: the path is used by several files",
+                file.path
+            )));
+        }
+    }
+    auditor::check_manifest(files).map_err(AppError::Validation)?;
+    auditor::crate_root(files).ok_or_else(|| {
+        AppError::Validation(format!(
+            "files must include a crate root: one of {}",
+            auditor::CRATE_ROOTS.join(", ")
+        ))
+    })
+}
+
+/// Validates every Rust file of a crate submitted as several files, telling in which file
+/// each finding was found.
+fn validate_files(files: &[AuditFile], policy: &AuditPolicy) -> Vec<Finding> {
+    files
+        .iter()
+        .filter(|file| file.path.ends_with(".rs"))
+        .flat_map(|file| {
+            auditor::validate_code(&file.content, policy)
+                .into_iter()
+                .map(|finding| Finding {
+                    file: Some(file.path.clone()),
+                    ..finding
+                })
+        })
+        .collect()
+}
+
+/// The findings and the verdict of audited code, as kept by the audit result cache.
+#[derive(Clone)]
+pub struct JudgedCode {
+    findings: Vec<Finding>,
+    verdict: Verdict,
+}
+
+/// Returns the edition code is compiled with: the one of the request, else the one
+/// guessed from the code.
+pub(crate) fn audit_edition(input: &CreateAuditRequest, code: &str) -> Edition {
+    input
+        .edition
+        .unwrap_or_else(|| auditor::detect_edition_from_code(code))
+}
+
+/// Compiles code to determine its validity, sending the compiler output to `output` if
+/// set. The dependencies of a Cargo package are then checked against the advisory
+/// database and the license policy.
+///
+/// # Returns
+///
+/// * `Ok(Verdict)` - The verdict of the compilation; a compile error if `rustc` could
+///   not be run.
+/// * `Err(AppError)` - If the compilation workers fail for another reason than `rustc`
+///   itself.
+pub(crate) async fn compile_verdict(
+    policy: &AuditPolicy,
+    compiler: &CompilationQueue,
+    code: &str,
+    options: CompileOptions,
+    output: Option<mpsc::Sender<CompilerOutput>>,
+) -> Result<Verdict, AppError> {
+    let channel = options.channel;
+    match compiler
+        .compile_cached(code.to_string(), options.clone(), output)
+        .await
+    {
+        Ok(mut outcome) => {
+            let compile_command = auditor::compile_command(&options);
+            let artifacts = CompiledArtifacts::take(&mut outcome, options);
+            let lockfile = outcome.lockfile.take();
+            let dependencies = outcome.dependencies.take();
+            let security_advisories = outcome.security_advisories.take();
+            Ok(Verdict {
+                compile_command: Some(compile_command),
+                artifacts: Some(artifacts),
+                security_advisories,
+                ..Verdict::compiled(outcome, channel, policy)
+            }
+            .with_dependencies_checked(
+                lockfile.as_deref(),
+                dependencies.as_deref(),
+                policy,
+                compiler,
+            ))
+        }
+        Err(AppError::Audit(e)) => Ok(Verdict::not_compiled(e)),
+        Err(e) => Err(e), // Propagate other error types
+    }
+}
+
+/// The results of the analyses of an audit, from which its metrics are derived.
+pub struct PartialAudit<'a> {
+    /// The audited code.
+    pub code: &'a str,
+    /// Whether the code compiled.
+    pub is_valid: bool,
+    /// The findings of the validation.
+    pub findings: &'a [Finding],
+    /// The diagnostics emitted by `rustc`, if the code was compiled.
+    pub diagnostics: &'a [Diagnostic],
+    /// The doc coverage of the code, if it parses.
+    pub doc_coverage_percent: Option<f64>,
+    /// The `unsafe` code of the code, if it parses.
+    pub unsafe_report: Option<&'a UnsafeReport>,
+    /// The leftovers of the code.
+    pub hygiene_report: &'a HygieneReport,
+    /// How long `rustc` took to compile the code, if it was compiled.
+    pub compilation_duration_ms: Option<i32>,
+    /// The weights of the quality score.
+    pub weights: &'a ScoreWeights,
+}
+
+/// Derives the metrics of an audit from the results of its analyses.
+///
+/// With the default weights, the quality score adds up to 100 points: 40 if the code
+/// compiles, 20 weighted by the doc coverage, 20 weighted by the hygiene score, and 20
+/// minus 5 per warning and 10 per error finding (down to 0). Each security finding then
+/// costs 25 more points, down to a score of 0. Audit profiles may change the weights.
+///
+/// # Arguments
+///
+/// * `audit` - The results of the analyses.
+///
+/// # Returns
+///
+/// * `AuditMetrics` - The metrics. Item counts are 0 and the complexity is `None` if the
+///   code does not parse.
+pub fn compute_audit_metrics(audit: &PartialAudit<'_>) -> AuditMetrics {
+    let count = |n: usize| i32::try_from(n).unwrap_or(i32::MAX);
+    let structure = auditor::analyze_structure(audit.code).unwrap_or_default();
+    let errors = audit
+        .findings
+        .iter()
+        .filter(|f| f.severity == Severity::Error)
+        .count();
+    let warnings = audit
+        .findings
+        .iter()
+        .filter(|f| f.severity == Severity::Warning)
+        .count()
+        + audit
+            .diagnostics
+            .iter()
+            .filter(|d| d.level == "warning")
+            .count();
+
+    let weights = audit.weights;
+    let compiles = if audit.is_valid {
+        weights.compilation
+    } else {
+        0.0
+    };
+    let documentation = weights.documentation * audit.doc_coverage_percent.unwrap_or(0.0) / 100.0;
+    let hygiene = weights.hygiene * audit.hygiene_report.hygiene_score / 100.0;
+    let cleanliness = (weights.cleanliness
+        - weights.warning_penalty * warnings as f64
+        - weights.error_penalty * errors as f64)
+        .max(0.0);
+    let vulnerabilities = audit
+        .findings
+        .iter()
+        .filter(|f| f.category == Some(FindingCategory::Security))
+        .count();
+    let security_penalty = weights.security_penalty * vulnerabilities as f64;
+
+    AuditMetrics {
+        quality_score: (compiles + documentation + hygiene + cleanliness - security_penalty)
+            .max(0.0),
+        complexity_score: structure.complexity,
+        doc_coverage_pct: audit.doc_coverage_percent,
+        unsafe_count: count(audit.unsafe_report.map_or(0, |r| r.total_count)),
+        todo_count: count(audit.hygiene_report.todo_marker_count),
+        warning_count: count(warnings),
+        function_count: count(structure.function_count),
+        struct_count: count(structure.struct_count),
+        compilation_duration_ms: audit.compilation_duration_ms,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODE: &str = "/// A point.\npub struct Point;\n\n/// Adds.\npub fn add(a: i32, b: i32) -> i32 { a + b }\n";
+
+    fn finding(severity: Severity, category: Option<FindingCategory>) -> Finding {
+        Finding {
+            code: "RAA0000".to_string(),
+            severity,
+            message: String::new(),
+            line: None,
+            category,
+            file: None,
+        }
+    }
+
+    fn diagnostic(level: &str) -> Diagnostic {
+        Diagnostic {
+            code: None,
+            level: level.to_string(),
+            message: String::new(),
+            line: None,
+            column: None,
+            file: None,
+        }
+    }
+
+    fn hygiene(score: f64, todo_markers: usize) -> HygieneReport {
+        HygieneReport {
+            todo_marker_count: todo_markers,
+            hygiene_score: score,
+            ..HygieneReport::default()
+        }
+    }
+
+    #[test]
+    fn clean_code_scores_100() {
+        let metrics = compute_audit_metrics(&PartialAudit {
+            code: CODE,
+            is_valid: true,
+            findings: &[],
+            diagnostics: &[],
+            doc_coverage_percent: Some(100.0),
+            unsafe_report: None,
+            hygiene_report: &hygiene(100.0, 0),
+            compilation_duration_ms: Some(42),
+            weights: &ScoreWeights::default(),
+        });
+
+        assert_eq!(metrics.quality_score, 100.0);
+        assert_eq!(metrics.function_count, 1);
+        assert_eq!(metrics.struct_count, 1);
+        assert!(metrics.complexity_score.is_some());
+        assert_eq!(metrics.doc_coverage_pct, Some(100.0));
+        assert_eq!(metrics.warning_count, 0);
+        assert_eq!(metrics.compilation_duration_ms, Some(42));
+    }
+
+    #[test]
+    fn findings_and_warnings_cost_cleanliness_points() {
+        let metrics = compute_audit_metrics(&PartialAudit {
+            code: CODE,
+            is_valid: false,
+            findings: &[
+                finding(Severity::Error, None),
+                finding(Severity::Warning, None),
+            ],
+            // Only the warnings of `rustc` count.
+            diagnostics: &[diagnostic("warning"), diagnostic("error")],
+            doc_coverage_percent: Some(50.0),
+            unsafe_report: None,
+            hygiene_report: &hygiene(50.0, 2),
+            compilation_duration_ms: None,
+            weights: &ScoreWeights::default(),
+        });
+
+        // 0 (compilation) + 10 (documentation) + 10 (hygiene) + 20 - 2 * 5 - 10.
+        assert_eq!(metrics.quality_score, 20.0);
+        assert_eq!(metrics.warning_count, 2);
+        assert_eq!(metrics.todo_count, 2);
+    }
+
+    #[test]
+    fn security_findings_lower_the_score_down_to_0() {
+        let score = |vulnerabilities: usize| {
+            let findings =
+                vec![finding(Severity::Warning, Some(FindingCategory::Security)); vulnerabilities];
+            compute_audit_metrics(&PartialAudit {
+                code: CODE,
+                is_valid: true,
+                findings: &findings,
+                diagnostics: &[],
+                doc_coverage_percent: Some(100.0),
+                unsafe_report: None,
+                hygiene_report: &hygiene(100.0, 0),
+                compilation_duration_ms: None,
+                weights: &ScoreWeights::default(),
+            })
+            .quality_score
+        };
+
+        // 100 - 5 (warning) - 25 (security).
+        assert_eq!(score(1), 70.0);
+        assert_eq!(score(4), 0.0);
+    }
+
+    #[test]
+    fn weights_change_the_score() {
+        let weights = ScoreWeights {
+            compilation: 100.0,
+            documentation: 0.0,
+            hygiene: 0.0,
+            cleanliness: 0.0,
+            warning_penalty: 0.0,
+            error_penalty: 0.0,
+            security_penalty: 0.0,
+        };
+        let score = |is_valid: bool| {
+            compute_audit_metrics(&PartialAudit {
+                code: CODE,
+                is_valid,
+                findings: &[finding(Severity::Error, None)],
+                diagnostics: &[],
+                doc_coverage_percent: None,
+                unsafe_report: None,
+                hygiene_report: &hygiene(0.0, 0),
+                compilation_duration_ms: None,
+                weights: &weights,
+            })
+            .quality_score
+        };
+
+        assert_eq!(score(true), 100.0);
+        assert_eq!(score(false), 0.0);
+    }
+
+    #[test]
+    fn unparseable_code_has_no_structure() {
+        let metrics = compute_audit_metrics(&PartialAudit {
+            code: "pub fn (",
+            is_valid: false,
+            findings: &[],
+            diagnostics: &[],
+            doc_coverage_percent: None,
+            unsafe_report: None,
+            hygiene_report: &hygiene(100.0, 0),
+            compilation_duration_ms: None,
+            weights: &ScoreWeights::default(),
+        });
+
+        assert_eq!(metrics.function_count, 0);
+        assert_eq!(metrics.struct_count, 0);
+        assert_eq!(metrics.complexity_score, None);
+        // 0 + 0 + 20 (hygiene) + 20 (cleanliness).
+        assert_eq!(metrics.quality_score, 40.0);
+    }
+}
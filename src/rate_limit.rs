@@ -0,0 +1,132 @@
+//! Limits how many audits each client may create per minute, so that a single client
+//! cannot keep every compilation worker busy.
+
+use crate::error::AppError;
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// The length of the windows requests are counted in.
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// The number of tracked clients above which the expired windows are forgotten.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// The address of the client of a GraphQL request, added to its data by the HTTP handler.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientAddr(pub IpAddr);
+
+/// Counts the audits created by each client address in fixed one-minute windows.
+pub struct RateLimiter {
+    requests_per_minute: Option<u32>,
+    windows: Mutex<HashMap<IpAddr, Window>>,
+}
+
+/// The requests of a client in the current window.
+struct Window {
+    started: Instant,
+    requests: u32,
+}
+
+impl RateLimiter {
+    /// Builds a limiter allowing `requests_per_minute` audits to each client.
+    ///
+    /// # Arguments
+    ///
+    /// * `requests_per_minute` - The number of audits each client may create per minute,
+    ///   or `None` for no limit (see `AppConfig::rate_limit_rpm`).
+    pub fn new(requests_per_minute: Option<u32>) -> Self {
+        RateLimiter {
+            requests_per_minute,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Counts a request of a client, unless it is over its limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The address of the client.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the request is allowed.
+    /// * `Err(AppError::RateLimited)` - If the client already made `requests_per_minute`
+    ///   requests in the current window.
+    pub fn check(&self, client: IpAddr) -> Result<(), AppError> {
+        let Some(limit) = self.requests_per_minute else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        // The windows hold no invariants that a panic could break, so recover from
+        // poisoning.
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        if windows.len() >= MAX_TRACKED_CLIENTS {
+            windows.retain(|_, window| now.duration_since(window.started) < WINDOW);
+        }
+        let window = windows.entry(client).or_insert(Window {
+            started: now,
+            requests: 0,
+        });
+        if now.duration_since(window.started) >= WINDOW {
+            *window = Window {
+                started: now,
+                requests: 0,
+            };
+        }
+        if window.requests >= limit {
+            let retry_in = WINDOW.saturating_sub(now.duration_since(window.started));
+            return Err(AppError::RateLimited(format!(
+                "Too many audits: at most {} per minute are allowed, retry in {} seconds",
+                limit,
+                retry_in.as_secs().max(1)
+            )));
+        }
+        window.requests += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_client_has_its_own_limit() {
+        let limiter = RateLimiter::new(Some(2));
+        let (first, second) = ("10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap());
+        assert!(limiter.check(first).is_ok());
+        assert!(limiter.check(first).is_ok());
+        assert!(matches!(
+            limiter.check(first),
+            Err(AppError::RateLimited(_))
+        ));
+        assert!(limiter.check(second).is_ok());
+    }
+
+    #[test]
+    fn no_limit_allows_every_request() {
+        let limiter = RateLimiter::new(None);
+        let client = "10.0.0.1".parse().unwrap();
+        assert!((0..1000).all(|_| limiter.check(client).is_ok()));
+    }
+
+    #[test]
+    fn a_poisoned_lock_keeps_counting_requests() {
+        let limiter = RateLimiter::new(Some(1));
+        let client = "10.0.0.1".parse().unwrap();
+        let _ = std::panic::catch_unwind(|| {
+            let _windows = limiter.windows.lock().unwrap();
+            panic!("poison the lock");
+        });
+        assert!(limiter.windows.is_poisoned());
+        assert!(limiter.check(client).is_ok());
+        assert!(matches!(
+            limiter.check(client),
+            Err(AppError::RateLimited(_))
+        ));
+    }
+}
@@ -0,0 +1,153 @@
+//! Converts audits into SARIF 2.1.0 documents for code scanning tools.
+
+use crate::models::{AiAudit, Diagnostic, Finding, Severity};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+
+/// The URI of the SARIF 2.1.0 JSON schema.
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// Builds a SARIF 2.1.0 log describing the findings and compiler diagnostics of an audit.
+///
+/// The log holds a single run of the `rust-ai-auditor` tool. Each distinct finding code
+/// and rustc error code becomes a rule. Diagnostics without a source location (e.g.
+/// "aborting due to 1 previous error") only summarize other diagnostics and are left out.
+/// The results of an audit of several files point to the file they were found in.
+///
+/// # Arguments
+///
+/// * `audit` - The audit to describe.
+/// * `diagnostics` - The compiler diagnostics of the audit.
+///
+/// # Returns
+///
+/// * `Value` - The SARIF log, with an empty `results` array if there is nothing to report.
+pub fn audit_to_sarif(audit: &AiAudit, diagnostics: &[Diagnostic]) -> Value {
+    let uri = audit
+        .source_path
+        .clone()
+        .unwrap_or_else(|| format!("audit-{}.rs", audit.id));
+
+    let mut rules = BTreeMap::new();
+    let mut results = Vec::new();
+
+    for finding in &audit.findings {
+        rules
+            .entry(finding.code.clone())
+            .or_insert_with(|| finding_rule(finding));
+        results.push(result(
+            Some(&finding.code),
+            severity_level(finding.severity),
+            &finding.message,
+            finding.file.as_deref().unwrap_or(&uri),
+            finding.line,
+            None,
+        ));
+    }
+
+    for diagnostic in diagnostics.iter().filter(|d| d.line.is_some()) {
+        if let Some(code) = &diagnostic.code {
+            rules
+                .entry(code.clone())
+                .or_insert_with(|| rustc_rule(code));
+        }
+        results.push(result(
+            diagnostic.code.as_deref(),
+            rustc_level(&diagnostic.level),
+            &diagnostic.message,
+            diagnostic.file.as_deref().unwrap_or(&uri),
+            diagnostic.line,
+            diagnostic.column,
+        ));
+    }
+
+    json!({
+        "$schema": SARIF_SCHEMA,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "rust-ai-auditor",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules.into_values().collect::<Vec<_>>(),
+                }
+            },
+            "results": results,
+        }]
+    })
+}
+
+/// Builds the rule describing a validation finding code.
+fn finding_rule(finding: &Finding) -> Value {
+    json!({
+        "id": finding.code,
+        "shortDescription": { "text": finding.message },
+    })
+}
+
+/// Builds the rule describing a rustc diagnostic code.
+fn rustc_rule(code: &str) -> Value {
+    let mut rule = json!({
+        "id": code,
+        "shortDescription": { "text": format!("rustc {}", code) },
+    });
+    // Only error codes (e.g. `E0308`) are documented in the error index; lints are not.
+    if code.starts_with('E') {
+        rule["helpUri"] = json!(format!(
+            "https://doc.rust-lang.org/error_codes/{}.html",
+            code
+        ));
+    }
+    rule
+}
+
+/// Builds a SARIF result.
+fn result(
+    rule_id: Option<&str>,
+    level: &str,
+    message: &str,
+    uri: &str,
+    line: Option<u32>,
+    column: Option<u32>,
+) -> Value {
+    let mut region = serde_json::Map::new();
+    if let Some(line) = line.filter(|&l| l > 0) {
+        region.insert("startLine".to_string(), json!(line));
+        if let Some(column) = column.filter(|&c| c > 0) {
+            region.insert("startColumn".to_string(), json!(column));
+        }
+    }
+
+    let mut physical_location = json!({ "artifactLocation": { "uri": uri } });
+    if !region.is_empty() {
+        physical_location["region"] = Value::Object(region);
+    }
+
+    let mut result = json!({
+        "level": level,
+        "message": { "text": message },
+        "locations": [{ "physicalLocation": physical_location }],
+    });
+    if let Some(rule_id) = rule_id {
+        result["ruleId"] = json!(rule_id);
+    }
+    result
+}
+
+/// Maps the severity of a finding to a SARIF level.
+fn severity_level(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Info => "note",
+    }
+}
+
+/// Maps the level of a rustc diagnostic to a SARIF level.
+fn rustc_level(level: &str) -> &'static str {
+    match level {
+        "error" | "error: internal compiler error" => "error",
+        "warning" => "warning",
+        _ => "note",
+    }
+}
@@ -1,12 +1,33 @@
-//! Defines the GraphQL schema, including queries and mutations.
+//! Defines the GraphQL schema, including queries, mutations and subscriptions.
 
 use crate::{
+    auditor::AuditPolicy,
+    auth::{AdminAuth, Caller, Claims},
+    dataloaders::{CommentLoader, DiagnosticsLoader, RatingLoader},
+    db::Db,
     error::AppError,
-    models::{AiAudit, AuditStats, CreateAuditRequest},
+    generation::CodeGenerators,
+    highlight::HighlightCache,
+    jobs::{CancellationTokens, ProgressChannels},
+    models::{
+        AiAudit, AuditArtifacts, AuditCacheStats, AuditComment, AuditComparison, AuditFile,
+        AuditFilter, AuditJob, AuditOutcome, AuditProfile, AuditProfileInput, AuditProgress,
+        AuditStats, CreateAuditRequest, Diagnostic, ErrorCodeFrequency, Finding, FindingCategory,
+        GenerateOptions, JobQueueStats, LicensePolicy, LicensePolicyInput, ReauditReport,
+        SimilarAudit, StatsBucket, StatsGroupBy,
+    },
+    notifications::AuditNotifiers,
+    rate_limit::{ClientAddr, RateLimiter},
     services,
+    workers::{CompilationQueue, ShutdownFlag},
 };
-use async_graphql::{Context, Object, Schema};
+use async_graphql::{
+    ComplexObject, Context, Json, Object, Schema, Subscription, dataloader::DataLoader,
+};
+use futures_util::Stream;
+use similar::TextDiff;
 use sqlx::PgPool;
+use std::sync::Arc;
 use uuid::Uuid;
 
 /// The root of all GraphQL queries.
@@ -15,28 +36,329 @@ pub struct QueryRoot;
 
 #[Object]
 impl QueryRoot {
-    /// Retrieves a list of all AI audits, sorted by creation date.
-    async fn audits(&self, ctx: &Context<'_>) -> Result<Vec<AiAudit>, AppError> {
+    /// Retrieves a list of AI audits, sorted by creation date, optionally filtered by
+    /// the number of lines of their code, by tags, by the toolchain that compiled them and
+    /// by the estimated number of tokens of their prompt and code, by whether their code
+    /// declares a license, by whether their dependencies violate the license policy, and by
+    /// whether `cargo audit` reported advisories for their dependencies.
+    // Each filter is a separate argument of the GraphQL field.
+    #[allow(clippy::too_many_arguments)]
+    async fn audits(
+        &self,
+        ctx: &Context<'_>,
+        min_lines: Option<i32>,
+        max_lines: Option<i32>,
+        tags_contains: Option<Vec<String>>,
+        rustc_version: Option<String>,
+        max_tokens: Option<i32>,
+        has_license: Option<bool>,
+        license_violations: Option<bool>,
+        has_security_issues: Option<bool>,
+    ) -> Result<Vec<AiAudit>, AppError> {
         let pool = ctx
-            .data::<PgPool>()
-            .map_err(|_| AppError::NotFound("Database pool not found in context".to_string()))?;
-        services::list_audits(pool).await
+            .data::<Db>()
+            .map_err(|_| AppError::NotFound("Read pool not found in context".to_string()))?
+            .read();
+        let filter = AuditFilter {
+            min_lines,
+            max_lines,
+            tags_contains,
+            rustc_version,
+            max_tokens,
+            has_license,
+            license_violations,
+            has_security_issues,
+        };
+        services::list_audits(pool, &filter).await
     }
 
     /// Retrieves a single AI audit by its unique identifier.
     async fn audit(&self, ctx: &Context<'_>, id: Uuid) -> Result<Option<AiAudit>, AppError> {
         let pool = ctx
-            .data::<PgPool>()
-            .map_err(|_| AppError::NotFound("Database pool not found in context".to_string()))?;
+            .data::<Db>()
+            .map_err(|_| AppError::NotFound("Read pool not found in context".to_string()))?
+            .read();
         services::get_audit_by_id(pool, id).await
     }
 
-    /// Retrieves aggregated statistics about all audits.
-    async fn stats(&self, ctx: &Context<'_>) -> Result<AuditStats, AppError> {
+    /// Retrieves the chain of fix attempts an audit belongs to, from the original audit
+    /// to the last attempt.
+    async fn audit_chain(&self, ctx: &Context<'_>, id: Uuid) -> Result<Vec<AiAudit>, AppError> {
+        let pool = ctx
+            .data::<Db>()
+            .map_err(|_| AppError::NotFound("Read pool not found in context".to_string()))?
+            .read();
+        services::get_audit_chain(pool, id).await
+    }
+
+    /// Retrieves the audits an audit derives from through fixes, from the original audit
+    /// to the given one.
+    async fn audit_lineage(&self, ctx: &Context<'_>, id: Uuid) -> Result<Vec<AiAudit>, AppError> {
+        let pool = ctx
+            .data::<Db>()
+            .map_err(|_| AppError::NotFound("Read pool not found in context".to_string()))?
+            .read();
+        services::get_audit_lineage(pool, id).await
+    }
+
+    /// Compares two audits, returning a unified diff of their code and the change of
+    /// their quality score.
+    async fn compare_audits(
+        &self,
+        ctx: &Context<'_>,
+        id_a: Uuid,
+        id_b: Uuid,
+    ) -> Result<AuditComparison, AppError> {
+        let pool = ctx
+            .data::<Db>()
+            .map_err(|_| AppError::NotFound("Read pool not found in context".to_string()))?
+            .read();
+        services::compare_audits(pool, id_a, id_b).await
+    }
+
+    /// Retrieves the audits whose code is at least `threshold` similar (from 0 to 1) to
+    /// the code of an audit, most similar first.
+    async fn similar(
+        &self,
+        ctx: &Context<'_>,
+        id: Uuid,
+        threshold: f64,
+    ) -> Result<Vec<SimilarAudit>, AppError> {
+        let pool = ctx
+            .data::<Db>()
+            .map_err(|_| AppError::NotFound("Read pool not found in context".to_string()))?
+            .read();
+        services::find_similar_audits(pool, id, threshold).await
+    }
+
+    /// Retrieves the findings and compiler diagnostics of an audit as a SARIF 2.1.0 log.
+    async fn audit_sarif(
+        &self,
+        ctx: &Context<'_>,
+        id: Uuid,
+    ) -> Result<Json<serde_json::Value>, AppError> {
+        let pool = ctx
+            .data::<Db>()
+            .map_err(|_| AppError::NotFound("Read pool not found in context".to_string()))?
+            .read();
+        services::get_audit_sarif(pool, id).await.map(Json)
+    }
+
+    /// Retrieves aggregated statistics about all audits, with the number of audits created
+    /// on each of the last `days` days.
+    async fn stats(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 30, validator(minimum = 1, maximum = 366))] days: i32,
+    ) -> Result<AuditStats, AppError> {
+        let pool = ctx
+            .data::<Db>()
+            .map_err(|_| AppError::NotFound("Read pool not found in context".to_string()))?
+            .read();
+        services::get_audit_stats(pool, days, None).await
+    }
+
+    /// Groups the audits by day, model, tag or error code, counting the audits and
+    /// valid audits of each group.
+    async fn grouped_stats(
+        &self,
+        ctx: &Context<'_>,
+        group_by: StatsGroupBy,
+        #[graphql(default = 100, validator(minimum = 1, maximum = 1000))] limit: i32,
+        #[graphql(default = 0, validator(minimum = 0))] offset: i32,
+    ) -> Result<Vec<StatsBucket>, AppError> {
+        let pool = ctx
+            .data::<Db>()
+            .map_err(|_| AppError::NotFound("Read pool not found in context".to_string()))?
+            .read();
+        services::get_grouped_stats(pool, group_by, limit.into(), offset.into(), None).await
+    }
+
+    /// Retrieves the rustc error codes that most often cause compilation to fail,
+    /// most frequent first.
+    async fn top_errors(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 10, validator(minimum = 1, maximum = 100))] limit: i32,
+    ) -> Result<Vec<ErrorCodeFrequency>, AppError> {
+        let pool = ctx
+            .data::<Db>()
+            .map_err(|_| AppError::NotFound("Read pool not found in context".to_string()))?
+            .read();
+        services::get_top_error_codes(pool, limit.into()).await
+    }
+
+    /// Returns the audit profiles selectable with the `profile` field of `createAudit`,
+    /// sorted by name.
+    async fn audit_profiles(&self, ctx: &Context<'_>) -> Result<Vec<AuditProfile>, AppError> {
+        let pool = ctx
+            .data::<Db>()
+            .map_err(|_| AppError::NotFound("Read pool not found in context".to_string()))?
+            .read();
+        services::list_audit_profiles(pool).await
+    }
+
+    /// Returns the licenses the dependencies of audited Cargo packages may and may not use.
+    async fn license_policy(&self, ctx: &Context<'_>) -> Result<LicensePolicy, AppError> {
+        let pool = ctx
+            .data::<Db>()
+            .map_err(|_| AppError::NotFound("Read pool not found in context".to_string()))?
+            .read();
+        services::get_license_policy(pool).await
+    }
+
+    /// Describes the queue of the audits compiled in the background: its depth, the age
+    /// of its oldest job, and the jobs that failed too many times.
+    ///
+    /// Requires the admin bearer token.
+    async fn job_queue(&self, ctx: &Context<'_>) -> Result<JobQueueStats, AppError> {
+        ctx.data_opt::<AdminAuth>().ok_or_else(|| {
+            AppError::Unauthorized("Administrator bearer token required".to_string())
+        })?;
         let pool = ctx
             .data::<PgPool>()
             .map_err(|_| AppError::NotFound("Database pool not found in context".to_string()))?;
-        services::get_audit_stats(pool).await
+        services::get_job_queue_stats(pool).await
+    }
+
+    /// Describes the in-memory cache of audit results of this replica: its size and its
+    /// hit, miss and bypass counters.
+    ///
+    /// Requires the admin bearer token.
+    async fn cache_stats(&self, ctx: &Context<'_>) -> Result<AuditCacheStats, AppError> {
+        ctx.data_opt::<AdminAuth>().ok_or_else(|| {
+            AppError::Unauthorized("Administrator bearer token required".to_string())
+        })?;
+        let compiler = ctx.data_unchecked::<CompilationQueue>();
+        Ok(compiler.audit_results().stats())
+    }
+
+    /// Audits code like `createAudit` without storing anything: returns the verdict, the
+    /// findings, the compiler diagnostics and the metrics of the code.
+    async fn analyze_code(
+        &self,
+        ctx: &Context<'_>,
+        input: CreateAuditRequest,
+    ) -> Result<AuditOutcome, AppError> {
+        ctx.data_unchecked::<ShutdownFlag>().check()?;
+        let pool = ctx
+            .data::<Db>()
+            .map_err(|_| AppError::NotFound("Read pool not found in context".to_string()))?
+            .read();
+        let policy = ctx.data_unchecked::<AuditPolicy>();
+        let compiler = ctx.data_unchecked::<CompilationQueue>();
+        services::analyze_code(pool, policy, compiler, input).await
+    }
+}
+
+/// Resolvers for the fields of `AiAudit` that are not stored on the audit row.
+#[ComplexObject]
+impl AiAudit {
+    /// The reviewer comments attached to this audit, oldest first.
+    ///
+    /// Comments are batch-loaded across all audits of a query.
+    async fn comments(&self, ctx: &Context<'_>) -> Result<Vec<AuditComment>, AppError> {
+        let loader = ctx
+            .data::<DataLoader<CommentLoader>>()
+            .map_err(|_| AppError::NotFound("Comment loader not found in context".to_string()))?;
+        Ok(loader.load_one(self.id).await?.unwrap_or_default())
+    }
+
+    /// The average score of the ratings of this audit, from 1 to 5, or null if it was
+    /// not rated.
+    ///
+    /// Ratings are batch-loaded across all audits of a query.
+    async fn average_rating(&self, ctx: &Context<'_>) -> Result<Option<f64>, AppError> {
+        let loader = ctx
+            .data::<DataLoader<RatingLoader>>()
+            .map_err(|_| AppError::NotFound("Rating loader not found in context".to_string()))?;
+        Ok(loader.load_one(self.id).await?.map(|(average, _)| average))
+    }
+
+    /// The number of reviewers who rated this audit.
+    async fn rating_count(&self, ctx: &Context<'_>) -> Result<i64, AppError> {
+        let loader = ctx
+            .data::<DataLoader<RatingLoader>>()
+            .map_err(|_| AppError::NotFound("Rating loader not found in context".to_string()))?;
+        Ok(loader
+            .load_one(self.id)
+            .await?
+            .map_or(0, |(_, count)| count))
+    }
+
+    /// The files kept from the compilation of this audit, with the URLs they can be
+    /// downloaded from. None are listed until they are uploaded.
+    async fn artifacts(&self, ctx: &Context<'_>) -> Result<AuditArtifacts, AppError> {
+        let pool = ctx
+            .data::<Db>()
+            .map_err(|_| AppError::NotFound("Read pool not found in context".to_string()))?
+            .read();
+        let compiler = ctx.data_unchecked::<CompilationQueue>();
+        services::get_audit_artifacts(pool, compiler.artifacts(), self.id).await
+    }
+
+    /// The files of the crate, for an audit submitted as several files, ordered by path.
+    /// Empty for an audit of a single file.
+    async fn files(&self, ctx: &Context<'_>) -> Result<Vec<AuditFile>, AppError> {
+        let pool = ctx
+            .data::<Db>()
+            .map_err(|_| AppError::NotFound("Read pool not found in context".to_string()))?
+            .read();
+        services::get_audit_files(pool, self.id).await
+    }
+
+    /// The unified diff from the code to its `rustfmt` formatting, empty if the code is
+    /// already formatted, or null if it could not be formatted.
+    async fn formatting_diff(&self) -> Option<String> {
+        let formatted = self.formatted_code.as_deref()?;
+        Some(
+            TextDiff::from_lines(&self.generated_code, formatted)
+                .unified_diff()
+                .header("original", "formatted")
+                .to_string(),
+        )
+    }
+
+    /// The code as syntax-highlighted HTML, with markers on the lines that have findings
+    /// or diagnostics. Only rendered when `render` is true, so that listing audits does
+    /// not highlight every one of them.
+    async fn highlighted_html(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = false)] render: bool,
+    ) -> Result<Option<String>, AppError> {
+        if !render {
+            return Ok(None);
+        }
+        let cache = ctx.data_unchecked::<Arc<HighlightCache>>();
+        if let Some(fragment) = cache.get(self) {
+            return Ok(Some(fragment.to_string()));
+        }
+        let loader = ctx.data::<DataLoader<DiagnosticsLoader>>().map_err(|_| {
+            AppError::NotFound("Diagnostics loader not found in context".to_string())
+        })?;
+        let diagnostics = loader.load_one(self.id).await?.unwrap_or_default();
+        Ok(Some(cache.render(self, diagnostics).await?.to_string()))
+    }
+
+    /// The findings reporting vulnerabilities, such as SQL or command injection.
+    async fn security_findings(&self) -> Vec<Finding> {
+        self.findings
+            .iter()
+            .filter(|f| f.category == Some(FindingCategory::Security))
+            .cloned()
+            .collect()
+    }
+
+    /// The structured diagnostics emitted by `rustc` when compiling the code.
+    ///
+    /// Empty if the code was not compiled, and for audits created before diagnostics
+    /// were stored.
+    async fn diagnostics(&self, ctx: &Context<'_>) -> Result<Vec<Diagnostic>, AppError> {
+        let loader = ctx.data::<DataLoader<DiagnosticsLoader>>().map_err(|_| {
+            AppError::NotFound("Diagnostics loader not found in context".to_string())
+        })?;
+        Ok(loader.load_one(self.id).await?.unwrap_or_default())
     }
 }
 
@@ -48,19 +370,285 @@ pub struct MutationRoot;
 impl MutationRoot {
     /// Creates a new AI audit.
     ///
-    /// It takes a prompt and the AI-generated code as input, performs a compilation check,
-    /// and stores the result in the database.
+    /// It takes a prompt and the AI-generated code as input, performs validation and a
+    /// compilation check, and stores the result in the database. If `autoFix` is set,
+    /// the last audit of the fix chain is returned; see `auditChain` for the others. If
+    /// `background` is set, the audit is returned pending and compiled by a job. Requests
+    /// over HTTP count against the rate limit of their client (`RATE_LIMIT_RPM`).
     async fn create_audit(
         &self,
         ctx: &Context<'_>,
         input: CreateAuditRequest,
     ) -> Result<AiAudit, AppError> {
+        ctx.data_unchecked::<ShutdownFlag>().check()?;
+        if let Ok(ClientAddr(client)) = ctx.data::<ClientAddr>() {
+            ctx.data_unchecked::<Arc<RateLimiter>>().check(*client)?;
+        }
+        let pool = ctx
+            .data::<PgPool>()
+            .map_err(|_| AppError::NotFound("Database pool not found in context".to_string()))?;
+        let policy = ctx.data_unchecked::<AuditPolicy>();
+        let compiler = ctx.data_unchecked::<CompilationQueue>();
+        let generators = ctx.data_unchecked::<CodeGenerators>();
+        let notifiers = ctx.data_unchecked::<AuditNotifiers>();
+        if input.auto_fix.is_some() {
+            let chain =
+                services::create_audit_chain(pool, policy, compiler, generators, &input, notifiers)
+                    .await?;
+            return chain
+                .attempts
+                .into_iter()
+                .last()
+                .ok_or_else(|| AppError::NotFound("The audit chain is empty".to_string()));
+        }
+        services::create_audit(pool, policy, compiler, generators, &input, None, notifiers).await
+    }
+
+    /// Asks a model for a corrected version of an audit that does not compile, and
+    /// returns the audit of the corrected code, which may still not compile.
+    ///
+    /// `generate` defaults to the model that generated the code, for audits generated
+    /// server-side. Requests over HTTP count against the rate limit of their client
+    /// (`RATE_LIMIT_RPM`).
+    ///
+    /// Requires the admin bearer token or a user access token.
+    async fn fix_audit(
+        &self,
+        ctx: &Context<'_>,
+        id: Uuid,
+        generate: Option<GenerateOptions>,
+    ) -> Result<AiAudit, AppError> {
+        caller(ctx)?;
+        ctx.data_unchecked::<ShutdownFlag>().check()?;
+        if let Ok(ClientAddr(client)) = ctx.data::<ClientAddr>() {
+            ctx.data_unchecked::<Arc<RateLimiter>>().check(*client)?;
+        }
+        let pool = ctx
+            .data::<PgPool>()
+            .map_err(|_| AppError::NotFound("Database pool not found in context".to_string()))?;
+        services::attempt_fix(
+            pool,
+            ctx.data_unchecked::<AuditPolicy>(),
+            ctx.data_unchecked::<CompilationQueue>(),
+            ctx.data_unchecked::<CodeGenerators>(),
+            id,
+            generate,
+            ctx.data_unchecked::<AuditNotifiers>(),
+        )
+        .await
+    }
+
+    /// Cancels the background compilation of an audit, which gets the `CANCELLED`
+    /// status. Returns false if the audit has no background compilation left to cancel.
+    ///
+    /// Requires the admin bearer token or a user access token.
+    async fn cancel_audit(&self, ctx: &Context<'_>, id: Uuid) -> Result<bool, AppError> {
+        caller(ctx)?;
+        let pool = ctx
+            .data::<PgPool>()
+            .map_err(|_| AppError::NotFound("Database pool not found in context".to_string()))?;
+        services::cancel_audit(pool, ctx.data_unchecked::<CancellationTokens>(), id).await
+    }
+
+    /// Creates an audit profile.
+    ///
+    /// Requires the admin bearer token.
+    async fn create_audit_profile(
+        &self,
+        ctx: &Context<'_>,
+        input: AuditProfileInput,
+    ) -> Result<AuditProfile, AppError> {
+        ctx.data_opt::<AdminAuth>().ok_or_else(|| {
+            AppError::Unauthorized("Administrator bearer token required".to_string())
+        })?;
+        let pool = ctx
+            .data::<PgPool>()
+            .map_err(|_| AppError::NotFound("Database pool not found in context".to_string()))?;
+        services::create_audit_profile(pool, &input).await
+    }
+
+    /// Replaces the name, rules and weights of an audit profile. Audits created with it
+    /// keep their results.
+    ///
+    /// Requires the admin bearer token.
+    async fn update_audit_profile(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+        input: AuditProfileInput,
+    ) -> Result<AuditProfile, AppError> {
+        ctx.data_opt::<AdminAuth>().ok_or_else(|| {
+            AppError::Unauthorized("Administrator bearer token required".to_string())
+        })?;
+        let pool = ctx
+            .data::<PgPool>()
+            .map_err(|_| AppError::NotFound("Database pool not found in context".to_string()))?;
+        let policy = ctx.data_unchecked::<AuditPolicy>();
+        services::update_audit_profile(pool, policy, &name, &input).await
+    }
+
+    /// Replaces the licenses the dependencies of audited Cargo packages may and may not
+    /// use. Audits already created keep their findings.
+    ///
+    /// Requires the admin bearer token.
+    async fn license_policy(
+        &self,
+        ctx: &Context<'_>,
+        input: LicensePolicyInput,
+    ) -> Result<LicensePolicy, AppError> {
+        ctx.data_opt::<AdminAuth>().ok_or_else(|| {
+            AppError::Unauthorized("Administrator bearer token required".to_string())
+        })?;
+        let pool = ctx
+            .data::<PgPool>()
+            .map_err(|_| AppError::NotFound("Database pool not found in context".to_string()))?;
+        services::set_license_policy(pool, &input).await
+    }
+
+    /// Deletes an audit profile, other than the default one. Audits created with it keep
+    /// their results.
+    ///
+    /// Requires the admin bearer token.
+    async fn delete_audit_profile(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+    ) -> Result<bool, AppError> {
+        ctx.data_opt::<AdminAuth>().ok_or_else(|| {
+            AppError::Unauthorized("Administrator bearer token required".to_string())
+        })?;
+        let pool = ctx
+            .data::<PgPool>()
+            .map_err(|_| AppError::NotFound("Database pool not found in context".to_string()))?;
+        let policy = ctx.data_unchecked::<AuditPolicy>();
+        services::delete_audit_profile(pool, policy, &name).await?;
+        Ok(true)
+    }
+
+    /// Replaces the tags of an existing audit, normalized like those of a new audit.
+    ///
+    /// Requires the admin bearer token or a user access token.
+    async fn set_tags(
+        &self,
+        ctx: &Context<'_>,
+        id: Uuid,
+        tags: Vec<String>,
+    ) -> Result<AiAudit, AppError> {
+        caller(ctx)?;
+        let pool = ctx
+            .data::<PgPool>()
+            .map_err(|_| AppError::NotFound("Database pool not found in context".to_string()))?;
+        services::update_tags(pool, id, &tags).await
+    }
+
+    /// Adds a reviewer comment to an existing audit, in the name of the user.
+    ///
+    /// Requires a user access token.
+    async fn add_comment(
+        &self,
+        ctx: &Context<'_>,
+        audit_id: Uuid,
+        body: String,
+    ) -> Result<AuditComment, AppError> {
+        let claims = ctx
+            .data_opt::<Claims>()
+            .ok_or_else(|| AppError::Unauthorized("Access token required".to_string()))?;
+        let pool = ctx
+            .data::<PgPool>()
+            .map_err(|_| AppError::NotFound("Database pool not found in context".to_string()))?;
+        services::add_comment(pool, audit_id, &claims.username, &body).await
+    }
+
+    /// Recomputes the running totals behind the statistics from the audits, and returns
+    /// the statistics.
+    ///
+    /// Requires the admin bearer token.
+    async fn recalculate_stats(&self, ctx: &Context<'_>) -> Result<AuditStats, AppError> {
+        ctx.data_opt::<AdminAuth>().ok_or_else(|| {
+            AppError::Unauthorized("Administrator bearer token required".to_string())
+        })?;
+        let pool = ctx
+            .data::<PgPool>()
+            .map_err(|_| AppError::NotFound("Database pool not found in context".to_string()))?;
+        services::recalculate_audit_stats(pool).await
+    }
+
+    /// Recompiles every audit that failed to compile against the current toolchain.
+    ///
+    /// Requires the admin bearer token.
+    async fn reaudit_invalid(&self, ctx: &Context<'_>) -> Result<ReauditReport, AppError> {
+        ctx.data_opt::<AdminAuth>().ok_or_else(|| {
+            AppError::Unauthorized("Administrator bearer token required".to_string())
+        })?;
+        let pool = ctx
+            .data::<PgPool>()
+            .map_err(|_| AppError::NotFound("Database pool not found in context".to_string()))?;
+        let policy = ctx.data_unchecked::<AuditPolicy>();
+        let compiler = ctx.data_unchecked::<CompilationQueue>();
+        services::reaudit_invalid_audits(pool, policy, compiler).await
+    }
+
+    /// Sends a job that failed too many times back to the queue, with its attempts
+    /// reset.
+    ///
+    /// Requires the admin bearer token.
+    async fn retry_job(&self, ctx: &Context<'_>, id: Uuid) -> Result<AuditJob, AppError> {
+        ctx.data_opt::<AdminAuth>().ok_or_else(|| {
+            AppError::Unauthorized("Administrator bearer token required".to_string())
+        })?;
+        let pool = ctx
+            .data::<PgPool>()
+            .map_err(|_| AppError::NotFound("Database pool not found in context".to_string()))?;
+        services::retry_audit_job(pool, id).await
+    }
+}
+
+/// Returns the authenticated caller of a GraphQL request.
+///
+/// # Returns
+///
+/// * `Ok(Caller)` - If the request carries the admin bearer token or a valid user access
+///   token.
+/// * `Err(AppError::Unauthorized)` - If it carries neither.
+fn caller(ctx: &Context<'_>) -> Result<Caller, AppError> {
+    if ctx.data_opt::<AdminAuth>().is_some() {
+        return Ok(Caller::Admin);
+    }
+    ctx.data_opt::<Claims>()
+        .cloned()
+        .map(Caller::User)
+        .ok_or_else(|| {
+            AppError::Unauthorized(
+                "Administrator bearer token or access token required".to_string(),
+            )
+        })
+}
+
+/// The root of all GraphQL subscriptions, served over WebSocket on `/graphql/ws`.
+#[derive(Default)]
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    /// Follows the progress of an audit: `VALIDATED` while it waits for a background job,
+    /// `DEPENDENCIES_RESOLVED` once cargo resolved the dependencies of a Cargo package,
+    /// `COMPILING` with batches of compiler output while this replica compiles it, and a
+    /// final `COMPLETED` with the audit, right away if it already has its verdict.
+    async fn audit_progress(
+        &self,
+        ctx: &Context<'_>,
+        id: Uuid,
+    ) -> Result<impl Stream<Item = Result<AuditProgress, AppError>> + use<>, AppError> {
         let pool = ctx
             .data::<PgPool>()
             .map_err(|_| AppError::NotFound("Database pool not found in context".to_string()))?;
-        services::create_audit(pool, &input).await
+        Ok(services::audit_progress(
+            pool,
+            ctx.data_unchecked::<ProgressChannels>(),
+            id,
+        ))
     }
 }
 
 /// The application's complete GraphQL schema.
-pub type AppSchema = Schema<QueryRoot, MutationRoot, async_graphql::EmptySubscription>;
+pub type AppSchema = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
@@ -1,33 +1,350 @@
 //! Contains the core business logic for database operations.
 
 use crate::{
-    auditor,
+    artifacts::ArtifactSettings,
+    auditor::{self, AuditPolicy, CompileOptions, CompilerOutput},
     error::AppError,
-    models::{AiAudit, AuditStats, CommonError, CreateAuditRequest},
+    generation::{self, CodeGenerators, GeneratedCode},
+    jobs::{CancellationTokens, ProgressChannels},
+    licenses,
+    models::{
+        AiAudit, AuditArtifacts, AuditChain, AuditComment, AuditComparison, AuditFile, AuditFilter,
+        AuditJob, AuditJobStatus, AuditOutcome, AuditProfile, AuditProfileInput, AuditProgress,
+        AuditProgressStage, AuditRating, AuditSource, AuditStats, AuditStatus, CategoryFrequency,
+        Channel, CommonError, CreateAuditRequest, CreateWebhookRequest, DailyCount, Diagnostic,
+        Edition, EditionStats, ErrorCodeFrequency, Finding, GenerateOptions, HygieneStats,
+        ImportAuditRecord, ImportReport, ImportRowError, JobQueueStats, JobState, LicensePolicy,
+        LicensePolicyInput, LicenseStat, OptLevel, ReauditReport, RerunReport, Role, ScoreWeights,
+        SimilarAudit, StatsBucket, StatsGroupBy, TargetStats, User, ValidityCounts, Webhook,
+    },
+    notifications::AuditNotifiers,
+    pipeline::{self, Auditor, PartialAudit, Verdict, compile_verdict, compute_audit_metrics},
+    sarif, webhooks,
+    workers::CompilationQueue,
+};
+use chrono::{DateTime, Utc};
+use futures_util::{Stream, StreamExt, stream};
+use sha2::{Digest, Sha256};
+use similar::TextDiff;
+use sqlx::{Acquire, PgConnection, PgExecutor, PgPool, types::Json};
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{LazyLock, OnceLock},
+    time::Duration,
+};
+use tokio::{
+    sync::{broadcast, mpsc},
+    task::JoinSet,
 };
-use sqlx::PgPool;
 use uuid::Uuid;
 
-/// Retrieves a list of all AI audits from the database, sorted by creation date.
+/// The columns selected when loading an `AiAudit` from the `ai_audits` table.
+const AUDIT_COLUMNS: &str = "id, prompt, generated_code, is_valid, status, tags, code_line_count, \
+     code_char_count, compilation_error, \
+     primary_error_code, primary_error_category, channel, opt_level, edition, edition_detected, target, rustc_version, compilation_duration_ms, compile_command, vulnerability_count, advisory_note, license_violation_count, security_advisories, has_security_issues, prompt_token_count, code_token_count, detected_license, doc_coverage_percent, findings, unsafe_report, hygiene_report, metrics, profile, formatted_code, needs_formatting, rejection_reason, source_repository, source_pull_request, source_path, generation_provider, generation_model, \
+     raw_model_output, prompt_tokens, completion_tokens, generation_latency_ms, parent_audit_id, \
+     attempt_number, created_at, \
+     updated_at";
+
+/// How queries failing with a transient database error are retried.
+#[derive(Debug, Clone, Copy)]
+pub struct DbRetryPolicy {
+    /// The number of times a query is retried after its first attempt; `0` disables
+    /// retries.
+    pub max_retries: u32,
+    /// The delay before the first retry, doubled before each of the next ones.
+    pub base_delay: Duration,
+}
+
+impl Default for DbRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(100),
+        }
+    }
+}
+
+impl DbRetryPolicy {
+    /// Returns the delay before a retry: the base delay doubled for each earlier retry,
+    /// scaled by a random factor between 0.5 and 1.5 so that requests failing together
+    /// do not retry together.
+    ///
+    /// # Arguments
+    ///
+    /// * `retry` - The number of the retry, starting at 1.
+    fn delay(&self, retry: u32) -> Duration {
+        let backoff = self
+            .base_delay
+            .saturating_mul(1 << retry.saturating_sub(1).min(16));
+        let jitter = (Uuid::new_v4().as_u128() % 1000) as f64 / 1000.0;
+        backoff.mul_f64(0.5 + jitter)
+    }
+}
+
+/// The retry policy of the process, set once at startup.
+static DB_RETRY_POLICY: OnceLock<DbRetryPolicy> = OnceLock::new();
+
+/// Sets how queries failing with a transient database error are retried.
+///
+/// Only the first call has an effect; without one, the default policy is used.
+///
+/// # Arguments
+///
+/// * `policy` - The retry policy.
+pub fn set_db_retry_policy(policy: DbRetryPolicy) {
+    let _ = DB_RETRY_POLICY.set(policy);
+}
+
+/// Returns the retry policy of the process.
+fn db_retry_policy() -> DbRetryPolicy {
+    DB_RETRY_POLICY.get().copied().unwrap_or_default()
+}
+
+/// Tells whether a database error is worth retrying: the connection was lost or could
+/// not be made (e.g. during a failover), not a problem with the query itself.
+///
+/// # Arguments
+///
+/// * `error` - The error of a query.
+///
+/// # Returns
+///
+/// `true` for I/O errors, pool timeouts and the `08` (connection exception) and `57P0x`
+/// (server shutdown) SQLSTATE codes; `false` for constraint violations, syntax errors
+/// and any other error.
+fn is_transient(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut => true,
+        sqlx::Error::Database(e) => e
+            .code()
+            .is_some_and(|code| code.starts_with("08") || code.starts_with("57P0")),
+        _ => false,
+    }
+}
+
+/// Logs a transient database error and waits before the next attempt.
+///
+/// # Arguments
+///
+/// * `operation` - The name of the operation being retried.
+/// * `retry` - The number of the upcoming retry, starting at 1.
+/// * `error` - The error of the failed attempt.
+async fn wait_before_retry(operation: &str, retry: u32, error: &sqlx::Error) {
+    let delay = db_retry_policy().delay(retry);
+    tracing::warn!(
+        operation,
+        attempt = retry + 1,
+        delay_ms = delay.as_millis() as u64,
+        error = %error,
+        "Retrying after a transient database error"
+    );
+    tokio::time::sleep(delay).await;
+}
+
+/// Runs a read-only query, retrying it on transient errors.
+///
+/// Only queries that can safely run more than once may be retried this way.
+///
+/// # Arguments
+///
+/// * `operation` - The name of the operation, for the logs.
+/// * `query` - Runs one attempt of the query.
+///
+/// # Returns
+///
+/// The result of the first attempt that did not fail with a transient error, or of the
+/// last attempt allowed by the retry policy.
+async fn retry_transient<T, F, Fut>(operation: &str, mut query: F) -> Result<T, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    let max_retries = db_retry_policy().max_retries;
+    let mut retry = 0;
+    loop {
+        match query().await {
+            Err(e) if retry < max_retries && is_transient(&e) => {
+                retry += 1;
+                wait_before_retry(operation, retry, &e).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// The number of audits read ahead of a client streaming the audits.
+const AUDIT_STREAM_BUFFER: usize = 64;
+
+/// Retrieves a list of AI audits from the database, sorted by creation date.
 ///
 /// # Arguments
 ///
 /// * `pool` - A reference to the database connection pool.
+/// * `filter` - The conditions the listed audits must meet.
 ///
 /// # Returns
 ///
 /// * `Ok(Vec<AiAudit>)` - A vector of audit records.
+/// * `Err(AppError::Validation)` - If a line bound is negative, the bounds are inverted,
+///   or the tags are invalid.
 /// * `Err(AppError::Sqlx)` - If a database query fails.
 #[tracing::instrument(skip(pool))]
-pub async fn list_audits(pool: &PgPool) -> Result<Vec<AiAudit>, AppError> {
-    sqlx::query_as::<_, AiAudit>(
-        "SELECT id, prompt, generated_code, is_valid, compilation_error, created_at FROM ai_audits ORDER BY created_at DESC"
-    )
-    .fetch_all(pool)
+pub async fn list_audits(pool: &PgPool, filter: &AuditFilter) -> Result<Vec<AiAudit>, AppError> {
+    let tags = check_audit_filter(filter)?;
+    let sql = list_audits_sql();
+    retry_transient("list_audits", || {
+        sqlx::query_as::<_, AiAudit>(&sql)
+            .bind(filter.min_lines)
+            .bind(filter.max_lines)
+            .bind(&tags)
+            .bind(&filter.rustc_version)
+            .bind(filter.max_tokens)
+            .bind(filter.has_license)
+            .bind(filter.license_violations)
+            .bind(filter.has_security_issues)
+            .fetch_all(pool)
+    })
     .await
     .map_err(AppError::from)
 }
 
+/// Streams the AI audits matching a filter, sorted by creation date, as they are read.
+///
+/// Unlike `list_audits`, the audits are never all held in memory: a task reads them on
+/// a dedicated connection and hands them over through a channel of
+/// `AUDIT_STREAM_BUFFER` audits, so reading waits for the consumer. Once the stream is
+/// dropped, the task closes its connection instead of returning it to the pool, which
+/// makes the server abort the query rather than send the remaining rows.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `filter` - The conditions the streamed audits must meet.
+///
+/// # Returns
+///
+/// * `Ok(impl Stream)` - The audits, or the error that ended the stream.
+/// * `Err(AppError::Validation)` - If a line bound is negative, the bounds are inverted,
+///   or the tags are invalid.
+#[tracing::instrument(skip(pool))]
+pub fn stream_audits(
+    pool: &PgPool,
+    filter: &AuditFilter,
+) -> Result<impl Stream<Item = Result<AiAudit, sqlx::Error>> + use<>, AppError> {
+    let tags = check_audit_filter(filter)?;
+    let (min_lines, max_lines) = (filter.min_lines, filter.max_lines);
+    let rustc_version = filter.rustc_version.clone();
+    let max_tokens = filter.max_tokens;
+    let has_license = filter.has_license;
+    let license_violations = filter.license_violations;
+    let has_security_issues = filter.has_security_issues;
+    let pool = pool.clone();
+    let (sender, receiver) = tokio::sync::mpsc::channel(AUDIT_STREAM_BUFFER);
+    tokio::spawn(async move {
+        let mut conn = match pool.acquire().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                let _ = sender.send(Err(e)).await;
+                return;
+            }
+        };
+        let sql = list_audits_sql();
+        let mut rows = sqlx::query_as::<_, AiAudit>(&sql)
+            .bind(min_lines)
+            .bind(max_lines)
+            .bind(&tags)
+            .bind(&rustc_version)
+            .bind(max_tokens)
+            .bind(has_license)
+            .bind(license_violations)
+            .bind(has_security_issues)
+            .fetch(&mut *conn);
+        let mut streamed = 0u64;
+        let cancelled = loop {
+            let row = tokio::select! {
+                row = rows.next() => row,
+                () = sender.closed() => break true,
+            };
+            let Some(row) = row else {
+                break false;
+            };
+            let failed = row.is_err();
+            if sender.send(row).await.is_err() {
+                break true;
+            }
+            if failed {
+                break false;
+            }
+            streamed += 1;
+        };
+        drop(rows);
+        if cancelled {
+            tracing::debug!(streamed, "Audit stream dropped, cancelling its query.");
+            conn.close_on_drop();
+        } else {
+            tracing::debug!(streamed, "Audit stream completed.");
+        }
+    });
+    Ok(stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|row| (row, receiver))
+    }))
+}
+
+/// Checks the bounds of a filter on audits.
+///
+/// # Returns
+///
+/// * `Ok(Option<Vec<String>>)` - The normalized tags of the filter, if any.
+/// * `Err(AppError::Validation)` - If a line bound is negative, the bounds are inverted,
+///   or the tags are invalid.
+fn check_audit_filter(filter: &AuditFilter) -> Result<Option<Vec<String>>, AppError> {
+    if filter.min_lines.is_some_and(|n| n < 0) || filter.max_lines.is_some_and(|n| n < 0) {
+        return Err(AppError::Validation(
+            "Line bounds must not be negative".to_string(),
+        ));
+    }
+    if filter.max_tokens.is_some_and(|n| n < 0) {
+        return Err(AppError::Validation(
+            "max_tokens must not be negative".to_string(),
+        ));
+    }
+    if let (Some(min), Some(max)) = (filter.min_lines, filter.max_lines)
+        && min > max
+    {
+        return Err(AppError::Validation(
+            "min_lines must not be greater than max_lines".to_string(),
+        ));
+    }
+
+    filter
+        .tags_contains
+        .as_deref()
+        .map(normalize_tags)
+        .transpose()
+}
+
+/// Builds the query listing the audits matching a filter, bound to the minimum and
+/// maximum number of lines, the tags, the toolchain, the maximum number of tokens and
+/// whether the code has a license header.
+fn list_audits_sql() -> String {
+    format!(
+        r#"
+        SELECT {} FROM ai_audits
+        WHERE ($1::int IS NULL OR code_line_count >= $1)
+          AND ($2::int IS NULL OR code_line_count <= $2)
+          AND ($3::text[] IS NULL OR tags @> $3)
+          AND ($4::text IS NULL OR rustc_version = $4)
+          AND ($5::int IS NULL OR prompt_token_count + code_token_count <= $5)
+          AND ($6::bool IS NULL OR (detected_license IS NOT NULL) = $6)
+          AND ($7::bool IS NULL OR (COALESCE(license_violation_count, 0) > 0) = $7)
+          AND ($8::bool IS NULL OR has_security_issues = $8)
+        ORDER BY created_at DESC
+        "#,
+        AUDIT_COLUMNS
+    )
+}
+
 /// Retrieves a single AI audit by its ID.
 ///
 /// # Arguments
@@ -41,106 +358,3904 @@ pub async fn list_audits(pool: &PgPool) -> Result<Vec<AiAudit>, AppError> {
 /// * `Err(AppError::Sqlx)` - If a database query fails.
 #[tracing::instrument(skip(pool))]
 pub async fn get_audit_by_id(pool: &PgPool, id: Uuid) -> Result<Option<AiAudit>, AppError> {
-    sqlx::query_as::<_, AiAudit>(
-        "SELECT id, prompt, generated_code, is_valid, compilation_error, created_at FROM ai_audits WHERE id = $1"
-    )
-    .bind(id)
-    .fetch_optional(pool)
+    let sql = format!("SELECT {} FROM ai_audits WHERE id = $1", AUDIT_COLUMNS);
+    retry_transient("get_audit_by_id", || {
+        sqlx::query_as::<_, AiAudit>(&sql)
+            .bind(id)
+            .fetch_optional(pool)
+    })
     .await
     .map_err(AppError::from)
 }
 
-/// Creates a new AI audit record in the database.
+/// Replaces the tags of an audit.
 ///
-/// This function first compiles the provided code using `auditor::check_compilation`.
-/// Based on the result, it sets the `is_valid` and `compilation_error` fields
-/// before inserting the new record into the database.
+/// The tags are normalized like those of a new audit.
 ///
 /// # Arguments
 ///
 /// * `pool` - A reference to the database connection pool.
-/// * `input` - The request payload containing the prompt and generated code.
+/// * `id` - The UUID of the audit.
+/// * `tags` - The new tags, replacing all the previous ones.
 ///
 /// # Returns
 ///
-/// * `Ok(AiAudit)` - The newly created audit record.
-/// * `Err(AppError)` - If the code compilation or database insertion fails.
-#[tracing::instrument(skip(pool, input))]
-pub async fn create_audit(pool: &PgPool, input: &CreateAuditRequest) -> Result<AiAudit, AppError> {
-    // Compile the generated code to determine its validity.
-    let (is_valid, compilation_error) = match auditor::check_compilation(&input.generated_code) {
-        Ok(()) => (true, None),
-        Err(AppError::Audit(e)) => (false, Some(e)),
-        Err(e) => return Err(e), // Propagate other error types
-    };
-
-    sqlx::query_as::<_, AiAudit>(
+/// * `Ok(AiAudit)` - The updated audit.
+/// * `Err(AppError::Validation)` - If there are too many tags or a tag is too long.
+/// * `Err(AppError::NotFound)` - If the audit does not exist.
+/// * `Err(AppError::Sqlx)` - If the database query fails.
+#[tracing::instrument(skip(pool))]
+pub async fn update_tags(pool: &PgPool, id: Uuid, tags: &[String]) -> Result<AiAudit, AppError> {
+    let tags = normalize_tags(tags)?;
+    // Tags do not count in the statistics, but the summary must not look stale.
+    sqlx::query_as::<_, AiAudit>(&format!(
         r#"
-        INSERT INTO ai_audits (prompt, generated_code, is_valid, compilation_error)
-        VALUES ($1, $2, $3, $4)
-        RETURNING id, prompt, generated_code, is_valid, compilation_error, created_at
+        WITH updated AS (
+            UPDATE ai_audits SET tags = $2, updated_at = NOW() WHERE id = $1 RETURNING *
+        ), touched AS (
+            UPDATE audit_stats_summary SET updated_at = GREATEST(updated_at, NOW())
+        )
+        SELECT {} FROM updated
         "#,
-    )
-    .bind(&input.prompt)
-    .bind(&input.generated_code)
-    .bind(is_valid)
-    .bind(compilation_error)
-    .fetch_one(pool)
-    .await
-    .map_err(AppError::from)
+        AUDIT_COLUMNS
+    ))
+    .bind(id)
+    .bind(&tags)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("Audit {} not found", id)))
 }
 
-/// Calculates and retrieves statistics about all AI audits.
+/// Retrieves several audits by their unique identifiers.
 ///
 /// # Arguments
 ///
 /// * `pool` - A reference to the database connection pool.
+/// * `ids` - The UUIDs of the audits.
 ///
 /// # Returns
 ///
-/// * `Ok(AuditStats)` - The calculated statistics.
-/// * `Err(AppError::Sqlx)` - If any database query fails.
+/// * `Ok(Vec<AiAudit>)` - The audits, in the order of `ids`.
+/// * `Err(AppError::NotFound)` - If any of the audits does not exist.
+/// * `Err(AppError::Sqlx)` - If a database query fails.
 #[tracing::instrument(skip(pool))]
-pub async fn get_audit_stats(pool: &PgPool) -> Result<AuditStats, AppError> {
-    // Get total and valid counts.
-    let (total_audits, valid_audits): (i64, i64) = sqlx::query_as(
-        "SELECT
-            COUNT(*) as total,
-            COUNT(*) FILTER (WHERE is_valid = true) as valid
-         FROM ai_audits",
-    )
-    .fetch_one(pool)
+pub async fn get_audits_by_ids(pool: &PgPool, ids: &[Uuid]) -> Result<Vec<AiAudit>, AppError> {
+    let sql = format!("SELECT {} FROM ai_audits WHERE id = ANY($1)", AUDIT_COLUMNS);
+    let audits = retry_transient("get_audits_by_ids", || {
+        sqlx::query_as::<_, AiAudit>(&sql).bind(ids).fetch_all(pool)
+    })
     .await?;
 
-    let invalid_audits = total_audits - valid_audits;
-    let validation_rate = if total_audits > 0 {
-        valid_audits as f64 / total_audits as f64
-    } else {
-        0.0
-    };
+    let mut by_id: HashMap<Uuid, AiAudit> = audits.into_iter().map(|a| (a.id, a)).collect();
+    ids.iter()
+        .map(|id| {
+            by_id
+                .remove(id)
+                .ok_or_else(|| AppError::NotFound(format!("Audit {} not found", id)))
+        })
+        .collect()
+}
 
-    // Get the most common compilation errors.
-    let common_errors = sqlx::query_as::<_, CommonError>(
+/// Compares two audits, typically generated from two versions of a prompt.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `id_a` - The ID of the audit to compare from.
+/// * `id_b` - The ID of the audit to compare to.
+///
+/// # Returns
+///
+/// * `Ok(AuditComparison)` - Both audits and the differences between them.
+/// * `Err(AppError::NotFound)` - If either audit does not exist.
+/// * `Err(AppError::Sqlx)` - If a database query fails.
+#[tracing::instrument(skip(pool))]
+pub async fn compare_audits(
+    pool: &PgPool,
+    id_a: Uuid,
+    id_b: Uuid,
+) -> Result<AuditComparison, AppError> {
+    let not_found = |id: Uuid| AppError::NotFound(format!("Audit {} not found", id));
+    let audit_a = get_audit_by_id(pool, id_a)
+        .await?
+        .ok_or_else(|| not_found(id_a))?;
+    let audit_b = get_audit_by_id(pool, id_b)
+        .await?
+        .ok_or_else(|| not_found(id_b))?;
+
+    let code_diff = TextDiff::from_lines(&audit_a.generated_code, &audit_b.generated_code)
+        .unified_diff()
+        .header(&format!("a/{}", id_a), &format!("b/{}", id_b))
+        .to_string();
+    let validity_changed = audit_a.is_valid != audit_b.is_valid;
+    let quality_score_delta = quality_score_delta(&audit_a, &audit_b);
+
+    Ok(AuditComparison {
+        audit_a,
+        audit_b,
+        code_diff,
+        validity_changed,
+        quality_score_delta,
+    })
+}
+
+/// Returns how much the quality score of `audit_b` differs from the one of `audit_a`,
+/// or `None` if either audit has no metrics (e.g. it was imported).
+fn quality_score_delta(audit_a: &AiAudit, audit_b: &AiAudit) -> Option<f64> {
+    let score = |audit: &AiAudit| audit.metrics.as_ref().map(|m| m.quality_score);
+    Some(score(audit_b)? - score(audit_a)?)
+}
+
+/// The maximum number of similar audits returned by `find_similar_audits`.
+const MAX_SIMILAR_AUDITS: i64 = 50;
+
+/// Finds the audits whose code is similar to the code of an audit.
+///
+/// Codes are compared by the share of their character trigrams in common (`pg_trgm`), so
+/// renamed identifiers or reformatted code still match closely. The trigram index keeps
+/// the search fast however many audits there are.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `id` - The UUID of the audit to compare the others to.
+/// * `threshold` - The minimum similarity of the returned audits, above 0 and at most 1.
+///
+/// # Returns
+///
+/// * `Ok(Vec<SimilarAudit>)` - At most 50 other audits, most similar first.
+/// * `Err(AppError::Validation)` - If `threshold` is out of range.
+/// * `Err(AppError::NotFound)` - If the audit does not exist.
+/// * `Err(AppError::Sqlx)` - If a database query fails.
+#[tracing::instrument(skip(pool))]
+pub async fn find_similar_audits(
+    pool: &PgPool,
+    id: Uuid,
+    threshold: f64,
+) -> Result<Vec<SimilarAudit>, AppError> {
+    if !(threshold > 0.0 && threshold <= 1.0) {
+        return Err(AppError::Validation(
+            "threshold must be above 0 and at most 1".to_string(),
+        ));
+    }
+    let audit = get_audit_by_id(pool, id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Audit {} not found", id)))?;
+
+    // The `%` operator, which can use the trigram index, compares against the threshold
+    // set for the transaction.
+    let mut tx = pool.begin().await?;
+    sqlx::query("SELECT set_config('pg_trgm.similarity_threshold', $1, true)")
+        .bind(threshold.to_string())
+        .execute(&mut *tx)
+        .await?;
+    let similar = sqlx::query_as::<_, SimilarAudit>(&format!(
         r#"
-        SELECT
-            LEFT(compilation_error, 200) as error_message,
-            COUNT(*) as frequency
+        SELECT {}, similarity(generated_code, $1)::float8 AS similarity
         FROM ai_audits
-        WHERE compilation_error IS NOT NULL
-          AND compilation_error != ''
-        GROUP BY LEFT(compilation_error, 200)
-        ORDER BY frequency DESC
-        LIMIT 10
+        WHERE generated_code % $1 AND id <> $2
+        ORDER BY similarity DESC, created_at DESC
+        LIMIT $3
         "#,
+        AUDIT_COLUMNS
+    ))
+    .bind(&audit.generated_code)
+    .bind(id)
+    .bind(MAX_SIMILAR_AUDITS)
+    .fetch_all(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(similar)
+}
+
+/// Builds the SARIF 2.1.0 log of an audit's findings and compiler diagnostics.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `id` - The UUID of the audit.
+///
+/// # Returns
+///
+/// * `Ok(serde_json::Value)` - The SARIF log.
+/// * `Err(AppError::NotFound)` - If the audit does not exist.
+/// * `Err(AppError::Sqlx)` - If a database query fails.
+#[tracing::instrument(skip(pool))]
+pub async fn get_audit_sarif(pool: &PgPool, id: Uuid) -> Result<serde_json::Value, AppError> {
+    let audit = get_audit_by_id(pool, id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Audit {} not found", id)))?;
+    let diagnostics = get_audit_diagnostics(pool, id).await?;
+    Ok(sarif::audit_to_sarif(&audit, &diagnostics))
+}
+
+/// Retrieves the compiler diagnostics of an audit.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `id` - The UUID of the audit.
+///
+/// # Returns
+///
+/// * `Ok(Vec<Diagnostic>)` - The diagnostics, in the order `rustc` emitted them.
+/// * `Err(AppError::NotFound)` - If the audit does not exist.
+/// * `Err(AppError::Sqlx)` - If the database query fails.
+#[tracing::instrument(skip(pool))]
+pub async fn get_audit_diagnostics(pool: &PgPool, id: Uuid) -> Result<Vec<Diagnostic>, AppError> {
+    let Json(diagnostics) = sqlx::query_scalar!(
+        r#"SELECT diagnostics AS "diagnostics: Json<Vec<Diagnostic>>" FROM ai_audits WHERE id = $1"#,
+        id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("Audit {} not found", id)))?;
+    Ok(diagnostics)
+}
+
+/// Retrieves the files of an audit submitted as several files.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `id` - The UUID of the audit.
+///
+/// # Returns
+///
+/// * `Ok(Vec<AuditFile>)` - The files, ordered by path; none for an audit of a single
+///   file.
+/// * `Err(AppError::Sqlx)` - If the database query fails.
+#[tracing::instrument(skip(pool))]
+pub async fn get_audit_files(pool: &PgPool, id: Uuid) -> Result<Vec<AuditFile>, AppError> {
+    let files = sqlx::query_as!(
+        AuditFile,
+        "SELECT path, content FROM audit_files WHERE audit_id = $1 ORDER BY path",
+        id
     )
     .fetch_all(pool)
     .await?;
+    Ok(files)
+}
 
-    Ok(AuditStats {
-        total_audits,
-        valid_audits,
-        invalid_audits,
-        validation_rate,
-        common_errors,
+/// The maximum length accepted for an idempotency key.
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+/// The maximum number of tags on an audit.
+const MAX_TAGS: usize = 20;
+
+/// The maximum length of a tag.
+const MAX_TAG_LEN: usize = 64;
+
+/// Normalizes tags so that they compare equal regardless of case and spacing.
+///
+/// Tags are trimmed and lowercased; empty tags are dropped and duplicates removed.
+///
+/// # Arguments
+///
+/// * `tags` - The tags as supplied by the client.
+///
+/// # Returns
+///
+/// * `Ok(Vec<String>)` - The normalized tags, sorted alphabetically.
+/// * `Err(AppError::Validation)` - If there are too many tags or a tag is too long.
+fn normalize_tags(tags: &[String]) -> Result<Vec<String>, AppError> {
+    let tags: BTreeSet<String> = tags
+        .iter()
+        .map(|tag| tag.trim().to_lowercase())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+
+    if tags.len() > MAX_TAGS {
+        return Err(AppError::Validation(format!(
+            "At most {} tags are allowed",
+            MAX_TAGS
+        )));
+    }
+    if let Some(tag) = tags.iter().find(|tag| tag.chars().count() > MAX_TAG_LEN) {
+        return Err(AppError::Validation(format!(
+            "Tag '{}' is longer than {} characters",
+            tag, MAX_TAG_LEN
+        )));
+    }
+    Ok(tags.into_iter().collect())
+}
+
+/// Computes a fingerprint of the parts of a request that define an audit.
+///
+/// Two requests sharing an idempotency key must have the same fingerprint. Every field
+/// changing the verdict is hashed with the default it takes when omitted, so that
+/// omitting a setting and sending its default give the same fingerprint.
+///
+/// # Arguments
+///
+/// * `input` - The audit request.
+/// * `policy` - The server-wide audit settings giving the omitted defaults.
+/// * `code` - The submitted code the edition is detected from when omitted: the crate
+///   root, or `generated_code`.
+/// * `tags` - The normalized tags of the audit.
+fn request_fingerprint(
+    input: &CreateAuditRequest,
+    policy: &AuditPolicy,
+    code: &str,
+    tags: &[String],
+) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(input.prompt.as_bytes());
+    hasher.update([0]);
+    hasher.update(input.generated_code.as_bytes());
+    for file in &input.files {
+        hasher.update([0]);
+        hasher.update(file.path.as_bytes());
+        hasher.update([0]);
+        hasher.update(file.content.as_bytes());
+    }
+    if let Some(generate) = &input.generate {
+        hasher.update([0]);
+        hasher.update(generate.provider.as_str().as_bytes());
+        hasher.update([0]);
+        hasher.update(generate.model.as_bytes());
+    }
+    let settings = [
+        input.channel.unwrap_or_default().as_str(),
+        input.opt_level.unwrap_or_default().as_str(),
+        pipeline::audit_edition(input, code).as_str(),
+        input.target.as_deref().unwrap_or_default(),
+        input.profile.as_deref().unwrap_or(&policy.default_profile),
+        if input.strict.unwrap_or(policy.strict) {
+            "strict"
+        } else {
+            "lenient"
+        },
+    ];
+    for setting in settings {
+        hasher.update([0]);
+        hasher.update(setting.as_bytes());
+    }
+    hasher.update([0]);
+    hasher.update(
+        input
+            .auto_fix
+            .map_or(0, |auto_fix| auto_fix.max_attempts)
+            .to_be_bytes(),
+    );
+    for tag in tags {
+        hasher.update([0]);
+        hasher.update(tag.as_bytes());
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Looks up the audit previously created with an idempotency key.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `key` - The idempotency key supplied by the client.
+/// * `fingerprint` - The fingerprint of the current request.
+///
+/// # Returns
+///
+/// * `Ok(Some(AiAudit))` - The audit created by an earlier request with the same key and payload.
+/// * `Ok(None)` - If the key has not been used yet.
+/// * `Err(AppError::Conflict)` - If the key was used with a different payload.
+/// * `Err(AppError::Sqlx)` - If a database query fails.
+async fn find_idempotent_audit(
+    pool: &PgPool,
+    key: &str,
+    fingerprint: &str,
+) -> Result<Option<AiAudit>, AppError> {
+    let stored = retry_transient("find_idempotent_audit", || {
+        sqlx::query!(
+            "SELECT id, request_fingerprint FROM ai_audits WHERE idempotency_key = $1",
+            key
+        )
+        .fetch_optional(pool)
     })
+    .await?
+    .map(|row| (row.id, row.request_fingerprint));
+
+    match stored {
+        None => Ok(None),
+        Some((_, stored_fingerprint)) if stored_fingerprint.as_deref() != Some(fingerprint) => {
+            Err(AppError::Conflict(format!(
+                "Idempotency key '{}' was already used with a different payload",
+                key
+            )))
+        }
+        Some((id, _)) => {
+            tracing::info!(%id, "Returning audit created by an earlier request with the same idempotency key.");
+            get_audit_by_id(pool, id).await
+        }
+    }
+}
+
+/// Forgets the idempotency keys of audits created more than `ttl` ago.
+///
+/// Requests retried with a forgotten key create a new audit. The keys are cleared
+/// rather than the audits deleted, and so are the payload fingerprints they guard.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `ttl` - How long a key is remembered after its audit was created.
+///
+/// # Returns
+///
+/// * `Ok(u64)` - The number of keys forgotten.
+/// * `Err(AppError::Sqlx)` - If the database query fails.
+#[tracing::instrument(skip(pool))]
+pub async fn expire_idempotency_keys(pool: &PgPool, ttl: Duration) -> Result<u64, AppError> {
+    let result = sqlx::query!(
+        "UPDATE ai_audits SET idempotency_key = NULL, request_fingerprint = NULL
+         WHERE idempotency_key IS NOT NULL AND created_at < NOW() - make_interval(secs => $1)",
+        ttl.as_secs_f64()
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Deletes the audits created more than `retention` ago, with their comments, ratings,
+/// files, jobs and artifact records.
+///
+/// Newer audits fixing a deleted one keep their verdict but lose the link to it. The
+/// running totals behind the statistics are recomputed in the same transaction, so that
+/// they never count deleted audits.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `retention` - How long audits are kept after they were created.
+///
+/// # Returns
+///
+/// * `Ok(u64)` - The number of audits deleted.
+/// * `Err(AppError::Sqlx)` - If a database query fails.
+#[tracing::instrument(skip(pool))]
+pub async fn delete_expired_audits(pool: &PgPool, retention: Duration) -> Result<u64, AppError> {
+    let mut tx = pool.begin().await?;
+    let cutoff = retention.as_secs_f64();
+    sqlx::query!(
+        "UPDATE ai_audits SET parent_audit_id = NULL
+         WHERE parent_audit_id IN (
+             SELECT id FROM ai_audits WHERE created_at < NOW() - make_interval(secs => $1)
+         )",
+        cutoff
+    )
+    .execute(&mut *tx)
+    .await?;
+    let deleted = sqlx::query!(
+        "DELETE FROM ai_audits WHERE created_at < NOW() - make_interval(secs => $1)",
+        cutoff
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if deleted > 0 {
+        write_stats_summary(&mut tx).await?;
+    }
+    tx.commit().await?;
+    Ok(deleted)
+}
+
+/// The maximum number of fix attempts an audit may request.
+const MAX_AUTO_FIX_ATTEMPTS: u8 = 5;
+
+/// Creates a new AI audit record in the database.
+///
+/// If the request sets `generate`, the code is first generated from the prompt by the
+/// requested provider; the answer of the model, its token usage and latency are stored
+/// along with the extracted code. This function then validates the code using
+/// `auditor::validate_code`. In strict mode, code with blocking findings is rejected
+/// without being compiled; otherwise the code is compiled by the compilation workers, or,
+/// if the request sets `background`, stored with the `pending` status along with a job
+/// compiling it later (rejected code gets a job that is already done). Based on the
+/// result, it sets the verdict fields before inserting the new record into the database.
+/// The prompt and the code are audited and stored without surrounding whitespace.
+///
+/// When the request carries an idempotency key that was already used with the same
+/// payload, the previously created audit is returned without recompiling, even if a
+/// request with the same key is being processed concurrently: the unique index on the
+/// key decides which request creates the audit. Otherwise the
+/// webhooks subscribed to `audit.completed` are notified in the background, and so is
+/// Slack if the code is not valid; for a pending audit, once its job completes. The
+/// outputs of the compilation are then uploaded to the artifact store, if one is
+/// configured (see `ArtifactSettings::upload`).
+///
+/// The `auto_fix` option of the request is validated but ignored: see
+/// `create_audit_chain`.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `policy` - The server-wide audit settings.
+/// * `compiler` - The queue of the compilation workers, holding the artifact store.
+/// * `generators` - The LLM providers generating code for requests setting `generate`.
+/// * `input` - The request payload containing the prompt and generated code.
+/// * `source` - Where the code came from, for audits created by an integration.
+/// * `notifiers` - The notifiers told about the new audit.
+///
+/// # Returns
+///
+/// * `Ok(AiAudit)` - The newly created (or previously created) audit record.
+/// * `Err(AppError::Validation)` - If the prompt is blank, `generated_code` is blank
+///   without `generate`, the idempotency key is empty or too long, the tags are invalid,
+///   both `generated_code` and `generate` are set, `auto_fix` is invalid or combined
+///   with `background`,
+///   the nightly toolchain is requested but not installed, or the requested provider is
+///   not configured.
+/// * `Err(AppError::Conflict)` - If the idempotency key was used with a different payload.
+/// * `Err(AppError::RateLimited)`, `Err(AppError::Provider)` or
+///   `Err(AppError::MissingCodeBlock)` - If the code could not be generated.
+/// * `Err(AppError)` - If the code compilation or database insertion fails.
+pub async fn create_audit(
+    pool: &PgPool,
+    policy: &AuditPolicy,
+    compiler: &CompilationQueue,
+    generators: &CodeGenerators,
+    input: &CreateAuditRequest,
+    source: Option<&AuditSource>,
+    notifiers: &AuditNotifiers,
+) -> Result<AiAudit, AppError> {
+    create_or_replay_audit(pool, policy, compiler, generators, input, source, notifiers)
+        .await
+        .map(|(audit, _)| audit)
+}
+
+/// Creates an audit like `create_audit`, also telling whether it was created by this
+/// request or by an earlier one with the same idempotency key.
+///
+/// # Arguments
+///
+/// The same as `create_audit`.
+///
+/// # Returns
+///
+/// * `Ok((AiAudit, bool))` - The audit, and `true` if this request created it or
+///   `false` if it was created earlier with the same idempotency key.
+/// * `Err(AppError)` - The same errors as `create_audit`.
+#[tracing::instrument(skip(pool, compiler, generators, input, notifiers))]
+pub async fn create_or_replay_audit(
+    pool: &PgPool,
+    policy: &AuditPolicy,
+    compiler: &CompilationQueue,
+    generators: &CodeGenerators,
+    input: &CreateAuditRequest,
+    source: Option<&AuditSource>,
+    notifiers: &AuditNotifiers,
+) -> Result<(AiAudit, bool), AppError> {
+    let root = pipeline::check_request(input, policy)?;
+    let tags = normalize_tags(&input.tags)?;
+    let submitted = root.map_or(input.generated_code.as_str(), |root| root.content.as_str());
+    let fingerprint = request_fingerprint(input, policy, submitted.trim(), &tags);
+    if let Some(key) = &input.idempotency_key {
+        if key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LEN {
+            return Err(AppError::Validation(format!(
+                "idempotency_key must be between 1 and {} characters",
+                MAX_IDEMPOTENCY_KEY_LEN
+            )));
+        }
+        if let Some(audit) = find_idempotent_audit(pool, key, &fingerprint).await? {
+            return Ok((audit, false));
+        }
+    }
+
+    let profile = resolve_audit_profile(pool, policy, input.profile.as_deref()).await?;
+    let policy = &with_license_policy(pool, policy).await?;
+    let auditor = Auditor::new(policy.clone(), compiler.clone()).with_profile(&profile);
+
+    if let Some(auto_fix) = &input.auto_fix {
+        if input.background {
+            return Err(AppError::Validation(
+                "auto_fix and background are mutually exclusive".to_string(),
+            ));
+        }
+        if input.generate.is_none() {
+            return Err(AppError::Validation(
+                "auto_fix requires generate".to_string(),
+            ));
+        }
+        if !(1..=MAX_AUTO_FIX_ATTEMPTS).contains(&auto_fix.max_attempts) {
+            return Err(AppError::Validation(format!(
+                "auto_fix.max_attempts must be between 1 and {}",
+                MAX_AUTO_FIX_ATTEMPTS
+            )));
+        }
+    }
+
+    let generated = match &input.generate {
+        Some(options) => Some(generators.generate_code(options, &input.prompt).await?),
+        None => None,
+    };
+    let code = match (&generated, root) {
+        (Some(generated), _) => generated.code.as_str(),
+        (None, Some(root)) => root.content.as_str(),
+        (None, None) => input.generated_code.as_str(),
+    }
+    .trim();
+
+    let (outcome, artifacts) = auditor.run_audit_pipeline(input, code).await?;
+    let formatted_code = format_code(code).await;
+    let record = AuditRecord {
+        input,
+        code,
+        tags: &tags,
+        source,
+        generated: generated.as_ref(),
+        idempotency_key: input.idempotency_key.as_deref(),
+        fingerprint: Some(&fingerprint),
+        parent_audit_id: None,
+        attempt_number: 1,
+        formatted_code: formatted_code.as_deref(),
+        profile: &profile,
+    };
+    let mut retry = 0;
+    let audit = loop {
+        let (error, maybe_committed) = match commit_audit(pool, &record, &outcome).await {
+            Ok(audit) => break audit,
+            Err(failure) => failure,
+        };
+        match (error, &input.idempotency_key) {
+            // A concurrent request with the same key won the race: return its audit.
+            (sqlx::Error::Database(e), Some(key)) if e.is_unique_violation() => {
+                return find_idempotent_audit(pool, key, &fingerprint)
+                    .await?
+                    .map(|audit| (audit, false))
+                    .ok_or_else(|| {
+                        AppError::Conflict(format!("Idempotency key '{}' is in use", key))
+                    });
+            }
+            (e, key) if retry < db_retry_policy().max_retries && is_transient(&e) => {
+                // If the connection was lost while committing, the audit may have been
+                // stored: only its idempotency key tells, so never insert it twice.
+                if maybe_committed {
+                    let Some(key) = key else {
+                        return Err(e.into());
+                    };
+                    if let Some(audit) = find_idempotent_audit(pool, key, &fingerprint).await? {
+                        break audit;
+                    }
+                }
+                retry += 1;
+                wait_before_retry("create_audit", retry, &e).await;
+            }
+            (e, _) => return Err(e.into()),
+        }
+    };
+    if let Some(artifacts) = artifacts {
+        compiler
+            .artifacts()
+            .upload(pool, audit.id, &audit.generated_code, artifacts);
+    }
+    // Audits compiled in the background are notified once their job completes.
+    if audit.status != AuditStatus::Pending {
+        notifiers.audit_created(pool, &audit);
+    }
+    Ok((audit, true))
+}
+
+/// Audits code like `create_audit` without storing anything, to try code out.
+///
+/// The code goes through the same pipeline as the audits that are stored (see
+/// `Auditor::run_audit_pipeline`), under the requested audit profile.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool, to read the audit profile.
+/// * `policy` - The server-wide audit settings.
+/// * `compiler` - The queue of the compilation workers.
+/// * `input` - The request payload containing the prompt and the code.
+///
+/// # Returns
+///
+/// * `Ok(AuditOutcome)` - The verdict, the findings, the compiler diagnostics and the
+///   metrics of the code.
+/// * `Err(AppError::Validation)` - If the request is invalid (see
+///   `pipeline::check_request`), sets `generate`, `auto_fix` or `background`, or names an
+///   unknown profile.
+/// * `Err(AppError)` - If the profile cannot be read or the code cannot be compiled.
+#[tracing::instrument(skip(pool, compiler, input))]
+pub async fn analyze_code(
+    pool: &PgPool,
+    policy: &AuditPolicy,
+    compiler: &CompilationQueue,
+    input: CreateAuditRequest,
+) -> Result<AuditOutcome, AppError> {
+    let profile = resolve_audit_profile(pool, policy, input.profile.as_deref()).await?;
+    Auditor::new(with_license_policy(pool, policy).await?, compiler.clone())
+        .with_profile(&profile)
+        .audit(input)
+        .await
+}
+
+/// Inserts an audit in its own transaction.
+///
+/// The audit, its idempotency key and its fingerprint are committed together, before
+/// anyone is notified, so a failed insertion never leaves a partial audit behind. Every
+/// audit created from a request, including auto-fix attempts, goes through here, so
+/// further writes belonging to a new audit must be made on the same transaction, like
+/// the job compiling a pending audit. The compilation happens before, so that no
+/// connection is held while compiling.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `record` - The audit to insert.
+/// * `outcome` - The result of the validation, compilation and analyses of the code.
+///
+/// # Returns
+///
+/// * `Ok(AiAudit)` - The inserted audit.
+/// * `Err((sqlx::Error, bool))` - The error, and whether it happened while committing,
+///   in which case the audit may have been stored anyway.
+async fn commit_audit(
+    pool: &PgPool,
+    record: &AuditRecord<'_>,
+    outcome: &AuditOutcome,
+) -> Result<AiAudit, (sqlx::Error, bool)> {
+    let mut tx = pool.begin().await.map_err(|e| (e, false))?;
+    let audit = insert_audit(&mut *tx, record, outcome)
+        .await
+        .map_err(|e| (e, false))?;
+    if !record.input.files.is_empty() {
+        let (paths, contents): (Vec<_>, Vec<_>) = record
+            .input
+            .files
+            .iter()
+            .map(|file| (file.path.clone(), file.content.clone()))
+            .unzip();
+        sqlx::query!(
+            "INSERT INTO audit_files (audit_id, path, content) SELECT $1, * FROM UNNEST($2::text[], $3::text[])",
+            audit.id,
+            &paths,
+            &contents
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| (e, false))?;
+    }
+    // Every audit submitted in the background gets a job, so that clients can follow
+    // it; code rejected without compiling has nothing left to do.
+    if record.input.background {
+        sqlx::query!(
+            "INSERT INTO audit_jobs (audit_id, state) VALUES ($1, CASE WHEN $2 THEN 'pending' ELSE 'done' END)",
+            audit.id,
+            audit.status == AuditStatus::Pending
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| (e, false))?;
+    }
+    tx.commit().await.map_err(|e| (e, true))?;
+    Ok(audit)
+}
+
+/// Creates an audit like `create_audit` and, if the request sets `auto_fix` and the code
+/// does not compile, asks the model generating the code for corrected versions.
+///
+/// Each corrected version is audited and stored as a new audit linked to the previous
+/// one (`parent_audit_id`, `attempt_number`), until the code compiles, the attempt limit
+/// is reached, or the policy's time budget runs out. Compilations go through the
+/// workers like any other. A fix attempt that fails (e.g. the provider is rate-limited)
+/// ends the chain without failing the request, since the audits already created are
+/// kept.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `policy` - The server-wide audit settings.
+/// * `compiler` - The queue of the compilation workers.
+/// * `generators` - The LLM providers generating code.
+/// * `input` - The request payload.
+/// * `notifiers` - The notifiers told about the new audits.
+///
+/// # Returns
+///
+/// * `Ok(AuditChain)` - The audits created, ordered by attempt number. For a retried
+///   request, the chain created by the original request.
+/// * `Err(AppError)` - If the first audit cannot be created (see `create_audit`).
+#[tracing::instrument(skip(pool, compiler, generators, input, notifiers))]
+pub async fn create_audit_chain(
+    pool: &PgPool,
+    policy: &AuditPolicy,
+    compiler: &CompilationQueue,
+    generators: &CodeGenerators,
+    input: &CreateAuditRequest,
+    notifiers: &AuditNotifiers,
+) -> Result<AuditChain, AppError> {
+    let deadline = tokio::time::Instant::now() + policy.auto_fix_budget;
+    let first = create_audit(pool, policy, compiler, generators, input, None, notifiers).await?;
+
+    // A retried request returns the chain created by the original one.
+    let mut attempts = get_audit_chain(pool, first.id).await?;
+    if let (Some(auto_fix), [last]) = (&input.auto_fix, attempts.as_slice()) {
+        let mut last = last.clone();
+        while last.status == AuditStatus::CompileError
+            && last.attempt_number <= i32::from(auto_fix.max_attempts)
+        {
+            let attempt = fix_audit(pool, policy, compiler, generators, input, &last);
+            match tokio::time::timeout_at(deadline, attempt).await {
+                Ok(Ok(audit)) => {
+                    tracing::info!(id = %audit.id, attempt = audit.attempt_number, is_valid = audit.is_valid, "Fix attempt audited.");
+                    notifiers.audit_created(pool, &audit);
+                    attempts.push(audit.clone());
+                    last = audit;
+                }
+                Ok(Err(e)) => {
+                    tracing::warn!(error = %e, "Fix attempt failed, ending the chain.");
+                    break;
+                }
+                Err(_) => {
+                    tracing::warn!("Auto-fix time budget exhausted, ending the chain.");
+                    break;
+                }
+            }
+        }
+    }
+
+    let fixed = attempts.len() > 1 && attempts.last().is_some_and(|a| a.is_valid);
+    Ok(AuditChain { fixed, attempts })
+}
+
+/// Asks a model for a corrected version of a stored audit that does not compile.
+///
+/// The corrected code is audited with the settings of the original audit and stored as
+/// a new audit linked to it (`parent_audit_id`, `attempt_number`), then the notifiers
+/// are told about it. The new audit may still not compile.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `policy` - The server-wide audit settings.
+/// * `compiler` - The queue of the compilation workers.
+/// * `generators` - The LLM providers generating code.
+/// * `id` - The UUID of the audit to fix.
+/// * `generate` - The model asked to fix the code, or `None` for the model that
+///   generated it.
+/// * `notifiers` - The notifiers told about the new audit.
+///
+/// # Returns
+///
+/// * `Ok(AiAudit)` - The audit of the corrected code.
+/// * `Err(AppError::NotFound)` - If no audit has this ID.
+/// * `Err(AppError::Validation)` - If the audit did not fail to compile, has several
+///   files, no model is given for code that was not generated server-side, or the
+///   provider is not configured.
+/// * `Err(AppError::RateLimited)`, `Err(AppError::Provider)` or
+///   `Err(AppError::MissingCodeBlock)` - If the code could not be generated.
+/// * `Err(AppError)` - If the code compilation or database insertion fails.
+#[tracing::instrument(skip(pool, compiler, generators, generate, notifiers))]
+pub async fn attempt_fix(
+    pool: &PgPool,
+    policy: &AuditPolicy,
+    compiler: &CompilationQueue,
+    generators: &CodeGenerators,
+    id: Uuid,
+    generate: Option<GenerateOptions>,
+    notifiers: &AuditNotifiers,
+) -> Result<AiAudit, AppError> {
+    let audit = get_audit_by_id(pool, id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Audit {} not found", id)))?;
+    if audit.status != AuditStatus::CompileError {
+        return Err(AppError::Validation(format!(
+            "Audit {} did not fail to compile, there is nothing to fix",
+            id
+        )));
+    }
+    // Models are asked for a single file, which would lose the other ones.
+    if !get_audit_files(pool, id).await?.is_empty() {
+        return Err(AppError::Validation(format!(
+            "Audit {} has several files, which cannot be fixed",
+            id
+        )));
+    }
+    let generate = match (generate, audit.generation_provider, &audit.generation_model) {
+        (Some(generate), _, _) => generate,
+        (None, Some(provider), Some(model)) => GenerateOptions {
+            provider,
+            model: model.clone(),
+        },
+        _ => {
+            return Err(AppError::Validation(
+                "generate is required for audits whose code was not generated by this server"
+                    .to_string(),
+            ));
+        }
+    };
+
+    // Audit the corrected code like the original one.
+    let input = CreateAuditRequest {
+        prompt: audit.prompt.clone(),
+        generated_code: String::new(),
+        files: Vec::new(),
+        generate: Some(generate),
+        auto_fix: None,
+        idempotency_key: None,
+        strict: None,
+        tags: audit.tags.clone(),
+        channel: Some(audit.channel),
+        opt_level: Some(audit.opt_level),
+        // An edition guessed from the original code is guessed again from the fixed code.
+        edition: audit
+            .edition_detected
+            .is_none()
+            .then(|| audit.edition.unwrap_or(Edition::UNRECORDED)),
+        target: audit.target.clone(),
+        profile: audit.profile.clone(),
+        background: false,
+        bypass_cache: false,
+    };
+    let fixed = fix_audit(pool, policy, compiler, generators, &input, &audit).await?;
+    tracing::info!(id = %fixed.id, is_valid = fixed.is_valid, "Fix attempt audited.");
+    notifiers.audit_created(pool, &fixed);
+    Ok(fixed)
+}
+
+/// Asks the model generating the code of an audit for a corrected version, then audits
+/// and stores it as the next attempt.
+///
+/// # Returns
+///
+/// * `Ok(AiAudit)` - The audit of the corrected code.
+/// * `Err(AppError)` - If the code cannot be generated, compiled or stored.
+async fn fix_audit(
+    pool: &PgPool,
+    policy: &AuditPolicy,
+    compiler: &CompilationQueue,
+    generators: &CodeGenerators,
+    input: &CreateAuditRequest,
+    previous: &AiAudit,
+) -> Result<AiAudit, AppError> {
+    let generate = input
+        .generate
+        .as_ref()
+        .ok_or_else(|| AppError::Validation("auto_fix requires generate".to_string()))?;
+    let prompt = generation::fix_prompt(
+        &input.prompt,
+        &previous.generated_code,
+        previous.compilation_error.as_deref().unwrap_or_default(),
+    );
+    let generated = generators.generate_code(generate, &prompt).await?;
+
+    let tags = normalize_tags(&input.tags)?;
+    let profile = resolve_audit_profile(pool, policy, input.profile.as_deref()).await?;
+    let policy = with_license_policy(pool, policy).await?;
+    let auditor = Auditor::new(policy, compiler.clone()).with_profile(&profile);
+    let (outcome, artifacts) = auditor.run_audit_pipeline(input, &generated.code).await?;
+    let formatted_code = format_code(&generated.code).await;
+    let record = AuditRecord {
+        input,
+        code: &generated.code,
+        tags: &tags,
+        source: None,
+        generated: Some(&generated),
+        idempotency_key: None,
+        fingerprint: None,
+        parent_audit_id: Some(previous.id),
+        attempt_number: previous.attempt_number + 1,
+        formatted_code: formatted_code.as_deref(),
+        profile: &profile,
+    };
+    let audit = commit_audit(pool, &record, &outcome)
+        .await
+        .map_err(|(e, _)| AppError::from(e))?;
+    if let Some(artifacts) = artifacts {
+        compiler
+            .artifacts()
+            .upload(pool, audit.id, &audit.generated_code, artifacts);
+    }
+    Ok(audit)
+}
+
+/// Formats code with `rustfmt` on a blocking task.
+///
+/// # Returns
+///
+/// * `Some(String)` - The formatted code.
+/// * `None` - If `rustfmt` is not installed or cannot parse the code.
+async fn format_code(code: &str) -> Option<String> {
+    let code = code.to_string();
+    tokio::task::spawn_blocking(move || auditor::format_code(&code))
+        .await
+        .ok()?
+        .inspect_err(|e| tracing::debug!(error = %e, "Code not formatted."))
+        .ok()
+}
+
+/// The columns of a new audit that do not depend on its verdict.
+struct AuditRecord<'a> {
+    input: &'a CreateAuditRequest,
+    code: &'a str,
+    tags: &'a [String],
+    source: Option<&'a AuditSource>,
+    generated: Option<&'a GeneratedCode>,
+    idempotency_key: Option<&'a str>,
+    fingerprint: Option<&'a str>,
+    parent_audit_id: Option<Uuid>,
+    attempt_number: i32,
+    formatted_code: Option<&'a str>,
+    profile: &'a AuditProfile,
+}
+
+/// Estimates the number of tokens a language model splits a text into.
+///
+/// Each run of letters, digits and underscores counts as one token, and so does every
+/// other character that is not whitespace. Real tokenizers split long words further, so
+/// this underestimates them, but it ranks prompts and code by their cost well enough.
+///
+/// # Arguments
+///
+/// * `text` - The text to estimate the number of tokens of.
+///
+/// # Returns
+///
+/// * `u32` - The estimated number of tokens.
+pub fn estimate_token_count(text: &str) -> u32 {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let mut count = 0u32;
+    let mut in_word = false;
+    for c in text.chars() {
+        if is_word(c) {
+            if !in_word {
+                count = count.saturating_add(1);
+            }
+            in_word = true;
+        } else {
+            in_word = false;
+            if !c.is_whitespace() {
+                count = count.saturating_add(1);
+            }
+        }
+    }
+    count
+}
+
+/// The estimated number of tokens of a text, as stored in a column of the audits.
+fn token_count(text: &str) -> i32 {
+    i32::try_from(estimate_token_count(text)).unwrap_or(i32::MAX)
+}
+
+/// Inserts a new audit, with `executor` being the pool or an open transaction.
+///
+/// # Returns
+///
+/// * `Ok(AiAudit)` - The inserted audit.
+/// * `Err(sqlx::Error)` - If the insertion fails, e.g. because the idempotency key is
+///   already used.
+async fn insert_audit(
+    executor: impl PgExecutor<'_>,
+    record: &AuditRecord<'_>,
+    outcome: &AuditOutcome,
+) -> Result<AiAudit, sqlx::Error> {
+    let (input, code, generated) = (record.input, record.code, record.generated);
+    let code_line_count = i32::try_from(code.lines().count()).unwrap_or(i32::MAX);
+    let code_char_count = i32::try_from(code.chars().count()).unwrap_or(i32::MAX);
+
+    // The running totals of the statistics are updated by the same statement.
+    sqlx::query_as::<_, AiAudit>(&format!(
+        r#"
+        WITH inserted AS (
+            INSERT INTO ai_audits (
+                prompt, generated_code, is_valid, status, tags, code_line_count, code_char_count,
+                compilation_error, primary_error_code, primary_error_category, channel, opt_level, target,
+                rustc_version, compilation_duration_ms, doc_coverage_percent, findings, unsafe_report,
+                hygiene_report, metrics, profile, profile_weights, formatted_code, needs_formatting,
+                rejection_reason, diagnostics,
+                source_repository, source_pull_request, source_path, idempotency_key, request_fingerprint,
+                generation_provider, generation_model, raw_model_output, prompt_tokens, completion_tokens,
+                generation_latency_ms, parent_audit_id, attempt_number, edition, compile_command,
+                prompt_token_count, code_token_count, edition_detected, detected_license,
+                vulnerability_count, advisory_note, license_violation_count, security_advisories,
+                has_security_issues
+            )
+            VALUES (
+                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18,
+                $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29, $30, $31, $32, $33, $34,
+                $35, $36, $37, $38, $39, $40, $41, $42, $43, $44, $45, $46, $47, $48, $49, $50
+            )
+            RETURNING *
+        ), {}
+        SELECT {} FROM inserted
+        "#,
+        count_inserted_audits(),
+        AUDIT_COLUMNS
+    ))
+    .bind(input.prompt.trim())
+    .bind(code)
+    .bind(outcome.is_valid)
+    .bind(outcome.status)
+    .bind(record.tags)
+    .bind(code_line_count)
+    .bind(code_char_count)
+    .bind(&outcome.compilation_error)
+    .bind(&outcome.primary_error_code)
+    .bind(&outcome.primary_error_category)
+    .bind(input.channel.unwrap_or_default())
+    .bind(input.opt_level.unwrap_or_default())
+    .bind(&input.target)
+    .bind(&outcome.rustc_version)
+    .bind(outcome.compilation_duration_ms)
+    .bind(outcome.doc_coverage_percent)
+    .bind(Json(&outcome.findings))
+    .bind(outcome.unsafe_report.as_ref().map(Json))
+    .bind(Json(&outcome.hygiene_report))
+    .bind(Json(&outcome.metrics))
+    .bind(&record.profile.name)
+    .bind(Json(&record.profile.weights))
+    .bind(record.formatted_code)
+    // A missing final newline alone does not make code badly formatted.
+    .bind(
+        record
+            .formatted_code
+            .map(|formatted| formatted.trim_end() != code.trim_end()),
+    )
+    .bind(&outcome.rejection_reason)
+    .bind(Json(&outcome.diagnostics))
+    .bind(record.source.map(|s| &s.repository))
+    .bind(record.source.map(|s| s.pull_request))
+    .bind(record.source.map(|s| &s.path))
+    .bind(record.idempotency_key)
+    .bind(record.fingerprint)
+    .bind(input.generate.as_ref().map(|g| g.provider))
+    .bind(input.generate.as_ref().map(|g| &g.model))
+    .bind(generated.map(|g| &g.response.text))
+    .bind(generated.and_then(|g| g.response.prompt_tokens))
+    .bind(generated.and_then(|g| g.response.completion_tokens))
+    .bind(generated.map(|g| i32::try_from(g.latency.as_millis()).unwrap_or(i32::MAX)))
+    .bind(record.parent_audit_id)
+    .bind(record.attempt_number)
+    .bind(outcome.edition)
+    .bind(&outcome.compile_command)
+    .bind(token_count(input.prompt.trim()))
+    .bind(token_count(code))
+    .bind(
+        input
+            .edition
+            .is_none()
+            .then(|| auditor::detect_edition_from_code(code)),
+    )
+    .bind(auditor::detect_license_header(code))
+    .bind(outcome.vulnerability_count)
+    .bind(&outcome.advisory_note)
+    .bind(outcome.license_violation_count)
+    .bind(outcome.security_advisories.as_ref().map(Json))
+    .bind(outcome.has_security_issues)
+    .fetch_one(executor)
+    .await
+}
+
+/// Retrieves the ancestry of an audit by walking its `parent_audit_id` links.
+///
+/// Unlike `get_audit_chain`, only the audits the given one derives from are returned,
+/// not the other attempts derived from them.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `id` - The UUID of the audit.
+///
+/// # Returns
+///
+/// * `Ok(Vec<AiAudit>)` - The audits from the original one to the given one, which is
+///   the only one for an audit without a parent.
+/// * `Err(AppError::NotFound)` - If no such audit exists.
+/// * `Err(AppError::Sqlx)` - If the database query fails.
+#[tracing::instrument(skip(pool))]
+pub async fn get_audit_lineage(pool: &PgPool, id: Uuid) -> Result<Vec<AiAudit>, AppError> {
+    let lineage = sqlx::query_as::<_, AiAudit>(&format!(
+        r#"
+        WITH RECURSIVE lineage AS (
+            SELECT id, parent_audit_id, 0 AS depth FROM ai_audits WHERE id = $1
+            UNION ALL
+            SELECT a.id, a.parent_audit_id, l.depth + 1
+            FROM ai_audits a JOIN lineage l ON a.id = l.parent_audit_id
+        )
+        SELECT {} FROM ai_audits
+        WHERE id IN (SELECT id FROM lineage)
+        ORDER BY (SELECT depth FROM lineage WHERE lineage.id = ai_audits.id) DESC
+        "#,
+        AUDIT_COLUMNS
+    ))
+    .bind(id)
+    .fetch_all(pool)
+    .await?;
+
+    if lineage.is_empty() {
+        return Err(AppError::NotFound(format!("Audit {} not found", id)));
+    }
+    Ok(lineage)
+}
+
+/// Retrieves the chain of fix attempts an audit belongs to.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `id` - The UUID of any audit of the chain.
+///
+/// # Returns
+///
+/// * `Ok(Vec<AiAudit>)` - The audits of the chain, from the original audit to the last
+///   fix attempt.
+/// * `Err(AppError::NotFound)` - If no such audit exists.
+/// * `Err(AppError::Sqlx)` - If the database query fails.
+#[tracing::instrument(skip(pool))]
+pub async fn get_audit_chain(pool: &PgPool, id: Uuid) -> Result<Vec<AiAudit>, AppError> {
+    let chain = sqlx::query_as::<_, AiAudit>(&format!(
+        r#"
+        WITH RECURSIVE ancestors AS (
+            SELECT id, parent_audit_id FROM ai_audits WHERE id = $1
+            UNION ALL
+            SELECT a.id, a.parent_audit_id
+            FROM ai_audits a JOIN ancestors c ON a.id = c.parent_audit_id
+        ),
+        chain AS (
+            SELECT id FROM ancestors WHERE parent_audit_id IS NULL
+            UNION ALL
+            SELECT a.id FROM ai_audits a JOIN chain c ON a.parent_audit_id = c.id
+        )
+        SELECT {} FROM ai_audits
+        WHERE id IN (SELECT id FROM chain)
+        ORDER BY attempt_number
+        "#,
+        AUDIT_COLUMNS
+    ))
+    .bind(id)
+    .fetch_all(pool)
+    .await?;
+
+    if chain.is_empty() {
+        return Err(AppError::NotFound(format!("Audit {} not found", id)));
+    }
+    Ok(chain)
+}
+
+/// Counts the audits, optionally restricted to those carrying a tag, and how many of
+/// them are valid.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `tag` - The tag the audits must carry, or `None` for every audit.
+///
+/// # Returns
+///
+/// * `Ok(ValidityCounts)` - The counts, both zero if no audit matches.
+/// * `Err(AppError::Sqlx)` - If the database query fails.
+#[tracing::instrument(skip(pool))]
+pub async fn get_validity_counts(
+    pool: &PgPool,
+    tag: Option<&str>,
+) -> Result<ValidityCounts, AppError> {
+    let counts = sqlx::query_as!(
+        ValidityCounts,
+        r#"SELECT
+            COUNT(*) AS "total!",
+            COUNT(*) FILTER (WHERE is_valid = true) AS "valid!"
+         FROM ai_audits
+         WHERE $1::text IS NULL OR tags @> ARRAY[$1::text]"#,
+        tag
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(counts)
+}
+
+/// Retrieves the number of audits and when one was last updated.
+///
+/// Any audit created, updated or deleted changes the result, which makes it a cheap
+/// version of the statistics computed over all audits.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+///
+/// # Returns
+///
+/// * `Ok((i64, Option<DateTime<Utc>>))` - The number of audits and the latest
+///   `updated_at`, or `None` if there are no audits.
+/// * `Err(AppError::Sqlx)` - If the database query fails.
+#[tracing::instrument(skip(pool))]
+pub async fn get_audits_version(pool: &PgPool) -> Result<(i64, Option<DateTime<Utc>>), AppError> {
+    let version = retry_transient("get_audits_version", || {
+        sqlx::query!(
+            r#"SELECT COUNT(*) AS "count!", MAX(updated_at) AS last_updated_at FROM ai_audits"#
+        )
+        .fetch_one(pool)
+    })
+    .await?;
+    Ok((version.count, version.last_updated_at))
+}
+
+/// Groups the audits by a property, counting the audits and valid audits of each group.
+///
+/// Days are listed most recent first; other groups are listed largest first.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `group_by` - The property to group the audits by.
+/// * `limit` - The maximum number of buckets to return (1 to 1000).
+/// * `offset` - The number of buckets to skip, for pagination.
+/// * `edition` - The edition the grouped audits are compiled with, or `None` for all.
+///
+/// # Returns
+///
+/// * `Ok(Vec<StatsBucket>)` - The buckets of the requested page.
+/// * `Err(AppError::Validation)` - If `limit` or `offset` is out of range.
+/// * `Err(AppError::Sqlx)` - If the database query fails.
+#[tracing::instrument(skip(pool))]
+pub async fn get_grouped_stats(
+    pool: &PgPool,
+    group_by: StatsGroupBy,
+    limit: i64,
+    offset: i64,
+    edition: Option<Edition>,
+) -> Result<Vec<StatsBucket>, AppError> {
+    if !(1..=1000).contains(&limit) {
+        return Err(AppError::Validation(
+            "limit must be between 1 and 1000".to_string(),
+        ));
+    }
+    if offset < 0 {
+        return Err(AppError::Validation(
+            "offset must not be negative".to_string(),
+        ));
+    }
+
+    let (key, source, order) = match group_by {
+        StatsGroupBy::Day => (
+            "to_char(created_at AT TIME ZONE 'UTC', 'YYYY-MM-DD')",
+            "ai_audits",
+            "key DESC",
+        ),
+        StatsGroupBy::Model => ("generation_model", "ai_audits", "total_audits DESC, key"),
+        StatsGroupBy::Tag => (
+            "tag",
+            "ai_audits CROSS JOIN LATERAL unnest(tags) AS tag",
+            "total_audits DESC, key",
+        ),
+        StatsGroupBy::ErrorCode => ("primary_error_code", "ai_audits", "total_audits DESC, key"),
+    };
+    let query = format!(
+        "SELECT
+            {key} AS key,
+            COUNT(*) AS total_audits,
+            COUNT(*) FILTER (WHERE is_valid = true) AS valid_audits,
+            (COUNT(*) FILTER (WHERE is_valid = true))::float8 / COUNT(*) AS success_rate
+         FROM {source}
+         WHERE $3::text IS NULL OR edition = $3
+         GROUP BY 1
+         ORDER BY {order}
+         LIMIT $1 OFFSET $2"
+    );
+    let buckets = sqlx::query_as::<_, StatsBucket>(&query)
+        .bind(limit)
+        .bind(offset)
+        .bind(edition)
+        .fetch_all(pool)
+        .await?;
+    Ok(buckets)
+}
+
+/// The columns of `audit_stats_summary` holding running totals.
+const SUMMARY_COLUMNS: &str = "total_audits, valid_audits, total_code_lines, total_code_chars, \
+     max_code_line_count, scored_audits, total_quality_score, timed_audits, total_compilation_ms, \
+     total_prompt_tokens, total_code_tokens, total_vulnerabilities";
+
+/// Computes the values of `SUMMARY_COLUMNS`, in order, over a set of audit rows.
+const SUMMARY_AGGREGATES: &str = "COUNT(*) AS total_audits, \
+     COUNT(*) FILTER (WHERE is_valid = true) AS valid_audits, \
+     COALESCE(SUM(code_line_count), 0)::bigint AS total_code_lines, \
+     COALESCE(SUM(code_char_count), 0)::bigint AS total_code_chars, \
+     COALESCE(MAX(code_line_count), 0)::bigint AS max_code_line_count, \
+     COUNT(metrics->'quality_score') AS scored_audits, \
+     COALESCE(SUM((metrics->>'quality_score')::float8), 0)::float8 AS total_quality_score, \
+     COUNT(compilation_duration_ms) AS timed_audits, \
+     COALESCE(SUM(compilation_duration_ms), 0)::bigint AS total_compilation_ms, \
+     COALESCE(SUM(prompt_token_count), 0)::bigint AS total_prompt_tokens, \
+     COALESCE(SUM(code_token_count), 0)::bigint AS total_code_tokens, \
+     COALESCE(SUM(vulnerability_count), 0)::bigint AS total_vulnerabilities, \
+     MAX(updated_at) AS last_updated_at";
+
+/// Adds the audits returned by an `inserted` CTE to `audit_stats_summary`, as a further
+/// CTE of the same statement, so that the totals are updated atomically with the rows.
+fn count_inserted_audits() -> String {
+    format!(
+        "counted AS (
+            UPDATE audit_stats_summary AS s SET
+                total_audits = s.total_audits + i.total_audits,
+                valid_audits = s.valid_audits + i.valid_audits,
+                total_code_lines = s.total_code_lines + i.total_code_lines,
+                total_code_chars = s.total_code_chars + i.total_code_chars,
+                max_code_line_count = GREATEST(s.max_code_line_count, i.max_code_line_count),
+                scored_audits = s.scored_audits + i.scored_audits,
+                total_quality_score = s.total_quality_score + i.total_quality_score,
+                timed_audits = s.timed_audits + i.timed_audits,
+                total_compilation_ms = s.total_compilation_ms + i.total_compilation_ms,
+                total_prompt_tokens = s.total_prompt_tokens + i.total_prompt_tokens,
+                total_code_tokens = s.total_code_tokens + i.total_code_tokens,
+                total_vulnerabilities = s.total_vulnerabilities + i.total_vulnerabilities,
+                updated_at = GREATEST(s.updated_at, NOW(), i.last_updated_at)
+            FROM (SELECT {} FROM inserted) AS i
+        )",
+        SUMMARY_AGGREGATES
+    )
+}
+
+/// The running totals of all audits, kept in `audit_stats_summary`.
+#[derive(sqlx::FromRow)]
+struct StatsSummary {
+    total_audits: i64,
+    valid_audits: i64,
+    total_code_lines: i64,
+    total_code_chars: i64,
+    max_code_line_count: i64,
+    scored_audits: i64,
+    total_quality_score: f64,
+    timed_audits: i64,
+    total_compilation_ms: i64,
+    total_prompt_tokens: i64,
+    total_code_tokens: i64,
+    total_vulnerabilities: i64,
+    /// Whether an audit was written after the totals were last updated, meaning some
+    /// write did not update them.
+    #[sqlx(default)]
+    drifted: bool,
+}
+
+/// Recomputes `audit_stats_summary` from the audits.
+///
+/// Writers update the totals with increments, so the table is locked against them while
+/// the audits are aggregated: writes committed before the lock are counted by the
+/// aggregate, and writes blocked by it are added to the new totals afterwards.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+///
+/// # Returns
+///
+/// * `Ok(StatsSummary)` - The recomputed totals.
+/// * `Err(AppError::Sqlx)` - If a database query fails.
+async fn recompute_stats_summary(pool: &PgPool) -> Result<StatsSummary, AppError> {
+    let mut tx = pool.begin().await?;
+    let summary = write_stats_summary(&mut tx).await?;
+    tx.commit().await?;
+    tracing::info!(
+        total_audits = summary.total_audits,
+        "Recomputed the audit statistics."
+    );
+    Ok(summary)
+}
+
+/// Locks `audit_stats_summary` and overwrites it with the totals of the audits, in the
+/// transaction of the caller (see `recompute_stats_summary`).
+///
+/// # Arguments
+///
+/// * `conn` - The connection of the transaction holding the lock until it ends.
+///
+/// # Returns
+///
+/// * `Ok(StatsSummary)` - The recomputed totals.
+/// * `Err(AppError::Sqlx)` - If a database query fails.
+async fn write_stats_summary(conn: &mut PgConnection) -> Result<StatsSummary, AppError> {
+    sqlx::query!("LOCK TABLE audit_stats_summary IN SHARE ROW EXCLUSIVE MODE")
+        .execute(&mut *conn)
+        .await?;
+    let summary = sqlx::query_as::<_, StatsSummary>(&format!(
+        r#"
+        INSERT INTO audit_stats_summary (id, {columns}, updated_at)
+        SELECT TRUE, {columns}, GREATEST(clock_timestamp(), last_updated_at)
+        FROM (SELECT {aggregates} FROM ai_audits) AS totals
+        ON CONFLICT (id) DO UPDATE SET
+            total_audits = EXCLUDED.total_audits,
+            valid_audits = EXCLUDED.valid_audits,
+            total_code_lines = EXCLUDED.total_code_lines,
+            total_code_chars = EXCLUDED.total_code_chars,
+            max_code_line_count = EXCLUDED.max_code_line_count,
+            scored_audits = EXCLUDED.scored_audits,
+            total_quality_score = EXCLUDED.total_quality_score,
+            timed_audits = EXCLUDED.timed_audits,
+            total_compilation_ms = EXCLUDED.total_compilation_ms,
+            total_prompt_tokens = EXCLUDED.total_prompt_tokens,
+            total_code_tokens = EXCLUDED.total_code_tokens,
+            total_vulnerabilities = EXCLUDED.total_vulnerabilities,
+            updated_at = EXCLUDED.updated_at
+        RETURNING {columns}
+        "#,
+        columns = SUMMARY_COLUMNS,
+        aggregates = SUMMARY_AGGREGATES
+    ))
+    .fetch_one(conn)
+    .await?;
+    Ok(summary)
+}
+
+/// Recomputes the running totals behind the statistics from the audits, and returns
+/// the statistics.
+///
+/// The totals are normally kept up to date as audits are written; this repairs them if
+/// they were changed by hand or lost.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+///
+/// # Returns
+///
+/// * `Ok(AuditStats)` - The statistics computed from the new totals.
+/// * `Err(AppError::Sqlx)` - If a database query fails.
+#[tracing::instrument(skip(pool))]
+pub async fn recalculate_audit_stats(pool: &PgPool) -> Result<AuditStats, AppError> {
+    recompute_stats_summary(pool).await?;
+    get_audit_stats(pool, DEFAULT_DAILY_COUNT_DAYS, None).await
+}
+
+/// Calculates and retrieves statistics about all AI audits, or those compiled with an
+/// edition.
+///
+/// The counts and averages of all audits are derived from the running totals in
+/// `audit_stats_summary` instead of aggregating every audit. The totals are recomputed
+/// first if they are missing or have drifted from the audits. Those of the audits of an
+/// edition are aggregated from the audits.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `days` - The number of days covered by the daily counts, including today.
+/// * `edition` - The edition the counted audits are compiled with, or `None` for all.
+///
+/// # Returns
+///
+/// * `Ok(AuditStats)` - The calculated statistics.
+/// * `Err(AppError::Validation)` - If `days` is out of range.
+/// * `Err(AppError::Sqlx)` - If any database query fails.
+#[tracing::instrument(skip(pool))]
+pub async fn get_audit_stats(
+    pool: &PgPool,
+    days: i32,
+    edition: Option<Edition>,
+) -> Result<AuditStats, AppError> {
+    let daily_counts = get_daily_audit_counts(pool, days, edition).await?;
+
+    let summary = match edition {
+        // The running totals cover all audits.
+        Some(edition) => {
+            sqlx::query_as::<_, StatsSummary>(&format!(
+                "SELECT {} FROM ai_audits WHERE edition = $1",
+                SUMMARY_AGGREGATES
+            ))
+            .bind(edition)
+            .fetch_one(pool)
+            .await?
+        }
+        None => {
+            // Both are read in one statement, so that they come from the same snapshot.
+            let summary = sqlx::query_as::<_, StatsSummary>(&format!(
+                "SELECT {}, COALESCE((SELECT MAX(updated_at) FROM ai_audits) > updated_at, false) AS drifted
+                 FROM audit_stats_summary",
+                SUMMARY_COLUMNS
+            ))
+            .fetch_optional(pool)
+            .await?;
+            match summary {
+                Some(summary) if !summary.drifted => summary,
+                _ => {
+                    tracing::warn!(
+                        "The audit statistics summary is missing or stale; recomputing it."
+                    );
+                    recompute_stats_summary(pool).await?
+                }
+            }
+        }
+    };
+    let edition = edition.map(Edition::as_str);
+
+    let average = |total: f64, count: i64| if count > 0 { total / count as f64 } else { 0.0 };
+    let (total_audits, valid_audits) = (summary.total_audits, summary.valid_audits);
+    let average_code_line_count = average(summary.total_code_lines as f64, total_audits);
+    let average_code_char_count = average(summary.total_code_chars as f64, total_audits);
+    let max_code_line_count = summary.max_code_line_count;
+    let average_quality_score = average(summary.total_quality_score, summary.scored_audits);
+    let average_compilation_duration_ms =
+        average(summary.total_compilation_ms as f64, summary.timed_audits);
+    let total_tokens_processed = summary.total_prompt_tokens + summary.total_code_tokens;
+    let average_prompt_tokens = average(summary.total_prompt_tokens as f64, total_audits);
+
+    let invalid_audits = total_audits - valid_audits;
+    let validation_rate = if total_audits > 0 {
+        valid_audits as f64 / total_audits as f64
+    } else {
+        0.0
+    };
+
+    // Get the most common compilation errors.
+    let common_errors = sqlx::query_as!(
+        CommonError,
+        r#"
+        SELECT
+            LEFT(compilation_error, 200) as "error_message!",
+            COUNT(*) as "frequency!"
+        FROM ai_audits
+        WHERE compilation_error IS NOT NULL
+          AND compilation_error != ''
+          AND ($1::text IS NULL OR edition = $1)
+        GROUP BY LEFT(compilation_error, 200)
+        ORDER BY COUNT(*) DESC
+        LIMIT 10
+        "#,
+        edition
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let by_target = sqlx::query_as!(
+        TargetStats,
+        r#"
+        SELECT
+            target,
+            COUNT(*) as "total_audits!",
+            COUNT(*) FILTER (WHERE is_valid = true) as "valid_audits!"
+        FROM ai_audits
+        WHERE $1::text IS NULL OR edition = $1
+        GROUP BY target
+        ORDER BY COUNT(*) DESC
+        "#,
+        edition
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let by_edition = sqlx::query_as!(
+        EditionStats,
+        r#"
+        SELECT
+            COALESCE(edition, 'unknown') as "edition!",
+            COUNT(*) as "count!",
+            COUNT(*) FILTER (WHERE is_valid = true) as "valid_count!",
+            (COUNT(*) FILTER (WHERE is_valid = true))::float8 / COUNT(*) as "pass_rate!"
+        FROM ai_audits
+        WHERE $1::text IS NULL OR edition = $1
+        GROUP BY edition
+        ORDER BY COUNT(*) DESC, edition
+        "#,
+        edition
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let license_distribution = sqlx::query_as!(
+        LicenseStat,
+        r#"
+        SELECT
+            COALESCE(detected_license, 'none') as "license!",
+            COUNT(*) as "count!"
+        FROM ai_audits
+        WHERE $1::text IS NULL OR edition = $1
+        GROUP BY detected_license
+        ORDER BY COUNT(*) DESC, detected_license
+        "#,
+        edition
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let hygiene = sqlx::query_as!(
+        HygieneStats,
+        r#"
+        SELECT
+            COUNT(*) as "analyzed_audits!",
+            COALESCE(AVG(((hygiene_report->>'debug_output_count')::int > 0)::int), 0)::float8
+                as "debug_output_rate!",
+            COALESCE(AVG(((hygiene_report->>'todo_marker_count')::int > 0)::int), 0)::float8
+                as "todo_marker_rate!",
+            COALESCE(AVG(((hygiene_report->>'placeholder_count')::int > 0)::int), 0)::float8
+                as "placeholder_rate!",
+            COALESCE(AVG((hygiene_report->>'hygiene_score')::float8), 0)::float8
+                as "average_hygiene_score!"
+        FROM ai_audits
+        WHERE hygiene_report IS NOT NULL
+          AND ($1::text IS NULL OR edition = $1)
+        "#,
+        edition
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(AuditStats {
+        total_audits,
+        valid_audits,
+        invalid_audits,
+        validation_rate,
+        average_code_line_count,
+        average_code_char_count,
+        max_code_line_count,
+        average_quality_score,
+        average_compilation_duration_ms,
+        total_tokens_processed,
+        average_prompt_tokens,
+        vulnerability_count: summary.total_vulnerabilities,
+        common_errors,
+        by_target,
+        by_edition,
+        license_distribution,
+        hygiene,
+        daily_counts,
+    })
+}
+
+/// The number of days covered by the daily counts of the statistics by default.
+pub const DEFAULT_DAILY_COUNT_DAYS: i32 = 30;
+
+/// The maximum number of days covered by the daily counts of the statistics.
+pub const MAX_DAILY_COUNT_DAYS: i32 = 366;
+
+/// Counts the audits created on each of the last days.
+///
+/// Days are UTC days, like those of the statistics grouped by day. Every day of the
+/// range is listed, with zero counts if no audit was created that day, so that the
+/// series can be plotted as is.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `days` - The number of days, including today.
+/// * `edition` - The edition the counted audits are compiled with, or `None` for all.
+///
+/// # Returns
+///
+/// * `Ok(Vec<DailyCount>)` - The counts of each day, oldest first.
+/// * `Err(AppError::Validation)` - If `days` is not between 1 and `MAX_DAILY_COUNT_DAYS`.
+/// * `Err(AppError::Sqlx)` - If the database query fails.
+#[tracing::instrument(skip(pool))]
+pub async fn get_daily_audit_counts(
+    pool: &PgPool,
+    days: i32,
+    edition: Option<Edition>,
+) -> Result<Vec<DailyCount>, AppError> {
+    if !(1..=MAX_DAILY_COUNT_DAYS).contains(&days) {
+        return Err(AppError::Validation(format!(
+            "days must be between 1 and {}",
+            MAX_DAILY_COUNT_DAYS
+        )));
+    }
+    Ok(sqlx::query_as!(
+        DailyCount,
+        r#"
+        WITH counts AS (
+            SELECT
+                DATE_TRUNC('day', created_at AT TIME ZONE 'UTC')::date AS day,
+                COUNT(*) AS count,
+                COUNT(*) FILTER (WHERE is_valid) AS valid_count
+            FROM ai_audits
+            WHERE created_at >= DATE_TRUNC('day', NOW() AT TIME ZONE 'UTC') AT TIME ZONE 'UTC'
+                - make_interval(days => $1 - 1)
+              AND ($2::text IS NULL OR edition = $2)
+            GROUP BY 1
+        )
+        SELECT
+            d.day::date AS "date!",
+            COALESCE(c.count, 0) AS "count!",
+            COALESCE(c.valid_count, 0) AS "valid_count!"
+        FROM generate_series(
+            DATE_TRUNC('day', NOW() AT TIME ZONE 'UTC') - make_interval(days => $1 - 1),
+            DATE_TRUNC('day', NOW() AT TIME ZONE 'UTC'),
+            INTERVAL '1 day'
+        ) AS d(day)
+        LEFT JOIN counts c ON c.day = d.day::date
+        ORDER BY d.day
+        "#,
+        days,
+        edition.map(Edition::as_str)
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Retrieves how often each error category was the dominant error of an audit.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+///
+/// # Returns
+///
+/// * `Ok(Vec<CategoryFrequency>)` - The error categories, most frequent first.
+/// * `Err(AppError::Sqlx)` - If a database query fails.
+#[tracing::instrument(skip(pool))]
+pub async fn get_category_frequencies(pool: &PgPool) -> Result<Vec<CategoryFrequency>, AppError> {
+    sqlx::query_as!(
+        CategoryFrequency,
+        r#"
+        SELECT primary_error_category as "category!", COUNT(*) as "count!"
+        FROM ai_audits
+        WHERE primary_error_category IS NOT NULL
+        GROUP BY primary_error_category
+        ORDER BY COUNT(*) DESC, primary_error_category
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(AppError::from)
+}
+
+/// Retrieves the rustc error codes that most often cause compilation to fail.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `limit` - The maximum number of error codes to return.
+///
+/// # Returns
+///
+/// * `Ok(Vec<ErrorCodeFrequency>)` - The error codes, most frequent first.
+/// * `Err(AppError::Sqlx)` - If a database query fails.
+#[tracing::instrument(skip(pool))]
+pub async fn get_top_error_codes(
+    pool: &PgPool,
+    limit: i64,
+) -> Result<Vec<ErrorCodeFrequency>, AppError> {
+    sqlx::query_as!(
+        ErrorCodeFrequency,
+        r#"
+        SELECT primary_error_code as "code!", COUNT(*) as "count!"
+        FROM ai_audits
+        WHERE primary_error_code IS NOT NULL
+        GROUP BY primary_error_code
+        ORDER BY COUNT(*) DESC, primary_error_code
+        LIMIT $1
+        "#,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(AppError::from)
+}
+
+/// Adds a reviewer comment to an existing audit.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `audit_id` - The UUID of the audit being commented on.
+/// * `author` - The name of the reviewer.
+/// * `body` - The content of the comment.
+///
+/// # Returns
+///
+/// * `Ok(AuditComment)` - The newly created comment.
+/// * `Err(AppError::NotFound)` - If the audit does not exist.
+/// * `Err(AppError::Sqlx)` - If the database insertion fails.
+#[tracing::instrument(skip(pool, body))]
+pub async fn add_comment(
+    pool: &PgPool,
+    audit_id: Uuid,
+    author: &str,
+    body: &str,
+) -> Result<AuditComment, AppError> {
+    sqlx::query_as!(
+        AuditComment,
+        r#"
+        INSERT INTO audit_comments (audit_id, author, body)
+        SELECT $1, $2, $3
+        WHERE EXISTS (SELECT 1 FROM ai_audits WHERE id = $1)
+        RETURNING id, audit_id, author, body, created_at
+        "#,
+        audit_id,
+        author,
+        body
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("Audit {} not found", audit_id)))
+}
+
+/// Retrieves the comments of an audit, oldest first.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `audit_id` - The UUID of the audit whose comments to retrieve.
+///
+/// # Returns
+///
+/// * `Ok(Vec<AuditComment>)` - The comments of the audit (empty if there are none).
+/// * `Err(AppError::Sqlx)` - If a database query fails.
+#[tracing::instrument(skip(pool))]
+pub async fn list_comments(pool: &PgPool, audit_id: Uuid) -> Result<Vec<AuditComment>, AppError> {
+    sqlx::query_as!(
+        AuditComment,
+        "SELECT id, audit_id, author, body, created_at FROM audit_comments WHERE audit_id = $1 ORDER BY created_at",
+        audit_id
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(AppError::from)
+}
+
+/// Deletes a comment from an audit.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `audit_id` - The UUID of the audit the comment belongs to.
+/// * `comment_id` - The UUID of the comment to delete.
+/// * `author` - The user the comment must have been written by, or `None` to delete the
+///   comment of any user (for administrators).
+///
+/// # Returns
+///
+/// * `Ok(())` - If the comment was deleted.
+/// * `Err(AppError::NotFound)` - If no such comment exists on the audit.
+/// * `Err(AppError::Forbidden)` - If the comment was written by another user.
+/// * `Err(AppError::Sqlx)` - If the database deletion fails.
+#[tracing::instrument(skip(pool))]
+pub async fn delete_comment(
+    pool: &PgPool,
+    audit_id: Uuid,
+    comment_id: Uuid,
+    author: Option<&str>,
+) -> Result<(), AppError> {
+    let result = sqlx::query!(
+        "DELETE FROM audit_comments
+         WHERE id = $1 AND audit_id = $2 AND ($3::text IS NULL OR author = $3)",
+        comment_id,
+        audit_id,
+        author
+    )
+    .execute(pool)
+    .await?;
+    if result.rows_affected() > 0 {
+        return Ok(());
+    }
+
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM audit_comments WHERE id = $1 AND audit_id = $2) AS "exists!""#,
+        comment_id,
+        audit_id
+    )
+    .fetch_one(pool)
+    .await?;
+    if exists {
+        Err(AppError::Forbidden(
+            "Only the author of a comment or an administrator can delete it".to_string(),
+        ))
+    } else {
+        Err(AppError::NotFound(format!(
+            "Comment {} not found on audit {}",
+            comment_id, audit_id
+        )))
+    }
+}
+
+/// The maximum length of the name of a reviewer rating an audit.
+const MAX_RATER_LEN: usize = 64;
+
+/// Rates the quality of an existing audit, replacing the earlier rating of the same
+/// reviewer.
+///
+/// The name of the reviewer and the comment are trimmed; a blank comment is dropped.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `audit_id` - The UUID of the audit being rated.
+/// * `rater` - The name of the reviewer.
+/// * `score` - The score, from 1 to 5.
+/// * `comment` - The reason for the score, if any.
+///
+/// # Returns
+///
+/// * `Ok(AuditRating)` - The rating.
+/// * `Err(AppError::Validation)` - If the name of the reviewer is blank or longer than
+///   64 characters, or the score is not between 1 and 5.
+/// * `Err(AppError::NotFound)` - If the audit does not exist.
+/// * `Err(AppError::Sqlx)` - If the database insertion fails.
+#[tracing::instrument(skip(pool, comment))]
+pub async fn rate_audit(
+    pool: &PgPool,
+    audit_id: Uuid,
+    rater: &str,
+    score: i16,
+    comment: Option<&str>,
+) -> Result<AuditRating, AppError> {
+    let rater = rater.trim();
+    if rater.is_empty() || rater.chars().count() > MAX_RATER_LEN {
+        return Err(AppError::Validation(format!(
+            "rater must be between 1 and {} characters",
+            MAX_RATER_LEN
+        )));
+    }
+    if !(1..=5).contains(&score) {
+        return Err(AppError::Validation(
+            "score must be between 1 and 5".to_string(),
+        ));
+    }
+    let comment = comment.map(str::trim).filter(|c| !c.is_empty());
+    sqlx::query_as!(
+        AuditRating,
+        r#"
+        INSERT INTO audit_ratings (audit_id, rater, score, comment)
+        SELECT $1, $2, $3, $4
+        WHERE EXISTS (SELECT 1 FROM ai_audits WHERE id = $1)
+        ON CONFLICT (audit_id, rater)
+            DO UPDATE SET score = EXCLUDED.score, comment = EXCLUDED.comment, rated_at = NOW()
+        RETURNING audit_id, rater, score, comment, rated_at
+        "#,
+        audit_id,
+        rater,
+        score,
+        comment
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("Audit {} not found", audit_id)))
+}
+
+/// Retrieves the ratings of an audit, most recent first.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `audit_id` - The UUID of the audit whose ratings to retrieve.
+///
+/// # Returns
+///
+/// * `Ok(Vec<AuditRating>)` - The ratings of the audit (empty if there are none).
+/// * `Err(AppError::Sqlx)` - If a database query fails.
+#[tracing::instrument(skip(pool))]
+pub async fn get_audit_ratings(
+    pool: &PgPool,
+    audit_id: Uuid,
+) -> Result<Vec<AuditRating>, AppError> {
+    sqlx::query_as!(
+        AuditRating,
+        "SELECT audit_id, rater, score, comment, rated_at FROM audit_ratings WHERE audit_id = $1 ORDER BY rated_at DESC",
+        audit_id
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(AppError::from)
+}
+
+/// Records the artifacts uploaded for an audit, replacing those recorded before.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `audit_id` - The UUID of the audit.
+/// * `keys` - The keys of the uploaded files in the artifact store.
+/// * `error` - Why some files could not be produced or uploaded, if any.
+///
+/// # Returns
+///
+/// * `Ok(())` - If the artifacts were recorded, or the audit was deleted meanwhile.
+/// * `Err(AppError::Sqlx)` - If the query fails.
+pub async fn record_audit_artifacts(
+    pool: &PgPool,
+    audit_id: Uuid,
+    keys: &[String],
+    error: Option<&str>,
+) -> Result<(), AppError> {
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_artifacts (audit_id, keys, error)
+        SELECT id, $2, $3 FROM ai_audits WHERE id = $1
+        ON CONFLICT (audit_id) DO UPDATE
+            SET keys = EXCLUDED.keys, error = EXCLUDED.error, uploaded_at = NOW()
+        "#,
+        audit_id,
+        keys,
+        error
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Retrieves the artifacts of an audit, with the URLs they can be downloaded from.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `settings` - The artifact store signing the URLs.
+/// * `audit_id` - The UUID of the audit whose artifacts to retrieve.
+///
+/// # Returns
+///
+/// * `Ok(AuditArtifacts)` - The artifacts of the audit (none while they are uploaded, or
+///   if the audit was not compiled or artifacts are not kept).
+/// * `Err(AppError::NotFound)` - If no audit has this ID.
+/// * `Err(AppError::Upstream)` - If a URL cannot be signed.
+/// * `Err(AppError::Sqlx)` - If the query fails.
+#[tracing::instrument(skip(pool, settings))]
+pub async fn get_audit_artifacts(
+    pool: &PgPool,
+    settings: &ArtifactSettings,
+    audit_id: Uuid,
+) -> Result<AuditArtifacts, AppError> {
+    let record = sqlx::query!(
+        r#"
+        SELECT x.keys AS "keys?", x.error, x.uploaded_at AS "uploaded_at?"
+        FROM ai_audits a LEFT JOIN audit_artifacts x ON x.audit_id = a.id
+        WHERE a.id = $1
+        "#,
+        audit_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("Audit {} not found", audit_id)))?;
+    settings
+        .links(
+            audit_id,
+            &record.keys.unwrap_or_default(),
+            record.error,
+            record.uploaded_at,
+        )
+        .await
+}
+
+/// Registers a webhook notified when audits complete.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `input` - The URL, secret and events of the webhook.
+///
+/// # Returns
+///
+/// * `Ok(Webhook)` - The newly registered webhook.
+/// * `Err(AppError::Validation)` - If the URL is not an `http` or `https` URL, the secret
+///   is empty or an event is unknown.
+/// * `Err(AppError::Sqlx)` - If the database insertion fails.
+#[tracing::instrument(skip(pool, input), fields(url = %input.url))]
+pub async fn create_webhook(
+    pool: &PgPool,
+    input: &CreateWebhookRequest,
+) -> Result<Webhook, AppError> {
+    let url = reqwest::Url::parse(&input.url)
+        .map_err(|e| AppError::Validation(format!("Invalid webhook URL: {}", e)))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(AppError::Validation(
+            "Webhook URLs must use http or https".to_string(),
+        ));
+    }
+    if input.secret.is_empty() {
+        return Err(AppError::Validation(
+            "Webhook secret must not be empty".to_string(),
+        ));
+    }
+    if let Some(event) = input
+        .events
+        .iter()
+        .find(|e| !webhooks::EVENTS.contains(&e.as_str()))
+    {
+        return Err(AppError::Validation(format!(
+            "Unknown webhook event '{}' (expected one of: {})",
+            event,
+            webhooks::EVENTS.join(", ")
+        )));
+    }
+    let events: Vec<String> = if input.events.is_empty() {
+        webhooks::EVENTS.iter().map(|e| e.to_string()).collect()
+    } else {
+        input
+            .events
+            .iter()
+            .cloned()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    };
+
+    sqlx::query_as!(
+        Webhook,
+        r#"
+        INSERT INTO webhooks (url, secret, events, active)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id, url, events, active, created_at
+        "#,
+        url.as_str(),
+        input.secret,
+        &events,
+        input.active.unwrap_or(true)
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(AppError::from)
+}
+
+/// Retrieves every registered webhook, oldest first.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+///
+/// # Returns
+///
+/// * `Ok(Vec<Webhook>)` - The webhooks (empty if there are none).
+/// * `Err(AppError::Sqlx)` - If a database query fails.
+#[tracing::instrument(skip(pool))]
+pub async fn list_webhooks(pool: &PgPool) -> Result<Vec<Webhook>, AppError> {
+    sqlx::query_as!(
+        Webhook,
+        "SELECT id, url, events, active, created_at FROM webhooks ORDER BY created_at"
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(AppError::from)
+}
+
+/// Removes a webhook.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `id` - The UUID of the webhook to remove.
+///
+/// # Returns
+///
+/// * `Ok(())` - If the webhook was removed.
+/// * `Err(AppError::NotFound)` - If no such webhook exists.
+/// * `Err(AppError::Sqlx)` - If the database deletion fails.
+#[tracing::instrument(skip(pool))]
+pub async fn delete_webhook(pool: &PgPool, id: Uuid) -> Result<(), AppError> {
+    let result = sqlx::query!("DELETE FROM webhooks WHERE id = $1", id)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound(format!("Webhook {} not found", id)));
+    }
+    Ok(())
+}
+
+/// The maximum length of the name of an audit profile, in characters.
+const MAX_PROFILE_NAME_LEN: usize = 64;
+
+/// The columns selected when loading an `AuditProfile`.
+const PROFILE_COLUMNS: &str = "name, enabled_rules, weights, created_at, updated_at";
+
+/// Retrieves every audit profile, sorted by name.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+///
+/// # Returns
+///
+/// * `Ok(Vec<AuditProfile>)` - The profiles.
+/// * `Err(AppError::Sqlx)` - If the database query fails.
+pub async fn list_audit_profiles(pool: &PgPool) -> Result<Vec<AuditProfile>, AppError> {
+    sqlx::query_as::<_, AuditProfile>(&format!(
+        "SELECT {} FROM audit_profiles ORDER BY name",
+        PROFILE_COLUMNS
+    ))
+    .fetch_all(pool)
+    .await
+    .map_err(AppError::from)
+}
+
+/// Loads the audit profile applied to a new audit.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `policy` - The server-wide audit settings, naming the default profile.
+/// * `name` - The profile chosen by the request, if any.
+///
+/// # Returns
+///
+/// * `Ok(AuditProfile)` - The chosen profile, or the default one.
+/// * `Err(AppError::Validation)` - If no profile has this name.
+/// * `Err(AppError::Sqlx)` - If the database query fails.
+async fn resolve_audit_profile(
+    pool: &PgPool,
+    policy: &AuditPolicy,
+    name: Option<&str>,
+) -> Result<AuditProfile, AppError> {
+    let name = name.unwrap_or(&policy.default_profile);
+    sqlx::query_as::<_, AuditProfile>(&format!(
+        "SELECT {} FROM audit_profiles WHERE name = $1",
+        PROFILE_COLUMNS
+    ))
+    .bind(name)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::Validation(format!("Unknown audit profile '{}'", name)))
+}
+
+/// Checks an audit profile and normalizes its rule codes.
+///
+/// # Returns
+///
+/// * `Ok((Vec<String>, ScoreWeights))` - The sorted, deduplicated and uppercased rule
+///   codes, and the weights.
+/// * `Err(AppError::Validation)` - If the name is invalid, a rule code does not exist,
+///   or a weight is negative or not finite.
+fn validate_profile_input(
+    input: &AuditProfileInput,
+) -> Result<(Vec<String>, ScoreWeights), AppError> {
+    let valid_name = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_';
+    if input.name.is_empty()
+        || input.name.chars().count() > MAX_PROFILE_NAME_LEN
+        || !input.name.chars().all(valid_name)
+    {
+        return Err(AppError::Validation(format!(
+            "Profile names must be 1 to {} lowercase letters, digits, '-' or '_'",
+            MAX_PROFILE_NAME_LEN
+        )));
+    }
+    let rules: BTreeSet<String> = input
+        .enabled_rules
+        .iter()
+        .map(|code| code.trim().to_ascii_uppercase())
+        .collect();
+    if let Some(unknown) = rules
+        .iter()
+        .find(|code| !auditor::RULE_CODES.contains(&code.as_str()))
+    {
+        return Err(AppError::Validation(format!(
+            "Unknown rule code '{}' (expected one of: {})",
+            unknown,
+            auditor::RULE_CODES.join(", ")
+        )));
+    }
+    let weights = input.weights.clone().unwrap_or_default();
+    let values = [
+        weights.compilation,
+        weights.documentation,
+        weights.hygiene,
+        weights.cleanliness,
+        weights.warning_penalty,
+        weights.error_penalty,
+        weights.security_penalty,
+    ];
+    if values.iter().any(|w| !w.is_finite() || *w < 0.0) {
+        return Err(AppError::Validation(
+            "Score weights must be non-negative numbers".to_string(),
+        ));
+    }
+    Ok((rules.into_iter().collect(), weights))
+}
+
+/// Creates an audit profile.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `input` - The name, rules and weights of the profile.
+///
+/// # Returns
+///
+/// * `Ok(AuditProfile)` - The created profile.
+/// * `Err(AppError::Validation)` - If the profile is invalid (see `AuditProfileInput`).
+/// * `Err(AppError::Conflict)` - If a profile already has this name.
+/// * `Err(AppError::Sqlx)` - If the database insertion fails.
+#[tracing::instrument(skip(pool))]
+pub async fn create_audit_profile(
+    pool: &PgPool,
+    input: &AuditProfileInput,
+) -> Result<AuditProfile, AppError> {
+    let (rules, weights) = validate_profile_input(input)?;
+    sqlx::query_as::<_, AuditProfile>(&format!(
+        r#"
+        INSERT INTO audit_profiles (name, enabled_rules, weights)
+        VALUES ($1, $2, $3)
+        RETURNING {}
+        "#,
+        PROFILE_COLUMNS
+    ))
+    .bind(&input.name)
+    .bind(&rules)
+    .bind(Json(&weights))
+    .fetch_one(pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => {
+            AppError::Conflict(format!("Audit profile '{}' already exists", input.name))
+        }
+        e => AppError::from(e),
+    })
+}
+
+/// Replaces the name, rules and weights of an audit profile.
+///
+/// Audits already created with the profile keep their findings and scores, and their
+/// record of the profile keeps its former name.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `policy` - The server-wide audit settings, naming the default profile.
+/// * `name` - The current name of the profile.
+/// * `input` - The new name, rules and weights of the profile.
+///
+/// # Returns
+///
+/// * `Ok(AuditProfile)` - The updated profile.
+/// * `Err(AppError::Validation)` - If the profile is invalid (see `AuditProfileInput`),
+///   or the default profile would be renamed.
+/// * `Err(AppError::NotFound)` - If no profile has this name.
+/// * `Err(AppError::Conflict)` - If another profile already has the new name.
+/// * `Err(AppError::Sqlx)` - If the database update fails.
+#[tracing::instrument(skip(pool, policy))]
+pub async fn update_audit_profile(
+    pool: &PgPool,
+    policy: &AuditPolicy,
+    name: &str,
+    input: &AuditProfileInput,
+) -> Result<AuditProfile, AppError> {
+    let (rules, weights) = validate_profile_input(input)?;
+    if name == policy.default_profile && input.name != name {
+        return Err(AppError::Validation(format!(
+            "Audit profile '{}' is the default profile and cannot be renamed",
+            name
+        )));
+    }
+    sqlx::query_as::<_, AuditProfile>(&format!(
+        r#"
+        UPDATE audit_profiles
+        SET name = $2, enabled_rules = $3, weights = $4, updated_at = NOW()
+        WHERE name = $1
+        RETURNING {}
+        "#,
+        PROFILE_COLUMNS
+    ))
+    .bind(name)
+    .bind(&input.name)
+    .bind(&rules)
+    .bind(Json(&weights))
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => {
+            AppError::Conflict(format!("Audit profile '{}' already exists", input.name))
+        }
+        e => AppError::from(e),
+    })?
+    .ok_or_else(|| AppError::NotFound(format!("Audit profile '{}' not found", name)))
+}
+
+/// Removes an audit profile.
+///
+/// Audits already created with the profile keep their findings, scores and record of
+/// the profile.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `policy` - The server-wide audit settings, naming the default profile.
+/// * `name` - The name of the profile to remove.
+///
+/// # Returns
+///
+/// * `Ok(())` - If the profile was removed.
+/// * `Err(AppError::Validation)` - If the profile is the default one.
+/// * `Err(AppError::NotFound)` - If no profile has this name.
+/// * `Err(AppError::Sqlx)` - If the database deletion fails.
+#[tracing::instrument(skip(pool, policy))]
+pub async fn delete_audit_profile(
+    pool: &PgPool,
+    policy: &AuditPolicy,
+    name: &str,
+) -> Result<(), AppError> {
+    if name == policy.default_profile {
+        return Err(AppError::Validation(format!(
+            "Audit profile '{}' is the default profile and cannot be deleted",
+            name
+        )));
+    }
+    let result = sqlx::query!("DELETE FROM audit_profiles WHERE name = $1", name)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound(format!(
+            "Audit profile '{}' not found",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Retrieves the license policy the dependencies of Cargo packages are checked against.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+///
+/// # Returns
+///
+/// * `Ok(LicensePolicy)` - The allowed and denied licenses.
+/// * `Err(AppError::Sqlx)` - If the database query fails.
+pub async fn get_license_policy(pool: &PgPool) -> Result<LicensePolicy, AppError> {
+    sqlx::query_as::<_, LicensePolicy>("SELECT allowed, denied FROM license_policy")
+        .fetch_one(pool)
+        .await
+        .map_err(AppError::from)
+}
+
+/// Replaces the license policy. Audits already created keep their findings, and
+/// rerunning them checks their dependencies against the new policy.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `input` - The new allowed and denied licenses.
+///
+/// # Returns
+///
+/// * `Ok(LicensePolicy)` - The new policy, with its licenses trimmed and deduplicated.
+/// * `Err(AppError::Validation)` - If a list is too long or a license is not an SPDX
+///   license identifier (see `licenses::check_policy`).
+/// * `Err(AppError::Sqlx)` - If the database update fails.
+#[tracing::instrument(skip(pool))]
+pub async fn set_license_policy(
+    pool: &PgPool,
+    input: &LicensePolicyInput,
+) -> Result<LicensePolicy, AppError> {
+    let policy = licenses::check_policy(input).map_err(AppError::Validation)?;
+    sqlx::query_as::<_, LicensePolicy>(
+        "UPDATE license_policy SET allowed = $1, denied = $2, updated_at = NOW()
+         RETURNING allowed, denied",
+    )
+    .bind(&policy.allowed)
+    .bind(&policy.denied)
+    .fetch_one(pool)
+    .await
+    .map_err(AppError::from)
+}
+
+/// Returns the server-wide audit settings with the license policy of the database.
+async fn with_license_policy(pool: &PgPool, policy: &AuditPolicy) -> Result<AuditPolicy, AppError> {
+    Ok(AuditPolicy {
+        licenses: get_license_policy(pool).await?,
+        ..policy.clone()
+    })
+}
+
+/// The maximum length of a username, in characters.
+const MAX_USERNAME_LEN: usize = 64;
+
+/// The minimum length of a password, in characters.
+const MIN_PASSWORD_LEN: usize = 8;
+
+/// A hash verified when the username is unknown, so that the response time does not
+/// reveal which usernames exist.
+static DUMMY_PASSWORD_HASH: LazyLock<String> =
+    LazyLock::new(|| bcrypt::hash("dummy password", bcrypt::DEFAULT_COST).unwrap_or_default());
+
+/// Checks the credentials of a user.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `username` - The name of the user.
+/// * `password` - The password of the user.
+///
+/// # Returns
+///
+/// * `Ok(User)` - The user, if the password matches.
+/// * `Err(AppError::Unauthorized)` - If the user does not exist or the password does not match.
+/// * `Err(AppError::Sqlx)` - If the database query fails.
+#[tracing::instrument(skip(pool, password))]
+pub async fn authenticate_user(
+    pool: &PgPool,
+    username: &str,
+    password: &str,
+) -> Result<User, AppError> {
+    let user = sqlx::query_as!(
+        User,
+        r#"SELECT id, username, password_hash, role AS "role: Role" FROM users WHERE username = $1"#,
+        username
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    let hash = user
+        .as_ref()
+        .map_or_else(|| DUMMY_PASSWORD_HASH.clone(), |u| u.password_hash.clone());
+    let password = password.to_string();
+    // bcrypt is deliberately slow, so it must not block the async runtime.
+    let matches = tokio::task::spawn_blocking(move || bcrypt::verify(password, &hash))
+        .await
+        .map_err(|e| AppError::Audit(format!("Password verification failed: {}", e)))?
+        .unwrap_or(false);
+
+    match user {
+        Some(user) if matches => Ok(user),
+        _ => Err(AppError::Unauthorized(
+            "Invalid username or password".to_string(),
+        )),
+    }
+}
+
+/// Creates a user account with the `user` role.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `username` - The name of the user, which must not be taken.
+/// * `password` - The password of the user.
+///
+/// # Returns
+///
+/// * `Ok(User)` - The newly created user.
+/// * `Err(AppError::Validation)` - If the username is empty or too long, or the password
+///   is too short.
+/// * `Err(AppError::Conflict)` - If the username is taken.
+/// * `Err(AppError::Sqlx)` - If the database insertion fails.
+#[tracing::instrument(skip(pool, password))]
+pub async fn register_user(
+    pool: &PgPool,
+    username: &str,
+    password: &str,
+) -> Result<User, AppError> {
+    let username = username.trim();
+    if username.is_empty() || username.chars().count() > MAX_USERNAME_LEN {
+        return Err(AppError::Validation(format!(
+            "username must be between 1 and {} characters",
+            MAX_USERNAME_LEN
+        )));
+    }
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(AppError::Validation(format!(
+            "password must be at least {} characters",
+            MIN_PASSWORD_LEN
+        )));
+    }
+
+    let password = password.to_string();
+    let password_hash =
+        tokio::task::spawn_blocking(move || bcrypt::hash(password, bcrypt::DEFAULT_COST))
+            .await
+            .map_err(|e| AppError::Audit(format!("Password hashing failed: {}", e)))?
+            .map_err(|e| AppError::Audit(format!("Password hashing failed: {}", e)))?;
+
+    let inserted = sqlx::query_as!(
+        User,
+        r#"INSERT INTO users (username, password_hash, role) VALUES ($1, $2, $3)
+         RETURNING id, username, password_hash, role AS "role: Role""#,
+        username,
+        password_hash,
+        Role::User as Role
+    )
+    .fetch_one(pool)
+    .await;
+
+    match inserted {
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => Err(AppError::Conflict(
+            format!("Username '{}' is already taken", username),
+        )),
+        result => {
+            let user = result?;
+            tracing::info!(id = %user.id, "User registered.");
+            Ok(user)
+        }
+    }
+}
+
+/// The number of failing audits loaded at a time by `reaudit_invalid_audits`.
+const REAUDIT_BATCH_SIZE: i64 = 100;
+
+/// Recompiles previously failing audits and records their new result.
+///
+/// This is meant to be run after a toolchain upgrade, when code that used to fail may
+/// compile now. Audits rejected by strict validation are not recompiled.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `policy` - The server-wide audit settings, deciding whether warnings fail the code.
+/// * `compiler` - The queue of the compilation workers.
+/// * `limit` - The maximum number of audits to recompile, oldest first.
+///
+/// # Returns
+///
+/// * `Ok(RerunReport)` - A summary of the re-run.
+/// * `Err(AppError::Sqlx)` - If a database query fails.
+#[tracing::instrument(skip(pool, compiler))]
+pub async fn rerun_failed_audits(
+    pool: &PgPool,
+    policy: &AuditPolicy,
+    compiler: &CompilationQueue,
+    limit: i64,
+) -> Result<RerunReport, AppError> {
+    let failing = sqlx::query_as!(
+        FailedAudit,
+        r#"
+        SELECT id, generated_code, channel AS "channel: Channel", opt_level AS "opt_level: OptLevel",
+            edition AS "edition: Edition", target, created_at
+        FROM ai_audits WHERE status = 'compile_error' ORDER BY created_at LIMIT $1
+        "#,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+    let failing = failing.into_iter().map(FailedAudit::into_job).collect();
+
+    let mut report = RerunReport::default();
+    recompile_audits(pool, policy, compiler, failing, &mut report).await?;
+
+    tracing::info!(
+        attempted = report.attempted,
+        now_passing = report.now_passing,
+        still_failing = report.still_failing,
+        "Re-ran failed audits."
+    );
+    Ok(report)
+}
+
+/// Recompiles every audit that failed to compile and records its new result.
+///
+/// Unlike `rerun_failed_audits`, this walks the whole table. When the policy counts
+/// warnings as errors, the valid audits whose code compiled with warnings are recompiled
+/// too, since the policy may have become stricter since they were judged. Audits are
+/// loaded `REAUDIT_BATCH_SIZE` at a time, oldest first, so memory use does not grow with
+/// the size of the table.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `policy` - The server-wide audit settings, deciding whether warnings fail the code.
+/// * `compiler` - The queue of the compilation workers.
+///
+/// # Returns
+///
+/// * `Ok(ReauditReport)` - A summary of the re-audit.
+/// * `Err(AppError::Sqlx)` - If a database query fails.
+#[tracing::instrument(skip(pool, compiler))]
+pub async fn reaudit_invalid_audits(
+    pool: &PgPool,
+    policy: &AuditPolicy,
+    compiler: &CompilationQueue,
+) -> Result<ReauditReport, AppError> {
+    let invalid = reaudit_matching(pool, policy, compiler, "status = 'compile_error'").await?;
+    // Runs second, so that the audits it invalidates are not recompiled twice.
+    let warned = if policy.warnings_as_errors {
+        reaudit_matching(
+            pool,
+            policy,
+            compiler,
+            r#"status = 'valid' AND diagnostics @> '[{"level": "warning"}]'"#,
+        )
+        .await?
+    } else {
+        RerunReport::default()
+    };
+
+    tracing::info!(
+        processed = invalid.attempted + warned.attempted,
+        newly_valid = invalid.now_passing,
+        still_invalid = invalid.still_failing,
+        newly_invalid = warned.still_failing,
+        "Re-audited invalid audits."
+    );
+    Ok(ReauditReport {
+        processed: invalid.attempted + warned.attempted,
+        newly_valid: invalid.now_passing,
+        still_invalid: invalid.still_failing,
+        newly_invalid: warned.still_failing,
+    })
+}
+
+/// Recompiles every audit matching a condition, `REAUDIT_BATCH_SIZE` at a time, oldest
+/// first (see `reaudit_invalid_audits`).
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `policy` - The server-wide audit settings the new verdicts are given under.
+/// * `compiler` - The queue of the compilation workers.
+/// * `condition` - The SQL condition on `ai_audits` the recompiled audits meet.
+///
+/// # Returns
+///
+/// * `Ok(RerunReport)` - The results of the recompilations: the audits valid and
+///   invalid afterwards.
+/// * `Err(AppError::Sqlx)` - If a database query fails.
+async fn reaudit_matching(
+    pool: &PgPool,
+    policy: &AuditPolicy,
+    compiler: &CompilationQueue,
+    condition: &str,
+) -> Result<RerunReport, AppError> {
+    let mut report = RerunReport::default();
+    let mut cursor: Option<(DateTime<Utc>, Uuid)> = None;
+    let sql = format!(
+        r#"
+        SELECT id, generated_code, channel, opt_level, edition, target, created_at
+        FROM ai_audits
+        WHERE {}
+          AND ($1::timestamptz IS NULL OR (created_at, id) > ($1, $2))
+        ORDER BY created_at, id
+        LIMIT $3
+        "#,
+        condition
+    );
+
+    loop {
+        // Keyset pagination: audits that keep their verdict still match the condition,
+        // so an offset would skip or revisit rows as others change.
+        let batch = sqlx::query_as::<_, FailedAudit>(&sql)
+            .bind(cursor.map(|(created_at, _)| created_at))
+            .bind(cursor.map(|(_, id)| id))
+            .bind(REAUDIT_BATCH_SIZE)
+            .fetch_all(pool)
+            .await?;
+
+        let Some(last) = batch.last() else {
+            break;
+        };
+        cursor = Some((last.created_at, last.id));
+        let exhausted = (batch.len() as i64) < REAUDIT_BATCH_SIZE;
+
+        let audits = batch.into_iter().map(FailedAudit::into_job).collect();
+        recompile_audits(pool, policy, compiler, audits, &mut report).await?;
+        tracing::debug!(processed = report.attempted, "Re-audited batch.");
+
+        if exhausted {
+            break;
+        }
+    }
+    Ok(report)
+}
+
+/// An audit that failed to compile, or compiled with warnings, selected to be
+/// recompiled.
+#[derive(sqlx::FromRow)]
+struct FailedAudit {
+    id: Uuid,
+    generated_code: String,
+    channel: Channel,
+    opt_level: OptLevel,
+    edition: Option<Edition>,
+    target: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+impl FailedAudit {
+    /// Returns the identifier, code and compilation settings passed to `recompile_audits`.
+    fn into_job(self) -> (Uuid, String, CompileOptions) {
+        let options = CompileOptions {
+            channel: self.channel,
+            opt_level: self.opt_level,
+            edition: self.edition.unwrap_or(Edition::UNRECORDED),
+            target: self.target,
+            files: Vec::new(),
+        };
+        (self.id, self.generated_code, options)
+    }
+}
+
+/// Recompiles the given audits, updates their verdict and adds the results to `report`.
+///
+/// The audits are submitted to the compilation workers all at once, which bound how many
+/// are compiled at the same time. Audits that cannot be recompiled (e.g. `rustc` cannot
+/// be executed) are left unchanged and counted as still failing.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `policy` - The server-wide audit settings the new verdicts are given under.
+/// * `compiler` - The queue of the compilation workers.
+/// * `audits` - The identifier, code and compilation settings of each audit to recompile.
+/// * `report` - The summary to add the results to.
+///
+/// # Returns
+///
+/// * `Ok(())` - If every recompiled audit was updated.
+/// * `Err(AppError::Sqlx)` - If a database query fails.
+async fn recompile_audits(
+    pool: &PgPool,
+    policy: &AuditPolicy,
+    compiler: &CompilationQueue,
+    audits: Vec<(Uuid, String, CompileOptions)>,
+    report: &mut RerunReport,
+) -> Result<(), AppError> {
+    let policy = &with_license_policy(pool, policy).await?;
+    let mut compilations = JoinSet::new();
+    for (id, code, mut options) in audits {
+        options.files = get_audit_files(pool, id).await?;
+        let compiler = compiler.clone();
+        compilations.spawn(async move {
+            let outcome = compiler.compile(code.clone(), options.clone()).await;
+            (id, code, options.channel, outcome)
+        });
+    }
+
+    while let Some(joined) = compilations.join_next().await {
+        let Ok((id, code, channel, outcome)) = joined else {
+            continue;
+        };
+        report.attempted += 1;
+
+        let mut outcome = match outcome {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                tracing::warn!(%id, error = %e, "Could not recompile audit.");
+                report.still_failing += 1;
+                continue;
+            }
+        };
+
+        let lockfile = outcome.lockfile.take();
+        let dependencies = outcome.dependencies.take();
+        let security_advisories = outcome.security_advisories.take();
+        let verdict = Verdict {
+            security_advisories,
+            ..Verdict::compiled(outcome, channel, policy)
+        }
+        .with_dependencies_checked(
+            lockfile.as_deref(),
+            dependencies.as_deref(),
+            policy,
+            compiler,
+        );
+        let mut conn = pool.acquire().await?;
+        let is_valid = update_verdict(&mut conn, id, &code, verdict).await?;
+
+        tracing::debug!(%id, is_valid, "Re-ran audit.");
+        if is_valid {
+            report.now_passing += 1;
+        } else {
+            report.still_failing += 1;
+        }
+    }
+
+    Ok(())
+}
+
+/// Replaces the verdict of a stored audit and rescores it.
+///
+/// The audit is scored with the findings and weights it was created with, except that the
+/// `RAA0203` and `RAA0301` findings of its dependencies are replaced by those of the new
+/// verdict. The
+/// new verdict replaces the old one in the running totals of the statistics. Run it on a
+/// transaction to make further writes atomic with it.
+///
+/// # Arguments
+///
+/// * `conn` - The connection or transaction to update the audit on.
+/// * `id` - The UUID of the audit.
+/// * `code` - The code of the audit.
+/// * `verdict` - The new verdict.
+///
+/// # Returns
+///
+/// * `Ok(bool)` - Whether the audit is now valid.
+/// * `Err(sqlx::Error)` - If no audit has this ID or a query fails.
+async fn update_verdict(
+    conn: &mut PgConnection,
+    id: Uuid,
+    code: &str,
+    verdict: Verdict,
+) -> Result<bool, sqlx::Error> {
+    let is_valid = verdict.is_valid();
+    // Score the audit with the weights it was created with, not the current ones.
+    let stored = sqlx::query_as!(
+        StoredAnalysis,
+        r#"
+        SELECT findings AS "findings: Json<Vec<Finding>>",
+            profile_weights AS "profile_weights: Json<ScoreWeights>"
+        FROM ai_audits WHERE id = $1
+        "#,
+        id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    let weights = stored.profile_weights.map(|w| w.0).unwrap_or_default();
+    // The dependencies are checked again along with each compilation.
+    let vulnerability_count = verdict.vulnerability_count();
+    let license_violation_count = verdict.license_violation_count();
+    let has_security_issues = verdict
+        .security_advisories
+        .as_ref()
+        .is_some_and(|advisories| !advisories.is_empty());
+    let findings: Vec<Finding> = stored
+        .findings
+        .0
+        .into_iter()
+        .filter(|f| {
+            f.code != auditor::VULNERABLE_DEPENDENCY_CODE
+                && f.code != auditor::LICENSE_VIOLATION_CODE
+        })
+        .chain(verdict.vulnerabilities.unwrap_or_default())
+        .chain(verdict.license_violations.unwrap_or_default())
+        .collect();
+    let unsafe_report = auditor::check_unsafe_usage(code).ok();
+    let metrics = compute_audit_metrics(&PartialAudit {
+        code,
+        is_valid,
+        findings: &findings,
+        diagnostics: &verdict.diagnostics,
+        doc_coverage_percent: auditor::compute_doc_coverage(code).ok(),
+        unsafe_report: unsafe_report.as_ref(),
+        hygiene_report: &auditor::check_hygiene(code),
+        compilation_duration_ms: verdict.compilation_duration_ms,
+        weights: &weights,
+    });
+    // The new verdict replaces the old one in the running totals of the statistics.
+    sqlx::query(&format!(
+        r#"
+        WITH previous AS (
+            SELECT * FROM ai_audits WHERE id = $1 FOR UPDATE
+        ), updated AS (
+            -- Joining `previous` reads the old row before it is replaced.
+            UPDATE ai_audits
+            SET is_valid = $2, status = $3, compilation_error = $4, primary_error_code = $5,
+                primary_error_category = $6, diagnostics = $7, rustc_version = $8,
+                compilation_duration_ms = $9, metrics = $10, compile_command = $11,
+                findings = $12, vulnerability_count = $13, advisory_note = $14,
+                license_violation_count = $15, security_advisories = $16,
+                has_security_issues = $17, updated_at = NOW()
+            FROM previous
+            WHERE ai_audits.id = previous.id
+            RETURNING ai_audits.*
+        ), counted AS (
+            UPDATE audit_stats_summary AS s SET
+                valid_audits = s.valid_audits + n.valid_audits - o.valid_audits,
+                scored_audits = s.scored_audits + n.scored_audits - o.scored_audits,
+                total_quality_score =
+                    s.total_quality_score + n.total_quality_score - o.total_quality_score,
+                timed_audits = s.timed_audits + n.timed_audits - o.timed_audits,
+                total_compilation_ms =
+                    s.total_compilation_ms + n.total_compilation_ms - o.total_compilation_ms,
+                total_vulnerabilities =
+                    s.total_vulnerabilities + n.total_vulnerabilities - o.total_vulnerabilities,
+                updated_at = GREATEST(s.updated_at, NOW())
+            FROM (SELECT {aggregates} FROM updated) AS n,
+                (SELECT {aggregates} FROM previous) AS o
+        )
+        SELECT 1
+        "#,
+        aggregates = SUMMARY_AGGREGATES
+    ))
+    .bind(id)
+    .bind(is_valid)
+    .bind(verdict.status)
+    .bind(verdict.compilation_error)
+    .bind(verdict.primary_error_code)
+    .bind(verdict.primary_error_category)
+    .bind(Json(&verdict.diagnostics))
+    .bind(verdict.rustc_version)
+    .bind(verdict.compilation_duration_ms)
+    .bind(Json(metrics))
+    .bind(verdict.compile_command)
+    .bind(Json(&findings))
+    .bind(vulnerability_count)
+    .bind(verdict.advisory_note)
+    .bind(license_violation_count)
+    .bind(verdict.security_advisories.map(Json))
+    .bind(has_security_issues)
+    .execute(&mut *conn)
+    .await?;
+    Ok(is_valid)
+}
+
+/// The stored results of the analyses of an audit that recompiling it does not change.
+struct StoredAnalysis {
+    findings: Json<Vec<Finding>>,
+    profile_weights: Option<Json<ScoreWeights>>,
+}
+
+/// The maximum number of records accepted by one bulk import.
+pub const MAX_IMPORT_RECORDS: usize = 1000;
+
+/// Stores audit records as-is, without validating or compiling their code.
+///
+/// Each record is checked and inserted on its own: invalid records and records that
+/// cannot be inserted are reported, while the others are committed together in one
+/// transaction. Records whose identifier is already used are skipped without error, so
+/// that importing the same export twice stores its audits once. The code size
+/// metrics, doc coverage and unsafe report are computed from the code, since they do
+/// not require compiling it.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `records` - The JSON records to import, each deserialized as an `ImportAuditRecord`.
+///
+/// # Returns
+///
+/// * `Ok(ImportReport)` - How many records were stored or skipped, and why the others
+///   were not stored.
+/// * `Err(AppError::Validation)` - If there are more than `MAX_IMPORT_RECORDS` records.
+/// * `Err(AppError::Sqlx)` - If the transaction cannot be committed.
+#[tracing::instrument(skip(pool, records), fields(records = records.len()))]
+pub async fn import_audits(
+    pool: &PgPool,
+    records: Vec<serde_json::Value>,
+) -> Result<ImportReport, AppError> {
+    if records.len() > MAX_IMPORT_RECORDS {
+        return Err(AppError::Validation(format!(
+            "At most {} records can be imported at once",
+            MAX_IMPORT_RECORDS
+        )));
+    }
+
+    let mut report = ImportReport {
+        attempted: records.len(),
+        ..ImportReport::default()
+    };
+    let mut tx = pool.begin().await?;
+    for (index, record) in records.into_iter().enumerate() {
+        let record = match parse_import_record(record) {
+            Ok(record) => record,
+            Err(message) => {
+                report.errors.push(ImportRowError { index, message });
+                continue;
+            }
+        };
+
+        // A savepoint keeps the transaction usable when a single insertion fails.
+        let mut savepoint = Acquire::begin(&mut *tx).await?;
+        match insert_imported_audit(&mut *savepoint, &record).await {
+            Ok(inserted) => {
+                savepoint.commit().await?;
+                if inserted {
+                    report.imported += 1;
+                } else {
+                    report.skipped += 1;
+                }
+            }
+            Err(e) => {
+                savepoint.rollback().await?;
+                report.errors.push(ImportRowError {
+                    index,
+                    message: format!("Could not store the record: {}", e),
+                });
+            }
+        }
+    }
+    tx.commit().await?;
+
+    tracing::info!(
+        imported = report.imported,
+        skipped = report.skipped,
+        rejected = report.errors.len(),
+        "Imported audits."
+    );
+    Ok(report)
+}
+
+/// Deserializes and checks one record of a bulk import.
+///
+/// # Returns
+///
+/// * `Ok(ImportAuditRecord)` - The record, with its tags normalized.
+/// * `Err(String)` - Why the record cannot be imported.
+fn parse_import_record(record: serde_json::Value) -> Result<ImportAuditRecord, String> {
+    let mut record: ImportAuditRecord =
+        serde_json::from_value(record).map_err(|e| format!("Invalid record: {}", e))?;
+    if record.prompt.trim().is_empty() {
+        return Err("prompt must not be empty".to_string());
+    }
+    if let Some(status) = record.status
+        && (status == AuditStatus::Valid) != record.is_valid
+    {
+        return Err("status contradicts is_valid".to_string());
+    }
+    // Imported audits are stored as-is, never compiled, so they cannot wait for a job.
+    if record.status == Some(AuditStatus::Pending) {
+        return Err("pending audits cannot be imported".to_string());
+    }
+    record.tags = normalize_tags(&record.tags).map_err(|e| e.to_string())?;
+    Ok(record)
+}
+
+/// Inserts an imported audit record, unless an audit with the same identifier exists.
+///
+/// # Returns
+///
+/// * `Ok(bool)` - Whether the record was inserted.
+/// * `Err(sqlx::Error)` - If the record cannot be stored.
+async fn insert_imported_audit(
+    executor: impl PgExecutor<'_>,
+    record: &ImportAuditRecord,
+) -> Result<bool, sqlx::Error> {
+    let code = &record.generated_code;
+    let status = record.status.unwrap_or(if record.is_valid {
+        AuditStatus::Valid
+    } else {
+        AuditStatus::CompileError
+    });
+    let category = record
+        .primary_error_code
+        .as_deref()
+        .map(|code| auditor::categorize_error_code(code).as_str().to_string());
+    let doc_coverage_percent = auditor::compute_doc_coverage(code).ok();
+    let unsafe_report = auditor::check_unsafe_usage(code).ok();
+    let hygiene_report = auditor::check_hygiene(code);
+    let metrics = compute_audit_metrics(&PartialAudit {
+        code,
+        is_valid: record.is_valid,
+        findings: &record.findings,
+        diagnostics: &[],
+        doc_coverage_percent,
+        unsafe_report: unsafe_report.as_ref(),
+        hygiene_report: &hygiene_report,
+        compilation_duration_ms: None,
+        weights: &ScoreWeights::default(),
+    });
+
+    let inserted: i64 = sqlx::query_scalar(&format!(
+        r#"
+        WITH inserted AS (
+            INSERT INTO ai_audits (
+                id, prompt, generated_code, is_valid, status, tags, code_line_count, code_char_count,
+                compilation_error, primary_error_code, primary_error_category, channel, opt_level,
+                target, rustc_version, doc_coverage_percent, findings, unsafe_report, hygiene_report,
+                metrics, created_at, updated_at, edition, compile_command, prompt_token_count,
+                code_token_count, detected_license
+            )
+            VALUES (
+                COALESCE($1, gen_random_uuid()), $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12,
+                $13, $14, $15, $16, $17, $18, $19, $20, COALESCE($21, NOW()), COALESCE($21, NOW()),
+                $22, $23, $24, $25, $26
+            )
+            ON CONFLICT (id) DO NOTHING
+            RETURNING *
+        ), {}
+        SELECT COUNT(*) FROM inserted
+        "#,
+        count_inserted_audits()
+    ))
+    .bind(record.id)
+    .bind(&record.prompt)
+    .bind(code)
+    .bind(record.is_valid)
+    .bind(status)
+    .bind(&record.tags)
+    .bind(i32::try_from(code.lines().count()).unwrap_or(i32::MAX))
+    .bind(i32::try_from(code.chars().count()).unwrap_or(i32::MAX))
+    .bind(&record.compilation_error)
+    .bind(&record.primary_error_code)
+    .bind(category)
+    .bind(record.channel.unwrap_or_default())
+    .bind(record.opt_level.unwrap_or_default())
+    .bind(&record.target)
+    .bind(&record.rustc_version)
+    .bind(doc_coverage_percent)
+    .bind(Json(&record.findings))
+    .bind(unsafe_report.map(Json))
+    .bind(Json(hygiene_report))
+    .bind(Json(metrics))
+    .bind(record.created_at)
+    .bind(record.edition)
+    .bind(&record.compile_command)
+    .bind(token_count(&record.prompt))
+    .bind(token_count(code))
+    .bind(auditor::detect_license_header(code))
+    .fetch_one(executor)
+    .await?;
+    Ok(inserted > 0)
+}
+
+/// Claims the next due background audit job, if any.
+///
+/// A job is due when it waits and its `run_at` has passed, or when it is running but its
+/// worker has not completed it within `lease` (e.g. the replica running it stopped).
+/// Jobs are locked with `FOR UPDATE SKIP LOCKED`, so that the workers of every replica
+/// sharing the database claim different jobs, and each claim is counted as an attempt.
+/// A job whose lease expired after its last attempt is dead-lettered instead.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `lease` - How long a worker may run a job before others may claim it.
+/// * `max_attempts` - The number of attempts after which a job is dead-lettered.
+///
+/// # Returns
+///
+/// * `Ok(Some(AuditJob))` - The claimed job, `running` or `failed`.
+/// * `Ok(None)` - If no job is due.
+/// * `Err(AppError::Sqlx)` - If the query fails.
+pub async fn claim_audit_job(
+    pool: &PgPool,
+    lease: Duration,
+    max_attempts: i32,
+) -> Result<Option<AuditJob>, AppError> {
+    let job = sqlx::query_as!(
+        AuditJob,
+        r#"
+        WITH next AS (
+            SELECT id FROM audit_jobs
+            WHERE (state = 'pending' AND run_at <= NOW())
+               OR (state = 'running' AND locked_at < NOW() - make_interval(secs => $1))
+            ORDER BY run_at
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED
+        )
+        UPDATE audit_jobs AS j SET
+            state = CASE WHEN j.attempts >= $2 THEN 'failed' ELSE 'running' END,
+            attempts = CASE WHEN j.attempts >= $2 THEN j.attempts ELSE j.attempts + 1 END,
+            last_error = CASE
+                WHEN j.attempts >= $2 THEN 'The worker running the last attempt stopped'
+                ELSE j.last_error
+            END,
+            locked_at = NOW(), updated_at = NOW()
+        FROM next
+        WHERE j.id = next.id
+        RETURNING j.id, j.audit_id, j.state AS "state: JobState", j.attempts, j.run_at,
+            j.last_error, j.created_at, j.updated_at
+        "#,
+        lease.as_secs_f64(),
+        max_attempts
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(job)
+}
+
+/// Compiles the audit of a claimed job, stores its verdict and marks the job done.
+///
+/// The verdict is stored and the job marked done in one transaction, and only if the
+/// job is still held by this attempt: if it was cancelled, or its lease expired and
+/// another worker claimed it, the result is dropped. The notifiers are told about the audit once it has its
+/// verdict, and the outputs of its compilation are uploaded to the artifact store.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `policy` - The server-wide audit settings the verdict is given under.
+/// * `compiler` - The queue of the compilation workers.
+/// * `notifiers` - The notifiers told about the audit.
+/// * `job` - The claimed job.
+/// * `output` - The channel receiving the compiler output line by line, if any.
+///
+/// # Returns
+///
+/// * `Ok(Some(AiAudit))` - The audit with its verdict.
+/// * `Ok(None)` - If the audit was deleted, or the job was cancelled or claimed by
+///   another worker.
+/// * `Err(AppError)` - If the compilation workers or a database query fail.
+pub async fn run_audit_job(
+    pool: &PgPool,
+    policy: &AuditPolicy,
+    compiler: &CompilationQueue,
+    notifiers: &AuditNotifiers,
+    job: &AuditJob,
+    output: Option<mpsc::Sender<CompilerOutput>>,
+) -> Result<Option<AiAudit>, AppError> {
+    let Some(audit) = get_audit_by_id(pool, job.audit_id).await? else {
+        return Ok(None);
+    };
+    let options = CompileOptions {
+        channel: audit.channel,
+        opt_level: audit.opt_level,
+        edition: audit.edition.unwrap_or(Edition::UNRECORDED),
+        target: audit.target.clone(),
+        files: get_audit_files(pool, audit.id).await?,
+    };
+    let policy = with_license_policy(pool, policy).await?;
+    let mut verdict =
+        compile_verdict(&policy, compiler, &audit.generated_code, options, output).await?;
+    let artifacts = verdict.artifacts.take();
+
+    let mut tx = pool.begin().await?;
+    let held = sqlx::query!(
+        r#"
+        UPDATE audit_jobs SET state = 'done', locked_at = NULL, last_error = NULL,
+            updated_at = NOW()
+        WHERE id = $1 AND state = 'running' AND attempts = $2
+        "#,
+        job.id,
+        job.attempts
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected()
+        > 0;
+    if !held {
+        tracing::warn!(job = %job.id, "Audit job was cancelled or claimed by another worker, dropping its result.");
+        return Ok(None);
+    }
+    update_verdict(&mut tx, audit.id, &audit.generated_code, verdict).await?;
+    tx.commit().await?;
+    if let Some(artifacts) = artifacts {
+        compiler
+            .artifacts()
+            .upload(pool, audit.id, &audit.generated_code, artifacts);
+    }
+
+    let audit = get_audit_by_id(pool, audit.id).await?;
+    if let Some(audit) = &audit {
+        notifiers.audit_created(pool, audit);
+    }
+    Ok(audit)
+}
+
+/// Records that an attempt of a job failed, and schedules its retry.
+///
+/// A job that failed `max_attempts` times is dead-lettered instead: it is not retried
+/// until an administrator asks for it (see `retry_audit_job`), and its audit stays
+/// pending meanwhile.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `job` - The claimed job.
+/// * `error` - Why the attempt failed.
+/// * `retry_in` - How long to wait before the next attempt.
+/// * `max_attempts` - The number of attempts after which a job is dead-lettered.
+///
+/// # Returns
+///
+/// * `Ok(Some(JobState))` - The new state of the job, `pending` or `failed`.
+/// * `Ok(None)` - If the job was claimed by another worker meanwhile.
+/// * `Err(AppError::Sqlx)` - If the query fails.
+pub async fn fail_audit_job(
+    pool: &PgPool,
+    job: &AuditJob,
+    error: &str,
+    retry_in: Duration,
+    max_attempts: i32,
+) -> Result<Option<JobState>, AppError> {
+    let state = sqlx::query_scalar!(
+        r#"
+        UPDATE audit_jobs SET
+            state = CASE WHEN attempts >= $3 THEN 'failed' ELSE 'pending' END,
+            run_at = CASE WHEN attempts >= $3 THEN run_at
+                ELSE NOW() + make_interval(secs => $4) END,
+            locked_at = NULL, last_error = $5, updated_at = NOW()
+        WHERE id = $1 AND state = 'running' AND attempts = $2
+        RETURNING state AS "state: JobState"
+        "#,
+        job.id,
+        job.attempts,
+        max_attempts,
+        retry_in.as_secs_f64(),
+        error
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(state)
+}
+
+/// Retrieves a background audit job.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `id` - The UUID of the job.
+///
+/// # Returns
+///
+/// * `Ok(AuditJob)` - The job.
+/// * `Err(AppError::NotFound)` - If no job has this ID.
+/// * `Err(AppError::Sqlx)` - If the query fails.
+pub async fn get_audit_job(pool: &PgPool, id: Uuid) -> Result<AuditJob, AppError> {
+    sqlx::query_as!(
+        AuditJob,
+        r#"
+        SELECT id, audit_id, state AS "state: JobState", attempts, run_at, last_error,
+            created_at, updated_at
+        FROM audit_jobs WHERE id = $1
+        "#,
+        id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("Job {} not found", id)))
+}
+
+/// Attaches its audit to a background audit job, if the job is done.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `job` - The job.
+///
+/// # Returns
+///
+/// * `Ok(AuditJobStatus)` - The job, with its audit if it is done.
+/// * `Err(AppError::Sqlx)` - If the query fails.
+pub async fn audit_job_status(pool: &PgPool, job: AuditJob) -> Result<AuditJobStatus, AppError> {
+    let audit = match job.state {
+        JobState::Done => get_audit_by_id(pool, job.audit_id).await?,
+        _ => None,
+    };
+    Ok(AuditJobStatus { job, audit })
+}
+
+/// Retrieves the job of an audit submitted in the background.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `audit_id` - The UUID of the audit.
+///
+/// # Returns
+///
+/// * `Ok(Some(AuditJob))` - The job of the audit.
+/// * `Ok(None)` - If the audit was not submitted in the background.
+/// * `Err(AppError::Sqlx)` - If the query fails.
+pub async fn find_audit_job(pool: &PgPool, audit_id: Uuid) -> Result<Option<AuditJob>, AppError> {
+    let job = sqlx::query_as!(
+        AuditJob,
+        r#"
+        SELECT id, audit_id, state AS "state: JobState", attempts, run_at, last_error,
+            created_at, updated_at
+        FROM audit_jobs WHERE audit_id = $1
+        "#,
+        audit_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(job)
+}
+
+/// The maximum number of dead-lettered jobs listed by `get_job_queue_stats`.
+const MAX_DEAD_LETTERS: i64 = 100;
+
+/// Describes the queue of background audit jobs.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+///
+/// # Returns
+///
+/// * `Ok(JobQueueStats)` - The depth of the queue, the age of its oldest job, and the
+///   most recently dead-lettered jobs.
+/// * `Err(AppError::Sqlx)` - If a query fails.
+pub async fn get_job_queue_stats(pool: &PgPool) -> Result<JobQueueStats, AppError> {
+    let counts = sqlx::query!(
+        r#"
+        SELECT COUNT(*) FILTER (WHERE state IN ('pending', 'running')) AS "depth!",
+            EXTRACT(EPOCH FROM NOW() - MIN(created_at)
+                FILTER (WHERE state IN ('pending', 'running')))::float8 AS oldest_pending_age_secs,
+            COUNT(*) FILTER (WHERE state = 'failed') AS "dead_count!"
+        FROM audit_jobs
+        "#
+    )
+    .fetch_one(pool)
+    .await?;
+    let dead_letters = sqlx::query_as!(
+        AuditJob,
+        r#"
+        SELECT id, audit_id, state AS "state: JobState", attempts, run_at, last_error,
+            created_at, updated_at
+        FROM audit_jobs WHERE state = 'failed' ORDER BY updated_at DESC LIMIT $1
+        "#,
+        MAX_DEAD_LETTERS
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(JobQueueStats {
+        depth: counts.depth,
+        oldest_pending_age_secs: counts.oldest_pending_age_secs,
+        dead_count: counts.dead_count,
+        dead_letters,
+    })
+}
+
+/// Sends a dead-lettered job back to the queue, with its attempts reset.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `id` - The UUID of the job.
+///
+/// # Returns
+///
+/// * `Ok(AuditJob)` - The job, waiting for a worker.
+/// * `Err(AppError::NotFound)` - If no dead-lettered job has this ID.
+/// * `Err(AppError::Sqlx)` - If the query fails.
+pub async fn retry_audit_job(pool: &PgPool, id: Uuid) -> Result<AuditJob, AppError> {
+    sqlx::query_as!(
+        AuditJob,
+        r#"
+        UPDATE audit_jobs SET state = 'pending', attempts = 0, run_at = NOW(),
+            locked_at = NULL, updated_at = NOW()
+        WHERE id = $1 AND state = 'failed'
+        RETURNING id, audit_id, state AS "state: JobState", attempts, run_at, last_error,
+            created_at, updated_at
+        "#,
+        id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("Dead-lettered job {} not found", id)))
+}
+
+/// Cancels the background job of an audit that has no verdict yet.
+///
+/// The job is marked cancelled and the audit gets the `cancelled` status in one
+/// statement. A worker of this replica running the job stops waiting for its
+/// compilation; a worker of another replica drops the result once the compilation ends,
+/// since the job is no longer held.
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `cancellation_tokens` - The tokens cancelling the jobs running on this replica.
+/// * `id` - The UUID of the audit.
+///
+/// # Returns
+///
+/// * `Ok(true)` - If the job of the audit was cancelled.
+/// * `Ok(false)` - If the audit has no job to cancel: it was not submitted in the
+///   background, already has its verdict or was cancelled before.
+/// * `Err(AppError::NotFound)` - If no audit has this ID.
+/// * `Err(AppError::Sqlx)` - If the query fails.
+pub async fn cancel_audit(
+    pool: &PgPool,
+    cancellation_tokens: &CancellationTokens,
+    id: Uuid,
+) -> Result<bool, AppError> {
+    let result = sqlx::query!(
+        r#"
+        WITH cancelled_job AS (
+            UPDATE audit_jobs SET state = 'cancelled', locked_at = NULL, updated_at = NOW()
+            WHERE audit_id = $1 AND state IN ('pending', 'running', 'failed')
+            RETURNING audit_id
+        ), cancelled AS (
+            UPDATE ai_audits SET status = 'cancelled', updated_at = NOW()
+            WHERE id IN (SELECT audit_id FROM cancelled_job) AND status = 'pending'
+            RETURNING id
+        ), counted AS (
+            UPDATE audit_stats_summary SET updated_at = GREATEST(updated_at, NOW())
+            WHERE EXISTS (SELECT 1 FROM cancelled)
+        )
+        SELECT EXISTS (SELECT 1 FROM ai_audits WHERE id = $1) AS "found!",
+            EXISTS (SELECT 1 FROM cancelled) AS "cancelled!"
+        "#,
+        id
+    )
+    .fetch_one(pool)
+    .await?;
+    if !result.found {
+        return Err(AppError::NotFound(format!("Audit {} not found", id)));
+    }
+    if result.cancelled {
+        let running_here = cancellation_tokens.cancel(id);
+        tracing::info!(audit = %id, running_here, "Audit cancelled.");
+    }
+    Ok(result.cancelled)
+}
+
+/// How often `audit_progress` checks whether an audit compiled by another replica got
+/// its verdict.
+const PROGRESS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What `audit_progress` waits for next.
+enum ProgressState {
+    /// The verdict of the audit, read from the database, once `validated` was sent.
+    Verdict { validated: bool },
+    /// The events of the job compiling the audit on this replica.
+    Job(broadcast::Receiver<AuditProgress>),
+    /// Nothing, the stream ended.
+    Done,
+}
+
+/// Streams the progress of an audit until it has its verdict.
+///
+/// An audit waiting for a background job is reported `validated`, then its job streams
+/// `compiling` events with the compiler output, if it runs on this replica. The stream
+/// ends with a `completed` event carrying the audit, right away for an audit that already
+/// has its verdict. The verdict of an audit compiled by another replica is polled from
+/// the database every [`PROGRESS_POLL_INTERVAL`].
+///
+/// # Arguments
+///
+/// * `pool` - A reference to the database connection pool.
+/// * `progress` - The channels publishing the progress of the jobs of this replica.
+/// * `id` - The UUID of the audit.
+///
+/// # Returns
+///
+/// * `impl Stream` - The events, or the error that ended the stream, such as
+///   `AppError::NotFound` if no audit has this ID.
+pub fn audit_progress(
+    pool: &PgPool,
+    progress: &ProgressChannels,
+    id: Uuid,
+) -> impl Stream<Item = Result<AuditProgress, AppError>> + use<> {
+    let (pool, progress) = (pool.clone(), progress.clone());
+    stream::unfold(ProgressState::Verdict { validated: false }, move |state| {
+        let (pool, progress) = (pool.clone(), progress.clone());
+        async move {
+            let mut state = state;
+            loop {
+                state = match state {
+                    ProgressState::Done => return None,
+                    ProgressState::Job(mut events) => match events.recv().await {
+                        Ok(event) if event.stage == AuditProgressStage::Completed => {
+                            return Some((Ok(event), ProgressState::Done));
+                        }
+                        Ok(event) => return Some((Ok(event), ProgressState::Job(events))),
+                        // A slow client misses some compiler output, never the verdict.
+                        Err(broadcast::error::RecvError::Lagged(_)) => ProgressState::Job(events),
+                        // The job stopped without a verdict, e.g. to be retried later.
+                        Err(broadcast::error::RecvError::Closed) => {
+                            ProgressState::Verdict { validated: true }
+                        }
+                    },
+                    ProgressState::Verdict { validated } => {
+                        let audit = match get_audit_by_id(&pool, id).await {
+                            Ok(Some(audit)) => audit,
+                            Ok(None) => {
+                                let e = AppError::NotFound(format!("Audit {} not found", id));
+                                return Some((Err(e), ProgressState::Done));
+                            }
+                            Err(e) => return Some((Err(e), ProgressState::Done)),
+                        };
+                        if audit.status != AuditStatus::Pending {
+                            let event = AuditProgress::completed(audit);
+                            return Some((Ok(event), ProgressState::Done));
+                        }
+                        if !validated {
+                            let event = AuditProgress::new(AuditProgressStage::Validated);
+                            return Some((Ok(event), ProgressState::Verdict { validated: true }));
+                        }
+                        match progress.subscribe(id) {
+                            Some(events) => ProgressState::Job(events),
+                            None => {
+                                tokio::time::sleep(PROGRESS_POLL_INTERVAL).await;
+                                ProgressState::Verdict { validated: true }
+                            }
+                        }
+                    }
+                };
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::{error::ErrorKind, postgres::PgPoolOptions};
+    use std::{
+        borrow::Cow,
+        collections::VecDeque,
+        sync::{
+            Mutex,
+            atomic::{AtomicU32, Ordering},
+        },
+    };
+
+    /// A database error with a SQLSTATE code, as Postgres would report it.
+    #[derive(Debug)]
+    struct FakeDatabaseError(&'static str);
+
+    impl std::fmt::Display for FakeDatabaseError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "error {}", self.0)
+        }
+    }
+
+    impl std::error::Error for FakeDatabaseError {}
+
+    impl sqlx::error::DatabaseError for FakeDatabaseError {
+        fn message(&self) -> &str {
+            self.0
+        }
+
+        fn code(&self) -> Option<Cow<'_, str>> {
+            Some(Cow::Borrowed(self.0))
+        }
+
+        fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+            self
+        }
+
+        fn kind(&self) -> ErrorKind {
+            match self.0 {
+                "23505" => ErrorKind::UniqueViolation,
+                _ => ErrorKind::Other,
+            }
+        }
+    }
+
+    /// A query failing with the errors it is given, one per attempt, then succeeding,
+    /// which counts its attempts.
+    struct FlakyQuery {
+        errors: Mutex<VecDeque<sqlx::Error>>,
+        attempts: AtomicU32,
+    }
+
+    impl FlakyQuery {
+        fn new(errors: impl IntoIterator<Item = sqlx::Error>) -> Self {
+            FlakyQuery {
+                errors: Mutex::new(errors.into_iter().collect()),
+                attempts: AtomicU32::new(0),
+            }
+        }
+
+        async fn run(&self) -> Result<&'static str, sqlx::Error> {
+            self.attempts.fetch_add(1, Ordering::SeqCst);
+            match self.errors.lock().unwrap().pop_front() {
+                Some(error) => Err(error),
+                None => Ok("row"),
+            }
+        }
+
+        fn attempts(&self) -> u32 {
+            self.attempts.load(Ordering::SeqCst)
+        }
+    }
+
+    fn connection_reset() -> sqlx::Error {
+        sqlx::Error::Io(std::io::Error::from(std::io::ErrorKind::ConnectionReset))
+    }
+
+    fn database_error(code: &'static str) -> sqlx::Error {
+        sqlx::Error::Database(Box::new(FakeDatabaseError(code)))
+    }
+
+    #[test]
+    fn only_connection_errors_are_transient() {
+        assert!(is_transient(&connection_reset()));
+        assert!(is_transient(&sqlx::Error::PoolTimedOut));
+        assert!(is_transient(&database_error("08006")));
+        assert!(is_transient(&database_error("57P01")));
+        assert!(!is_transient(&database_error("23505")));
+        assert!(!is_transient(&database_error("42601")));
+        assert!(!is_transient(&sqlx::Error::RowNotFound));
+    }
+
+    #[tokio::test]
+    async fn transient_errors_are_retried_until_the_query_succeeds() {
+        let query = FlakyQuery::new([connection_reset(), database_error("57P01")]);
+        let result = retry_transient("test", || query.run()).await;
+        assert_eq!(result.unwrap(), "row");
+        assert_eq!(query.attempts(), 3);
+    }
+
+    #[tokio::test]
+    async fn retries_stop_after_the_policy_allows() {
+        let max_retries = db_retry_policy().max_retries;
+        let query = FlakyQuery::new((0..=max_retries).map(|_| connection_reset()));
+        let result = retry_transient("test", || query.run()).await;
+        assert!(matches!(result, Err(sqlx::Error::Io(_))));
+        assert_eq!(query.attempts(), max_retries + 1);
+    }
+
+    #[tokio::test]
+    async fn logical_errors_are_not_retried() {
+        for error in [
+            database_error("23505"),
+            database_error("42601"),
+            sqlx::Error::RowNotFound,
+        ] {
+            let query = FlakyQuery::new([error]);
+            assert!(retry_transient("test", || query.run()).await.is_err());
+            assert_eq!(query.attempts(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn unreachable_databases_are_retried() {
+        let pool = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(100))
+            .connect_lazy("postgres://postgres@127.0.0.1:1/ai_auditor")
+            .unwrap();
+        let attempts = AtomicU32::new(0);
+        let result = retry_transient("test", || {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            sqlx::query_scalar::<_, i32>("SELECT 1").fetch_one(&pool)
+        })
+        .await;
+        let error = result.unwrap_err();
+        assert!(is_transient(&error), "{:?}", error);
+        assert_eq!(
+            attempts.load(Ordering::SeqCst),
+            db_retry_policy().max_retries + 1
+        );
+    }
 }
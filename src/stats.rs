@@ -0,0 +1,170 @@
+//! Memoizes the statistics served by `GET /stats`.
+//!
+//! Dashboards poll the statistics every few seconds, and aggregating every audit for each
+//! poll is expensive. Computed statistics are reused for a few seconds, then only
+//! recomputed if an audit was created, updated or deleted since; the same version check
+//! yields the entity tag clients revalidate with.
+
+use crate::{
+    error::AppError,
+    models::{Edition, StatsGroupBy, StatsResponse},
+    services,
+};
+use chrono::{DateTime, Utc};
+use lru::LruCache;
+use sqlx::PgPool;
+use std::{
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// How long computed statistics are served without checking for new audits.
+const CACHE_TTL: Duration = Duration::from_secs(5);
+
+/// The number of distinct groupings and pages whose statistics are kept in memory.
+const CACHE_CAPACITY: NonZeroUsize = NonZeroUsize::new(100).unwrap();
+
+/// The `Cache-Control` header sent with statistics, matching the in-memory cache.
+pub const CACHE_CONTROL: &str = "public, max-age=5";
+
+/// Identifies the statistics of a grouping and page, or of a range of daily counts, of
+/// the audits of an edition: `group_by`, `limit`, `offset`, `days` and `edition`.
+type StatsKey = (Option<StatsGroupBy>, i64, i64, i32, Option<Edition>);
+
+/// Statistics computed for a version of the audits.
+pub struct CachedStats {
+    /// The weak entity tag of the statistics, changing whenever an audit is created,
+    /// updated or deleted.
+    pub etag: String,
+    /// The statistics.
+    pub response: StatsResponse,
+}
+
+/// A cache entry: the statistics, the version of the audits they were computed for, and
+/// when that version was last checked.
+struct Entry {
+    /// When the version of the audits was last checked.
+    checked_at: Instant,
+    /// The number of audits and their last update when the statistics were computed.
+    version: (i64, Option<DateTime<Utc>>),
+    /// The statistics.
+    stats: Arc<CachedStats>,
+}
+
+/// Caches statistics, keyed by grouping and page.
+pub struct StatsCache {
+    /// The cached statistics, by grouping and page.
+    entries: Mutex<LruCache<StatsKey, Entry>>,
+}
+
+impl Default for StatsCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StatsCache {
+    /// Creates an empty cache.
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(LruCache::new(CACHE_CAPACITY)),
+        }
+    }
+
+    /// Returns the statistics of all audits, or of those compiled with `edition`, or of
+    /// their groups if `group_by` is set.
+    ///
+    /// Statistics checked less than [`CACHE_TTL`] ago are returned as is. Older ones are
+    /// returned if no audit changed since, and recomputed otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - A reference to the database connection pool.
+    /// * `group_by` - The property to group the audits by, if any.
+    /// * `limit` - The maximum number of buckets when grouping.
+    /// * `offset` - The number of buckets to skip when grouping.
+    /// * `days` - The number of days covered by the daily counts when not grouping.
+    /// * `edition` - The edition the audits are compiled with, or `None` for all.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Arc<CachedStats>)` - The statistics and their entity tag.
+    /// * `Err(AppError)` - If the statistics cannot be computed.
+    pub async fn stats(
+        &self,
+        pool: &PgPool,
+        group_by: Option<StatsGroupBy>,
+        limit: i64,
+        offset: i64,
+        days: i32,
+        edition: Option<Edition>,
+    ) -> Result<Arc<CachedStats>, AppError> {
+        // Pagination only applies to groups and daily counts to all-audit statistics, so
+        // entries only differ by the parameters that apply.
+        let (limit, offset, days) = if group_by.is_some() {
+            (limit, offset, 0)
+        } else {
+            (0, 0, days)
+        };
+        let key = (group_by, limit, offset, days, edition);
+        if let Some(entry) = self.lock_entries().get(&key)
+            && entry.checked_at.elapsed() < CACHE_TTL
+        {
+            return Ok(entry.stats.clone());
+        }
+
+        let version = services::get_audits_version(pool).await?;
+        if let Some(entry) = self.lock_entries().get_mut(&key)
+            && entry.version == version
+        {
+            entry.checked_at = Instant::now();
+            return Ok(entry.stats.clone());
+        }
+
+        let response = match group_by {
+            None => StatsResponse::Aggregate(Box::new(
+                services::get_audit_stats(pool, days, edition).await?,
+            )),
+            Some(group_by) => StatsResponse::Grouped(
+                services::get_grouped_stats(pool, group_by, limit, offset, edition).await?,
+            ),
+        };
+        let grouping = match group_by {
+            None => "all",
+            Some(StatsGroupBy::Day) => "day",
+            Some(StatsGroupBy::Model) => "model",
+            Some(StatsGroupBy::Tag) => "tag",
+            Some(StatsGroupBy::ErrorCode) => "error_code",
+        };
+        let (count, last_updated_at) = version;
+        let stats = Arc::new(CachedStats {
+            etag: format!(
+                "W/\"{}-{}-{}-{}-{}-{}-{}\"",
+                grouping,
+                limit,
+                offset,
+                days,
+                edition.map_or("all", Edition::as_str),
+                count,
+                last_updated_at.map_or(0, |t| t.timestamp_micros())
+            ),
+            response,
+        });
+        self.lock_entries().put(
+            key,
+            Entry {
+                checked_at: Instant::now(),
+                version,
+                stats: stats.clone(),
+            },
+        );
+        Ok(stats)
+    }
+
+    /// Locks the cached statistics.
+    fn lock_entries(&self) -> std::sync::MutexGuard<'_, LruCache<StatsKey, Entry>> {
+        // The cache holds no invariants that a panic could break, so recover from poisoning.
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,49 @@
+//! Exports the spans of the server to an OpenTelemetry collector over OTLP/HTTP.
+
+use anyhow::Context;
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{
+    Resource,
+    trace::{SdkTracer, SdkTracerProvider},
+};
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+/// The name the spans of the server are reported under.
+const SERVICE_NAME: &str = "rust-ai-auditor";
+
+/// Builds the layer sending the spans of the server to a collector.
+///
+/// The spans are exported in batches by a background thread. The batches can be tuned
+/// with the standard `OTEL_BSP_*` environment variables.
+///
+/// # Arguments
+///
+/// * `endpoint` - The base URL of the collector (see `AppConfig::otlp_endpoint`); the
+///   spans are sent to its `/v1/traces` path.
+///
+/// # Returns
+///
+/// * `Ok((OpenTelemetryLayer, SdkTracerProvider))` - The layer, and the provider to shut
+///   down when the server stops, so that the last spans are sent.
+/// * `Err(anyhow::Error)` - If the exporter cannot be built.
+pub fn otlp_layer<S>(
+    endpoint: &str,
+) -> anyhow::Result<(OpenTelemetryLayer<S, SdkTracer>, SdkTracerProvider)>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+        .build()
+        .context("Failed to build the OTLP exporter")?;
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build())
+        .build();
+    let layer = tracing_opentelemetry::layer().with_tracer(provider.tracer(SERVICE_NAME));
+    Ok((layer, provider))
+}
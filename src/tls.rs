@@ -0,0 +1,43 @@
+//! Loads the certificate used to serve HTTPS directly, without a reverse proxy.
+
+use anyhow::{Context, bail};
+use axum_server::tls_rustls::RustlsConfig;
+
+/// Builds the TLS configuration from the environment.
+///
+/// * `TLS_CERT_PATH` - The path of a PEM file holding the certificate chain.
+/// * `TLS_KEY_PATH` - The path of a PEM file holding the private key.
+///
+/// When neither variable is set, the server is served over plain HTTP.
+///
+/// # Returns
+///
+/// * `Ok(Some(RustlsConfig))` - If both variables are set and the files were loaded.
+/// * `Ok(None)` - If neither variable is set.
+/// * `Err(anyhow::Error)` - If only one variable is set, or if the certificate or key
+///   cannot be read or parsed.
+pub async fn tls_config_from_env() -> anyhow::Result<Option<RustlsConfig>> {
+    let cert_path = std::env::var("TLS_CERT_PATH")
+        .ok()
+        .filter(|v| !v.is_empty());
+    let key_path = std::env::var("TLS_KEY_PATH").ok().filter(|v| !v.is_empty());
+
+    let (cert_path, key_path) = match (cert_path, key_path) {
+        (Some(cert_path), Some(key_path)) => (cert_path, key_path),
+        (None, None) => return Ok(None),
+        (Some(_), None) => bail!("TLS_CERT_PATH is set but TLS_KEY_PATH is not"),
+        (None, Some(_)) => bail!("TLS_KEY_PATH is set but TLS_CERT_PATH is not"),
+    };
+
+    let config = RustlsConfig::from_pem_file(&cert_path, &key_path)
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to load TLS certificate from {} and key from {}",
+                cert_path, key_path
+            )
+        })?;
+
+    tracing::info!(cert = %cert_path, "TLS certificate loaded");
+    Ok(Some(config))
+}
@@ -0,0 +1,377 @@
+//! Reads audit requests uploaded as files instead of JSON.
+//!
+//! `POST /audit` accepts, besides its JSON body:
+//!
+//! * `multipart/form-data`, with a `prompt` field and one or more `code` file parts. A
+//!   `code` part may also be a zip archive, whose `.rs` files are read.
+//! * A raw `text/x-rust` (or `text/plain`) body holding the code, with the prompt in the
+//!   `X-Audit-Prompt` header or the `prompt` query parameter.
+//!
+//! The other fields of the JSON request are given as form fields or query parameters.
+//! Uploads of several files are bundled into a single source file: the `mod`
+//! declarations of the crate root are replaced by the files they refer to, as inline
+//! modules. JSON requests may keep the files apart with `files` instead.
+
+use crate::{error::AppError, models::CreateAuditRequest};
+use axum::{
+    Json,
+    body::Bytes,
+    extract::{FromRequest, Multipart, Query, Request},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use proc_macro2::LineColumn;
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{Cursor, Read},
+};
+
+/// The largest uploaded file, zip archive or file of a zip archive, in bytes.
+pub const MAX_FILE_BYTES: usize = 1024 * 1024;
+
+/// The largest number of source files an upload may hold.
+pub const MAX_FILES: usize = 100;
+
+/// The header carrying the prompt of raw code uploads.
+pub const PROMPT_HEADER: &str = "x-audit-prompt";
+
+/// The request of `POST /audit`, read from a JSON body, a multipart form or raw code.
+///
+/// Bodies that cannot be read (malformed forms or archives, code that is not UTF-8,
+/// fields of the wrong type) are rejected with `422 Unprocessable Entity`, and files
+/// larger than [`MAX_FILE_BYTES`] with `400 Bad Request`.
+pub struct AuditPayload(pub CreateAuditRequest);
+
+impl<S> FromRequest<S> for AuditPayload
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let content_type = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .map(|essence| essence.trim().to_ascii_lowercase());
+        let upload = match content_type.as_deref() {
+            Some("multipart/form-data") => from_multipart(req, state).await,
+            Some("text/x-rust" | "text/plain") => from_raw_code(req, state).await,
+            _ => {
+                return Json::<CreateAuditRequest>::from_request(req, state)
+                    .await
+                    .map(|Json(payload)| AuditPayload(payload))
+                    .map_err(IntoResponse::into_response);
+            }
+        };
+        upload
+            .map(AuditPayload)
+            .map_err(IntoResponse::into_response)
+    }
+}
+
+/// The fields of an uploaded request, other than its code.
+#[derive(Default)]
+struct UploadFields(Map<String, Value>);
+
+impl UploadFields {
+    /// Adds a field given as text. Values that are valid JSON (numbers, booleans,
+    /// objects) are read as such, others as strings; `tags` may be repeated.
+    fn add(&mut self, name: &str, value: &str) {
+        let value = if name == "prompt" {
+            Value::String(value.to_string())
+        } else {
+            serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()))
+        };
+        if name == "tags" {
+            let tags = self
+                .0
+                .entry("tags")
+                .or_insert_with(|| Value::Array(Vec::new()));
+            if let Value::Array(tags) = tags {
+                tags.push(value);
+            }
+        } else {
+            self.0.insert(name.to_string(), value);
+        }
+    }
+
+    /// Builds the request auditing `code`.
+    fn into_request(mut self, code: String) -> Result<CreateAuditRequest, AppError> {
+        if self.0.contains_key("generated_code") {
+            return Err(AppError::Unprocessable(
+                "Uploaded code cannot be combined with a generated_code field".to_string(),
+            ));
+        }
+        self.0
+            .insert("generated_code".to_string(), Value::String(code));
+        serde_json::from_value(Value::Object(self.0))
+            .map_err(|e| AppError::Unprocessable(format!("Invalid upload fields: {}", e)))
+    }
+}
+
+/// Reads a `multipart/form-data` upload.
+async fn from_multipart<S: Send + Sync>(
+    req: Request,
+    state: &S,
+) -> Result<CreateAuditRequest, AppError> {
+    let mut multipart = Multipart::from_request(req, state)
+        .await
+        .map_err(|e| AppError::Unprocessable(e.body_text()))?;
+    let mut fields = UploadFields::default();
+    let mut files = Vec::new();
+    while let Some(mut field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| AppError::Unprocessable(e.body_text()))?
+    {
+        let name = field.name().unwrap_or_default().to_string();
+        let file_name = field.file_name().map(str::to_string);
+        let is_zip = field.content_type().is_some_and(|t| t.contains("zip"))
+            || file_name.as_deref().is_some_and(|n| n.ends_with(".zip"));
+        let mut bytes = Vec::new();
+        while let Some(chunk) = field
+            .chunk()
+            .await
+            .map_err(|e| AppError::Unprocessable(e.body_text()))?
+        {
+            if bytes.len() + chunk.len() > MAX_FILE_BYTES {
+                return Err(too_large(file_name.as_deref().unwrap_or(&name)));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        if name != "code" {
+            fields.add(&name, &utf8(&name, bytes)?);
+        } else if is_zip {
+            files.extend(read_zip(&bytes)?);
+        } else {
+            let path = file_name.unwrap_or_else(|| "lib.rs".to_string());
+            let code = utf8(&path, bytes)?;
+            files.push((path, code));
+        }
+        if files.len() > MAX_FILES {
+            return Err(AppError::Validation(format!(
+                "An upload holds at most {} source files",
+                MAX_FILES
+            )));
+        }
+    }
+    if files.is_empty() {
+        return Err(AppError::Validation(
+            "The upload has no code file part".to_string(),
+        ));
+    }
+    fields.into_request(bundle(files)?)
+}
+
+/// Reads a raw code upload, whose other fields are query parameters.
+async fn from_raw_code<S: Send + Sync>(
+    req: Request,
+    state: &S,
+) -> Result<CreateAuditRequest, AppError> {
+    let mut fields = UploadFields::default();
+    let Query(params) = Query::<Vec<(String, String)>>::try_from_uri(req.uri())
+        .map_err(|e| AppError::Unprocessable(e.body_text()))?;
+    for (name, value) in &params {
+        fields.add(name, value);
+    }
+    if let Some(prompt) = req.headers().get(PROMPT_HEADER) {
+        let prompt = prompt.to_str().map_err(|_| {
+            AppError::Validation("X-Audit-Prompt header must be valid ASCII".to_string())
+        })?;
+        fields.add("prompt", prompt);
+    }
+    let body = Bytes::from_request(req, state)
+        .await
+        .map_err(|e| AppError::Unprocessable(e.body_text()))?;
+    if body.len() > MAX_FILE_BYTES {
+        return Err(too_large("The request body"));
+    }
+    fields.into_request(utf8("The request body", body.to_vec())?)
+}
+
+/// Reads the `.rs` files of a zip archive, ignoring its other files.
+fn read_zip(bytes: &[u8]) -> Result<Vec<(String, String)>, AppError> {
+    let invalid =
+        |e: zip::result::ZipError| AppError::Unprocessable(format!("Invalid zip archive: {}", e));
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).map_err(invalid)?;
+    let mut files = Vec::new();
+    for index in 0..archive.len() {
+        let entry = archive.by_index(index).map_err(invalid)?;
+        let Some(path) = entry.enclosed_name() else {
+            continue;
+        };
+        let path = path.to_string_lossy().replace('\\', "/");
+        if entry.is_dir() || !path.ends_with(".rs") {
+            continue;
+        }
+        if entry.size() > MAX_FILE_BYTES as u64 {
+            return Err(too_large(&path));
+        }
+        let mut code = Vec::new();
+        entry
+            .take(MAX_FILE_BYTES as u64 + 1)
+            .read_to_end(&mut code)
+            .map_err(|e| AppError::Unprocessable(format!("Invalid zip archive: {}", e)))?;
+        if code.len() > MAX_FILE_BYTES {
+            return Err(too_large(&path));
+        }
+        files.push((path.clone(), utf8(&path, code)?));
+    }
+    Ok(files)
+}
+
+/// Decodes an uploaded file or field, rejecting bytes that are not UTF-8.
+fn utf8(name: &str, bytes: Vec<u8>) -> Result<String, AppError> {
+    String::from_utf8(bytes)
+        .map_err(|_| AppError::Unprocessable(format!("{} is not valid UTF-8", name)))
+}
+
+/// The error of a file larger than [`MAX_FILE_BYTES`].
+fn too_large(name: &str) -> AppError {
+    AppError::Validation(format!("{} is larger than {} bytes", name, MAX_FILE_BYTES))
+}
+
+/// Bundles the source files of a crate into a single file.
+///
+/// The crate root is the shortest `lib.rs`, else the shortest `main.rs`, else the only
+/// file. Its `mod name;` declarations (and those of the files they refer to) are replaced
+/// by the content of `name.rs` or `name/mod.rs` next to it, as inline modules; files no
+/// declaration refers to are left out, as `cargo` would. Without a root, every file
+/// becomes a module of an empty crate, named after the file.
+///
+/// # Arguments
+///
+/// * `files` - The paths and contents of the files.
+///
+/// # Returns
+///
+/// * `Ok(String)` - The code of the crate.
+/// * `Err(AppError::Validation)` - If two files have the same path, or files without a
+///   root have the same name.
+pub fn bundle(files: Vec<(String, String)>) -> Result<String, AppError> {
+    let mut sources = BTreeMap::new();
+    for (path, code) in files {
+        let path = path.trim_start_matches("./").to_string();
+        if sources.insert(path.clone(), code).is_some() {
+            return Err(AppError::Validation(format!(
+                "The upload holds {} twice",
+                path
+            )));
+        }
+    }
+    let root = ["lib.rs", "main.rs"].iter().find_map(|name| {
+        sources
+            .keys()
+            .filter(|path| *path == name || path.ends_with(&format!("/{}", name)))
+            .min_by_key(|path| path.len())
+            .cloned()
+    });
+    let root = root.or_else(|| {
+        (sources.len() == 1)
+            .then(|| sources.keys().next().cloned())
+            .flatten()
+    });
+    match root {
+        Some(root) => {
+            let code = sources.remove(&root).unwrap_or_default();
+            let dir = root.rsplit_once('/').map_or("", |(dir, _)| dir).to_string();
+            Ok(inline_modules(&code, &dir, &mut sources))
+        }
+        None => {
+            let mut modules = BTreeSet::new();
+            let mut code = String::new();
+            for (path, source) in &sources {
+                let file = path.rsplit('/').next().unwrap_or(path);
+                let name = file.trim_end_matches(".rs").replace(['-', '.', ' '], "_");
+                if !modules.insert(name.clone()) {
+                    return Err(AppError::Validation(format!(
+                        "Several files would become the module {}; upload a lib.rs declaring them",
+                        name
+                    )));
+                }
+                code.push_str(&format!("pub mod {} {{\n{}\n}}\n", name, source));
+            }
+            Ok(code)
+        }
+    }
+}
+
+/// Replaces the `mod name;` declarations of `code` by inline modules holding the files
+/// they refer to in `dir`, taken out of `sources`. Code that does not parse is kept as
+/// is, for the compiler to report.
+fn inline_modules(code: &str, dir: &str, sources: &mut BTreeMap<String, String>) -> String {
+    let Ok(file) = syn::parse_file(code) else {
+        return code.to_string();
+    };
+    let mut replacements = Vec::new();
+    collect_modules(&file.items, dir, sources, &mut replacements);
+
+    let mut code = code.to_string();
+    // Replace from the end so that the positions of earlier declarations stay valid.
+    replacements.sort_by_key(|(at, _)| std::cmp::Reverse((at.line, at.column)));
+    for (at, module) in replacements {
+        if let Some(offset) = byte_offset(&code, at) {
+            code.replace_range(offset..offset + 1, &module);
+        }
+    }
+    code
+}
+
+/// Collects the `mod name;` declarations among `items` whose file is in `sources`, with
+/// the position of their `;` and the inline module replacing it.
+fn collect_modules(
+    items: &[syn::Item],
+    dir: &str,
+    sources: &mut BTreeMap<String, String>,
+    replacements: &mut Vec<(LineColumn, String)>,
+) {
+    use syn::ext::IdentExt;
+
+    for item in items {
+        let syn::Item::Mod(module) = item else {
+            continue;
+        };
+        let name = module.ident.unraw().to_string();
+        let module_dir = if dir.is_empty() {
+            name.clone()
+        } else {
+            format!("{}/{}", dir, name)
+        };
+        match (&module.content, module.semi) {
+            (Some((_, items)), _) => collect_modules(items, &module_dir, sources, replacements),
+            (None, Some(semi)) => {
+                // `#[path]` attributes point outside of the upload's layout.
+                if module.attrs.iter().any(|attr| attr.path().is_ident("path")) {
+                    continue;
+                }
+                let source = [
+                    format!("{}.rs", module_dir),
+                    format!("{}/mod.rs", module_dir),
+                ]
+                .iter()
+                .find_map(|path| sources.remove(path));
+                if let Some(source) = source {
+                    let inlined = inline_modules(&source, &module_dir, sources);
+                    replacements.push((semi.span.start(), format!(" {{\n{}\n}}", inlined)));
+                }
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+/// Converts a line (1-based) and column (0-based, in characters) into a byte offset.
+fn byte_offset(code: &str, at: LineColumn) -> Option<usize> {
+    let line_start = if at.line == 1 {
+        0
+    } else {
+        code.match_indices('\n').nth(at.line - 2)?.0 + 1
+    };
+    code[line_start..]
+        .char_indices()
+        .nth(at.column)
+        .map(|(offset, _)| line_start + offset)
+}
@@ -0,0 +1,136 @@
+//! Notifies the registered webhooks when audits complete.
+//!
+//! Each notification is a JSON `POST` signed with the webhook's secret, in the same way
+//! GitHub signs its own webhooks: `X-Signature-256: sha256=<hex HMAC-SHA256 of the body>`.
+
+use crate::{error::AppError, models::AiAudit};
+use hmac::{Hmac, KeyInit, Mac};
+use serde_json::json;
+use sha2::Sha256;
+use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// The event sent once an audit has been created and its verdict recorded.
+pub const AUDIT_COMPLETED: &str = "audit.completed";
+
+/// Every event a webhook can subscribe to.
+pub const EVENTS: &[&str] = &[AUDIT_COMPLETED];
+
+/// The number of times a failed delivery is retried.
+const MAX_DELIVERY_RETRIES: u32 = 3;
+
+/// The delay before the first retry, doubled before each following one.
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Sends notifications to the registered webhooks.
+#[derive(Clone)]
+pub struct WebhookNotifier {
+    http: reqwest::Client,
+}
+
+impl WebhookNotifier {
+    /// Creates a notifier with its own HTTP client.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(WebhookNotifier)` - The notifier.
+    /// * `Err(reqwest::Error)` - If the HTTP client cannot be created.
+    pub fn new() -> Result<Self, reqwest::Error> {
+        let http = reqwest::Client::builder()
+            .user_agent(concat!("rust-ai-auditor/", env!("CARGO_PKG_VERSION")))
+            .timeout(Duration::from_secs(10))
+            .build()?;
+        Ok(WebhookNotifier { http })
+    }
+
+    /// Notifies the active webhooks subscribed to `audit.completed` about an audit.
+    ///
+    /// Delivery happens in the background: this returns immediately, and failures are
+    /// only logged.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - A reference to the database connection pool.
+    /// * `audit` - The completed audit.
+    pub fn audit_completed(&self, pool: &PgPool, audit: &AiAudit) {
+        let body = match serde_json::to_vec(&json!({ "event": AUDIT_COMPLETED, "audit": audit })) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::error!(error = %e, "Could not serialize the webhook payload.");
+                return;
+            }
+        };
+        let (pool, http) = (pool.clone(), self.http.clone());
+        tokio::spawn(async move {
+            let targets = match subscribed_webhooks(&pool, AUDIT_COMPLETED).await {
+                Ok(targets) => targets,
+                Err(e) => {
+                    tracing::error!(error = %e, "Could not load the webhooks to notify.");
+                    return;
+                }
+            };
+            for (id, url, secret) in targets {
+                tokio::spawn(deliver(http.clone(), id, url, secret, body.clone()));
+            }
+        });
+    }
+}
+
+/// Retrieves the ID, URL and secret of the active webhooks subscribed to an event.
+async fn subscribed_webhooks(
+    pool: &PgPool,
+    event: &str,
+) -> Result<Vec<(Uuid, String, String)>, AppError> {
+    let webhooks = sqlx::query!(
+        "SELECT id, url, secret FROM webhooks WHERE active AND $1 = ANY(events)",
+        event
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(webhooks
+        .into_iter()
+        .map(|webhook| (webhook.id, webhook.url, webhook.secret))
+        .collect())
+}
+
+/// POSTs a notification to a webhook, retrying with exponential backoff until it is
+/// accepted with a 2xx status or the retries are exhausted.
+async fn deliver(http: reqwest::Client, id: Uuid, url: String, secret: String, body: Vec<u8>) {
+    let signature = sign(&secret, &body);
+    let mut delay = INITIAL_RETRY_DELAY;
+    for attempt in 0..=MAX_DELIVERY_RETRIES {
+        if attempt > 0 {
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+        let response = http
+            .post(&url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("X-Signature-256", &signature)
+            .body(body.clone())
+            .send()
+            .await;
+        match response {
+            Ok(response) if response.status().is_success() => {
+                tracing::debug!(webhook = %id, attempt, "Webhook notified.");
+                return;
+            }
+            Ok(response) => {
+                tracing::warn!(webhook = %id, attempt, status = %response.status(), "Webhook rejected the notification.");
+            }
+            Err(e) => {
+                tracing::warn!(webhook = %id, attempt, error = %e, "Could not reach webhook.");
+            }
+        }
+    }
+    tracing::error!(webhook = %id, "Giving up on webhook notification.");
+}
+
+/// Computes the `X-Signature-256` header value of a body.
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
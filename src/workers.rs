@@ -0,0 +1,397 @@
+//! A fixed pool of workers compiling audited code in the background.
+//!
+//! Compiling runs `rustc`, which is slow and CPU-bound. Every compilation goes through
+//! the shared queue so that the number of concurrent `rustc` processes never exceeds
+//! the configured number of workers, whatever the number of concurrent requests.
+
+use crate::{
+    advisories::AdvisoryDatabase,
+    artifacts::ArtifactSettings,
+    auditor::{self, CompilationOutcome, CompileOptions, CompilerOutput},
+    cache::{self, AuditResultCache, CacheSettings},
+    error::AppError,
+    models::WorkerStats,
+    pipeline::JudgedCode,
+};
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::sync::{Mutex, Notify, mpsc, oneshot};
+
+/// The number of workers used when `AUDIT_WORKER_CONCURRENCY` is not set.
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+/// How long a compilation may run when `COMPILATION_TIMEOUT_SECS` is not set.
+pub const DEFAULT_COMPILATION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The number of jobs that may wait in the queue before submitters have to wait.
+const QUEUE_CAPACITY: usize = 256;
+
+/// How often `drain` checks whether the workers are idle.
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Whether the server is shutting down, shared by everything that starts audits.
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownFlag(Arc<ShutdownState>);
+
+/// The state behind a `ShutdownFlag`.
+#[derive(Debug, Default)]
+struct ShutdownState {
+    triggered: AtomicBool,
+    notify: Notify,
+}
+
+impl ShutdownFlag {
+    /// Marks the server as shutting down. New audits are refused from then on.
+    pub fn trigger(&self) {
+        self.0.triggered.store(true, Ordering::SeqCst);
+        self.0.notify.notify_waiters();
+    }
+
+    /// Returns whether the server is shutting down.
+    pub fn is_triggered(&self) -> bool {
+        self.0.triggered.load(Ordering::SeqCst)
+    }
+
+    /// Waits until the server starts shutting down.
+    pub async fn triggered(&self) {
+        // Wait on the notification before checking, so a concurrent trigger is not missed.
+        let notified = self.0.notify.notified();
+        if !self.is_triggered() {
+            notified.await;
+        }
+    }
+
+    /// Checks that a new audit may be started.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the server is not shutting down.
+    /// * `Err(AppError::Unavailable)` - If it is.
+    pub fn check(&self) -> Result<(), AppError> {
+        if self.is_triggered() {
+            return Err(AppError::Unavailable(
+                "The server is shutting down, retry later".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A request to compile code, answered through `reply`.
+///
+/// If `output` is set, the compiler output is sent to it line by line, and the
+/// compilation is cancelled as soon as its receiver is dropped.
+struct CompilationJob {
+    code: String,
+    options: CompileOptions,
+    output: Option<mpsc::Sender<CompilerOutput>>,
+    reply: oneshot::Sender<Result<CompilationOutcome, AppError>>,
+}
+
+/// Counters describing the activity of the compilation workers.
+#[derive(Debug, Default)]
+pub struct WorkerMetrics {
+    /// The number of workers currently compiling.
+    active_workers: AtomicU64,
+    /// The number of jobs waiting for a worker.
+    queue_depth: AtomicU64,
+    /// The number of jobs completed, successfully or not.
+    total_processed: AtomicU64,
+    /// The number of jobs for which `rustc` could not be run.
+    total_failed: AtomicU64,
+}
+
+impl WorkerMetrics {
+    /// Returns a snapshot of the counters.
+    pub fn stats(&self) -> WorkerStats {
+        WorkerStats {
+            active_workers: self.active_workers.load(Ordering::Relaxed),
+            queue_depth: self.queue_depth.load(Ordering::Relaxed),
+            total_processed: self.total_processed.load(Ordering::Relaxed),
+            total_failed: self.total_failed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The handle used to submit code to the compilation workers.
+#[derive(Clone)]
+pub struct CompilationQueue {
+    sender: mpsc::Sender<CompilationJob>,
+    /// The counters shared with the workers.
+    pub metrics: Arc<WorkerMetrics>,
+    /// The cache consulted by `compile_cached`.
+    cache: CacheSettings,
+    /// The store the artifacts of audits are uploaded to.
+    artifacts: ArtifactSettings,
+    /// The results of recent audits, reused for code submitted again.
+    audit_results: Arc<AuditResultCache<JudgedCode>>,
+    /// The advisory database the dependencies of compiled packages are checked against.
+    advisories: AdvisoryDatabase,
+    /// The number of workers compiling jobs.
+    concurrency: usize,
+}
+
+impl CompilationQueue {
+    /// Spawns `concurrency` long-running workers sharing one job queue.
+    ///
+    /// The workers stop once every handle to the queue has been dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `concurrency` - The number of workers, and so of `rustc` processes at most.
+    /// * `timeout` - How long a compilation may run before `rustc` is killed.
+    pub fn start(concurrency: usize, timeout: Duration) -> Self {
+        tracing::info!(
+            concurrency,
+            timeout_secs = timeout.as_secs(),
+            "Starting compilation workers"
+        );
+        let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let metrics = Arc::new(WorkerMetrics::default());
+
+        for worker in 0..concurrency {
+            tokio::spawn(run_worker(
+                worker,
+                receiver.clone(),
+                metrics.clone(),
+                timeout,
+            ));
+        }
+        CompilationQueue {
+            sender,
+            metrics,
+            cache: CacheSettings::default(),
+            artifacts: ArtifactSettings::default(),
+            audit_results: Arc::new(AuditResultCache::default()),
+            advisories: AdvisoryDatabase::default(),
+            concurrency,
+        }
+    }
+
+    /// Returns the number of compilations run at the same time.
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    /// Makes `compile_cached` reuse the results stored in `cache`.
+    pub fn with_cache(mut self, cache: CacheSettings) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Makes the outputs of the compilations of audits be kept in `artifacts`.
+    pub fn with_artifacts(mut self, artifacts: ArtifactSettings) -> Self {
+        self.artifacts = artifacts;
+        self
+    }
+
+    /// Returns the store the artifacts of audits are uploaded to.
+    pub fn artifacts(&self) -> &ArtifactSettings {
+        &self.artifacts
+    }
+
+    /// Makes audits reuse the results of recent audits of the same code kept in
+    /// `audit_results`.
+    pub fn with_audit_results(mut self, audit_results: AuditResultCache<JudgedCode>) -> Self {
+        self.audit_results = Arc::new(audit_results);
+        self
+    }
+
+    /// Returns the in-memory cache of the results of recent audits.
+    pub fn audit_results(&self) -> &AuditResultCache<JudgedCode> {
+        &self.audit_results
+    }
+
+    /// Makes the dependencies of compiled Cargo packages be checked against `advisories`.
+    pub fn with_advisories(mut self, advisories: AdvisoryDatabase) -> Self {
+        self.advisories = advisories;
+        self
+    }
+
+    /// Returns the advisory database the dependencies of compiled packages are checked
+    /// against.
+    pub fn advisories(&self) -> &AdvisoryDatabase {
+        &self.advisories
+    }
+
+    /// Waits until no job is queued or being compiled.
+    ///
+    /// Jobs submitted meanwhile are waited for as well, so new submissions should be
+    /// stopped first (see `ShutdownFlag`).
+    pub async fn drain(&self) {
+        loop {
+            let stats = self.metrics.stats();
+            if stats.queue_depth == 0 && stats.active_workers == 0 {
+                return;
+            }
+            tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Compiles `code` on one of the workers and waits for the result.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The Rust code to compile.
+    /// * `options` - The toolchain and flags to compile with.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(CompilationOutcome)` - The result of the compilation, whether it succeeded or not.
+    /// * `Err(AppError::Audit)` - If `rustc` could not be run or timed out, or the workers
+    ///   have stopped.
+    pub async fn compile(
+        &self,
+        code: String,
+        options: CompileOptions,
+    ) -> Result<CompilationOutcome, AppError> {
+        self.submit(code, options, None).await
+    }
+
+    /// Compiles `code` like `compile`, unless the same code was already compiled with the
+    /// same settings and toolchain and its result is still cached.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The Rust code to compile.
+    /// * `options` - The toolchain and flags to compile with.
+    /// * `output` - The channel receiving the compiler output line by line, like with
+    ///   `compile_streaming`, if any. A cached result sends no output.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(CompilationOutcome)` - The result of the compilation, whether it succeeded or not.
+    /// * `Err(AppError::Audit)` - If `rustc` could not be run or the workers have stopped.
+    pub async fn compile_cached(
+        &self,
+        code: String,
+        options: CompileOptions,
+        output: Option<mpsc::Sender<CompilerOutput>>,
+    ) -> Result<CompilationOutcome, AppError> {
+        let key = cache::cache_key(&code, &options);
+        if let Some(outcome) = self.cache.cache.get(&key).await {
+            tracing::debug!(key, "Compilation cache hit.");
+            return Ok(outcome);
+        }
+        let outcome = self.submit(code, options, output).await?;
+        self.cache.cache.set(&key, &outcome, self.cache.ttl).await;
+        Ok(outcome)
+    }
+
+    /// Compiles `code` on one of the workers, sending the compiler output to `output`
+    /// line by line, and waits for the result.
+    ///
+    /// Dropping the receiver of `output` kills `rustc`.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The Rust code to compile.
+    /// * `options` - The toolchain and flags to compile with.
+    /// * `output` - The channel receiving the compiler output.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(CompilationOutcome)` - The result of the compilation, whether it succeeded or not.
+    /// * `Err(AppError::Audit)` - If `rustc` could not be run, the compilation was
+    ///   cancelled or the workers have stopped.
+    pub async fn compile_streaming(
+        &self,
+        code: String,
+        options: CompileOptions,
+        output: mpsc::Sender<CompilerOutput>,
+    ) -> Result<CompilationOutcome, AppError> {
+        self.submit(code, options, Some(output)).await
+    }
+
+    /// Queues a compilation job and waits for its result.
+    async fn submit(
+        &self,
+        code: String,
+        options: CompileOptions,
+        output: Option<mpsc::Sender<CompilerOutput>>,
+    ) -> Result<CompilationOutcome, AppError> {
+        let (reply, outcome) = oneshot::channel();
+        self.metrics.queue_depth.fetch_add(1, Ordering::Relaxed);
+        if self
+            .sender
+            .send(CompilationJob {
+                code,
+                options,
+                output,
+                reply,
+            })
+            .await
+            .is_err()
+        {
+            self.metrics.queue_depth.fetch_sub(1, Ordering::Relaxed);
+            return Err(AppError::Audit(
+                "Compilation workers are not running".to_string(),
+            ));
+        }
+        outcome
+            .await
+            .map_err(|_| AppError::Audit("Compilation worker stopped unexpectedly".to_string()))?
+    }
+}
+
+/// Runs compilation jobs from the shared queue until it is closed, killing `rustc` when
+/// a compilation takes longer than `timeout`.
+async fn run_worker(
+    worker: usize,
+    receiver: Arc<Mutex<mpsc::Receiver<CompilationJob>>>,
+    metrics: Arc<WorkerMetrics>,
+    timeout: Duration,
+) {
+    loop {
+        // Only hold the lock while waiting for a job, so other workers can take the next one.
+        let Some(job) = receiver.lock().await.recv().await else {
+            break;
+        };
+        metrics.queue_depth.fetch_sub(1, Ordering::Relaxed);
+        metrics.active_workers.fetch_add(1, Ordering::Relaxed);
+
+        let (code, options) = (job.code, job.options);
+        // Dropping the compilation future kills rustc.
+        let compilation = async {
+            match &job.output {
+                Some(output) => {
+                    tokio::select! {
+                        outcome = auditor::stream_compilation(&code, &options, output) => outcome,
+                        _ = output.closed() => {
+                            Err(AppError::Audit("Compilation cancelled".to_string()))
+                        }
+                    }
+                }
+                None => auditor::check_compilation(&code, &options).await,
+            }
+        };
+        let outcome = tokio::time::timeout(timeout, compilation)
+            .await
+            .unwrap_or_else(|_| {
+                tracing::warn!(
+                    worker,
+                    timeout_secs = timeout.as_secs(),
+                    "Compilation timed out."
+                );
+                Err(AppError::Audit(format!(
+                    "Compilation timed out after {} seconds",
+                    timeout.as_secs()
+                )))
+            });
+
+        metrics.active_workers.fetch_sub(1, Ordering::Relaxed);
+        metrics.total_processed.fetch_add(1, Ordering::Relaxed);
+        if outcome.is_err() {
+            metrics.total_failed.fetch_add(1, Ordering::Relaxed);
+        }
+        // The submitter may have gone away (e.g. the client disconnected).
+        let _ = job.reply.send(outcome);
+    }
+    tracing::debug!(worker, "Compilation worker stopped.");
+}
@@ -0,0 +1,158 @@
+//! Tests of the check of the dependencies of Cargo packages against the RustSec advisory
+//! database.
+//!
+//! The servers use the snapshot of the database vendored in `tests/fixtures/advisory-db`,
+//! in which `rsa` 0.9.10 is affected by RUSTSEC-2023-0071 (a medium CVSS severity).
+
+mod common;
+
+use common::{TestServer, package};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+use std::time::{Duration, Instant};
+
+/// The vendored snapshot of the advisory database.
+const SNAPSHOT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/advisory-db");
+
+/// How long a background audit may take, resolving its dependencies included.
+const JOB_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Returns the `RAA0203` findings of an audit.
+fn vulnerabilities(audit: &Value) -> Vec<&Value> {
+    audit["findings"]
+        .as_array()
+        .expect("the audit has no findings")
+        .iter()
+        .filter(|finding| finding["code"] == "RAA0203")
+        .collect()
+}
+
+#[sqlx::test]
+async fn vulnerable_dependencies_are_reported(pool: PgPool) {
+    let server = TestServer::start_with(&pool, &[("ADVISORY_DB_PATH", SNAPSHOT)]).await;
+    let audit = server
+        .create_audit_with(json!({ "files": package("rsa = \"=0.9.10\"\n") }))
+        .await;
+
+    assert_eq!(audit["status"], "valid", "{}", audit);
+    assert_eq!(audit["vulnerability_count"], 1);
+    assert!(audit["advisory_note"].is_null());
+    let findings = vulnerabilities(&audit);
+    assert_eq!(findings.len(), 1, "{:?}", findings);
+    assert_eq!(findings[0]["severity"], "warning");
+    assert_eq!(findings[0]["category"], "security");
+    assert_eq!(findings[0]["file"], "Cargo.toml");
+    let message = findings[0]["message"].as_str().unwrap();
+    assert!(message.contains("rsa 0.9.10"), "{}", message);
+    assert!(message.contains("RUSTSEC-2023-0071"), "{}", message);
+    assert!(message.contains("Marvin Attack"), "{}", message);
+
+    let stats: Value = server
+        .client()
+        .get(server.url("/stats"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(stats["vulnerability_count"], 1, "{}", stats);
+    let response = server
+        .graphql(
+            "query($id: UUID!) { audit(id: $id) { vulnerabilityCount advisoryNote } \
+             stats { vulnerabilityCount } }",
+            json!({ "id": audit["id"] }),
+        )
+        .await;
+    assert_eq!(
+        response["data"],
+        json!({
+            "audit": { "vulnerabilityCount": 1, "advisoryNote": null },
+            "stats": { "vulnerabilityCount": 1 },
+        }),
+        "{}",
+        response
+    );
+}
+
+#[sqlx::test]
+async fn background_audits_check_their_dependencies(pool: PgPool) {
+    let server = TestServer::start_with(&pool, &[("ADVISORY_DB_PATH", SNAPSHOT)]).await;
+    let response = server
+        .client()
+        .post(server.url("/audit/async"))
+        .json(&json!({
+            "prompt": "Write a signer",
+            "files": package("rsa = \"=0.9.10\"\n"),
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let job: Value = response.json().await.unwrap();
+
+    let url = server.url(&format!("/audit/job/{}", job["id"].as_str().unwrap()));
+    let started = Instant::now();
+    let job = loop {
+        let job: Value = server
+            .client()
+            .get(&url)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        if job["state"] != "pending" && job["state"] != "running" {
+            break job;
+        }
+        assert!(started.elapsed() < JOB_TIMEOUT, "the job did not finish");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    };
+    assert_eq!(job["state"], "done", "{}", job);
+    assert_eq!(job["audit"]["vulnerability_count"], 1, "{}", job);
+    assert_eq!(vulnerabilities(&job["audit"]).len(), 1);
+}
+
+#[sqlx::test]
+async fn dependencies_without_advisories_are_not_reported(pool: PgPool) {
+    let server = TestServer::start_with(&pool, &[("ADVISORY_DB_PATH", SNAPSHOT)]).await;
+    let audit = server
+        .create_audit_with(json!({ "files": package("") }))
+        .await;
+
+    assert_eq!(audit["status"], "valid", "{}", audit);
+    assert_eq!(audit["vulnerability_count"], 0);
+    assert!(audit["advisory_note"].is_null());
+    assert!(vulnerabilities(&audit).is_empty());
+}
+
+#[sqlx::test]
+async fn an_unavailable_database_is_noted_on_the_audit(pool: PgPool) {
+    let path = std::env::temp_dir().join(format!("advisory-db-{}", uuid::Uuid::new_v4()));
+    let server = TestServer::start_with(
+        &pool,
+        &[
+            ("ADVISORY_DB_PATH", path.to_str().unwrap()),
+            // Nothing listens on port 1, so the database cannot be fetched.
+            ("ADVISORY_DB_URL", "http://127.0.0.1:1/advisory-db"),
+        ],
+    )
+    .await;
+    let audit = server
+        .create_audit_with(json!({ "files": package("") }))
+        .await;
+
+    assert_eq!(audit["status"], "valid", "{}", audit);
+    assert_eq!(audit["advisory_note"], "advisory DB unavailable");
+    assert!(audit["vulnerability_count"].is_null());
+    assert!(!path.exists());
+
+    // Single files have no dependencies to check.
+    let audit = server
+        .create_audit("pub fn answer() -> u32 {\n    42\n}\n")
+        .await;
+    assert!(audit["advisory_note"].is_null());
+    assert!(audit["vulnerability_count"].is_null());
+}
@@ -0,0 +1,110 @@
+//! Tests of Automatic Persisted Queries.
+
+mod common;
+
+use common::TestServer;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+const QUERY: &str = "{ audits { id } }";
+
+fn sha256(query: &str) -> String {
+    hex::encode(Sha256::digest(query.as_bytes()))
+}
+
+async fn send(server: &TestServer, query: Option<&str>, hash: &str) -> Value {
+    let mut body = json!({
+        "extensions": { "persistedQuery": { "version": 1, "sha256Hash": hash } }
+    });
+    if let Some(query) = query {
+        body["query"] = json!(query);
+    }
+    server
+        .client()
+        .post(server.url("/graphql"))
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+async fn stats(server: &TestServer) -> Value {
+    server
+        .client()
+        .get(server.url("/metrics/apq"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn unknown_hash_is_registered_then_hit(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let hash = sha256(QUERY);
+
+    // The server does not know the hash yet.
+    let response = send(&server, None, &hash).await;
+    assert_eq!(
+        response["errors"][0]["extensions"]["code"],
+        "PERSISTED_QUERY_NOT_FOUND"
+    );
+
+    // The client retries with the query, which registers it.
+    let response = send(&server, Some(QUERY), &hash).await;
+    assert!(response["errors"].is_null(), "{}", response);
+    assert_eq!(response["data"]["audits"], json!([]));
+
+    // The hash alone is now enough.
+    let response = send(&server, None, &hash).await;
+    assert!(response["errors"].is_null(), "{}", response);
+    assert_eq!(response["data"]["audits"], json!([]));
+
+    let stats = stats(&server).await;
+    assert_eq!(stats["hits"], 1);
+    assert_eq!(stats["misses"], 1);
+    assert_eq!(stats["registrations"], 1);
+    assert_eq!(stats["hit_rate"], 0.5);
+}
+
+#[sqlx::test]
+async fn hash_not_matching_the_query_is_rejected(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let wrong_hash = sha256("{ __typename }");
+
+    let response = send(&server, Some(QUERY), &wrong_hash).await;
+    assert_eq!(
+        response["errors"][0]["message"],
+        "provided sha does not match query"
+    );
+    assert!(response["data"].is_null());
+
+    // Nothing was registered under the wrong hash.
+    let response = send(&server, None, &wrong_hash).await;
+    assert_eq!(
+        response["errors"][0]["extensions"]["code"],
+        "PERSISTED_QUERY_NOT_FOUND"
+    );
+    assert_eq!(stats(&server).await["registrations"], 0);
+}
+
+#[sqlx::test]
+async fn persisted_queries_survive_restarts(pool: PgPool) {
+    let hash = sha256(QUERY);
+    {
+        let server = TestServer::start_with(&pool, &[("APQ_PERSIST", "true")]).await;
+        let response = send(&server, Some(QUERY), &hash).await;
+        assert!(response["errors"].is_null(), "{}", response);
+    }
+
+    let server = TestServer::start_with(&pool, &[("APQ_PERSIST", "true")]).await;
+    let response = send(&server, None, &hash).await;
+    assert!(response["errors"].is_null(), "{}", response);
+    assert_eq!(response["data"]["audits"], json!([]));
+}
@@ -0,0 +1,231 @@
+//! Tests of the artifact stores keeping the files produced by compilations.
+
+mod common;
+
+use axum::{
+    Router,
+    body::Bytes,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode, header},
+    response::IntoResponse,
+    routing::get,
+};
+use common::TestServer;
+use serde_json::Value;
+use sqlx::PgPool;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+const CODE: &str = "pub fn answer() -> u32 {\n    42\n}\n";
+
+/// The bucket of the fake S3 server.
+const BUCKET: &str = "audit-artifacts";
+
+/// The objects stored by a fake S3 server, by path, and whether it refuses uploads.
+#[derive(Clone, Default)]
+struct Bucket {
+    objects: Arc<Mutex<HashMap<String, Bytes>>>,
+    refuse_uploads: bool,
+}
+
+/// Starts a fake S3 server answering path-style `PUT` and `GET` requests, and returns its
+/// URL.
+async fn start_s3(bucket: Bucket) -> String {
+    async fn put(
+        State(bucket): State<Bucket>,
+        Path(path): Path<String>,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> impl IntoResponse {
+        assert!(
+            headers[header::AUTHORIZATION]
+                .to_str()
+                .unwrap()
+                .starts_with("AWS4-HMAC-SHA256 ")
+        );
+        if bucket.refuse_uploads {
+            return StatusCode::FORBIDDEN.into_response();
+        }
+        let mut objects = bucket.objects.lock().unwrap();
+        let etag = format!("\"{}\"", objects.len());
+        objects.insert(path, body);
+        // The client needs the ETag of an uploaded object.
+        (StatusCode::OK, [(header::ETAG, etag)]).into_response()
+    }
+
+    async fn fetch(State(bucket): State<Bucket>, Path(path): Path<String>) -> impl IntoResponse {
+        match bucket.objects.lock().unwrap().get(&path) {
+            Some(data) => data.clone().into_response(),
+            None => StatusCode::NOT_FOUND.into_response(),
+        }
+    }
+
+    let app = Router::new()
+        .route("/{*path}", get(fetch).put(put))
+        .with_state(bucket);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    url
+}
+
+/// Starts a server keeping its artifacts in the bucket of the fake S3 server at `url`.
+async fn start_with_s3(pool: &PgPool, url: &str) -> TestServer {
+    TestServer::start_with(
+        pool,
+        &[
+            ("ARTIFACT_S3_BUCKET", BUCKET),
+            ("AWS_ENDPOINT", url),
+            ("AWS_ALLOW_HTTP", "true"),
+            ("AWS_REGION", "us-east-1"),
+            ("AWS_ACCESS_KEY_ID", "test"),
+            ("AWS_SECRET_ACCESS_KEY", "test"),
+        ],
+    )
+    .await
+}
+
+/// Waits for the artifacts of an audit to be recorded, and returns them.
+async fn uploaded_artifacts(server: &TestServer, audit: &Value) -> Value {
+    let url = server.url(&format!(
+        "/audit/{}/artifacts",
+        audit["id"].as_str().unwrap()
+    ));
+    let deadline = Instant::now() + Duration::from_secs(60);
+    loop {
+        let artifacts: Value = server
+            .client()
+            .get(&url)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        if !artifacts["uploaded_at"].is_null() {
+            return artifacts;
+        }
+        assert!(Instant::now() < deadline, "the artifacts were not uploaded");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+fn names(artifacts: &Value) -> Vec<&str> {
+    artifacts["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|file| file["name"].as_str().unwrap())
+        .collect()
+}
+
+#[sqlx::test]
+async fn the_filesystem_store_keeps_the_files_of_audits(pool: PgPool) {
+    let dir = std::env::temp_dir().join(format!("artifacts-{}", uuid::Uuid::new_v4()));
+    let server = TestServer::start_with(&pool, &[("ARTIFACT_DIR", dir.to_str().unwrap())]).await;
+    let audit = server.create_audit(CODE).await;
+    assert_eq!(audit["status"], "valid");
+
+    let artifacts = uploaded_artifacts(&server, &audit).await;
+    assert!(artifacts["error"].is_null(), "{}", artifacts);
+    assert_eq!(
+        names(&artifacts),
+        ["diagnostics.json", "clippy.json", "lib.rlib"]
+    );
+    for file in artifacts["files"].as_array().unwrap() {
+        assert!(file["expires_at"].is_null());
+        let stored = std::fs::read(dir.join(file["key"].as_str().unwrap())).unwrap();
+        let response = server
+            .client()
+            .get(server.url(file["url"].as_str().unwrap()))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.bytes().await.unwrap(), stored);
+    }
+
+    let response = server
+        .client()
+        .get(server.url(&format!(
+            "/audit/{}/artifacts/Cargo.toml",
+            audit["id"].as_str().unwrap()
+        )))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[sqlx::test]
+async fn the_s3_store_uploads_to_the_bucket_and_presigns_downloads(pool: PgPool) {
+    let bucket = Bucket::default();
+    let url = start_s3(bucket.clone()).await;
+    let server = start_with_s3(&pool, &url).await;
+    let audit = server.create_audit(CODE).await;
+
+    let artifacts = uploaded_artifacts(&server, &audit).await;
+    assert!(artifacts["error"].is_null(), "{}", artifacts);
+    let id = audit["id"].as_str().unwrap();
+    let mut stored: Vec<String> = bucket.objects.lock().unwrap().keys().cloned().collect();
+    stored.sort();
+    assert_eq!(
+        stored,
+        ["clippy.json", "diagnostics.json", "lib.rlib"]
+            .map(|name| format!("{}/audits/{}/{}", BUCKET, id, name))
+    );
+
+    for file in artifacts["files"].as_array().unwrap() {
+        let presigned = file["url"].as_str().unwrap();
+        assert!(presigned.starts_with(&url), "{}", presigned);
+        assert!(presigned.contains("X-Amz-Signature="), "{}", presigned);
+        assert!(!file["expires_at"].is_null());
+        let data = reqwest::get(presigned)
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        let key = format!("{}/{}", BUCKET, file["key"].as_str().unwrap());
+        assert_eq!(data, bucket.objects.lock().unwrap()[&key]);
+    }
+}
+
+#[sqlx::test]
+async fn failed_uploads_are_recorded_without_invalidating_the_audit(pool: PgPool) {
+    let bucket = Bucket {
+        refuse_uploads: true,
+        ..Bucket::default()
+    };
+    let url = start_s3(bucket.clone()).await;
+    let server = start_with_s3(&pool, &url).await;
+    let audit = server.create_audit(CODE).await;
+
+    let artifacts = uploaded_artifacts(&server, &audit).await;
+    assert_eq!(artifacts["files"], Value::Array(vec![]));
+    let error = artifacts["error"].as_str().unwrap();
+    for name in ["diagnostics.json", "clippy.json", "lib.rlib"] {
+        assert!(
+            error.contains(&format!("{}: Failed to upload", name)),
+            "{}",
+            error
+        );
+    }
+    assert!(bucket.objects.lock().unwrap().is_empty());
+
+    let audit: Value = server
+        .client()
+        .get(server.url(&format!("/audit/{}", audit["id"].as_str().unwrap())))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(audit["status"], "valid");
+    assert_eq!(audit["is_valid"], true);
+}
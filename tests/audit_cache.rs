@@ -0,0 +1,133 @@
+//! Tests of the in-memory cache of audit results.
+
+mod common;
+
+use common::TestServer;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+/// Code importing a made-up path of the standard library, which RAA0014 reports.
+const CODE: &str = "use std::collections::SortedMap;\n\npub fn answer() -> u32 {\n    42\n}\n";
+
+/// Returns the hit, miss and bypass counters of the cache.
+async fn counters(server: &TestServer) -> (u64, u64, u64) {
+    let stats: Value = server
+        .client()
+        .get(server.url("/metrics/audit-cache"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let counter = |name: &str| stats[name].as_u64().unwrap();
+    (counter("hits"), counter("misses"), counter("bypasses"))
+}
+
+/// Returns the codes of the findings of an audit.
+fn finding_codes(audit: &Value) -> Vec<&str> {
+    audit["findings"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|finding| finding["code"].as_str().unwrap())
+        .collect()
+}
+
+/// Creates the `playground` profile, or updates it if `exists`, enabling `rules`.
+async fn save_profile(server: &TestServer, exists: bool, rules: &[&str]) {
+    let mutation = if exists {
+        "updateAuditProfile(name: \"playground\", input: $input)"
+    } else {
+        "createAuditProfile(input: $input)"
+    };
+    let body = server
+        .graphql_as(
+            common::ADMIN_TOKEN,
+            &format!(
+                "mutation($input: AuditProfileInput!) {{ {} {{ name }} }}",
+                mutation
+            ),
+            json!({ "input": { "name": "playground", "enabledRules": rules } }),
+        )
+        .await;
+    assert!(body["errors"].is_null(), "{}", body);
+}
+
+#[sqlx::test]
+async fn repeated_audits_hit_the_cache(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let request = json!({ "prompt": "Write a function", "generated_code": CODE });
+
+    let first = server.create_audit_with(request.clone()).await;
+    assert_eq!(counters(&server).await, (0, 1, 0));
+    let second = server.create_audit_with(request.clone()).await;
+    assert_eq!(counters(&server).await, (1, 1, 0));
+    assert_ne!(first["id"], second["id"]);
+    for field in [
+        "status",
+        "findings",
+        "compilation_error",
+        "primary_error_code",
+    ] {
+        assert_eq!(first[field], second[field], "{}", field);
+    }
+
+    // Dry runs share the cache.
+    let response = server
+        .client()
+        .post(server.url("/audit/dry-run"))
+        .json(&request)
+        .send()
+        .await
+        .unwrap();
+    let outcome: Value = response.json().await.unwrap();
+    assert_eq!(outcome["status"], first["status"]);
+    assert_eq!(counters(&server).await, (2, 1, 0));
+
+    // Other options are other entries.
+    server
+        .create_audit_with(json!({ "generated_code": CODE, "edition": "2018" }))
+        .await;
+    assert_eq!(counters(&server).await, (2, 2, 0));
+
+    let mut bypassing = request.clone();
+    bypassing["bypass_cache"] = json!(true);
+    server.create_audit_with(bypassing).await;
+    assert_eq!(counters(&server).await, (2, 2, 1));
+
+    let body = server
+        .graphql_as(
+            common::ADMIN_TOKEN,
+            "{ cacheStats { hits misses bypasses entries } }",
+            Value::Null,
+        )
+        .await;
+    assert_eq!(
+        body["data"]["cacheStats"],
+        json!({ "hits": 2, "misses": 2, "bypasses": 1, "entries": 2 }),
+        "{}",
+        body
+    );
+}
+
+#[sqlx::test]
+async fn editing_the_rules_invalidates_cached_results(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let request = json!({
+        "prompt": "Write a function",
+        "generated_code": CODE,
+        "profile": "playground",
+    });
+    save_profile(&server, false, &["RAA0014"]).await;
+
+    let audit = server.create_audit_with(request.clone()).await;
+    assert!(finding_codes(&audit).contains(&"RAA0014"), "{}", audit);
+    server.create_audit_with(request.clone()).await;
+    assert_eq!(counters(&server).await, (1, 1, 0));
+
+    save_profile(&server, true, &["RAA0001"]).await;
+    let audit = server.create_audit_with(request.clone()).await;
+    assert_eq!(counters(&server).await, (1, 2, 0));
+    assert!(!finding_codes(&audit).contains(&"RAA0014"), "{}", audit);
+}
@@ -0,0 +1,178 @@
+//! Tests of the user access tokens.
+
+mod common;
+
+use common::{JWT_SECRET, TestServer, create_user};
+use jsonwebtoken::{EncodingKey, Header};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+const CODE: &str = "pub fn answer() -> u32 { 42 }";
+
+async fn comment(server: &TestServer, audit: &Value, token: Option<&str>) -> reqwest::Response {
+    let mut request = server
+        .client()
+        .post(server.url(&format!(
+            "/audit/{}/comments",
+            audit["id"].as_str().unwrap()
+        )))
+        .json(&json!({ "body": "Looks good" }));
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    request.send().await.unwrap()
+}
+
+/// Signs claims with the secret of the test servers, as the server would.
+fn sign(claims: Value) -> String {
+    jsonwebtoken::encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(JWT_SECRET.as_bytes()),
+    )
+    .unwrap()
+}
+
+#[sqlx::test]
+async fn wrong_password_is_rejected(pool: PgPool) {
+    create_user(&pool, "alice", "correct horse", "user").await;
+    let server = TestServer::start(&pool).await;
+
+    for (username, password) in [("alice", "battery staple"), ("bob", "correct horse")] {
+        let response = server
+            .client()
+            .post(server.url("/auth/token"))
+            .json(&json!({ "username": username, "password": password }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let error: Value = response.json().await.unwrap();
+        assert_eq!(error["error"], "Invalid username or password");
+    }
+}
+
+#[sqlx::test]
+async fn issued_token_authenticates_the_user(pool: PgPool) {
+    create_user(&pool, "alice", "correct horse", "user").await;
+    let server = TestServer::start(&pool).await;
+    let audit = server.create_audit(CODE).await;
+
+    let token = server.access_token("alice", "correct horse").await;
+
+    let response = comment(&server, &audit, Some(&token)).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let created: Value = response.json().await.unwrap();
+    assert_eq!(created["author"], "alice");
+
+    let response = server
+        .graphql_as(
+            &token,
+            "mutation($id: UUID!) { addComment(auditId: $id, body: \"Agreed\") { author } }",
+            json!({ "id": audit["id"] }),
+        )
+        .await;
+    assert!(response["errors"].is_null(), "{}", response);
+    assert_eq!(response["data"]["addComment"]["author"], "alice");
+
+    let response = server
+        .client()
+        .post(server.url(&format!("/audit/{}/rate", audit["id"].as_str().unwrap())))
+        .bearer_auth(&token)
+        .json(&json!({ "score": 4 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let rating: Value = response.json().await.unwrap();
+    assert_eq!(rating["rater"], "alice");
+}
+
+#[sqlx::test]
+async fn missing_forged_or_expired_tokens_are_rejected(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let audit = server.create_audit(CODE).await;
+    let now = chrono::Utc::now().timestamp();
+    let claims = |exp: i64| {
+        json!({
+            "sub": uuid::Uuid::new_v4(),
+            "username": "mallory",
+            "role": "admin",
+            "iat": now - 7200,
+            "exp": exp,
+        })
+    };
+    let forged = jsonwebtoken::encode(
+        &Header::default(),
+        &claims(now + 3600),
+        &EncodingKey::from_secret(b"another secret of at least 32 bytes"),
+    )
+    .unwrap();
+    let expired = sign(claims(now - 3600));
+
+    let response = comment(&server, &audit, None).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = comment(&server, &audit, Some(&forged)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let error: Value = response.json().await.unwrap();
+    assert_eq!(error["error"], "Invalid access token");
+    let response = comment(&server, &audit, Some(&expired)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let error: Value = response.json().await.unwrap();
+    assert_eq!(error["error"], "The access token has expired");
+
+    let response = server
+        .graphql(
+            "mutation($id: UUID!) { addComment(auditId: $id, body: \"Spam\") { author } }",
+            json!({ "id": audit["id"] }),
+        )
+        .await;
+    assert_eq!(
+        response["errors"][0]["message"],
+        "Unauthorized: Access token required"
+    );
+
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM audit_comments")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+}
+
+#[sqlx::test]
+async fn token_settings_are_read_from_the_configuration_file(pool: PgPool) {
+    create_user(&pool, "alice", "correct horse", "user").await;
+    let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("jwt_expiry.toml");
+    std::fs::write(&path, "jwt_expiry_secs = 60\n").unwrap();
+    let server = TestServer::start_with(&pool, &[("CONFIG_FILE", path.to_str().unwrap())]).await;
+
+    let response = server
+        .client()
+        .post(server.url("/auth/token"))
+        .json(&json!({ "username": "alice", "password": "correct horse" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let token: Value = response.json().await.unwrap();
+    assert_eq!(token["expires_in"], 60);
+}
+
+#[sqlx::test]
+async fn short_jwt_secrets_are_refused(pool: PgPool) {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_rust-ai-auditor"))
+        .arg("serve")
+        .env_remove("CONFIG_FILE")
+        .env("DATABASE_URL", common::database_url(&pool))
+        .env("JWT_SECRET", "change-me")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("JWT_SECRET must be at least 32 bytes long"),
+        "{}",
+        stderr
+    );
+}
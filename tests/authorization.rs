@@ -0,0 +1,191 @@
+//! Tests of the authorization of the routes modifying existing audits.
+
+mod common;
+
+use common::{ADMIN_TOKEN, TestServer, create_user};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+const CODE: &str = "pub fn answer() -> u32 { 42 }";
+const PASSWORD: &str = "correct horse";
+
+/// Comments on an audit as a user and returns the URL of the comment.
+async fn comment(server: &TestServer, audit: &Value, token: &str) -> String {
+    let audit_url = format!("/audit/{}/comments", audit["id"].as_str().unwrap());
+    let response = server
+        .client()
+        .post(server.url(&audit_url))
+        .bearer_auth(token)
+        .json(&json!({ "body": "Needs a doc comment" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let comment: Value = response.json().await.unwrap();
+    format!("{}/{}", audit_url, comment["id"].as_str().unwrap())
+}
+
+async fn delete(server: &TestServer, path: &str, token: Option<&str>) -> StatusCode {
+    let mut request = server.client().delete(server.url(path));
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    request.send().await.unwrap().status()
+}
+
+#[sqlx::test]
+async fn comments_are_deleted_by_their_author_or_an_admin(pool: PgPool) {
+    create_user(&pool, "alice", PASSWORD, "user").await;
+    create_user(&pool, "bob", PASSWORD, "user").await;
+    create_user(&pool, "carol", PASSWORD, "admin").await;
+    let server = TestServer::start(&pool).await;
+    let audit = server.create_audit(CODE).await;
+    let alice = server.access_token("alice", PASSWORD).await;
+    let bob = server.access_token("bob", PASSWORD).await;
+    let carol = server.access_token("carol", PASSWORD).await;
+
+    let first = comment(&server, &audit, &alice).await;
+    assert_eq!(
+        delete(&server, &first, None).await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        delete(&server, &first, Some(&bob)).await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        delete(&server, &first, Some(&alice)).await,
+        StatusCode::NO_CONTENT
+    );
+    assert_eq!(
+        delete(&server, &first, Some(&alice)).await,
+        StatusCode::NOT_FOUND
+    );
+
+    let second = comment(&server, &audit, &alice).await;
+    assert_eq!(
+        delete(&server, &second, Some(&carol)).await,
+        StatusCode::NO_CONTENT
+    );
+    let third = comment(&server, &audit, &bob).await;
+    assert_eq!(
+        delete(&server, &third, Some(ADMIN_TOKEN)).await,
+        StatusCode::NO_CONTENT
+    );
+
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM audit_comments")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+}
+
+#[sqlx::test]
+async fn cancelling_a_compilation_requires_authentication(pool: PgPool) {
+    create_user(&pool, "alice", PASSWORD, "user").await;
+    let server = TestServer::start(&pool).await;
+    let audit = server.create_audit(CODE).await;
+    let path = format!("/audit/{}/compilation", audit["id"].as_str().unwrap());
+    let alice = server.access_token("alice", PASSWORD).await;
+
+    assert_eq!(delete(&server, &path, None).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        delete(&server, &path, Some("not-a-token")).await,
+        StatusCode::UNAUTHORIZED
+    );
+    // Authenticated callers reach the audit, which has nothing left to cancel.
+    assert_eq!(
+        delete(&server, &path, Some(&alice)).await,
+        StatusCode::CONFLICT
+    );
+    assert_eq!(
+        delete(&server, &path, Some(ADMIN_TOKEN)).await,
+        StatusCode::CONFLICT
+    );
+
+    let response = server
+        .graphql(
+            "mutation($id: UUID!) { cancelAudit(id: $id) }",
+            json!({ "id": audit["id"] }),
+        )
+        .await;
+    assert_eq!(
+        response["errors"][0]["message"],
+        "Unauthorized: Administrator bearer token or access token required"
+    );
+}
+
+#[sqlx::test]
+async fn replacing_tags_requires_authentication(pool: PgPool) {
+    create_user(&pool, "alice", PASSWORD, "user").await;
+    let server = TestServer::start(&pool).await;
+    let audit = server
+        .create_audit_with(json!({ "generated_code": CODE, "tags": ["draft"] }))
+        .await;
+    let query = "mutation($id: UUID!) { setTags(id: $id, tags: [\"Reviewed\"]) { tags } }";
+    let variables = json!({ "id": audit["id"] });
+
+    let response = server.graphql(query, variables.clone()).await;
+    assert_eq!(
+        response["errors"][0]["message"],
+        "Unauthorized: Administrator bearer token or access token required"
+    );
+    let tags: Vec<String> = sqlx::query_scalar("SELECT tags FROM ai_audits")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(tags, ["draft"]);
+
+    let alice = server.access_token("alice", PASSWORD).await;
+    let response = server.graphql_as(&alice, query, variables.clone()).await;
+    assert!(response["errors"].is_null(), "{}", response);
+    assert_eq!(response["data"]["setTags"]["tags"], json!(["reviewed"]));
+
+    let response = server.graphql_as(ADMIN_TOKEN, query, variables).await;
+    assert!(response["errors"].is_null(), "{}", response);
+}
+
+#[sqlx::test]
+async fn fixing_an_audit_requires_authentication(pool: PgPool) {
+    create_user(&pool, "alice", PASSWORD, "user").await;
+    let server = TestServer::start(&pool).await;
+    let audit = server.create_audit(CODE).await;
+    let path = format!("/audit/{}/fix", audit["id"].as_str().unwrap());
+    let alice = server.access_token("alice", PASSWORD).await;
+    let fix = |token: Option<&str>| {
+        let mut request = server.client().post(server.url(&path));
+        if let Some(token) = token {
+            request = request.bearer_auth(token.to_string());
+        }
+        async move { request.send().await.unwrap().status() }
+    };
+
+    assert_eq!(fix(None).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(fix(Some("not-a-token")).await, StatusCode::UNAUTHORIZED);
+    // Authenticated callers reach the audit, which compiles and has nothing to fix.
+    assert_eq!(fix(Some(&alice)).await, StatusCode::BAD_REQUEST);
+    assert_eq!(fix(Some(ADMIN_TOKEN)).await, StatusCode::BAD_REQUEST);
+
+    let query = "mutation($id: UUID!) { fixAudit(id: $id) { id } }";
+    let variables = json!({ "id": audit["id"] });
+    let response = server.graphql(query, variables.clone()).await;
+    assert_eq!(
+        response["errors"][0]["message"],
+        "Unauthorized: Administrator bearer token or access token required"
+    );
+    let response = server.graphql_as(&alice, query, variables).await;
+    assert!(
+        response["errors"][0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("nothing to fix"),
+        "{}",
+        response
+    );
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM ai_audits")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+}
@@ -0,0 +1,59 @@
+//! Tests of the crates that audited code may not use.
+
+mod common;
+
+use common::TestServer;
+use serde_json::Value;
+use sqlx::PgPool;
+
+fn codes(audit: &Value) -> Vec<&str> {
+    audit["findings"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|finding| finding["code"].as_str())
+        .collect()
+}
+
+#[sqlx::test]
+async fn banned_crates_are_rejected_before_compiling(pool: PgPool) {
+    let server = TestServer::start_with(&pool, &[("AUDIT_BANNED_CRATES", "reqwest, tokio*")]).await;
+
+    let audit = server
+        .create_audit("use tokio_util::codec::LinesCodec;\n\npub fn codec() -> LinesCodec { LinesCodec::new() }")
+        .await;
+
+    assert_eq!(audit["status"], "rejected", "{}", audit);
+    assert_eq!(audit["is_valid"], false);
+    assert!(audit["compilation_error"].is_null(), "{}", audit);
+    let finding = &audit["findings"][0];
+    assert_eq!(finding["code"], "RAA0008");
+    assert_eq!(finding["line"], 1);
+    assert_eq!(
+        finding["message"],
+        "Uses the crate `tokio_util`, which is banned by the pattern `tokio*`"
+    );
+}
+
+#[sqlx::test]
+async fn allowed_crates_are_compiled(pool: PgPool) {
+    let server = TestServer::start_with(&pool, &[("AUDIT_BANNED_CRATES", "reqwest, tokio*")]).await;
+
+    let audit = server
+        .create_audit(
+            "use regex::Regex;\n\npub fn digits() -> Regex { Regex::new(\"[0-9]+\").unwrap() }",
+        )
+        .await;
+
+    // The crate is not available to `rustc`, but the code reached it.
+    assert_ne!(audit["status"], "rejected", "{}", audit);
+    assert!(!codes(&audit).contains(&"RAA0008"), "{}", audit);
+    assert!(
+        audit["compilation_error"]
+            .as_str()
+            .unwrap()
+            .contains("regex"),
+        "{}",
+        audit
+    );
+}
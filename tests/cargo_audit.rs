@@ -0,0 +1,113 @@
+//! Tests of the `cargo audit` run on the dependencies of Cargo packages.
+//!
+//! The servers find the stand-in of `tests/fixtures/cargo-audit` on their `PATH`, which
+//! reports `rsa` 0.9.10 (a medium CVSS severity) and `demo-parser` 1.0.0 (no score)
+//! whatever the lockfile.
+
+mod common;
+
+use common::{TestServer, package};
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+/// The directory of the stand-in of `cargo-audit`.
+const FAKE_CARGO_AUDIT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/cargo-audit");
+
+/// Starts a server running the stand-in of `cargo-audit`.
+async fn start_with_cargo_audit(pool: &PgPool) -> TestServer {
+    let path = format!(
+        "{}:{}",
+        FAKE_CARGO_AUDIT,
+        std::env::var("PATH").unwrap_or_default()
+    );
+    TestServer::start_with(pool, &[("PATH", path.as_str())]).await
+}
+
+/// Returns the identifiers of the audits listed by `GET /audits?has_security_issues=`.
+async fn listed(server: &TestServer, has_security_issues: bool) -> Vec<Value> {
+    let audits: Vec<Value> = server
+        .client()
+        .get(server.url("/audits"))
+        .query(&[("has_security_issues", has_security_issues)])
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    audits
+        .into_iter()
+        .map(|audit| audit["id"].clone())
+        .collect()
+}
+
+#[sqlx::test]
+async fn cargo_audit_advisories_are_stored_on_the_audit(pool: PgPool) {
+    let server = start_with_cargo_audit(&pool).await;
+    let audit = server
+        .create_audit_with(json!({ "files": package("rsa = \"=0.9.10\"\n") }))
+        .await;
+
+    assert_eq!(audit["status"], "valid", "{}", audit);
+    assert_eq!(audit["has_security_issues"], true);
+    assert_eq!(
+        audit["security_advisories"],
+        json!([
+            {
+                "package": "rsa 0.9.10",
+                "vulnerability_id": "RUSTSEC-2023-0071",
+                "title": "Marvin Attack: potential key recovery through timing sidechannels",
+                "severity": "medium",
+            },
+            {
+                "package": "demo-parser 1.0.0",
+                "vulnerability_id": "RUSTSEC-2025-0001",
+                "title": "Unchecked input length",
+                "severity": "unknown",
+            },
+        ])
+    );
+
+    // Single files have no lockfile to audit.
+    let single = server
+        .create_audit("pub fn answer() -> u32 {\n    42\n}\n")
+        .await;
+    assert_eq!(single["has_security_issues"], false);
+    assert!(single["security_advisories"].is_null());
+
+    assert_eq!(listed(&server, true).await, [audit["id"].clone()]);
+    assert_eq!(listed(&server, false).await, [single["id"].clone()]);
+    let response = server
+        .graphql(
+            "{ audits(hasSecurityIssues: true) { id hasSecurityIssues \
+             securityAdvisories { package vulnerabilityId severity } } }",
+            json!({}),
+        )
+        .await;
+    assert_eq!(
+        response["data"]["audits"],
+        json!([{
+            "id": audit["id"],
+            "hasSecurityIssues": true,
+            "securityAdvisories": [
+                { "package": "rsa 0.9.10", "vulnerabilityId": "RUSTSEC-2023-0071", "severity": "medium" },
+                { "package": "demo-parser 1.0.0", "vulnerabilityId": "RUSTSEC-2025-0001", "severity": "unknown" },
+            ],
+        }]),
+        "{}",
+        response
+    );
+}
+
+#[sqlx::test]
+async fn audits_succeed_without_cargo_audit(pool: PgPool) {
+    // `cargo-audit` is not installed along with the toolchain.
+    let server = TestServer::start(&pool).await;
+    let audit = server
+        .create_audit_with(json!({ "files": package("rsa = \"=0.9.10\"\n") }))
+        .await;
+
+    assert_eq!(audit["status"], "valid", "{}", audit);
+    assert_eq!(audit["has_security_issues"], false);
+    assert!(audit["security_advisories"].is_null());
+}
@@ -0,0 +1,102 @@
+//! Tests of the `audit` subcommand, which runs without the server or a database.
+
+use assert_cmd::Command;
+use predicates::prelude::*;
+use serde_json::Value;
+
+const GOOD: &str = "tests/fixtures/cli/good.rs";
+const BAD: &str = "tests/fixtures/cli/bad.rs";
+/// An `async fn`, which does not exist in the 2015 edition.
+const ASYNC_FN: &str = "tests/fixtures/cli/async_fn.rs";
+
+fn audit() -> Command {
+    let mut command = Command::cargo_bin("rust-ai-auditor").unwrap();
+    command
+        .env_remove("DATABASE_URL")
+        .env_remove("CONFIG_FILE")
+        .env("RUST_LOG", "warn")
+        .arg("audit");
+    command
+}
+
+#[test]
+fn valid_file_exits_with_success() {
+    audit()
+        .arg(GOOD)
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains(format!("{}: valid", GOOD)));
+}
+
+#[test]
+fn invalid_file_exits_with_failure() {
+    audit()
+        .arg(BAD)
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains(format!("{}: compile error", BAD)))
+        .stdout(predicate::str::contains(
+            "error[E0308] line 5:5: mismatched types",
+        ));
+}
+
+#[test]
+fn json_output_reports_every_file() {
+    let output = audit()
+        .args(["--format", "json", GOOD, BAD])
+        .assert()
+        .code(1)
+        .get_output()
+        .stdout
+        .clone();
+
+    let reports: Value = serde_json::from_slice(&output).unwrap();
+    let reports = reports.as_array().unwrap();
+    assert_eq!(reports.len(), 2);
+    assert_eq!(reports[0]["path"], GOOD);
+    assert_eq!(reports[0]["status"], "valid");
+    assert_eq!(reports[0]["is_valid"], true);
+    assert!(reports[0]["compilation_error"].is_null());
+    assert_eq!(reports[1]["path"], BAD);
+    assert_eq!(reports[1]["status"], "compile_error");
+    assert_eq!(reports[1]["is_valid"], false);
+    assert_eq!(reports[1]["diagnostics"][0]["code"], "E0308");
+    assert!(
+        reports[1]["compilation_error"]
+            .as_str()
+            .unwrap()
+            .contains("mismatched types")
+    );
+}
+
+#[test]
+fn the_edition_is_passed_to_rustc() {
+    audit()
+        .args(["--edition", "2018", ASYNC_FN])
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains(format!("{}: valid", ASYNC_FN)));
+    audit()
+        .args(["--edition", "2015", ASYNC_FN])
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains(format!(
+            "{}: compile error",
+            ASYNC_FN
+        )))
+        .stdout(predicate::str::contains("error[E0670]"));
+    audit()
+        .args(["--edition", "2017", ASYNC_FN])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("--edition"));
+}
+
+#[test]
+fn missing_file_is_an_error() {
+    audit()
+        .arg("tests/fixtures/cli/missing.rs")
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("Failed to read"));
+}
@@ -0,0 +1,294 @@
+//! Helpers shared by the integration tests.
+//!
+//! The tests run the server binary against the database `#[sqlx::test]` creates for each
+//! of them, so that they go through the same routes and middleware as real clients.
+
+#![allow(dead_code)]
+
+use sqlx::PgPool;
+use std::{
+    net::TcpListener,
+    process::{Child, Command, Stdio},
+    time::{Duration, Instant},
+};
+
+/// How long the server may take to run its migrations and probe `rustc`.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The bearer token of the administrative endpoints of the test servers.
+pub const ADMIN_TOKEN: &str = "test-admin-token";
+
+/// The secret the test servers sign JWTs with.
+pub const JWT_SECRET: &str = "test-jwt-secret-of-at-least-32-bytes";
+
+/// A server running on a free port, killed when dropped.
+pub struct TestServer {
+    child: Child,
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl TestServer {
+    /// Starts a server using the database of `pool`, with the admin token and the JWT
+    /// secret of the tests.
+    pub async fn start(pool: &PgPool) -> TestServer {
+        TestServer::start_with(pool, &[]).await
+    }
+
+    /// Starts a server like [`TestServer::start`], with extra environment variables.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - The pool of the database of the test.
+    /// * `envs` - The environment variables set on top of the defaults of the tests.
+    pub async fn start_with(pool: &PgPool, envs: &[(&str, &str)]) -> TestServer {
+        let server = TestServer::spawn_with(pool, envs);
+        server.wait_until_ready().await;
+        server
+    }
+
+    /// Starts a server like [`TestServer::start_with`], without waiting for it to be
+    /// ready.
+    pub fn spawn_with(pool: &PgPool, envs: &[(&str, &str)]) -> TestServer {
+        let port = free_port();
+        let mut command = Command::new(env!("CARGO_BIN_EXE_rust-ai-auditor"));
+        command
+            .arg("serve")
+            .env_remove("CONFIG_FILE")
+            .env_remove("DATABASE_READ_URL")
+            .env_remove("READ_REPLICA_DATABASE_URL")
+            .env_remove("REDIS_URL")
+            .env_remove("ADVISORY_DB_PATH")
+            // The test servers never fetch the advisory database.
+            .env("ADVISORY_DB_URL", "")
+            .env("DATABASE_URL", database_url(pool))
+            .env("PORT", port.to_string())
+            .env("ADMIN_API_TOKEN", ADMIN_TOKEN)
+            .env("JWT_SECRET", JWT_SECRET)
+            .env("RUST_LOG", "rust_ai_auditor=warn")
+            .stdout(Stdio::null());
+        for (name, value) in envs {
+            command.env(name, value);
+        }
+        let child = command.spawn().expect("failed to start the server");
+        // A server given a certificate serves HTTPS, for the name of the certificate.
+        let tls_cert = envs
+            .iter()
+            .find(|(name, _)| *name == "TLS_CERT_PATH")
+            .map(|(_, path)| std::fs::read(path).expect("failed to read the certificate"));
+        let (base_url, client) = match tls_cert {
+            Some(pem) => (
+                format!("https://localhost:{}", port),
+                reqwest::Client::builder()
+                    .add_root_certificate(
+                        reqwest::Certificate::from_pem(&pem).expect("invalid certificate"),
+                    )
+                    .build()
+                    .expect("failed to build the HTTPS client"),
+            ),
+            None => (format!("http://127.0.0.1:{}", port), reqwest::Client::new()),
+        };
+        TestServer {
+            child,
+            base_url,
+            client,
+        }
+    }
+
+    /// Returns the number of live `name` processes the server is running, such as `rustc`.
+    pub fn child_processes(&self, name: &str) -> usize {
+        let pid = self.child.id().to_string();
+        std::fs::read_dir("/proc")
+            .expect("failed to list the processes")
+            .filter_map(|entry| std::fs::read_to_string(entry.ok()?.path().join("stat")).ok())
+            .filter(|stat| {
+                // `pid (comm) state ppid ...`
+                let Some((comm, rest)) = stat.split_once(") ") else {
+                    return false;
+                };
+                let mut fields = rest.split(' ');
+                comm.split_once(" (").map(|(_, comm)| comm) == Some(name)
+                    && fields.next() != Some("Z")
+                    && fields.next() == Some(pid.as_str())
+            })
+            .count()
+    }
+
+    /// Returns the resident memory of the server, in KiB.
+    pub fn memory_kib(&self) -> u64 {
+        let status = std::fs::read_to_string(format!("/proc/{}/status", self.child.id()))
+            .expect("failed to read the status of the server");
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix("VmRSS:"))
+            .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+            .expect("the status of the server has no VmRSS")
+    }
+
+    /// Returns the URL of `path` on the server.
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// Returns the HTTP client of the test.
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    /// Audits `code` with `POST /audit` and returns the created audit.
+    pub async fn create_audit(&self, code: &str) -> serde_json::Value {
+        self.create_audit_with(serde_json::json!({ "generated_code": code }))
+            .await
+    }
+
+    /// Sends `body` to `POST /audit`, with a prompt unless it has one, and returns the
+    /// created audit.
+    pub async fn create_audit_with(&self, mut body: serde_json::Value) -> serde_json::Value {
+        if body.get("prompt").is_none() {
+            body["prompt"] = "Write a function".into();
+        }
+        let response = self
+            .client
+            .post(self.url("/audit"))
+            .json(&body)
+            .send()
+            .await
+            .expect("audit request failed");
+        let status = response.status();
+        let audit = response.json().await.expect("audit response is not JSON");
+        assert_eq!(status, reqwest::StatusCode::CREATED, "{}", audit);
+        audit
+    }
+
+    /// Sends `body` to the administrative endpoint `path` with `POST` and the admin token,
+    /// and returns the response.
+    pub async fn admin_post(&self, path: &str, body: &serde_json::Value) -> reqwest::Response {
+        self.client
+            .post(self.url(path))
+            .bearer_auth(ADMIN_TOKEN)
+            .json(body)
+            .send()
+            .await
+            .expect("admin request failed")
+    }
+
+    /// Exchanges the credentials of a user for an access token with `POST /auth/token`.
+    pub async fn access_token(&self, username: &str, password: &str) -> String {
+        let response = self
+            .client
+            .post(self.url("/auth/token"))
+            .json(&serde_json::json!({ "username": username, "password": password }))
+            .send()
+            .await
+            .expect("token request failed");
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let body: serde_json::Value = response.json().await.expect("token is not JSON");
+        body["access_token"]
+            .as_str()
+            .expect("no access token")
+            .to_string()
+    }
+
+    /// Sends a GraphQL query (or mutation) with a bearer token and returns the whole JSON
+    /// response.
+    pub async fn graphql_as(
+        &self,
+        token: &str,
+        query: &str,
+        variables: serde_json::Value,
+    ) -> serde_json::Value {
+        self.client
+            .post(self.url("/graphql"))
+            .bearer_auth(token)
+            .json(&serde_json::json!({ "query": query, "variables": variables }))
+            .send()
+            .await
+            .expect("GraphQL request failed")
+            .json()
+            .await
+            .expect("GraphQL response is not JSON")
+    }
+
+    /// Sends a GraphQL query (or mutation) and returns the whole JSON response.
+    pub async fn graphql(&self, query: &str, variables: serde_json::Value) -> serde_json::Value {
+        self.client
+            .post(self.url("/graphql"))
+            .json(&serde_json::json!({ "query": query, "variables": variables }))
+            .send()
+            .await
+            .expect("GraphQL request failed")
+            .json()
+            .await
+            .expect("GraphQL response is not JSON")
+    }
+
+    /// Polls `/ready` until the server answers `200 OK`.
+    pub async fn wait_until_ready(&self) {
+        let started = Instant::now();
+        while started.elapsed() < STARTUP_TIMEOUT {
+            if let Ok(response) = self.client.get(self.url("/ready")).send().await
+                && response.status().is_success()
+            {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("the server was not ready after {:?}", STARTUP_TIMEOUT);
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Returns the files of a Cargo package depending on `dependencies`, lines of its
+/// `[dependencies]` table such as `rsa = "=0.9.10"\n`.
+pub fn package(dependencies: &str) -> serde_json::Value {
+    serde_json::json!([
+        {
+            "path": "Cargo.toml",
+            "content": format!(
+                "[package]\nname = \"audited\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[dependencies]\n{}",
+                dependencies
+            ),
+        },
+        { "path": "src/lib.rs", "content": "pub fn answer() -> u32 {\n    42\n}\n" },
+    ])
+}
+
+/// Creates a user account with a password and a role (`user` or `admin`).
+pub async fn create_user(pool: &PgPool, username: &str, password: &str, role: &str) {
+    let hash = bcrypt::hash(password, 4).expect("failed to hash the password");
+    sqlx::query("INSERT INTO users (username, password_hash, role) VALUES ($1, $2, $3)")
+        .bind(username)
+        .bind(hash)
+        .bind(role)
+        .execute(pool)
+        .await
+        .expect("failed to create the user");
+}
+
+/// Returns the URL of the database of `pool`, on the server of `DATABASE_URL`.
+pub fn database_url(pool: &PgPool) -> String {
+    let url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set to run the tests");
+    let (server, _) = url
+        .rsplit_once('/')
+        .expect("DATABASE_URL has no database name");
+    let database = pool
+        .connect_options()
+        .get_database()
+        .expect("the test pool has no database name")
+        .to_string();
+    format!("{}/{}", server, database)
+}
+
+/// Returns a port no socket is bound to.
+pub fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .expect("failed to find a free port")
+        .port()
+}
@@ -0,0 +1,75 @@
+//! Tests of the comparison of two audits.
+
+mod common;
+
+use common::TestServer;
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+const UNDOCUMENTED: &str = "pub fn add(a: i32, b: i32) -> i32 { a + b }";
+const DOCUMENTED: &str = "/// Adds two numbers.\npub fn add(a: i32, b: i32) -> i32 { a + b }";
+
+async fn compare(server: &TestServer, a: &Value, b: &Value) -> Value {
+    let response = server
+        .client()
+        .get(server.url("/audits/compare"))
+        .query(&[
+            ("a", a["id"].as_str().unwrap()),
+            ("b", b["id"].as_str().unwrap()),
+        ])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    response.json().await.unwrap()
+}
+
+#[sqlx::test]
+async fn comparison_reports_the_quality_score_delta(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let a = server.create_audit(UNDOCUMENTED).await;
+    let b = server.create_audit(DOCUMENTED).await;
+    let expected = b["metrics"]["quality_score"].as_f64().unwrap()
+        - a["metrics"]["quality_score"].as_f64().unwrap();
+    assert!(
+        expected > 0.0,
+        "documenting the code should raise its score"
+    );
+
+    let comparison = compare(&server, &a, &b).await;
+    assert_eq!(comparison["quality_score_delta"].as_f64(), Some(expected));
+
+    let response = server
+        .graphql(
+            "query($a: UUID!, $b: UUID!) { compareAudits(idA: $a, idB: $b) { qualityScoreDelta } }",
+            json!({ "a": b["id"], "b": a["id"] }),
+        )
+        .await;
+    assert!(response["errors"].is_null(), "{}", response);
+    assert_eq!(
+        response["data"]["compareAudits"]["qualityScoreDelta"].as_f64(),
+        Some(-expected)
+    );
+}
+
+#[sqlx::test]
+async fn quality_score_delta_is_null_without_metrics(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let a = server.create_audit(UNDOCUMENTED).await;
+    let b = server.create_audit(DOCUMENTED).await;
+    // Audits stored before the metrics were introduced have none.
+    sqlx::query("UPDATE ai_audits SET metrics = NULL WHERE id = $1")
+        .bind(b["id"].as_str().unwrap().parse::<uuid::Uuid>().unwrap())
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let comparison = compare(&server, &a, &b).await;
+    assert!(
+        comparison["quality_score_delta"].is_null(),
+        "{}",
+        comparison
+    );
+    assert_eq!(comparison["validity_changed"], false);
+}
@@ -0,0 +1,80 @@
+//! Tests of the `rustc` invocation recorded on audits.
+
+mod common;
+
+use common::TestServer;
+use serde_json::json;
+use sqlx::PgPool;
+use std::process::Command;
+
+const CODE: &str = "pub fn double(x: u32) -> u32 {\n    x * 2\n}\n";
+
+/// Returns the target triple of the host, which is always installed.
+fn host_target() -> String {
+    let output = Command::new("rustc").arg("-vV").output().unwrap();
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .unwrap()
+        .to_string()
+}
+
+#[sqlx::test]
+async fn the_command_reflects_the_requested_flags(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let target = host_target();
+
+    let audit = server
+        .create_audit_with(json!({
+            "generated_code": CODE,
+            "edition": "2018",
+            "opt_level": "s",
+            "target": target,
+        }))
+        .await;
+    assert_eq!(audit["is_valid"], true, "{}", audit);
+    assert_eq!(
+        audit["compile_command"],
+        json!([
+            "rustc",
+            "--crate-type",
+            "lib",
+            "--error-format=json",
+            "--edition",
+            "2018",
+            "-C",
+            "opt-level=s",
+            "--target",
+            target,
+        ])
+    );
+
+    // The defaults are recorded too, and GraphQL exposes the same command.
+    let default = server.create_audit(CODE).await;
+    let body = server
+        .graphql(
+            "query($id: UUID!) { audit(id: $id) { compileCommand } }",
+            json!({ "id": default["id"] }),
+        )
+        .await;
+    assert_eq!(
+        body["data"]["audit"]["compileCommand"],
+        json!([
+            "rustc",
+            "--crate-type",
+            "lib",
+            "--error-format=json",
+            "--edition",
+            "2021",
+            "-C",
+            "opt-level=0",
+        ]),
+        "{}",
+        body
+    );
+    assert_eq!(
+        default["compile_command"],
+        body["data"]["audit"]["compileCommand"]
+    );
+}
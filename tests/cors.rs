@@ -0,0 +1,117 @@
+//! Tests of the CORS policy.
+
+mod common;
+
+use common::TestServer;
+use reqwest::{Method, StatusCode, header};
+use sqlx::PgPool;
+
+const DASHBOARD: &str = "https://dashboard.example.com";
+
+async fn start(pool: &PgPool) -> TestServer {
+    TestServer::start_with(
+        pool,
+        &[(
+            "CORS_ALLOWED_ORIGINS",
+            "https://dashboard.example.com, http://localhost:5173",
+        )],
+    )
+    .await
+}
+
+#[sqlx::test]
+async fn allowed_origin_gets_cors_headers(pool: PgPool) {
+    let server = start(&pool).await;
+
+    let response = server
+        .client()
+        .get(server.url("/stats"))
+        .header(header::ORIGIN, DASHBOARD)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let headers = response.headers();
+    assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], DASHBOARD);
+    assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+}
+
+#[sqlx::test]
+async fn disallowed_origin_gets_no_cors_headers(pool: PgPool) {
+    let server = start(&pool).await;
+
+    let response = server
+        .client()
+        .get(server.url("/stats"))
+        .header(header::ORIGIN, "https://evil.example.com")
+        .send()
+        .await
+        .unwrap();
+
+    // The request is served, but browsers do not let the page read the response.
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(
+        !response
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+    );
+}
+
+#[sqlx::test]
+async fn preflight_request_is_answered(pool: PgPool) {
+    let server = start(&pool).await;
+
+    for path in ["/audit", "/graphql", "/stats"] {
+        let response = server
+            .client()
+            .request(Method::OPTIONS, server.url(path))
+            .header(header::ORIGIN, DASHBOARD)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .header(
+                header::ACCESS_CONTROL_REQUEST_HEADERS,
+                "content-type,authorization,idempotency-key",
+            )
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK, "preflight of {}", path);
+        let headers = response.headers();
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], DASHBOARD);
+        let methods = headers[header::ACCESS_CONTROL_ALLOW_METHODS]
+            .to_str()
+            .unwrap();
+        assert!(methods.contains("POST"), "allowed methods: {}", methods);
+        let allowed_headers = headers[header::ACCESS_CONTROL_ALLOW_HEADERS]
+            .to_str()
+            .unwrap()
+            .to_ascii_lowercase();
+        for name in ["content-type", "authorization", "idempotency-key"] {
+            assert!(
+                allowed_headers.contains(name),
+                "allowed headers: {}",
+                allowed_headers
+            );
+        }
+        assert!(headers.contains_key(header::ACCESS_CONTROL_MAX_AGE));
+    }
+}
+
+#[sqlx::test]
+async fn any_origin_is_allowed_only_when_asked_to(pool: PgPool) {
+    let server = TestServer::start_with(&pool, &[("CORS_ALLOW_ANY", "true")]).await;
+
+    let response = server
+        .client()
+        .get(server.url("/stats"))
+        .header(header::ORIGIN, "https://anywhere.example.com")
+        .send()
+        .await
+        .unwrap();
+
+    let headers = response.headers();
+    assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+    // Browsers refuse credentials with a wildcard origin, so none are allowed.
+    assert!(!headers.contains_key(header::ACCESS_CONTROL_ALLOW_CREDENTIALS));
+}
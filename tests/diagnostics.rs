@@ -0,0 +1,61 @@
+//! Tests of the structured compiler diagnostics of audits.
+
+mod common;
+
+use common::TestServer;
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+const QUERY: &str =
+    "query($id: UUID!) { audit(id: $id) { diagnostics { code level message line column } } }";
+
+async fn diagnostics(server: &TestServer, id: &Value) -> Value {
+    let response = server.graphql(QUERY, json!({ "id": id })).await;
+    assert!(response["errors"].is_null(), "{}", response);
+    response["data"]["audit"]["diagnostics"].clone()
+}
+
+#[sqlx::test]
+async fn failing_audit_has_diagnostics(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let audit = server
+        .create_audit("pub fn port() -> u16 {\n    \"8080\"\n}\n")
+        .await;
+    assert_eq!(audit["is_valid"], false);
+
+    let diagnostics = diagnostics(&server, &audit["id"]).await;
+
+    let mismatch = diagnostics
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|diagnostic| diagnostic["code"] == "E0308")
+        .unwrap_or_else(|| panic!("no E0308 diagnostic in {}", diagnostics));
+    assert_eq!(mismatch["level"], "error");
+    assert_eq!(mismatch["message"], "mismatched types");
+    assert_eq!(mismatch["line"], 2);
+    assert_eq!(mismatch["column"], 5);
+}
+
+#[sqlx::test]
+async fn audit_without_stored_diagnostics_has_none(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let id = uuid::Uuid::new_v4();
+    // Imported records predate the stored diagnostics.
+    let response = server
+        .admin_post(
+            "/admin/import",
+            &json!([{
+                "id": id,
+                "prompt": "Write a function",
+                "generated_code": "pub fn port() -> u16 { \"8080\" }",
+                "is_valid": false,
+                "compilation_error": "error[E0308]: mismatched types",
+            }]),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    assert_eq!(diagnostics(&server, &json!(id)).await, json!([]));
+}
@@ -0,0 +1,76 @@
+//! Tests of the download of the generated code of audits, as a file or as plain text.
+
+mod common;
+
+use common::TestServer;
+use reqwest::{StatusCode, header};
+use sqlx::PgPool;
+
+const CODE: &str = "pub fn add(a: i32, b: i32) -> i32 { a + b }";
+
+#[sqlx::test]
+async fn code_is_downloaded_as_a_rust_file(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let audit = server.create_audit(CODE).await;
+    let id = audit["id"].as_str().unwrap();
+
+    let response = server
+        .client()
+        .get(server.url(&format!("/audit/{}/download", id)))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "text/x-rust; charset=utf-8"
+    );
+    assert_eq!(
+        response.headers()[header::CONTENT_DISPOSITION],
+        format!("attachment; filename=\"audit_{}.rs\"", id).as_str()
+    );
+    assert_eq!(response.text().await.unwrap(), CODE);
+}
+
+#[sqlx::test]
+async fn code_is_served_as_plain_text(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let audit = server.create_audit(CODE).await;
+    let id = audit["id"].as_str().unwrap();
+
+    let response = server
+        .client()
+        .get(server.url(&format!("/audit/{}/code", id)))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "text/plain; charset=utf-8"
+    );
+    assert_eq!(
+        response.headers()[header::CONTENT_DISPOSITION],
+        format!("inline; filename=\"audit-{}.rs\"", id).as_str()
+    );
+    assert_eq!(response.text().await.unwrap(), CODE);
+}
+
+#[sqlx::test]
+async fn unknown_audit_is_not_found(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+
+    let id = uuid::Uuid::new_v4();
+
+    for path in ["download", "code"] {
+        let response = server
+            .client()
+            .get(server.url(&format!("/audit/{}/{}", id, path)))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", path);
+    }
+}
@@ -0,0 +1,115 @@
+//! Tests of dry runs, which audit code without storing anything.
+
+mod common;
+
+use common::TestServer;
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+const CODE: &str = "pub fn answer() -> u32 {\n    42\n}\n";
+
+/// The number of rows of the tables audits are stored in.
+async fn row_counts(pool: &PgPool) -> (i64, i64) {
+    sqlx::query_as("SELECT (SELECT COUNT(*) FROM ai_audits), (SELECT COUNT(*) FROM audit_files)")
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+async fn stats(server: &TestServer) -> Value {
+    server
+        .client()
+        .get(server.url("/stats"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+async fn dry_run(server: &TestServer, body: Value) -> reqwest::Response {
+    server
+        .client()
+        .post(server.url("/audit/dry-run"))
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn dry_runs_store_nothing(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    server.create_audit(CODE).await;
+    let counts = row_counts(&pool).await;
+    let summary = stats(&server).await;
+
+    let response = dry_run(
+        &server,
+        json!({ "prompt": "Write a function", "generated_code": "pub fn f() -> u32 { \"1\" }" }),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let outcome: Value = response.json().await.unwrap();
+    assert_eq!(outcome["status"], "compile_error");
+    assert_eq!(outcome["primary_error_code"], "E0308");
+    assert!(outcome.get("id").is_none());
+
+    let response = dry_run(
+        &server,
+        json!({
+            "prompt": "Write a tokenizer",
+            "files": [
+                { "path": "src/lib.rs", "content": "pub mod token;\n" },
+                { "path": "src/token.rs", "content": "pub fn width() -> usize {\n    4\n}\n" },
+            ],
+        }),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let outcome: Value = response.json().await.unwrap();
+    assert_eq!(outcome["status"], "valid", "{}", outcome);
+
+    let body = server
+        .graphql(
+            "query($code: String!) { analyzeCode(input: { prompt: \"Write a function\", generatedCode: $code }) { isValid status compilationDurationMs } }",
+            json!({ "code": CODE }),
+        )
+        .await;
+    assert_eq!(body["data"]["analyzeCode"]["isValid"], true, "{}", body);
+    assert!(body["data"]["analyzeCode"]["compilationDurationMs"].is_number());
+
+    assert_eq!(row_counts(&pool).await, counts);
+    assert_eq!(stats(&server).await, summary);
+}
+
+#[sqlx::test]
+async fn dry_runs_are_size_limited(pool: PgPool) {
+    let server = TestServer::start_with(&pool, &[("MAX_CODE_SIZE_BYTES", "16")]).await;
+
+    let response = dry_run(
+        &server,
+        json!({ "prompt": "Write a function", "generated_code": CODE }),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(row_counts(&pool).await, (0, 0));
+}
+
+#[sqlx::test]
+async fn dry_runs_are_rate_limited(pool: PgPool) {
+    let server = TestServer::start_with(&pool, &[("RATE_LIMIT_RPM", "1")]).await;
+    let body = json!({ "prompt": "Write a function", "generated_code": CODE });
+
+    assert_eq!(
+        dry_run(&server, body.clone()).await.status(),
+        StatusCode::OK
+    );
+    assert_eq!(
+        dry_run(&server, body).await.status(),
+        StatusCode::TOO_MANY_REQUESTS
+    );
+    assert_eq!(row_counts(&pool).await, (0, 0));
+}
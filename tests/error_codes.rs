@@ -0,0 +1,52 @@
+//! Tests of the analytics of the rustc error codes of failed audits.
+
+mod common;
+
+use common::TestServer;
+use serde_json::json;
+use sqlx::PgPool;
+
+/// A type mismatch (`E0308`).
+const MISMATCHED_TYPES: &str = "pub fn f() -> i32 { \"not a number\" }";
+/// A use of an undeclared name (`E0425`).
+const UNRESOLVED_NAME: &str = "pub fn f() -> i32 { undeclared }";
+/// A use of a moved value (`E0382`).
+const USE_AFTER_MOVE: &str = "pub fn f(s: String) -> usize { drop(s); s.len() }";
+
+#[sqlx::test]
+async fn top_errors_ranks_error_codes_by_frequency(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let snippets = [
+        (MISMATCHED_TYPES, "E0308"),
+        (MISMATCHED_TYPES, "E0308"),
+        (MISMATCHED_TYPES, "E0308"),
+        (UNRESOLVED_NAME, "E0425"),
+        (UNRESOLVED_NAME, "E0425"),
+        (USE_AFTER_MOVE, "E0382"),
+    ];
+    for (code, error_code) in snippets {
+        let audit = server.create_audit(code).await;
+        assert_eq!(audit["is_valid"], false);
+        assert_eq!(audit["primary_error_code"], error_code);
+    }
+    server.create_audit("pub fn valid() {}").await;
+
+    let response = server
+        .graphql("{ topErrors(limit: 2) { code count } }", json!({}))
+        .await;
+
+    assert!(response["errors"].is_null(), "{}", response);
+    assert_eq!(
+        response["data"]["topErrors"],
+        json!([
+            { "code": "E0308", "count": 3 },
+            { "code": "E0425", "count": 2 },
+        ])
+    );
+    let stored: Vec<Option<String>> =
+        sqlx::query_scalar("SELECT primary_error_code FROM ai_audits WHERE is_valid")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(stored, vec![None::<String>]);
+}
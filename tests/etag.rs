@@ -0,0 +1,78 @@
+//! Tests of the conditional requests of individual audits.
+
+mod common;
+
+use common::TestServer;
+use reqwest::{StatusCode, header};
+use serde_json::json;
+use sqlx::PgPool;
+
+#[sqlx::test]
+async fn unchanged_audit_is_not_modified(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let audit = server.create_audit("pub fn answer() -> u32 { 42 }").await;
+    let url = server.url(&format!("/audit/{}", audit["id"].as_str().unwrap()));
+
+    let response = server.client().get(&url).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag = response.headers()[header::ETAG].clone();
+
+    let response = server
+        .client()
+        .get(&url)
+        .header(header::IF_NONE_MATCH, &etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()[header::ETAG], etag);
+    assert!(response.bytes().await.unwrap().is_empty());
+
+    // Another tag gets the audit again, while a wildcard matches any version.
+    let response = server
+        .client()
+        .get(&url)
+        .header(header::IF_NONE_MATCH, "\"something-else\"")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = server
+        .client()
+        .get(&url)
+        .header(header::IF_NONE_MATCH, "*")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+}
+
+#[sqlx::test]
+async fn modified_audit_gets_a_new_etag(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let audit = server.create_audit("pub fn answer() -> u32 { 42 }").await;
+    let url = server.url(&format!("/audit/{}", audit["id"].as_str().unwrap()));
+    let response = server.client().get(&url).send().await.unwrap();
+    let etag = response.headers()[header::ETAG].clone();
+
+    let response = server
+        .graphql_as(
+            common::ADMIN_TOKEN,
+            "mutation($id: UUID!) { setTags(id: $id, tags: [\"reviewed\"]) { tags } }",
+            json!({ "id": audit["id"] }),
+        )
+        .await;
+    assert!(response["errors"].is_null(), "{}", response);
+
+    let response = server
+        .client()
+        .get(&url)
+        .header(header::IF_NONE_MATCH, &etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_ne!(response.headers()[header::ETAG], etag);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["tags"], json!(["reviewed"]));
+}
@@ -0,0 +1,105 @@
+//! Tests of the NDJSON export of the audits, `GET /audits/stream`.
+
+mod common;
+
+use common::TestServer;
+use futures_util::StreamExt;
+use reqwest::StatusCode;
+use sqlx::PgPool;
+use std::time::{Duration, Instant};
+
+/// The number of audits of the exported table.
+const AUDITS: i64 = 1_000_000;
+
+/// How much the memory of the server may grow while it streams the table, in KiB. The
+/// export of the table is well over a gigabyte.
+const MAX_MEMORY_GROWTH_KIB: u64 = 64 * 1024;
+
+/// Inserts `count` audits, without going through the server.
+async fn seed(pool: &PgPool, count: i64) {
+    sqlx::query(
+        "INSERT INTO ai_audits (prompt, generated_code, is_valid, status, code_line_count,
+             code_char_count, prompt_token_count, code_token_count)
+         SELECT 'Write a function', 'pub fn f() -> u64 { ' || n || ' }', true, 'valid', 1, 30,
+             3, 12
+         FROM generate_series(1, $1) AS n",
+    )
+    .bind(count)
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+/// Returns the number of queries of the audits the database is running.
+async fn running_exports(pool: &PgPool) -> i64 {
+    sqlx::query_scalar(
+        "SELECT COUNT(*) FROM pg_stat_activity
+         WHERE datname = current_database() AND backend_type = 'client backend'
+             AND state = 'active'
+             AND query LIKE '%FROM ai_audits%' AND pid <> pg_backend_pid()",
+    )
+    .fetch_one(pool)
+    .await
+    .unwrap()
+}
+
+#[sqlx::test]
+async fn a_million_audits_stream_in_flat_memory(pool: PgPool) {
+    seed(&pool, AUDITS).await;
+    let server = TestServer::start(&pool).await;
+    let baseline = server.memory_kib();
+
+    let response = server
+        .client()
+        .get(server.url("/audits/stream"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+    let mut body = response.bytes_stream();
+    let (mut lines, mut peak, mut first) = (0i64, baseline, None);
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.unwrap();
+        if first.is_none() {
+            let line = chunk.split(|&b| b == b'\n').next().unwrap();
+            first = Some(serde_json::from_slice::<serde_json::Value>(line).unwrap());
+        }
+        lines += chunk.iter().filter(|&&b| b == b'\n').count() as i64;
+        if lines % 10_000 < 100 {
+            peak = peak.max(server.memory_kib());
+        }
+    }
+
+    assert_eq!(lines, AUDITS);
+    assert_eq!(first.unwrap()["status"], "valid");
+    assert!(
+        peak < baseline + MAX_MEMORY_GROWTH_KIB,
+        "the server grew from {} KiB to {} KiB",
+        baseline,
+        peak
+    );
+}
+
+#[sqlx::test]
+async fn disconnecting_cancels_the_query(pool: PgPool) {
+    seed(&pool, 200_000).await;
+    let server = TestServer::start(&pool).await;
+
+    let response = server
+        .client()
+        .get(server.url("/audits/stream"))
+        .send()
+        .await
+        .unwrap();
+    let mut body = response.bytes_stream();
+    body.next().await.unwrap().unwrap();
+    assert_eq!(running_exports(&pool).await, 1);
+    drop(body);
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while running_exports(&pool).await > 0 {
+        assert!(Instant::now() < deadline, "the query still runs");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
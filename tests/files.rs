@@ -0,0 +1,222 @@
+//! Tests of audits of crates submitted as several files.
+
+mod common;
+
+use common::TestServer;
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+const MANIFEST: &str = "[package]\nname = \"tokenizer\"\nversion = \"0.1.0\"\nedition = \"2021\"\n";
+
+const LIB: &str = "pub mod token;\n";
+
+/// A module whose function returns a string where a number is expected.
+const BROKEN_TOKEN: &str = "pub fn width() -> usize {\n    \"4\"\n}\n";
+
+fn file(path: &str, content: &str) -> Value {
+    json!({ "path": path, "content": content })
+}
+
+/// Posts an audit of `files`, returning the status and body of the response.
+async fn post_files(server: &TestServer, files: Value) -> (StatusCode, Value) {
+    let response = server
+        .client()
+        .post(server.url("/audit"))
+        .json(&json!({ "prompt": "Write a tokenizer", "files": files }))
+        .send()
+        .await
+        .unwrap();
+    let status = response.status();
+    (status, response.json().await.unwrap())
+}
+
+/// Returns the diagnostics of an audit with their file.
+async fn diagnostics(server: &TestServer, audit: &Value) -> Vec<Value> {
+    let body = server
+        .graphql(
+            "query($id: UUID!) { audit(id: $id) { diagnostics { code file line } } }",
+            json!({ "id": audit["id"] }),
+        )
+        .await;
+    body["data"]["audit"]["diagnostics"]
+        .as_array()
+        .unwrap_or_else(|| panic!("no diagnostics in {}", body))
+        .clone()
+}
+
+async fn audit_count(pool: &PgPool) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM ai_audits")
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn path_traversal_is_rejected(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+
+    for (path, error) in [
+        (
+            "../escape.rs",
+            "../escape.rs: paths must be relative, without . or .. components",
+        ),
+        (
+            "src/../../escape.rs",
+            "src/../../escape.rs: paths must be relative, without . or .. components",
+        ),
+        (
+            "/tmp/escape.rs",
+            "/tmp/escape.rs: paths must be relative, without . or .. components",
+        ),
+        (
+            "src\\..\\..\\escape.rs",
+            "src\\..\\..\\escape.rs: paths must use / as the separator, without NUL characters",
+        ),
+        (
+            "a/b/c/d/e/escape.rs",
+            "a/b/c/d/e/escape.rs: files must be at most 4 directories deep",
+        ),
+        (
+            ".cargo/config.toml",
+            ".cargo/config.toml: only .rs files and a Cargo.toml at the root are accepted",
+        ),
+    ] {
+        let (status, body) =
+            post_files(&server, json!([file("src/lib.rs", LIB), file(path, "")])).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", path);
+        assert_eq!(body["error"], error);
+    }
+    assert_eq!(audit_count(&pool).await, 0);
+}
+
+#[sqlx::test]
+async fn manifests_with_build_scripts_or_path_dependencies_are_rejected(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let with_path_dependency = format!(
+        "{}\n[dependencies]\nsecrets = {{ path = \"/etc\" }}\n",
+        MANIFEST
+    );
+
+    let (status, body) = post_files(
+        &server,
+        json!([
+            file("Cargo.toml", &with_path_dependency),
+            file("src/lib.rs", LIB),
+            file("src/token.rs", ""),
+        ]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body["error"],
+        "Cargo.toml: the dependency `secrets` must come from crates.io"
+    );
+
+    let (status, body) = post_files(
+        &server,
+        json!([
+            file("Cargo.toml", MANIFEST),
+            file("build.rs", "fn main() {}"),
+            file("src/lib.rs", LIB),
+            file("src/token.rs", ""),
+        ]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body["error"],
+        "Cargo.toml: build scripts are not run; remove build.rs or set `build = false`"
+    );
+    assert_eq!(audit_count(&pool).await, 0);
+}
+
+#[sqlx::test]
+async fn crates_without_a_manifest_are_compiled_by_rustc(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+
+    let (status, audit) = post_files(
+        &server,
+        json!([file("src/lib.rs", LIB), file("src/token.rs", BROKEN_TOKEN)]),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(audit["status"], "compile_error");
+    assert_eq!(audit["compile_command"][0], "rustc");
+    // The crate root is the audited code.
+    assert_eq!(audit["generated_code"], LIB.trim());
+    let diagnostics = diagnostics(&server, &audit).await;
+    assert!(
+        diagnostics.contains(&json!({ "code": "E0308", "file": "src/token.rs", "line": 2 })),
+        "{:?}",
+        diagnostics
+    );
+}
+
+#[sqlx::test]
+async fn packages_are_checked_by_cargo(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+
+    let (status, audit) = post_files(
+        &server,
+        json!([
+            file("Cargo.toml", MANIFEST),
+            file("src/lib.rs", LIB),
+            file("src/token.rs", BROKEN_TOKEN),
+        ]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(audit["status"], "compile_error", "{}", audit);
+    assert_eq!(
+        audit["compile_command"],
+        json!([
+            "cargo",
+            "check",
+            "--message-format=json",
+            "--offline",
+            "--frozen",
+            "--quiet",
+            "--config",
+            "profile.dev.opt-level=0",
+        ])
+    );
+    assert!(
+        audit["compilation_error"]
+            .as_str()
+            .unwrap()
+            .contains("--> src/token.rs:2:5"),
+        "{}",
+        audit["compilation_error"]
+    );
+    let diagnostics = diagnostics(&server, &audit).await;
+    assert!(
+        diagnostics.contains(&json!({ "code": "E0308", "file": "src/token.rs", "line": 2 })),
+        "{:?}",
+        diagnostics
+    );
+
+    let (status, audit) = post_files(
+        &server,
+        json!([
+            file("Cargo.toml", MANIFEST),
+            file("src/lib.rs", LIB),
+            file("src/token.rs", "pub fn width() -> usize {\n    4\n}\n"),
+        ]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(audit["status"], "valid", "{}", audit);
+    let files: Vec<Value> = server
+        .client()
+        .get(server.url(&format!("/audit/{}/files", audit["id"].as_str().unwrap())))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(files.len(), 3);
+    assert_eq!(files[0], file("Cargo.toml", MANIFEST));
+}
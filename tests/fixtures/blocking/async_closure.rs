@@ -0,0 +1,6 @@
+pub async fn download(urls: Vec<String>) {
+    let fetch = async |url: String| reqwest::blocking::get(url).unwrap().text().unwrap();
+    for url in urls {
+        fetch(url).await;
+    }
+}
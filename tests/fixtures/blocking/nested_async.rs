@@ -0,0 +1,12 @@
+use std::time::Duration;
+
+pub async fn poll() {
+    let task = async {
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    task.await;
+}
+
+pub fn spawn() -> impl std::future::Future<Output = String> {
+    async move { std::fs::read_to_string("config.toml").unwrap() }
+}
@@ -0,0 +1,8 @@
+pub async fn load() -> String {
+    let text = tokio::task::spawn_blocking(|| std::fs::read_to_string("data.txt").unwrap())
+        .await
+        .unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    let bytes = tokio::fs::read("data.bin").await.unwrap();
+    format!("{} {}", text, bytes.len())
+}
@@ -0,0 +1,15 @@
+use std::time::Duration;
+
+pub fn wait_and_read() -> String {
+    std::thread::sleep(Duration::from_millis(10));
+    let read = || std::fs::read_to_string("data.txt").unwrap();
+    read()
+}
+
+pub struct Store;
+
+impl Store {
+    pub fn save(&self, data: &[u8]) {
+        std::fs::write("data.bin", data).unwrap();
+    }
+}
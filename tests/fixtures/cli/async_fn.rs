@@ -0,0 +1,4 @@
+/// Returns the answer, once awaited.
+pub async fn answer() -> u32 {
+    42
+}
@@ -0,0 +1,6 @@
+// SPDX-License-Identifier: MIT
+
+/// Returns the port the server listens on.
+pub fn port() -> u16 {
+    "8080"
+}
@@ -0,0 +1,6 @@
+// SPDX-License-Identifier: MIT
+
+/// Returns the largest value of a slice, if any.
+pub fn largest(values: &[i64]) -> Option<i64> {
+    values.iter().copied().max()
+}
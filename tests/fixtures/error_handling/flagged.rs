@@ -0,0 +1,27 @@
+use std::error::Error;
+
+pub fn parse_port(text: &str) -> Result<u16, String> {
+    text.parse().map_err(|_| format!("invalid port: {}", text))
+}
+
+pub fn load(path: &str) -> Result<String, Box<dyn Error>> {
+    Ok(std::fs::read_to_string(path)?)
+}
+
+pub fn first_number(text: &str) -> u32 {
+    text.parse::<u32>().ok().unwrap()
+}
+
+pub fn save(path: &str, data: &str) {
+    match std::fs::write(path, data) {
+        Ok(()) => println!("saved"),
+        _ => {}
+    }
+}
+
+pub fn checked_div(a: u32, b: u32) -> Result<u32, std::num::TryFromIntError> {
+    if b == 0 {
+        panic!("division by zero");
+    }
+    u32::try_from(u64::from(a) / u64::from(b))
+}
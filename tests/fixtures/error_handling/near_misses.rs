@@ -0,0 +1,60 @@
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug)]
+pub struct PortError(String);
+
+impl fmt::Display for PortError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid port: {}", self.0)
+    }
+}
+
+impl Error for PortError {}
+
+// A structured error type.
+pub fn parse_port(text: &str) -> Result<u16, PortError> {
+    text.parse().map_err(|_| PortError(text.to_string()))
+}
+
+// Private helpers and `main` may use unstructured errors.
+fn load(path: &str) -> Result<String, Box<dyn Error>> {
+    Ok(std::fs::read_to_string(path)?)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    println!("{}", load("config.toml")?);
+    Ok(())
+}
+
+// `.ok()` not followed by `unwrap`, and `unwrap_or` on the `Option`.
+pub fn first_number(text: &str) -> u32 {
+    text.parse::<u32>().ok().unwrap_or(0)
+}
+
+// The error arm handles the error, and the empty arm matches an `Option`.
+pub fn save(path: &str, data: &str, label: Option<&str>) {
+    match std::fs::write(path, data) {
+        Ok(()) => println!("saved"),
+        Err(e) => eprintln!("could not save: {}", e),
+    }
+    match label {
+        Some(label) => println!("{}", label),
+        _ => {}
+    }
+}
+
+// Panics outside of functions returning a `Result`, and in their closures.
+pub fn div(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        panic!("division by zero");
+    }
+    a / b
+}
+
+pub fn divide_all(values: &[u32], by: u32) -> Result<Vec<u32>, PortError> {
+    Ok(values
+        .iter()
+        .map(|v| v.checked_div(by).unwrap_or_else(|| panic!("division by zero")))
+        .collect())
+}
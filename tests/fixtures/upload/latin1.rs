@@ -0,0 +1,4 @@
+// Caf, encoded as Latin-1.
+pub fn name() -> &'static str {
+    "caf"
+}
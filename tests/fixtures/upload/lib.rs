@@ -0,0 +1,5 @@
+pub mod token;
+
+pub fn first(input: &str) -> Option<&str> {
+    token::split(input).next()
+}
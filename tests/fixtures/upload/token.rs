@@ -0,0 +1,3 @@
+pub fn split(input: &str) -> impl Iterator<Item = &str> {
+    input.split_whitespace()
+}
@@ -0,0 +1,63 @@
+//! Tests of the formatting of audited code with `rustfmt`.
+
+mod common;
+
+use common::TestServer;
+use serde_json::json;
+use sqlx::PgPool;
+use std::path::PathBuf;
+
+const UNFORMATTED: &str = "pub fn add(a:i32,b:i32)->i32{a+b}\n";
+const FORMATTED: &str = "pub fn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n";
+
+#[sqlx::test]
+async fn unformatted_code_needs_formatting(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+
+    let audit = server.create_audit(UNFORMATTED).await;
+
+    assert_eq!(audit["needs_formatting"], true, "{}", audit);
+    assert_eq!(audit["formatted_code"], FORMATTED);
+    let response = server
+        .graphql(
+            "query($id: UUID!) { audit(id: $id) { formattingDiff } }",
+            json!({ "id": audit["id"] }),
+        )
+        .await;
+    let diff = response["data"]["audit"]["formattingDiff"]
+        .as_str()
+        .unwrap();
+    assert!(
+        diff.contains("-pub fn add(a:i32,b:i32)->i32{a+b}"),
+        "{}",
+        diff
+    );
+    assert!(diff.contains("+    a + b"), "{}", diff);
+
+    let audit = server.create_audit(FORMATTED).await;
+    assert_eq!(audit["needs_formatting"], false, "{}", audit);
+}
+
+#[sqlx::test]
+async fn missing_rustfmt_leaves_formatting_unknown(pool: PgPool) {
+    // A `PATH` offering `rustc`, but not `rustfmt`.
+    let bin = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("rustc-without-rustfmt");
+    std::fs::create_dir_all(&bin).unwrap();
+    let rustc = String::from_utf8(
+        std::process::Command::new("rustup")
+            .args(["which", "rustc"])
+            .output()
+            .unwrap()
+            .stdout,
+    )
+    .unwrap();
+    let _ = std::fs::remove_file(bin.join("rustc"));
+    std::os::unix::fs::symlink(rustc.trim(), bin.join("rustc")).unwrap();
+    let server = TestServer::start_with(&pool, &[("PATH", bin.to_str().unwrap())]).await;
+
+    let audit = server.create_audit(UNFORMATTED).await;
+
+    assert_eq!(audit["is_valid"], true, "{}", audit);
+    assert!(audit["needs_formatting"].is_null(), "{}", audit);
+    assert!(audit["formatted_code"].is_null(), "{}", audit);
+}
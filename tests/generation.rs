@@ -0,0 +1,147 @@
+//! Tests of audits generated by an LLM provider, replaced by a stub `CodeGenerator`.
+
+use axum::{http::StatusCode, response::IntoResponse};
+use rust_ai_auditor::{
+    auditor::AuditPolicy,
+    error::AppError,
+    generation::{CodeGenerator, CodeGenerators, ModelResponse},
+    models::{AiAudit, AuditStatus, CreateAuditRequest},
+    notifications::AuditNotifiers,
+    services,
+    webhooks::WebhookNotifier,
+    workers::CompilationQueue,
+};
+use serde_json::json;
+use sqlx::PgPool;
+use std::{sync::Arc, time::Duration};
+
+/// How long the stub takes to answer.
+const LATENCY: Duration = Duration::from_millis(50);
+
+/// A provider answering every prompt the same way.
+struct Stub(fn() -> Result<ModelResponse, AppError>);
+
+#[async_trait::async_trait]
+impl CodeGenerator for Stub {
+    async fn generate(&self, _model: &str, _prompt: &str) -> Result<ModelResponse, AppError> {
+        tokio::time::sleep(LATENCY).await;
+        (self.0)()
+    }
+}
+
+/// Creates an audit of the code generated by `stub`, standing in for OpenAI.
+async fn create_audit(
+    pool: &PgPool,
+    stub: fn() -> Result<ModelResponse, AppError>,
+) -> Result<AiAudit, AppError> {
+    let generators = CodeGenerators {
+        openai: Some(Arc::new(Stub(stub))),
+        ..CodeGenerators::default()
+    };
+    let notifiers = AuditNotifiers {
+        webhooks: WebhookNotifier::new().unwrap(),
+        slack: None,
+    };
+    let input: CreateAuditRequest = serde_json::from_value(json!({
+        "prompt": "Write a function doubling a number",
+        "generate": { "provider": "openai", "model": "gpt-4o" },
+    }))
+    .unwrap();
+    services::create_audit(
+        pool,
+        &AuditPolicy::default(),
+        &CompilationQueue::start(1, Duration::from_secs(60)),
+        &generators,
+        &input,
+        None,
+        &notifiers,
+    )
+    .await
+}
+
+/// Returns the number of stored audits.
+async fn count_audits(pool: &PgPool) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM ai_audits")
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn generated_code_is_audited_with_its_usage_and_latency(pool: PgPool) {
+    let audit = create_audit(&pool, || {
+        Ok(ModelResponse {
+            text: "Sure:\n\n```rust\npub fn double(x: u32) -> u32 {\n    x * 2\n}\n```\n"
+                .to_string(),
+            prompt_tokens: Some(42),
+            completion_tokens: Some(17),
+        })
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(audit.status, AuditStatus::Valid);
+    assert_eq!(
+        audit.generated_code,
+        "pub fn double(x: u32) -> u32 {\n    x * 2\n}"
+    );
+    let stored: (Option<i32>, Option<i32>, Option<i32>, Option<String>) = sqlx::query_as(
+        "SELECT prompt_tokens, completion_tokens, generation_latency_ms, raw_model_output
+         FROM ai_audits WHERE id = $1",
+    )
+    .bind(audit.id)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!((stored.0, stored.1), (Some(42), Some(17)));
+    let latency = stored.2.expect("no latency was stored");
+    assert!(latency >= LATENCY.as_millis() as i32, "{}", latency);
+    assert!(stored.3.unwrap().starts_with("Sure:"));
+}
+
+#[sqlx::test]
+async fn provider_failures_map_to_distinct_errors(pool: PgPool) {
+    let rate_limited = create_audit(&pool, || {
+        Err(AppError::RateLimited(
+            "OpenAI rate limit exceeded, retry after 30 seconds".to_string(),
+        ))
+    })
+    .await
+    .unwrap_err();
+    assert!(matches!(rate_limited, AppError::RateLimited(_)));
+    assert_eq!(
+        rate_limited.into_response().status(),
+        StatusCode::TOO_MANY_REQUESTS
+    );
+
+    let failed = create_audit(&pool, || {
+        Err(AppError::Provider(
+            "OpenAI returned 500 Internal Server Error".to_string(),
+        ))
+    })
+    .await
+    .unwrap_err();
+    assert!(matches!(failed, AppError::Provider(_)));
+    assert_eq!(failed.into_response().status(), StatusCode::BAD_GATEWAY);
+
+    let missing = create_audit(&pool, || {
+        Ok(ModelResponse {
+            text: "I cannot help with that.".to_string(),
+            prompt_tokens: Some(42),
+            completion_tokens: Some(6),
+        })
+    })
+    .await
+    .unwrap_err();
+    assert_eq!(
+        missing.to_string(),
+        "Missing code block: The answer of gpt-4o contains no Rust code block"
+    );
+    assert_eq!(
+        missing.into_response().status(),
+        StatusCode::UNPROCESSABLE_ENTITY
+    );
+
+    // No audit is stored without code to audit.
+    assert_eq!(count_audits(&pool).await, 0);
+}
@@ -0,0 +1,145 @@
+//! Tests of the GitHub pull request integration, against a fake GitHub API.
+
+mod common;
+
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    routing::{get, post},
+};
+use common::TestServer;
+use reqwest::StatusCode;
+use serde_json::Value;
+use sqlx::PgPool;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// A `pull_request` delivery recorded from GitHub, and the signature it came with.
+const DELIVERY: &str = include_str!("fixtures/github/pull_request_opened.json");
+const DELIVERY_SIGNATURE: &str =
+    "sha256=5a126ab5ac8701176b2b6a4122d15c9551bd133ca23d39378688dafcc39c1d63";
+const WEBHOOK_SECRET: &str = "It's a Secret to Everybody";
+
+/// The files of the pull request of the delivery, as listed by the API.
+const FILES: &str = include_str!("fixtures/github/pull_request_files.json");
+
+/// The commit statuses posted on the fake API.
+type Statuses = Arc<Mutex<Vec<Value>>>;
+
+/// Starts a fake GitHub API serving the pull request of the delivery, and returns its
+/// URL with the commit statuses posted on it.
+async fn fake_github_api() -> (String, Statuses) {
+    let statuses = Statuses::default();
+    let app = Router::new()
+        .route(
+            "/repos/octocat/hello-world/pulls/42/files",
+            get(|| async { ([("content-type", "application/json")], FILES) }),
+        )
+        .route(
+            "/repos/octocat/hello-world/contents/{*path}",
+            get(|Path(path): Path<String>| async move {
+                match path.as_str() {
+                    "src/config.rs" => "pub fn port() -> u16 { 8080 }",
+                    _ => "pub fn broken() -> u16 { \"8080\" }",
+                }
+            }),
+        )
+        .route(
+            "/repos/octocat/hello-world/statuses/{sha}",
+            post(
+                |State(statuses): State<Statuses>, Json(status): Json<Value>| async move {
+                    statuses.lock().unwrap().push(status);
+                    StatusCode::CREATED
+                },
+            ),
+        )
+        .with_state(statuses.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    (url, statuses)
+}
+
+async fn start(pool: &PgPool, api_url: &str) -> TestServer {
+    TestServer::start_with(
+        pool,
+        &[
+            ("GITHUB_WEBHOOK_SECRET", WEBHOOK_SECRET),
+            ("GITHUB_TOKEN", "test-token"),
+            ("GITHUB_API_URL", api_url),
+        ],
+    )
+    .await
+}
+
+async fn deliver(server: &TestServer, signature: &str) -> reqwest::Response {
+    server
+        .client()
+        .post(server.url("/integrations/github/webhook"))
+        .header("X-GitHub-Event", "pull_request")
+        .header("X-Hub-Signature-256", signature)
+        .header("Content-Type", "application/json")
+        .body(DELIVERY)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn pull_request_is_audited_and_reported(pool: PgPool) {
+    let (api_url, statuses) = fake_github_api().await;
+    let server = start(&pool, &api_url).await;
+
+    let response = deliver(&server, DELIVERY_SIGNATURE).await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+    let started = Instant::now();
+    while statuses.lock().unwrap().len() < 2 {
+        assert!(
+            started.elapsed() < Duration::from_secs(30),
+            "no final status"
+        );
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let statuses = statuses.lock().unwrap().clone();
+    assert_eq!(statuses[0]["state"], "pending");
+    assert_eq!(statuses[1]["state"], "failure");
+    assert_eq!(
+        statuses[1]["description"],
+        "1 of 2 Rust files failed the audit"
+    );
+    assert_eq!(statuses[1]["context"], "rust-ai-auditor");
+
+    // Removed files and other languages are not audited.
+    let audited: Vec<(String, bool)> = sqlx::query_as(
+        "SELECT source_path, is_valid FROM ai_audits
+         WHERE source_repository = 'octocat/hello-world' ORDER BY source_path",
+    )
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        audited,
+        [
+            ("src/broken.rs".to_string(), false),
+            ("src/config.rs".to_string(), true)
+        ]
+    );
+}
+
+#[sqlx::test]
+async fn signature_mismatch_is_rejected(pool: PgPool) {
+    let (api_url, statuses) = fake_github_api().await;
+    let server = start(&pool, &api_url).await;
+
+    let forged = format!("sha256={}", "0".repeat(64));
+    let response = deliver(&server, &forged).await;
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let error: Value = response.json().await.unwrap();
+    assert_eq!(error["error"], "Invalid webhook signature");
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(statuses.lock().unwrap().is_empty());
+}
@@ -0,0 +1,191 @@
+//! Tests of the GraphQL endpoint over HTTP.
+
+mod common;
+
+use common::TestServer;
+use reqwest::{StatusCode, header};
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+#[sqlx::test]
+async fn get_executes_queries(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+
+    let response = server
+        .client()
+        .get(server.url("/graphql"))
+        .query(&[
+            (
+                "query",
+                "query Audits($max: Int) { audits(maxLines: $max) { id } }",
+            ),
+            ("variables", r#"{"max": 10}"#),
+            ("operationName", "Audits"),
+        ])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["errors"].is_null(), "{}", body);
+    assert_eq!(body["data"]["audits"], Value::Array(vec![]));
+}
+
+#[sqlx::test]
+async fn get_rejects_mutations(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+
+    let response = server
+        .client()
+        .get(server.url("/graphql"))
+        .query(&[(
+            "query",
+            r#"mutation { createAudit(input: { prompt: "p", generatedCode: "fn f() {}" }) { id } }"#,
+        )])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(response.headers()[header::ALLOW], "POST");
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM ai_audits")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+}
+
+#[sqlx::test]
+async fn get_negotiates_between_graphiql_and_json(pool: PgPool) {
+    let server = TestServer::start_with(&pool, &[("ENABLE_GRAPHIQL", "true")]).await;
+
+    // Browsers prefer HTML, so they get the IDE.
+    let response = server
+        .client()
+        .get(server.url("/graphql"))
+        .header(
+            header::ACCEPT,
+            "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(
+        response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("text/html")
+    );
+
+    // API clients accepting anything keep receiving JSON.
+    let response = server
+        .client()
+        .get(server.url("/graphql"))
+        .query(&[("query", "{ __typename }")])
+        .header(header::ACCEPT, "*/*")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["data"]["__typename"], "QueryRoot");
+}
+
+#[sqlx::test]
+async fn graphiql_is_not_served_when_disabled(pool: PgPool) {
+    let server = TestServer::start_with(&pool, &[("ENABLE_GRAPHIQL", "false")]).await;
+
+    let response = server.client().get(server.url("/")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Browsers asking `/graphql` for HTML do not get the IDE either.
+    let response = server
+        .client()
+        .get(server.url("/graphql"))
+        .query(&[("query", "{ __typename }")])
+        .header(header::ACCEPT, "text/html")
+        .send()
+        .await
+        .unwrap();
+    assert!(
+        !response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("text/html")
+    );
+
+    // The schema is still served.
+    let body = server.graphql("{ __typename }", Value::Null).await;
+    assert_eq!(body["data"]["__typename"], "QueryRoot");
+}
+
+#[sqlx::test]
+async fn graphiql_is_served_at_the_root_when_enabled(pool: PgPool) {
+    let server = TestServer::start_with(&pool, &[("ENABLE_GRAPHIQL", "true")]).await;
+
+    let response = server.client().get(server.url("/")).send().await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.text().await.unwrap().contains("graphiql"));
+}
+
+#[sqlx::test]
+async fn blank_code_is_rejected_with_a_message_naming_it(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let mutation = "mutation($code: String!) {
+        createAudit(input: { prompt: \"Write a function\", generatedCode: $code }) { id }
+    }";
+
+    for code in ["", " \n\t "] {
+        let body = server.graphql(mutation, json!({ "code": code })).await;
+        assert_eq!(
+            body["errors"][0]["message"],
+            "Validation error: generated_code must not be empty unless generate or files is set",
+            "{}",
+            body
+        );
+    }
+
+    // The REST API maps the same error to `400 Bad Request`.
+    let response = server
+        .client()
+        .post(server.url("/audit"))
+        .json(&json!({ "prompt": "Write a function", "generated_code": "  " }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(
+        body["error"],
+        "generated_code must not be empty unless generate or files is set"
+    );
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM ai_audits")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+
+    // Surrounding whitespace is trimmed before the audit is stored.
+    let body = server
+        .graphql(
+            "mutation($code: String!) {
+                createAudit(input: { prompt: \"  Write a function \", generatedCode: $code }) {
+                    prompt generatedCode
+                }
+            }",
+            json!({ "code": "\n pub fn f() {}\n\n" }),
+        )
+        .await;
+    assert_eq!(
+        body["data"]["createAudit"]["prompt"], "Write a function",
+        "{}",
+        body
+    );
+    assert_eq!(
+        body["data"]["createAudit"]["generatedCode"],
+        "pub fn f() {}"
+    );
+}
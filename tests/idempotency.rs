@@ -0,0 +1,152 @@
+//! Tests of the idempotency keys of audit creation.
+
+mod common;
+
+use common::TestServer;
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+const CODE: &str = "pub fn add(a: i32, b: i32) -> i32 { a + b }";
+
+async fn create_audit(server: &TestServer, key: &str, body: &Value) -> (StatusCode, Value) {
+    let response = server
+        .client()
+        .post(server.url("/audit"))
+        .header("Idempotency-Key", key)
+        .json(body)
+        .send()
+        .await
+        .unwrap();
+    let status = response.status();
+    (status, response.json().await.unwrap())
+}
+
+async fn count_audits(pool: &PgPool) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM ai_audits")
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn same_key_creates_a_single_audit(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let body = json!({ "prompt": "Add two numbers", "generated_code": CODE });
+
+    let (first_status, first) = create_audit(&server, "key-1", &body).await;
+    let (second_status, second) = create_audit(&server, "key-1", &body).await;
+
+    assert_eq!(first_status, StatusCode::CREATED);
+    assert_eq!(second_status, StatusCode::OK);
+    assert_eq!(first, second);
+    assert_eq!(count_audits(&pool).await, 1);
+}
+
+#[sqlx::test]
+async fn same_key_with_another_payload_conflicts(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let body = json!({ "prompt": "Add two numbers", "generated_code": CODE });
+    let other = json!({ "prompt": "Add two other numbers", "generated_code": CODE });
+
+    let (status, _) = create_audit(&server, "key-2", &body).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, error) = create_audit(&server, "key-2", &other).await;
+
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert!(
+        error["error"]
+            .as_str()
+            .unwrap()
+            .contains("different payload"),
+        "{}",
+        error
+    );
+    assert_eq!(count_audits(&pool).await, 1);
+}
+
+#[sqlx::test]
+async fn same_key_with_other_compilation_settings_conflicts(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let body = json!({ "prompt": "Add two numbers", "generated_code": CODE });
+
+    let (status, _) = create_audit(&server, "key-5", &body).await;
+    assert_eq!(status, StatusCode::CREATED);
+    for other in [
+        json!({ "prompt": "Add two numbers", "generated_code": CODE, "edition": "2018" }),
+        json!({ "prompt": "Add two numbers", "generated_code": CODE, "channel": "nightly" }),
+        json!({ "prompt": "Add two numbers", "generated_code": CODE, "strict": true }),
+        json!({ "prompt": "Add two numbers", "generated_code": CODE, "tags": ["baseline"] }),
+    ] {
+        let (status, error) = create_audit(&server, "key-5", &other).await;
+        assert_eq!(status, StatusCode::CONFLICT, "{}: {}", other, error);
+    }
+    assert_eq!(count_audits(&pool).await, 1);
+}
+
+#[sqlx::test]
+async fn omitted_settings_match_their_defaults(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let body = json!({ "prompt": "Add two numbers", "generated_code": CODE });
+    let explicit = json!({
+        "prompt": "Add two numbers",
+        "generated_code": CODE,
+        "edition": "2021",
+        "channel": "stable",
+        "opt_level": 0,
+        "profile": "default",
+        "strict": false,
+    });
+
+    let (status, first) = create_audit(&server, "key-6", &body).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, second) = create_audit(&server, "key-6", &explicit).await;
+
+    assert_eq!(status, StatusCode::OK, "{}", second);
+    assert_eq!(first["id"], second["id"]);
+    assert_eq!(count_audits(&pool).await, 1);
+}
+
+#[sqlx::test]
+async fn concurrent_requests_with_the_same_key_create_one_audit(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let body = json!({ "prompt": "Add two numbers", "generated_code": CODE });
+
+    let responses =
+        futures_util::future::join_all((0..8).map(|_| create_audit(&server, "key-3", &body))).await;
+
+    // The requests that lose the race either get the audit or are told to retry.
+    let mut created = 0;
+    for (status, audit) in &responses {
+        match *status {
+            StatusCode::CREATED => created += 1,
+            StatusCode::OK => assert_eq!(audit["generated_code"], CODE),
+            StatusCode::CONFLICT => {}
+            status => panic!("{}: {}", status, audit),
+        }
+    }
+    assert_eq!(created, 1);
+    assert_eq!(count_audits(&pool).await, 1);
+}
+
+#[sqlx::test]
+async fn concurrent_mutations_with_the_same_key_create_one_audit(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let mutation = "mutation($input: CreateAuditRequest!) { createAudit(input: $input) { id } }";
+    let variables = json!({
+        "input": { "prompt": "Add two numbers", "generatedCode": CODE, "idempotencyKey": "key-4" }
+    });
+
+    let responses =
+        futures_util::future::join_all((0..4).map(|_| server.graphql(mutation, variables.clone())))
+            .await;
+
+    let ids: Vec<&Value> = responses
+        .iter()
+        .map(|response| &response["data"]["createAudit"]["id"])
+        .filter(|id| !id.is_null())
+        .collect();
+    assert!(!ids.is_empty(), "{:?}", responses);
+    assert!(ids.iter().all(|id| *id == ids[0]), "{:?}", responses);
+    assert_eq!(count_audits(&pool).await, 1);
+}
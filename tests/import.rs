@@ -0,0 +1,107 @@
+//! Tests of the bulk import of audit records.
+
+mod common;
+
+use common::TestServer;
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+async fn count_audits(pool: &PgPool) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM ai_audits")
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn records_are_stored_unchanged(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let records = json!([
+        {
+            "id": "6f9619ff-8b86-4d11-b42d-00c04fc964ff",
+            "prompt": "Add two numbers",
+            "codigo_generado": "pub fn add(a: i32, b: i32) -> i32 { a + b }",
+            "es_valido": true,
+            "created_at": "2024-01-02T03:04:05Z"
+        },
+        {
+            "id": "7f9619ff-8b86-4d11-b42d-00c04fc964ff",
+            "prompt": "Parse a port",
+            // Stored as given, even though this code compiles.
+            "codigo_generado": "pub fn port() -> u16 { 8080 }",
+            "es_valido": false,
+            "error_compilacion": "error[E0308]: mismatched types",
+            "created_at": "2024-01-03T03:04:05Z"
+        },
+        { "prompt": "", "generated_code": "fn f() {}", "is_valid": true }
+    ]);
+
+    let response = server.admin_post("/admin/import", &records).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let report: Value = response.json().await.unwrap();
+    assert_eq!(report["attempted"], 3);
+    assert_eq!(report["imported"], 2);
+    assert_eq!(
+        report["errors"],
+        json!([{ "index": 2, "message": "prompt must not be empty" }])
+    );
+
+    let stored: Vec<(String, bool, Option<String>, String)> = sqlx::query_as(
+        "SELECT generated_code, is_valid, compilation_error,
+                to_char(created_at AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS')
+         FROM ai_audits ORDER BY created_at",
+    )
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        stored,
+        [
+            (
+                "pub fn add(a: i32, b: i32) -> i32 { a + b }".to_string(),
+                true,
+                None,
+                "2024-01-02T03:04:05".to_string()
+            ),
+            (
+                "pub fn port() -> u16 { 8080 }".to_string(),
+                false,
+                Some("error[E0308]: mismatched types".to_string()),
+                "2024-01-03T03:04:05".to_string()
+            ),
+        ]
+    );
+
+    // Importing the same records again skips them.
+    let report: Value = server
+        .admin_post("/admin/import", &records)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["imported"], 0);
+    assert_eq!(report["skipped"], 2);
+    assert_eq!(count_audits(&pool).await, 2);
+}
+
+#[sqlx::test]
+async fn import_requires_the_admin_token(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let records = json!([
+        { "prompt": "Add", "generated_code": "fn f() {}", "is_valid": true }
+    ]);
+
+    for path in ["/admin/import", "/audits/import"] {
+        let response = server
+            .client()
+            .post(server.url(path))
+            .json(&records)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{}", path);
+    }
+    assert_eq!(count_audits(&pool).await, 0);
+}
@@ -0,0 +1,238 @@
+//! Tests of the Postgres-backed queue of background audit jobs.
+
+mod common;
+
+use common::TestServer;
+use reqwest::StatusCode;
+use rust_ai_auditor::services;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+
+/// Keeps the const evaluator of `rustc` busy until the compilation timeout kills it.
+const SPIN: &str = "#![allow(long_running_const_eval)]\n\npub const SPIN: u64 = {\n    let mut i = 0u64;\n    while i < u64::MAX {\n        i += 1;\n    }\n    i\n};\n";
+
+/// How long the jobs of a test may take to run.
+const JOBS_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Enqueues the audit of `code` and returns its job.
+async fn enqueue(server: &TestServer, code: &str) -> Value {
+    let response = server
+        .client()
+        .post(server.url("/audit/async"))
+        .json(&json!({ "prompt": "Write a function", "generated_code": code }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    response.json().await.unwrap()
+}
+
+/// Returns the number of jobs waiting for a worker or being run.
+async fn unfinished_jobs(pool: &PgPool) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM audit_jobs WHERE state IN ('pending', 'running')")
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn concurrent_claims_never_return_the_same_job(pool: PgPool) {
+    const JOBS: i64 = 200;
+    sqlx::query(
+        "WITH audits AS (
+             INSERT INTO ai_audits (prompt, generated_code, is_valid, status, code_line_count,
+                 code_char_count, prompt_token_count, code_token_count)
+             SELECT 'Write a function', 'pub fn f() {}', false, 'pending', 1, 13, 3, 5
+             FROM generate_series(1, $1)
+             RETURNING id
+         )
+         INSERT INTO audit_jobs (audit_id, state) SELECT id, 'pending' FROM audits",
+    )
+    .bind(JOBS)
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let workers: Vec<_> = (0..16)
+        .map(|_| {
+            let pool = pool.clone();
+            tokio::spawn(async move {
+                let mut claimed = Vec::new();
+                while let Some(job) = services::claim_audit_job(&pool, Duration::from_secs(600), 3)
+                    .await
+                    .unwrap()
+                {
+                    claimed.push(job.id);
+                }
+                claimed
+            })
+        })
+        .collect();
+    let mut claimed = Vec::new();
+    for worker in workers {
+        claimed.extend(worker.await.unwrap());
+    }
+
+    assert_eq!(claimed.len() as i64, JOBS);
+    assert_eq!(claimed.iter().collect::<HashSet<_>>().len() as i64, JOBS);
+    let attempts: Vec<i32> = sqlx::query_scalar("SELECT DISTINCT attempts FROM audit_jobs")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(attempts, [1]);
+}
+
+#[sqlx::test]
+async fn replicas_share_the_queue_without_running_a_job_twice(pool: PgPool) {
+    let envs = [("AUDIT_JOB_WORKERS", "4")];
+    let replicas = [
+        TestServer::start_with(&pool, &envs).await,
+        TestServer::start_with(&pool, &envs).await,
+    ];
+    for i in 0..12 {
+        let code = format!("pub fn answer() -> u32 {{\n    {}\n}}\n", i);
+        enqueue(&replicas[i % 2], &code).await;
+    }
+
+    let started = Instant::now();
+    while unfinished_jobs(&pool).await > 0 {
+        assert!(started.elapsed() < JOBS_TIMEOUT, "the jobs did not finish");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    // A job claimed twice would have a second attempt.
+    let jobs: Vec<(String, i32)> = sqlx::query_as("SELECT state, attempts FROM audit_jobs")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(jobs, vec![("done".to_string(), 1); 12]);
+    let valid: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM ai_audits WHERE status = 'valid'")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(valid, 12);
+}
+
+#[sqlx::test]
+async fn the_worker_pool_bounds_rustc_processes(pool: PgPool) {
+    let server = TestServer::start_with(
+        &pool,
+        &[
+            ("AUDIT_JOB_WORKERS", "6"),
+            ("AUDIT_WORKER_CONCURRENCY", "2"),
+            ("COMPILATION_TIMEOUT_SECS", "2"),
+        ],
+    )
+    .await;
+    for _ in 0..6 {
+        enqueue(&server, SPIN).await;
+    }
+
+    let started = Instant::now();
+    let mut most = 0;
+    while unfinished_jobs(&pool).await > 0 {
+        assert!(started.elapsed() < JOBS_TIMEOUT, "the jobs did not finish");
+        most = most.max(server.child_processes("rustc"));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    assert_eq!(most, 2);
+    assert_eq!(server.child_processes("rustc"), 0);
+}
+
+#[sqlx::test]
+async fn a_submitted_job_is_polled_until_done(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let job = enqueue(&server, "pub fn answer() -> u32 {\n    42\n}\n").await;
+    assert_eq!(job["state"], "pending");
+    assert!(job["audit"].is_null());
+
+    let url = server.url(&format!("/audit/job/{}", job["id"].as_str().unwrap()));
+    let started = Instant::now();
+    let job = loop {
+        let job: Value = server
+            .client()
+            .get(&url)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        if job["state"] != "pending" && job["state"] != "running" {
+            break job;
+        }
+        assert!(started.elapsed() < JOBS_TIMEOUT, "the job did not finish");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    };
+    assert_eq!(job["state"], "done", "{}", job);
+    assert_eq!(job["attempts"], 1);
+    assert_eq!(job["audit"]["status"], "valid");
+
+    let audit: Value = server
+        .client()
+        .get(server.url(&format!("/audit/{}", job["audit_id"].as_str().unwrap())))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(audit, job["audit"]);
+}
+
+#[sqlx::test]
+async fn jobs_that_failed_too_many_times_are_failed_until_retried(pool: PgPool) {
+    let server = TestServer::start_with(&pool, &[("AUDIT_JOB_WORKERS", "0")]).await;
+    let job = enqueue(&server, "pub fn answer() -> u32 {\n    42\n}\n").await;
+    sqlx::query(
+        "UPDATE audit_jobs SET state = 'failed', attempts = 5, last_error = 'Connection lost'",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let url = server.url(&format!("/audit/job/{}", job["id"].as_str().unwrap()));
+    let failed: Value = server
+        .client()
+        .get(&url)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(failed["state"], "failed");
+    assert_eq!(failed["last_error"], "Connection lost");
+    let body = server
+        .graphql_as(
+            common::ADMIN_TOKEN,
+            "{ jobQueue { deadCount deadLetters { id state } } }",
+            Value::Null,
+        )
+        .await;
+    assert_eq!(
+        body["data"]["jobQueue"],
+        json!({ "deadCount": 1, "deadLetters": [{ "id": job["id"], "state": "FAILED" }] }),
+        "{}",
+        body
+    );
+
+    let body = server
+        .graphql_as(
+            common::ADMIN_TOKEN,
+            "mutation($id: UUID!) { retryJob(id: $id) { state attempts } }",
+            json!({ "id": job["id"] }),
+        )
+        .await;
+    assert_eq!(
+        body["data"]["retryJob"],
+        json!({ "state": "PENDING", "attempts": 0 }),
+        "{}",
+        body
+    );
+}
@@ -0,0 +1,110 @@
+//! Tests of the JUnit XML report of audits.
+
+mod common;
+
+use common::TestServer;
+use reqwest::{StatusCode, header};
+use serde_json::{Value, json};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Compiler output with markup characters, a CDATA terminator and the ANSI escapes of
+/// colored output.
+const COMPILER_OUTPUT: &str = "\u{1b}[31merror[E0308]\u{1b}[0m: mismatched types\n  \
+    expected `Vec<u32>`, found `&str` && `]]>`\n";
+
+async fn import(server: &TestServer, records: Value) -> Vec<Uuid> {
+    let response = server.admin_post("/admin/import", &records).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let ids: Vec<Uuid> = records
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|record| record["id"].as_str().unwrap().parse().unwrap())
+        .collect();
+    ids
+}
+
+async fn junit(server: &TestServer, ids: &[Uuid]) -> String {
+    let ids: Vec<String> = ids.iter().map(Uuid::to_string).collect();
+    let response = server
+        .client()
+        .get(server.url("/audits/report/junit"))
+        .query(&[("ids", ids.join(","))])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(
+        response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .contains("xml")
+    );
+    response.text().await.unwrap()
+}
+
+#[sqlx::test]
+async fn report_is_well_formed_and_escaped(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let (valid, failed) = (Uuid::new_v4(), Uuid::new_v4());
+    let ids = import(
+        &server,
+        json!([
+            {
+                "id": valid,
+                "prompt": "Sum a Vec<u32>",
+                "generated_code": "pub fn sum(v: Vec<u32>) -> u32 { v.iter().sum() }",
+                "is_valid": true,
+            },
+            {
+                "id": failed,
+                "prompt": "Parse \"<T>\" & friends\u{7}\nwith a second line",
+                "generated_code": "pub fn parse() -> Vec<u32> { \"1\" }",
+                "is_valid": false,
+                "compilation_error": COMPILER_OUTPUT,
+            },
+        ]),
+    )
+    .await;
+    // Imported records carry no compilation duration.
+    for (id, duration) in [(valid, 250), (failed, 500)] {
+        sqlx::query("UPDATE ai_audits SET compilation_duration_ms = $2 WHERE id = $1")
+            .bind(id)
+            .bind(duration)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    let xml = junit(&server, &ids).await;
+    let document = roxmltree::Document::parse(&xml).expect("the report is not well-formed XML");
+
+    let suite = document
+        .descendants()
+        .find(|node| node.has_tag_name("testsuite"))
+        .unwrap();
+    assert_eq!(suite.attribute("tests"), Some("2"));
+    assert_eq!(suite.attribute("failures"), Some("1"));
+    assert_eq!(suite.attribute("time"), Some("0.750"));
+
+    let cases: Vec<_> = suite
+        .children()
+        .filter(|node| node.has_tag_name("testcase"))
+        .collect();
+    assert_eq!(cases[0].attribute("name"), Some("Sum a Vec<u32>"));
+    assert!(cases[0].children().all(|node| !node.is_element()));
+    // Only the first line of the prompt names the case, and the bell character, which
+    // XML cannot carry, is replaced.
+    assert_eq!(
+        cases[1].attribute("name"),
+        Some("Parse \"<T>\" & friends\u{FFFD}")
+    );
+    let failure = cases[1]
+        .children()
+        .find(|node| node.has_tag_name("failure"))
+        .unwrap();
+    assert_eq!(failure.attribute("type"), Some("compile_error"));
+    let details: String = failure.children().filter_map(|node| node.text()).collect();
+    assert_eq!(details, COMPILER_OUTPUT.replace('\u{1b}', "\u{FFFD}"));
+}
@@ -0,0 +1,96 @@
+//! Tests of the in-process `Auditor`, used as a library without a server or a database.
+
+use rust_ai_auditor::{
+    auditor::AuditPolicy,
+    error::AppError,
+    models::{AuditStatus, CreateAuditRequest},
+    pipeline::Auditor,
+    workers::CompilationQueue,
+};
+use serde_json::{Value, json};
+use std::time::Duration;
+
+fn auditor() -> Auditor {
+    Auditor::new(
+        AuditPolicy::default(),
+        CompilationQueue::start(2, Duration::from_secs(60)),
+    )
+}
+
+fn request(body: Value) -> CreateAuditRequest {
+    serde_json::from_value(body).unwrap()
+}
+
+#[tokio::test]
+async fn valid_code_is_compiled_and_scored() {
+    let outcome = auditor()
+        .audit(request(json!({
+            "prompt": "Write a function doubling a number",
+            "generated_code": "/// Doubles `x`.\npub fn double(x: u32) -> u32 {\n    x * 2\n}\n",
+        })))
+        .await
+        .unwrap();
+
+    assert!(outcome.is_valid);
+    assert_eq!(outcome.status, AuditStatus::Valid);
+    assert!(outcome.compilation_error.is_none());
+    assert!(outcome.rustc_version.is_some());
+    assert!(outcome.compilation_duration_ms.is_some());
+}
+
+#[tokio::test]
+async fn compile_errors_are_reported_with_their_diagnostics() {
+    let outcome = auditor()
+        .audit(request(json!({
+            "prompt": "Write a function returning a width",
+            "generated_code": "pub fn width() -> usize {\n    \"4\"\n}\n",
+        })))
+        .await
+        .unwrap();
+
+    assert!(!outcome.is_valid);
+    assert_eq!(outcome.status, AuditStatus::CompileError);
+    assert_eq!(outcome.primary_error_code.as_deref(), Some("E0308"));
+    assert!(
+        outcome
+            .diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.code.as_deref() == Some("E0308")),
+        "{:?}",
+        outcome.diagnostics
+    );
+}
+
+#[tokio::test]
+async fn crates_of_several_files_are_audited_from_their_root() {
+    let outcome = auditor()
+        .audit(request(json!({
+            "prompt": "Write a tokenizer",
+            "files": [
+                { "path": "src/lib.rs", "content": "pub mod token;\n" },
+                { "path": "src/token.rs", "content": "pub fn width() -> usize {\n    4\n}\n" },
+            ],
+        })))
+        .await
+        .unwrap();
+
+    assert_eq!(outcome.status, AuditStatus::Valid, "{:?}", outcome);
+}
+
+#[tokio::test]
+async fn requests_needing_the_server_are_rejected() {
+    let auditor = auditor();
+    for body in [
+        json!({ "prompt": " ", "generated_code": "pub fn f() {}" }),
+        json!({ "prompt": "Write a function", "generated_code": "" }),
+        json!({ "prompt": "Write a function", "generated_code": "pub fn f() {}", "background": true }),
+    ] {
+        let result = auditor.audit(request(body.clone())).await;
+        assert!(
+            matches!(result, Err(AppError::Validation(_))),
+            "{}: {:?}",
+            body,
+            result.map(|outcome| outcome.status)
+        );
+    }
+}
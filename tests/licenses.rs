@@ -0,0 +1,183 @@
+//! Tests of the check of the licenses of the dependencies of Cargo packages.
+//!
+//! The packages depend on crates of the local registry cache: `untrusted` 0.9.0, under
+//! the ISC license, and `r-efi` 5.3.0, under `MIT OR Apache-2.0 OR LGPL-2.1-or-later`.
+
+mod common;
+
+use common::{TestServer, package};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+const SET_POLICY: &str = "mutation($input: LicensePolicyInput!) { \
+     licensePolicy(input: $input) { allowed denied } }";
+
+/// Returns the severity and message of the `RAA0301` findings of an audit.
+fn violations(audit: &Value) -> Vec<(&str, &str)> {
+    audit["findings"]
+        .as_array()
+        .expect("the audit has no findings")
+        .iter()
+        .filter(|finding| finding["code"] == "RAA0301")
+        .map(|finding| {
+            assert_eq!(finding["file"], "Cargo.toml");
+            (
+                finding["severity"].as_str().unwrap(),
+                finding["message"].as_str().unwrap(),
+            )
+        })
+        .collect()
+}
+
+/// Returns the identifiers of the audits listed by `GET /audits?license_violations=`.
+async fn listed(server: &TestServer, license_violations: bool) -> Vec<Value> {
+    let audits: Vec<Value> = server
+        .client()
+        .get(server.url("/audits"))
+        .query(&[("license_violations", license_violations)])
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    audits
+        .into_iter()
+        .map(|audit| audit["id"].clone())
+        .collect()
+}
+
+#[sqlx::test]
+async fn licenses_outside_the_default_policy_are_reported(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let response = server
+        .graphql("{ licensePolicy { allowed denied } }", json!({}))
+        .await;
+    assert_eq!(
+        response["data"]["licensePolicy"],
+        json!({ "allowed": ["MIT", "Apache-2.0", "BSD-*"], "denied": ["AGPL-*", "GPL-*"] })
+    );
+
+    let audit = server
+        .create_audit_with(json!({
+            "files": package("untrusted = \"=0.9.0\"\nr-efi = \"=5.3.0\"\n"),
+        }))
+        .await;
+
+    assert_eq!(audit["status"], "valid", "{}", audit);
+    assert_eq!(audit["license_violation_count"], 1);
+    assert_eq!(
+        violations(&audit),
+        [(
+            "warning",
+            "Depends on untrusted 0.9.0, licensed under ISC, which the license policy does \
+             not allow"
+        )]
+    );
+
+    let clean = server
+        .create_audit_with(json!({ "files": package("") }))
+        .await;
+    assert_eq!(clean["license_violation_count"], 0);
+    let single = server
+        .create_audit("pub fn answer() -> u32 {\n    42\n}\n")
+        .await;
+    assert!(single["license_violation_count"].is_null());
+
+    assert_eq!(listed(&server, true).await, [audit["id"].clone()]);
+    let without = listed(&server, false).await;
+    assert_eq!(without.len(), 2);
+    assert!(without.contains(&clean["id"]) && without.contains(&single["id"]));
+    let response = server
+        .graphql(
+            "{ audits(licenseViolations: true) { id licenseViolationCount } }",
+            json!({}),
+        )
+        .await;
+    assert_eq!(
+        response["data"]["audits"],
+        json!([{ "id": audit["id"], "licenseViolationCount": 1 }]),
+        "{}",
+        response
+    );
+}
+
+#[sqlx::test]
+async fn denied_licenses_are_errors_unless_an_alternative_is_allowed(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let files = package("untrusted = \"=0.9.0\"\nr-efi = \"=5.3.0\"\n");
+    // Cached under the default policy, which changing the policy must not serve again.
+    let audit = server
+        .create_audit_with(json!({ "files": files.clone() }))
+        .await;
+    assert_eq!(violations(&audit)[0].0, "warning");
+
+    let response = server
+        .graphql_as(
+            common::ADMIN_TOKEN,
+            SET_POLICY,
+            json!({ "input": { "allowed": ["Apache-2.0"], "denied": ["ISC", "MIT", "LGPL-*"] } }),
+        )
+        .await;
+    assert!(response["errors"].is_null(), "{}", response);
+    let audit = server.create_audit_with(json!({ "files": files })).await;
+
+    // `r-efi` may still be used under the Apache 2.0 license.
+    assert_eq!(audit["license_violation_count"], 1);
+    assert_eq!(
+        violations(&audit),
+        [(
+            "error",
+            "Depends on untrusted 0.9.0, licensed under ISC, which the license policy denies"
+        )]
+    );
+}
+
+#[sqlx::test]
+async fn only_administrators_set_valid_license_policies(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let input = json!({ "input": { "allowed": ["MIT"], "denied": ["GPL-*"] } });
+
+    let response = server.graphql(SET_POLICY, input.clone()).await;
+    assert_eq!(
+        response["errors"][0]["message"],
+        "Unauthorized: Administrator bearer token required"
+    );
+
+    let response = server
+        .graphql_as(
+            common::ADMIN_TOKEN,
+            SET_POLICY,
+            json!({ "input": { "denied": ["GPL-3.0 OR MIT"] } }),
+        )
+        .await;
+    assert_eq!(
+        response["errors"][0]["message"],
+        "Validation error: denied: 'GPL-3.0 OR MIT' is not an SPDX license identifier",
+        "{}",
+        response
+    );
+
+    let response = server
+        .graphql_as(common::ADMIN_TOKEN, SET_POLICY, input)
+        .await;
+    assert_eq!(
+        response["data"]["licensePolicy"],
+        json!({ "allowed": ["MIT"], "denied": ["GPL-*"] })
+    );
+    let response = server
+        .graphql("{ licensePolicy { allowed denied } }", json!({}))
+        .await;
+    assert_eq!(
+        response["data"]["licensePolicy"],
+        json!({ "allowed": ["MIT"], "denied": ["GPL-*"] })
+    );
+    let response = server
+        .client()
+        .get(server.url("/audits?license_violations=maybe"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
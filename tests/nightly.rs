@@ -0,0 +1,77 @@
+//! Tests of the compilation of code on the nightly channel.
+
+mod common;
+
+use common::TestServer;
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+use std::process::Command;
+
+/// Uses a library feature that is unstable, so only nightly accepts the feature gate.
+const UNSTABLE_CODE: &str = "#![feature(f16)]\n\npub fn half(x: f16) -> f16 {\n    x / 2.0\n}\n";
+
+fn nightly_installed() -> bool {
+    Command::new("rustc")
+        .args(["+nightly", "--version"])
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+#[sqlx::test]
+async fn feature_gates_compile_on_nightly_only(pool: PgPool) {
+    if !nightly_installed() {
+        eprintln!("skipping: the nightly toolchain is not installed");
+        return;
+    }
+    let server = TestServer::start(&pool).await;
+
+    let nightly = server
+        .create_audit_with(json!({ "generated_code": UNSTABLE_CODE, "channel": "nightly" }))
+        .await;
+    assert_eq!(
+        nightly["is_valid"], true,
+        "{}",
+        nightly["compilation_error"]
+    );
+    assert_eq!(nightly["channel"], "nightly");
+    assert!(
+        nightly["rustc_version"]
+            .as_str()
+            .unwrap()
+            .contains("nightly")
+    );
+
+    let stable = server.create_audit(UNSTABLE_CODE).await;
+    assert_eq!(stable["is_valid"], false);
+    assert_eq!(stable["channel"], "stable");
+    assert_eq!(stable["primary_error_code"], "E0554");
+}
+
+#[sqlx::test]
+async fn missing_nightly_toolchain_is_reported(pool: PgPool) {
+    let server = TestServer::start_with(&pool, &[("RUSTC_NIGHTLY", "/nonexistent/rustc")]).await;
+
+    let response = server
+        .client()
+        .post(server.url("/audit"))
+        .json(&json!({
+            "prompt": "Halve a number",
+            "generated_code": UNSTABLE_CODE,
+            "channel": "nightly",
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let error: Value = response.json().await.unwrap();
+    assert!(
+        error["error"]
+            .as_str()
+            .unwrap()
+            .contains("The nightly toolchain is not installed"),
+        "{}",
+        error
+    );
+}
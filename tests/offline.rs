@@ -0,0 +1,78 @@
+//! Tests of the offline resolution of the dependencies of Cargo packages.
+//!
+//! The packages depend on `untrusted` 0.9.0, which is in the local registry cache of the
+//! server, or on crates that were never downloaded there.
+
+mod common;
+
+use common::{TestServer, package};
+use serde_json::json;
+use sqlx::PgPool;
+
+#[sqlx::test]
+async fn cached_dependencies_are_compiled_offline(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let mut files = package("untrusted = \"=0.9.0\"\n");
+    files[1]["content"] =
+        json!("pub fn len(bytes: &[u8]) -> usize {\n    untrusted::Input::from(bytes).len()\n}\n");
+    let audit = server.create_audit_with(json!({ "files": files })).await;
+
+    assert_eq!(audit["status"], "valid", "{}", audit);
+    assert!(
+        audit["compile_command"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("--frozen")),
+        "{}",
+        audit["compile_command"]
+    );
+}
+
+#[sqlx::test]
+async fn dependencies_missing_from_the_cache_fail_cleanly(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+
+    let audit = server
+        .create_audit_with(json!({ "files": package("raa-never-published = \"1\"\n") }))
+        .await;
+    assert_eq!(audit["status"], "compile_error", "{}", audit);
+    assert_eq!(
+        audit["compilation_error"],
+        "dependency not available offline: raa-never-published is not in the local \
+         registry cache"
+    );
+
+    let audit = server
+        .create_audit_with(json!({ "files": package("untrusted = \"=0.9.99\"\n") }))
+        .await;
+    assert_eq!(audit["status"], "compile_error", "{}", audit);
+    assert_eq!(
+        audit["compilation_error"],
+        "dependency not available offline: untrusted = \"=0.9.99\" is not in the local \
+         registry cache"
+    );
+}
+
+#[sqlx::test]
+async fn dependencies_are_resolved_from_the_configured_registry(pool: PgPool) {
+    let home = std::env::temp_dir().join(format!("cargo-home-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&home).unwrap();
+    let server =
+        TestServer::start_with(&pool, &[("OFFLINE_CARGO_HOME", home.to_str().unwrap())]).await;
+
+    let audit = server
+        .create_audit_with(json!({ "files": package("untrusted = \"=0.9.0\"\n") }))
+        .await;
+    assert_eq!(audit["status"], "compile_error", "{}", audit);
+    assert_eq!(
+        audit["compilation_error"],
+        "dependency not available offline: untrusted is not in the local registry cache"
+    );
+
+    // Packages without dependencies need no registry.
+    let audit = server
+        .create_audit_with(json!({ "files": package("") }))
+        .await;
+    assert_eq!(audit["status"], "valid", "{}", audit);
+    let _ = std::fs::remove_dir_all(home);
+}
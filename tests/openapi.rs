@@ -0,0 +1,125 @@
+//! Tests of the OpenAPI document and the Swagger UI.
+
+mod common;
+
+use common::TestServer;
+use reqwest::StatusCode;
+use serde_json::Value;
+use sqlx::PgPool;
+
+#[sqlx::test]
+async fn openapi_document_describes_audit_creation(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+
+    let response = server
+        .client()
+        .get(server.url("/openapi.json"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let document: Value = response.json().await.unwrap();
+
+    assert!(document["openapi"].as_str().unwrap().starts_with("3."));
+    let create_audit = &document["paths"]["/audit"]["post"];
+    assert_eq!(
+        create_audit["requestBody"]["content"]["application/json"]["schema"]["$ref"],
+        "#/components/schemas/CreateAuditRequest"
+    );
+    assert_eq!(
+        create_audit["responses"]["400"]["content"]["application/json"]["schema"]["$ref"],
+        "#/components/schemas/ErrorResponse"
+    );
+    let request_schema = &document["components"]["schemas"]["CreateAuditRequest"];
+    let required: Vec<&str> = request_schema["required"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(Value::as_str)
+        .collect();
+    assert!(required.contains(&"prompt"));
+    assert!(request_schema["properties"]["generated_code"].is_object());
+}
+
+#[sqlx::test]
+async fn swagger_ui_is_served(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+
+    let response = server
+        .client()
+        .get(server.url("/swagger-ui/"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.text().await.unwrap().contains("swagger-ui"));
+
+    // The former location redirects to the Swagger UI.
+    let response = server
+        .client()
+        .get(server.url("/docs"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.url().path().starts_with("/swagger-ui/"));
+}
+
+#[sqlx::test]
+async fn openapi_document_lists_rest_endpoints(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+
+    let document: Value = server
+        .client()
+        .get(server.url("/openapi.json"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    let expected = [
+        ("/audit", "post"),
+        ("/audit/{id}", "get"),
+        ("/audits", "get"),
+        ("/audits/stream", "get"),
+        ("/audits/compare", "get"),
+        ("/audits/report/junit", "get"),
+        ("/audit/{id}/comments", "post"),
+        ("/audit/{id}/comments", "get"),
+        ("/audit/{id}/comments/{comment_id}", "delete"),
+        ("/audit/{id}/compilation", "delete"),
+        ("/admin/rerun-failed", "post"),
+        ("/admin/import", "post"),
+        ("/stats", "get"),
+        ("/auth/token", "post"),
+        ("/health", "get"),
+    ];
+    for (path, method) in expected {
+        assert!(
+            document["paths"][path][method].is_object(),
+            "{} {} is not documented",
+            method.to_uppercase(),
+            path
+        );
+    }
+
+    // The errors with a body share the `AppError` JSON shape (`/ready` answers 503 without
+    // one).
+    for (path, operations) in document["paths"].as_object().unwrap() {
+        for (method, operation) in operations.as_object().unwrap() {
+            for (status, response) in operation["responses"].as_object().unwrap() {
+                let is_error = status.starts_with('4') || status.starts_with('5');
+                if let Some(content) = response.get("content").filter(|_| is_error) {
+                    let schema = &content["application/json"]["schema"]["$ref"];
+                    assert_eq!(
+                        schema, "#/components/schemas/ErrorResponse",
+                        "{} {} {}",
+                        method, path, status
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! Tests of the optimization level audits are compiled with.
+
+mod common;
+
+use common::TestServer;
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+const CODE: &str = "pub fn double(x: u32) -> u32 { x * 2 }";
+
+/// Returns the value of the `-C` flag of a compile command.
+fn codegen_flag(audit: &Value) -> &str {
+    let command: Vec<&str> = audit["compile_command"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|arg| arg.as_str().unwrap())
+        .collect();
+    let position = command.iter().position(|&arg| arg == "-C").unwrap();
+    command[position + 1]
+}
+
+#[sqlx::test]
+async fn opt_level_is_passed_to_rustc(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+
+    let optimized = server
+        .create_audit_with(json!({ "generated_code": CODE, "opt_level": 3 }))
+        .await;
+    assert_eq!(optimized["is_valid"], true);
+    assert_eq!(optimized["opt_level"], "3");
+    assert_eq!(codegen_flag(&optimized), "opt-level=3");
+
+    let for_size = server
+        .create_audit_with(json!({ "generated_code": CODE, "opt_level": "z" }))
+        .await;
+    assert_eq!(codegen_flag(&for_size), "opt-level=z");
+
+    let default = server.create_audit(CODE).await;
+    assert_eq!(default["opt_level"], "0");
+    assert_eq!(codegen_flag(&default), "opt-level=0");
+
+    let stored: Vec<String> =
+        sqlx::query_scalar("SELECT opt_level FROM ai_audits ORDER BY created_at")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(stored, ["3", "z", "0"]);
+}
+
+#[sqlx::test]
+async fn invalid_opt_level_is_rejected(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+
+    let response = server
+        .client()
+        .post(server.url("/audit"))
+        .json(&json!({ "prompt": "Double", "generated_code": CODE, "opt_level": 4 }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(
+        response
+            .text()
+            .await
+            .unwrap()
+            .contains("invalid optimization level '4'")
+    );
+}
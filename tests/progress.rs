@@ -0,0 +1,209 @@
+//! Tests of `/audit/{id}/progress`, following background audits as Server-Sent Events.
+
+mod common;
+
+use common::TestServer;
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+use std::{os::unix::fs::PermissionsExt, path::PathBuf, time::Duration};
+
+/// How long `rustc` waits before compiling, so that the stream follows the job from its
+/// start: the progress of a job is only sent to the clients following it already.
+const RUSTC_DELAY_SECS: u32 = 3;
+
+/// How many unused functions `many_warnings` declares.
+const UNUSED_FUNCTIONS: usize = 200;
+
+/// Returns code declaring `UNUSED_FUNCTIONS` private functions, each warned about.
+fn many_warnings() -> String {
+    (0..UNUSED_FUNCTIONS)
+        .map(|i| format!("fn unused_{}() {{}}\n", i))
+        .collect()
+}
+
+/// A directory holding a `rustc` that waits `RUSTC_DELAY_SECS` before running the real
+/// one, removed on drop.
+struct SlowRustc(PathBuf);
+
+impl SlowRustc {
+    fn install() -> Self {
+        let real = std::env::split_paths(&std::env::var_os("PATH").unwrap())
+            .map(|dir| dir.join("rustc"))
+            .find(|path| path.is_file())
+            .expect("rustc is not installed");
+        let dir = std::env::temp_dir().join(format!("slow-rustc-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("rustc");
+        std::fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\nsleep {}\nexec {} \"$@\"\n",
+                RUSTC_DELAY_SECS,
+                real.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        SlowRustc(dir)
+    }
+
+    /// Starts a server compiling with this `rustc`, whether directly or through cargo.
+    async fn start_server(&self, pool: &PgPool) -> TestServer {
+        let path = std::env::join_paths(
+            std::iter::once(self.0.clone())
+                .chain(std::env::split_paths(&std::env::var_os("PATH").unwrap())),
+        )
+        .unwrap();
+        let rustc = self.0.join("rustc");
+        TestServer::start_with(
+            pool,
+            &[
+                ("PATH", path.to_str().unwrap()),
+                ("RUSTC", rustc.to_str().unwrap()),
+                ("COMPILATION_TIMEOUT_SECS", "120"),
+            ],
+        )
+        .await
+    }
+}
+
+impl Drop for SlowRustc {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Reads the whole progress stream of an audit, returning the name and data of its events.
+async fn progress(server: &TestServer, id: &Value) -> Vec<(String, Value)> {
+    let response = server
+        .client()
+        .get(server.url(&format!("/audit/{}/progress", id.as_str().unwrap())))
+        .timeout(Duration::from_secs(120))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.text().await.unwrap();
+    body.split("\n\n")
+        .filter_map(|event| {
+            let field = |name: &str| {
+                event
+                    .lines()
+                    .find_map(|line| line.strip_prefix(&format!("{}: ", name)))
+            };
+            Some((
+                field("event")?.to_string(),
+                serde_json::from_str(field("data")?).unwrap(),
+            ))
+        })
+        .collect()
+}
+
+/// Enqueues the audit of `body` and returns its ID.
+async fn enqueue(server: &TestServer, body: Value) -> Value {
+    let response = server
+        .client()
+        .post(server.url("/audit/async"))
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let job: Value = response.json().await.unwrap();
+    job["audit_id"].clone()
+}
+
+fn names(events: &[(String, Value)]) -> Vec<&str> {
+    let mut names: Vec<&str> = events.iter().map(|(name, _)| name.as_str()).collect();
+    names.dedup();
+    names
+}
+
+#[sqlx::test]
+async fn compiler_output_is_streamed_in_batches(pool: PgPool) {
+    let rustc = SlowRustc::install();
+    let server = rustc.start_server(&pool).await;
+    let id = enqueue(
+        &server,
+        json!({ "prompt": "Write many functions", "generated_code": many_warnings() }),
+    )
+    .await;
+
+    let events = progress(&server, &id).await;
+    assert_eq!(names(&events), ["validated", "compiling", "completed"]);
+    let (_, completed) = events.last().unwrap();
+    assert_eq!(completed["audit"]["status"], "valid");
+
+    let lines: Vec<&str> = events
+        .iter()
+        .filter(|(name, _)| name == "compiling")
+        .flat_map(|(_, event)| event["lines"].as_array().unwrap())
+        .map(|line| line.as_str().unwrap())
+        .collect();
+    for i in [0, UNUSED_FUNCTIONS - 1] {
+        let warning = format!("warning: function `unused_{}` is never used", i);
+        assert!(lines.contains(&warning.as_str()), "{:?}", lines);
+    }
+    let warnings = lines
+        .iter()
+        .filter(|line| line.starts_with("warning: function `unused_"))
+        .count();
+    assert_eq!(warnings, UNUSED_FUNCTIONS);
+}
+
+#[sqlx::test]
+async fn packages_resolve_their_dependencies_before_compiling(pool: PgPool) {
+    let rustc = SlowRustc::install();
+    let server = rustc.start_server(&pool).await;
+    let id = enqueue(
+        &server,
+        json!({
+            "prompt": "Write a tokenizer",
+            "files": [
+                {
+                    "path": "Cargo.toml",
+                    "content": "[package]\nname = \"tokenizer\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+                },
+                { "path": "src/lib.rs", "content": "pub mod token;\n" },
+                { "path": "src/token.rs", "content": "pub fn width() -> usize {\n    \"4\"\n}\n" },
+            ],
+        }),
+    )
+    .await;
+
+    let events = progress(&server, &id).await;
+    assert_eq!(
+        names(&events),
+        [
+            "validated",
+            "dependencies_resolved",
+            "compiling",
+            "completed"
+        ]
+    );
+    let lines: Vec<&Value> = events
+        .iter()
+        .flat_map(|(_, event)| event["lines"].as_array().unwrap())
+        .collect();
+    assert!(
+        lines.contains(&&json!("error[E0308]: mismatched types")),
+        "{:?}",
+        lines
+    );
+    let (_, completed) = events.last().unwrap();
+    assert_eq!(completed["audit"]["status"], "compile_error");
+}
+
+#[sqlx::test]
+async fn finished_audits_complete_right_away(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let audit = server
+        .create_audit("pub fn answer() -> u32 {\n    42\n}\n")
+        .await;
+
+    let events = progress(&server, &audit["id"]).await;
+    assert_eq!(names(&events), ["completed"]);
+    assert_eq!(events[0].1["audit"]["id"], audit["id"]);
+    assert_eq!(events[0].1["audit"]["status"], "valid");
+}
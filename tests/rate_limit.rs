@@ -0,0 +1,90 @@
+//! Tests of the limit of audits each client may create per minute.
+
+mod common;
+
+use common::{ADMIN_TOKEN, TestServer};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+const CODE: &str = "pub fn answer() -> u32 {\n    42\n}\n";
+
+#[sqlx::test]
+async fn clients_over_the_limit_are_refused(pool: PgPool) {
+    let server = TestServer::start_with(&pool, &[("RATE_LIMIT_RPM", "2")]).await;
+    let audit = server.create_audit(CODE).await;
+    server.create_audit(CODE).await;
+
+    let response = server
+        .client()
+        .post(server.url("/audit"))
+        .json(&json!({ "prompt": "Write a function", "generated_code": CODE }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let error: Value = response.json().await.unwrap();
+    assert!(
+        error["error"]
+            .as_str()
+            .unwrap()
+            .starts_with("Too many audits: at most 2 per minute are allowed"),
+        "{}",
+        error
+    );
+
+    let response = server
+        .graphql(
+            "mutation($code: String!) { createAudit(input: { prompt: \"Write a function\", generatedCode: $code }) { id } }",
+            json!({ "code": CODE }),
+        )
+        .await;
+    assert!(
+        response["errors"][0]["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("Rate limited: Too many audits"),
+        "{}",
+        response
+    );
+
+    // Asking a model to fix an audit creates one too.
+    let response = server
+        .client()
+        .post(server.url(&format!("/audit/{}/fix", audit["id"].as_str().unwrap())))
+        .bearer_auth(ADMIN_TOKEN)
+        .json(&json!({}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let response = server
+        .graphql_as(
+            ADMIN_TOKEN,
+            "mutation($id: UUID!) { fixAudit(id: $id) { id } }",
+            json!({ "id": audit["id"] }),
+        )
+        .await;
+    assert!(
+        response["errors"][0]["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("Rate limited: Too many audits"),
+        "{}",
+        response
+    );
+
+    // Reading audits is not limited.
+    let response = server
+        .client()
+        .get(server.url(&format!("/audit/{}", audit["id"].as_str().unwrap())))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let audits: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM ai_audits")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(audits, 2);
+}
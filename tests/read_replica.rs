@@ -0,0 +1,131 @@
+//! Tests of the routing of pure reads to the read replica.
+
+mod common;
+
+use common::TestServer;
+use serde_json::Value;
+use sqlx::{PgPool, postgres::PgPoolOptions};
+
+/// A second database standing in for the read replica of a test database.
+struct Replica {
+    url: String,
+    pool: PgPool,
+    admin: PgPool,
+    name: String,
+}
+
+impl Replica {
+    /// Creates an empty database with the schema of the server, next to the one of `pool`.
+    async fn create(pool: &PgPool) -> Replica {
+        let primary_url = common::database_url(pool);
+        let name = format!("replica_{}", uuid::Uuid::new_v4().simple());
+        sqlx::query(&format!("CREATE DATABASE \"{}\"", name))
+            .execute(pool)
+            .await
+            .unwrap();
+        let (server, _) = primary_url.rsplit_once('/').unwrap();
+        let url = format!("{}/{}", server, name);
+        let replica = PgPoolOptions::new().connect(&url).await.unwrap();
+        sqlx::migrate!().run(&replica).await.unwrap();
+        Replica {
+            url,
+            pool: replica,
+            admin: pool.clone(),
+            name,
+        }
+    }
+
+    /// Drops the replica, once the server using it has stopped.
+    async fn drop(self) {
+        self.pool.close().await;
+        sqlx::query(&format!("DROP DATABASE \"{}\" WITH (FORCE)", self.name))
+            .execute(&self.admin)
+            .await
+            .unwrap();
+    }
+}
+
+/// Inserts an audit whose prompt tells which database it was read from.
+async fn mark(pool: &PgPool, marker: &str) {
+    sqlx::query(
+        "INSERT INTO ai_audits (prompt, generated_code, is_valid, status, code_line_count,
+             code_char_count, prompt_token_count, code_token_count)
+         VALUES ($1, 'pub fn f() {}', true, 'valid', 1, 13, 1, 5)",
+    )
+    .bind(marker)
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+async fn count(pool: &PgPool) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM ai_audits")
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+/// Returns the prompts of the audits listed by the server.
+async fn listed_prompts(server: &TestServer) -> Vec<String> {
+    let audits: Vec<Value> = server
+        .client()
+        .get(server.url("/audits"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    audits
+        .iter()
+        .map(|audit| audit["prompt"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[sqlx::test]
+async fn reads_go_to_the_replica_and_writes_to_the_primary(pool: PgPool) {
+    let replica = Replica::create(&pool).await;
+    mark(&pool, "primary").await;
+    mark(&replica.pool, "replica").await;
+    // `DATABASE_READ_URL` wins over the older name.
+    let primary_url = common::database_url(&pool);
+    let server = TestServer::start_with(
+        &pool,
+        &[
+            ("DATABASE_READ_URL", &replica.url),
+            ("READ_REPLICA_DATABASE_URL", &primary_url),
+        ],
+    )
+    .await;
+
+    assert_eq!(listed_prompts(&server).await, ["replica"]);
+
+    // The audit is written to the primary, and returned although the replica lacks it.
+    let audit = server.create_audit("pub fn answer() -> u32 { 42 }").await;
+    assert_eq!(audit["status"], "valid");
+    assert_eq!((count(&pool).await, count(&replica.pool).await), (2, 1));
+    let response = server
+        .client()
+        .get(server.url(&format!("/audit/{}", audit["id"].as_str().unwrap())))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+    drop(server);
+    replica.drop().await;
+}
+
+#[sqlx::test]
+async fn the_older_replica_variable_is_still_read(pool: PgPool) {
+    let replica = Replica::create(&pool).await;
+    mark(&pool, "primary").await;
+    mark(&replica.pool, "replica").await;
+    let server =
+        TestServer::start_with(&pool, &[("READ_REPLICA_DATABASE_URL", &replica.url)]).await;
+
+    assert_eq!(listed_prompts(&server).await, ["replica"]);
+
+    drop(server);
+    replica.drop().await;
+}
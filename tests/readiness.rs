@@ -0,0 +1,43 @@
+//! Tests of the liveness and readiness probes.
+
+mod common;
+
+use common::TestServer;
+use reqwest::StatusCode;
+use sqlx::PgPool;
+use std::time::{Duration, Instant};
+
+/// Returns the status of `GET path`, or `None` while the server is not listening.
+async fn probe(server: &TestServer, path: &str) -> Option<StatusCode> {
+    let response = server.client().get(server.url(path)).send().await.ok()?;
+    Some(response.status())
+}
+
+#[sqlx::test]
+async fn ready_waits_for_the_migrations(pool: PgPool) {
+    // Holding a lock on the migrations table blocks the migrations of the server.
+    let mut lock = pool.begin().await.unwrap();
+    sqlx::query("LOCK TABLE _sqlx_migrations IN ACCESS EXCLUSIVE MODE")
+        .execute(&mut *lock)
+        .await
+        .unwrap();
+
+    let server = TestServer::spawn_with(&pool, &[]);
+    let started = Instant::now();
+    while probe(&server, "/health").await != Some(StatusCode::OK) {
+        assert!(started.elapsed() < Duration::from_secs(30), "never live");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    // The server is live, but not ready while the migrations wait.
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(
+        probe(&server, "/ready").await,
+        Some(StatusCode::SERVICE_UNAVAILABLE)
+    );
+
+    lock.rollback().await.unwrap();
+    server.wait_until_ready().await;
+    assert_eq!(probe(&server, "/ready").await, Some(StatusCode::OK));
+    assert_eq!(probe(&server, "/health").await, Some(StatusCode::OK));
+}
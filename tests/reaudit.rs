@@ -0,0 +1,73 @@
+//! Tests of the re-audit of the audits whose code failed to compile.
+
+mod common;
+
+use common::TestServer;
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+/// Imports failed audits of `code`. Imported records keep the verdict of the toolchain
+/// that produced them, which stands in for an older `rustc` here.
+async fn seed_failed_audits(server: &TestServer, code: &str, count: usize) {
+    let records: Vec<Value> = (0..count)
+        .map(|_| {
+            json!({
+                "prompt": "Write a function",
+                "generated_code": code,
+                "is_valid": false,
+                "status": "compile_error",
+                "compilation_error": "error: failed with an older toolchain",
+            })
+        })
+        .collect();
+    let response = server
+        .admin_post("/admin/import", &Value::Array(records))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let report: Value = response.json().await.unwrap();
+    assert_eq!(report["imported"], count);
+}
+
+#[sqlx::test]
+async fn reaudit_counts_newly_valid_and_still_invalid_audits(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    seed_failed_audits(&server, "pub fn compiles_now() -> i32 { 1 }", 3).await;
+    seed_failed_audits(&server, "pub fn still_broken() -> i32 { \"1\" }", 2).await;
+
+    let response = server
+        .admin_post("/audits/reaudit-invalid", &json!({}))
+        .await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let report: Value = response.json().await.unwrap();
+    assert_eq!(
+        report,
+        json!({ "processed": 5, "newly_valid": 3, "still_invalid": 2, "newly_invalid": 0 })
+    );
+    let valid: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM ai_audits WHERE is_valid")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(valid, 3);
+    let still_invalid: Vec<Option<String>> =
+        sqlx::query_scalar("SELECT primary_error_code FROM ai_audits WHERE NOT is_valid")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(still_invalid, vec![Some("E0308".to_string()); 2]);
+}
+
+#[sqlx::test]
+async fn reaudit_requires_the_admin_token(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+
+    let response = server
+        .client()
+        .post(server.url("/audits/reaudit-invalid"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
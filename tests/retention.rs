@@ -0,0 +1,81 @@
+//! Tests of the deletion of audits past their retention period.
+
+mod common;
+
+use common::TestServer;
+use rust_ai_auditor::services;
+use sqlx::PgPool;
+use std::time::Duration;
+
+const WEEK: Duration = Duration::from_secs(7 * 86_400);
+
+#[sqlx::test]
+async fn expired_audits_are_deleted_with_their_totals(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let long = format!(
+        "pub fn answer() -> u32 {{\n{}    42\n}}\n",
+        "    // padding\n".repeat(20)
+    );
+    let old = server.create_audit(&long).await;
+    let fixed = server
+        .create_audit("pub fn answer() -> u32 {\n    42\n}\n")
+        .await;
+    let recent = server
+        .create_audit("pub fn one() -> u32 {\n    1\n}\n")
+        .await;
+    let (old, fixed) = (old["id"].as_str().unwrap(), fixed["id"].as_str().unwrap());
+    sqlx::query("UPDATE ai_audits SET created_at = NOW() - INTERVAL '8 days' WHERE id = $1::uuid")
+        .bind(old)
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("UPDATE ai_audits SET parent_audit_id = $1::uuid WHERE id = $2::uuid")
+        .bind(old)
+        .bind(fixed)
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query(
+        "INSERT INTO audit_comments (audit_id, author, body) VALUES ($1::uuid, 'alice', 'Old')",
+    )
+    .bind(old)
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    assert_eq!(
+        services::delete_expired_audits(&pool, WEEK).await.unwrap(),
+        1
+    );
+    assert_eq!(
+        services::delete_expired_audits(&pool, WEEK).await.unwrap(),
+        0
+    );
+
+    let ids: Vec<String> = sqlx::query_scalar("SELECT id::text FROM ai_audits ORDER BY created_at")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(ids, [fixed, recent["id"].as_str().unwrap()]);
+    let parent: Option<String> =
+        sqlx::query_scalar("SELECT parent_audit_id::text FROM ai_audits WHERE id = $1::uuid")
+            .bind(fixed)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(parent, None);
+    let comments: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM audit_comments")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(comments, 0);
+
+    // The totals no longer count the deleted audit, down to the longest code.
+    let (total, lines, max_lines): (i64, i64, i64) = sqlx::query_as(
+        "SELECT total_audits, total_code_lines, max_code_line_count FROM audit_stats_summary",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!((total, lines, max_lines), (2, 6, 3));
+}
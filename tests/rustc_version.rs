@@ -0,0 +1,38 @@
+//! Tests of the recording of the toolchain that audited the code.
+
+mod common;
+
+use common::TestServer;
+use serde_json::json;
+use sqlx::PgPool;
+use std::process::Command;
+
+#[sqlx::test]
+async fn rustc_version_is_stored_and_filterable(pool: PgPool) {
+    let output = Command::new("rustc").arg("--version").output().unwrap();
+    let version = String::from_utf8(output.stdout).unwrap().trim().to_string();
+    let server = TestServer::start(&pool).await;
+
+    let audit = server.create_audit("pub fn answer() -> u32 { 42 }").await;
+
+    assert_eq!(audit["rustc_version"], version);
+    let stored: Option<String> = sqlx::query_scalar("SELECT rustc_version FROM ai_audits")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(stored.as_deref(), Some(version.as_str()));
+
+    let query = "query($version: String) { audits(rustcVersion: $version) { id rustcVersion } }";
+    let response = server.graphql(query, json!({ "version": version })).await;
+    assert_eq!(
+        response["data"]["audits"],
+        json!([{ "id": audit["id"], "rustcVersion": version }])
+    );
+    let response = server
+        .graphql(
+            query,
+            json!({ "version": "rustc 1.0.0 (a59de37e9 2015-05-13)" }),
+        )
+        .await;
+    assert_eq!(response["data"]["audits"], json!([]));
+}
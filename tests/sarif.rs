@@ -0,0 +1,92 @@
+//! Tests of the SARIF export of audits.
+
+mod common;
+
+use common::TestServer;
+use reqwest::{StatusCode, header};
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+/// The SARIF 2.1.0 JSON schema published by OASIS, with the stray `]` of its `language`
+/// patterns removed so that they are valid ECMA 262 regexes.
+const SARIF_SCHEMA: &str = include_str!("fixtures/sarif/sarif-schema-2.1.0.json");
+
+fn assert_valid_sarif(log: &Value) {
+    let schema: Value = serde_json::from_str(SARIF_SCHEMA).unwrap();
+    let validator = jsonschema::draft7::new(&schema).unwrap();
+    let errors: Vec<String> = validator
+        .iter_errors(log)
+        .map(|error| format!("{} at {}", error, error.instance_path()))
+        .collect();
+    assert!(errors.is_empty(), "invalid SARIF log: {:#?}", errors);
+}
+
+async fn sarif(server: &TestServer, audit: &Value) -> Value {
+    let response = server
+        .client()
+        .get(server.url(&format!("/audit/{}/sarif", audit["id"].as_str().unwrap())))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "application/sarif+json"
+    );
+    response.json().await.unwrap()
+}
+
+#[sqlx::test]
+async fn findings_and_diagnostics_are_valid_sarif(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let audit = server
+        .create_audit("pub fn first(v: &[u32]) -> u32 {\n    let s: String = v[0];\n    s\n}\n")
+        .await;
+    assert_eq!(audit["is_valid"], false);
+
+    let log = sarif(&server, &audit).await;
+
+    assert_valid_sarif(&log);
+    assert_eq!(log["version"], "2.1.0");
+    let run = &log["runs"][0];
+    assert_eq!(run["tool"]["driver"]["name"], "rust-ai-auditor");
+    let results = run["results"].as_array().unwrap();
+    let mismatch = results
+        .iter()
+        .find(|result| result["ruleId"] == "E0308")
+        .expect("no result for the type mismatch");
+    assert_eq!(mismatch["level"], "error");
+    assert_eq!(
+        mismatch["locations"][0]["physicalLocation"]["region"]["startLine"],
+        2
+    );
+    let rules: Vec<&Value> = run["tool"]["driver"]["rules"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|rule| &rule["id"])
+        .collect();
+    assert!(rules.contains(&&json!("E0308")), "{:?}", rules);
+}
+
+#[sqlx::test]
+async fn audit_without_findings_has_no_results(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let audit = server
+        .create_audit("// SPDX-License-Identifier: MIT\n/// Returns the answer.\npub fn answer() -> u32 {\n    42\n}\n")
+        .await;
+
+    let log = sarif(&server, &audit).await;
+
+    assert_valid_sarif(&log);
+    assert_eq!(log["runs"][0]["results"], json!([]));
+
+    let response = server
+        .graphql(
+            "query($id: UUID!) { auditSarif(id: $id) }",
+            json!({ "id": audit["id"] }),
+        )
+        .await;
+    assert!(response["errors"].is_null(), "{}", response);
+    assert_eq!(response["data"]["auditSarif"], log);
+}
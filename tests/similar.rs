@@ -0,0 +1,75 @@
+//! Tests of the search for audits with near-duplicate code.
+
+mod common;
+
+use common::TestServer;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+const SUM: &str = "pub fn sum(values: &[i32]) -> i32 {\n    let mut total = 0;\n    for value in values {\n        total += value;\n    }\n    total\n}\n";
+
+/// `SUM` with its variables renamed.
+const RENAMED: &str = "pub fn sum(items: &[i32]) -> i32 {\n    let mut acc = 0;\n    for item in items {\n        acc += item;\n    }\n    acc\n}\n";
+
+const UNRELATED: &str = "pub struct Point {\n    pub x: f64,\n    pub y: f64,\n}\n\nimpl Point {\n    pub fn norm(&self) -> f64 {\n        self.x.hypot(self.y)\n    }\n}\n";
+
+const QUERY: &str = "query($id: UUID!, $threshold: Float!) { similar(id: $id, threshold: $threshold) { similarity audit { id } } }";
+
+async fn similar(server: &TestServer, id: &Value, threshold: f64) -> Value {
+    server
+        .graphql(QUERY, json!({ "id": id, "threshold": threshold }))
+        .await
+}
+
+#[sqlx::test]
+async fn near_duplicates_match_above_the_threshold(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let original = server.create_audit(SUM).await;
+    let copy = server.create_audit(SUM).await;
+    let renamed = server.create_audit(RENAMED).await;
+    let unrelated = server.create_audit(UNRELATED).await;
+
+    let response = similar(&server, &original["id"], 0.5).await;
+    assert!(response["errors"].is_null(), "{}", response);
+    let matches = response["data"]["similar"].as_array().unwrap();
+    let ids: Vec<&Value> = matches.iter().map(|m| &m["audit"]["id"]).collect();
+    assert_eq!(ids, [&copy["id"], &renamed["id"]], "{}", response);
+    assert_eq!(matches[0]["similarity"], 1.0);
+    let score = matches[1]["similarity"].as_f64().unwrap();
+    assert!((0.5..1.0).contains(&score), "{}", score);
+
+    // A threshold above the score of the renamed copy leaves the exact copy only.
+    let response = similar(&server, &original["id"], (score + 1.0) / 2.0).await;
+    let matches = response["data"]["similar"].as_array().unwrap();
+    assert_eq!(matches.len(), 1, "{}", response);
+    assert_eq!(matches[0]["audit"]["id"], copy["id"]);
+
+    // The unrelated code matches nothing.
+    let response = similar(&server, &unrelated["id"], 0.5).await;
+    assert_eq!(response["data"]["similar"], json!([]), "{}", response);
+}
+
+#[sqlx::test]
+async fn invalid_searches_are_refused(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let audit = server.create_audit(SUM).await;
+
+    for threshold in [0.0, 1.5] {
+        let response = similar(&server, &audit["id"], threshold).await;
+        assert_eq!(
+            response["errors"][0]["message"],
+            "Validation error: threshold must be above 0 and at most 1",
+            "{}",
+            response
+        );
+    }
+    let response = similar(&server, &json!(uuid::Uuid::new_v4()), 0.5).await;
+    assert!(
+        response["errors"][0]["message"]
+            .as_str()
+            .unwrap()
+            .ends_with("not found"),
+        "{}",
+        response
+    );
+}
@@ -0,0 +1,239 @@
+//! Tests of the `/stats` endpoint.
+
+mod common;
+
+use common::TestServer;
+use reqwest::{StatusCode, header};
+use serde_json::{Value, json};
+use sqlx::PgPool;
+use std::time::{Duration, Instant};
+
+/// Imports an audit generated by `model` (or submitted directly when `None`).
+async fn seed(server: &TestServer, pool: &PgPool, model: Option<&str>, is_valid: bool) {
+    let id = uuid::Uuid::new_v4();
+    let record = json!([{
+        "id": id,
+        "prompt": "Write a function",
+        "generated_code": "pub fn f() {}",
+        "is_valid": is_valid,
+        "compilation_error": (!is_valid).then_some("error[E0308]: mismatched types"),
+    }]);
+    let response = server.admin_post("/admin/import", &record).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    if let Some(model) = model {
+        sqlx::query(
+            "UPDATE ai_audits SET generation_provider = 'openai', generation_model = $1
+             WHERE id = $2",
+        )
+        .bind(model)
+        .bind(id)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+}
+
+async fn get_stats(server: &TestServer, query: &[(&str, &str)]) -> reqwest::Response {
+    server
+        .client()
+        .get(server.url("/stats"))
+        .query(query)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn stats_are_grouped_by_model(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    for (model, is_valid) in [
+        (Some("gpt-4o"), true),
+        (Some("gpt-4o"), true),
+        (Some("gpt-4o"), false),
+        (None, true),
+        (None, false),
+        (Some("gpt-4o-mini"), false),
+    ] {
+        seed(&server, &pool, model, is_valid).await;
+    }
+
+    let response = get_stats(&server, &[("group_by", "model")]).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let buckets: Value = response.json().await.unwrap();
+    let gpt_4o_rate = 2.0 / 3.0;
+    assert_eq!(
+        buckets,
+        json!([
+            { "key": "gpt-4o", "total_audits": 3, "valid_audits": 2, "success_rate": gpt_4o_rate },
+            { "key": null, "total_audits": 2, "valid_audits": 1, "success_rate": 0.5 },
+            { "key": "gpt-4o-mini", "total_audits": 1, "valid_audits": 0, "success_rate": 0.0 },
+        ])
+    );
+
+    // Buckets are paginated.
+    let page: Value = get_stats(
+        &server,
+        &[("group_by", "model"), ("offset", "1"), ("limit", "1")],
+    )
+    .await
+    .json()
+    .await
+    .unwrap();
+    assert_eq!(page, json!([buckets[1]]));
+}
+
+#[sqlx::test]
+async fn unknown_grouping_is_rejected(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+
+    let response = get_stats(&server, &[("group_by", "weekday")]).await;
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[sqlx::test]
+async fn unchanged_stats_are_not_modified(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    seed(&server, &pool, None, true).await;
+
+    let response = get_stats(&server, &[]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CACHE_CONTROL],
+        "public, max-age=5"
+    );
+    let etag = response.headers()[header::ETAG]
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert!(etag.starts_with("W/"), "{}", etag);
+
+    let response = server
+        .client()
+        .get(server.url("/stats"))
+        .header(header::IF_NONE_MATCH, &etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()[header::ETAG], etag.as_str());
+    assert!(response.bytes().await.unwrap().is_empty());
+
+    // Other parameters are other statistics.
+    let response = server
+        .client()
+        .get(server.url("/stats"))
+        .query(&[("days", "7")])
+        .header(header::IF_NONE_MATCH, &etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[sqlx::test]
+async fn new_audits_change_the_stats_etag(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    seed(&server, &pool, None, true).await;
+    let response = get_stats(&server, &[]).await;
+    let etag = response.headers()[header::ETAG]
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    server.create_audit("pub fn g() {}").await;
+
+    // Statistics are cached for as long as `Cache-Control` lets clients keep them.
+    let started = Instant::now();
+    let response = loop {
+        let response = server
+            .client()
+            .get(server.url("/stats"))
+            .header(header::IF_NONE_MATCH, &etag)
+            .send()
+            .await
+            .unwrap();
+        if response.status() != StatusCode::NOT_MODIFIED {
+            break response;
+        }
+        assert!(started.elapsed() < Duration::from_secs(10), "stale ETag");
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    };
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_ne!(response.headers()[header::ETAG], etag.as_str());
+    let stats: Value = response.json().await.unwrap();
+    assert_eq!(stats["total_audits"], 2);
+}
+
+#[sqlx::test]
+async fn stats_read_the_running_totals_instead_of_the_audits(pool: PgPool) {
+    // Audits written without updating the totals, which are then recomputed once.
+    sqlx::query(
+        "INSERT INTO ai_audits (prompt, generated_code, is_valid, status, code_line_count,
+             code_char_count, prompt_token_count, code_token_count)
+         SELECT 'Write a function', 'pub fn f() {}', n % 4 <> 0,
+             CASE WHEN n % 4 <> 0 THEN 'valid' ELSE 'compile_error' END, 1, 13, 3, 6
+         FROM generate_series(1, 10000) AS n",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    sqlx::query("ANALYZE ai_audits")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let server = TestServer::start(&pool).await;
+
+    let stats: Value = get_stats(&server, &[]).await.json().await.unwrap();
+    assert_eq!(stats["total_audits"], 10000);
+    assert_eq!(stats["valid_audits"], 7500);
+
+    // New audits update the totals in place.
+    server.create_audit("pub fn g() {}").await;
+    let (total, drifted): (i64, bool) = sqlx::query_as(
+        "SELECT total_audits, (SELECT MAX(updated_at) FROM ai_audits) > updated_at
+         FROM audit_stats_summary",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!((total, drifted), (10001, false));
+
+    // Reading the totals, and checking that they did not drift, does not scan the audits.
+    let plan: Value = sqlx::query_scalar(
+        "EXPLAIN (FORMAT JSON)
+         SELECT total_audits, valid_audits,
+             COALESCE((SELECT MAX(updated_at) FROM ai_audits) > updated_at, false) AS drifted
+         FROM audit_stats_summary",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    let scans = scans(&plan[0]["Plan"]);
+    assert!(
+        scans.contains(&("Index Only Scan".to_string(), "ai_audits".to_string())),
+        "{:?}",
+        scans
+    );
+    assert!(
+        !scans.contains(&("Seq Scan".to_string(), "ai_audits".to_string())),
+        "{:?}",
+        scans
+    );
+}
+
+/// Returns the type and table of the nodes of a query plan that read a table.
+fn scans(node: &Value) -> Vec<(String, String)> {
+    let mut scans: Vec<(String, String)> = node["Plans"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(scans)
+        .collect();
+    if let (Some(kind), Some(table)) = (node["Node Type"].as_str(), node["Relation Name"].as_str())
+    {
+        scans.push((kind.to_string(), table.to_string()));
+    }
+    scans
+}
@@ -0,0 +1,84 @@
+//! Tests of the streaming of compiler output over WebSocket.
+
+mod common;
+
+use common::TestServer;
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{Value, json};
+use sqlx::PgPool;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+/// Sends `request` to `/audit/stream` and returns the JSON messages received until the
+/// server closes the socket.
+async fn stream(server: &TestServer, request: Value) -> Vec<Value> {
+    let url = server.url("/audit/stream").replacen("http", "ws", 1);
+    let (mut socket, _) = connect_async(url).await.unwrap();
+    socket
+        .send(Message::text(request.to_string()))
+        .await
+        .unwrap();
+
+    let mut messages = Vec::new();
+    while let Some(message) = socket.next().await {
+        match message.unwrap() {
+            Message::Text(text) => messages.push(serde_json::from_str(&text).unwrap()),
+            Message::Close(_) => break,
+            _ => {}
+        }
+    }
+    messages
+}
+
+#[sqlx::test]
+async fn failing_snippet_streams_logs_then_the_verdict(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+
+    let messages = stream(
+        &server,
+        json!({ "generated_code": "pub fn f() -> i32 { \"x\" }" }),
+    )
+    .await;
+
+    let (verdict, logs) = messages.split_last().unwrap();
+    assert!(logs.iter().all(|message| message["type"] == "log"));
+    assert!(
+        logs.iter()
+            .any(|message| message["line"] == "error[E0308]: mismatched types"),
+        "{:?}",
+        logs
+    );
+    assert_eq!(verdict["type"], "verdict");
+    assert_eq!(verdict["success"], false);
+    assert_eq!(verdict["primary_error_code"], "E0308");
+    // Streamed snippets are not stored.
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM ai_audits")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+}
+
+#[sqlx::test]
+async fn valid_snippet_ends_with_a_successful_verdict(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+
+    let messages = stream(
+        &server,
+        json!({ "generated_code": "pub fn f() -> i32 { 1 }" }),
+    )
+    .await;
+
+    let verdict = messages.last().unwrap();
+    assert_eq!(verdict["type"], "verdict");
+    assert_eq!(verdict["success"], true);
+}
+
+#[sqlx::test]
+async fn malformed_request_gets_an_error_message(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+
+    let messages = stream(&server, json!({ "code": "pub fn f() {}" })).await;
+
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0]["type"], "error");
+}
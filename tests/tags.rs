@@ -0,0 +1,134 @@
+//! Tests of the tags of audits.
+
+mod common;
+
+use common::{ADMIN_TOKEN, TestServer};
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+const CODE: &str = "pub fn answer() -> u32 { 42 }";
+
+fn ids(audits: &Value) -> Vec<&str> {
+    let mut ids: Vec<&str> = audits
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|audit| audit["id"].as_str().unwrap())
+        .collect();
+    ids.sort();
+    ids
+}
+
+#[sqlx::test]
+async fn tags_are_normalized_and_filtered(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let tagged = server
+        .create_audit_with(json!({
+            "generated_code": CODE,
+            "tags": [" Dataset-A ", "baseline", "dataset-a", ""],
+        }))
+        .await;
+    let other = server
+        .create_audit_with(json!({ "generated_code": CODE, "tags": ["dataset-b", "baseline"] }))
+        .await;
+    server.create_audit(CODE).await;
+
+    assert_eq!(tagged["tags"], json!(["baseline", "dataset-a"]));
+
+    let response = server
+        .graphql(
+            "query($tags: [String!]) { audits(tagsContains: $tags) { id tags } }",
+            json!({ "tags": ["Dataset-A"] }),
+        )
+        .await;
+    assert!(response["errors"].is_null(), "{}", response);
+    assert_eq!(
+        ids(&response["data"]["audits"]),
+        [tagged["id"].as_str().unwrap()]
+    );
+
+    // Every tag of the filter must be present.
+    let response = server
+        .graphql(
+            "query($tags: [String!]) { audits(tagsContains: $tags) { id } }",
+            json!({ "tags": ["baseline"] }),
+        )
+        .await;
+    let mut expected = vec![
+        tagged["id"].as_str().unwrap(),
+        other["id"].as_str().unwrap(),
+    ];
+    expected.sort();
+    assert_eq!(ids(&response["data"]["audits"]), expected);
+
+    let audits: Value = server
+        .client()
+        .get(server.url("/audits"))
+        .query(&[("tags_contains", "baseline,dataset-b")])
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(ids(&audits), [other["id"].as_str().unwrap()]);
+}
+
+#[sqlx::test]
+async fn tags_are_added_then_replaced(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let audit = server.create_audit(CODE).await;
+    assert_eq!(audit["tags"], json!([]));
+    let set_tags =
+        "mutation($id: UUID!, $tags: [String!]!) { setTags(id: $id, tags: $tags) { tags } }";
+    let updated_at: chrono::DateTime<chrono::Utc> =
+        sqlx::query_scalar("SELECT updated_at FROM ai_audits")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+
+    let response = server
+        .graphql_as(
+            ADMIN_TOKEN,
+            set_tags,
+            json!({ "id": audit["id"], "tags": ["Reviewed", "dataset-a", "reviewed"] }),
+        )
+        .await;
+    assert!(response["errors"].is_null(), "{}", response);
+    assert_eq!(
+        response["data"]["setTags"]["tags"],
+        json!(["dataset-a", "reviewed"])
+    );
+
+    let response = server
+        .graphql_as(
+            ADMIN_TOKEN,
+            set_tags,
+            json!({ "id": audit["id"], "tags": ["baseline"] }),
+        )
+        .await;
+    assert!(response["errors"].is_null(), "{}", response);
+    let (tags, touched_at): (Vec<String>, chrono::DateTime<chrono::Utc>) =
+        sqlx::query_as("SELECT tags, updated_at FROM ai_audits")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(tags, ["baseline"]);
+    assert!(touched_at > updated_at);
+
+    let response = server
+        .graphql_as(
+            ADMIN_TOKEN,
+            set_tags,
+            json!({ "id": uuid::Uuid::new_v4(), "tags": ["baseline"] }),
+        )
+        .await;
+    assert!(
+        response["errors"][0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("not found"),
+        "{}",
+        response
+    );
+}
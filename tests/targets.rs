@@ -0,0 +1,111 @@
+//! Tests of the compilation of code for other targets than the host.
+
+mod common;
+
+use common::TestServer;
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+use std::{path::PathBuf, process::Command};
+
+const WASM: &str = "wasm32-unknown-unknown";
+
+/// Code without `std`, which only compiles for targets providing `core`.
+const NO_STD_CODE: &str = "#![no_std]\n\npub fn checksum(bytes: &[u8]) -> u8 {\n    \
+    bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))\n}\n";
+
+/// Returns whether the standard library of `target` is installed for the stable
+/// toolchain.
+fn target_installed(target: &str) -> bool {
+    let output = Command::new("rustc")
+        .args(["--print", "sysroot"])
+        .output()
+        .unwrap();
+    let sysroot = PathBuf::from(String::from_utf8(output.stdout).unwrap().trim());
+    sysroot
+        .join("lib/rustlib")
+        .join(target)
+        .join("lib")
+        .is_dir()
+}
+
+async fn post_audit(server: &TestServer, target: &str) -> (StatusCode, Value) {
+    let response = server
+        .client()
+        .post(server.url("/audit"))
+        .json(&json!({ "prompt": "Checksum", "generated_code": NO_STD_CODE, "target": target }))
+        .send()
+        .await
+        .unwrap();
+    let status = response.status();
+    (status, response.json().await.unwrap())
+}
+
+#[sqlx::test]
+async fn no_std_code_compiles_for_wasm(pool: PgPool) {
+    if !target_installed(WASM) {
+        eprintln!("skipping: the {} target is not installed", WASM);
+        return;
+    }
+    let server = TestServer::start(&pool).await;
+
+    let (status, audit) = post_audit(&server, WASM).await;
+
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(audit["is_valid"], true, "{}", audit["compilation_error"]);
+    assert_eq!(audit["target"], WASM);
+    let command = audit["compile_command"].as_array().unwrap();
+    let position = command.iter().position(|arg| arg == "--target").unwrap();
+    assert_eq!(command[position + 1], WASM);
+
+    let stats: Value = server
+        .client()
+        .get(server.url("/stats"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        stats["by_target"],
+        json!([{ "target": WASM, "total_audits": 1, "valid_audits": 1 }])
+    );
+}
+
+#[sqlx::test]
+async fn unknown_and_missing_targets_are_rejected(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+
+    let (status, error) = post_audit(&server, "not-a-target").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(
+        error["error"]
+            .as_str()
+            .unwrap()
+            .starts_with("Unknown target 'not-a-target'"),
+        "{}",
+        error
+    );
+
+    let missing = "aarch64-unknown-none";
+    if !target_installed(missing) {
+        let (status, error) = post_audit(&server, missing).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(
+            error["error"]
+                .as_str()
+                .unwrap()
+                .contains("rustup target add aarch64-unknown-none"),
+            "{}",
+            error
+        );
+    }
+    assert_eq!(
+        sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM ai_audits")
+            .fetch_one(&pool)
+            .await
+            .unwrap(),
+        0
+    );
+}
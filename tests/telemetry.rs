@@ -0,0 +1,77 @@
+//! Tests of the export of the spans of the server to an OpenTelemetry collector.
+
+mod common;
+
+use axum::{Router, body::Bytes, extract::State, http::HeaderMap, routing::post};
+use common::TestServer;
+use sqlx::PgPool;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// The content types of the export requests a fake collector received.
+type Exports = Arc<Mutex<Vec<String>>>;
+
+/// Starts a collector recording the requests sent to `/v1/traces`, and returns its URL.
+async fn start_collector(exports: Exports) -> String {
+    let app = Router::new()
+        .route(
+            "/v1/traces",
+            post(
+                |State(exports): State<Exports>, headers: HeaderMap, body: Bytes| async move {
+                    assert!(!body.is_empty());
+                    let content_type = headers["content-type"].to_str().unwrap().to_string();
+                    exports.lock().unwrap().push(content_type);
+                },
+            ),
+        )
+        .with_state(exports);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    url
+}
+
+#[sqlx::test]
+async fn spans_are_exported_to_the_collector(pool: PgPool) {
+    let exports = Exports::default();
+    let endpoint = start_collector(exports.clone()).await;
+    let server = TestServer::start_with(
+        &pool,
+        &[
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", endpoint.as_str()),
+            ("OTEL_BSP_SCHEDULE_DELAY", "100"),
+            ("RUST_LOG", "rust_ai_auditor=info"),
+        ],
+    )
+    .await;
+    server
+        .create_audit("pub fn answer() -> u32 {\n    42\n}\n")
+        .await;
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while exports.lock().unwrap().is_empty() {
+        assert!(Instant::now() < deadline, "no spans were exported");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(exports.lock().unwrap()[0], "application/x-protobuf");
+}
+
+#[sqlx::test]
+async fn endpoints_must_be_http_urls(pool: PgPool) {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_rust-ai-auditor"))
+        .arg("serve")
+        .env_remove("CONFIG_FILE")
+        .env("DATABASE_URL", common::database_url(&pool))
+        .env("OTEL_EXPORTER_OTLP_ENDPOINT", "localhost:4318")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("OTEL_EXPORTER_OTLP_ENDPOINT must be an http or https URL"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,97 @@
+//! Tests of the compilation timeout.
+
+mod common;
+
+use common::{TestServer, package};
+use serde_json::json;
+use sqlx::PgPool;
+use std::time::{Duration, Instant};
+
+/// The directory of the stand-in of `cargo` hanging while it resolves dependencies.
+const HANGING_CARGO: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/hanging-cargo");
+
+/// Keeps the const evaluator of `rustc` busy for far longer than the timeout.
+const SPIN: &str = "#![allow(long_running_const_eval)]\n\npub const SPIN: u64 = {\n    let mut i = 0u64;\n    while i < u64::MAX {\n        i += 1;\n    }\n    i\n};\n";
+
+#[sqlx::test]
+async fn slow_compilations_are_killed(pool: PgPool) {
+    let server = TestServer::start_with(
+        &pool,
+        &[
+            ("COMPILATION_TIMEOUT_SECS", "1"),
+            ("AUDIT_WORKER_CONCURRENCY", "1"),
+        ],
+    )
+    .await;
+
+    let started = Instant::now();
+    let audit = server.create_audit(SPIN).await;
+    assert!(
+        started.elapsed() < Duration::from_secs(10),
+        "{:?}",
+        started.elapsed()
+    );
+    assert_eq!(audit["is_valid"], false, "{}", audit);
+    assert_eq!(audit["status"], "compile_error");
+    assert!(
+        audit["compilation_error"]
+            .as_str()
+            .unwrap()
+            .contains("Compilation timed out after 1 seconds"),
+        "{}",
+        audit
+    );
+    assert_eq!(server.child_processes("rustc"), 0);
+
+    // The only worker is free again.
+    let audit = server
+        .create_audit("pub fn answer() -> u32 {\n    42\n}\n")
+        .await;
+    assert_eq!(audit["status"], "valid", "{}", audit);
+}
+
+#[sqlx::test]
+async fn slow_dependency_resolutions_are_killed(pool: PgPool) {
+    let pid_file = std::path::Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join(format!("hanging-cargo-{}.pid", uuid::Uuid::new_v4()));
+    let path = format!(
+        "{}:{}",
+        HANGING_CARGO,
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let server = TestServer::start_with(
+        &pool,
+        &[
+            ("COMPILATION_TIMEOUT_SECS", "1"),
+            ("PATH", path.as_str()),
+            ("HANGING_CARGO_PID_FILE", pid_file.to_str().unwrap()),
+        ],
+    )
+    .await;
+
+    let audit = server
+        .create_audit_with(json!({ "files": package("") }))
+        .await;
+    assert_eq!(audit["status"], "compile_error", "{}", audit);
+    assert!(
+        audit["compilation_error"]
+            .as_str()
+            .unwrap()
+            .contains("Compilation timed out after 1 seconds"),
+        "{}",
+        audit
+    );
+
+    // The process cargo was waiting on was killed along with it.
+    let pid = std::fs::read_to_string(&pid_file).unwrap();
+    let stat = std::path::Path::new("/proc").join(pid.trim()).join("stat");
+    let started = Instant::now();
+    while std::fs::read_to_string(&stat).is_ok_and(|stat| !stat.contains(") Z ")) {
+        assert!(
+            started.elapsed() < Duration::from_secs(5),
+            "the process of cargo is still running"
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    let _ = std::fs::remove_file(pid_file);
+}
@@ -0,0 +1,71 @@
+//! Tests of HTTPS served by the server itself.
+
+mod common;
+
+use common::TestServer;
+use reqwest::StatusCode;
+use sqlx::PgPool;
+use std::process::Command;
+
+/// A self-signed certificate for `localhost` and `127.0.0.1`, made with:
+///
+/// ```text
+/// openssl req -x509 -newkey rsa:2048 -nodes -keyout key.pem -out cert.pem -days 36500 \
+///   -subj "/CN=localhost" -addext "subjectAltName=DNS:localhost,IP:127.0.0.1" \
+///   -addext "basicConstraints=critical,CA:FALSE" -addext "extendedKeyUsage=serverAuth"
+/// ```
+const CERT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls/cert.pem");
+const KEY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls/key.pem");
+
+#[sqlx::test]
+async fn server_accepts_tls_handshakes(pool: PgPool) {
+    let server =
+        TestServer::start_with(&pool, &[("TLS_CERT_PATH", CERT), ("TLS_KEY_PATH", KEY)]).await;
+
+    let response = server
+        .client()
+        .get(server.url("/health"))
+        .send()
+        .await
+        .unwrap();
+
+    assert!(server.url("/health").starts_with("https://"));
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[sqlx::test]
+async fn invalid_certificate_fails_startup(pool: PgPool) {
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-ai-auditor"))
+        .arg("serve")
+        .env("DATABASE_URL", common::database_url(&pool))
+        .env("PORT", common::free_port().to_string())
+        // The key is not a certificate.
+        .env("TLS_CERT_PATH", KEY)
+        .env("TLS_KEY_PATH", KEY)
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Invalid TLS configuration"), "{}", stderr);
+}
+
+#[sqlx::test]
+async fn certificate_without_key_fails_startup(pool: PgPool) {
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-ai-auditor"))
+        .arg("serve")
+        .env("DATABASE_URL", common::database_url(&pool))
+        .env("PORT", common::free_port().to_string())
+        .env("TLS_CERT_PATH", CERT)
+        .env_remove("TLS_KEY_PATH")
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("TLS_CERT_PATH is set but TLS_KEY_PATH is not"),
+        "{}",
+        stderr
+    );
+}
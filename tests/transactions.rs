@@ -0,0 +1,111 @@
+//! Tests of the atomicity of audit creation, with failures injected by database
+//! triggers into the writes made after the audit is inserted.
+
+mod common;
+
+use common::TestServer;
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+const CODE: &str = "pub fn answer() -> u32 { 42 }";
+
+/// Makes every insertion into `table` fail.
+async fn fail_inserts_into(pool: &PgPool, table: &str) {
+    sqlx::raw_sql(
+        "CREATE FUNCTION fail_insert() RETURNS trigger AS $$
+         BEGIN RAISE EXCEPTION 'injected failure'; END $$ LANGUAGE plpgsql",
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+    sqlx::raw_sql(&format!(
+        "CREATE TRIGGER fail_insert BEFORE INSERT ON {} FOR EACH ROW EXECUTE FUNCTION fail_insert()",
+        table
+    ))
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+async fn count(pool: &PgPool, table: &str) -> i64 {
+    sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table))
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+async fn post_audit(server: &TestServer, body: &Value, key: &str) -> StatusCode {
+    server
+        .client()
+        .post(server.url("/audit"))
+        .header("Idempotency-Key", key)
+        .json(body)
+        .send()
+        .await
+        .unwrap()
+        .status()
+}
+
+#[sqlx::test]
+async fn failed_write_after_the_insert_leaves_no_partial_records(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    fail_inserts_into(&pool, "audit_jobs").await;
+    let body = json!({ "prompt": "Answer", "generated_code": CODE, "background": true });
+
+    let status = post_audit(&server, &body, "attempt-1").await;
+
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(count(&pool, "ai_audits").await, 0);
+    assert_eq!(count(&pool, "audit_jobs").await, 0);
+
+    // The idempotency key was rolled back with the audit, so a retry creates it.
+    sqlx::raw_sql("DROP TRIGGER fail_insert ON audit_jobs")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let status = post_audit(&server, &body, "attempt-1").await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(count(&pool, "ai_audits").await, 1);
+    assert_eq!(count(&pool, "audit_jobs").await, 1);
+}
+
+/// Returns the running totals behind the statistics.
+async fn summary(pool: &PgPool) -> (i64, i64, i64) {
+    sqlx::query_as("SELECT total_audits, valid_audits, total_code_lines FROM audit_stats_summary")
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn failed_file_writes_roll_back_the_audit_and_its_totals(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    server.create_audit(CODE).await;
+    let before = summary(&pool).await;
+    fail_inserts_into(&pool, "audit_files").await;
+    let body = json!({
+        "prompt": "Write a tokenizer",
+        "files": [
+            { "path": "src/lib.rs", "content": "pub mod token;" },
+            { "path": "src/token.rs", "content": "pub struct Token;" },
+        ],
+    });
+
+    let status = post_audit(&server, &body, "files-1").await;
+
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(count(&pool, "ai_audits").await, 1);
+    assert_eq!(count(&pool, "audit_files").await, 0);
+    assert_eq!(summary(&pool).await, before);
+
+    sqlx::raw_sql("DROP TRIGGER fail_insert ON audit_files")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let status = post_audit(&server, &body, "files-1").await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(count(&pool, "ai_audits").await, 2);
+    assert_eq!(count(&pool, "audit_files").await, 2);
+    assert_eq!(summary(&pool).await.0, before.0 + 1);
+}
@@ -0,0 +1,104 @@
+//! Tests of `updated_at`, set by the server and kept current by a trigger.
+
+mod common;
+
+use chrono::{DateTime, Utc};
+use common::TestServer;
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+async fn updated_at(pool: &PgPool) -> DateTime<Utc> {
+    sqlx::query_scalar("SELECT updated_at FROM ai_audits")
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn manual_edits_bump_updated_at_but_bookkeeping_does_not(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let audit = server
+        .create_audit_with(json!({
+            "generated_code": "pub fn answer() -> u32 { 42 }",
+            "idempotency_key": "edit-by-hand",
+        }))
+        .await;
+    let created = updated_at(&pool).await;
+
+    // Forgetting the idempotency key of an audit does not change it.
+    sqlx::query("UPDATE ai_audits SET idempotency_key = NULL, request_fingerprint = NULL")
+        .execute(&pool)
+        .await
+        .unwrap();
+    assert_eq!(updated_at(&pool).await, created);
+
+    // A statement not setting updated_at, e.g. a manual fix, gets it set by the trigger.
+    sqlx::query("UPDATE ai_audits SET prompt = 'Write a better function'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let edited = updated_at(&pool).await;
+    assert!(edited > created);
+
+    // Both APIs expose the new timestamp.
+    let fetched: Value = server
+        .client()
+        .get(server.url(&format!("/audit/{}", audit["id"].as_str().unwrap())))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        fetched["updated_at"]
+            .as_str()
+            .unwrap()
+            .parse::<DateTime<Utc>>()
+            .unwrap(),
+        edited
+    );
+    let body = server
+        .graphql(
+            "query($id: UUID!) { audit(id: $id) { updatedAt } }",
+            json!({ "id": audit["id"] }),
+        )
+        .await;
+    assert_eq!(
+        body["data"]["audit"]["updatedAt"]
+            .as_str()
+            .unwrap()
+            .parse::<DateTime<Utc>>()
+            .unwrap(),
+        edited,
+        "{}",
+        body
+    );
+}
+
+#[sqlx::test]
+async fn reaudits_bump_updated_at(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let response = server
+        .admin_post(
+            "/admin/import",
+            &json!([{
+                "prompt": "Write a function",
+                "generated_code": "pub fn compiles_now() -> i32 { 1 }",
+                "is_valid": false,
+                "status": "compile_error",
+                "compilation_error": "error: failed with an older toolchain",
+            }]),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let imported = updated_at(&pool).await;
+
+    let response = server
+        .admin_post("/audits/reaudit-invalid", &json!({}))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    assert!(updated_at(&pool).await > imported);
+}
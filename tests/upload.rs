@@ -0,0 +1,205 @@
+//! Tests of audits uploaded as multipart forms, zip archives and raw code.
+
+mod common;
+
+use common::TestServer;
+use reqwest::{
+    StatusCode,
+    multipart::{Form, Part},
+};
+use serde_json::Value;
+use sqlx::PgPool;
+use std::io::Write;
+
+const LIB: &[u8] = include_bytes!("fixtures/upload/lib.rs");
+const TOKEN: &[u8] = include_bytes!("fixtures/upload/token.rs");
+/// A source file encoded as Latin-1, which is not valid UTF-8.
+const LATIN1: &[u8] = include_bytes!("fixtures/upload/latin1.rs");
+
+/// The size of a file just above the upload limit.
+const TOO_LARGE: usize = 1024 * 1024 + 1;
+
+fn code(name: &str, bytes: &[u8]) -> Part {
+    Part::bytes(bytes.to_vec()).file_name(name.to_string())
+}
+
+/// Builds a zip archive of `files`.
+fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut archive = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+    for (path, bytes) in files {
+        archive.start_file(*path, options).unwrap();
+        archive.write_all(bytes).unwrap();
+    }
+    archive.finish().unwrap().into_inner()
+}
+
+/// Posts `form` to `POST /audit`, returning the status and body of the response.
+async fn post_form(server: &TestServer, form: Form) -> (StatusCode, Value) {
+    let response = server
+        .client()
+        .post(server.url("/audit"))
+        .multipart(form)
+        .send()
+        .await
+        .unwrap();
+    let status = response.status();
+    (status, response.json().await.unwrap())
+}
+
+async fn audit_count(pool: &PgPool) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM ai_audits")
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn multipart_files_are_bundled_into_one_audit(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let form = Form::new()
+        .text("prompt", "Write a tokenizer")
+        .text("tags", "upload")
+        .part("code", code("src/lib.rs", LIB))
+        .part("code", code("src/token.rs", TOKEN));
+
+    let (status, audit) = post_form(&server, form).await;
+    assert_eq!(status, StatusCode::CREATED, "{}", audit);
+    assert_eq!(audit["prompt"], "Write a tokenizer");
+    assert_eq!(audit["status"], "valid", "{}", audit);
+    assert_eq!(audit["tags"], serde_json::json!(["upload"]));
+    let code = audit["generated_code"].as_str().unwrap();
+    assert!(code.contains("pub mod token {"), "{}", code);
+    assert!(code.contains("input.split_whitespace()"), "{}", code);
+}
+
+#[sqlx::test]
+async fn zip_archives_are_read_as_several_files(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let archive = zip(&[
+        ("tokenizer/src/lib.rs", LIB),
+        ("tokenizer/src/token.rs", TOKEN),
+        ("tokenizer/README.md", b"Not Rust"),
+    ]);
+    let form = Form::new().text("prompt", "Write a tokenizer").part(
+        "code",
+        Part::bytes(archive)
+            .file_name("tokenizer.zip")
+            .mime_str("application/zip")
+            .unwrap(),
+    );
+
+    let (status, audit) = post_form(&server, form).await;
+    assert_eq!(status, StatusCode::CREATED, "{}", audit);
+    assert_eq!(audit["status"], "valid", "{}", audit);
+    let code = audit["generated_code"].as_str().unwrap();
+    assert!(code.contains("pub mod token {"), "{}", code);
+    assert!(!code.contains("Not Rust"), "{}", code);
+}
+
+#[sqlx::test]
+async fn raw_code_takes_its_prompt_from_a_header(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let response = server
+        .client()
+        .post(server.url("/audit?tags=raw"))
+        .header("content-type", "text/x-rust")
+        .header("x-audit-prompt", "Write a tokenizer")
+        .body(TOKEN)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let audit: Value = response.json().await.unwrap();
+    assert_eq!(audit["prompt"], "Write a tokenizer");
+    assert_eq!(audit["tags"], serde_json::json!(["raw"]));
+    assert_eq!(
+        audit["generated_code"],
+        std::str::from_utf8(TOKEN).unwrap().trim()
+    );
+}
+
+#[sqlx::test]
+async fn code_that_is_not_utf8_is_unprocessable(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+
+    let form = Form::new()
+        .text("prompt", "Write a function")
+        .part("code", code("src/lib.rs", LIB))
+        .part("code", code("src/latin1.rs", LATIN1));
+    let (status, body) = post_form(&server, form).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["error"], "src/latin1.rs is not valid UTF-8");
+
+    let archive = zip(&[("src/lib.rs", LIB), ("src/latin1.rs", LATIN1)]);
+    let form = Form::new()
+        .text("prompt", "Write a function")
+        .part("code", code("crate.zip", &archive));
+    let (status, body) = post_form(&server, form).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["error"], "src/latin1.rs is not valid UTF-8");
+
+    let response = server
+        .client()
+        .post(server.url("/audit?prompt=Write%20a%20function"))
+        .header("content-type", "text/x-rust")
+        .body(LATIN1)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "The request body is not valid UTF-8");
+
+    assert_eq!(audit_count(&pool).await, 0);
+}
+
+#[sqlx::test]
+async fn each_file_is_limited_in_size(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let large = vec![b' '; TOO_LARGE];
+
+    let form = Form::new()
+        .text("prompt", "Write a function")
+        .part("code", code("src/lib.rs", LIB))
+        .part("code", code("src/token.rs", &large));
+    let (status, body) = post_form(&server, form).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "src/token.rs is larger than 1048576 bytes");
+
+    // Compressed, the file is small; its size once extracted is limited.
+    let archive = zip(&[("src/lib.rs", LIB), ("src/token.rs", &large)]);
+    assert!(archive.len() < TOO_LARGE);
+    let form = Form::new()
+        .text("prompt", "Write a function")
+        .part("code", code("crate.zip", &archive));
+    let (status, body) = post_form(&server, form).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "src/token.rs is larger than 1048576 bytes");
+
+    let response = server
+        .client()
+        .post(server.url("/audit?prompt=Write%20a%20function"))
+        .header("content-type", "text/x-rust")
+        .body(large.clone())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(
+        body["error"],
+        "The request body is larger than 1048576 bytes"
+    );
+
+    // The limit applies to the other fields too.
+    let form = Form::new()
+        .text("prompt", String::from_utf8(large).unwrap())
+        .part("code", code("src/lib.rs", TOKEN));
+    let (status, body) = post_form(&server, form).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "prompt is larger than 1048576 bytes");
+
+    assert_eq!(audit_count(&pool).await, 0);
+}
@@ -0,0 +1,157 @@
+//! Tests of the validation rules applied before code is compiled.
+
+mod common;
+
+use common::TestServer;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+/// Code that compiles but uses `unsafe`, a blocking finding.
+const UNSAFE_CODE: &str = "pub fn read(p: *const i32) -> i32 { unsafe { *p } }";
+
+fn finding_codes(audit: &Value) -> Vec<&str> {
+    audit["findings"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|finding| finding["code"].as_str())
+        .collect()
+}
+
+#[sqlx::test]
+async fn strict_mode_rejects_blocking_findings_without_compiling(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+
+    let audit = server
+        .create_audit_with(json!({ "generated_code": UNSAFE_CODE, "strict": true }))
+        .await;
+
+    assert_eq!(audit["status"], "rejected");
+    assert_eq!(audit["is_valid"], false);
+    assert!(
+        audit["rejection_reason"]
+            .as_str()
+            .unwrap()
+            .starts_with("RAA0001")
+    );
+    assert!(audit["compile_command"].is_null());
+    assert!(audit["compilation_duration_ms"].is_null());
+}
+
+#[sqlx::test]
+async fn advisory_mode_compiles_despite_blocking_findings(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+
+    let audit = server
+        .create_audit_with(json!({ "generated_code": UNSAFE_CODE, "strict": false }))
+        .await;
+
+    assert_eq!(audit["status"], "valid");
+    assert_eq!(audit["is_valid"], true);
+    assert!(audit["rejection_reason"].is_null());
+    assert!(audit["compile_command"].is_array());
+    assert!(finding_codes(&audit).contains(&"RAA0001"));
+}
+
+#[sqlx::test]
+async fn server_wide_strict_mode_can_be_overridden(pool: PgPool) {
+    let server = TestServer::start_with(&pool, &[("AUDIT_STRICT", "true")]).await;
+
+    let audit = server.create_audit(UNSAFE_CODE).await;
+    assert_eq!(audit["status"], "rejected");
+
+    let audit = server
+        .create_audit_with(json!({ "generated_code": UNSAFE_CODE, "strict": false }))
+        .await;
+    assert_eq!(audit["status"], "valid");
+}
+
+#[sqlx::test]
+async fn strict_mode_is_read_from_the_configuration_file(pool: PgPool) {
+    let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("audit_strict.toml");
+    std::fs::write(&path, "audit_strict = true\n").unwrap();
+    let server = TestServer::start_with(&pool, &[("CONFIG_FILE", path.to_str().unwrap())]).await;
+
+    let audit = server.create_audit(UNSAFE_CODE).await;
+    assert_eq!(audit["status"], "rejected");
+}
+
+#[sqlx::test]
+async fn oversized_code_is_refused(pool: PgPool) {
+    let server = TestServer::start_with(&pool, &[("MAX_CODE_SIZE_BYTES", "64")]).await;
+
+    let code = format!(
+        "pub fn answer() -> u32 {{\n{}    42\n}}\n",
+        "    // padding\n".repeat(4)
+    );
+    assert!(code.len() > 64);
+    let response = server
+        .client()
+        .post(server.url("/audit"))
+        .json(&json!({ "prompt": "Write a function", "generated_code": code }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(
+        body["error"],
+        format!("The code must be at most 64 bytes long, got {}", code.len())
+    );
+    let audits: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM ai_audits")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(audits, 0);
+
+    let audit = server
+        .create_audit("pub fn answer() -> u32 {\n    42\n}\n")
+        .await;
+    assert_eq!(audit["status"], "valid");
+}
+
+#[sqlx::test]
+async fn the_code_size_limit_must_be_positive(pool: PgPool) {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_rust-ai-auditor"))
+        .arg("serve")
+        .env_remove("CONFIG_FILE")
+        .env("DATABASE_URL", common::database_url(&pool))
+        .env("MAX_CODE_SIZE_BYTES", "0")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("MAX_CODE_SIZE_BYTES"),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[sqlx::test]
+async fn code_longer_than_the_line_limit_is_rejected_without_compiling(pool: PgPool) {
+    let server = TestServer::start_with(&pool, &[("AUDIT_MAX_LINES", "5")]).await;
+
+    // Five `\r\n` lines are within the limit, whatever their line breaks.
+    let code = "pub fn answer() -> u32 {\r\n    // one\r\n    // two\r\n    42\r\n}\r\n";
+    let audit = server.create_audit(code).await;
+    assert_eq!(audit["status"], "valid");
+    assert!(!finding_codes(&audit).contains(&"RAA0012"));
+    assert!(audit["compile_command"].is_array());
+
+    let code =
+        "pub fn answer() -> u32 {\r\n    // one\r\n    // two\r\n    // three\r\n    42\r\n}\r\n";
+    let audit = server
+        .create_audit_with(json!({ "generated_code": code, "strict": false }))
+        .await;
+    assert_eq!(audit["status"], "rejected");
+    assert_eq!(audit["is_valid"], false);
+    assert_eq!(finding_codes(&audit), ["RAA0012"]);
+    assert!(
+        audit["rejection_reason"]
+            .as_str()
+            .unwrap()
+            .contains("Has 6 lines, more than the limit of 5")
+    );
+    assert!(audit["compile_command"].is_null());
+    assert!(audit["compilation_duration_ms"].is_null());
+}
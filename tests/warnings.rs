@@ -0,0 +1,89 @@
+//! Tests of the policy counting `rustc` warnings as errors.
+
+mod common;
+
+use common::TestServer;
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+/// Compiles with an `unused_variables` warning.
+const CODE: &str = "pub fn answer() -> u32 {\n    let unused = 1;\n    42\n}\n";
+
+#[sqlx::test]
+async fn warnings_are_errors_only_when_enabled(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let audit = server.create_audit(CODE).await;
+    assert_eq!(audit["is_valid"], true, "{}", audit);
+    assert_eq!(audit["status"], "valid");
+    drop(server);
+
+    let server = TestServer::start_with(&pool, &[("AUDIT_WARNINGS_AS_ERRORS", "true")]).await;
+    let audit = server.create_audit(CODE).await;
+    assert_eq!(audit["is_valid"], false, "{}", audit);
+    assert_eq!(audit["status"], "compile_error");
+    assert!(
+        audit["compilation_error"]
+            .as_str()
+            .unwrap()
+            .contains("unused variable: `unused`"),
+        "{}",
+        audit
+    );
+}
+
+#[sqlx::test]
+async fn reaudits_apply_the_current_policy(pool: PgPool) {
+    let server = TestServer::start_with(&pool, &[("AUDIT_WARNINGS_AS_ERRORS", "true")]).await;
+    let audit = server.create_audit(CODE).await;
+    assert_eq!(audit["is_valid"], false, "{}", audit);
+    drop(server);
+
+    let server = TestServer::start(&pool).await;
+    let response = server
+        .admin_post("/audits/reaudit-invalid", &json!({}))
+        .await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let report: Value = response.json().await.unwrap();
+    assert_eq!(
+        report,
+        json!({ "processed": 1, "newly_valid": 1, "still_invalid": 0, "newly_invalid": 0 })
+    );
+    let status: String = sqlx::query_scalar("SELECT status FROM ai_audits")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "valid");
+}
+
+#[sqlx::test]
+async fn reaudits_invalidate_warnings_once_they_count_as_errors(pool: PgPool) {
+    let server = TestServer::start(&pool).await;
+    let warned = server.create_audit(CODE).await;
+    assert_eq!(warned["status"], "valid", "{}", warned);
+    let clean = server
+        .create_audit("pub fn answer() -> u32 {\n    42\n}\n")
+        .await;
+    assert_eq!(clean["status"], "valid", "{}", clean);
+    drop(server);
+
+    let server = TestServer::start_with(&pool, &[("AUDIT_WARNINGS_AS_ERRORS", "true")]).await;
+    let response = server
+        .admin_post("/audits/reaudit-invalid", &json!({}))
+        .await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let report: Value = response.json().await.unwrap();
+    assert_eq!(
+        report,
+        json!({ "processed": 1, "newly_valid": 0, "still_invalid": 0, "newly_invalid": 1 })
+    );
+    let status = |id: &Value| {
+        sqlx::query_scalar::<_, String>("SELECT status FROM ai_audits WHERE id = $1::uuid")
+            .bind(id.as_str().unwrap().to_string())
+            .fetch_one(&pool)
+    };
+    assert_eq!(status(&warned["id"]).await.unwrap(), "compile_error");
+    assert_eq!(status(&clean["id"]).await.unwrap(), "valid");
+}